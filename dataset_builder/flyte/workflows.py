@@ -63,17 +63,14 @@ def filter_repositories(
         output_path = f.name
     
     # Get GitHub token from secret
+    # (read by dataset_builder from the secret's file, or else from GITHUB_TOKEN; never passed as an argument)
     github_token = os.environ.get("FLYTE_SECRETS_DEFAULT_DIR", "") + "/github-token"
-    if os.path.exists(github_token):
-        with open(github_token, 'r') as f:
-            token = f.read().strip()
-    else:
-        token = os.environ.get("GITHUB_TOKEN", "dummy-token")
+    token_args = ["--token-file", github_token] if os.path.exists(github_token) else []
     
     # Run filter command
     cmd = [
         "/usr/local/bin/dataset_builder",
-        token,
+        *token_args,
         "filter",
         input_path,
         output_path
@@ -110,17 +107,14 @@ def clone_repositories(
     datasets_dir = tempfile.mkdtemp(prefix="datasets_")
     
     # Get GitHub token from secret
+    # (read by dataset_builder from the secret's file, or else from GITHUB_TOKEN; never passed as an argument)
     github_token = os.environ.get("FLYTE_SECRETS_DEFAULT_DIR", "") + "/github-token"
-    if os.path.exists(github_token):
-        with open(github_token, 'r') as f:
-            token = f.read().strip()
-    else:
-        token = os.environ.get("GITHUB_TOKEN", "dummy-token")
+    token_args = ["--token-file", github_token] if os.path.exists(github_token) else []
     
     # Run clone command
     cmd = [
         "/usr/local/bin/dataset_builder",
-        token,
+        *token_args,
         "clone",
         repos_path,
         datasets_dir
@@ -158,17 +152,14 @@ def analyze_repositories(
         outputs_path = f.name
     
     # Get GitHub token (needed for some analysis tools)
+    # (read by dataset_builder from the secret's file, or else from GITHUB_TOKEN; never passed as an argument)
     github_token = os.environ.get("FLYTE_SECRETS_DEFAULT_DIR", "") + "/github-token"
-    if os.path.exists(github_token):
-        with open(github_token, 'r') as f:
-            token = f.read().strip()
-    else:
-        token = os.environ.get("GITHUB_TOKEN", "dummy-token")
+    token_args = ["--token-file", github_token] if os.path.exists(github_token) else []
     
     # Run outputs command
     cmd = [
         "/usr/local/bin/dataset_builder",
-        token,
+        *token_args,
         "outputs",
         datasets_path,
         outputs_path
@@ -205,17 +196,14 @@ def collect_source_code(
         code_path = f.name
     
     # Get GitHub token
+    # (read by dataset_builder from the secret's file, or else from GITHUB_TOKEN; never passed as an argument)
     github_token = os.environ.get("FLYTE_SECRETS_DEFAULT_DIR", "") + "/github-token"
-    if os.path.exists(github_token):
-        with open(github_token, 'r') as f:
-            token = f.read().strip()
-    else:
-        token = os.environ.get("GITHUB_TOKEN", "dummy-token")
+    token_args = ["--token-file", github_token] if os.path.exists(github_token) else []
     
     # Run collect command
     cmd = [
         "/usr/local/bin/dataset_builder",
-        token,
+        *token_args,
         "collect",
         datasets_path,
         code_path
@@ -0,0 +1,919 @@
+//! The `outputs` step: run every analysis tool on each checkout and write `outputs.jsonl`.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use clap::Args;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::analyzers::{nightly_toolchain, select_analyzers, Analyzer, RepoTarget, Report, Selection, ToolOutput};
+use crate::cache::{clean_head, CacheKey, ResultCache};
+use crate::clone::{clone_records, head_commit, name_map, repo_name};
+use crate::columnar::{finish_as_parquet, working_jsonl, DatasetKind, FileFormat};
+use crate::complexity::{metrics_beside, MetricsEntry};
+use crate::jsonl::{open_for_run, read_jsonl, replace_file, retain_checkouts, scan_jsonl, Codec, JsonlWriter};
+use crate::manifest::RunManifest;
+use crate::parse::{write_sarif, Finding};
+use crate::progress::Progress;
+use crate::sandbox::{SandboxKind, SandboxOptions, HOST};
+use crate::schema::OUTPUT_SCHEMA_VERSION;
+use crate::shard::{finish_as_shards, ShardOptions};
+use crate::shutdown::{shutdown_requested, tools_killed, Checkpoint};
+use crate::targets::{expanded_beside, ir_beside, ExpandedEntry, IrEntry, DEFAULT_MAX_EXPANDED_BYTES, DEFAULT_MAX_IR_BYTES};
+use crate::tool::{cargo_fetch, Limits, ToolResult, ToolStatus};
+use crate::workspace::{cargo_workspace, dep_graph, find_manifests, pinned_toolchain, DepGraph, Member};
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct AnalyzeOptions {
+    /// Kill any single tool invocation that runs longer than this many seconds
+    #[arg(long)]
+    pub tool_timeout: Option<u64>,
+    /// Total time budget per repository in seconds; remaining tools are marked TIMEOUT once exceeded
+    #[arg(long)]
+    pub repo_timeout: Option<u64>,
+    /// Keep raw tool output (e.g. the clippy JSON stream) alongside the parsed results
+    #[arg(long)]
+    pub raw: bool,
+    /// Also write each repo's SAST findings as `<DIR>/<name>.sarif`
+    #[arg(long, value_name = "DIR")]
+    pub sarif: Option<String>,
+    /// CodeQL query suite or pack to analyze with (default: `codeql/<language>-queries`)
+    #[arg(long, value_name = "PATH_OR_NAME")]
+    pub codeql_suite: Option<String>,
+    /// Leave each repo's CodeQL database in `<repo>/.codeql-db` instead of deleting it
+    #[arg(long)]
+    pub keep_codeql_db: bool,
+    /// Store the unified diff `cargo fmt` would apply to each file it would reformat, up to 64 KiB
+    /// a file, next to its changed-line counts
+    #[arg(long)]
+    pub fmt_capture_diff: bool,
+    /// cargo-deny config for repositories without a `deny.toml` of their own (default: a built-in
+    /// one checking advisories, bans and licenses)
+    #[arg(long, value_name = "PATH")]
+    pub deny_config: Option<String>,
+    /// Run every cargo tool with `cargo +<TOOLCHAIN>`, overriding the repo's rust-toolchain.toml
+    #[arg(long, value_name = "TOOLCHAIN")]
+    pub force_toolchain: Option<String>,
+    /// Run only these analyzers (comma-separated, e.g. `clippy,geiger`); the rest are recorded as skipped
+    #[arg(long, value_name = "NAMES", value_delimiter = ',', conflicts_with = "enable")]
+    pub only: Vec<String>,
+    /// Also run these analyzers, including opt-in ones such as `tree` (comma-separated)
+    #[arg(long, value_name = "NAMES", value_delimiter = ',')]
+    pub enable: Vec<String>,
+    /// Don't run these analyzers (comma-separated); they are recorded as skipped
+    #[arg(long, visible_alias = "disable", value_name = "NAMES", value_delimiter = ',')]
+    pub skip: Vec<String>,
+    /// Extra analyzers to run, defined in a TOML file (see `tools.example.toml`)
+    #[arg(long, value_name = "PATH")]
+    pub tools_config: Option<String>,
+    /// Also store the `cargo tree` text next to the structured `dep_graph` (same as `--enable tree`)
+    #[arg(long)]
+    pub include_tree_text: bool,
+    /// Run clippy, geiger and the tests even when `cargo check` found that the crate doesn't
+    /// build, instead of recording them as `skipped_build_failed`
+    #[arg(long)]
+    pub keep_going_on_build_failure: bool,
+    /// Also run the crate's tests, with `cargo nextest` when it is installed (same as `--enable test`)
+    #[arg(long)]
+    pub with_tests: bool,
+    /// Where `complexity` writes the metrics of each `.rs` file (default: metrics.jsonl beside
+    /// the outputs file)
+    #[arg(long, value_name = "FILE")]
+    pub metrics_file: Option<String>,
+    /// Where `expand` writes the macro-expanded source of each target (default: expanded.jsonl
+    /// beside the outputs file)
+    #[arg(long, value_name = "FILE")]
+    pub expanded_file: Option<String>,
+    /// Cut each expanded source at a line end within this many bytes (default 4 MiB, 0 for no limit)
+    #[arg(long, value_name = "BYTES")]
+    pub max_expanded_bytes: Option<usize>,
+    /// Also dump these compiler representations of every target, `hir` and `mir` (same as
+    /// `--enable hir,mir`); the AST is dumped by default
+    #[arg(long, value_name = "REPRS", value_delimiter = ',', value_parser = ["ast", "hir", "mir"])]
+    pub emit: Vec<String>,
+    /// Where `ast`, `hir` and `mir` write their dumps (default: ir.jsonl beside the outputs file)
+    #[arg(long, value_name = "FILE")]
+    pub ir_file: Option<String>,
+    /// Cut each dump at a line end within this many bytes (default 8 MiB, 0 for no limit)
+    #[arg(long, value_name = "BYTES")]
+    pub max_ir_bytes: Option<usize>,
+    /// Number of repositories to analyze concurrently
+    #[arg(long, default_value_t = 1)]
+    pub jobs: usize,
+    /// Cap each cargo invocation's build parallelism (`CARGO_BUILD_JOBS`); useful with `--jobs`
+    #[arg(long, value_name = "N")]
+    pub cargo_jobs: Option<usize>,
+    /// Append to an existing outputs file, skipping repositories it already has entries for
+    #[arg(long)]
+    pub resume: bool,
+    /// With --resume, analyze this repository (`owner/repo` or checkout directory) again even if
+    /// it is already recorded (repeatable)
+    #[arg(long, value_name = "NAME", requires = "resume")]
+    pub force: Vec<String>,
+    /// With --resume, analyze again every repository whose previous entries have errors
+    #[arg(long, requires = "resume")]
+    pub rerun_failed: bool,
+    /// fsync the outputs file after every N entries (each entry is always flushed)
+    #[arg(long, value_name = "N")]
+    pub fsync_every: Option<usize>,
+    /// Write the outputs file as JSONL, or as Parquet once every repository is analyzed, with a
+    /// `.parquet` extension
+    #[arg(long, id = "output_format", value_name = "FORMAT", value_enum, default_value_t = FileFormat::Jsonl)]
+    pub format: FileFormat,
+    /// Rows per row group with `--format parquet` (default 8192)
+    #[arg(long, value_name = "N")]
+    pub row_group_size: Option<usize>,
+    /// Compress the outputs file with gzip or zstd, at an optional level (`zstd:9`), adding `.gz`
+    /// or `.zst` to its name; an outputs file named so is compressed without it
+    #[arg(long, value_name = "CODEC[:LEVEL]", conflicts_with = "output_format")]
+    pub compress: Option<Codec>,
+    #[command(flatten)]
+    pub shard: ShardOptions,
+    /// Let builds use the network instead of fetching dependencies up front and building offline
+    #[arg(long)]
+    pub allow_network: bool,
+    /// Point every cargo-based analyzer at this target directory (`CARGO_TARGET_DIR`), so
+    /// dependencies shared between repositories are compiled once per worker
+    #[arg(long, value_name = "DIR")]
+    pub shared_target_dir: Option<String>,
+    /// Compiler wrapper for every build (`RUSTC_WRAPPER`), e.g. `sccache`
+    #[arg(long, value_name = "PROGRAM")]
+    pub rustc_wrapper: Option<String>,
+    /// Cut each tool's stdout and stderr at this many bytes (default 8 MiB, 0 for no limit)
+    #[arg(long, value_name = "BYTES")]
+    pub max_tool_output_bytes: Option<usize>,
+    /// Write the full text of every truncated output to `<DIR>/<name>/<tool>.<stream>`
+    #[arg(long, value_name = "DIR")]
+    pub overflow_dir: Option<String>,
+    /// Reuse tool results from this directory for checkouts whose HEAD hasn't moved since they were stored
+    #[arg(long, value_name = "DIR", env = "DATASET_BUILDER_CACHE_DIR")]
+    pub cache_dir: Option<String>,
+    /// Neither read nor write `--cache-dir` in this run
+    #[arg(long)]
+    pub no_cache: bool,
+    /// Write the full stderr of every tool run on a repository to `<DIR>/<name>.log`
+    #[arg(long, value_name = "DIR")]
+    pub per_repo_logs: Option<String>,
+    #[command(flatten)]
+    pub sandbox: SandboxOptions,
+    /// Further variables every tool process (and the sandbox runtime that starts it) is given,
+    /// e.g. a `PATH` that finds tools the host's doesn't; for callers of the library
+    #[arg(skip)]
+    pub tool_env: Vec<(String, String)>,
+}
+
+impl AnalyzeOptions {
+    pub(crate) fn limits(&self) -> Limits {
+        Limits { tool: self.tool_timeout.map(Duration::from_secs), repo: self.repo_timeout.map(Duration::from_secs) }
+    }
+
+    pub(crate) fn cache(&self) -> Option<ResultCache> {
+        self.cache_dir.as_ref().filter(|_| !self.no_cache).map(ResultCache::new)
+    }
+
+    /// `--shared-target-dir` as an absolute path, since cargo resolves it from each checkout.
+    pub(crate) fn target_root(&self) -> Option<PathBuf> {
+        self.shared_target_dir.as_deref().map(|d| std::path::absolute(d).unwrap_or_else(|_| PathBuf::from(d)))
+    }
+
+    /// The variables every tool process is started with (CodeQL's traced build included):
+    /// `--cargo-jobs` as `CARGO_BUILD_JOBS`, `--rustc-wrapper` as `RUSTC_WRAPPER`, and offline
+    /// cargo, since dependencies are downloaded by each repository's `cargo fetch` and nothing
+    /// after it may reach the network. Then `tool_env`.
+    pub(crate) fn tool_vars(&self) -> Vec<(String, String)> {
+        let mut vars = Vec::new();
+        if let Some(n) = self.cargo_jobs {
+            vars.push(("CARGO_BUILD_JOBS".to_string(), n.to_string()));
+        }
+        if !self.allow_network {
+            vars.push(("CARGO_NET_OFFLINE".to_string(), "true".to_string()));
+        }
+        if let Some(wrapper) = &self.rustc_wrapper {
+            vars.push(("RUSTC_WRAPPER".to_string(), wrapper.clone()));
+        }
+        vars.extend(self.tool_env.iter().cloned());
+        vars
+    }
+
+    /// Where this worker's cargo invocations build: cargo locks a target dir for a whole build,
+    /// so with `--jobs` each worker thread gets its own `job-<N>` subdirectory.
+    fn worker_target_dir(&self) -> Option<PathBuf> {
+        let root = self.target_root()?;
+        Some(match rayon::current_thread_index() {
+            Some(i) if self.jobs > 1 => root.join(format!("job-{}", i)),
+            _ => root,
+        })
+    }
+
+    /// Rejects unknown analyzer names, a bad `--tools-config`, a missing `--deny-config` and an
+    /// unusable `--sandbox`, so they fail the run before any repository is touched.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        select_analyzers(self)?;
+        if let Some(config) = &self.deny_config {
+            anyhow::ensure!(Path::new(config).is_file(), "--deny-config: {} is not a file", config);
+        }
+        if self.allow_network && self.sandbox.sandbox != SandboxKind::None {
+            anyhow::bail!("--allow-network can't be combined with --sandbox, which has no network");
+        }
+        if self.rustc_wrapper.is_some() && self.sandbox.sandbox != SandboxKind::None {
+            anyhow::bail!("--rustc-wrapper can't be combined with --sandbox; the wrapper's cache would live outside it");
+        }
+        self.sandbox.check()
+    }
+}
+
+/// `--max-tool-output-bytes` when none is given: enough for any sane tool run, small enough that
+/// a broken build's clippy stream can't blow up `outputs.jsonl`.
+pub const DEFAULT_MAX_TOOL_OUTPUT_BYTES: usize = 8 << 20;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct OutputEntry {
+    pub schema_version: u32,
+    /// `run_id` of the `manifest.json` describing the environment that produced this entry
+    pub run_id: String,
+    /// The repository: `owner/repo` on GitHub, its URL elsewhere; the checkout directory when
+    /// neither `clone`'s records nor the checkout's `origin` tell (`name_unresolved`)
+    pub name: String,
+    /// The checkout directory, as in `CloneRecord::name`; `name` held it in older entries
+    #[serde(default)]
+    pub dir: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub name_unresolved: bool,
+    /// The names-file entry the checkout was cloned from (`owner/repo` or a git URL), from
+    /// `name_map.json` or the clone manifest; absent for checkouts `clone` didn't record
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    /// HEAD commit of the checkout that was analyzed, or the clone manifest's when its `.git` was
+    /// stripped; absent when it isn't a git repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// The `Cargo.toml` of the project analyzed, relative to the checkout: the root's, or else
+    /// the first one a level down; absent when there is none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_manifest: Option<String>,
+    /// The checkout's other first-level `Cargo.toml` files, which aren't members of the project
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub other_manifests: Vec<String>,
+    /// No `Cargo.toml` was found, so only the tools that don't need one ran
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub not_a_cargo_project: bool,
+    /// Crate this entry describes; absent when `cargo metadata` could not read the manifest
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    /// The crate's `Cargo.toml`, relative to the checkout
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manifest_path: Option<String>,
+    /// Set on workspace member entries: the workspace root, relative to the checkout. Every member
+    /// gets its own entry; lockfile-level tools (`audit`, `auditable`) are shared between them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workspace_root: Option<String>,
+    /// One record per analyzer that ran, keyed by analyzer name
+    pub tools: BTreeMap<String, ToolOutput>,
+    /// SAST findings from semgrep and CodeQL in one schema
+    pub findings: Vec<Finding>,
+    /// Resolved dependency graph of this crate; absent when `cargo metadata` could not resolve it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dep_graph: Option<DepGraph>,
+    /// Toolchain the cargo-based tools ran under: `--force-toolchain`, the repo's
+    /// `rust-toolchain(.toml)` channel, or `default`
+    pub toolchain: String,
+    /// Toolchain the AST, HIR and MIR dumps ran under (always a nightly); absent when none ran
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ast_toolchain: Option<String>,
+    /// `cargo fetch` run before any tool, absent with `--allow-network` or without a Cargo project
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fetch: Option<ToolResult>,
+    /// Dependencies could not be downloaded, so the tools that need them are marked `fetch_failed`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fetch_failed: bool,
+    /// Milliseconds per analyzer, plus separately timed phases (`fetch`, `metadata`, `codeql_create`, `codeql_analyze`)
+    pub time_ms: BTreeMap<String, u128>,
+    /// Wall-clock time of the whole repository, the same for every entry of a workspace
+    #[serde(default)]
+    pub total_duration_ms: u128,
+    /// `tool: message` for every tool that could not be run on this repo
+    pub errors: Vec<String>,
+}
+
+pub fn run_outputs(root: &str, outputs_file: &str, opts: &AnalyzeOptions) -> anyhow::Result<()> {
+    opts.validate()?;
+    let run = RunManifest::capture(opts);
+    if opts.resume {
+        RunManifest::archive_beside(outputs_file)?;
+    }
+    run.write_beside(outputs_file)?;
+    run_outputs_with(root, outputs_file, opts, &run)
+}
+
+/// `run_outputs` under a manifest the caller captured and wrote, as `full` runs it: the file is
+/// named, compressed, sharded or converted to Parquet as there.
+pub fn run_outputs_with(root: &str, outputs_file: &str, opts: &AnalyzeOptions, run: &RunManifest) -> anyhow::Result<()> {
+    let working = outputs_working_file(outputs_file, opts)?;
+    analyze_all(root, &working, opts, run)?;
+    finish_outputs(&working, opts)
+}
+
+/// The JSONL file entries are appended to as repositories finish, before `finish_outputs`:
+/// `outputs_file` with `--compress`'s extension, or its JSONL twin with `--format parquet`, since
+/// --resume reads the entries back.
+pub(crate) fn outputs_working_file(outputs_file: &str, opts: &AnalyzeOptions) -> anyhow::Result<String> {
+    match opts.format {
+        FileFormat::Parquet => Ok(working_jsonl(outputs_file)),
+        FileFormat::Jsonl => Codec::path(opts.compress, outputs_file),
+    }
+}
+
+/// Turns the finished working file into the outputs file `opts` asks for: Parquet, or shards
+/// with a manifest, or as it is.
+pub(crate) fn finish_outputs(working: &str, opts: &AnalyzeOptions) -> anyhow::Result<()> {
+    if opts.format == FileFormat::Parquet {
+        return finish_as_parquet(working, DatasetKind::Outputs, opts.row_group_size);
+    }
+    if opts.shard.enabled() {
+        return finish_as_shards(working, opts.compress.unwrap_or(Codec::of(working)), &opts.shard);
+    }
+    Ok(())
+}
+
+fn analyze_all(root: &str, outputs_file: &str, opts: &AnalyzeOptions, run: &RunManifest) -> anyhow::Result<()> {
+    let analyzers = prepare_analysis(opts, run)?;
+    let sccache = sccache(opts);
+    let checkpoint = if opts.resume { Checkpoint::load_beside(outputs_file, "outputs")? } else { None };
+    let done = if opts.resume { load_for_resume(outputs_file, opts, checkpoint.as_ref())? } else { HashSet::new() };
+    let records = clone_records(root);
+    let names = name_map(root);
+    let mut repos = Vec::new();
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        if path.is_dir() && !done.contains(path.file_name().unwrap().to_string_lossy().as_ref()) {
+            repos.push(path);
+        }
+    }
+    if opts.resume {
+        if let Some(c) = &checkpoint {
+            tracing::info!("Resuming from checkpoint.json (interrupted at {})", c.interrupted_at);
+        }
+        tracing::info!("Resuming: {} repositories already analyzed, {} to go", done.len(), repos.len());
+    }
+
+    let file = if opts.resume {
+        OpenOptions::new().create(true).append(true).open(outputs_file)?
+    } else {
+        File::create(outputs_file)?
+    };
+    // A worker holds the lock for all of a repo's entries, so lines from concurrent repos never interleave.
+    let w = Mutex::new(JsonlWriter::compressed(file, opts.compress.unwrap_or(Codec::of(outputs_file)), opts.fsync_every)?);
+    // Side files of the analyzers whose output is too long for the entries.
+    let side_file = |writers: &[&str], file: &Option<String>, beside: fn(&str) -> PathBuf| -> anyhow::Result<Option<Mutex<JsonlWriter>>> {
+        if !analyzers.run.iter().any(|a| writers.contains(&a.name())) {
+            return Ok(None);
+        }
+        let file = file.as_ref().map(PathBuf::from).unwrap_or_else(|| beside(outputs_file));
+        if opts.resume {
+            retain_checkouts(&file, &done)?;
+        }
+        Ok(Some(Mutex::new(JsonlWriter::new(open_for_run(&file, opts.resume)?, None))))
+    };
+    let metrics = side_file(&["complexity"], &opts.metrics_file, metrics_beside)?;
+    let expanded = side_file(&["expand"], &opts.expanded_file, expanded_beside)?;
+    let ir = side_file(&["ast", "hir", "mir"], &opts.ir_file, ir_beside)?;
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(opts.jobs.max(1)).build()?;
+    // Shared results are copied into every member's entry, so they are counted once per repo.
+    let shared: HashSet<&str> = analyzers.run.iter().filter(|a| a.per_workspace()).map(|a| a.name()).collect();
+    let progress = Progress::new(repos.len(), "repositories analyzed");
+    let results: Vec<Option<RepoSummary>> = pool.install(|| {
+        repos.par_iter()
+            .map(|path| {
+                // After Ctrl-C, only the repositories already in flight are finished.
+                if shutdown_requested() {
+                    return Ok(None);
+                }
+                let name = path.file_name().unwrap().to_string_lossy();
+                let bar = progress.start(&name);
+                let mut entries = analyze_repo(path, &name, &analyzers, opts, run);
+                let record = records.get(name.as_ref());
+                if !path.join(".git").exists() {
+                    progress.suspend(|| tracing::warn!("⚠ {}: no .git (cloned with --strip-git?); commit taken from the clone manifest, result cache not used", name));
+                }
+                let repo = names.get(name.as_ref()).or(record.map(|r| &r.repo));
+                let resolved = repo_name(path, repo.map(String::as_str));
+                for e in &mut entries {
+                    e.repo = repo.cloned();
+                    e.name_unresolved = resolved.is_none();
+                    e.name = resolved.clone().unwrap_or_else(|| e.dir.clone());
+                    e.commit = e.commit.take().or_else(|| record.map(|r| r.commit.clone()));
+                }
+                progress.finish(bar);
+                if tools_killed() {
+                    // Some of its tools were killed by the shutdown; it is analyzed again on --resume.
+                    return Ok(None);
+                }
+                let errors: Vec<&str> = entries.iter().flat_map(|e| &e.errors).map(String::as_str).collect();
+                if !errors.is_empty() {
+                    progress.suspend(|| tracing::warn!(event = "repo_failed", repo = %name, reason = %errors.join("; "), "✗ {}: tool failures", name));
+                }
+                let mut w = w.lock().unwrap();
+                for out in &entries {
+                    w.write_entry(out)?;
+                }
+                // Shared by every member's entry, so written once.
+                if let (Some(m), Some(first)) = (&metrics, entries.first()) {
+                    let mut m = m.lock().unwrap();
+                    for file in first.tools.get("complexity").map_or(&[][..], |o| &o.file_metrics) {
+                        m.write_entry(&MetricsEntry { name: first.name.clone(), dir: first.dir.clone(), repo: first.repo.clone(), commit: first.commit.clone(), metrics: file.clone() })?;
+                    }
+                }
+                // Member by member, as the targets are each member's own.
+                for e in &entries {
+                    for (tool, out) in &e.tools {
+                        for source in out.sources.iter().cloned() {
+                            let (name, dir, repo, commit) = (e.name.clone(), e.dir.clone(), e.repo.clone(), e.commit.clone());
+                            if tool == "expand" {
+                                if let Some(x) = &expanded {
+                                    x.lock().unwrap().write_entry(&ExpandedEntry { name, dir, repo, commit, source })?;
+                                }
+                            } else if let Some(x) = &ir {
+                                x.lock().unwrap().write_entry(&IrEntry { name, dir, repo, commit, repr: tool.clone(), source })?;
+                            }
+                        }
+                    }
+                }
+                let mut tool_ms = BTreeMap::new();
+                let (mut units_fresh, mut units_built) = (0, 0);
+                for e in &entries {
+                    if let Some(Report::Clippy(r)) = e.tools.get("clippy").and_then(|o| o.report.as_ref()) {
+                        units_fresh += r.units_fresh;
+                        units_built += r.units_built;
+                    }
+                }
+                for (i, e) in entries.iter().enumerate() {
+                    for a in &analyzers.run {
+                        if i == 0 || !shared.contains(a.name()) {
+                            *tool_ms.entry(a.name().to_string()).or_default() += e.time_ms.get(a.name()).copied().unwrap_or(0);
+                        }
+                    }
+                }
+                Ok(Some(RepoSummary {
+                    name: name.into_owned(),
+                    failed: entries.iter().any(|e| !e.errors.is_empty()),
+                    total_ms: entries.first().map_or(0, |e| e.total_duration_ms),
+                    tool_ms,
+                    units_fresh,
+                    units_built,
+                }))
+            })
+            .collect::<anyhow::Result<_>>()
+    })?;
+    progress.done();
+    w.into_inner().unwrap().finish()?;
+    for side in [metrics, expanded, ir].into_iter().flatten() {
+        side.into_inner().unwrap().finish()?;
+    }
+    let results: Vec<RepoSummary> = results.into_iter().flatten().collect();
+    if shutdown_requested() {
+        let completed = done.into_iter().chain(results.iter().map(|r| r.name.clone())).collect();
+        Checkpoint::new("outputs", completed).write_beside(outputs_file)?;
+        anyhow::bail!("interrupted after analyzing {} of {} repositories; run again with --resume to continue from checkpoint.json",
+            results.len(), repos.len());
+    }
+    Checkpoint::remove_beside(outputs_file, "outputs");
+
+    let total = results.len();
+    log_slowest(&results);
+    if opts.shared_target_dir.is_some() || sccache.is_some() {
+        log_build_cache(&results, sccache);
+    }
+    let failed: Vec<String> = results.into_iter().filter(|r| r.failed).map(|r| r.name).collect();
+
+    if failed.is_empty() {
+        tracing::info!("Analyzed {} repositories without tool failures", total);
+    } else {
+        tracing::info!("Analyzed {} repositories, {} with tool failures: {}", total, failed.len(), failed.join(", "));
+    }
+    if total > 0 && failed.len() == total {
+        anyhow::bail!("every repository had tool failures; see the `errors` field in {}", outputs_file);
+    }
+    Ok(())
+}
+
+struct RepoSummary {
+    name: String,
+    failed: bool,
+    total_ms: u128,
+    tool_ms: BTreeMap<String, u128>,
+    /// Crates clippy found up to date or had to check, over all of the repo's entries
+    units_fresh: usize,
+    units_built: usize,
+}
+
+/// Logs the repositories and tools that took longest in this run.
+fn log_slowest(results: &[RepoSummary]) {
+    const TOP: usize = 5;
+    let secs = |ms: u128| ms as f64 / 1000.0;
+    let mut repos: Vec<&RepoSummary> = results.iter().collect();
+    repos.sort_by_key(|r| std::cmp::Reverse(r.total_ms));
+    let mut tools: BTreeMap<&str, u128> = BTreeMap::new();
+    for (tool, ms) in results.iter().flat_map(|r| &r.tool_ms) {
+        *tools.entry(tool).or_default() += ms;
+    }
+    let mut tools: Vec<(&str, u128)> = tools.into_iter().filter(|&(_, ms)| ms > 0).collect();
+    tools.sort_by_key(|&(_, ms)| std::cmp::Reverse(ms));
+    if !repos.is_empty() {
+        tracing::info!("Slowest repositories:");
+        for r in repos.iter().take(TOP) {
+            tracing::info!("  {:>8.1}s  {}", secs(r.total_ms), r.name);
+        }
+    }
+    if !tools.is_empty() {
+        tracing::info!("Slowest tools (total over all repositories):");
+        for (tool, ms) in tools.iter().take(TOP) {
+            tracing::info!("  {:>8.1}s  {}", secs(*ms), tool);
+        }
+    }
+}
+
+/// Selects and validates the analyzers and warns about missing tools. Must run before any worker
+/// starts.
+pub(crate) fn prepare_analysis(opts: &AnalyzeOptions, run: &RunManifest) -> anyhow::Result<Selection> {
+    let analyzers = select_analyzers(opts)?;
+    opts.validate()?;
+    let mut missing = run.tools.missing();
+    missing.retain(|tool| analyzers.run.iter().any(|a| a.name() == *tool));
+    if !missing.is_empty() {
+        tracing::warn!("⚠ Warning: not installed, the dataset will be partial: {}", missing.join(", "));
+    }
+    if let Some(sccache) = sccache(opts) {
+        // Zeroed so the summary's hit rate is this run's alone.
+        let _ = Command::new(sccache).arg("--zero-stats").output();
+    }
+    Ok(analyzers)
+}
+
+/// `--rustc-wrapper` when it is sccache, whose statistics the summary reports.
+fn sccache(opts: &AnalyzeOptions) -> Option<&str> {
+    opts.rustc_wrapper.as_deref().filter(|w| Path::new(w).file_stem().is_some_and(|s| s == "sccache"))
+}
+
+/// Logs how much of the run's building `--shared-target-dir` and sccache saved.
+fn log_build_cache(results: &[RepoSummary], sccache: Option<&str>) {
+    let fresh: usize = results.iter().map(|r| r.units_fresh).sum();
+    let units = fresh + results.iter().map(|r| r.units_built).sum::<usize>();
+    let build_ms: u128 = results.iter().map(|r| r.tool_ms.get("clippy").copied().unwrap_or(0)).sum();
+    if units > 0 {
+        tracing::info!("Build cache: clippy reused {} of {} crates ({:.0}%), {:.1}s of clippy in total",
+            fresh, units, 100.0 * fresh as f64 / units as f64, build_ms as f64 / 1000.0);
+    }
+    let Some(sccache) = sccache else { return };
+    if let Ok(out) = Command::new(sccache).arg("--show-stats").output() {
+        for line in String::from_utf8_lossy(&out.stdout).lines() {
+            // The totals only; per-language breakdowns are in parentheses.
+            if (line.starts_with("Cache hits") || line.starts_with("Cache misses")) && !line.contains('(') {
+                tracing::info!("  sccache: {}", line.split_whitespace().collect::<Vec<_>>().join(" "));
+            }
+        }
+    }
+}
+
+/// Reads an existing outputs file for `--resume` and returns the repositories to skip.
+/// Entries of repositories picked by `--force`/`--rerun-failed`, missing from a Ctrl-C
+/// `checkpoint`, and a truncated last line left by a crash, are removed from the file first so
+/// re-analyzing never leaves duplicates behind.
+fn load_for_resume(outputs_file: &str, opts: &AnalyzeOptions, checkpoint: Option<&Checkpoint>) -> anyhow::Result<HashSet<String>> {
+    let (done, rewrite) = resume_state(outputs_file, opts, checkpoint);
+    if let Some(kept) = rewrite {
+        replace_file(outputs_file, &kept)?;
+    }
+    Ok(done)
+}
+
+/// What `load_for_resume` skips, and what it rewrites the outputs file with if anything has to
+/// be removed, without touching the file.
+pub(crate) fn resume_state(outputs_file: &str, opts: &AnalyzeOptions, checkpoint: Option<&Checkpoint>) -> (HashSet<String>, Option<String>) {
+    // An unfinished compressed stream is rewritten as a finished one, as a truncated line is.
+    let Ok((text, truncated)) = read_jsonl(outputs_file) else { return (HashSet::new(), None) };
+    let mut entries = Vec::new();
+    let mut dropped = false;
+    let mut rerun: HashSet<&str> = opts.force.iter().map(String::as_str).collect();
+    for line in scan_jsonl(&text) {
+        match line.value {
+            Some(v) if entry_dir(&v).is_some() => entries.push((line.text, v)),
+            _ => {
+                tracing::warn!("⚠ Warning: dropping unreadable line {} of {} (truncated by an interrupted run?)", line.number, outputs_file);
+                dropped = true;
+                // The other entries of a workspace may have made it to disk; analyze the whole repo again.
+                let field = |key: &str| line.text.split_once(&format!("\"{}\":\"", key)).and_then(|(_, rest)| rest.split_once('"')).map(|(value, _)| value);
+                if let Some(dir) = field("dir").or_else(|| field("name")) {
+                    rerun.insert(dir);
+                }
+            }
+        }
+    }
+    // `--force` takes the repository's name as well as its checkout directory.
+    rerun.extend(entries.iter().filter(|(_, v)| v["name"].as_str().is_some_and(|n| opts.force.iter().any(|f| f == n))).filter_map(|(_, v)| entry_dir(v)));
+    if let Some(c) = checkpoint {
+        let completed: HashSet<&str> = c.completed.iter().map(String::as_str).collect();
+        rerun.extend(entries.iter().filter_map(|(_, v)| entry_dir(v)).filter(|dir| !completed.contains(dir)));
+    }
+    if opts.rerun_failed {
+        rerun.extend(entries.iter()
+            .filter(|(_, v)| v["errors"].as_array().is_some_and(|e| !e.is_empty()))
+            .filter_map(|(_, v)| entry_dir(v)));
+    }
+    let kept: Vec<&(&str, serde_json::Value)> = entries.iter().filter(|(_, v)| !rerun.contains(entry_dir(v).unwrap())).collect();
+    let rewrite = (dropped || truncated || kept.len() < entries.len()).then(|| kept.iter().map(|(line, _)| *line).collect::<String>());
+    (kept.iter().filter_map(|(_, v)| entry_dir(v).map(String::from)).collect(), rewrite)
+}
+
+/// The checkout directory of an outputs line, which entries from before `dir` kept in `name`.
+fn entry_dir(v: &serde_json::Value) -> Option<&str> {
+    v["dir"].as_str().filter(|d| !d.is_empty()).or(v["name"].as_str())
+}
+
+/// Analyzes one checkout. Workspaces yield an entry per member crate, anything else a single entry.
+pub fn analyze_repo(path: &Path, name: &str, analyzers: &Selection, opts: &AnalyzeOptions, run: &RunManifest) -> Vec<OutputEntry> {
+    tracing::debug!(event = "repo_started", repo = name);
+    let repo_start = Instant::now();
+    if let Some(log) = repo_log(opts, path) {
+        // Start afresh, so a rerun's log doesn't carry the previous run's output.
+        if let Err(e) = fs::create_dir_all(log.parent().unwrap()).and_then(|_| fs::write(&log, "")) {
+            tracing::warn!("failed to create {}: {}", log.display(), e);
+        }
+    }
+    let limits = opts.limits();
+    let commit = head_commit(path);
+    let manifests = find_manifests(path);
+    // Paths cargo reports are relative to the project; entries give them relative to the checkout.
+    let prefix = manifests.first().map_or("", |m| m.trim_end_matches("Cargo.toml").trim_end_matches('/'));
+    let in_checkout = |p: &str| match (prefix, p) {
+        ("", _) => p.to_string(),
+        (_, ".") => prefix.to_string(),
+        _ => format!("{}/{}", prefix, p),
+    };
+    let project = manifests.first().map(|_| path.join(prefix));
+    if project.is_none() {
+        tracing::debug!(event = "not_a_cargo_project", repo = name);
+    }
+    let project_dir = project.clone().unwrap_or_else(|| path.to_path_buf());
+    let mut shared_times = BTreeMap::new();
+    let mut shared_errors = Vec::new();
+    let forced = opts.force_toolchain.as_deref();
+    let fetch = (!opts.allow_network && project.is_some()).then(|| {
+        let start = Instant::now();
+        let mut fetch = cargo_fetch(&project_dir, forced, limits.deadline(repo_start)).unwrap_or_else(|e| ToolResult::failed(&e));
+        shared_times.insert("fetch".to_string(), start.elapsed().as_millis());
+        append_repo_log(opts, path, "fetch", &fetch);
+        limit_output(&mut fetch, opts, name, "fetch", &mut shared_errors);
+        fetch
+    });
+    let fetch_failed = fetch.as_ref().is_some_and(|f| f.exit_code != Some(0));
+    if fetch_failed {
+        shared_errors.push("fetch: cargo fetch failed, dependent tools were not run".to_string());
+    }
+    // Only a checkout identical to its HEAD commit can be looked up by it.
+    let cache = opts.cache();
+    let head = cache.as_ref().and_then(|_| clean_head(path));
+    let cache = cache.as_ref().zip(head.as_deref());
+    let target_dir = opts.worker_target_dir();
+    if let Some(dir) = &target_dir {
+        // Created up front so a sandbox can mount it.
+        let _ = fs::create_dir_all(dir);
+    }
+    let start = Instant::now();
+    let vars = opts.tool_vars();
+    let workspace = project.as_deref().and_then(|dir| cargo_workspace(dir, forced, &vars, limits.deadline(repo_start)));
+    shared_times.insert("metadata".to_string(), start.elapsed().as_millis());
+    for skipped in &analyzers.skipped {
+        shared_times.insert(skipped.clone(), 0);
+    }
+
+    let root = RepoTarget { path, project: project.as_deref(), dir: project_dir.clone(), member: None, opts, sandbox: &opts.sandbox, target_dir: target_dir.as_deref(), vars: &vars, limits, repo_start };
+    let mut shared: Vec<(String, ToolOutput)> = analyzers.run.iter()
+        .filter(|a| a.per_workspace())
+        .map(|a| (a.name().to_string(), run_analyzer(a.as_ref(), &root, run, cache, fetch_failed, &mut shared_times, &mut shared_errors)))
+        .collect();
+    // Skipped analyzers still get a record, so every entry of a run has the same keys.
+    shared.extend(analyzers.skipped.iter().map(|s| (s.clone(), ToolResult::with_status(ToolStatus::Skipped).into())));
+
+    let members: Vec<Option<&Member>> = match &workspace {
+        Some(ws) if !ws.members.is_empty() => ws.members.iter().map(Some).collect(),
+        _ => vec![None],
+    };
+    let mut entries = Vec::new();
+    for member in members {
+        let mut time_ms = shared_times.clone();
+        let mut errors = shared_errors.clone();
+        // Member crates of a real workspace are selected with `-p` and scanned from their own dir.
+        let scoped = member.filter(|_| workspace.as_ref().is_some_and(|ws| ws.is_workspace));
+        let dir = scoped.map_or(project_dir.clone(), |m| project_dir.join(&m.dir));
+        let target = RepoTarget { path, project: project.as_deref(), dir, member: scoped, opts, sandbox: &opts.sandbox, target_dir: target_dir.as_deref(), vars: &vars, limits, repo_start };
+
+        let mut tools: BTreeMap<String, ToolOutput> = shared.iter().cloned().collect();
+        let mut findings = Vec::new();
+        let mut build_failed = false;
+        for a in analyzers.run.iter().filter(|a| !a.per_workspace()) {
+            let mut out = if build_failed && a.needs_build() {
+                time_ms.insert(a.name().to_string(), 0);
+                ToolResult::with_status(ToolStatus::SkippedBuildFailed).into()
+            } else {
+                run_analyzer(a.as_ref(), &target, run, cache, fetch_failed, &mut time_ms, &mut errors)
+            };
+            // Only a check that ran to the end says the crate doesn't build; a timeout says nothing.
+            if a.name() == "check" && !opts.keep_going_on_build_failure {
+                build_failed = matches!(out.result.status, ToolStatus::Completed) && matches!(&out.report, Some(Report::Check(c)) if !c.builds);
+            }
+            findings.append(&mut out.findings);
+            tools.insert(a.name().to_string(), out);
+        }
+        if let Some(sarif_dir) = &opts.sarif {
+            let stem = scoped.map_or(name.to_string(), |m| format!("{}__{}", name, m.name));
+            let file = Path::new(sarif_dir).join(format!("{}.sarif", stem));
+            if let Err(e) = fs::create_dir_all(sarif_dir).map_err(anyhow::Error::from).and_then(|_| write_sarif(&file, &findings)) {
+                errors.push(format!("sarif: failed to write {}: {:#}", file.display(), e));
+            }
+        }
+        let ast_toolchain = ["ast", "hir", "mir"].iter().filter_map(|name| tools.get(*name))
+            .find(|o| matches!(o.result.status, ToolStatus::Completed))
+            .map(|_| nightly_toolchain(forced).to_string());
+
+        entries.push(OutputEntry {
+            schema_version: OUTPUT_SCHEMA_VERSION,
+            run_id:    run.run_id.clone(),
+            name:      name.into(),
+            dir:       name.into(),
+            name_unresolved: false,
+            repo: None,
+            commit: commit.clone(),
+            project_manifest: manifests.first().cloned(),
+            other_manifests: manifests.iter().skip(1)
+                .filter(|m| !workspace.as_ref().is_some_and(|ws| ws.members.iter().any(|member| in_checkout(&member.manifest_path) == **m)))
+                .cloned().collect(),
+            not_a_cargo_project: project.is_none(),
+            package:   member.map(|m| m.name.clone()),
+            manifest_path: member.map(|m| in_checkout(&m.manifest_path)),
+            workspace_root: scoped.and(workspace.as_ref()).map(|ws| in_checkout(&ws.root)),
+            fetch: fetch.clone(),
+            fetch_failed,
+            tools,
+            findings,
+            dep_graph: workspace.as_ref().and_then(|ws| dep_graph(ws.metadata.as_ref()?, member.map(|m| m.id.as_str()))),
+            toolchain: forced.map(str::to_string).or_else(|| pinned_toolchain(&project_dir)).or_else(|| pinned_toolchain(path)).unwrap_or_else(|| "default".to_string()),
+            ast_toolchain,
+            time_ms,
+            total_duration_ms: 0,
+            errors,
+        });
+    }
+    let total_duration_ms = repo_start.elapsed().as_millis();
+    for e in &mut entries {
+        e.total_duration_ms = total_duration_ms;
+    }
+    tracing::debug!(event = "repo_finished", repo = name, total_ms = total_duration_ms as u64);
+    entries
+}
+
+/// Runs one analyzer, or reuses its cached result, recording its time and turning failures into
+/// an `errors` line.
+fn run_analyzer(
+    a: &dyn Analyzer,
+    target: &RepoTarget,
+    run: &RunManifest,
+    cache: Option<(&ResultCache, &str)>,
+    fetch_failed: bool,
+    time_ms: &mut BTreeMap<String, u128>,
+    errors: &mut Vec<String>,
+) -> ToolOutput {
+    if !run.tools.is_installed(a.name()) {
+        return ToolResult::with_status(a.missing_status()).into();
+    }
+    if target.project.is_none() && a.needs_cargo_project() {
+        time_ms.insert(a.name().to_string(), 0);
+        return ToolResult::with_status(ToolStatus::NotACargoProject).into();
+    }
+    let cache = cache.map(|(cache, head)| (cache, cache_key(a, target, run, head)));
+    if let Some((mut out, ms)) = cache.as_ref().and_then(|(cache, key)| cache.get(key)) {
+        tracing::debug!(event = "tool_finished", repo = %target.path.display(), tool = a.name(), cached = true);
+        time_ms.insert(a.name().to_string(), ms);
+        time_ms.extend(out.phases.drain(..));
+        return out;
+    }
+    if fetch_failed && a.needs_dependencies() {
+        time_ms.insert(a.name().to_string(), 0);
+        return ToolResult::with_status(ToolStatus::FetchFailed).into();
+    }
+    let on_host;
+    let target = if a.needs_sandbox() {
+        target
+    } else {
+        on_host = RepoTarget { dir: target.dir.clone(), sandbox: &HOST, ..*target };
+        &on_host
+    };
+    let started_at = timestamp();
+    let start = Instant::now();
+    let res = a.run(target);
+    let elapsed = start.elapsed().as_millis();
+    time_ms.insert(a.name().to_string(), elapsed);
+    let mut out = match res {
+        Ok(out) => {
+            if let Some(e) = &out.result.error {
+                errors.push(format!("{}: {}", a.name(), e));
+            }
+            out
+        }
+        Err(e) => {
+            errors.push(format!("{}: {:#}", a.name(), e));
+            ToolResult::failed(&e).into()
+        }
+    };
+    let repo = target.path.file_name().unwrap_or_default().to_string_lossy();
+    let stem = target.member.map_or(repo.to_string(), |m| format!("{}__{}", repo, m.name));
+    tracing::debug!(
+        event = "tool_finished", repo = %repo, member = target.member.map(|m| m.name.as_str()), tool = a.name(),
+        status = ?out.result.status, exit_code = out.result.exit_code, ms = elapsed as u64,
+    );
+    let section = target.member.map_or(a.name().to_string(), |m| format!("{} ({})", a.name(), m.name));
+    append_repo_log(target.opts, target.path, &section, &out.result);
+    limit_output(&mut out.result, target.opts, &stem, a.name(), errors);
+    let mut out = ToolOutput { started_at: Some(started_at), finished_at: Some(timestamp()), ..out };
+    // Timeouts and failures to run may go away on a retry; only clean completions are kept.
+    if let Some((cache, key)) = &cache {
+        if matches!(out.result.status, ToolStatus::Completed) && out.result.error.is_none() {
+            if let Err(e) = cache.put(key, &out, elapsed) {
+                tracing::warn!("⚠ Warning: failed to cache {} for {}: {:#}", a.name(), stem, e);
+            }
+        }
+    }
+    time_ms.extend(out.phases.drain(..));
+    out
+}
+
+/// What `a`'s output on `target` depends on besides the checkout's commit.
+fn cache_key(a: &dyn Analyzer, target: &RepoTarget, run: &RunManifest, head: &str) -> CacheKey {
+    let opts = target.opts;
+    let mut args = a.fingerprint();
+    args.extend([
+        format!("member={}", target.member.map_or("", |m| m.name.as_str())),
+        format!("rustc={}", run.toolchain.get("rustc").cloned().flatten().unwrap_or_default()),
+        format!("force_toolchain={}", opts.force_toolchain.as_deref().unwrap_or_default()),
+        format!("raw={}", opts.raw),
+        format!("codeql_suite={}", opts.codeql_suite.as_deref().unwrap_or_default()),
+        format!("max_tool_output_bytes={}", opts.max_tool_output_bytes.unwrap_or(DEFAULT_MAX_TOOL_OUTPUT_BYTES)),
+    ]);
+    if opts.fmt_capture_diff && a.name() == "fmt" {
+        args.push("fmt_capture_diff=true".into());
+    }
+    match a.name() {
+        "expand" => args.push(format!("max_expanded_bytes={}", opts.max_expanded_bytes.unwrap_or(DEFAULT_MAX_EXPANDED_BYTES))),
+        "ast" | "hir" | "mir" => args.push(format!("max_ir_bytes={}", opts.max_ir_bytes.unwrap_or(DEFAULT_MAX_IR_BYTES))),
+        _ => {}
+    }
+    if let Some(config) = opts.deny_config.as_ref().filter(|_| a.name() == "deny") {
+        args.push(format!("deny_config={}", config));
+    }
+    CacheKey {
+        head: head.to_string(),
+        tool: a.name().to_string(),
+        version: run.tools.versions.get(a.name()).cloned().flatten(),
+        args,
+    }
+}
+
+/// `<--per-repo-logs>/<name>.log` for the checkout at `path`.
+fn repo_log(opts: &AnalyzeOptions, path: &Path) -> Option<PathBuf> {
+    let dir = opts.per_repo_logs.as_ref()?;
+    Some(Path::new(dir).join(format!("{}.log", path.file_name()?.to_string_lossy())))
+}
+
+/// Appends a tool's full stderr to the repository's `--per-repo-logs` file.
+fn append_repo_log(opts: &AnalyzeOptions, path: &Path, section: &str, result: &ToolResult) {
+    let Some(log) = repo_log(opts, path) else { return };
+    let status = result.exit_code.map_or(format!("{:?}", result.status), |c| format!("exit code {}", c));
+    let text = format!("===== {} ({}) =====\n{}\n", section, status, result.stderr);
+    let res = OpenOptions::new().append(true).create(true).open(&log).and_then(|mut f| std::io::Write::write_all(&mut f, text.as_bytes()));
+    if let Err(e) = res {
+        tracing::warn!("failed to write {}: {}", log.display(), e);
+    }
+}
+
+/// Applies `--max-tool-output-bytes` to a tool's result, keeping the full text in `--overflow-dir`.
+fn limit_output(result: &mut ToolResult, opts: &AnalyzeOptions, stem: &str, tool: &str, errors: &mut Vec<String>) {
+    let max = opts.max_tool_output_bytes.unwrap_or(DEFAULT_MAX_TOOL_OUTPUT_BYTES);
+    if max == 0 {
+        return;
+    }
+    for (stream, full) in result.truncate_output(max) {
+        let Some(dir) = &opts.overflow_dir else { continue };
+        let file = Path::new(dir).join(stem).join(format!("{}.{}", tool, stream));
+        if let Err(e) = fs::create_dir_all(file.parent().unwrap()).and_then(|_| fs::write(&file, full)) {
+            errors.push(format!("{}: failed to write {}: {}", tool, file.display(), e));
+        }
+    }
+}
+
+fn timestamp() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
@@ -0,0 +1,1187 @@
+//! The analysis tools run on every crate, behind a common `Analyzer` trait.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::analyze::AnalyzeOptions;
+use crate::collect::{rust_files, CodeSource};
+use crate::complexity::{file_metrics, ComplexityReport, FileMetrics};
+use crate::layout::CrateTargets;
+use crate::parse::{
+    parse_audit, parse_cargo_test, parse_check, parse_clippy, parse_crev, parse_deny, parse_fmt, parse_geiger, parse_machete, parse_nextest,
+    parse_outdated, parse_sarif, parse_semgrep, parse_tokei, parse_udeps, parse_vet, AuditReport, AuditStatus, CheckReport, ClippyReport, CrevReport,
+    DenyReport, Finding, FmtReport, GeigerReport, GeigerStatus, MacheteReport, OutdatedReport, TestReport, TokeiReport, UdepsReport, VetReport,
+};
+use crate::sandbox::SandboxOptions;
+use crate::targets::{target_error, ExpandReport, IrReport, TargetOutcome, TargetSource, DEFAULT_MAX_EXPANDED_BYTES, DEFAULT_MAX_IR_BYTES};
+use crate::tool::{run_cargo, run_in, Limits, ResourceUsage, TimedOut, ToolEnv, ToolResult, ToolStatus};
+use crate::tools_config::load_tools_config;
+use crate::workspace::Member;
+
+/// One analysis tool. Its output is stored in `OutputEntry::tools` under `name()`, and its
+/// wall-clock time in `time_ms` under the same key.
+pub trait Analyzer: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn run(&self, target: &RepoTarget) -> anyhow::Result<ToolOutput>;
+
+    /// Analyzers that are off by default run only when named in `--enable`.
+    fn enabled_by_default(&self) -> bool {
+        true
+    }
+
+    /// Lockfile-level analyzers run once per checkout; every workspace member shares the result.
+    fn per_workspace(&self) -> bool {
+        false
+    }
+
+    /// Status recorded when the manifest's version probe found the tool missing.
+    fn missing_status(&self) -> ToolStatus {
+        ToolStatus::ToolNotInstalled
+    }
+
+    /// Whether the tool may execute code from the repository (build scripts, proc macros), and
+    /// so runs inside `--sandbox`. Tools that only read files stay on the host, with network.
+    fn needs_sandbox(&self) -> bool {
+        true
+    }
+
+    /// Whether the tool needs a Cargo project, and so is recorded as `not_a_cargo_project`
+    /// instead of run in a checkout without a `Cargo.toml`.
+    fn needs_cargo_project(&self) -> bool {
+        true
+    }
+
+    /// Whether the tool needs the repository's dependencies downloaded, and so is recorded as
+    /// `fetch_failed` instead of run when `cargo fetch` failed.
+    fn needs_dependencies(&self) -> bool {
+        true
+    }
+
+    /// Whether the tool builds the crate, and so is recorded as `skipped_build_failed` instead of
+    /// run when `check` found it doesn't build, unless `--keep-going-on-build-failure`.
+    fn needs_build(&self) -> bool {
+        false
+    }
+
+    /// Configuration beyond the name that changes the tool's output, part of its `--cache-dir` key.
+    fn fingerprint(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// The command `--dry-run` shows for the tool, as run on a crate outside a workspace; `None`
+    /// for analyzers computed in process.
+    fn command(&self, _opts: &AnalyzeOptions) -> Option<String> {
+        None
+    }
+
+    /// Seconds the tool may take, unless `--repo-timeout` runs out first; `None` for no limit.
+    fn timeout_secs(&self, opts: &AnalyzeOptions) -> Option<u64> {
+        opts.tool_timeout
+    }
+}
+
+/// The crate an analyzer runs on, with the helpers to invoke tools on it under the run's deadlines.
+pub struct RepoTarget<'a> {
+    /// Checkout root
+    pub path: &'a Path,
+    /// The Cargo project's directory, where cargo is always invoked: the checkout root, or the
+    /// first-level subdirectory with the `Cargo.toml`; `None` when there is none
+    pub project: Option<&'a Path>,
+    /// Directory of the crate: the project's, or a workspace member's directory
+    pub dir: PathBuf,
+    /// The workspace member under analysis, selected with `-p`; `None` outside workspaces
+    pub member: Option<&'a Member>,
+    pub opts: &'a AnalyzeOptions,
+    /// Where tool processes run: `opts.sandbox`, or the host for analyzers that don't need it
+    pub sandbox: &'a SandboxOptions,
+    /// `CARGO_TARGET_DIR` for cargo invocations, from `--shared-target-dir`; `None` builds into the checkout
+    pub target_dir: Option<&'a Path>,
+    /// The variables every tool process gets, from `AnalyzeOptions::tool_vars`
+    pub vars: &'a [(String, String)],
+    pub(crate) limits: Limits,
+    pub(crate) repo_start: Instant,
+}
+
+impl RepoTarget<'_> {
+    /// Deadline for a tool starting now.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.limits.deadline(self.repo_start)
+    }
+
+    pub fn toolchain(&self) -> Option<&str> {
+        self.opts.force_toolchain.as_deref()
+    }
+
+    /// `cargo <subcommand> [-p <member>] <args>`, so workspace members are analyzed on their own.
+    pub fn cargo(&self, args: &[&str]) -> anyhow::Result<ToolResult> {
+        self.cargo_on(self.toolchain(), args)
+    }
+
+    /// Like `cargo`, under an explicit toolchain.
+    pub fn cargo_on(&self, toolchain: Option<&str>, args: &[&str]) -> anyhow::Result<ToolResult> {
+        let mut args = args.to_vec();
+        if let Some(m) = self.member {
+            args.splice(1..1, ["-p", m.name.as_str()]);
+        }
+        run_cargo(self.sandbox, self.path, self.cargo_dir(), toolchain, &args, self.env(self.target_dir), self.deadline())
+    }
+
+    /// `cargo <args>` for the whole checkout, without selecting a member.
+    pub fn cargo_root(&self, args: &[&str]) -> anyhow::Result<ToolResult> {
+        run_cargo(self.sandbox, self.path, self.cargo_dir(), self.toolchain(), args, self.env(self.target_dir), self.deadline())
+    }
+
+    /// `cargo_root` with `KEY=VALUE` variables set, through `env` so they reach a sandboxed cargo too.
+    pub fn cargo_root_with_env(&self, env: &[&str], args: &[&str]) -> anyhow::Result<ToolResult> {
+        let toolchain = self.toolchain().map(|tc| format!("+{}", tc));
+        let args: Vec<&str> = env.iter().copied().chain(["cargo"]).chain(toolchain.as_deref()).chain(args.iter().copied()).collect();
+        run_in(self.sandbox, self.path, self.cargo_dir(), "env", &args, self.env(self.target_dir), self.deadline())
+    }
+
+    fn cargo_dir(&self) -> &Path {
+        self.project.unwrap_or(self.path)
+    }
+
+    pub(crate) fn env<'t>(&'t self, target_dir: Option<&'t Path>) -> ToolEnv<'t> {
+        ToolEnv { target_dir, vars: self.vars }
+    }
+
+    /// A non-cargo tool, run in the crate's directory.
+    pub fn command(&self, cmd: &str, args: &[&str]) -> anyhow::Result<ToolResult> {
+        run_in(self.sandbox, self.path, &self.dir, cmd, args, self.env(None), self.deadline())
+    }
+}
+
+/// What an analyzer produced for one crate.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ToolOutput {
+    #[serde(flatten)]
+    pub result: ToolResult,
+    /// Structured view of the output, for tools that have a parser
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report: Option<Report>,
+    /// SAST findings; collected into the entry-level `findings` list
+    #[serde(skip)]
+    pub findings: Vec<Finding>,
+    /// Per-file metrics of `complexity`; written to `metrics.jsonl` instead of the entry
+    #[serde(skip)]
+    pub file_metrics: Vec<FileMetrics>,
+    /// Per-target output of `expand`, `ast`, `hir` and `mir`; written to `expanded.jsonl` or
+    /// `ir.jsonl` instead of the entry
+    #[serde(skip)]
+    pub sources: Vec<TargetSource>,
+    /// Separately timed phases (e.g. `codeql_create`); merged into the entry's `time_ms`
+    #[serde(skip)]
+    pub phases: Vec<(String, u128)>,
+    /// When the analyzer started and finished (RFC 3339, UTC); absent for tools that didn't run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<String>,
+    /// Reused from `--cache-dir` instead of run; the timestamps and `time_ms` are the original run's
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+}
+
+impl From<ToolResult> for ToolOutput {
+    fn from(result: ToolResult) -> Self {
+        ToolOutput { result, report: None, findings: Vec::new(), file_metrics: Vec::new(), sources: Vec::new(), phases: Vec::new(), started_at: None, finished_at: None, cached: false }
+    }
+}
+
+impl ToolOutput {
+    /// Parses stdout into `findings`, keeping the raw output and flagging it when that fails.
+    fn parse_findings(&mut self, raw: bool, parse: impl Fn(&str) -> Option<Vec<Finding>>) {
+        match parse(&self.result.stdout) {
+            Some(parsed) => {
+                self.findings = parsed;
+                if !raw {
+                    self.result.stdout.clear();
+                }
+            }
+            None => self.result.parse_error = true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Report {
+    Check(CheckReport),
+    Clippy(ClippyReport),
+    Fmt(FmtReport),
+    Audit(AuditReport),
+    Geiger(GeigerReport),
+    Deny(DenyReport),
+    Test(TestReport),
+    Udeps(UdepsReport),
+    Outdated(OutdatedReport),
+    Machete(MacheteReport),
+    Tokei(TokeiReport),
+    Complexity(ComplexityReport),
+    Vet(VetReport),
+    Crev(CrevReport),
+    Expand(ExpandReport),
+    Ir(IrReport),
+    /// Output of a `format = "json"` tool from `--tools-config`
+    Json(serde_json::Value),
+}
+
+/// Every analyzer shipped with dataset_builder, in the order they run.
+pub fn builtin_analyzers() -> Vec<Box<dyn Analyzer>> {
+    vec![
+        Box::new(Check),
+        Box::new(Clippy),
+        Box::new(Fmt),
+        Box::new(Audit),
+        Box::new(Auditable),
+        Box::new(Deny),
+        Box::new(Geiger),
+        Box::new(Tree),
+        Box::new(Test),
+        Box::new(Udeps),
+        Box::new(Outdated),
+        Box::new(Machete),
+        Box::new(Tokei),
+        Box::new(Complexity),
+        Box::new(Vet),
+        Box::new(Crev),
+        Box::new(Expand),
+        Box::new(Ir { name: "ast", unpretty: "ast-tree", by_default: true }),
+        Box::new(Ir { name: "hir", unpretty: "hir", by_default: false }),
+        Box::new(Ir { name: "mir", unpretty: "mir", by_default: false }),
+        Box::new(Semgrep),
+        Box::new(Codeql),
+    ]
+}
+
+/// The analyzers of a run, split into those that run and those recorded as skipped.
+pub struct Selection {
+    /// In run order
+    pub run: Vec<Box<dyn Analyzer>>,
+    /// Names of the known analyzers left out by `--only`/`--skip`, or opt-in and not enabled
+    pub skipped: Vec<String>,
+}
+
+/// Splits the built-in and `--tools-config` analyzers by `--only`/`--enable`/`--skip`,
+/// rejecting names no analyzer has.
+pub fn select_analyzers(opts: &AnalyzeOptions) -> anyhow::Result<Selection> {
+    let mut all = builtin_analyzers();
+    if let Some(path) = &opts.tools_config {
+        all.extend(load_tools_config(path)?.into_iter().map(|t| Box::new(t) as Box<dyn Analyzer>));
+    }
+    let mut enable: Vec<&str> = opts.enable.iter().map(String::as_str).collect();
+    if opts.include_tree_text {
+        enable.push("tree");
+    }
+    if opts.with_tests {
+        enable.push("test");
+    }
+    enable.extend(opts.emit.iter().map(String::as_str));
+    let known: Vec<&str> = all.iter().map(|a| a.name()).collect();
+    for (flag, name) in opts.only.iter().map(|n| ("--only", n.as_str()))
+        .chain(enable.iter().map(|n| ("--enable", *n)))
+        .chain(opts.skip.iter().map(|n| ("--skip", n.as_str())))
+    {
+        if !known.contains(&name) {
+            let hint = closest(name, &known).map(|k| format!("; did you mean `{}`?", k)).unwrap_or_default();
+            anyhow::bail!("{}: unknown analyzer `{}`{} (known: {})", flag, name, hint, known.join(", "));
+        }
+    }
+    let selected = |a: &dyn Analyzer| {
+        let wanted = if opts.only.is_empty() { a.enabled_by_default() } else { opts.only.iter().any(|n| n == a.name()) };
+        (wanted || enable.contains(&a.name())) && !opts.skip.iter().any(|n| n == a.name())
+    };
+    let (run, skipped): (Vec<_>, Vec<_>) = all.into_iter().partition(|a| selected(a.as_ref()));
+    Ok(Selection { run, skipped: skipped.iter().map(|a| a.name().to_string()).collect() })
+}
+
+/// The known name closest to a misspelt one, if any is within a couple of edits.
+fn closest<'a>(name: &str, known: &[&'a str]) -> Option<&'a str> {
+    known.iter()
+        .map(|k| (edit_distance(name, k), *k))
+        .filter(|&(d, k)| d <= 2.min(k.len() / 2))
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, k)| k)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = (prev + usize::from(ca != cb)).min(row[j] + 1).min(cur + 1);
+            prev = cur;
+        }
+    }
+    row[b.len()]
+}
+
+/// `cargo [+toolchain] <args>` as `--dry-run` shows it.
+fn cargo_line(toolchain: Option<&str>, args: &[&str]) -> Option<String> {
+    let toolchain = toolchain.map(|tc| format!("+{}", tc));
+    Some(["cargo"].into_iter().chain(toolchain.as_deref()).chain(args.iter().copied()).collect::<Vec<_>>().join(" "))
+}
+
+/// The nightly the AST dump runs under: a forced nightly toolchain, or plain `nightly`.
+pub(crate) fn nightly_toolchain(forced: Option<&str>) -> &str {
+    forced.filter(|t| t.starts_with("nightly")).unwrap_or("nightly")
+}
+
+/// Whether the crate builds, run first so the tools that build it can be skipped when it doesn't.
+struct Check;
+
+impl Analyzer for Check {
+    fn name(&self) -> &str {
+        "check"
+    }
+
+    fn command(&self, opts: &AnalyzeOptions) -> Option<String> {
+        cargo_line(opts.force_toolchain.as_deref(), &["check", "--message-format=json"])
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        let mut result = t.cargo(&["check", "--message-format=json"])?;
+        let report = parse_check(&result);
+        if !t.opts.raw {
+            result.stdout.clear();
+        }
+        Ok(ToolOutput { report: Some(Report::Check(report)), ..result.into() })
+    }
+}
+
+struct Clippy;
+
+impl Analyzer for Clippy {
+    fn name(&self) -> &str {
+        "clippy"
+    }
+
+    fn command(&self, opts: &AnalyzeOptions) -> Option<String> {
+        cargo_line(opts.force_toolchain.as_deref(), &["clippy", "--message-format=json"])
+    }
+
+    fn needs_build(&self) -> bool {
+        true
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        let mut result = t.cargo(&["clippy", "--message-format=json"])?;
+        let report = parse_clippy(&result);
+        if !t.opts.raw {
+            result.stdout.clear();
+        }
+        Ok(ToolOutput { report: Some(Report::Clippy(report)), ..result.into() })
+    }
+}
+
+struct Fmt;
+
+impl Analyzer for Fmt {
+    fn name(&self) -> &str {
+        "fmt"
+    }
+
+    fn command(&self, opts: &AnalyzeOptions) -> Option<String> {
+        cargo_line(opts.force_toolchain.as_deref(), &["fmt", "--", "--check", "--color", "never"])
+    }
+
+    fn needs_dependencies(&self) -> bool {
+        false
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        let mut result = t.cargo(&["fmt", "--", "--check", "--color", "never"])?;
+        // rustfmt prints absolute paths.
+        let root = fs::canonicalize(t.path).unwrap_or_else(|_| t.path.to_path_buf());
+        let report = parse_fmt(&result, &root, t.opts.fmt_capture_diff);
+        if !t.opts.raw && !report.failed {
+            result.stdout.clear();
+        }
+        Ok(ToolOutput { report: Some(Report::Fmt(report)), ..result.into() })
+    }
+}
+
+struct Audit;
+
+impl Analyzer for Audit {
+    fn name(&self) -> &str {
+        "audit"
+    }
+
+    fn command(&self, opts: &AnalyzeOptions) -> Option<String> {
+        cargo_line(opts.force_toolchain.as_deref(), &["audit", "--json"])
+    }
+
+    fn needs_dependencies(&self) -> bool {
+        false
+    }
+
+    fn needs_sandbox(&self) -> bool {
+        false
+    }
+
+    fn per_workspace(&self) -> bool {
+        true
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        let mut result = t.cargo_root(&["audit", "--json"])?;
+        let report = parse_audit(&result);
+        if !t.opts.raw && matches!(report.status, AuditStatus::Ok) {
+            result.stdout.clear();
+        }
+        Ok(ToolOutput { report: Some(Report::Audit(report)), ..result.into() })
+    }
+}
+
+struct Auditable;
+
+impl Analyzer for Auditable {
+    fn name(&self) -> &str {
+        "auditable"
+    }
+
+    fn command(&self, opts: &AnalyzeOptions) -> Option<String> {
+        cargo_line(opts.force_toolchain.as_deref(), &["auditable"])
+    }
+
+    fn per_workspace(&self) -> bool {
+        true
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        Ok(t.cargo_root(&["auditable"])?.into())
+    }
+}
+
+/// The config `deny` writes for repositories without one, unless `--deny-config` names another:
+/// RustSec advisories, licenses against the permissive ones common on crates.io, and warnings for
+/// duplicate versions and wildcard requirements.
+const DEFAULT_DENY_CONFIG: &str = r#"[advisories]
+version = 2
+yanked = "warn"
+
+[licenses]
+version = 2
+allow = [
+    "MIT", "Apache-2.0", "Apache-2.0 WITH LLVM-exception", "BSD-2-Clause", "BSD-3-Clause", "ISC", "Zlib",
+    "0BSD", "BSL-1.0", "CC0-1.0", "MPL-2.0", "Unicode-3.0", "Unicode-DFS-2016", "Unlicense",
+]
+confidence-threshold = 0.8
+
+[bans]
+multiple-versions = "warn"
+wildcards = "warn"
+"#;
+
+/// The checks the built-in config sets up; a repository's own config, or `--deny-config`, runs them all.
+const DEFAULT_DENY_CHECKS: [&str; 3] = ["advisories", "bans", "licenses"];
+
+/// First line of the `deny.toml`s written for a run.
+const DENY_CONFIG_MARKER: &str = "# Written by dataset_builder for one cargo-deny run; not the repository's.";
+
+/// The names cargo-deny looks for, in the manifest's directory and every one above it.
+const DENY_CONFIG_FILES: [&str; 3] = ["deny.toml", ".deny.toml", ".cargo/deny.toml"];
+
+struct Deny;
+
+impl Analyzer for Deny {
+    fn name(&self) -> &str {
+        "deny"
+    }
+
+    fn command(&self, opts: &AnalyzeOptions) -> Option<String> {
+        let mut args = vec!["deny", "--format", "json", "check"];
+        if opts.deny_config.is_none() {
+            args.extend(DEFAULT_DENY_CHECKS);
+        }
+        cargo_line(opts.force_toolchain.as_deref(), &args)
+    }
+
+    fn needs_sandbox(&self) -> bool {
+        false
+    }
+
+    /// Without a config of its own the crate gets `DEFAULT_DENY_CONFIG` (or `--deny-config`) as a
+    /// `deny.toml` beside its manifest for the run, since cargo-deny refuses to check without one.
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        let written = t.dir.join(DENY_CONFIG_FILES[0]);
+        // One left behind by a run that was killed isn't the repository's.
+        let own_config = t.dir.ancestors().take_while(|d| d.starts_with(t.path)).any(|d| {
+            DENY_CONFIG_FILES.iter().map(|f| d.join(f)).any(|f| f.is_file() && !fs::read_to_string(&f).is_ok_and(|c| c.starts_with(DENY_CONFIG_MARKER)))
+        });
+        if !own_config {
+            let config = match &t.opts.deny_config {
+                Some(path) => fs::read_to_string(path).with_context(|| format!("failed to read {}", path))?,
+                None => DEFAULT_DENY_CONFIG.to_string(),
+            };
+            fs::write(&written, format!("{}\n{}", DENY_CONFIG_MARKER, config)).with_context(|| format!("failed to write {}", written.display()))?;
+        }
+        let mut args = vec!["deny", "--format", "json"];
+        if let Some(m) = t.member {
+            args.extend(["--manifest-path", m.manifest_path.as_str()]);
+        }
+        args.push("check");
+        if !own_config && t.opts.deny_config.is_none() {
+            args.extend(DEFAULT_DENY_CHECKS);
+        }
+        let result = t.cargo_root(&args);
+        if !own_config {
+            let _ = fs::remove_file(&written);
+        }
+        let result = result?;
+        let report = parse_deny(&result, own_config);
+        Ok(ToolOutput { report: Some(Report::Deny(report)), ..result.into() })
+    }
+}
+
+struct Geiger;
+
+impl Analyzer for Geiger {
+    fn name(&self) -> &str {
+        "geiger"
+    }
+
+    fn command(&self, opts: &AnalyzeOptions) -> Option<String> {
+        cargo_line(opts.force_toolchain.as_deref(), &["geiger", "--output-format", "Json"])
+    }
+
+    fn needs_build(&self) -> bool {
+        true
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        // geiger learns which files a crate uses from the rustc invocations of its build, so units
+        // already fresh in a shared target dir would go uncounted; it builds into the checkout.
+        let t = RepoTarget { dir: t.dir.clone(), target_dir: None, ..*t };
+        let mut result = t.cargo(&["geiger", "--output-format", "Json"])?;
+        let report = parse_geiger(&result);
+        if !t.opts.raw && matches!(report.geiger_status, GeigerStatus::Ok) {
+            result.stdout.clear();
+        }
+        Ok(ToolOutput { report: Some(Report::Geiger(report)), ..result.into() })
+    }
+}
+
+/// The `cargo tree` text; `dep_graph` has the same information in structured form.
+struct Tree;
+
+impl Analyzer for Tree {
+    fn name(&self) -> &str {
+        "tree"
+    }
+
+    fn command(&self, opts: &AnalyzeOptions) -> Option<String> {
+        cargo_line(opts.force_toolchain.as_deref(), &["tree"])
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        Ok(t.cargo(&["tree"])?.into())
+    }
+}
+
+/// The crate's tests, with `cargo nextest` when it is installed where they run. Opt-in: it runs
+/// the repository's code, and takes the longest of all the analyzers.
+struct Test;
+
+impl Analyzer for Test {
+    fn name(&self) -> &str {
+        "test"
+    }
+
+    // `cargo test --no-fail-fast --message-format json` where cargo-nextest isn't installed.
+    fn command(&self, opts: &AnalyzeOptions) -> Option<String> {
+        cargo_line(opts.force_toolchain.as_deref(), &["nextest", "run", "--no-fail-fast", "--message-format", "libtest-json"])
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    fn needs_build(&self) -> bool {
+        true
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        let start = Instant::now();
+        let nextest = t.cargo_root(&["nextest", "--version"]).is_ok_and(|r| r.exit_code == Some(0));
+        let (mut result, mut report) = if nextest {
+            let mut args = vec!["nextest", "run", "--no-fail-fast", "--message-format", "libtest-json"];
+            if let Some(m) = t.member {
+                args.extend(["-p", m.name.as_str()]);
+            }
+            let result = t.cargo_root_with_env(&["NEXTEST_EXPERIMENTAL_LIBTEST_JSON=1"], &args)?;
+            let report = parse_nextest(&result);
+            (result, report)
+        } else {
+            let result = t.cargo(&["test", "--no-fail-fast", "--message-format", "json"])?;
+            let report = parse_cargo_test(&result);
+            (result, report)
+        };
+        report.duration_ms = start.elapsed().as_millis() as u64;
+        if !t.opts.raw {
+            result.stdout.clear();
+        }
+        Ok(ToolOutput { report: Some(Report::Test(report)), ..result.into() })
+    }
+}
+
+/// Attaches a parsed report to `result`, or flags the output when it has none.
+fn hygiene_output<R>(mut result: ToolResult, report: Option<R>, raw: bool, wrap: fn(R) -> Report) -> ToolOutput {
+    match report {
+        Some(report) => {
+            if !raw {
+                result.stdout.clear();
+            }
+            ToolOutput { report: Some(wrap(report)), ..result.into() }
+        }
+        None => {
+            result.parse_error = true;
+            result.into()
+        }
+    }
+}
+
+/// Dependencies the crate declares but never uses, found by building it with `cargo udeps` on
+/// nightly. Opt-in, like the two below.
+struct Udeps;
+
+impl Analyzer for Udeps {
+    fn name(&self) -> &str {
+        "udeps"
+    }
+
+    fn command(&self, opts: &AnalyzeOptions) -> Option<String> {
+        cargo_line(Some(nightly_toolchain(opts.force_toolchain.as_deref())), &["udeps", "--output", "json"])
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    fn needs_build(&self) -> bool {
+        true
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        let result = t.cargo_on(Some(nightly_toolchain(t.toolchain())), &["udeps", "--output", "json"])?;
+        if result.stderr.contains("is not installed") && result.stderr.contains("toolchain") {
+            return Ok(ToolResult::with_status(ToolStatus::SkippedNoNightly).into());
+        }
+        // Manifest paths are absolute.
+        let root = fs::canonicalize(t.path).unwrap_or_else(|_| t.path.to_path_buf());
+        let report = parse_udeps(&result.stdout, &root);
+        Ok(hygiene_output(result, report, t.opts.raw, Report::Udeps))
+    }
+}
+
+/// Direct dependencies behind their latest release on crates.io. It reads the registry index,
+/// so it runs on the host with the network on.
+struct Outdated;
+
+impl Analyzer for Outdated {
+    fn name(&self) -> &str {
+        "outdated"
+    }
+
+    fn command(&self, opts: &AnalyzeOptions) -> Option<String> {
+        cargo_line(opts.force_toolchain.as_deref(), &["outdated", "--root-deps-only", "--format", "json"])
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    fn needs_sandbox(&self) -> bool {
+        false
+    }
+
+    fn needs_dependencies(&self) -> bool {
+        false
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        let manifest = t.member.map(|_| t.dir.join("Cargo.toml").display().to_string());
+        let mut args = vec!["outdated", "--root-deps-only", "--format", "json"];
+        if let Some(m) = &manifest {
+            args.extend(["--manifest-path", m.as_str()]);
+        }
+        // Without the variable that keeps the rest of the run's cargo offline.
+        let online: Vec<(String, String)> = t.vars.iter().filter(|(var, _)| var != "CARGO_NET_OFFLINE").cloned().collect();
+        let result = RepoTarget { dir: t.dir.clone(), vars: &online, ..*t }.cargo_root(&args)?;
+        let report = parse_outdated(&result.stdout);
+        Ok(hygiene_output(result, report, t.opts.raw, Report::Outdated))
+    }
+}
+
+/// Unused dependencies by a search of the sources for their names: faster than `udeps` and
+/// needs no build, with false positives for crates only used through macros.
+struct Machete;
+
+impl Analyzer for Machete {
+    fn name(&self) -> &str {
+        "machete"
+    }
+
+    fn command(&self, opts: &AnalyzeOptions) -> Option<String> {
+        cargo_line(opts.force_toolchain.as_deref(), &["machete"])
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    fn per_workspace(&self) -> bool {
+        true
+    }
+
+    fn needs_sandbox(&self) -> bool {
+        false
+    }
+
+    fn needs_dependencies(&self) -> bool {
+        false
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        let result = t.cargo_root(&["machete"])?;
+        let root = fs::canonicalize(t.path).unwrap_or_else(|_| t.path.to_path_buf());
+        let report = parse_machete(&result.stdout, &root);
+        Ok(hygiene_output(result, report, t.opts.raw, Report::Machete))
+    }
+}
+
+/// Lines of code, comments and blanks per language in the checkout. Opt-in, like `complexity`.
+struct Tokei;
+
+impl Analyzer for Tokei {
+    fn name(&self) -> &str {
+        "tokei"
+    }
+
+    fn command(&self, _opts: &AnalyzeOptions) -> Option<String> {
+        Some("tokei --output json --exclude target {repo}".into())
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    fn per_workspace(&self) -> bool {
+        true
+    }
+
+    fn needs_sandbox(&self) -> bool {
+        false
+    }
+
+    fn needs_cargo_project(&self) -> bool {
+        false
+    }
+
+    fn needs_dependencies(&self) -> bool {
+        false
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        let path = t.path.display().to_string();
+        let result = t.command("tokei", &["--output", "json", "--exclude", "target", &path])?;
+        let report = parse_tokei(&result.stdout);
+        Ok(hygiene_output(result, report, t.opts.raw, Report::Tokei))
+    }
+}
+
+/// Complexity of the checkout's `.rs` files, as `collect` finds them, computed in-process with
+/// no tool to install. Files that don't parse are only counted.
+struct Complexity;
+
+impl Analyzer for Complexity {
+    fn name(&self) -> &str {
+        "complexity"
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    fn per_workspace(&self) -> bool {
+        true
+    }
+
+    fn needs_sandbox(&self) -> bool {
+        false
+    }
+
+    fn needs_cargo_project(&self) -> bool {
+        false
+    }
+
+    fn needs_dependencies(&self) -> bool {
+        false
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        let start = Instant::now();
+        let dir = t.path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let source = CodeSource { name: dir, name_unresolved: false, repo: None, commit: None };
+        let mut report = ComplexityReport::default();
+        let mut files = Vec::new();
+        for (_, file) in rust_files(t.path, &source, &[])? {
+            if t.deadline().is_some_and(|d| Instant::now() >= d) {
+                return Err(TimedOut(start.elapsed()).into());
+            }
+            match file_metrics(&file.path, &file.content) {
+                Ok(metrics) => {
+                    report.add(&metrics);
+                    files.push(metrics);
+                }
+                Err(_) => report.parse_failed += 1,
+            }
+        }
+        Ok(ToolOutput { report: Some(Report::Complexity(report)), file_metrics: files, ..ToolResult::with_status(ToolStatus::Completed).into() })
+    }
+}
+
+/// The store cargo-vet is pointed at for a repository without `supply-chain/config.toml`: no
+/// audits, imports or exemptions, so every third-party dependency shows up as unreviewed.
+const EMPTY_VET_STORE: [(&str, &str); 3] = [("config.toml", "[cargo-vet]\nversion = \"0.10\"\n"), ("audits.toml", ""), ("imports.lock", "")];
+
+/// cargo's answer when a subcommand's binary isn't on PATH, in case the probe saw another PATH.
+fn no_such_command(result: &ToolResult) -> bool {
+    result.exit_code != Some(0) && result.stderr.contains("no such command")
+}
+
+/// Name and version of every lockfile package that doesn't come from the checkout itself.
+fn third_party_packages(lockfile: &Path) -> Vec<(String, String)> {
+    let Some(lock) = fs::read_to_string(lockfile).ok().and_then(|l| l.parse::<toml::Table>().ok()) else { return Vec::new() };
+    let packages = lock.get("package").and_then(|p| p.as_array()).cloned().unwrap_or_default();
+    packages
+        .iter()
+        .filter(|p| p.get("source").is_some())
+        .filter_map(|p| Some((p.get("name")?.as_str()?.to_string(), p.get("version")?.as_str()?.to_string())))
+        .collect()
+}
+
+/// Whether each dependency is audited, exempted or unreviewed by cargo-vet. A repository without
+/// a store of its own is checked against an empty one in a temporary directory, so nothing is
+/// written to the checkout. Opt-in, like `crev`.
+struct Vet;
+
+impl Analyzer for Vet {
+    fn name(&self) -> &str {
+        "vet"
+    }
+
+    fn command(&self, opts: &AnalyzeOptions) -> Option<String> {
+        cargo_line(opts.force_toolchain.as_deref(), &["vet", "--locked", "--output-format", "json"])
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    fn per_workspace(&self) -> bool {
+        true
+    }
+
+    fn needs_sandbox(&self) -> bool {
+        false
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        let root = t.project.unwrap_or(t.path);
+        let own_config = root.join("supply-chain/config.toml").is_file();
+        let dir = t.path.file_name().unwrap_or_default().to_string_lossy();
+        let store = std::env::temp_dir().join(format!("dataset_builder_vet_{}_{}", std::process::id(), dir));
+        let store_arg = store.display().to_string();
+        let mut args = vec!["vet", "--locked", "--output-format", "json"];
+        if !own_config {
+            fs::create_dir_all(&store).with_context(|| format!("failed to create {}", store.display()))?;
+            for (file, contents) in EMPTY_VET_STORE {
+                fs::write(store.join(file), contents).with_context(|| format!("failed to write {}", store.join(file).display()))?;
+            }
+            args.extend(["--store-path", store_arg.as_str()]);
+        }
+        let result = t.cargo_root(&args);
+        if !own_config {
+            let _ = fs::remove_dir_all(&store);
+        }
+        let result = result?;
+        if no_such_command(&result) {
+            return Ok(ToolResult::with_status(ToolStatus::ToolNotInstalled).into());
+        }
+        let report = parse_vet(&result.stdout, &third_party_packages(&root.join("Cargo.lock")), own_config);
+        Ok(hygiene_output(result, report, t.opts.raw, Report::Vet))
+    }
+}
+
+/// crev review coverage of the dependencies, from the proofs already in the local crev
+/// database; none are fetched.
+struct Crev;
+
+impl Analyzer for Crev {
+    fn name(&self) -> &str {
+        "crev"
+    }
+
+    fn command(&self, opts: &AnalyzeOptions) -> Option<String> {
+        cargo_line(opts.force_toolchain.as_deref(), &["crev", "verify"])
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    fn per_workspace(&self) -> bool {
+        true
+    }
+
+    fn needs_sandbox(&self) -> bool {
+        false
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        let result = t.cargo_root(&["crev", "verify"])?;
+        if no_such_command(&result) {
+            return Ok(ToolResult::with_status(ToolStatus::ToolNotInstalled).into());
+        }
+        let report = parse_crev(&result.stdout);
+        Ok(hygiene_output(result, report, t.opts.raw, Report::Crev))
+    }
+}
+
+/// What a nightly cargo subcommand printed for each library and binary target of a crate.
+struct TargetRuns {
+    package: String,
+    /// The first run that failed, else the last one; their stdout is in `sources`
+    result: ToolResult,
+    targets: Vec<TargetOutcome>,
+    sources: Vec<TargetSource>,
+}
+
+/// Runs `cargo +nightly <before> --lib|--bin NAME <after>` once per target of the crate, so a
+/// target that doesn't compile leaves the others' output, each cut at `max` bytes. `Err` is the
+/// result to record without a report: a crate without a package, no nightly, no such subcommand.
+fn run_per_target(t: &RepoTarget, before: &[&str], after: &[&str], max: usize) -> anyhow::Result<Result<TargetRuns, ToolResult>> {
+    let Some(crate_targets) = CrateTargets::read(&t.dir) else { return Ok(Err(ToolResult::with_status(ToolStatus::Completed))) };
+    let mut runs = TargetRuns { package: crate_targets.package.clone(), result: ToolResult::with_status(ToolStatus::Completed), targets: Vec::new(), sources: Vec::new() };
+    let mut failed = false;
+    for target in crate_targets.names() {
+        let mut args = before.to_vec();
+        match target.strip_prefix("bin:") {
+            Some(bin) => args.extend(["--bin", bin]),
+            None => args.push("--lib"),
+        }
+        args.extend(after);
+        let mut result = t.cargo_on(Some(nightly_toolchain(t.toolchain())), &args)?;
+        if result.stderr.contains("is not installed") && result.stderr.contains("toolchain") {
+            return Ok(Err(ToolResult::with_status(ToolStatus::SkippedNoNightly)));
+        }
+        if no_such_command(&result) {
+            return Ok(Err(ToolResult::with_status(ToolStatus::ToolNotInstalled)));
+        }
+        let content = std::mem::take(&mut result.stdout);
+        match (&result.status, result.exit_code) {
+            (ToolStatus::Completed, Some(0)) => {
+                let source = TargetSource::new(&runs.package, &target, content, max);
+                runs.targets.push(TargetOutcome { target, bytes: source.bytes, truncated: source.truncated, error: None });
+                runs.sources.push(source);
+                if !failed {
+                    runs.result = result;
+                }
+            }
+            (ToolStatus::Completed, code) => {
+                runs.targets.push(TargetOutcome { target, error: Some(target_error(&result.stderr, code)), ..TargetOutcome::default() });
+                if !failed {
+                    runs.result = result;
+                }
+                failed = true;
+            }
+            // A timeout leaves the rest of the targets out.
+            _ => {
+                runs.result = result;
+                break;
+            }
+        }
+    }
+    Ok(Ok(runs))
+}
+
+/// The source of the crate's library and each of its binaries after macro expansion, with
+/// `cargo expand` on nightly. The sources go to `expanded.jsonl`; the report keeps their sizes and,
+/// for the targets that didn't expand, the error that stopped them.
+struct Expand;
+
+impl Analyzer for Expand {
+    fn name(&self) -> &str {
+        "expand"
+    }
+
+    fn command(&self, opts: &AnalyzeOptions) -> Option<String> {
+        cargo_line(Some(nightly_toolchain(opts.force_toolchain.as_deref())), &["expand", "--color=never", "{target}"])
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        let max = t.opts.max_expanded_bytes.unwrap_or(DEFAULT_MAX_EXPANDED_BYTES);
+        let runs = match run_per_target(t, &["expand", "--color=never"], &[], max)? {
+            Ok(runs) => runs,
+            Err(result) => return Ok(result.into()),
+        };
+        let (expanded, failed) = (runs.sources.len(), runs.targets.len() - runs.sources.len());
+        let report = ExpandReport { package: runs.package, targets: runs.targets, expanded, failed };
+        Ok(ToolOutput { report: Some(Report::Expand(report)), sources: runs.sources, ..runs.result.into() })
+    }
+}
+
+/// A compiler-internal representation of each target, from `cargo +nightly rustc --
+/// -Zunpretty=<format>`: the AST (`ast`, on by default), HIR and MIR (`--emit hir,mir`). The
+/// dumps go to `ir.jsonl`.
+struct Ir {
+    name: &'static str,
+    unpretty: &'static str,
+    by_default: bool,
+}
+
+impl Analyzer for Ir {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn command(&self, opts: &AnalyzeOptions) -> Option<String> {
+        cargo_line(Some(nightly_toolchain(opts.force_toolchain.as_deref())), &["rustc", "{target}", "--", &format!("-Zunpretty={}", self.unpretty)])
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        self.by_default
+    }
+
+    fn missing_status(&self) -> ToolStatus {
+        ToolStatus::SkippedNoNightly
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        let max = t.opts.max_ir_bytes.unwrap_or(DEFAULT_MAX_IR_BYTES);
+        let unpretty = format!("-Zunpretty={}", self.unpretty);
+        let runs = match run_per_target(t, &["rustc"], &["--", &unpretty], max)? {
+            Ok(runs) => runs,
+            Err(result) => return Ok(result.into()),
+        };
+        let (emitted, failed) = (runs.sources.len(), runs.targets.len() - runs.sources.len());
+        let report = IrReport { package: runs.package, targets: runs.targets, emitted, failed };
+        Ok(ToolOutput { report: Some(Report::Ir(report)), sources: runs.sources, ..runs.result.into() })
+    }
+}
+
+struct Semgrep;
+
+impl Analyzer for Semgrep {
+    fn name(&self) -> &str {
+        "semgrep"
+    }
+
+    fn command(&self, _opts: &AnalyzeOptions) -> Option<String> {
+        Some("semgrep --config p/rust --json".into())
+    }
+
+    fn needs_dependencies(&self) -> bool {
+        false
+    }
+
+    fn needs_sandbox(&self) -> bool {
+        false
+    }
+
+    fn needs_cargo_project(&self) -> bool {
+        false
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        let mut out: ToolOutput = t.command("semgrep", &["--config", "p/rust", "--json"])?.into();
+        out.parse_findings(t.opts.raw, parse_semgrep);
+        Ok(out)
+    }
+}
+
+/// Where the CodeQL database is built, inside the checkout so `clean` can find leftovers.
+pub const CODEQL_DB_DIR: &str = ".codeql-db";
+
+/// Creates a CodeQL database for the crate, then analyzes it. If database creation fails its
+/// output is returned instead of the SARIF log, with `error` set.
+struct Codeql;
+
+impl Analyzer for Codeql {
+    fn name(&self) -> &str {
+        "codeql"
+    }
+
+    fn command(&self, opts: &AnalyzeOptions) -> Option<String> {
+        let suite = opts.codeql_suite.as_deref().unwrap_or("codeql/rust-queries");
+        Some(format!("codeql database create {{crate}}/{} --language=rust --source-root=. --overwrite && codeql database analyze {{crate}}/{} {} --format=sarif-latest", CODEQL_DB_DIR, CODEQL_DB_DIR, suite))
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        let db = t.dir.join(CODEQL_DB_DIR);
+        let mut phases = Vec::new();
+        let res = create_and_analyze_codeql(t, &db, &mut phases);
+        if !t.opts.keep_codeql_db {
+            let _ = fs::remove_dir_all(&db);
+        }
+        let mut out = ToolOutput { phases, ..res?.into() };
+        if out.result.error.is_none() {
+            out.parse_findings(t.opts.raw, |log| parse_sarif("codeql", log));
+        }
+        Ok(out)
+    }
+}
+
+fn create_and_analyze_codeql(t: &RepoTarget, db: &Path, phases: &mut Vec<(String, u128)>) -> anyhow::Result<ToolResult> {
+    let db_arg = db.to_string_lossy().into_owned();
+    let start = Instant::now();
+    let mut lang = "rust";
+    let mut create = t.command("codeql", &["database", "create", &db_arg, "--language=rust", "--source-root=.", "--overwrite"]);
+    if create.as_ref().is_ok_and(|r| r.exit_code != Some(0) && missing_codeql_extractor(r)) {
+        // Older CodeQL bundles have no Rust extractor; tracing a cargo build as C/C++ still covers build scripts and FFI.
+        lang = "cpp";
+        create = t.command("codeql", &["database", "create", &db_arg, "--language=cpp", "--source-root=.", "--overwrite", "--command=cargo build"]);
+    }
+    phases.push(("codeql_create".into(), start.elapsed().as_millis()));
+    let create = create?;
+    if create.exit_code != Some(0) {
+        return Ok(ToolResult { error: Some(format!("database create failed (--language={})", lang)), ..create });
+    }
+
+    let start = Instant::now();
+    let sarif = db.join("results.sarif");
+    let suite = t.opts.codeql_suite.clone().unwrap_or_else(|| format!("codeql/{}-queries", lang));
+    let output = format!("--output={}", sarif.display());
+    let analyze = t.command("codeql", &["database", "analyze", &db_arg, &suite, "--format=sarif-latest", &output]);
+    phases.push(("codeql_analyze".into(), start.elapsed().as_millis()));
+    let mut analyze = analyze?;
+    analyze.usage = ResourceUsage::merge(create.usage, analyze.usage);
+    if let Ok(log) = fs::read_to_string(&sarif) {
+        analyze.stdout = log;
+    }
+    Ok(analyze)
+}
+
+fn missing_codeql_extractor(r: &ToolResult) -> bool {
+    let text = format!("{}{}", r.stdout, r.stderr).to_lowercase();
+    text.contains("extractor") || text.contains("not supported") || text.contains("unknown language")
+}
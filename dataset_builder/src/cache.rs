@@ -0,0 +1,142 @@
+//! `--cache-dir`: tool results reused across runs while a checkout's HEAD commit stays the same.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use git2::{Repository, Status, StatusOptions};
+use serde::{Deserialize, Serialize};
+
+use crate::analyzers::{ToolOutput, CODEQL_DB_DIR};
+use crate::complexity::FileMetrics;
+use crate::jsonl::replace_file;
+use crate::parse::Finding;
+use crate::targets::TargetSource;
+
+/// Everything a cached result depends on. It is stored in the entry and compared on lookup, so a
+/// file name collision can never hand out another tool's output.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct CacheKey {
+    /// HEAD commit of the checkout
+    pub head: String,
+    pub tool: String,
+    /// The tool's `--version` from the run manifest; absent for tools without a probe
+    pub version: Option<String>,
+    /// The analyzer's own configuration plus the run options that change its output
+    pub args: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct CacheEntry {
+    key: CacheKey,
+    output: ToolOutput,
+    // Not part of `ToolOutput`'s serialized form, which only the entry-level fields carry.
+    findings: Vec<Finding>,
+    #[serde(default)]
+    file_metrics: Vec<FileMetrics>,
+    #[serde(default)]
+    sources: Vec<TargetSource>,
+    phases: Vec<(String, u128)>,
+    time_ms: u128,
+}
+
+/// A directory of JSON files, one per cached tool result.
+pub struct ResultCache {
+    dir: PathBuf,
+}
+
+impl ResultCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        ResultCache { dir: dir.into() }
+    }
+
+    /// The cached output for `key`, marked `cached`, and the time the tool originally took.
+    pub fn get(&self, key: &CacheKey) -> Option<(ToolOutput, u128)> {
+        let path = self.path(key);
+        let entry: CacheEntry = serde_json::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+        if entry.key != *key {
+            return None;
+        }
+        // A hit counts as a use for `cache-gc`.
+        let _ = fs::File::options().append(true).open(&path).and_then(|f| f.set_modified(SystemTime::now()));
+        let output = ToolOutput { findings: entry.findings, file_metrics: entry.file_metrics, sources: entry.sources, phases: entry.phases, cached: true, ..entry.output };
+        Some((output, entry.time_ms))
+    }
+
+    pub fn put(&self, key: &CacheKey, output: &ToolOutput, time_ms: u128) -> anyhow::Result<()> {
+        let path = self.path(key);
+        fs::create_dir_all(path.parent().unwrap())?;
+        let entry = CacheEntry {
+            key: key.clone(),
+            output: output.clone(),
+            findings: output.findings.clone(),
+            file_metrics: output.file_metrics.clone(),
+            sources: output.sources.clone(),
+            phases: output.phases.clone(),
+            time_ms,
+        };
+        replace_file(&path.to_string_lossy(), &serde_json::to_string(&entry)?)
+    }
+
+    /// The `(head, tool)` of every entry, for `--dry-run` to tell which checkouts would be served
+    /// from the cache. The versions in the keys aren't compared: that takes the tools' probes.
+    pub(crate) fn heads(&self) -> HashSet<(String, String)> {
+        let files = fs::read_dir(&self.dir).into_iter().flatten().flatten().filter_map(|shard| fs::read_dir(shard.path()).ok()).flatten().flatten();
+        files
+            .filter_map(|f| serde_json::from_str::<CacheEntry>(&fs::read_to_string(f.path()).ok()?).ok())
+            .map(|e| (e.key.head, e.key.tool))
+            .collect()
+    }
+
+    fn path(&self, key: &CacheKey) -> PathBuf {
+        let hash = format!("{:016x}", fnv1a(serde_json::to_string(key).unwrap_or_default().as_bytes()));
+        self.dir.join(&hash[..2]).join(format!("{}.json", hash))
+    }
+}
+
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |h, &b| (h ^ u64::from(b)).wrapping_mul(0x100000001b3))
+}
+
+/// HEAD commit of the checkout at `path`, or `None` when it isn't a git repository or its working
+/// tree differs from HEAD. Files the tools themselves leave behind (a generated `Cargo.lock`,
+/// `target/`, the CodeQL database) don't count as changes.
+pub fn clean_head(path: &Path) -> Option<String> {
+    let repo = Repository::open(path).ok()?;
+    let head = repo.head().ok()?.peel_to_commit().ok()?.id().to_string();
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(true).recurse_untracked_dirs(false).include_ignored(false);
+    let statuses = repo.statuses(Some(&mut opts)).ok()?;
+    let dirty = statuses.iter().any(|s| {
+        let p = s.path().unwrap_or_default();
+        let generated = p.split('/').any(|c| c == "target" || c == CODEQL_DB_DIR)
+            || (s.status() == Status::WT_NEW && p.rsplit('/').next() == Some("Cargo.lock"));
+        !generated
+    });
+    (!dirty).then_some(head)
+}
+
+/// Removes cache entries not written or read in `max_age`, returning how many were removed.
+pub fn cache_gc(dir: &str, max_age: Duration) -> anyhow::Result<usize> {
+    let cutoff = SystemTime::now() - max_age;
+    let mut removed = 0;
+    for shard in fs::read_dir(dir).with_context(|| format!("failed to read cache dir {}", dir))? {
+        let shard = shard?.path();
+        if !shard.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(&shard)? {
+            let path = entry?.path();
+            let modified = fs::metadata(&path).and_then(|m| m.modified());
+            if modified.is_ok_and(|m| m < cutoff) {
+                fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+        // Drop shards left empty; fails harmlessly while they still hold entries.
+        let _ = fs::remove_dir(&shard);
+    }
+    Ok(removed)
+}
@@ -0,0 +1,208 @@
+//! `clean`: the build directories, CodeQL databases, git histories or whole checkouts under the
+//! datasets root removed, in parallel, with the space each kind took.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::Context;
+use clap::{Args, ValueEnum};
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::analyzers::CODEQL_DB_DIR;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum CleanKind {
+    /// Cargo's `target/` directories, beside a `Cargo.toml` or marked by cargo as its own
+    Targets,
+    /// The `.codeql-db` directories `--keep-codeql-db` leaves behind
+    Codeql,
+    /// `.git` directories, leaving the files of the checkouts
+    Git,
+    /// Every checkout, whole
+    Checkouts,
+}
+
+impl CleanKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            CleanKind::Targets => "targets",
+            CleanKind::Codeql => "codeql",
+            CleanKind::Git => "git",
+            CleanKind::Checkouts => "checkouts",
+        }
+    }
+
+    /// Whether `dir`, named `name`, is a directory of this kind.
+    fn matches(self, dir: &Path, name: &str) -> bool {
+        match self {
+            CleanKind::Targets => name == "target" && (dir.parent().is_some_and(|p| p.join("Cargo.toml").is_file()) || dir.join("CACHEDIR.TAG").is_file() || dir.join(".rustc_info.json").is_file()),
+            CleanKind::Codeql => name == CODEQL_DB_DIR,
+            CleanKind::Git => name == ".git",
+            CleanKind::Checkouts => false,
+        }
+    }
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct CleanOptions {
+    /// The directory the checkouts are in; nothing outside it is removed
+    #[arg(long, value_name = "DIR", default_value = "datasets")]
+    pub root: String,
+    /// What to remove, comma-separated
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = [CleanKind::Targets, CleanKind::Codeql])]
+    pub what: Vec<CleanKind>,
+    /// List what would be removed, and its size, without removing anything
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Directories removed at a time
+    #[arg(long, default_value_t = 8)]
+    pub jobs: usize,
+}
+
+impl Default for CleanOptions {
+    fn default() -> Self {
+        CleanOptions { root: "datasets".into(), what: vec![CleanKind::Targets, CleanKind::Codeql], dry_run: false, jobs: 8 }
+    }
+}
+
+/// A directory `clean` removed, or would remove with `--dry-run`.
+#[derive(Clone, Debug, Serialize)]
+pub struct CleanedDir {
+    pub kind: CleanKind,
+    pub path: PathBuf,
+    /// The size of its files; with errors, of the ones that were removed
+    pub bytes: u64,
+    pub files: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct CleanSummary {
+    pub dirs: Vec<CleanedDir>,
+    /// Bytes reclaimed (or reclaimable) per kind
+    pub bytes: BTreeMap<CleanKind, u64>,
+    /// `path: error` for what couldn't be read or removed, each skipped without stopping the rest
+    pub errors: Vec<String>,
+}
+
+/// The directories of `kinds` under `dir`, which isn't followed into matches or symlinks.
+fn find(dir: &Path, kinds: &[CleanKind], found: &mut Vec<(CleanKind, PathBuf)>, errors: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => return errors.push(format!("{}: {}", dir.display(), e)),
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        // Not `is_dir()` on the path, which would follow a symlink out of the root.
+        if !entry.file_type().is_ok_and(|t| t.is_dir()) {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        match kinds.iter().find(|k| k.matches(&path, &name)) {
+            Some(kind) => found.push((*kind, path)),
+            None => find(&path, kinds, found, errors),
+        }
+    }
+}
+
+/// Removes (or with `dry_run` only measures) the tree at `dir`, its subdirectories in parallel.
+/// Returns its files and their bytes; what fails is added to `errors` and the rest goes on.
+fn remove_tree(dir: &Path, dry_run: bool, errors: &Mutex<Vec<String>>) -> (usize, u64) {
+    let entries: Vec<fs::DirEntry> = match fs::read_dir(dir) {
+        Ok(entries) => entries.flatten().collect(),
+        Err(e) => {
+            errors.lock().unwrap().push(format!("{}: {}", dir.display(), e));
+            return (0, 0);
+        }
+    };
+    let (files, bytes) = entries
+        .par_iter()
+        .map(|entry| {
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => remove_tree(&path, dry_run, errors),
+                Ok(_) => {
+                    let size = entry.metadata().map_or(0, |m| m.len());
+                    match if dry_run { Ok(()) } else { fs::remove_file(&path) } {
+                        Ok(()) => (1, size),
+                        Err(e) => {
+                            errors.lock().unwrap().push(format!("{}: {}", path.display(), e));
+                            (0, 0)
+                        }
+                    }
+                }
+                Err(e) => {
+                    errors.lock().unwrap().push(format!("{}: {}", path.display(), e));
+                    (0, 0)
+                }
+            }
+        })
+        .reduce(|| (0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+    if !dry_run {
+        // Fails, and is reported, while something inside couldn't be removed.
+        if let Err(e) = fs::remove_dir(dir) {
+            errors.lock().unwrap().push(format!("{}: {}", dir.display(), e));
+        }
+    }
+    (files, bytes)
+}
+
+/// Removes the directories of `opts.what` under `opts.root`. Every one of them is checked to
+/// resolve inside the root before anything in it is touched.
+pub fn clean_dataset(opts: &CleanOptions) -> anyhow::Result<CleanSummary> {
+    let root = fs::canonicalize(&opts.root).with_context(|| format!("failed to read {}", opts.root))?;
+    anyhow::ensure!(root.is_dir(), "{} is not a directory", opts.root);
+    anyhow::ensure!(root.parent().is_some(), "refusing to clean the filesystem root");
+    // Checkouts removed whole take everything else in them along.
+    let kinds = if opts.what.contains(&CleanKind::Checkouts) { vec![CleanKind::Checkouts] } else { opts.what.clone() };
+    let mut found = Vec::new();
+    let mut errors = Vec::new();
+    if kinds == [CleanKind::Checkouts] {
+        for entry in fs::read_dir(&root).with_context(|| format!("failed to read {}", opts.root))?.flatten() {
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                found.push((CleanKind::Checkouts, entry.path()));
+            }
+        }
+    } else {
+        find(&root, &kinds, &mut found, &mut errors);
+    }
+    found.retain(|(_, path)| match fs::canonicalize(path) {
+        Ok(real) if real.starts_with(&root) && real != root => true,
+        Ok(real) => {
+            errors.push(format!("{}: resolves to {}, outside {}; skipped", path.display(), real.display(), root.display()));
+            false
+        }
+        Err(e) => {
+            errors.push(format!("{}: {}", path.display(), e));
+            false
+        }
+    });
+
+    let errors = Mutex::new(errors);
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(opts.jobs.max(1)).build()?;
+    let mut dirs: Vec<CleanedDir> = pool.install(|| {
+        found
+            .into_par_iter()
+            .map(|(kind, path)| {
+                let (files, bytes) = remove_tree(&path, opts.dry_run, &errors);
+                CleanedDir { kind, path, bytes, files }
+            })
+            .collect()
+    });
+    dirs.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut summary = CleanSummary { errors: errors.into_inner().unwrap(), ..CleanSummary::default() };
+    for kind in &kinds {
+        summary.bytes.insert(*kind, 0);
+    }
+    for d in &dirs {
+        *summary.bytes.entry(d.kind).or_default() += d.bytes;
+    }
+    for e in &summary.errors {
+        tracing::warn!("{}", e);
+    }
+    summary.dirs = dirs;
+    Ok(summary)
+}
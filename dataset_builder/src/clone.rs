@@ -0,0 +1,1115 @@
+//! The `clone` step: shallow-clone the filtered repositories.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use clap::{Args, ValueEnum};
+use git2::build::RepoBuilder;
+use git2::{Cred, ErrorClass, ErrorCode, FetchOptions, RemoteCallbacks, Repository, ResetType};
+use indicatif::{HumanBytes, ProgressBar};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::doctor::{existing_ancestor, free_bytes, DEFAULT_MIN_FREE_GB};
+use crate::filter::canonical_repo_name;
+use crate::git_cli;
+use crate::github::Tokens;
+use crate::metadata::{RepoMetadata, DEFAULT_METADATA_FILE};
+use crate::jsonl::{replace_file, scan_jsonl, JsonlWriter};
+use crate::progress::Progress;
+use crate::sample::Rng;
+use crate::shutdown::{shutdown_requested, sleep_unless_shutdown};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnExisting {
+    /// Leave the existing checkout untouched
+    Skip,
+    /// Fetch and hard-reset to the remote default branch
+    Update,
+    /// Delete the directory and clone fresh
+    Overwrite,
+}
+
+/// Where `clone` records failed repositories unless `--errors-file` says otherwise.
+pub const DEFAULT_CLONE_ERRORS_FILE: &str = "clone_errors.jsonl";
+
+#[derive(Args, Clone, Debug)]
+pub struct CloneOptions {
+    /// What to do when a repository's destination directory already exists
+    #[arg(long, value_enum, default_value_t = OnExisting::Skip)]
+    pub on_existing: OnExisting,
+    /// Number of repositories to clone concurrently
+    #[arg(long, default_value_t = 1)]
+    pub jobs: usize,
+    /// Retries of a clone after a network error, a 5xx or a rate limit, with exponential backoff
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub retries: u32,
+    /// Where to record the repositories that failed
+    #[arg(long, value_name = "PATH", default_value = DEFAULT_CLONE_ERRORS_FILE)]
+    pub errors_file: String,
+    /// Exit with an error only when more than this fraction of the repositories failed
+    #[arg(long, value_name = "F", default_value_t = 0.0)]
+    pub max_failure_rate: f64,
+    /// Clone only the names-file lines of the repositories recorded in this errors file
+    #[arg(long, value_name = "PATH")]
+    pub retry_from: Option<String>,
+    /// Abort before cloning when the output disk has less free space than this many GiB (without
+    /// it, only warn below 20)
+    #[arg(long, value_name = "GB")]
+    pub min_free_gb: Option<u64>,
+    /// Stop starting clones once the checkouts cloned by this run take this many GiB
+    #[arg(long, value_name = "GB")]
+    pub max_total_gb: Option<f64>,
+    /// Skip the repositories whose size in the metadata file is over this many MiB
+    #[arg(long, value_name = "MB")]
+    pub max_repo_mb: Option<u64>,
+    /// The `metadata` output that --max-repo-mb reads sizes from
+    #[arg(long, value_name = "PATH", default_value = DEFAULT_METADATA_FILE)]
+    pub metadata_file: String,
+    #[command(flatten)]
+    pub history: HistoryOptions,
+    /// Delete each checkout's `.git` once its revision is recorded, for datasets of source code only
+    #[arg(long)]
+    pub strip_git: bool,
+    /// What clones: libgit2, the `git` program, or libgit2 falling back to `git` on failure
+    #[arg(long, value_enum, default_value_t = GitBackend::Libgit2)]
+    pub git_backend: GitBackend,
+    #[command(flatten)]
+    pub auth: AuthOptions,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GitBackend {
+    /// git2-rs, in process
+    #[default]
+    Libgit2,
+    /// The `git` program on the PATH
+    Cli,
+    /// libgit2, retried with the `git` program when it fails; `git` alone with `--filter`
+    Auto,
+}
+
+/// What each clone fetches: how much history, and the submodules.
+#[derive(Args, Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct HistoryOptions {
+    /// Commits of history to fetch; 0 clones the whole history
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub depth: u32,
+    /// Fetch only the default branch instead of every branch
+    #[arg(long)]
+    pub single_branch: bool,
+    /// Partial clone: `blob:none` leaves out file contents until checkout, `tree:0` trees too
+    #[arg(long, value_enum, value_name = "SPEC")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<CloneFilter>,
+    /// Whether submodules are initialized, recursively: `init` with their whole history,
+    /// `shallow` at depth 1
+    #[arg(long, value_enum, default_value_t = Submodules::Skip)]
+    #[serde(default)]
+    pub submodules: Submodules,
+}
+
+impl Default for HistoryOptions {
+    fn default() -> Self {
+        HistoryOptions { depth: 1, single_branch: false, filter: None, submodules: Submodules::Skip }
+    }
+}
+
+impl HistoryOptions {
+    /// Fails on the options `backend` can't honour.
+    pub fn validate(&self, backend: GitBackend) -> anyhow::Result<()> {
+        if let (Some(filter), GitBackend::Libgit2) = (self.filter, backend) {
+            anyhow::bail!("--filter {} needs partial clone, which libgit2 doesn't support; use --git-backend cli (or auto)", filter.spec());
+        }
+        Ok(())
+    }
+
+    /// `FetchOptions::depth`: 0 is the whole history.
+    fn fetch_depth(&self) -> i32 {
+        i32::try_from(self.depth).unwrap_or(UNSHALLOW - 1)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Submodules {
+    /// Leave them uninitialized, as `git clone` does
+    #[default]
+    Skip,
+    /// Initialize them with their whole history
+    Init,
+    /// Initialize them at depth 1
+    Shallow,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
+pub enum CloneFilter {
+    #[value(name = "blob:none")]
+    #[serde(rename = "blob:none")]
+    BlobNone,
+    #[value(name = "tree:0")]
+    #[serde(rename = "tree:0")]
+    TreeZero,
+}
+
+impl CloneFilter {
+    /// The filter as `git clone --filter` spells it.
+    pub fn spec(self) -> &'static str {
+        match self {
+            CloneFilter::BlobNone => "blob:none",
+            CloneFilter::TreeZero => "tree:0",
+        }
+    }
+}
+
+/// How `clone` authenticates to the git server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthMode {
+    /// The GitHub token over HTTPS; anonymous without one
+    #[default]
+    Token,
+    /// SSH with `--ssh-key`, or the keys of the running ssh-agent
+    Ssh,
+    /// HTTPS with the credentials git's configured `credential.helper` returns
+    CredentialHelper,
+    /// Anonymous HTTPS, even when a token is set
+    None,
+}
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct AuthOptions {
+    /// How to authenticate clones and fetches
+    #[arg(long, value_enum, default_value_t = AuthMode::Token)]
+    pub auth: AuthMode,
+    /// With --auth ssh, the private key to use instead of the ssh-agent
+    #[arg(long, value_name = "PATH")]
+    pub ssh_key: Option<String>,
+    /// With --auth ssh and --ssh-key, the key's passphrase, read from SSH_KEY_PASSPHRASE: a value
+    /// on the command line is refused, since it stays in the shell history and shows in `ps`
+    #[arg(long, value_name = "PASSPHRASE", env = "SSH_KEY_PASSPHRASE", hide_env_values = true)]
+    pub ssh_key_passphrase: Option<String>,
+    /// Git server to clone from (default github.com), e.g. a GitHub Enterprise host, or a base
+    /// URL such as `http://git.internal:8080` for HTTP remotes
+    #[arg(long, value_name = "HOST")]
+    pub git_host: Option<String>,
+    /// The remote URL of bare `owner/repo` names, with `{}` for the name, for lists of another
+    /// host's repositories, e.g. `https://gitlab.com/{}.git`
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "git_host")]
+    pub host_template: Option<String>,
+}
+
+impl AuthOptions {
+    /// The remote URL of a names-file entry: a git URL as it is, `--host-template` filled in, or
+    /// `git@host:owner/repo.git` with --auth ssh and HTTPS otherwise.
+    pub fn remote_url(&self, name: &str) -> String {
+        if is_git_url(name) {
+            return name.to_string();
+        }
+        if let Some(template) = &self.host_template {
+            return template.replace("{}", name);
+        }
+        let host = self.git_host.as_deref().unwrap_or("github.com").trim_end_matches('/');
+        match (self.auth, host.split_once("://")) {
+            (AuthMode::Ssh, base) => format!("git@{}:{}.git", base.map_or(host, |(_, bare)| bare), name),
+            (_, Some(_)) => format!("{}/{}.git", host, name),
+            (_, None) => format!("https://{}/{}.git", host, name),
+        }
+    }
+
+    /// The scheme and host of `token_host`, e.g. `https://github.com`.
+    pub(crate) fn token_origin(&self) -> String {
+        match self.host_template.as_deref().or(self.git_host.as_deref()).and_then(|base| base.split_once("://")) {
+            Some((scheme, rest)) => format!("{}://{}", scheme, rest.split('/').next().unwrap_or_default()),
+            None => format!("https://{}", self.token_host()),
+        }
+    }
+
+    /// The host the token is offered to: `--host-template`'s or `--git-host`'s, github.com by default.
+    fn token_host(&self) -> &str {
+        self.host_template.as_deref().or(self.git_host.as_deref()).map_or("github.com", url_host)
+    }
+}
+
+/// Whether a names-file entry is a git URL (`https://...`, `git@host:path`) rather than `owner/repo`.
+pub fn is_git_url(name: &str) -> bool {
+    name.contains("://") || name.contains('@')
+}
+
+/// The host of `https://user@host:port/path`, `git@host:path` or a bare `host`.
+fn url_host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let rest = rest.split('/').next().unwrap_or_default();
+    let rest = rest.rsplit_once('@').map_or(rest, |(_, host)| host);
+    rest.split(':').next().unwrap_or_default()
+}
+
+/// The credentials a clone offers: the `--auth` mode and the token, if any.
+#[derive(Clone, Copy)]
+pub(crate) struct GitAuth<'a> {
+    pub(crate) opts: &'a AuthOptions,
+    pub(crate) token: Option<&'a str>,
+}
+
+impl GitAuth<'_> {
+    fn callbacks(&self, callbacks: &mut RemoteCallbacks) {
+        // libgit2 asks again after every rejected credential; a few tries are enough.
+        let mut asked = 0;
+        let (opts, token) = (self.opts.clone(), self.token.map(str::to_string));
+        match opts.auth {
+            AuthMode::None => {}
+            AuthMode::Token if token.is_none() => {}
+            _ => {
+                callbacks.credentials(move |url, user, allowed| {
+                    asked += 1;
+                    if asked > 3 {
+                        return Err(git2::Error::new(ErrorCode::Auth, ErrorClass::Http, "credentials rejected"));
+                    }
+                    match opts.auth {
+                        // The GitHub token is never sent to the other hosts a names file lists.
+                        AuthMode::Token if url_host(url) != opts.token_host() => {
+                            Err(git2::Error::new(ErrorCode::Auth, ErrorClass::Http, format!("the token is only offered to {}", opts.token_host())))
+                        }
+                        AuthMode::Ssh if allowed.contains(git2::CredentialType::USERNAME) => Cred::username(user.unwrap_or("git")),
+                        AuthMode::Ssh => match &opts.ssh_key {
+                            Some(key) => Cred::ssh_key(user.unwrap_or("git"), None, Path::new(key), opts.ssh_key_passphrase.as_deref()),
+                            None => Cred::ssh_key_from_agent(user.unwrap_or("git")),
+                        },
+                        AuthMode::CredentialHelper => Cred::credential_helper(&git2::Config::open_default()?, url, user),
+                        _ => Cred::userpass_plaintext("x-access-token", token.as_deref().unwrap_or_default()),
+                    }
+                });
+            }
+        }
+    }
+
+    /// `e`, with a hint naming the repository and the mode tried when authentication failed.
+    pub(crate) fn error(&self, e: git2::Error, name: &str) -> anyhow::Error {
+        let hint = match (is_auth_error(&e), self.opts.auth, self.token) {
+            (false, _, _) => None,
+            (true, AuthMode::Token, None) | (true, AuthMode::None, _) => Some(format!(
+                "cloning {} requires authentication (tried --auth {}): set GITHUB_TOKEN, or pass --token-file or --token-cmd, or pick another --auth, and retry",
+                name, self.mode()
+            )),
+            (true, AuthMode::Ssh, _) => Some(format!(
+                "SSH authentication failed for {} (tried --auth ssh with {})",
+                name,
+                self.opts.ssh_key.as_deref().map_or("the ssh-agent's keys".to_string(), |k| format!("key {}", k))
+            )),
+            (true, _, _) => Some(format!("the server refused the credentials for {} (tried --auth {})", name, self.mode())),
+        };
+        let e = anyhow::Error::from(e);
+        match hint {
+            Some(hint) => e.context(hint),
+            None => e,
+        }
+    }
+
+    fn mode(&self) -> String {
+        self.opts.auth.to_possible_value().map_or_else(String::new, |v| v.get_name().to_string())
+    }
+}
+
+impl Default for CloneOptions {
+    fn default() -> Self {
+        CloneOptions {
+            on_existing: OnExisting::Skip,
+            jobs: 1,
+            retries: 3,
+            errors_file: DEFAULT_CLONE_ERRORS_FILE.into(),
+            max_failure_rate: 0.0,
+            retry_from: None,
+            min_free_gb: None,
+            max_total_gb: None,
+            max_repo_mb: None,
+            metadata_file: DEFAULT_METADATA_FILE.into(),
+            history: HistoryOptions::default(),
+            strip_git: false,
+            git_backend: GitBackend::Libgit2,
+            auth: AuthOptions::default(),
+        }
+    }
+}
+
+/// Why a clone failed; only `network`, `rate_limited` and `server` failures are retried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CloneErrorClass {
+    Network,
+    RateLimited,
+    Server,
+    NotFound,
+    /// Authentication required or refused (GitHub also answers this for missing repositories)
+    Auth,
+    /// The line isn't an `owner/repo` name
+    InvalidName,
+    /// Another name already uses the destination directory
+    Conflict,
+    /// Not attempted: the `--max-total-gb` budget was used up first
+    Budget,
+    /// Not attempted: the metadata's size is over `--max-repo-mb`
+    TooLarge,
+    Other,
+}
+
+impl CloneErrorClass {
+    pub fn of(e: &anyhow::Error) -> Self {
+        let Some(git) = e.chain().find_map(|c| c.downcast_ref::<git2::Error>()) else {
+            return CloneErrorClass::Other;
+        };
+        let message = git.message().to_ascii_lowercase();
+        let status: Option<u16> = message.split_once("status code").and_then(|(_, rest)| rest.trim_start_matches([':', ' ']).get(..3)?.parse().ok());
+        match status {
+            _ if git.code() == ErrorCode::Auth => CloneErrorClass::Auth,
+            Some(401 | 403) => CloneErrorClass::Auth,
+            Some(404) => CloneErrorClass::NotFound,
+            Some(429) => CloneErrorClass::RateLimited,
+            Some(500..=599) => CloneErrorClass::Server,
+            _ if git.code() == ErrorCode::NotFound || message.contains("not found") => CloneErrorClass::NotFound,
+            _ if matches!(git.class(), ErrorClass::Net | ErrorClass::Ssl | ErrorClass::Http) => CloneErrorClass::Network,
+            _ => CloneErrorClass::Other,
+        }
+    }
+
+    pub fn is_transient(self) -> bool {
+        matches!(self, CloneErrorClass::Network | CloneErrorClass::RateLimited | CloneErrorClass::Server)
+    }
+}
+
+/// One line of `clone_errors.jsonl`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct CloneError {
+    /// The names-file entry, or the whole line when it isn't an `owner/repo` name or a git URL
+    pub name: String,
+    pub class: CloneErrorClass,
+    pub attempts: u32,
+    pub error: String,
+}
+
+/// Written to the clones' directory: the revision each checkout is at.
+pub const CLONE_MANIFEST_FILE: &str = "clone_manifest.jsonl";
+
+/// Written to the clones' directory: the names-file entry of each checkout directory, as a JSON
+/// object.
+pub const NAME_MAP_FILE: &str = "name_map.json";
+
+/// `GIT_FETCH_DEPTH_UNSHALLOW`: fetch the history a shallow clone left out.
+const UNSHALLOW: i32 = i32::MAX;
+
+pub(crate) enum CloneOutcome {
+    Cloned,
+    Skipped,
+    Updated,
+}
+
+#[derive(Debug, Default)]
+pub struct CloneSummary {
+    pub cloned: usize,
+    pub skipped: usize,
+    pub updated: usize,
+    /// Repositories that only cloned after one or more retries
+    pub retried: usize,
+    pub failed: Vec<(String, String)>,
+    /// Repositories left for a later run by `--max-total-gb` or `--max-repo-mb`
+    pub not_attempted: Vec<String>,
+    /// Bytes taken by the checkouts this run cloned
+    pub bytes: u64,
+    /// The five largest of them, largest first
+    pub largest: Vec<(String, u64)>,
+    /// `--max-total-gb` stopped the run before every repository was attempted
+    pub budget_exhausted: bool,
+}
+
+impl CloneSummary {
+    /// The fraction of the repositories that failed.
+    pub fn failure_rate(&self) -> f64 {
+        let total = self.cloned + self.skipped + self.updated + self.failed.len();
+        self.failed.len() as f64 / total.max(1) as f64
+    }
+}
+
+/// One line of `clone_manifest.jsonl`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct CloneRecord {
+    /// The checkout directory, as in `OutputEntry::dir` and `CodeEntry::dir`
+    pub name: String,
+    /// The names-file entry: `owner/repo` or a git URL
+    pub repo: String,
+    /// The commit SHA or tag the names file pinned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested: Option<String>,
+    /// HEAD's commit SHA
+    pub commit: String,
+    /// The branch checked out; absent when HEAD is detached at a pinned revision
+    pub branch: Option<String>,
+    /// HEAD's commit time, RFC 3339
+    pub committed_at: String,
+    /// Whether the checkout's history is truncated
+    #[serde(default)]
+    pub shallow: bool,
+    /// The options the checkout was cloned with; kept from the previous line when a run didn't
+    /// clone it (an existing checkout), absent when no run recorded them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<HistoryOptions>,
+    /// The checkout's `.git` was deleted (`--strip-git`) after this line was recorded
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub git_stripped: bool,
+    /// The checkout has submodules that weren't initialized (`--submodules skip`), so its builds
+    /// and collected code may be missing parts
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub has_uninitialized_submodules: bool,
+    /// `update` found the remote gone (deleted, or made private); the checkout is at the last
+    /// commit fetched
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub gone: bool,
+}
+
+/// A repository to clone, from one line of the names file.
+pub(crate) struct CloneJob<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) rev: Option<&'a str>,
+    pub(crate) dest: PathBuf,
+}
+
+/// Clones every repository in `names_file`, retrying transient failures. Failures don't stop the
+/// others; each is recorded in `opts.errors_file` as it happens.
+pub fn clone_repos(names_file: &str, out_root: &str, tokens: &Tokens, opts: &CloneOptions) -> anyhow::Result<CloneSummary> {
+    opts.history.validate(opts.git_backend)?;
+    check_free_space(out_root, opts.min_free_gb)?;
+    let names = clone_names(names_file, opts)?;
+    if let Some(errors) = &opts.retry_from {
+        tracing::info!("Retrying {} repositories from {}", names.lines().count(), errors);
+    }
+    let mut summary = CloneSummary::default();
+    let mut planned = Vec::new();
+    let mut work = plan_clones(&names, out_root, &mut planned);
+    write_name_map(out_root, &work)?;
+    if let Some(max) = opts.max_repo_mb {
+        work = skip_oversized(work, &opts.metadata_file, max, &mut planned);
+    }
+    let errors = Mutex::new(JsonlWriter::new(File::create(&opts.errors_file).with_context(|| format!("failed to write {}", opts.errors_file))?, None));
+    for e in planned {
+        errors.lock().unwrap().write_entry(&e)?;
+        match e.class {
+            CloneErrorClass::TooLarge => summary.not_attempted.push(e.name),
+            _ => summary.failed.push((e.name, e.error)),
+        }
+    }
+    let budget = opts.max_total_gb.map(|gb| (gb * (1u64 << 30) as f64) as u64);
+    let used = AtomicU64::new(0);
+
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(opts.jobs.max(1)).build()?;
+    let progress = Progress::new(work.len(), "repositories cloned");
+    let results: Vec<_> = pool.install(|| {
+        work.par_iter()
+            // After Ctrl-C, only the clones already in flight are finished.
+            .filter(|_| !shutdown_requested())
+            .map(|job| {
+                if budget.is_some_and(|budget| used.load(Ordering::SeqCst) >= budget) {
+                    let e = CloneError { name: job.name.to_string(), class: CloneErrorClass::Budget, attempts: 0, error: "not attempted: the --max-total-gb budget was used up".into() };
+                    return errors.lock().unwrap().write_entry(&e).map(|_| Err(e));
+                }
+                let bar = progress.start(job.name);
+                let auth = GitAuth { opts: &opts.auth, token: tokens.for_clone() };
+                let (res, attempts) = clone_with_retries(job, auth, opts.on_existing, &opts.history, opts.git_backend, opts.retries, &bar);
+                let res = res.and_then(|outcome| {
+                    let record = clone_record(job, &outcome, &opts.history, opts.strip_git);
+                    if opts.strip_git {
+                        strip_git(&job.dest)?;
+                    }
+                    Ok((outcome, record))
+                });
+                let res = match res {
+                    Ok((outcome, record)) => {
+                        let size = match outcome {
+                            CloneOutcome::Cloned => dir_size(&job.dest),
+                            _ => 0,
+                        };
+                        used.fetch_add(size, Ordering::SeqCst);
+                        Ok(Ok((outcome, record, attempts, (job.name, size))))
+                    }
+                    Err(e) => {
+                        let e = CloneError { name: job.name.to_string(), class: CloneErrorClass::of(&e), attempts, error: format!("{:#}", e) };
+                        errors.lock().unwrap().write_entry(&e).map(|_| Err(e))
+                    }
+                };
+                progress.finish(bar);
+                res
+            })
+            .collect::<anyhow::Result<_>>()
+    })?;
+    progress.done();
+    errors.into_inner().unwrap().finish()?;
+    let mut records = Vec::new();
+    for res in results {
+        match res {
+            Ok((outcome, record, attempts, (name, size))) => {
+                if size > 0 {
+                    summary.bytes += size;
+                    summary.largest.push((name.to_string(), size));
+                }
+                match outcome {
+                    CloneOutcome::Cloned => summary.cloned += 1,
+                    CloneOutcome::Skipped => summary.skipped += 1,
+                    CloneOutcome::Updated => summary.updated += 1,
+                }
+                summary.retried += usize::from(attempts > 1);
+                records.extend(record);
+            }
+            Err(e) if e.class == CloneErrorClass::Budget => {
+                summary.budget_exhausted = true;
+                summary.not_attempted.push(e.name);
+            }
+            Err(e) => summary.failed.push((e.name, format!("{:?} after {} attempts: {}", e.class, e.attempts, e.error))),
+        }
+    }
+    summary.largest.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+    summary.largest.truncate(5);
+    if !records.is_empty() {
+        write_clone_manifest(out_root, records)?;
+    }
+
+    for (name, err) in &summary.failed {
+        tracing::warn!("✗ {}: {}", name, err);
+    }
+    tracing::info!(
+        "Clone summary: {} succeeded ({} cloned, {} skipped (already present), {} updated, {} after retries), {} failed{}",
+        summary.cloned + summary.skipped + summary.updated,
+        summary.cloned,
+        summary.skipped,
+        summary.updated,
+        summary.retried,
+        summary.failed.len(),
+        if summary.failed.is_empty() { String::new() } else { format!(" (see {})", opts.errors_file) }
+    );
+    if summary.bytes > 0 {
+        let largest: Vec<String> = summary.largest.iter().map(|(name, size)| format!("{} ({})", name, HumanBytes(*size))).collect();
+        tracing::info!("Disk: {} cloned; largest: {}", HumanBytes(summary.bytes), largest.join(", "));
+    }
+    if !summary.not_attempted.is_empty() {
+        tracing::info!("{} repositories not attempted (--max-total-gb or --max-repo-mb), listed in {}", summary.not_attempted.len(), opts.errors_file);
+    }
+    Ok(summary)
+}
+
+/// Fails when the disk holding `out_root` has less than `min_free_gb` GiB free; without it,
+/// warns below `DEFAULT_MIN_FREE_GB`.
+fn check_free_space(out_root: &str, min_free_gb: Option<u64>) -> anyhow::Result<()> {
+    let dir = existing_ancestor(Path::new(out_root));
+    let Some(free) = free_bytes(&dir) else { return Ok(()) };
+    let gib = free as f64 / (1u64 << 30) as f64;
+    match min_free_gb {
+        Some(min) if gib < min as f64 => anyhow::bail!("only {:.1} GiB free in {}, less than --min-free-gb {}", gib, dir.display(), min),
+        None if gib < DEFAULT_MIN_FREE_GB as f64 => tracing::warn!("⚠ only {:.1} GiB free in {}; pass --min-free-gb to refuse to start", gib, dir.display()),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// The lines of the names file to clone: all of them, or with `--retry-from` those of the
+/// repositories recorded in that errors file.
+pub(crate) fn clone_names(names_file: &str, opts: &CloneOptions) -> anyhow::Result<String> {
+    let names = fs::read_to_string(names_file).with_context(|| format!("failed to read {}", names_file))?;
+    let Some(errors) = &opts.retry_from else { return Ok(names) };
+    let text = fs::read_to_string(errors).with_context(|| format!("failed to read {}", errors))?;
+    let failed: HashSet<String> = scan_jsonl(&text)
+        .into_iter()
+        .filter_map(|line| serde_json::from_value::<CloneError>(line.value?).ok())
+        .map(|e| e.name.to_ascii_lowercase())
+        .collect();
+    Ok(names.lines().filter(|line| failed.contains(&split_pin(line.trim()).0.to_ascii_lowercase())).map(|line| format!("{}\n", line)).collect())
+}
+
+/// GitHub's size of each repository in the metadata file, in KiB, by lowercase `owner/repo`;
+/// `None` when there is no such file.
+pub(crate) fn metadata_sizes(metadata_file: &str) -> Option<HashMap<String, u64>> {
+    let text = fs::read_to_string(metadata_file).ok()?;
+    Some(scan_jsonl(&text)
+        .into_iter()
+        .filter_map(|line| serde_json::from_value::<RepoMetadata>(line.value?).ok())
+        .filter_map(|m| Some((m.repo.to_ascii_lowercase(), m.size_kb?)))
+        .collect())
+}
+
+/// `work` without the repositories whose size in the metadata file is over `max_mb`, which are
+/// added to `skipped`. Repositories the file doesn't have are kept.
+pub(crate) fn skip_oversized<'a>(work: Vec<CloneJob<'a>>, metadata_file: &str, max_mb: u64, skipped: &mut Vec<CloneError>) -> Vec<CloneJob<'a>> {
+    let Some(sizes) = metadata_sizes(metadata_file) else {
+        tracing::warn!("⚠ {} not found; --max-repo-mb needs the sizes `metadata` writes, so no repository is skipped", metadata_file);
+        return work;
+    };
+    work.into_iter()
+        .filter(|job| {
+            let size_kb = github_repo(job.name).ok().and_then(|repo| sizes.get(&repo.to_ascii_lowercase()).copied());
+            match size_kb {
+                Some(kb) if kb > max_mb * 1024 => {
+                    let error = format!("not attempted: {} MiB on GitHub, over --max-repo-mb {}", kb / 1024, max_mb);
+                    skipped.push(CloneError { name: job.name.to_string(), class: CloneErrorClass::TooLarge, attempts: 0, error });
+                    false
+                }
+                _ => true,
+            }
+        })
+        .collect()
+}
+
+/// Bytes taken by the files under `path`.
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else { return 0 };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.file_type() {
+            Ok(t) if t.is_dir() => dir_size(&entry.path()),
+            _ => entry.metadata().map_or(0, |m| m.len()),
+        })
+        .sum()
+}
+
+/// `clone_one`, retried after transient failures with exponential backoff and jitter, so workers
+/// that failed together don't retry together. Returns the attempts made with the result.
+pub(crate) fn clone_with_retries(job: &CloneJob, auth: GitAuth, on_existing: OnExisting, history: &HistoryOptions, backend: GitBackend, retries: u32, bar: &ProgressBar) -> (anyhow::Result<CloneOutcome>, u32) {
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64 ^ job.dest.as_os_str().len() as u64;
+    let mut rng = Rng::new(seed);
+    let mut attempt = 1;
+    loop {
+        let res = clone_one(job, auth, on_existing, history, backend, bar);
+        let class = match &res {
+            Ok(_) => return (res, attempt),
+            Err(e) => CloneErrorClass::of(e),
+        };
+        if !class.is_transient() || attempt > retries || shutdown_requested() {
+            return (res, attempt);
+        }
+        // 1s, 2s, 4s, ... up to a minute, each scaled by a random factor in 0.5..1.5.
+        let base = 1000u64 << (attempt - 1).min(6);
+        let wait = Duration::from_millis(base / 2 + rng.below(base as usize) as u64);
+        tracing::debug!(repo = job.name, "{:?} error; retrying in {}ms (attempt {} of {})", class, wait.as_millis(), attempt + 1, retries + 1);
+        bar.set_message(format!("{:?} error, retrying in {}s", class, wait.as_secs()));
+        sleep_unless_shutdown(wait);
+        attempt += 1;
+    }
+}
+
+/// Replaces the records of `records`' checkouts in `out_root`'s clone manifest, keeping the
+/// others, and the clone options a record without them had before.
+pub(crate) fn write_clone_manifest(out_root: &str, mut records: Vec<CloneRecord>) -> anyhow::Result<()> {
+    let path = Path::new(out_root).join(CLONE_MANIFEST_FILE);
+    let previous = fs::read_to_string(&path).unwrap_or_default();
+    let mut previous: HashMap<String, CloneRecord> =
+        scan_jsonl(&previous).into_iter().filter_map(|line| serde_json::from_value::<CloneRecord>(line.value?).ok()).map(|r| (r.name.clone(), r)).collect();
+    for record in &mut records {
+        if let Some(old) = previous.remove(&record.name) {
+            record.history = record.history.take().or(old.history);
+        }
+    }
+    let mut previous: Vec<CloneRecord> = previous.into_values().collect();
+    previous.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut text = String::new();
+    for record in records.into_iter().chain(previous) {
+        text.push_str(&serde_json::to_string(&record)?);
+        text.push('\n');
+    }
+    replace_file(&path.display().to_string(), &text)
+}
+
+/// The clone manifest records of the checkouts in `root`, by directory.
+pub(crate) fn clone_records(root: &str) -> HashMap<String, CloneRecord> {
+    let text = fs::read_to_string(Path::new(root).join(CLONE_MANIFEST_FILE)).unwrap_or_default();
+    scan_jsonl(&text)
+        .into_iter()
+        .filter_map(|line| serde_json::from_value::<CloneRecord>(line.value?).ok())
+        .map(|r| (r.name.clone(), r))
+        .collect()
+}
+
+/// Adds the jobs' directories to `out_root`'s name map, keeping the other entries.
+pub(crate) fn write_name_map(out_root: &str, work: &[CloneJob]) -> anyhow::Result<()> {
+    if work.is_empty() {
+        return Ok(());
+    }
+    let mut map = name_map(out_root);
+    for job in work {
+        map.insert(job.dest.file_name().unwrap_or_default().to_string_lossy().into_owned(), job.name.to_string());
+    }
+    fs::create_dir_all(out_root)?;
+    let path = Path::new(out_root).join(NAME_MAP_FILE).display().to_string();
+    replace_file(&path, &format!("{}\n", serde_json::to_string_pretty(&map)?))
+}
+
+/// The names-file entry of each checkout directory in `root`, from its name map.
+pub(crate) fn name_map(root: &str) -> BTreeMap<String, String> {
+    let text = fs::read_to_string(Path::new(root).join(NAME_MAP_FILE)).unwrap_or_default();
+    serde_json::from_str(&text).unwrap_or_default()
+}
+
+/// Deletes the checkout's `.git`, if any.
+pub(crate) fn strip_git(dest: &Path) -> anyhow::Result<()> {
+    let git = dest.join(".git");
+    if git.is_dir() {
+        fs::remove_dir_all(&git).with_context(|| format!("failed to delete {}", git.display()))?;
+    }
+    Ok(())
+}
+
+/// A names-file line: `owner/repo` or a git URL, optionally followed by the commit SHA or tag to check out,
+/// separated by whitespace or a comma.
+pub(crate) fn split_pin(line: &str) -> (&str, Option<&str>) {
+    let mut fields = line.split(|c: char| c == ',' || c.is_whitespace()).filter(|f| !f.is_empty());
+    (fields.next().unwrap_or_default(), fields.next())
+}
+
+/// Resolves each name's destination up front, so two names that sanitize to the same directory
+/// never race each other inside a pool; the later ones are added to `failed`, as are lines that
+/// are neither `owner/repo` names nor git URLs, which are never cloned. Blank lines are ignored.
+pub(crate) fn plan_clones<'a>(names: &'a str, out_root: &str, failed: &mut Vec<CloneError>) -> Vec<CloneJob<'a>> {
+    let mut claimed: HashMap<String, &str> = HashMap::new();
+    let mut work = Vec::new();
+    for line in names.lines().map(str::trim).filter(|n| !n.is_empty()) {
+        let (name, rev) = split_pin(line);
+        if !is_git_url(name) && canonical_repo_name(name).as_deref() != Ok(name) {
+            failed.push(CloneError { name: line.to_string(), class: CloneErrorClass::InvalidName, attempts: 0, error: "not an owner/repo name or a git URL; skipped without cloning".into() });
+            continue;
+        }
+        let dir = format!("dataset_{}", sanitize(name));
+        // Names differing in case only are the same GitHub repository, and the same directory on
+        // case-insensitive filesystems.
+        match claimed.get(&dir.to_ascii_lowercase()) {
+            Some(&first) if first.eq_ignore_ascii_case(name) => {}
+            Some(&first) => failed.push(CloneError { name: name.to_string(), class: CloneErrorClass::Conflict, attempts: 0, error: format!("destination {} already used by {}", dir, first) }),
+            None => {
+                claimed.insert(dir.to_ascii_lowercase(), name);
+                work.push(CloneJob { name, rev, dest: Path::new(out_root).join(dir) });
+            }
+        }
+    }
+    work
+}
+
+/// Clones the job's repository into its `dest` with `backend`, showing libgit2's transfer progress
+/// on `bar`, then checks out its pinned revision, if any. Pins apply to existing checkouts too,
+/// even skipped ones.
+pub(crate) fn clone_one(job: &CloneJob, auth: GitAuth, on_existing: OnExisting, history: &HistoryOptions, backend: GitBackend, bar: &ProgressBar) -> anyhow::Result<CloneOutcome> {
+    match backend {
+        GitBackend::Libgit2 => checkout(job, auth, on_existing, history, false, bar),
+        GitBackend::Cli => checkout(job, auth, on_existing, history, true, bar),
+        GitBackend::Auto if history.filter.is_some() => checkout(job, auth, on_existing, history, true, bar),
+        GitBackend::Auto => match checkout(job, auth, on_existing, history, false, bar) {
+            Err(e) if !shutdown_requested() && git_cli::available() => {
+                tracing::debug!(repo = job.name, "libgit2 failed ({:#}); retrying with git", e);
+                bar.set_message("retrying with git");
+                checkout(job, auth, on_existing, history, true, bar)
+            }
+            res => res,
+        },
+    }
+}
+
+/// `clone_one` with libgit2, or with the `git` program when `cli`. Both leave the same checkout.
+fn checkout(job: &CloneJob, auth: GitAuth, on_existing: OnExisting, history: &HistoryOptions, cli: bool, bar: &ProgressBar) -> anyhow::Result<CloneOutcome> {
+    let (name, dest) = (job.name, job.dest.as_path());
+    let stripped = is_non_empty_dir(dest) && !dest.join(".git").exists();
+    if stripped && on_existing != OnExisting::Overwrite {
+        if on_existing == OnExisting::Update || job.rev.is_some() {
+            tracing::warn!("⚠ {}: {} has no .git (cloned with --strip-git?), so it can't be updated or checked out at a pin; left as it is", name, dest.display());
+        }
+        return Ok(CloneOutcome::Skipped);
+    }
+    let outcome = match is_non_empty_dir(dest) {
+        true if on_existing == OnExisting::Skip => CloneOutcome::Skipped,
+        true if on_existing == OnExisting::Update => {
+            match (job.rev, cli) {
+                (Some(_), _) => {}
+                (None, false) => update_repo(dest, name, auth, history, bar)?,
+                (None, true) => git_cli::update(dest, auth, history).map_err(|e| auth.error(e, name))?,
+            }
+            CloneOutcome::Updated
+        }
+        existing => {
+            if existing {
+                fs::remove_dir_all(dest)?;
+            }
+            fs::create_dir_all(dest)?;
+            let url = auth.opts.remote_url(name);
+            let res = match cli {
+                true => git_cli::clone(&url, dest, auth, history),
+                false => {
+                    let mut builder = RepoBuilder::new();
+                    builder.fetch_options(fetch_options(auth, bar, history.fetch_depth()));
+                    match history.single_branch {
+                        true => default_branch(&url, auth).and_then(|branch| {
+                            let refspec = format!("+refs/heads/{0}:refs/remotes/origin/{0}", branch);
+                            builder.branch(&branch).remote_create(move |repo, name, url| repo.remote_with_fetch(name, url, &refspec)).clone(&url, dest)
+                        }).map(drop),
+                        false => builder.clone(&url, dest).map(drop),
+                    }
+                }
+            };
+            if let Err(e) = res {
+                // A half-written checkout would otherwise be taken as present by `--on-existing skip`.
+                let _ = fs::remove_dir_all(dest);
+                return Err(auth.error(e, name));
+            }
+            CloneOutcome::Cloned
+        }
+    };
+    if let Some(rev) = job.rev {
+        let res = match cli {
+            true => git_cli::checkout_rev(dest, name, rev, auth, history),
+            false => checkout_rev(dest, name, rev, auth, history, bar),
+        };
+        res.with_context(|| format!("failed to check out {} of {}", rev, name))?;
+    }
+    if history.submodules != Submodules::Skip {
+        let res = match cli {
+            true => git_cli::update_submodules(dest, auth, history.submodules).map_err(|e| auth.error(e, name)),
+            false => Repository::open(dest).map_err(anyhow::Error::from).and_then(|repo| update_submodules(&repo, name, auth, history.submodules, bar)),
+        };
+        res.with_context(|| format!("failed to initialize the submodules of {}", name))?;
+    }
+    Ok(outcome)
+}
+
+/// Initializes and checks out the submodules of `repo`, then theirs, with the same credentials.
+/// A shallow submodule whose recorded commit isn't its remote's HEAD gets that commit fetched
+/// the way a pin is.
+fn update_submodules(repo: &Repository, name: &str, auth: GitAuth, mode: Submodules, bar: &ProgressBar) -> anyhow::Result<()> {
+    let depth = if mode == Submodules::Shallow { 1 } else { 0 };
+    for mut sub in repo.submodules()? {
+        let path = sub.path().display().to_string();
+        bar.set_message(format!("submodule {}", path));
+        let mut opts = git2::SubmoduleUpdateOptions::new();
+        opts.fetch(fetch_options(auth, bar, depth));
+        if let Err(e) = sub.update(true, Some(&mut opts)) {
+            let (Ok(checkout), Some(commit)) = (sub.open(), sub.index_id().or(sub.head_id())) else {
+                return Err(auth.error(e, name)).with_context(|| format!("failed to clone submodule {}", path));
+            };
+            let workdir = checkout.workdir().context("submodule without a working tree")?.to_path_buf();
+            let history = HistoryOptions { depth: 1, ..HistoryOptions::default() };
+            checkout_rev(&workdir, name, &commit.to_string(), auth, &history, bar).with_context(|| format!("failed to check out submodule {}", path))?;
+        }
+        update_submodules(&sub.open()?, name, auth, mode, bar)?;
+    }
+    Ok(())
+}
+
+/// Checks out `rev` with a detached HEAD. A revision the shallow clone doesn't have is fetched:
+/// by SHA (GitHub serves any commit by it) or as a tag, and failing that, with the whole history.
+fn checkout_rev(dest: &Path, name: &str, rev: &str, auth: GitAuth, history: &HistoryOptions, bar: &ProgressBar) -> anyhow::Result<()> {
+    let repo = Repository::open(dest)?;
+    let find = |repo: &Repository| repo.revparse_single(rev).and_then(|o| o.peel_to_commit()).ok().map(|c| c.id());
+    let commit = match find(&repo) {
+        Some(id) => id,
+        None => {
+            bar.set_message(format!("fetching {}", rev));
+            let mut remote = repo.find_remote("origin")?;
+            let is_sha = rev.len() == 40 && rev.bytes().all(|b| b.is_ascii_hexdigit());
+            let wanted = if is_sha { rev.to_string() } else { format!("+refs/tags/{0}:refs/tags/{0}", rev) };
+            if let Err(e) = remote.fetch(&[wanted.as_str()], Some(&mut fetch_options(auth, bar, history.fetch_depth())), None) {
+                tracing::debug!(repo = name, "fetching {} alone failed ({}); fetching the whole history", rev, e.message());
+            }
+            match find(&repo) {
+                Some(id) => id,
+                None => {
+                    let all = ["+refs/heads/*:refs/remotes/origin/*", "+refs/tags/*:refs/tags/*"];
+                    let depth = if repo.is_shallow() { UNSHALLOW } else { 0 };
+                    remote.fetch(&all, Some(&mut fetch_options(auth, bar, depth)), None).map_err(|e| auth.error(e, name))?;
+                    find(&repo).with_context(|| format!("{} has no revision `{}`", name, rev))?
+                }
+            }
+        }
+    };
+    let object = repo.find_object(commit, None)?;
+    repo.checkout_tree(&object, Some(git2::build::CheckoutBuilder::new().force()))?;
+    repo.set_head_detached(commit)?;
+    Ok(())
+}
+
+/// The commit checked out at `path`, or `None` when it isn't a git repository.
+pub fn head_commit(path: &Path) -> Option<String> {
+    Some(Repository::open(path).ok()?.head().ok()?.peel_to_commit().ok()?.id().to_string())
+}
+
+/// The job's clone manifest line, read from its checkout; `None` when an existing checkout isn't
+/// a git repository (or has no commit). `history` is only recorded when this run cloned it.
+pub(crate) fn clone_record(job: &CloneJob, outcome: &CloneOutcome, history: &HistoryOptions, git_stripped: bool) -> Option<CloneRecord> {
+    let repo = Repository::open(&job.dest).ok()?;
+    let head = repo.head().ok()?;
+    let commit = head.peel_to_commit().ok()?;
+    let time = chrono::DateTime::from_timestamp(commit.time().seconds(), 0).unwrap_or_default();
+    Some(CloneRecord {
+        name: job.dest.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+        repo: job.name.to_string(),
+        requested: job.rev.map(String::from),
+        commit: commit.id().to_string(),
+        branch: head.is_branch().then(|| head.shorthand().map(String::from)).flatten(),
+        committed_at: time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        shallow: repo.is_shallow(),
+        history: matches!(outcome, CloneOutcome::Cloned).then(|| history.clone()),
+        git_stripped,
+        has_uninitialized_submodules: repo.submodules().is_ok_and(|subs| subs.iter().any(|sub| sub.open().is_err())),
+        gone: false,
+    })
+}
+
+fn fetch_options(auth: GitAuth, bar: &ProgressBar, depth: i32) -> FetchOptions<'static> {
+    let mut callbacks = RemoteCallbacks::new();
+    let bar = bar.clone();
+    callbacks.transfer_progress(move |stats| {
+        if stats.received_objects() < stats.total_objects() {
+            bar.set_message(format!("{}/{} objects, {}", stats.received_objects(), stats.total_objects(), HumanBytes(stats.received_bytes() as u64)));
+        } else {
+            bar.set_message(format!("resolving deltas {}/{}", stats.indexed_deltas(), stats.total_deltas()));
+        }
+        true
+    });
+    auth.callbacks(&mut callbacks);
+    let mut fo = FetchOptions::new();
+    fo.depth(depth).remote_callbacks(callbacks);
+    fo
+}
+
+/// The remote's default branch, e.g. `main`, asked before a `--single-branch` clone.
+fn default_branch(url: &str, auth: GitAuth) -> Result<String, git2::Error> {
+    let mut remote = git2::Remote::create_detached(url)?;
+    let mut callbacks = RemoteCallbacks::new();
+    auth.callbacks(&mut callbacks);
+    let connection = remote.connect_auth(git2::Direction::Fetch, Some(callbacks), None)?;
+    let head = connection.default_branch()?;
+    Ok(head.as_str().unwrap_or_default().trim_start_matches("refs/heads/").to_string())
+}
+
+/// Fetches the remote HEAD into an existing checkout and hard-resets the working tree to it.
+fn update_repo(dest: &Path, name: &str, auth: GitAuth, history: &HistoryOptions, bar: &ProgressBar) -> anyhow::Result<()> {
+    let repo = Repository::open(dest)?;
+    let mut remote = repo.find_remote("origin")?;
+    if let Err(e) = remote.fetch(&["HEAD"], Some(&mut fetch_options(auth, bar, history.fetch_depth())), None) {
+        return Err(auth.error(e, name));
+    }
+    let head = repo.find_reference("FETCH_HEAD")?.peel_to_commit()?;
+    repo.reset(head.as_object(), ResetType::Hard, None)?;
+    Ok(())
+}
+
+/// Where `refresh_checkout` moved a checkout: from `old` to `new`, ahead (`fast_forward`) or onto
+/// a rewritten history; `None` when a shallow checkout has too little history to tell.
+pub(crate) struct Refresh {
+    pub(crate) old: String,
+    pub(crate) new: String,
+    pub(crate) fast_forward: Option<bool>,
+}
+
+/// Fetches the remote's default branch into the checkout at `dest`, as deep as it already is,
+/// and hard-resets the branch checked out to it: a fast-forward, or a force-pushed history
+/// replacing the local one.
+pub(crate) fn refresh_checkout(dest: &Path, name: &str, auth: GitAuth, bar: &ProgressBar) -> anyhow::Result<Refresh> {
+    let repo = Repository::open(dest)?;
+    let old = repo.head()?.peel_to_commit()?.id();
+    let shallow = repo.is_shallow();
+    let depth = if shallow { shallow_depth(&repo)? } else { 0 };
+    let mut remote = repo.find_remote("origin")?;
+    if let Err(e) = remote.fetch(&["HEAD"], Some(&mut fetch_options(auth, bar, depth)), None) {
+        return Err(auth.error(e, name));
+    }
+    let new = repo.find_reference("FETCH_HEAD")?.peel_to_commit()?;
+    let fast_forward = match new.id() == old {
+        true => Some(true),
+        false if shallow => None,
+        false => Some(repo.graph_descendant_of(new.id(), old)?),
+    };
+    repo.reset(new.as_object(), ResetType::Hard, None)?;
+    Ok(Refresh { old: old.to_string(), new: new.id().to_string(), fast_forward })
+}
+
+/// The depth a shallow checkout was cloned at: the commits on HEAD's first-parent chain, which
+/// ends at the shallow boundary.
+fn shallow_depth(repo: &Repository) -> anyhow::Result<i32> {
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+    walk.simplify_first_parent()?;
+    Ok(i32::try_from(walk.count()).unwrap_or(UNSHALLOW - 1).max(1))
+}
+
+fn is_non_empty_dir(path: &Path) -> bool {
+    fs::read_dir(path).map(|mut d| d.next().is_some()).unwrap_or(false)
+}
+
+fn is_auth_error(e: &git2::Error) -> bool {
+    e.code() == ErrorCode::Auth
+        || (e.class() == ErrorClass::Http && (e.message().contains("401") || e.message().contains("403")))
+}
+
+/// `owner/repo` of a names-file entry naming a GitHub repository, by name or by URL.
+pub(crate) fn github_repo(name: &str) -> Result<String, String> {
+    match is_git_url(name) && !["github.com", "www.github.com"].iter().any(|h| url_host(name).eq_ignore_ascii_case(h)) {
+        true => Err(format!("`{}` is not a GitHub repository", name)),
+        false => canonical_repo_name(name),
+    }
+}
+
+/// The name outputs give the repository of the checkout at `path`, from `repo`, its names-file
+/// entry when known, or else from the checkout's `origin` remote; `None` when there is neither.
+pub(crate) fn repo_name(path: &Path, repo: Option<&str>) -> Option<String> {
+    match repo {
+        Some(repo) => Some(output_name(repo)),
+        None => Some(output_name(Repository::open(path).ok()?.find_remote("origin").ok()?.url()?)),
+    }
+}
+
+/// `owner/repo` for a GitHub entry, the URL without credentials for another.
+pub(crate) fn output_name(entry: &str) -> String {
+    github_repo(entry).unwrap_or_else(|_| without_credentials(entry))
+}
+
+fn without_credentials(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else { return url.to_string() };
+    let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+    match authority.rsplit_once('@') {
+        Some((_, host)) => format!("{}://{}/{}", scheme, host, path),
+        None => url.to_string(),
+    }
+}
+
+/// The longest directory name `sanitize` returns, well under the limits of old filesystems once
+/// `dataset_` is prepended.
+const MAX_DIR_NAME: usize = 100;
+
+/// The checkout directory name (without `dataset_`) of a names-file entry. `owner/repo` becomes
+/// `owner__repo`, as does a GitHub URL of it; another git URL becomes `host__path__to__repo`,
+/// whatever its scheme, user or `.git` suffix. Characters other than `[A-Za-z0-9._-]` become `_`.
+/// Wherever the name couldn't be read back unambiguously (an `_` that could be taken for part of
+/// a separator, a character replaced), ends in a dot (which Windows drops) or is cut to
+/// `MAX_DIR_NAME`, a hash of the entry is appended, so that no two entries ever share a directory,
+/// whatever else is listed.
+pub fn sanitize(name: &str) -> String {
+    let path = match github_repo(name) {
+        Ok(repo) => repo,
+        Err(_) => {
+            let rest = match name.split_once("://") {
+                Some((_, rest)) => rest.to_string(),
+                // `git@host:path` puts its path after the colon.
+                None => name.replacen(':', "/", 1),
+            };
+            let (host, path) = rest.split_once('/').unwrap_or((&rest, ""));
+            let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+            let path = path.trim_end_matches('/');
+            format!("{}/{}", host, path.strip_suffix(".git").unwrap_or(path))
+        }
+    };
+    let valid = |c: char| c.is_ascii_alphanumeric() || "._-".contains(c);
+    let parts: Vec<&str> = path.split('/').collect();
+    let mut dir = parts.iter().map(|part| part.chars().map(|c| if valid(c) { c } else { '_' }).collect::<String>()).collect::<Vec<_>>().join("__");
+    let (last, owners) = parts.split_last().unwrap_or((&"", &[]));
+    let reversible = path.chars().all(|c| valid(c) || c == '/') && !owners.iter().any(|p| p.contains('_')) && !last.starts_with('_') && !last.contains("__");
+    if reversible && !dir.ends_with('.') && dir.len() <= MAX_DIR_NAME {
+        return dir;
+    }
+    dir.truncate(MAX_DIR_NAME - 9);
+    format!("{}_{:08x}", dir, crate::cache::fnv1a(path.as_bytes()) as u32)
+}
@@ -0,0 +1,847 @@
+//! The `collect` step: gather every source file of each checkout into `code.jsonl`.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+use anyhow::Context;
+use clap::Args;
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::analyzers::CODEQL_DB_DIR;
+use crate::clone::{clone_records, name_map, repo_name, CloneRecord};
+use crate::columnar::{finish_as_parquet, working_jsonl, DatasetKind, FileFormat};
+use crate::dedup::{content_hash, dedup_code, near_dedup_code, Dedup, NearDedupOptions};
+use crate::generated::{flagged_files_beside, generated_by, is_vendored, FlaggedFiles};
+use crate::items::{extract, CodeItem, Extracted, Granularity};
+use crate::jsonl::{read_jsonl, replace_file, scan_jsonl, Codec, JsonlWriter};
+use crate::layout::{CodeKind, CrateLayout};
+use crate::license::{detect_license, license_allowed, licenses_beside, LicenseTable, RepoLicense};
+use crate::progress::Progress;
+use crate::provenance::Provenance;
+use crate::schema::CODE_SCHEMA_VERSION;
+use crate::shard::{finish_as_shards, is_manifest, ShardOptions};
+use crate::shutdown::{shutdown_requested, Checkpoint};
+use crate::secrets::{redact_secrets, scan_secrets, secrets_report_beside, SecretFinding, SecretMatch, SecretRule, SecretsMode};
+use crate::tokenizer::{Tokenizer, TokenizerKind};
+
+/// Left out of every collect, as gitignore patterns: JavaScript dependencies and test fixtures.
+/// Vendored Rust is collected, flagged `vendored`, unless `--skip-vendored`.
+pub const DEFAULT_EXCLUDES: &[&str] = &["node_modules/", "fixtures/", "testdata/"];
+
+/// Written next to the code file: the files each repository had that weren't collected.
+pub const SKIPPED_FILES_FILE: &str = "skipped_files.jsonl";
+
+/// How much of a file is looked at for NUL bytes before it is read whole.
+const BINARY_SNIFF_BYTES: usize = 8192;
+
+#[derive(Args, Clone, Debug)]
+pub struct CollectOptions {
+    /// fsync the output file after every N entries (each entry is always flushed)
+    #[arg(long, value_name = "N")]
+    pub fsync_every: Option<usize>,
+    /// Write the code file as JSONL, or as Parquet once the collect is done, with a `.parquet`
+    /// extension
+    #[arg(long, id = "output_format", value_name = "FORMAT", value_enum, default_value_t = FileFormat::Jsonl)]
+    pub format: FileFormat,
+    /// Rows per row group with `--format parquet` (default 8192)
+    #[arg(long, value_name = "N")]
+    pub row_group_size: Option<usize>,
+    /// Compress the code file with gzip or zstd, at an optional level (`zstd:9`), adding `.gz` or
+    /// `.zst` to its name; a code file named so is compressed without it
+    #[arg(long, value_name = "CODEC[:LEVEL]", conflicts_with = "output_format")]
+    pub compress: Option<Codec>,
+    #[command(flatten)]
+    pub shard: ShardOptions,
+    /// Continue a collect stopped by Ctrl-C: append to the code file, skipping the repositories
+    /// listed in its checkpoint.json
+    #[arg(long)]
+    pub resume: bool,
+    /// Only the files with these extensions
+    #[arg(long, value_name = "EXT,...", value_delimiter = ',', default_value = "rs,toml")]
+    pub extensions: Vec<String>,
+    /// Every text file, whatever its extension
+    #[arg(long, conflicts_with = "extensions")]
+    pub all_files: bool,
+    /// Also leave out the paths matching this gitignore-style pattern, e.g. `benches/data/`
+    /// (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    pub exclude_glob: Vec<String>,
+    /// Skip the files larger than this, such as generated data and minified bundles (0: no limit)
+    #[arg(long, value_name = "N", default_value_t = 1 << 20)]
+    pub max_file_bytes: u64,
+    /// Collect files that aren't valid UTF-8, with the invalid bytes replaced by U+FFFD
+    #[arg(long)]
+    pub lossy_utf8: bool,
+    /// Convert CRLF and CR line endings to LF and strip a leading byte order mark before the
+    /// content is hashed and written, recording `original_newlines`
+    #[arg(long, value_name = "BOOL", default_value_t = true, action = clap::ArgAction::Set)]
+    pub normalize_newlines: bool,
+    /// Number of repositories to walk concurrently; a single thread writes the code file
+    #[arg(long, default_value_t = 1)]
+    pub jobs: usize,
+    /// Drop exact duplicate files once the collect is done: none, within-repo or global
+    #[arg(long, value_enum, default_value_t = Dedup::None)]
+    pub dedup: Dedup,
+    /// Only the files of these parts of their crate: lib, bin, tests, examples, benches, build or
+    /// other (all by default)
+    #[arg(long, value_name = "KIND,...", value_enum, value_delimiter = ',')]
+    pub code_kinds: Vec<CodeKind>,
+    /// One entry per file, per top-level item, or per function and macro_rules! of `.rs` files
+    #[arg(long, value_enum, default_value_t = Granularity::File)]
+    pub granularity: Granularity,
+    /// Skip the files with a line longer than this many characters, typical of minified code
+    #[arg(long, value_name = "N")]
+    pub max_line_length: Option<usize>,
+    /// Skip the files whose lines are longer than this on average, typical of generated code
+    #[arg(long, value_name = "N")]
+    pub max_avg_line_length: Option<f64>,
+    /// Count the tokens of every file into `tokens`: none, whitespace or tiktoken
+    #[arg(long, value_enum, default_value_t = TokenizerKind::None)]
+    pub tokenizer: TokenizerKind,
+    /// The ranks file of `--tokenizer tiktoken`, e.g. cl100k_base.tiktoken
+    #[arg(long, value_name = "FILE")]
+    pub tokenizer_file: Option<PathBuf>,
+    /// Scan files for committed keys and tokens: off, redact, tag (`secrets_found`) or skip-file;
+    /// every secret found is listed in secrets_report.jsonl, without its value
+    #[arg(long, value_enum, default_value_t = SecretsMode::Off)]
+    pub secrets: SecretsMode,
+    /// Secret rules not to apply, such as the false-positive-prone high-entropy
+    #[arg(long, value_name = "RULE,...", value_enum, value_delimiter = ',')]
+    pub disable_secret_rules: Vec<SecretRule>,
+    /// Only the repositories whose license allows one of these SPDX ids, e.g.
+    /// MIT,Apache-2.0,BSD-3-Clause; those without a detectable license only with `unknown`
+    #[arg(long, value_name = "SPDX,...", value_delimiter = ',')]
+    pub allow_licenses: Vec<String>,
+    /// Leave out the repositories that can only be used under one of these, e.g. GPL-3.0
+    #[arg(long, value_name = "SPDX,...", value_delimiter = ',')]
+    pub deny_licenses: Vec<String>,
+    /// Leave out the files flagged `generated`: named `*_generated.rs` or `*.pb.rs`, with an
+    /// `@generated`-like header comment, or mostly `#[automatically_derived]` impls
+    #[arg(long)]
+    pub skip_generated: bool,
+    /// Leave out the files flagged `vendored`, under vendor/, third_party/ and the like
+    #[arg(long)]
+    pub skip_vendored: bool,
+    /// Also flag as generated the files with this text in their first lines, case-insensitively
+    /// (repeatable)
+    #[arg(long, value_name = "TEXT")]
+    pub generated_marker: Vec<String>,
+    /// Then cluster near-duplicate files, as `near-dedup` does
+    #[arg(long)]
+    pub near_dedup: bool,
+    #[command(flatten)]
+    pub near: NearDedupOptions,
+}
+
+impl Default for CollectOptions {
+    fn default() -> Self {
+        CollectOptions {
+            fsync_every: None,
+            format: FileFormat::Jsonl,
+            row_group_size: None,
+            compress: None,
+            shard: ShardOptions::default(),
+            resume: false,
+            extensions: vec!["rs".into(), "toml".into()],
+            all_files: false,
+            exclude_glob: Vec::new(),
+            max_file_bytes: 1 << 20,
+            lossy_utf8: false,
+            normalize_newlines: true,
+            jobs: 1,
+            code_kinds: Vec::new(),
+            granularity: Granularity::File,
+            max_line_length: None,
+            max_avg_line_length: None,
+            tokenizer: TokenizerKind::None,
+            tokenizer_file: None,
+            dedup: Dedup::None,
+            secrets: SecretsMode::Off,
+            disable_secret_rules: Vec::new(),
+            allow_licenses: Vec::new(),
+            deny_licenses: Vec::new(),
+            skip_generated: false,
+            skip_vendored: false,
+            generated_marker: Vec::new(),
+            near_dedup: false,
+            near: NearDedupOptions::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CodeEntry {
+    pub schema_version: u32,
+    /// The repository, as in `OutputEntry::name`
+    pub name: String,
+    /// The checkout directory, as in `OutputEntry::dir`
+    #[serde(default)]
+    pub dir: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub name_unresolved: bool,
+    /// The names-file entry the checkout was cloned from, as in `OutputEntry::repo`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    /// HEAD commit of the checkout the file was read from, as in `OutputEntry::commit`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// The last commit that changed the file, and its author date; absent for a file HEAD
+    /// doesn't have, and for every file of a checkout without `.git`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_commit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_commit_date: Option<String>,
+    /// The file as collected differs from HEAD's, or HEAD doesn't have it; absent without `.git`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<bool>,
+    pub path: String,
+    /// Guessed from the file name, e.g. `Rust` or `TOML`; absent when it isn't known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// The part of its crate the file is, by where it is relative to the nearest `Cargo.toml`
+    #[serde(default)]
+    pub kind: CodeKind,
+    /// The package of a lib or bin file and its target, `lib` or `bin:<name>`, by the standard
+    /// layout; with `dir` they pair the file with its target's line in `expanded.jsonl`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// The item `content` is, with `--granularity item` or `function`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item: Option<CodeItem>,
+    /// The file didn't parse as Rust, so this entry is all of it despite `--granularity`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub parse_failed: bool,
+    pub content: String,
+    /// Size of `content` as written: lines, UTF-8 bytes, and characters per line
+    #[serde(default)]
+    pub lines: usize,
+    #[serde(default)]
+    pub bytes: usize,
+    #[serde(default)]
+    pub avg_line_length: f64,
+    #[serde(default)]
+    pub max_line_length: usize,
+    /// `content` in tokens of `--tokenizer`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<usize>,
+    /// blake3 of `content`, in hex
+    #[serde(default)]
+    pub content_hash: String,
+    /// Copies of this content dropped by `--dedup`: elsewhere in the repository, or anywhere
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub duplicate_count: usize,
+    /// The other repositories that had a dropped copy (`--dedup global`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub duplicate_repos: Vec<String>,
+    /// Entry number of the first file of its near-duplicate cluster (`near-dedup`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cluster_id: Option<usize>,
+    /// Near-duplicates dropped in favour of this file (`near-dedup --action drop`)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub near_duplicate_count: usize,
+    /// The file is in a submodule, whose license and authors may differ from the repository's
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub from_submodule: bool,
+    /// The file isn't valid UTF-8, and `content` has U+FFFD where it wasn't (`--lossy-utf8`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub lossy_utf8: bool,
+    /// The line endings `content` had before `--normalize-newlines` made them LF: crlf or cr
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original_newlines: Option<String>,
+    /// The repository's license, as in `licenses.jsonl`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license_spdx: Option<String>,
+    /// The rules of the secrets in `content` (`--secrets tag`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secrets_found: Vec<SecretRule>,
+    /// Written by a tool rather than a person, as `generated_by` tells
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub generated: bool,
+    /// The heuristic that flagged the file `generated`: path, marker or automatically_derived
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generated_by: Option<String>,
+    /// Under a directory of another project's code, such as vendor/
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub vendored: bool,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+/// Why a file that passed the extension and path filters wasn't collected.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// Over `--max-file-bytes`
+    TooLarge,
+    /// Has a NUL byte near its start
+    Binary,
+    /// Isn't valid UTF-8 and `--lossy-utf8` wasn't given
+    NonUtf8,
+    /// Has a line over `--max-line-length`
+    LineTooLong,
+    /// Its lines are over `--max-avg-line-length` on average
+    AvgLineTooLong,
+    /// Has a secret, with `--secrets skip-file`
+    Secrets,
+    /// Flagged `generated`, with `--skip-generated`
+    Generated,
+    /// Flagged `vendored`, with `--skip-vendored`
+    Vendored,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SkippedFile {
+    pub path: String,
+    pub reason: SkipReason,
+    pub bytes: u64,
+}
+
+/// One line of `skipped_files.jsonl`, for a repository with at least one skipped file.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SkippedFiles {
+    /// As in `CodeEntry::name`
+    pub name: String,
+    pub dir: String,
+    pub files: Vec<SkippedFile>,
+}
+
+/// What every entry of a checkout is labelled with, as in `CodeEntry`. `commit` is only used
+/// when the checkout has no `.git` to read HEAD from.
+#[derive(Clone, Debug, Default)]
+pub struct CodeSource {
+    pub name: String,
+    pub name_unresolved: bool,
+    pub repo: Option<String>,
+    pub commit: Option<String>,
+}
+
+/// A checkout's collected files, and those left out by size or content.
+#[derive(Debug, Default)]
+pub struct CollectedCode {
+    pub entries: Vec<CodeEntry>,
+    pub skipped: Vec<SkippedFile>,
+    pub secrets: Vec<SecretFinding>,
+    pub license: RepoLicense,
+    pub flagged: FlaggedFiles,
+}
+
+/// What `collect_code_into` reports besides its entries.
+#[derive(Debug, Default)]
+pub struct CollectReport {
+    pub skipped: Vec<SkippedFile>,
+    /// With `--secrets`, whatever the mode
+    pub secrets: Vec<SecretFinding>,
+    /// With `excluded` set, nothing was collected
+    pub license: RepoLicense,
+    /// The generated and vendored files, skipped or not
+    pub flagged: FlaggedFiles,
+}
+
+/// Where `collect` writes the skipped files of `code_file`.
+pub fn skipped_files_beside(code_file: &str) -> PathBuf {
+    Path::new(code_file).parent().unwrap_or(Path::new("")).join(SKIPPED_FILES_FILE)
+}
+
+/// The labels of the checkout at `path`, from the clone manifest and names file of its root.
+pub(crate) fn code_source(path: &Path, records: &HashMap<String, CloneRecord>, names: &BTreeMap<String, String>) -> CodeSource {
+    let dir = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let record = records.get(&dir);
+    let repo = names.get(&dir).or(record.map(|r| &r.repo));
+    let resolved = repo_name(path, repo.map(String::as_str));
+    CodeSource { name: resolved.clone().unwrap_or(dir), name_unresolved: resolved.is_none(), repo: repo.cloned(), commit: record.map(|r| r.commit.clone()) }
+}
+
+/// The labels of the checkouts under `root`, in name order, and their paths.
+pub(crate) fn checkouts(root: &str) -> anyhow::Result<Vec<(CodeSource, PathBuf)>> {
+    let records = clone_records(root);
+    let names = name_map(root);
+    let mut repos: Vec<PathBuf> = fs::read_dir(root)?.filter_map(|e| Some(e.ok()?.path())).filter(|p| p.is_dir()).collect();
+    repos.sort();
+    Ok(repos.into_iter().map(|path| (code_source(&path, &records, &names), path)).collect())
+}
+
+/// Collects the checkouts `dirs` of `root` again into the JSONL `code_file`, in place of their
+/// entries there; the other repositories' entries are kept. The side reports beside it (skipped
+/// files, licenses, flagged files, secrets) aren't rewritten. Returns the files collected.
+pub(crate) fn recollect(root: &str, dirs: &HashSet<String>, code_file: &str, opts: &CollectOptions) -> anyhow::Result<usize> {
+    anyhow::ensure!(!is_manifest(code_file) && !code_file.ends_with(".parquet"), "{} can't be rewritten in place; collect the repositories again with `collect`", code_file);
+    let (text, _) = if Path::new(code_file).exists() { read_jsonl(code_file)? } else { (String::new(), false) };
+    // Entries from before `dir` keep the checkout directory in `name`.
+    let dir_of = |v: &serde_json::Value| v["dir"].as_str().filter(|d| !d.is_empty()).or(v["name"].as_str()).map(String::from);
+    let mut kept: String = scan_jsonl(&text).into_iter().filter(|l| l.value.as_ref().and_then(dir_of).is_some_and(|d| !dirs.contains(&d))).map(|l| l.text).collect();
+    let records = clone_records(root);
+    let names = name_map(root);
+    let mut dirs: Vec<&String> = dirs.iter().collect();
+    dirs.sort();
+    let mut files = 0;
+    for dir in dirs {
+        let path = Path::new(root).join(dir);
+        collect_code_into(&path, &code_source(&path, &records, &names), opts, &mut |entry| {
+            files += 1;
+            kept.push_str(&serde_json::to_string(&entry)?);
+            kept.push('\n');
+            Ok(())
+        })?;
+    }
+    replace_file(code_file, &kept)?;
+    Ok(files)
+}
+
+/// The `.rs` files `collect` would take from the checkout with its defaults and `exclude_glob`,
+/// after the module their items are in.
+pub(crate) fn rust_files(repo_path: &Path, source: &CodeSource, exclude_glob: &[String]) -> anyhow::Result<Vec<(String, CodeEntry)>> {
+    let opts = CollectOptions { exclude_glob: exclude_glob.to_vec(), ..CollectOptions::default() };
+    let mut files = Vec::new();
+    // The manifests are collected too, for the layout the module paths come from.
+    collect_code_into(repo_path, source, &opts, &mut |entry| {
+        files.push(entry);
+        Ok(())
+    })?;
+    let layout = CrateLayout::scan(&files.iter().map(|f| repo_path.join(&f.path)).collect::<Vec<_>>());
+    Ok(files.into_iter().filter(|f| f.path.ends_with(".rs")).map(|f| (layout.module_path(&repo_path.join(&f.path)), f)).collect())
+}
+
+/// What `collect_code_all` workers send its writer.
+enum Collected {
+    Entry(Box<CodeEntry>),
+    Skipped(SkippedFiles),
+    Secrets(Vec<SecretFinding>),
+    Flagged(FlaggedFiles),
+    /// With the number of entries collected
+    License(RepoLicense, usize),
+    /// Every line of the repository has been sent.
+    Done(String),
+}
+
+/// Entries in flight between the walkers and the writer; bounds memory to a few files per worker.
+const CHANNEL_CAPACITY: usize = 64;
+
+pub fn collect_code_all(root: &str, code_file: &str, opts: &CollectOptions) -> anyhow::Result<()> {
+    let working = code_working_file(code_file, opts)?;
+    collect_all(root, &working, opts)?;
+    finish_code(&working, opts)
+}
+
+/// The JSONL file the collect writes and its dedup passes rewrite, before `finish_code`:
+/// `code_file` with `--compress`'s extension, or its JSONL twin with `--format parquet`, since
+/// --resume and the dedup passes need JSONL.
+pub(crate) fn code_working_file(code_file: &str, opts: &CollectOptions) -> anyhow::Result<String> {
+    match opts.format {
+        FileFormat::Parquet => Ok(working_jsonl(code_file)),
+        FileFormat::Jsonl => Codec::path(opts.compress, code_file),
+    }
+}
+
+/// Turns the finished working file into the code file `opts` asks for: Parquet, or shards with
+/// a manifest, or as it is.
+pub(crate) fn finish_code(working: &str, opts: &CollectOptions) -> anyhow::Result<()> {
+    if opts.format == FileFormat::Parquet {
+        return finish_as_parquet(working, DatasetKind::Code, opts.row_group_size);
+    }
+    if opts.shard.enabled() {
+        return finish_as_shards(working, opts.compress.unwrap_or(Codec::of(working)), &opts.shard);
+    }
+    Ok(())
+}
+
+fn collect_all(root: &str, code_file: &str, opts: &CollectOptions) -> anyhow::Result<()> {
+    let checkpoint = if opts.resume { Checkpoint::load_beside(code_file, "collect")? } else { None };
+    let mut done: Vec<String> = checkpoint.map(|c| c.completed).unwrap_or_default();
+    // Without a checkpoint there is no telling which repositories are complete, so start over.
+    let open = |path: &Path| match done.is_empty() {
+        true => File::create(path),
+        false => OpenOptions::new().create(true).append(true).open(path),
+    };
+    let mut w = JsonlWriter::compressed(open(Path::new(code_file))?, opts.compress.unwrap_or(Codec::of(code_file)), opts.fsync_every)?;
+    let mut skipped = JsonlWriter::new(open(&skipped_files_beside(code_file))?, None);
+    let mut secrets = match opts.secrets {
+        SecretsMode::Off => None,
+        _ => Some(JsonlWriter::new(open(&secrets_report_beside(code_file))?, None)),
+    };
+    let mut licenses = JsonlWriter::new(open(&licenses_beside(code_file))?, None);
+    let mut flagged = JsonlWriter::new(open(&flagged_files_beside(code_file))?, None);
+    let mut table = LicenseTable::default();
+    let records = clone_records(root);
+    let names = name_map(root);
+    let mut repos = Vec::new();
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        if path.is_dir() && !done.iter().any(|d| path.file_name().is_some_and(|n| n.to_string_lossy() == *d)) { repos.push(path); }
+    }
+    if !done.is_empty() {
+        tracing::info!("Resuming: {} repositories already collected, {} to go", done.len(), repos.len());
+    }
+    let progress = Progress::new(repos.len(), "repositories collected");
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(opts.jobs.max(1)).build()?;
+    let (tx, rx) = mpsc::sync_channel(CHANNEL_CAPACITY);
+    let walked = thread::scope(|scope| {
+        let walkers = scope.spawn(|| {
+            pool.install(|| {
+                repos.par_iter().try_for_each_with(tx, |tx, path| {
+                    // After Ctrl-C, only the repositories already in flight are finished.
+                    if shutdown_requested() {
+                        return Ok(());
+                    }
+                    let dir = path.file_name().unwrap().to_string_lossy().into_owned();
+                    let bar = progress.start(&dir);
+                    let source = code_source(path, &records, &names);
+                    let send = |msg| tx.send(msg).map_err(|_| anyhow::anyhow!("the code file writer stopped"));
+                    let mut files = 0;
+                    let report = collect_code_into(path, &source, opts, &mut |entry| {
+                        files += 1;
+                        send(Collected::Entry(Box::new(entry)))
+                    })?;
+                    send(Collected::License(report.license, files))?;
+                    if !report.secrets.is_empty() {
+                        send(Collected::Secrets(report.secrets))?;
+                    }
+                    if !report.flagged.is_empty() {
+                        send(Collected::Flagged(report.flagged))?;
+                    }
+                    if !report.skipped.is_empty() {
+                        send(Collected::Skipped(SkippedFiles { name: source.name, dir: dir.clone(), files: report.skipped }))?;
+                    }
+                    send(Collected::Done(dir))?;
+                    progress.finish(bar);
+                    Ok(())
+                })
+            })
+        });
+        // A write error drops `rx`, which stops the walkers at their next entry.
+        let written = rx.into_iter().try_for_each(|msg| match msg {
+            Collected::Entry(entry) => w.write_entry(&entry),
+            Collected::Skipped(files) => skipped.write_entry(&files),
+            Collected::License(license, files) => {
+                table.add(&license, files);
+                licenses.write_entry(&license)
+            }
+            Collected::Secrets(found) => found.iter().try_for_each(|f| secrets.as_mut().map_or(Ok(()), |w| w.write_entry(f))),
+            Collected::Flagged(files) => flagged.write_entry(&files),
+            Collected::Done(dir) => {
+                done.push(dir);
+                Ok(())
+            }
+        });
+        let walked: anyhow::Result<()> = walkers.join().expect("a collect worker panicked");
+        written.and(walked)
+    });
+    progress.done();
+    walked?;
+    w.finish()?;
+    skipped.finish()?;
+    licenses.finish()?;
+    flagged.finish()?;
+    if let Some(secrets) = secrets {
+        secrets.finish()?;
+    }
+    table.log();
+    if shutdown_requested() {
+        Checkpoint::new("collect", done).write_beside(code_file)?;
+        anyhow::bail!("interrupted while collecting source code; run again with --resume to continue from checkpoint.json");
+    }
+    Checkpoint::remove_beside(code_file, "collect");
+    dedup_collected(code_file, opts)
+}
+
+/// Runs `--dedup` and `--near-dedup` over the finished `code_file`, reporting what they did.
+pub(crate) fn dedup_collected(code_file: &str, opts: &CollectOptions) -> anyhow::Result<()> {
+    if opts.dedup != Dedup::None {
+        let s = dedup_code(code_file, code_file, opts.dedup)?;
+        tracing::info!("Dedup: removed {} of {} entries ({} bytes)", s.removed, s.entries, s.removed_bytes);
+    }
+    if opts.near_dedup {
+        let s = near_dedup_code(code_file, code_file, &opts.near)?;
+        tracing::info!("Near-dedup: {} entries in {} clusters, {} removed ({} bytes)", s.clustered, s.clusters, s.removed, s.removed_bytes);
+    }
+    Ok(())
+}
+
+/// The checkout's source files, filtered with the default `CollectOptions`.
+pub fn collect_code(repo_path: &Path) -> anyhow::Result<Vec<CodeEntry>> {
+    collect_code_with(repo_path, &CollectOptions::default()).map(|code| code.entries)
+}
+
+/// The checkout's text files with one of `opts.extensions` (any, with `opts.all_files`), outside
+/// `DEFAULT_EXCLUDES` and `opts.exclude_glob`. Files too large, binary or not UTF-8 are listed
+/// in `skipped` instead. Holds every file in memory; `collect_code_into` doesn't.
+pub fn collect_code_with(repo_path: &Path, opts: &CollectOptions) -> anyhow::Result<CollectedCode> {
+    let mut entries = Vec::new();
+    let report = collect_code_into(repo_path, &CodeSource::default(), opts, &mut |entry| {
+        entries.push(entry);
+        Ok(())
+    })?;
+    Ok(CollectedCode { entries, skipped: report.skipped, secrets: report.secrets, license: report.license, flagged: report.flagged })
+}
+
+/// Like `collect_code_with`, but hands each file to `emit` as soon as it is read, labelled with
+/// `source`, and returns the skipped files and secrets.
+pub fn collect_code_into(repo_path: &Path, source: &CodeSource, opts: &CollectOptions, emit: &mut dyn FnMut(CodeEntry) -> anyhow::Result<()>) -> anyhow::Result<CollectReport> {
+    let tokenizer = Tokenizer::new(opts.tokenizer, opts.tokenizer_file.as_deref())?;
+    let provenance = Provenance::open(repo_path);
+    let commit = provenance.as_ref().map(|p| p.head.clone()).or_else(|| source.commit.clone());
+    let dir = repo_path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let (license_spdx, license_confidence, license_files) = detect_license(repo_path);
+    let excluded = !license_allowed(&license_spdx, &opts.allow_licenses, &opts.deny_licenses);
+    let license = RepoLicense { name: source.name.clone(), dir: dir.clone(), license_spdx, license_confidence, license_files, excluded };
+    let flagged = FlaggedFiles { name: source.name.clone(), dir: dir.clone(), ..FlaggedFiles::default() };
+    let mut report = CollectReport { license, flagged, ..CollectReport::default() };
+    if excluded {
+        return Ok(report);
+    }
+    let submodules = submodule_paths(repo_path);
+    let mut excludes = OverrideBuilder::new(repo_path);
+    for glob in DEFAULT_EXCLUDES.iter().copied().chain(opts.exclude_glob.iter().map(String::as_str)) {
+        // An override starting with `!` excludes what it matches.
+        excludes.add(&format!("!{}", glob)).with_context(|| format!("invalid --exclude-glob `{}`", glob))?;
+    }
+    let extensions: Vec<String> = opts.extensions.iter().map(|e| e.trim().trim_start_matches('.').to_ascii_lowercase()).collect();
+    let wanted = |path: &Path| opts.all_files || path.extension().is_some_and(|e| extensions.contains(&e.to_string_lossy().to_ascii_lowercase()));
+    let files: Vec<PathBuf> = WalkBuilder::new(repo_path)
+        .standard_filters(true)
+        .overrides(excludes.build()?)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|d| d.file_type().map(|t| t.is_file()).unwrap_or(false))
+        .map(ignore::DirEntry::into_path)
+        .filter(|p| {
+            !p.starts_with(repo_path.join(".git"))
+                && !p.starts_with(repo_path.join("target"))
+                && !p.starts_with(repo_path.join(".idea"))
+                && !p.starts_with(repo_path.join(".vscode"))
+                && !p.starts_with(repo_path.join(CODEQL_DB_DIR))
+        })
+        .collect();
+    // Only the paths are held; the manifests and test modules are found before anything is read.
+    let layout = CrateLayout::scan(&files);
+    for file in files.iter().filter(|f| wanted(f)) {
+        let path = slash_path(file.strip_prefix(repo_path).unwrap());
+        let (content, lossy_utf8) = match read_text(file, opts) {
+            Ok(Some(read)) => read,
+            Ok(None) => continue,
+            Err((reason, bytes)) => {
+                report.skipped.push(SkippedFile { path, reason, bytes });
+                continue;
+            }
+        };
+        let (content, original_newlines) = match opts.normalize_newlines {
+            true => normalize_newlines(content),
+            false => (content, None),
+        };
+        let (_, avg_line_length, max_line_length) = line_stats(&content);
+        let long = match (opts.max_line_length, opts.max_avg_line_length) {
+            (Some(max), _) if max_line_length > max => Some(SkipReason::LineTooLong),
+            (_, Some(max)) if avg_line_length > max => Some(SkipReason::AvgLineTooLong),
+            _ => None,
+        };
+        if let Some(reason) = long {
+            report.skipped.push(SkippedFile { path, reason, bytes: content.len() as u64 });
+            continue;
+        }
+        let kind = layout.kind(file, &content);
+        if !opts.code_kinds.is_empty() && !opts.code_kinds.contains(&kind) {
+            continue;
+        }
+        let (package, target) = layout.target(file, kind).unzip();
+        let vendored = is_vendored(Path::new(&path));
+        let generated = generated_by(Path::new(&path), &content, &opts.generated_marker);
+        report.flagged.vendored += usize::from(vendored);
+        if let Some(by) = generated {
+            report.flagged.generated += 1;
+            *report.flagged.generated_by.entry(by.to_string()).or_default() += 1;
+        }
+        let flag = match (generated, vendored) {
+            (Some(_), _) if opts.skip_generated => Some(SkipReason::Generated),
+            (_, true) if opts.skip_vendored => Some(SkipReason::Vendored),
+            _ => None,
+        };
+        if let Some(reason) = flag {
+            report.flagged.skipped += 1;
+            report.skipped.push(SkippedFile { path, reason, bytes: content.len() as u64 });
+            continue;
+        }
+        let found = match opts.secrets {
+            SecretsMode::Off => Vec::new(),
+            _ => scan_secrets(&content, &opts.disable_secret_rules),
+        };
+        let finding = |m: &SecretMatch| SecretFinding { name: source.name.clone(), dir: dir.clone(), path: path.clone(), rule: m.rule, line: m.line };
+        report.secrets.extend(found.iter().map(finding));
+        let content = match opts.secrets {
+            SecretsMode::SkipFile if !found.is_empty() => {
+                report.skipped.push(SkippedFile { path, reason: SkipReason::Secrets, bytes: content.len() as u64 });
+                continue;
+            }
+            SecretsMode::Redact if !found.is_empty() => redact_secrets(&content, &found),
+            _ => content,
+        };
+        // Tags of an item are those of the secrets starting on its lines.
+        let tags = |lines: Option<(usize, usize)>| -> Vec<SecretRule> {
+            let mut rules: Vec<SecretRule> = found.iter().filter(|m| lines.is_none_or(|(start, end)| (start..=end).contains(&m.line))).map(|m| m.rule).collect();
+            rules.sort();
+            rules.dedup();
+            rules
+        };
+        let last = provenance.as_ref().and_then(|p| p.last_commit(&path));
+        let entry = |content: String, item: Option<CodeItem>, parse_failed: bool| {
+            let (lines, avg_line_length, max_line_length) = line_stats(&content);
+            let item_lines = item.as_ref().map(|i| (i.start_line, i.end_line));
+            CodeEntry {
+                schema_version: CODE_SCHEMA_VERSION,
+                name: source.name.clone(),
+                dir: dir.clone(),
+                name_unresolved: source.name_unresolved,
+                repo: source.repo.clone(),
+                commit: commit.clone(),
+                last_commit: last.map(|l| l.commit.clone()),
+                last_commit_date: last.map(|l| l.date.clone()),
+                modified: provenance.as_ref().map(|p| p.modified(&path)),
+                path: path.clone(),
+                language: language(file).map(String::from),
+                kind,
+                package: package.clone(),
+                target: target.clone(),
+                item,
+                parse_failed,
+                content_hash: content_hash(&content),
+                lines,
+                bytes: content.len(),
+                avg_line_length,
+                max_line_length,
+                tokens: tokenizer.count(&content),
+                content,
+                duplicate_count: 0,
+                duplicate_repos: Vec::new(),
+                cluster_id: None,
+                near_duplicate_count: 0,
+                from_submodule: submodules.iter().any(|s| file.starts_with(s)),
+                lossy_utf8,
+                original_newlines: original_newlines.map(String::from),
+                license_spdx: Some(report.license.license_spdx.clone()),
+                secrets_found: match opts.secrets {
+                    SecretsMode::Tag => tags(item_lines),
+                    _ => Vec::new(),
+                },
+                generated: generated.is_some(),
+                generated_by: generated.map(String::from),
+                vendored,
+            }
+        };
+        if opts.granularity == Granularity::File || file.extension().is_none_or(|e| e != "rs") {
+            emit(entry(content, None, false))?;
+            continue;
+        }
+        match extract(&content, &layout.module_path(file), opts.granularity) {
+            Ok(items) => {
+                for Extracted { text, item } in items {
+                    emit(entry(text, Some(item), false))?;
+                }
+            }
+            Err(e) => {
+                tracing::debug!(file = %file.display(), "not parsed ({}); collected whole", e);
+                emit(entry(content, None, true))?;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// `content` with LF line endings and no byte order mark, and the endings it had when they
+/// weren't LF: `crlf` when any line ended with CRLF, else `cr`.
+fn normalize_newlines(content: String) -> (String, Option<&'static str>) {
+    let content = match content.strip_prefix('\u{feff}') {
+        Some(rest) => rest.to_string(),
+        None => content,
+    };
+    if !content.contains('\r') {
+        return (content, None);
+    }
+    let original = if content.contains("\r\n") { "crlf" } else { "cr" };
+    (content.replace("\r\n", "\n").replace('\r', "\n"), Some(original))
+}
+
+/// A path relative to the checkout, with `/` separators whatever the platform.
+fn slash_path(path: &Path) -> String {
+    path.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+/// The lines of `content`, and their average and longest length in characters.
+fn line_stats(content: &str) -> (usize, f64, usize) {
+    let (mut lines, mut total, mut max) = (0, 0, 0);
+    for line in content.lines() {
+        let len = line.chars().count();
+        (lines, total, max) = (lines + 1, total + len, max.max(len));
+    }
+    (lines, if lines == 0 { 0.0 } else { total as f64 / lines as f64 }, max)
+}
+
+/// The text of a file and whether it was converted lossily, or why it is skipped and its size.
+/// `None` for a file that couldn't be read at all.
+fn read_text(path: &Path, opts: &CollectOptions) -> Result<Option<(String, bool)>, (SkipReason, u64)> {
+    let Ok(bytes) = fs::metadata(path).map(|m| m.len()) else { return Ok(None) };
+    if opts.max_file_bytes > 0 && bytes > opts.max_file_bytes {
+        return Err((SkipReason::TooLarge, bytes));
+    }
+    let mut head = Vec::with_capacity(BINARY_SNIFF_BYTES);
+    let Ok(mut file) = File::open(path) else { return Ok(None) };
+    if (&mut file).take(BINARY_SNIFF_BYTES as u64).read_to_end(&mut head).is_err() {
+        return Ok(None);
+    }
+    if head.contains(&0) {
+        return Err((SkipReason::Binary, bytes));
+    }
+    if file.read_to_end(&mut head).is_err() {
+        return Ok(None);
+    }
+    match String::from_utf8(head) {
+        Ok(text) => Ok(Some((text, false))),
+        Err(e) if opts.lossy_utf8 => Ok(Some((String::from_utf8_lossy(e.as_bytes()).into_owned(), true))),
+        Err(_) => Err((SkipReason::NonUtf8, bytes)),
+    }
+}
+
+/// The language of a file, by its name.
+fn language(path: &Path) -> Option<&'static str> {
+    if path.file_name().is_some_and(|n| n == "Cargo.lock") {
+        return Some("TOML");
+    }
+    Some(match path.extension()?.to_string_lossy().to_ascii_lowercase().as_str() {
+        "rs" => "Rust",
+        "toml" => "TOML",
+        "md" => "Markdown",
+        "c" | "h" => "C",
+        "cc" | "cpp" | "cxx" | "hpp" => "C++",
+        "py" => "Python",
+        "js" | "mjs" => "JavaScript",
+        "ts" => "TypeScript",
+        "go" => "Go",
+        "java" => "Java",
+        "sh" => "Shell",
+        "json" => "JSON",
+        "yml" | "yaml" => "YAML",
+        "html" => "HTML",
+        "css" => "CSS",
+        "proto" => "Protocol Buffers",
+        "wgsl" => "WGSL",
+        _ => return None,
+    })
+}
+
+/// The submodule directories `.gitmodules` lists, read as text so that it works without `.git`.
+/// Nested submodules are inside their parent's directory, so the top-level file is enough.
+fn submodule_paths(repo_path: &Path) -> Vec<PathBuf> {
+    let text = fs::read_to_string(repo_path.join(".gitmodules")).unwrap_or_default();
+    text.lines()
+        .filter_map(|line| line.trim().strip_prefix("path")?.trim_start().strip_prefix('='))
+        .map(|path| repo_path.join(path.trim()))
+        .collect()
+}
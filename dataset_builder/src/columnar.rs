@@ -0,0 +1,405 @@
+//! Parquet output for `collect` and `outputs` (`--format parquet`), and `convert` between JSONL
+//! and Parquet. The columns are the fields of `CodeEntry` and `OutputEntry`; tool results and
+//! other deep structures are JSON strings.
+
+use std::fs::{self, File};
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Context;
+use arrow::datatypes::{DataType, Field, Fields, Metadata, Schema};
+use arrow::json::reader::{Decoder, ReaderBuilder};
+use arrow::json::LineDelimitedWriter;
+use clap::{Args, ValueEnum};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::analyze::OutputEntry;
+use crate::collect::CodeEntry;
+use crate::jsonl::{open_jsonl, Codec, JsonlWriter};
+use crate::schema::{from_line, CODE_SCHEMA_VERSION, OUTPUT_SCHEMA_VERSION};
+
+pub const DEFAULT_ROW_GROUP_ROWS: usize = 8192;
+
+/// Field metadata key of the columns that hold a JSON document as a string.
+pub const ENCODING_METADATA_KEY: &str = "dataset_builder.encoding";
+
+/// Rows decoded into Arrow at a time; a row group is written once it has `--row-group-size` rows.
+const BATCH_ROWS: usize = 1024;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum FileFormat {
+    #[default]
+    Jsonl,
+    /// ZSTD-compressed, dictionary-encoded Parquet, with the file's lines as rows
+    Parquet,
+}
+
+/// What the lines of a file are, which decides its Parquet schema.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum DatasetKind {
+    /// `code.jsonl`, as written by `collect`
+    Code,
+    /// `outputs.jsonl`, as written by `outputs`
+    Outputs,
+}
+
+impl DatasetKind {
+    /// The kind of a file from one of its lines: outputs entries have `run_id` (and `tools`
+    /// since version 4), code entries `content`.
+    pub fn detect(line: &Value) -> Option<DatasetKind> {
+        match line {
+            Value::Object(m) if m.contains_key("tools") || m.contains_key("run_id") => Some(DatasetKind::Outputs),
+            Value::Object(m) if m.contains_key("content") => Some(DatasetKind::Code),
+            _ => None,
+        }
+    }
+
+    pub fn schema(self) -> Schema {
+        match self {
+            DatasetKind::Code => code_schema(),
+            DatasetKind::Outputs => outputs_schema(),
+        }
+    }
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ConvertOptions {
+    /// The lines' kind, `code` or `outputs`, when converting to Parquet (default: from the
+    /// first line)
+    #[arg(long, value_enum)]
+    pub kind: Option<DatasetKind>,
+    /// Rows per Parquet row group
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_ROW_GROUP_ROWS)]
+    pub row_group_size: usize,
+    /// Rewrite lines of an older `schema_version` in the newest layout; with a JSONL output
+    /// (not `.parquet`), the file stays JSONL
+    #[arg(long)]
+    pub upgrade: bool,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        ConvertOptions { kind: None, row_group_size: DEFAULT_ROW_GROUP_ROWS, upgrade: false }
+    }
+}
+
+fn text(name: &str, nullable: bool) -> Field {
+    Field::new(name, DataType::Utf8, nullable)
+}
+
+fn flag(name: &str) -> Field {
+    Field::new(name, DataType::Boolean, true)
+}
+
+fn count(name: &str) -> Field {
+    Field::new(name, DataType::UInt64, true)
+}
+
+fn texts(name: &str) -> Field {
+    Field::new(name, DataType::List(Arc::new(Field::new_list_field(DataType::Utf8, true))), true)
+}
+
+/// A column with the JSON text of the field's value.
+fn json(name: &str, nullable: bool) -> Field {
+    text(name, nullable).with_metadata(Metadata::new().with(ENCODING_METADATA_KEY, "json"))
+}
+
+/// The columns of `CodeEntry`, in its field order.
+pub fn code_schema() -> Schema {
+    let item = Fields::from(vec![
+        text("kind", false),
+        text("path", false),
+        text("signature", true),
+        text("body", true),
+        text("doc", true),
+        texts("attributes"),
+        count("start_line"),
+        count("end_line"),
+        flag("is_unsafe"),
+        flag("is_async"),
+        flag("is_test"),
+    ]);
+    Schema::new(vec![
+        Field::new("schema_version", DataType::UInt32, true),
+        text("name", false),
+        text("dir", true),
+        flag("name_unresolved"),
+        text("repo", true),
+        text("commit", true),
+        text("last_commit", true),
+        text("last_commit_date", true),
+        flag("modified"),
+        text("path", false),
+        text("language", true),
+        text("kind", true),
+        text("package", true),
+        text("target", true),
+        Field::new("item", DataType::Struct(item), true),
+        flag("parse_failed"),
+        text("content", false),
+        count("lines"),
+        count("bytes"),
+        Field::new("avg_line_length", DataType::Float64, true),
+        count("max_line_length"),
+        count("tokens"),
+        text("content_hash", true),
+        count("duplicate_count"),
+        texts("duplicate_repos"),
+        count("cluster_id"),
+        count("near_duplicate_count"),
+        flag("from_submodule"),
+        flag("lossy_utf8"),
+        text("original_newlines", true),
+        text("license_spdx", true),
+        texts("secrets_found"),
+        flag("generated"),
+        text("generated_by", true),
+        flag("vendored"),
+    ])
+}
+
+/// The columns of `OutputEntry`, in its field order. `findings` is a list of structs; `tools`,
+/// `dep_graph`, `fetch` and `time_ms` are JSON.
+pub fn outputs_schema() -> Schema {
+    let finding = Fields::from(vec![text("tool", false), text("rule_id", false), text("severity", false), text("file", true), count("start_line"), count("end_line"), text("message", true)]);
+    Schema::new(vec![
+        Field::new("schema_version", DataType::UInt32, false),
+        text("run_id", false),
+        text("name", false),
+        text("dir", true),
+        flag("name_unresolved"),
+        text("repo", true),
+        text("commit", true),
+        text("project_manifest", true),
+        texts("other_manifests"),
+        flag("not_a_cargo_project"),
+        text("package", true),
+        text("manifest_path", true),
+        text("workspace_root", true),
+        json("tools", false),
+        Field::new("findings", DataType::List(Arc::new(Field::new_list_field(DataType::Struct(finding), true))), true),
+        json("dep_graph", true),
+        text("toolchain", false),
+        text("ast_toolchain", true),
+        json("fetch", true),
+        flag("fetch_failed"),
+        json("time_ms", true),
+        count("total_duration_ms"),
+        texts("errors"),
+    ])
+}
+
+fn json_columns(schema: &Schema) -> Vec<String> {
+    schema.fields().iter().filter(|f| f.metadata().get(ENCODING_METADATA_KEY).is_some_and(|e| e == "json")).map(|f| f.name().clone()).collect()
+}
+
+/// Writes entries as Parquet rows, in row groups of `row_group_rows`. Unlike `JsonlWriter`, the
+/// file is only readable once `finish` has written its footer.
+pub struct ParquetWriter {
+    writer: ArrowWriter<File>,
+    decoder: Decoder,
+    json_columns: Vec<String>,
+    pending: Vec<Value>,
+    pub written: usize,
+}
+
+impl ParquetWriter {
+    pub fn new(file: File, kind: DatasetKind, row_group_rows: usize) -> anyhow::Result<Self> {
+        let schema = Arc::new(kind.schema());
+        let props = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .set_dictionary_enabled(true)
+            .set_max_row_group_row_count(Some(row_group_rows.max(1)))
+            .build();
+        // Strict, so a field missing from the schema is an error rather than a dropped column.
+        let decoder = ReaderBuilder::new(schema.clone()).with_batch_size(BATCH_ROWS).with_strict_mode(true).build_decoder()?;
+        Ok(ParquetWriter { json_columns: json_columns(&schema), writer: ArrowWriter::try_new(file, schema, Some(props))?, decoder, pending: Vec::new(), written: 0 })
+    }
+
+    pub fn write_entry<T: Serialize>(&mut self, entry: &T) -> anyhow::Result<()> {
+        self.write_value(serde_json::to_value(entry)?)
+    }
+
+    fn write_value(&mut self, mut row: Value) -> anyhow::Result<()> {
+        if let Value::Object(m) = &mut row {
+            for c in &self.json_columns {
+                if let Some(v) = m.get_mut(c).filter(|v| !v.is_null()) {
+                    *v = Value::String(v.to_string());
+                }
+            }
+        }
+        self.pending.push(row);
+        if self.pending.len() >= BATCH_ROWS {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        let rows = || format!("entries {} to {} don't fit the Parquet schema", self.written + 1, self.written + self.pending.len());
+        self.decoder.serialize(&self.pending).with_context(rows)?;
+        while let Some(batch) = self.decoder.flush().with_context(rows)? {
+            self.writer.write(&batch)?;
+        }
+        self.written += self.pending.len();
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Writes the last row group and the footer; returns the number of rows.
+    pub fn finish(mut self) -> anyhow::Result<usize> {
+        self.flush()?;
+        self.writer.close()?;
+        Ok(self.written)
+    }
+}
+
+/// `file` with a `.parquet` extension, where `--format parquet` leaves it.
+pub fn parquet_path(file: &str) -> PathBuf {
+    Path::new(file).with_extension("parquet")
+}
+
+/// The JSONL file `collect` and `outputs` write while they run with `--format parquet`, so
+/// `--resume` and the dedup passes work as with JSONL: `file` with a `.jsonl` extension.
+pub fn working_jsonl(file: &str) -> String {
+    Path::new(file).with_extension("jsonl").display().to_string()
+}
+
+/// Converts the finished JSONL `file` to `parquet_path(file)` and removes it, reporting the rows.
+pub(crate) fn finish_as_parquet(file: &str, kind: DatasetKind, row_group_rows: Option<usize>) -> anyhow::Result<()> {
+    let out = parquet_path(file).display().to_string();
+    let rows = jsonl_to_parquet(file, &out, Some(kind), row_group_rows.unwrap_or(DEFAULT_ROW_GROUP_ROWS))?;
+    fs::remove_file(file)?;
+    tracing::info!("Parquet: wrote {} rows to {}", rows, out);
+    Ok(())
+}
+
+/// Converts a JSONL file to Parquet, checking that the Parquet file has as many rows as `input`
+/// has lines. Returns the row count.
+pub fn jsonl_to_parquet(input: &str, output: &str, kind: Option<DatasetKind>, row_group_rows: usize) -> anyhow::Result<usize> {
+    to_parquet(input, output, kind, row_group_rows, false)
+}
+
+fn to_parquet(input: &str, output: &str, kind: Option<DatasetKind>, row_group_rows: usize, upgrade: bool) -> anyhow::Result<usize> {
+    let lines = open_jsonl(input)?.lines();
+    let mut writer: Option<(ParquetWriter, DatasetKind)> = None;
+    let mut read = 0;
+    for (i, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row: Value = serde_json::from_str(&line).with_context(|| format!("{}:{}: not JSON; `validate --repair` drops bad lines", input, i + 1))?;
+        let (w, kind) = match &mut writer {
+            Some((w, kind)) => (w, *kind),
+            None => {
+                let kind = kind.or_else(|| DatasetKind::detect(&row)).with_context(|| format!("{}: neither code nor outputs entries; pass --kind", input))?;
+                let w = ParquetWriter::new(File::create(output).with_context(|| format!("failed to write {}", output))?, kind, row_group_rows)?;
+                (&mut writer.insert((w, kind)).0, kind)
+            }
+        };
+        let row = match upgrade {
+            true => upgraded(kind, row, &line).with_context(|| format!("{}:{}: can't be upgraded", input, i + 1))?,
+            false => row,
+        };
+        w.write_value(row).with_context(|| format!("failed to convert {}", input))?;
+        read += 1;
+    }
+    let Some((writer, _)) = writer else {
+        // An empty file has no line to tell its kind by.
+        ParquetWriter::new(File::create(output)?, kind.unwrap_or(DatasetKind::Code), row_group_rows)?.finish()?;
+        return Ok(0);
+    };
+    writer.finish().with_context(|| format!("failed to convert {}", input))?;
+    let rows = parquet_rows(output)?;
+    anyhow::ensure!(rows == read, "{} has {} rows but {} has {} lines", output, rows, input, read);
+    Ok(rows)
+}
+
+/// The row count in the footer of a Parquet file.
+pub fn parquet_rows(path: &str) -> anyhow::Result<usize> {
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path).with_context(|| format!("failed to read {}", path))?)?;
+    Ok(builder.metadata().file_metadata().num_rows() as usize)
+}
+
+/// Converts a Parquet file written by `--format parquet` or `convert` back to JSONL, with the
+/// JSON columns as nested values again and null columns left out. Returns the row count, which
+/// is checked against the footer's.
+pub fn parquet_to_jsonl(input: &str, output: &str) -> anyhow::Result<usize> {
+    let expected = parquet_rows(input)?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(input)?)?;
+    let json = json_columns(builder.schema());
+    let reader = builder.build()?;
+    let mut w = JsonlWriter::compressed(File::create(output).with_context(|| format!("failed to write {}", output))?, Codec::of(output), None)?;
+    for batch in reader {
+        let mut text = LineDelimitedWriter::new(Vec::new());
+        text.write(&batch?)?;
+        text.finish()?;
+        for line in text.into_inner().split(|b| *b == b'\n').filter(|l| !l.is_empty()) {
+            let mut row: Value = serde_json::from_slice(line)?;
+            if let Value::Object(m) = &mut row {
+                for c in &json {
+                    if let Some(Value::String(s)) = m.get(c) {
+                        let v = serde_json::from_str(s).with_context(|| format!("{}: `{}` isn't JSON", input, c))?;
+                        m.insert(c.clone(), v);
+                    }
+                }
+            }
+            w.write_entry(&row)?;
+        }
+    }
+    let written = w.written;
+    w.finish()?;
+    anyhow::ensure!(written == expected, "{} has {} lines but {} has {} rows", output, written, input, expected);
+    Ok(written)
+}
+
+/// `row`, the JSON of `line`, in the newest layout of `kind`: as it is when it already has the
+/// current `schema_version`.
+fn upgraded(kind: DatasetKind, row: Value, line: &str) -> serde_json::Result<Value> {
+    let version = row.get("schema_version").and_then(Value::as_u64);
+    match kind {
+        DatasetKind::Code if version != Some(CODE_SCHEMA_VERSION as u64) => serde_json::to_value(from_line::<CodeEntry>(line)?),
+        DatasetKind::Outputs if version != Some(OUTPUT_SCHEMA_VERSION as u64) => serde_json::to_value(from_line::<OutputEntry>(line)?),
+        _ => Ok(row),
+    }
+}
+
+/// Rewrites a JSONL file with every line in the newest layout (`convert --upgrade` to JSONL).
+/// Returns the line count.
+pub fn upgrade_jsonl(input: &str, output: &str, kind: Option<DatasetKind>) -> anyhow::Result<usize> {
+    let mut w = JsonlWriter::compressed(File::create(output).with_context(|| format!("failed to write {}", output))?, Codec::of(output), None)?;
+    let mut kind = kind;
+    for (i, line) in open_jsonl(input)?.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row: Value = serde_json::from_str(&line).with_context(|| format!("{}:{}: not JSON; `validate --repair` drops bad lines", input, i + 1))?;
+        let kind = *match &mut kind {
+            Some(k) => k,
+            None => kind.insert(DatasetKind::detect(&row).with_context(|| format!("{}: neither code nor outputs entries; pass --kind", input))?),
+        };
+        w.write_entry(&upgraded(kind, row, &line).with_context(|| format!("{}:{}: can't be upgraded", input, i + 1))?)?;
+    }
+    let written = w.written;
+    w.finish()?;
+    Ok(written)
+}
+
+/// `convert`: Parquet to JSONL when `input` ends in `.parquet`, else JSONL to Parquet; or JSONL
+/// to JSONL in the newest layout with `--upgrade` and an `output` not ending in `.parquet`.
+pub fn convert(input: &str, output: &str, opts: &ConvertOptions) -> anyhow::Result<usize> {
+    let parquet = |path: &str| Path::new(path).extension().is_some_and(|e| e == "parquet");
+    match parquet(input) {
+        true => parquet_to_jsonl(input, output),
+        false if opts.upgrade && !parquet(output) => upgrade_jsonl(input, output, opts.kind),
+        false => to_parquet(input, output, opts.kind, opts.row_group_size, opts.upgrade),
+    }
+}
@@ -0,0 +1,384 @@
+//! The `complexity` analyzer's metrics, computed in-process from each `.rs` file's syntax tree:
+//! cyclomatic and cognitive complexity per function, Halstead metrics of the file's tokens, and
+//! function counts. The per-file metrics go to `metrics.jsonl`, the sums to the entry.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use proc_macro2::{Delimiter, Spacing, TokenStream, TokenTree};
+use serde::{Deserialize, Serialize};
+use syn::visit::{self, Visit};
+
+/// Written next to the outputs file, unless `--metrics-file` says otherwise: one line per `.rs`
+/// file of each repository `complexity` ran on.
+pub const METRICS_FILE: &str = "metrics.jsonl";
+
+/// Where the per-file metrics of `outputs_file` are written without `--metrics-file`.
+pub fn metrics_beside(outputs_file: &str) -> PathBuf {
+    Path::new(outputs_file).parent().unwrap_or(Path::new("")).join(METRICS_FILE)
+}
+
+/// Halstead's counts of a file's operators (punctuation, keywords, brackets) and operands
+/// (identifiers, literals, lifetimes), and the measures derived from them. Doc comments and
+/// comments aren't tokens.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Halstead {
+    pub distinct_operators: usize,
+    pub distinct_operands: usize,
+    pub operators: usize,
+    pub operands: usize,
+    /// `length * log2(vocabulary)`
+    pub volume: f64,
+    /// `distinct_operators / 2 * operands / distinct_operands`
+    pub difficulty: f64,
+    /// `difficulty * volume`
+    pub effort: f64,
+}
+
+impl Halstead {
+    fn new(operators: &HashMap<String, usize>, operands: &HashMap<String, usize>) -> Self {
+        let (n1, n2) = (operators.len(), operands.len());
+        let (total1, total2) = (operators.values().sum::<usize>(), operands.values().sum::<usize>());
+        let vocabulary = (n1 + n2) as f64;
+        let volume = if vocabulary > 0.0 { (total1 + total2) as f64 * vocabulary.log2() } else { 0.0 };
+        let difficulty = if n2 > 0 { n1 as f64 / 2.0 * total2 as f64 / n2 as f64 } else { 0.0 };
+        Halstead {
+            distinct_operators: n1,
+            distinct_operands: n2,
+            operators: total1,
+            operands: total2,
+            volume: round2(volume),
+            difficulty: round2(difficulty),
+            effort: round2(difficulty * volume),
+        }
+    }
+}
+
+fn round2(x: f64) -> f64 {
+    (x * 100.0).round() / 100.0
+}
+
+/// The metrics of one `.rs` file. Complexities are summed over its functions, with the largest
+/// of any one function beside them.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct FileMetrics {
+    /// As in `CodeEntry::path`
+    pub path: String,
+    /// Free functions, methods, and trait methods with a default body
+    pub functions: usize,
+    pub closures: usize,
+    pub cyclomatic: u32,
+    pub cyclomatic_max: u32,
+    pub cognitive: u32,
+    pub cognitive_max: u32,
+    pub halstead: Halstead,
+}
+
+/// One line of `metrics.jsonl`; `name` and `path` join with `CodeEntry`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MetricsEntry {
+    /// As in `OutputEntry::name`
+    pub name: String,
+    #[serde(default)]
+    pub dir: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    #[serde(flatten)]
+    pub metrics: FileMetrics,
+}
+
+/// The `complexity` report of a repository: its files' metrics summed.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ComplexityReport {
+    /// `.rs` files measured
+    pub files: usize,
+    /// `.rs` files that didn't parse, and aren't counted anywhere else
+    pub parse_failed: usize,
+    pub functions: usize,
+    pub closures: usize,
+    pub cyclomatic: u32,
+    pub cyclomatic_max: u32,
+    /// Per function; 0 without functions
+    pub cyclomatic_mean: f64,
+    pub cognitive: u32,
+    pub cognitive_max: u32,
+    pub cognitive_mean: f64,
+    pub halstead_volume: f64,
+    pub halstead_effort: f64,
+}
+
+impl ComplexityReport {
+    pub fn add(&mut self, file: &FileMetrics) {
+        self.files += 1;
+        self.functions += file.functions;
+        self.closures += file.closures;
+        self.cyclomatic += file.cyclomatic;
+        self.cyclomatic_max = self.cyclomatic_max.max(file.cyclomatic_max);
+        self.cognitive += file.cognitive;
+        self.cognitive_max = self.cognitive_max.max(file.cognitive_max);
+        self.halstead_volume = round2(self.halstead_volume + file.halstead.volume);
+        self.halstead_effort = round2(self.halstead_effort + file.halstead.effort);
+        let per_fn = |total: u32| if self.functions == 0 { 0.0 } else { round2(total as f64 / self.functions as f64) };
+        (self.cyclomatic_mean, self.cognitive_mean) = (per_fn(self.cyclomatic), per_fn(self.cognitive));
+    }
+}
+
+/// The metrics of `content`, the file at `path`; `Err` with syn's message when it doesn't parse.
+pub fn file_metrics(path: &str, content: &str) -> Result<FileMetrics, String> {
+    let parsed = syn::parse_file(content).map(|file| {
+        let mut counter = Counter::default();
+        counter.visit_file(&file);
+        counter
+    });
+    // As in `items::extract`.
+    proc_macro2::extra::invalidate_current_thread_spans();
+    let counter = parsed.map_err(|e| e.to_string())?;
+    let mut metrics = FileMetrics { path: path.to_string(), functions: counter.functions.len(), closures: counter.closures, ..FileMetrics::default() };
+    for f in &counter.functions {
+        metrics.cyclomatic += f.cyclomatic;
+        metrics.cyclomatic_max = metrics.cyclomatic_max.max(f.cyclomatic);
+        metrics.cognitive += f.cognitive;
+        metrics.cognitive_max = metrics.cognitive_max.max(f.cognitive);
+    }
+    // A shebang line isn't a token; such files get no Halstead metrics.
+    if let Ok(tokens) = content.parse::<TokenStream>() {
+        let (mut operators, mut operands) = (HashMap::new(), HashMap::new());
+        count_tokens(tokens, &mut operators, &mut operands);
+        metrics.halstead = Halstead::new(&operators, &operands);
+    }
+    proc_macro2::extra::invalidate_current_thread_spans();
+    Ok(metrics)
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "fn", "for", "if", "impl", "in", "let", "loop",
+    "match", "mod", "move", "mut", "pub", "ref", "return", "static", "struct", "super", "trait", "type", "unsafe", "use", "where", "while", "yield",
+];
+
+fn count_tokens(tokens: TokenStream, operators: &mut HashMap<String, usize>, operands: &mut HashMap<String, usize>) {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    let mut punct = String::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i] {
+            // `///` and `//!` lines are `#[doc = "..."]` and `#![doc = "..."]` attributes.
+            TokenTree::Punct(p) if p.as_char() == '#' && punct.is_empty() => {
+                let bang = matches!(tokens.get(i + 1), Some(TokenTree::Punct(p)) if p.as_char() == '!');
+                let attr = i + 1 + usize::from(bang);
+                if matches!(tokens.get(attr), Some(TokenTree::Group(g)) if is_doc(g)) {
+                    i = attr + 1;
+                    continue;
+                }
+                *operators.entry("#".to_string()).or_default() += 1;
+            }
+            TokenTree::Punct(p) if p.as_char() == '\'' => {
+                if let Some(TokenTree::Ident(lifetime)) = tokens.get(i + 1) {
+                    *operands.entry(format!("'{}", lifetime)).or_default() += 1;
+                    i += 2;
+                    continue;
+                }
+            }
+            TokenTree::Punct(p) => {
+                punct.push(p.as_char());
+                if p.spacing() == Spacing::Alone {
+                    *operators.entry(std::mem::take(&mut punct)).or_default() += 1;
+                }
+            }
+            TokenTree::Ident(ident) => {
+                let ident = ident.to_string();
+                let counts = if KEYWORDS.contains(&ident.as_str()) { &mut *operators } else { &mut *operands };
+                *counts.entry(ident).or_default() += 1;
+            }
+            TokenTree::Literal(lit) => *operands.entry(lit.to_string()).or_default() += 1,
+            TokenTree::Group(g) => {
+                let brackets = match g.delimiter() {
+                    Delimiter::Parenthesis => Some("()"),
+                    Delimiter::Bracket => Some("[]"),
+                    Delimiter::Brace => Some("{}"),
+                    Delimiter::None => None,
+                };
+                if let Some(b) = brackets {
+                    *operators.entry(b.to_string()).or_default() += 1;
+                }
+                count_tokens(g.stream(), operators, operands);
+            }
+        }
+        i += 1;
+    }
+}
+
+fn is_doc(group: &proc_macro2::Group) -> bool {
+    group.delimiter() == Delimiter::Bracket && matches!(group.stream().into_iter().next(), Some(TokenTree::Ident(i)) if i == "doc")
+}
+
+#[derive(Default)]
+struct Function {
+    cyclomatic: u32,
+    cognitive: u32,
+    /// How deep in branches and loops the visitor is, for cognitive complexity
+    nesting: u32,
+}
+
+/// Counts the complexity of every function body it visits. Decisions outside any function, as
+/// in a `const` initializer, aren't counted.
+#[derive(Default)]
+struct Counter {
+    /// The functions being visited, innermost last; a nested `fn` item is a function of its own
+    open: Vec<Function>,
+    functions: Vec<Function>,
+    closures: usize,
+    /// The `&&` or `||` of the expression being visited, so a run of the same operator counts once
+    logical: Option<bool>,
+}
+
+impl Counter {
+    fn function(&mut self, f: impl FnOnce(&mut Self)) {
+        self.open.push(Function { cyclomatic: 1, ..Function::default() });
+        f(self);
+        let done = self.open.pop().unwrap();
+        self.functions.push(done);
+    }
+
+    fn decision(&mut self, n: u32) {
+        if let Some(f) = self.open.last_mut() {
+            f.cyclomatic += n;
+        }
+    }
+
+    /// A branch or loop: one, plus one per level it is nested in.
+    fn structure(&mut self) {
+        if let Some(f) = self.open.last_mut() {
+            f.cognitive += 1 + f.nesting;
+        }
+    }
+
+    /// Breaks in the flow that don't nest: `else`, labelled jumps, operator runs.
+    fn flat(&mut self) {
+        if let Some(f) = self.open.last_mut() {
+            f.cognitive += 1;
+        }
+    }
+
+    fn nested(&mut self, f: impl FnOnce(&mut Self)) {
+        if let Some(open) = self.open.last_mut() {
+            open.nesting += 1;
+        }
+        f(self);
+        if let Some(open) = self.open.last_mut() {
+            open.nesting -= 1;
+        }
+    }
+
+    /// An `if`, and its `else if`s, which count flat, and `else`.
+    fn if_chain(&mut self, i: &syn::ExprIf, else_if: bool) {
+        self.decision(1);
+        if else_if {
+            self.flat();
+        } else {
+            self.structure();
+        }
+        self.visit_expr(&i.cond);
+        self.nested(|c| c.visit_block(&i.then_branch));
+        match i.else_branch.as_ref().map(|(_, e)| &**e) {
+            Some(syn::Expr::If(inner)) => self.if_chain(inner, true),
+            Some(other) => {
+                self.flat();
+                self.nested(|c| c.visit_expr(other));
+            }
+            None => {}
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for Counter {
+    fn visit_item_fn(&mut self, i: &'ast syn::ItemFn) {
+        self.function(|c| visit::visit_item_fn(c, i));
+    }
+
+    fn visit_impl_item_fn(&mut self, i: &'ast syn::ImplItemFn) {
+        self.function(|c| visit::visit_impl_item_fn(c, i));
+    }
+
+    fn visit_trait_item_fn(&mut self, i: &'ast syn::TraitItemFn) {
+        if i.default.is_some() {
+            self.function(|c| visit::visit_trait_item_fn(c, i));
+        }
+    }
+
+    fn visit_expr(&mut self, e: &'ast syn::Expr) {
+        let op = match e {
+            syn::Expr::Binary(b) => match b.op {
+                syn::BinOp::And(_) => Some(true),
+                syn::BinOp::Or(_) => Some(false),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(op) = op {
+            self.decision(1);
+            if self.logical != Some(op) {
+                self.flat();
+            }
+        }
+        let outer = std::mem::replace(&mut self.logical, op);
+        visit::visit_expr(self, e);
+        self.logical = outer;
+    }
+
+    fn visit_expr_if(&mut self, i: &'ast syn::ExprIf) {
+        self.if_chain(i, false);
+    }
+
+    fn visit_expr_match(&mut self, i: &'ast syn::ExprMatch) {
+        // One path per arm, and a guard adds one.
+        self.decision(i.arms.len().saturating_sub(1) as u32 + i.arms.iter().filter(|a| a.guard.is_some()).count() as u32);
+        self.structure();
+        self.visit_expr(&i.expr);
+        self.nested(|c| i.arms.iter().for_each(|a| c.visit_arm(a)));
+    }
+
+    fn visit_expr_for_loop(&mut self, i: &'ast syn::ExprForLoop) {
+        self.decision(1);
+        self.structure();
+        self.visit_expr(&i.expr);
+        self.nested(|c| c.visit_block(&i.body));
+    }
+
+    fn visit_expr_while(&mut self, i: &'ast syn::ExprWhile) {
+        self.decision(1);
+        self.structure();
+        self.visit_expr(&i.cond);
+        self.nested(|c| c.visit_block(&i.body));
+    }
+
+    fn visit_expr_loop(&mut self, i: &'ast syn::ExprLoop) {
+        self.structure();
+        self.nested(|c| c.visit_block(&i.body));
+    }
+
+    fn visit_expr_try(&mut self, i: &'ast syn::ExprTry) {
+        self.decision(1);
+        visit::visit_expr_try(self, i);
+    }
+
+    fn visit_expr_break(&mut self, i: &'ast syn::ExprBreak) {
+        if i.label.is_some() {
+            self.flat();
+        }
+        visit::visit_expr_break(self, i);
+    }
+
+    fn visit_expr_continue(&mut self, i: &'ast syn::ExprContinue) {
+        if i.label.is_some() {
+            self.flat();
+        }
+        visit::visit_expr_continue(self, i);
+    }
+
+    fn visit_expr_closure(&mut self, i: &'ast syn::ExprClosure) {
+        self.closures += 1;
+        self.nested(|c| visit::visit_expr_closure(c, i));
+    }
+}
@@ -0,0 +1,224 @@
+//! `dataset_builder.toml`: the paths and settings the `full` command runs with.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::clone::OnExisting;
+use crate::pipeline::Pipeline;
+
+/// Read by `full` when it exists and no `--config` is given.
+pub const DEFAULT_CONFIG_FILE: &str = "dataset_builder.toml";
+
+/// The commented file `config init` writes; every value in it is the default.
+pub const CONFIG_TEMPLATE: &str = include_str!("../dataset_builder.example.toml");
+
+/// A config file; absent keys leave the pipeline's defaults (or command-line flags) in place.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub paths: PathsConfig,
+    pub analyze: AnalyzeConfig,
+    pub clone: CloneConfig,
+    pub metadata: MetadataConfig,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathsConfig {
+    pub input_csv: Option<String>,
+    pub filtered_repos: Option<String>,
+    pub datasets_dir: Option<String>,
+    pub outputs_file: Option<String>,
+    pub code_file: Option<String>,
+    pub metadata_file: Option<String>,
+    pub clone_errors: Option<String>,
+    pub stream_errors: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalyzeConfig {
+    pub only: Option<Vec<String>>,
+    pub enable: Option<Vec<String>>,
+    pub skip: Option<Vec<String>>,
+    pub tools_config: Option<String>,
+    pub jobs: Option<usize>,
+    pub cargo_jobs: Option<usize>,
+    pub tool_timeout_secs: Option<u64>,
+    pub repo_timeout_secs: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CloneConfig {
+    pub on_existing: Option<OnExisting>,
+    pub jobs: Option<usize>,
+    pub retries: Option<u32>,
+    pub strip_git: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetadataConfig {
+    pub enabled: Option<bool>,
+}
+
+impl Config {
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("failed to read config {}", path))?;
+        toml::from_str(&text).with_context(|| format!("invalid config {}", path))
+    }
+
+    /// The file `full` reads: `--config`, which must exist, or `dataset_builder.toml` if present.
+    pub fn find(path: Option<&str>) -> anyhow::Result<Option<Self>> {
+        match path {
+            Some(path) => Self::load(path).map(Some),
+            None if Path::new(DEFAULT_CONFIG_FILE).is_file() => Self::load(DEFAULT_CONFIG_FILE).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Sets every configured value on `pipeline`, except the analysis options (and `--errors-file`)
+    /// `explicit` reports as given on the command line (by their flag's id, e.g. `jobs`).
+    pub fn apply(&self, pipeline: &mut Pipeline, explicit: impl Fn(&str) -> bool) {
+        fn set<T: Clone>(field: &mut T, value: &Option<T>) {
+            if let Some(v) = value {
+                *field = v.clone();
+            }
+        }
+        fn set_some<T: Clone>(field: &mut Option<T>, value: &Option<T>) {
+            if value.is_some() {
+                *field = value.clone();
+            }
+        }
+        let paths = &self.paths;
+        set(&mut pipeline.input_csv, &paths.input_csv);
+        set(&mut pipeline.filtered_repos, &paths.filtered_repos);
+        set(&mut pipeline.datasets_dir, &paths.datasets_dir);
+        set(&mut pipeline.outputs_file, &paths.outputs_file);
+        set(&mut pipeline.code_file, &paths.code_file);
+        set(&mut pipeline.metadata_file, &paths.metadata_file);
+        set_some(&mut pipeline.clone_errors, &paths.clone_errors);
+        if !explicit("errors_file") {
+            set_some(&mut pipeline.stream.errors_file, &paths.stream_errors);
+        }
+        set(&mut pipeline.metadata, &self.metadata.enabled);
+        set(&mut pipeline.on_existing, &self.clone.on_existing);
+        set(&mut pipeline.clone_jobs, &self.clone.jobs);
+        set(&mut pipeline.clone_retries, &self.clone.retries);
+        set(&mut pipeline.strip_git, &self.clone.strip_git);
+
+        let (a, opts) = (&self.analyze, &mut pipeline.analyze);
+        let from_config = |id: &str| !explicit(id);
+        if from_config("only") { set(&mut opts.only, &a.only) }
+        if from_config("enable") { set(&mut opts.enable, &a.enable) }
+        if from_config("skip") { set(&mut opts.skip, &a.skip) }
+        if from_config("jobs") { set(&mut opts.jobs, &a.jobs) }
+        if from_config("tools_config") { set_some(&mut opts.tools_config, &a.tools_config) }
+        if from_config("cargo_jobs") { set_some(&mut opts.cargo_jobs, &a.cargo_jobs) }
+        if from_config("tool_timeout") { set_some(&mut opts.tool_timeout, &a.tool_timeout_secs) }
+        if from_config("repo_timeout") { set_some(&mut opts.repo_timeout, &a.repo_timeout_secs) }
+    }
+
+    /// The settings `pipeline` runs with, as a config file would spell them.
+    pub fn effective(pipeline: &Pipeline) -> Self {
+        let opts = &pipeline.analyze;
+        Config {
+            paths: PathsConfig {
+                input_csv: Some(pipeline.input_csv.clone()),
+                filtered_repos: Some(pipeline.filtered_repos.clone()),
+                datasets_dir: Some(pipeline.datasets_dir.clone()),
+                outputs_file: Some(pipeline.outputs_file.clone()),
+                code_file: Some(pipeline.code_file.clone()),
+                metadata_file: Some(pipeline.metadata_file.clone()),
+                clone_errors: Some(pipeline.clone_errors_file()),
+                stream_errors: Some(pipeline.stream_errors_file()),
+            },
+            analyze: AnalyzeConfig {
+                only: Some(opts.only.clone()),
+                enable: Some(opts.enable.clone()),
+                skip: Some(opts.skip.clone()),
+                tools_config: opts.tools_config.clone(),
+                jobs: Some(opts.jobs.max(1)),
+                cargo_jobs: opts.cargo_jobs,
+                tool_timeout_secs: opts.tool_timeout,
+                repo_timeout_secs: opts.repo_timeout,
+            },
+            clone: CloneConfig { on_existing: Some(pipeline.on_existing), jobs: Some(pipeline.clone_jobs), retries: Some(pipeline.clone_retries), strip_git: Some(pipeline.strip_git) },
+            metadata: MetadataConfig { enabled: Some(pipeline.metadata) },
+        }
+    }
+}
+
+/// The `full` flags for settings that only the config file has otherwise.
+#[derive(Args, Clone, Debug, Default)]
+pub struct PipelineFlags {
+    /// Pipeline config file (default: `dataset_builder.toml` when it exists)
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<String>,
+    #[arg(long, value_name = "PATH")]
+    pub input_csv: Option<String>,
+    #[arg(long, value_name = "PATH")]
+    pub filtered_repos: Option<String>,
+    #[arg(long, value_name = "DIR")]
+    pub datasets_dir: Option<String>,
+    #[arg(long, value_name = "PATH")]
+    pub outputs_file: Option<String>,
+    #[arg(long, value_name = "PATH")]
+    pub code_file: Option<String>,
+    /// Also fetch each repository's GitHub metadata, one API request per repository
+    #[arg(long)]
+    pub metadata: bool,
+    #[arg(long, value_name = "PATH")]
+    pub metadata_file: Option<String>,
+    /// Where the clone step records failed repositories (default: `clone_errors.jsonl` next to
+    /// the outputs file)
+    #[arg(long, value_name = "PATH")]
+    pub clone_errors: Option<String>,
+    /// What to do when a repository's checkout already exists (default: skip)
+    #[arg(long, value_enum)]
+    pub on_existing: Option<OnExisting>,
+    /// Number of repositories to clone concurrently (default: 1)
+    #[arg(long, value_name = "N")]
+    pub clone_jobs: Option<usize>,
+    /// Retries of a clone after a network error, a 5xx or a rate limit (default: 3)
+    #[arg(long, value_name = "N")]
+    pub clone_retries: Option<u32>,
+    /// Delete each checkout's `.git` once its revision is recorded
+    #[arg(long)]
+    pub strip_git: bool,
+}
+
+impl PipelineFlags {
+    /// Overrides the config file with the flags that were given.
+    pub fn apply(&self, pipeline: &mut Pipeline) {
+        let flags = Config {
+            paths: PathsConfig {
+                input_csv: self.input_csv.clone(),
+                filtered_repos: self.filtered_repos.clone(),
+                datasets_dir: self.datasets_dir.clone(),
+                outputs_file: self.outputs_file.clone(),
+                code_file: self.code_file.clone(),
+                metadata_file: self.metadata_file.clone(),
+                clone_errors: self.clone_errors.clone(),
+                stream_errors: None,
+            },
+            analyze: AnalyzeConfig::default(),
+            clone: CloneConfig { on_existing: self.on_existing, jobs: self.clone_jobs, retries: self.clone_retries, strip_git: self.strip_git.then_some(true) },
+            metadata: MetadataConfig { enabled: self.metadata.then_some(true) },
+        };
+        flags.apply(pipeline, |_| false);
+    }
+}
+
+/// `config init`: writes the commented template to `path`, never over an existing file unless `force`.
+pub fn write_config_template(path: &str, force: bool) -> anyhow::Result<()> {
+    if Path::new(path).exists() && !force {
+        anyhow::bail!("{} already exists; pass --force to overwrite it", path);
+    }
+    fs::write(path, CONFIG_TEMPLATE).with_context(|| format!("failed to write {}", path))
+}
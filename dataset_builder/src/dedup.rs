@@ -0,0 +1,314 @@
+//! Deduplication of `code.jsonl`. Exact: the same file vendored into many repositories is kept
+//! once, with a count of its copies and the repositories they came from. Near: files differing
+//! only in a copyright year or formatting are clustered by MinHash over token shingles, with LSH
+//! to find the candidates.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::BufRead;
+
+use anyhow::Context;
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::collect::CodeEntry;
+use crate::jsonl::{open_jsonl, Codec, JsonlWriter};
+use crate::schema::from_line;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dedup {
+    /// Keep every file
+    #[default]
+    None,
+    /// Drop the files with the same content as an earlier one of the same repository
+    WithinRepo,
+    /// Drop the files with the same content as an earlier one of any repository
+    Global,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum NearAction {
+    /// Give every member of a cluster its `cluster_id`
+    #[default]
+    Annotate,
+    /// Keep the first member of each cluster only
+    Drop,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct NearDedupOptions {
+    /// Estimated Jaccard similarity of the token shingles from which two files are near-duplicates
+    #[arg(long, value_name = "0..1", default_value_t = 0.85)]
+    pub threshold: f64,
+    /// MinHash values per file
+    #[arg(long, value_name = "N", default_value_t = 128)]
+    pub num_perm: usize,
+    /// LSH bands, each of `--num-perm / --bands` values; more bands find less similar pairs
+    #[arg(long, value_name = "N", default_value_t = 16)]
+    pub bands: usize,
+    /// Tokens per shingle
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    pub shingle: usize,
+    /// Seed of the MinHash permutations; the same seed gives the same clusters
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+    #[arg(long, value_enum, default_value_t = NearAction::Annotate)]
+    pub action: NearAction,
+}
+
+impl Default for NearDedupOptions {
+    fn default() -> Self {
+        NearDedupOptions { threshold: 0.85, num_perm: 128, bands: 16, shingle: 5, seed: 0, action: NearAction::Annotate }
+    }
+}
+
+impl NearDedupOptions {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(0.0..=1.0).contains(&self.threshold) {
+            anyhow::bail!("--threshold must be between 0 and 1");
+        }
+        if self.num_perm == 0 || self.bands == 0 || !self.num_perm.is_multiple_of(self.bands) {
+            anyhow::bail!("--num-perm ({}) must be a non-zero multiple of --bands ({})", self.num_perm, self.bands);
+        }
+        if self.shingle == 0 {
+            anyhow::bail!("--shingle must be at least 1");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct NearDedupSummary {
+    pub entries: usize,
+    /// Clusters of two files or more
+    pub clusters: usize,
+    /// Entries in those clusters
+    pub clustered: usize,
+    /// Dropped with `--action drop`
+    pub removed: usize,
+    pub removed_bytes: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct DedupSummary {
+    pub entries: usize,
+    pub removed: usize,
+    /// Content bytes of the removed entries
+    pub removed_bytes: u64,
+}
+
+/// The blake3 hash of a file's content, in hex, as in `CodeEntry::content_hash`.
+pub fn content_hash(content: &str) -> String {
+    blake3::hash(content.as_bytes()).to_hex().to_string()
+}
+
+/// The copies of one content, counted on the first pass.
+#[derive(Default)]
+struct Copies {
+    /// Line number of the copy that is kept
+    first: usize,
+    count: usize,
+    repos: BTreeSet<String>,
+}
+
+/// Writes `input` to `output` without exact duplicates, which may be the same file; it is only
+/// replaced once the whole file is written. The first copy of a file is kept, with
+/// `duplicate_count` and `duplicate_repos` saying where the others were. Reads `input` twice,
+/// holding only the hashes in memory.
+pub fn dedup_code(input: &str, output: &str, mode: Dedup) -> anyhow::Result<DedupSummary> {
+    let mut summary = DedupSummary::default();
+    if mode == Dedup::None {
+        if input != output && Codec::of(input) == Codec::of(output) {
+            fs::copy(input, output).with_context(|| format!("failed to copy {} to {}", input, output))?;
+        } else if input != output {
+            let mut w = JsonlWriter::compressed(File::create(output).with_context(|| format!("failed to write {}", output))?, Codec::of(output), None)?;
+            for_each_entry(input, |_, entry| w.write_entry(&entry))?;
+            w.finish()?;
+        }
+        return Ok(summary);
+    }
+    let key = |e: &CodeEntry| match mode {
+        Dedup::WithinRepo => (Some(e.name.clone()), hash_of(e)),
+        _ => (None, hash_of(e)),
+    };
+    let mut copies: HashMap<(Option<String>, String), Copies> = HashMap::new();
+    for_each_entry(input, |number, entry| {
+        let c = copies.entry(key(&entry)).or_insert_with(|| Copies { first: number, ..Copies::default() });
+        c.count += 1;
+        c.repos.insert(entry.name);
+        Ok(())
+    })?;
+
+    let tmp = format!("{}.tmp", output);
+    let mut w = JsonlWriter::compressed(File::create(&tmp).with_context(|| format!("failed to write {}", tmp))?, Codec::of(output), None)?;
+    for_each_entry(input, |number, mut entry| {
+        summary.entries += 1;
+        let c = &copies[&key(&entry)];
+        if c.first != number {
+            summary.removed += 1;
+            summary.removed_bytes += entry.content.len() as u64;
+            return Ok(());
+        }
+        entry.content_hash = hash_of(&entry);
+        entry.duplicate_count = c.count - 1;
+        entry.duplicate_repos = c.repos.iter().filter(|r| **r != entry.name).cloned().collect();
+        w.write_entry(&entry)
+    })?;
+    w.finish()?;
+    fs::rename(&tmp, output)?;
+    Ok(summary)
+}
+
+fn hash_of(entry: &CodeEntry) -> String {
+    match entry.content_hash.is_empty() {
+        // Files collected before there were hashes.
+        true => content_hash(&entry.content),
+        false => entry.content_hash.clone(),
+    }
+}
+
+fn for_each_entry(path: &str, mut f: impl FnMut(usize, CodeEntry) -> anyhow::Result<()>) -> anyhow::Result<()> {
+    for (i, line) in open_jsonl(path)?.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = from_line(&line).with_context(|| format!("{}:{}: not a code entry; `validate --repair` drops bad lines", path, i + 1))?;
+        f(i + 1, entry)?;
+    }
+    Ok(())
+}
+
+/// Writes `input` to `output` (possibly the same file) with near-duplicates clustered. Members of
+/// a cluster of two or more get the entry number of its first member as `cluster_id`; with
+/// `NearAction::Drop` only that first member is kept, with `near_duplicate_count` set. Reads
+/// `input` twice, holding `num_perm` 32-bit values per entry and the LSH buckets in memory.
+pub fn near_dedup_code(input: &str, output: &str, opts: &NearDedupOptions) -> anyhow::Result<NearDedupSummary> {
+    opts.validate()?;
+    let salts: Vec<u64> = (0..opts.num_perm as u64).map(|i| splitmix64(opts.seed.wrapping_add(i))).collect();
+    let rows = opts.num_perm / opts.bands;
+    let mut signatures: Vec<u32> = Vec::new();
+    let mut buckets: HashMap<(usize, u64), usize> = HashMap::new();
+    let mut clusters = Clusters::default();
+    let mut n = 0;
+    for_each_entry(input, |_, entry| {
+        let sig = minhash(&entry.content, opts.shingle, &salts);
+        let found = clusters.add();
+        for (band, values) in sig.chunks(rows).enumerate() {
+            let key = (band, crate::cache::fnv1a(&values.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<_>>()));
+            match buckets.get(&key) {
+                Some(&first) => {
+                    let other = &signatures[first * opts.num_perm..(first + 1) * opts.num_perm];
+                    let same = other.iter().zip(&sig).filter(|(a, b)| a == b).count();
+                    if same as f64 / opts.num_perm as f64 >= opts.threshold {
+                        clusters.union(first, found);
+                    }
+                }
+                None => {
+                    buckets.insert(key, found);
+                }
+            }
+        }
+        signatures.extend(sig);
+        n += 1;
+        Ok(())
+    })?;
+
+    let mut sizes: HashMap<usize, usize> = HashMap::new();
+    for i in 0..n {
+        *sizes.entry(clusters.find(i)).or_default() += 1;
+    }
+    let mut summary = NearDedupSummary { entries: n, ..NearDedupSummary::default() };
+    for &size in sizes.values().filter(|s| **s > 1) {
+        summary.clusters += 1;
+        summary.clustered += size;
+    }
+    let tmp = format!("{}.tmp", output);
+    let mut w = JsonlWriter::compressed(File::create(&tmp).with_context(|| format!("failed to write {}", tmp))?, Codec::of(output), None)?;
+    let mut i = 0;
+    for_each_entry(input, |_, mut entry| {
+        let root = clusters.find(i);
+        let (size, first) = (sizes[&root], root == i);
+        i += 1;
+        if size > 1 {
+            match opts.action {
+                NearAction::Annotate => entry.cluster_id = Some(root),
+                NearAction::Drop if first => entry.near_duplicate_count = size - 1,
+                NearAction::Drop => {
+                    summary.removed += 1;
+                    summary.removed_bytes += entry.content.len() as u64;
+                    return Ok(());
+                }
+            }
+        }
+        w.write_entry(&entry)
+    })?;
+    w.finish()?;
+    fs::rename(&tmp, output)?;
+    Ok(summary)
+}
+
+/// The MinHash signature of the `k`-token shingles of `content`: words and numbers are tokens,
+/// other characters but whitespace are tokens of their own, so layout doesn't matter.
+fn minhash(content: &str, k: usize, salts: &[u64]) -> Vec<u32> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in content.char_indices() {
+        let word = c.is_alphanumeric() || c == '_';
+        if let Some(s) = start.filter(|_| !word) {
+            tokens.push(&content[s..i]);
+            start = None;
+        }
+        match c {
+            _ if word => start = start.or(Some(i)),
+            _ if c.is_whitespace() => {}
+            _ => tokens.push(&content[i..i + c.len_utf8()]),
+        }
+    }
+    if let Some(s) = start {
+        tokens.push(&content[s..]);
+    }
+    let mut sig = vec![u32::MAX; salts.len()];
+    // A file shorter than a shingle is a shingle on its own.
+    for shingle in tokens.windows(k.min(tokens.len()).max(1)) {
+        let h = crate::cache::fnv1a(shingle.join("\u{1f}").as_bytes());
+        for (min, salt) in sig.iter_mut().zip(salts) {
+            *min = (*min).min((splitmix64(h ^ salt) >> 32) as u32);
+        }
+    }
+    sig
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Union-find over entry numbers, whose roots are the first member of each cluster.
+#[derive(Default)]
+struct Clusters {
+    parent: Vec<usize>,
+}
+
+impl Clusters {
+    fn add(&mut self) -> usize {
+        self.parent.push(self.parent.len());
+        self.parent.len() - 1
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parent[a.max(b)] = a.min(b);
+    }
+}
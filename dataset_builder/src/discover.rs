@@ -0,0 +1,330 @@
+//! `discover` and `discover-crates`: build the input CSV from the GitHub search API, or from the
+//! crates.io database dump.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+
+use anyhow::Context;
+use chrono::{NaiveDate, Utc};
+use clap::Args;
+use serde::Deserialize;
+
+use crate::github::{GithubClient, License, Tokens};
+use crate::shutdown::{shutdown_requested, Checkpoint};
+
+/// The search API returns at most this many results per query, however many pages are asked for.
+const SEARCH_CAP: usize = 1000;
+const PER_PAGE: usize = 100;
+/// Nothing on GitHub was created before it launched.
+const FIRST_DAY: (i32, u32, u32) = (2008, 1, 1);
+
+#[derive(Args, Clone, Debug)]
+pub struct DiscoverOptions {
+    /// GitHub search query, e.g. `language:rust stars:>50 pushed:>2023-01-01`
+    #[arg(long)]
+    pub query: String,
+    /// CSV to write, in the columns `filter` reads
+    #[arg(long, value_name = "PATH", default_value = "repos.csv")]
+    pub out: String,
+    /// Leave out forks (`fork:false`)
+    #[arg(long)]
+    pub exclude_forks: bool,
+    /// Leave out archived repositories (`archived:false`)
+    #[arg(long)]
+    pub exclude_archived: bool,
+    /// Also list each repository's root to fill in has_cargo_toml/has_cargo_lock (one more
+    /// request per repository); without it `filter` can't check for them
+    #[arg(long)]
+    pub check_files: bool,
+    /// Continue an interrupted discovery from its checkpoint.json, appending to the CSV
+    #[arg(long)]
+    pub resume: bool,
+    /// Retries of a request after a network error, a server error or a rate limit
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    pub retries: u32,
+    #[arg(long, default_value = "https://api.github.com", hide = true)]
+    pub api_url: String,
+}
+
+#[derive(Debug, Default)]
+pub struct DiscoverSummary {
+    pub repos: usize,
+    pub requests: usize,
+    /// Creation-date ranges the query was split into to stay under the 1,000-result cap
+    pub slices: usize,
+}
+
+#[derive(Deserialize)]
+struct SearchPage {
+    total_count: usize,
+    items: Vec<SearchItem>,
+}
+
+#[derive(Deserialize)]
+struct SearchItem {
+    full_name: String,
+    stargazers_count: u64,
+    size: u64,
+    language: Option<String>,
+    license: Option<License>,
+    fork: bool,
+    archived: bool,
+    created_at: String,
+    pushed_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DirEntry {
+    name: String,
+}
+
+/// Pages through every result of `opts.query`, splitting it by creation date wherever a range has
+/// more results than the search API returns. Each finished page is recorded in checkpoint.json
+/// when the run stops early, so `--resume` continues after it.
+pub fn discover_repos(opts: &DiscoverOptions, tokens: &Tokens) -> anyhow::Result<DiscoverSummary> {
+    let checkpoint = match opts.resume {
+        true => Some(Checkpoint::load_beside(&opts.out, "discover")?.context("--resume needs the checkpoint.json of an interrupted discover")?),
+        false => None,
+    };
+    let mut done = checkpoint.map(|c| c.completed).unwrap_or_default();
+    let file = match opts.resume {
+        true => OpenOptions::new().append(true).open(&opts.out),
+        false => File::create(&opts.out),
+    }
+    .with_context(|| format!("failed to write {}", opts.out))?;
+    let mut csv = csv::Writer::from_writer(file);
+    if !opts.resume {
+        let mut header = vec!["name", "stargazers_count", "size_kb", "language", "license", "fork", "archived", "created_at", "pushed_at"];
+        if opts.check_files {
+            header.extend(["has_cargo_toml", "has_cargo_lock"]);
+        }
+        csv.write_record(header)?;
+        csv.flush()?;
+    }
+
+    let mut client = GithubClient::new(&opts.api_url, tokens, opts.retries);
+    let mut summary = DiscoverSummary::default();
+    let res = discover_into(&mut client, opts, &mut csv, &mut done, &mut summary);
+    summary.requests = client.requests;
+    if let Err(e) = res {
+        Checkpoint::new("discover", done).write_beside(&opts.out)?;
+        return Err(e.context(format!("discovery stopped after {} repositories; run again with --resume to continue from checkpoint.json", summary.repos)));
+    }
+    Checkpoint::remove_beside(&opts.out, "discover");
+    Ok(summary)
+}
+
+/// `done` holds finished ranges (`2023-01-01..2023-01-31`), the finished pages of the range in
+/// progress (`2023-01-01..2023-01-31#3`) and the last day searched (`until:2024-06-30`).
+fn discover_into(client: &mut GithubClient, opts: &DiscoverOptions, csv: &mut csv::Writer<File>, done: &mut Vec<String>, summary: &mut DiscoverSummary) -> anyhow::Result<()> {
+    let mut query = opts.query.trim().to_string();
+    if opts.exclude_forks {
+        query.push_str(" fork:false");
+    }
+    if opts.exclude_archived {
+        query.push_str(" archived:false");
+    }
+    // A query with its own creation dates can't be sliced further, so it's capped.
+    let sliced = !opts.query.contains("created:");
+    let first_day = NaiveDate::from_ymd_opt(FIRST_DAY.0, FIRST_DAY.1, FIRST_DAY.2).expect("valid date");
+    // The ranges must be the same when resuming on a later day, so the last day is kept too.
+    let last_day = done.iter().find_map(|d| d.strip_prefix("until:")?.parse().ok()).unwrap_or_else(|| Utc::now().date_naive());
+    if !done.iter().any(|d| d.starts_with("until:")) {
+        done.push(format!("until:{}", last_day));
+    }
+    let mut ranges = vec![(first_day, last_day)];
+    let mut seen = HashSet::new();
+    while let Some((from, to)) = ranges.pop() {
+        let key = if sliced { format!("{}..{}", from, to) } else { "query".to_string() };
+        if done.contains(&key) {
+            summary.slices += 1;
+            continue;
+        }
+        let q = if sliced { format!("{} created:{}..{}", query, from, to) } else { query.clone() };
+        let resumed = (1..=SEARCH_CAP / PER_PAGE).rev().find(|p| done.contains(&format!("{}#{}", key, p))).unwrap_or(0);
+        let mut page = search(client, &q, resumed + 1)?;
+        if sliced && resumed == 0 && page.total_count > SEARCH_CAP && from < to {
+            let mid = from + (to - from) / 2;
+            // Popped last-in first-out, so the earlier half goes on top.
+            ranges.push((mid.succ_opt().expect("before the last day"), to));
+            ranges.push((from, mid));
+            continue;
+        }
+        if page.total_count > SEARCH_CAP {
+            tracing::warn!("{} repositories match `{}`; only the first {} can be listed", page.total_count, q, SEARCH_CAP);
+        }
+        summary.slices += 1;
+        let pages = page.total_count.min(SEARCH_CAP).div_ceil(PER_PAGE);
+        let mut number = resumed + 1;
+        loop {
+            let short = page.items.len() < PER_PAGE;
+            for item in page.items {
+                if seen.insert(item.full_name.to_ascii_lowercase()) {
+                    write_item(client, opts, csv, item)?;
+                    summary.repos += 1;
+                }
+            }
+            csv.flush()?;
+            done.push(format!("{}#{}", key, number));
+            if short || number >= pages {
+                break;
+            }
+            if shutdown_requested() {
+                anyhow::bail!("interrupted by Ctrl-C");
+            }
+            number += 1;
+            page = search(client, &q, number)?;
+        }
+        done.retain(|d| !d.starts_with(&format!("{}#", key)));
+        done.push(key);
+        tracing::info!("✓ {}: {} repositories so far", if sliced { format!("created {}..{}", from, to) } else { q }, summary.repos);
+        if shutdown_requested() {
+            anyhow::bail!("interrupted by Ctrl-C");
+        }
+    }
+    Ok(())
+}
+
+fn write_item(client: &mut GithubClient, opts: &DiscoverOptions, csv: &mut csv::Writer<File>, item: SearchItem) -> anyhow::Result<()> {
+    let license = License::spdx(item.license).unwrap_or_default();
+    let mut record = vec![
+        item.full_name.clone(),
+        item.stargazers_count.to_string(),
+        item.size.to_string(),
+        item.language.unwrap_or_default(),
+        license,
+        item.fork.to_string(),
+        item.archived.to_string(),
+        item.created_at,
+        item.pushed_at.unwrap_or_default(),
+    ];
+    if opts.check_files {
+        let root = root_files(client, &item.full_name)?;
+        record.push(root.contains("Cargo.toml").to_string());
+        record.push(root.contains("Cargo.lock").to_string());
+    }
+    csv.write_record(record)?;
+    Ok(())
+}
+
+/// One page of `q`'s results.
+fn search(client: &mut GithubClient, q: &str, page: usize) -> anyhow::Result<SearchPage> {
+    let (page, per_page) = (page.to_string(), PER_PAGE.to_string());
+    let response = client.get("/search/repositories", &[("q", q), ("page", &page), ("per_page", &per_page)], &[])?
+        .with_context(|| format!("search for `{}` not found", q))?;
+    serde_json::from_reader(response.into_reader()).with_context(|| format!("unexpected search response for `{}`", q))
+}
+
+/// The names in the repository's root directory; none for an empty repository.
+fn root_files(client: &mut GithubClient, name: &str) -> anyhow::Result<HashSet<String>> {
+    let Some(response) = client.get(&format!("/repos/{}/contents/", name), &[], &[])? else {
+        return Ok(HashSet::new());
+    };
+    let entries: Vec<DirEntry> = serde_json::from_reader(response.into_reader()).with_context(|| format!("unexpected contents of {}", name))?;
+    Ok(entries.into_iter().map(|e| e.name).collect())
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct DiscoverCratesOptions {
+    /// The extracted crates.io database dump (https://static.crates.io/db-dump.tar.gz): its
+    /// directory, or its `data/crates.csv`
+    #[arg(long, value_name = "PATH")]
+    pub db_dump: String,
+    /// CSV to write, in the columns `filter` reads
+    #[arg(long, value_name = "PATH", default_value = "repos.csv")]
+    pub out: String,
+    /// Leave out crates downloaded fewer times than this, in total
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub min_downloads: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct DiscoverCratesSummary {
+    pub crates: usize,
+    /// Crates left out for their download count
+    pub below_min_downloads: usize,
+    /// Crates without a GitHub `repository`
+    pub not_on_github: usize,
+    pub repos: usize,
+}
+
+#[derive(Deserialize)]
+struct CrateRow {
+    id: String,
+    name: String,
+    repository: Option<String>,
+    /// Dumps before 2024 have it here rather than in `crate_downloads.csv`
+    downloads: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct CrateDownloads {
+    crate_id: String,
+    downloads: u64,
+}
+
+/// Writes one row per GitHub repository that a crate in the dump declares as its `repository`,
+/// with the crates sharing it (a workspace publishing several) and their summed downloads.
+pub fn discover_crates(opts: &DiscoverCratesOptions) -> anyhow::Result<DiscoverCratesSummary> {
+    let dump = std::path::Path::new(&opts.db_dump);
+    let crates_csv = match dump.is_dir() {
+        true => [dump.join("data/crates.csv"), dump.join("crates.csv")].into_iter().find(|p| p.is_file())
+            .with_context(|| format!("no data/crates.csv in {}", opts.db_dump))?,
+        false => dump.to_path_buf(),
+    };
+    let downloads_csv = crates_csv.with_file_name("crate_downloads.csv");
+    let downloads: HashMap<String, u64> = match downloads_csv.is_file() {
+        true => csv::Reader::from_path(&downloads_csv)?
+            .deserialize::<CrateDownloads>()
+            .map(|r| r.map(|d| (d.crate_id, d.downloads)))
+            .collect::<Result<_, _>>()
+            .with_context(|| format!("invalid {}", downloads_csv.display()))?,
+        false => HashMap::new(),
+    };
+
+    let mut summary = DiscoverCratesSummary::default();
+    // By lowercased owner/repo: the first spelling seen, its crates and their downloads.
+    let mut repos: HashMap<String, (String, Vec<String>, u64)> = HashMap::new();
+    let mut rdr = csv::Reader::from_path(&crates_csv).with_context(|| format!("failed to read {}", crates_csv.display()))?;
+    for row in rdr.deserialize::<CrateRow>() {
+        let row = row.with_context(|| format!("invalid {}", crates_csv.display()))?;
+        summary.crates += 1;
+        let crate_downloads = row.downloads.or_else(|| downloads.get(&row.id).copied()).unwrap_or(0);
+        if crate_downloads < opts.min_downloads {
+            summary.below_min_downloads += 1;
+            continue;
+        }
+        let Some(repo) = row.repository.as_deref().and_then(github_repo) else {
+            summary.not_on_github += 1;
+            continue;
+        };
+        let entry = repos.entry(repo.to_ascii_lowercase()).or_insert_with(|| (repo, Vec::new(), 0));
+        entry.1.push(row.name);
+        entry.2 += crate_downloads;
+    }
+
+    let mut rows: Vec<_> = repos.into_values().collect();
+    rows.sort_by_key(|(name, _, _)| name.to_ascii_lowercase());
+    let mut w = csv::Writer::from_path(&opts.out).with_context(|| format!("failed to write {}", opts.out))?;
+    w.write_record(["name", "crates", "downloads"])?;
+    for (name, mut crates, downloads) in rows {
+        crates.sort();
+        w.write_record([name, crates.join(";"), downloads.to_string()])?;
+        summary.repos += 1;
+    }
+    w.flush()?;
+    Ok(summary)
+}
+
+/// `owner/repo` of a GitHub `repository` URL, however it's spelled: `.git`, `/tree/main/sub`
+/// and `#readme` suffixes are dropped. `None` for other hosts.
+fn github_repo(url: &str) -> Option<String> {
+    let url = url.trim();
+    let rest = ["https://", "http://", "git://", "git+https://", "ssh://git@"].iter().find_map(|p| url.strip_prefix(p)).unwrap_or(url);
+    let rest = rest.strip_prefix("www.").unwrap_or(rest);
+    let path = rest.strip_prefix("github.com/").or_else(|| rest.strip_prefix("github.com:"))?;
+    let path = path.split(['#', '?']).next().unwrap_or_default();
+    let mut parts = path.split('/').filter(|p| !p.is_empty());
+    let (owner, repo) = (parts.next()?, parts.next()?);
+    crate::filter::canonical_repo_name(&format!("{}/{}", owner, repo)).ok()
+}
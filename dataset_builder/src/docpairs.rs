@@ -0,0 +1,209 @@
+//! The `doc-pairs` step: the doc comment and code of every documented public item of the
+//! checkouts' `.rs` files, for docstring-generation datasets.
+
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::Context;
+use clap::Args;
+use proc_macro2::{LineColumn, Span};
+use serde::{Deserialize, Serialize};
+use syn::spanned::Spanned;
+use syn::{Attribute, Fields, ImplItem, Item, TraitItem, Visibility};
+
+use crate::collect::{checkouts, rust_files, CodeSource};
+use crate::items::{doc, impl_name, Source};
+use crate::jsonl::{Codec, JsonlWriter};
+use crate::progress::Progress;
+
+pub const DEFAULT_DOC_PAIRS_FILE: &str = "docpairs.jsonl";
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct DocPairsOptions {
+    /// Skip the items whose doc comment is shorter than this many characters
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub min_doc_chars: usize,
+    /// Also leave out the paths matching this gitignore-style pattern (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    pub exclude_glob: Vec<String>,
+}
+
+/// One line of `docpairs.jsonl`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DocPair {
+    /// As in `CodeEntry::name`
+    pub name: String,
+    #[serde(default)]
+    pub dir: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    pub path: String,
+    /// `fn`, `struct`, `enum`, `union`, `trait`, `const`, `static` or `type`
+    pub kind: String,
+    /// As in `CodeItem::path`
+    pub item_path: String,
+    /// The doc comment's markdown, one line per `///` line
+    pub doc: String,
+    /// The item without its attributes, visibility and body, e.g. `fn get(&self, i: usize) -> u8`
+    /// or `struct Buf<T> where T: Copy`
+    pub signature: String,
+    /// A function's block, or the fields or variants of a type or the items of a trait, as
+    /// written; absent for a trait method without a default body, unit structs, consts, statics
+    /// and type aliases
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// 1-based and inclusive, doc comment included
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct DocPairsSummary {
+    pub repos: usize,
+    /// `.rs` files read
+    pub files: usize,
+    pub pairs: usize,
+    /// Skipped by `--min-doc-chars`
+    pub short_docs: usize,
+    pub parse_failed: usize,
+}
+
+/// Writes the doc pairs of every checkout under `root` to `out`.
+pub fn extract_doc_pairs_all(root: &str, out: &str, opts: &DocPairsOptions) -> anyhow::Result<DocPairsSummary> {
+    let mut w = JsonlWriter::compressed(File::create(out).with_context(|| format!("failed to write {}", out))?, Codec::of(out), None)?;
+    let repos = checkouts(root)?;
+    let progress = Progress::new(repos.len(), "repositories scanned for doc comments");
+    let mut summary = DocPairsSummary { repos: repos.len(), ..DocPairsSummary::default() };
+    for (source, path) in &repos {
+        let bar = progress.start(&path.file_name().unwrap_or_default().to_string_lossy());
+        let s = extract_doc_pairs(path, source, opts, &mut |pair| w.write_entry(&pair))?;
+        summary.files += s.files;
+        summary.pairs += s.pairs;
+        summary.short_docs += s.short_docs;
+        summary.parse_failed += s.parse_failed;
+        progress.finish(bar);
+    }
+    progress.done();
+    w.finish()?;
+    Ok(summary)
+}
+
+/// Hands the doc pairs of the checkout's `.rs` files, as `collect` would find them, to `emit`.
+pub fn extract_doc_pairs(repo_path: &Path, source: &CodeSource, opts: &DocPairsOptions, emit: &mut dyn FnMut(DocPair) -> anyhow::Result<()>) -> anyhow::Result<DocPairsSummary> {
+    let mut summary = DocPairsSummary { repos: 1, ..DocPairsSummary::default() };
+    for (module, file) in rust_files(repo_path, source, &opts.exclude_glob)? {
+        summary.files += 1;
+        let Ok(found) = find_pairs(&file.content, &module) else {
+            summary.parse_failed += 1;
+            continue;
+        };
+        for pair in found {
+            if pair.doc.chars().count() < opts.min_doc_chars {
+                summary.short_docs += 1;
+                continue;
+            }
+            summary.pairs += 1;
+            emit(DocPair { name: file.name.clone(), dir: file.dir.clone(), repo: file.repo.clone(), commit: file.commit.clone(), path: file.path.clone(), ..pair })?;
+        }
+    }
+    Ok(summary)
+}
+
+fn find_pairs(content: &str, module: &str) -> Result<Vec<DocPair>, String> {
+    let found = syn::parse_file(content)
+        .map(|file| {
+            let source = Source::new(content);
+            let mut out = Vec::new();
+            for item in &file.items {
+                pairs(&source, module, item, &mut out);
+            }
+            out
+        })
+        .map_err(|e| e.to_string());
+    // As in `items::extract`.
+    proc_macro2::extra::invalidate_current_thread_spans();
+    found
+}
+
+/// The pairs of `item` if it is public, recursing into public inline modules, inherent impls and
+/// traits.
+fn pairs(source: &Source, module: &str, item: &Item, out: &mut Vec<DocPair>) {
+    let path = |name: &dyn std::fmt::Display| format!("{}::{}", module, name);
+    match item {
+        Item::Fn(f) if public(&f.vis) => out.extend(pair(source, "fn", path(&f.sig.ident), f.span(), &f.attrs, f.sig.span().start(), Some(f.block.span().start()))),
+        Item::Struct(s) if public(&s.vis) => {
+            let body = match &s.fields {
+                Fields::Named(f) => Some(f.brace_token.span.open().start()),
+                Fields::Unnamed(f) => Some(f.paren_token.span.open().start()),
+                Fields::Unit => None,
+            };
+            out.extend(pair(source, "struct", path(&s.ident), s.span(), &s.attrs, s.struct_token.span.start(), body));
+        }
+        Item::Enum(e) if public(&e.vis) => out.extend(pair(source, "enum", path(&e.ident), e.span(), &e.attrs, e.enum_token.span.start(), Some(e.brace_token.span.open().start()))),
+        Item::Union(u) if public(&u.vis) => out.extend(pair(source, "union", path(&u.ident), u.span(), &u.attrs, u.union_token.span.start(), Some(u.fields.brace_token.span.open().start()))),
+        Item::Const(c) if public(&c.vis) => out.extend(pair(source, "const", path(&c.ident), c.span(), &c.attrs, c.const_token.span.start(), None)),
+        Item::Static(s) if public(&s.vis) => out.extend(pair(source, "static", path(&s.ident), s.span(), &s.attrs, s.static_token.span.start(), None)),
+        Item::Type(t) if public(&t.vis) => out.extend(pair(source, "type", path(&t.ident), t.span(), &t.attrs, t.type_token.span.start(), None)),
+        Item::Trait(t) if public(&t.vis) => {
+            let start = t.unsafety.map(|u| u.span).or(t.auto_token.map(|a| a.span)).unwrap_or(t.trait_token.span);
+            out.extend(pair(source, "trait", path(&t.ident), t.span(), &t.attrs, start.start(), Some(t.brace_token.span.open().start())));
+            // The methods of a public trait are public.
+            for inner in &t.items {
+                if let TraitItem::Fn(f) = inner {
+                    out.extend(pair(source, "fn", format!("{}::{}", path(&t.ident), f.sig.ident), f.span(), &f.attrs, f.sig.span().start(), f.default.as_ref().map(|b| b.span().start())));
+                }
+            }
+        }
+        // Trait impls follow the trait's visibility and seldom have docs of their own.
+        Item::Impl(i) if i.trait_.is_none() => {
+            let owner = format!("{}::{}", module, impl_name(source, i));
+            for inner in &i.items {
+                if let ImplItem::Fn(f) = inner {
+                    if public(&f.vis) {
+                        out.extend(pair(source, "fn", format!("{}::{}", owner, f.sig.ident), f.span(), &f.attrs, f.sig.span().start(), Some(f.block.span().start())));
+                    }
+                }
+            }
+        }
+        Item::Mod(m) if public(&m.vis) => {
+            for inner in m.content.iter().flat_map(|(_, items)| items) {
+                pairs(source, &format!("{}::{}", module, m.ident), inner, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The pair of an item spanning `span`, whose signature starts at `start` and body at `body`, if
+/// it has a doc comment and isn't `#[doc(hidden)]`. Provenance is filled in by the caller.
+fn pair(source: &Source, kind: &str, item_path: String, span: Span, attrs: &[Attribute], start: LineColumn, body: Option<LineColumn>) -> Option<DocPair> {
+    if attrs.iter().any(hidden) {
+        return None;
+    }
+    let doc = doc(attrs)?;
+    let end = span.end();
+    Some(DocPair {
+        name: String::new(),
+        dir: String::new(),
+        repo: None,
+        commit: None,
+        path: String::new(),
+        kind: kind.to_string(),
+        item_path,
+        doc,
+        signature: source.between(start, body.unwrap_or(end)).trim().trim_end_matches(';').to_string(),
+        body: body.map(|b| source.between(b, end).to_string()),
+        start_line: span.start().line,
+        end_line: end.line,
+    })
+}
+
+fn public(vis: &Visibility) -> bool {
+    matches!(vis, Visibility::Public(_))
+}
+
+fn hidden(attr: &Attribute) -> bool {
+    matches!(&attr.meta, syn::Meta::List(l) if l.path.is_ident("doc") && l.tokens.to_string().contains("hidden"))
+}
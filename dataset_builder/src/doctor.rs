@@ -0,0 +1,238 @@
+//! `doctor`: checks the tools, toolchain, token, disk and output paths a `full` run needs.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use clap::Args;
+
+use crate::analyzers::select_analyzers;
+use crate::github::Tokens;
+use crate::manifest::{ToolInventory, TOOL_PROBES};
+use crate::pipeline::Pipeline;
+
+/// Free space `doctor` requires on the checkouts' disk unless `--min-free-gb` says otherwise.
+pub const DEFAULT_MIN_FREE_GB: u64 = 20;
+
+#[derive(Args, Clone, Debug)]
+pub struct DoctorOptions {
+    /// Print how to install or fix every check that doesn't pass
+    #[arg(long)]
+    pub fix_hints: bool,
+    /// Fail when the disk holding the checkouts has less free space than this many GiB
+    #[arg(long, value_name = "GIB", default_value_t = DEFAULT_MIN_FREE_GB)]
+    pub min_free_gb: u64,
+}
+
+impl Default for DoctorOptions {
+    fn default() -> Self {
+        DoctorOptions { fix_hints: false, min_free_gb: DEFAULT_MIN_FREE_GB }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    /// Doesn't stop the run, but the dataset or the run will be worse for it
+    Warn,
+    /// The run would fail or produce a broken dataset
+    Fail,
+}
+
+#[derive(Clone, Debug)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// How to install or fix it
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn new(name: &str, status: CheckStatus, detail: impl Into<String>, hint: &str) -> Self {
+        let hint = (status != CheckStatus::Pass).then(|| hint.to_string());
+        Check { name: name.to_string(), status, detail: detail.into(), hint }
+    }
+}
+
+/// Install command per probed tool (see `TOOL_PROBES`).
+const TOOL_HINTS: &[(&str, &str)] = &[
+    ("clippy", "rustup component add clippy"),
+    ("fmt", "rustup component add rustfmt"),
+    ("audit", "cargo install cargo-audit --locked"),
+    ("auditable", "cargo install cargo-auditable --locked"),
+    ("deny", "cargo install cargo-deny --locked"),
+    ("geiger", "cargo install cargo-geiger --locked"),
+    ("tree", "rustup update (cargo tree ships with cargo 1.44+)"),
+    ("udeps", "cargo install cargo-udeps --locked (runs on nightly)"),
+    ("outdated", "cargo install cargo-outdated --locked"),
+    ("machete", "cargo install cargo-machete --locked"),
+    ("tokei", "cargo install tokei --locked"),
+    ("vet", "cargo install cargo-vet --locked"),
+    ("crev", "cargo install cargo-crev --locked"),
+    ("expand", "cargo install cargo-expand --locked (runs on nightly)"),
+    ("ast", "rustup toolchain install nightly"),
+    ("semgrep", "pip install semgrep"),
+    ("codeql", "download the CLI from https://github.com/github/codeql-cli-binaries/releases and put `codeql` on PATH"),
+];
+
+/// Runs every check against the settings `pipeline` would run with.
+pub fn doctor_checks(pipeline: &Pipeline, opts: &DoctorOptions) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let cargo = Command::new("cargo").arg("--version").output().ok().filter(|o| o.status.success());
+    checks.push(match cargo {
+        Some(out) => Check::new("cargo", CheckStatus::Pass, String::from_utf8_lossy(&out.stdout).trim(), ""),
+        None => Check::new("cargo", CheckStatus::Fail, "not found", "install Rust with rustup: https://rustup.rs"),
+    });
+
+    // Only the analyzers the run would use are required; a bad selection fails on its own.
+    let selected: Vec<String> = match select_analyzers(&pipeline.analyze) {
+        Ok(s) => s.run.iter().map(|a| a.name().to_string()).collect(),
+        Err(e) => {
+            checks.push(Check::new("analyzers", CheckStatus::Fail, format!("{:#}", e), "fix --only/--enable/--skip or the tools config"));
+            Vec::new()
+        }
+    };
+    let inventory = ToolInventory::probe(&pipeline.analyze);
+    for &(tool, _, _) in TOOL_PROBES {
+        // The same nightly as `ast`, checked once.
+        if matches!(tool, "hir" | "mir") {
+            continue;
+        }
+        let version = inventory.versions.get(tool).cloned().flatten();
+        let hint = TOOL_HINTS.iter().find(|(t, _)| *t == tool).map_or("", |(_, h)| h);
+        let name = if tool == "ast" { "nightly" } else { tool };
+        let wanted = selected.iter().any(|s| s == tool || (tool == "ast" && matches!(s.as_str(), "hir" | "mir")));
+        checks.push(match (version, wanted) {
+            (Some(v), _) => Check::new(name, CheckStatus::Pass, v.lines().next().unwrap_or_default(), hint),
+            (None, true) => Check::new(name, CheckStatus::Fail, "not installed (selected for the run)", hint),
+            (None, false) => Check::new(name, CheckStatus::Warn, "not installed (not selected)", hint),
+        });
+    }
+
+    checks.push(check_token(&pipeline.tokens));
+    checks.push(match Path::new(&pipeline.input_csv).is_file() {
+        true => Check::new("input", CheckStatus::Pass, pipeline.input_csv.clone(), ""),
+        false => Check::new("input", CheckStatus::Fail, format!("{} not found", pipeline.input_csv), "create it (columns: id,name,has_toml,has_lock) or set paths.input_csv"),
+    });
+    checks.push(check_disk(&pipeline.datasets_dir, opts.min_free_gb));
+    let dirs: BTreeSet<PathBuf> = [&pipeline.filtered_repos, &pipeline.outputs_file, &pipeline.code_file]
+        .into_iter()
+        .chain(pipeline.metadata.then_some(&pipeline.metadata_file))
+        .map(|f| parent_dir(f))
+        .chain([PathBuf::from(&pipeline.datasets_dir)])
+        .map(|d| existing_ancestor(&d))
+        .collect();
+    for dir in dirs {
+        checks.push(check_writable(&dir));
+    }
+    checks
+}
+
+/// Prints the checks as a table, failing if any required check failed.
+pub fn run_doctor(pipeline: &Pipeline, opts: &DoctorOptions) -> anyhow::Result<()> {
+    let checks = doctor_checks(pipeline, opts);
+    let width = checks.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    for c in &checks {
+        let status = match c.status {
+            CheckStatus::Pass => "✓ pass",
+            CheckStatus::Warn => "⚠ warn",
+            CheckStatus::Fail => "✗ FAIL",
+        };
+        println!("{:<width$}  {}  {}", c.name, status, c.detail, width = width);
+        if let Some(hint) = c.hint.as_ref().filter(|_| opts.fix_hints) {
+            println!("{:<width$}          → {}", "", hint, width = width);
+        }
+    }
+    let failed: Vec<&str> = checks.iter().filter(|c| c.status == CheckStatus::Fail).map(|c| c.name.as_str()).collect();
+    if !failed.is_empty() {
+        let hints = if opts.fix_hints { "" } else { " (--fix-hints shows how to fix them)" };
+        anyhow::bail!("{} required checks failed: {}{}", failed.len(), failed.join(", "), hints);
+    }
+    println!("✓ All required checks passed");
+    Ok(())
+}
+
+/// `GET /rate_limit` with each token: cheap, and it doesn't count against the limit.
+fn check_token(tokens: &Tokens) -> Check {
+    const HINT: &str = "create a token at https://github.com/settings/tokens and export GITHUB_TOKEN (or GITHUB_TOKENS, or pass --token-file)";
+    if tokens.is_empty() {
+        return Check::new("token", CheckStatus::Warn, "none: anonymous clones, 60 API requests/hour", HINT);
+    }
+    let (mut remaining, mut limit) = (0u64, 0u64);
+    for (i, token) in tokens.iter().enumerate() {
+        // Named by index: a token is never printed.
+        let which = tokens.label(i);
+        let response = ureq::get("https://api.github.com/rate_limit")
+            .set("Authorization", &format!("Bearer {}", token))
+            .set("User-Agent", "dataset_builder")
+            .timeout(Duration::from_secs(10))
+            .call();
+        match response {
+            Ok(r) => {
+                let header = |name: &str| r.header(name).and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+                remaining += header("x-ratelimit-remaining");
+                limit += header("x-ratelimit-limit");
+            }
+            Err(ureq::Error::Status(401, _)) => return Check::new("token", CheckStatus::Fail, format!("{} rejected by GitHub (401)", which), HINT),
+            Err(ureq::Error::Status(code, _)) => return Check::new("token", CheckStatus::Fail, format!("GitHub answered {} for {}", code, which), HINT),
+            Err(e) => return Check::new("token", CheckStatus::Fail, format!("could not reach api.github.com: {}", e), "check the network or proxy settings"),
+        }
+    }
+    let valid = if tokens.len() == 1 { "valid".to_string() } else { format!("{} tokens valid", tokens.len()) };
+    Check::new("token", CheckStatus::Pass, format!("{}, {} of {} API requests left", valid, remaining, limit), HINT)
+}
+
+fn check_disk(datasets_dir: &str, min_free_gb: u64) -> Check {
+    let dir = existing_ancestor(Path::new(datasets_dir));
+    let name = "disk";
+    let hint = "free up space, point paths.datasets_dir at a larger disk, or use `full --stream`";
+    match free_bytes(&dir) {
+        Some(free) => {
+            let gib = free as f64 / (1u64 << 30) as f64;
+            let status = if gib >= min_free_gb as f64 { CheckStatus::Pass } else { CheckStatus::Fail };
+            Check::new(name, status, format!("{:.1} GiB free in {} (need {})", gib, dir.display(), min_free_gb), hint)
+        }
+        None => Check::new(name, CheckStatus::Warn, format!("could not read free space of {}", dir.display()), hint),
+    }
+}
+
+fn check_writable(dir: &Path) -> Check {
+    let probe = dir.join(format!(".dataset_builder_doctor_{}", std::process::id()));
+    let name = format!("write {}", dir.display());
+    match fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = fs::remove_file(&probe);
+            Check::new(&name, CheckStatus::Pass, "writable", "")
+        }
+        Err(e) => Check::new(&name, CheckStatus::Fail, e.to_string(), "fix the directory's permissions or choose other paths"),
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn free_bytes(dir: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    (unsafe { libc::statvfs(path.as_ptr(), &mut stat) } == 0).then(|| stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn free_bytes(_: &Path) -> Option<u64> {
+    None
+}
+
+fn parent_dir(file: &str) -> PathBuf {
+    match Path::new(file).parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// `dir`, or the closest ancestor that exists when the run would still have to create it.
+pub(crate) fn existing_ancestor(dir: &Path) -> PathBuf {
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    dir.ancestors().find(|a| a.is_dir()).unwrap_or(Path::new(".")).to_path_buf()
+}
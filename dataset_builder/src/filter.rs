@@ -0,0 +1,501 @@
+//! The `filter` step: keep the repositories that have both a `Cargo.toml` and a `Cargo.lock`,
+//! optionally narrowed down by stars, size, language and last push.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+
+use anyhow::Context;
+use chrono::NaiveDate;
+use clap::{Args, ValueEnum};
+use csv::{ReaderBuilder, StringRecord};
+use serde::Deserialize;
+
+use crate::sample::{sample_names, SampleSize, Strata};
+
+/// Written next to the filter's output when rows had to be skipped.
+pub const DEFAULT_REJECTED_FILE: &str = "rejected_rows.csv";
+
+/// Columns of a headerless file, in order; also the names the overrides map columns to.
+const POSITIONAL: [&str; 4] = ["id", "name", "has_cargo_toml", "has_cargo_lock"];
+
+/// Header names read as each column without an override (compared case-insensitively); they
+/// match `RepoRow`'s serde names and aliases.
+const ALIASES: [(&str, &[&str]); 7] = [
+    ("name", &["name", "full_name", "repo_name"]),
+    ("has_cargo_toml", &["has_cargo_toml", "has_toml"]),
+    ("has_cargo_lock", &["has_cargo_lock", "has_lock"]),
+    ("stargazers_count", &["stargazers_count", "stars", "stargazers"]),
+    ("size_kb", &["size_kb", "size"]),
+    ("language", &["language"]),
+    ("pushed_at", &["pushed_at"]),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    Csv,
+    /// One JSON object per line, e.g. from GH Archive or `gh api --jq '.items[]'`
+    Jsonl,
+}
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct FilterOptions {
+    /// Input format (default: JSONL for `.jsonl` and `.ndjson` files, and for stdin starting with `{`; CSV otherwise)
+    #[arg(long, value_enum)]
+    pub format: Option<InputFormat>,
+    /// Column holding the `owner/name` of each repository: a header name or JSONL field, or a
+    /// 0-based index for files without a header (default: `name` or `full_name`)
+    #[arg(long, value_name = "COLUMN")]
+    pub name_column: Option<String>,
+    /// Column saying whether the repository has a Cargo.toml (default: `has_cargo_toml` or `has_toml`)
+    #[arg(long, value_name = "COLUMN")]
+    pub toml_column: Option<String>,
+    /// Column saying whether the repository has a Cargo.lock (default: `has_cargo_lock` or `has_lock`)
+    #[arg(long, value_name = "COLUMN")]
+    pub lock_column: Option<String>,
+    /// Keep only repositories with at least this many stars (`stargazers_count` column)
+    #[arg(long, value_name = "N")]
+    pub min_stars: Option<u64>,
+    /// Keep only repositories of at most this size (`size_kb` column)
+    #[arg(long, value_name = "KB")]
+    pub max_size_kb: Option<u64>,
+    /// Keep only repositories in this primary language, in any case (`language` column)
+    #[arg(long, value_name = "NAME")]
+    pub language: Option<String>,
+    /// Keep only repositories pushed to on or after this date (`pushed_at` column)
+    #[arg(long, value_name = "YYYY-MM-DD")]
+    pub pushed_after: Option<NaiveDate>,
+    /// Keep a random sample of this many of the qualifying repositories, sorted by name
+    #[arg(long, value_name = "N", conflicts_with = "sample_frac")]
+    pub sample: Option<usize>,
+    /// Keep a random sample of this fraction (0 to 1) of the qualifying repositories
+    #[arg(long, value_name = "FRACTION")]
+    pub sample_frac: Option<f64>,
+    /// Seed of the sample: the same seed and input always give the same repositories
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+    /// Sample each range of a numeric column in proportion to its size, e.g.
+    /// `stars:0-100,100-1000,1000+`; repositories outside every range are dropped
+    #[arg(long, value_name = "COLUMN:RANGES")]
+    pub stratify_by: Option<String>,
+    /// Fail on the first row that can't be parsed instead of skipping it
+    #[arg(long)]
+    pub strict: bool,
+    /// Where to write the skipped rows with their line numbers and errors (default:
+    /// `rejected_rows.csv` next to the output)
+    #[arg(long, value_name = "PATH")]
+    pub rejected_file: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RepoRow {
+    #[serde(alias = "full_name", alias = "repo_name")]
+    name: String,
+    #[serde(rename = "has_cargo_toml", alias = "has_toml", default, deserialize_with = "flag")]
+    has_toml: Option<bool>,
+    #[serde(rename = "has_cargo_lock", alias = "has_lock", default, deserialize_with = "flag")]
+    has_lock: Option<bool>,
+    #[serde(alias = "stars", alias = "stargazers", default, deserialize_with = "number")]
+    stargazers_count: Option<u64>,
+    #[serde(alias = "size", default, deserialize_with = "number")]
+    size_kb: Option<u64>,
+    #[serde(default)]
+    language: Option<String>,
+    #[serde(default, deserialize_with = "date")]
+    pushed_at: Option<NaiveDate>,
+}
+
+impl RepoRow {
+    fn number(&self, column: &str) -> Option<u64> {
+        match column {
+            "stargazers_count" => self.stargazers_count,
+            "size_kb" => self.size_kb,
+            _ => None,
+        }
+    }
+}
+
+/// `true`/`yes`/`1` and `false`/`no`/`0` in any case; empty is `None`.
+fn flag<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<bool>, D::Error> {
+    let text = Option::<String>::deserialize(d)?.unwrap_or_default();
+    match text.trim().to_ascii_lowercase().as_str() {
+        "" => Ok(None),
+        "true" | "t" | "yes" | "y" | "1" => Ok(Some(true)),
+        "false" | "f" | "no" | "n" | "0" => Ok(Some(false)),
+        other => Err(serde::de::Error::custom(format!("`{}` is not a boolean", other))),
+    }
+}
+
+fn number<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<u64>, D::Error> {
+    let text = Option::<String>::deserialize(d)?.unwrap_or_default();
+    match text.trim() {
+        "" => Ok(None),
+        t => t.parse().map(Some).map_err(|_| serde::de::Error::custom(format!("`{}` is not a number", t))),
+    }
+}
+
+/// The date of `2022-01-01`, `2022-01-01T10:00:00Z` or `2022-01-01 10:00:00 UTC`.
+fn date<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<NaiveDate>, D::Error> {
+    let text = Option::<String>::deserialize(d)?.unwrap_or_default();
+    match text.trim() {
+        "" => Ok(None),
+        t => t.get(..10).and_then(|day| NaiveDate::parse_from_str(day, "%Y-%m-%d").ok()).map(Some)
+            .ok_or_else(|| serde::de::Error::custom(format!("`{}` is not a date", t))),
+    }
+}
+
+/// A `--min-stars`-style flag: the column it reads and whether a row passes it. Rows without a
+/// value don't pass.
+struct Predicate {
+    flag: String,
+    column: &'static str,
+    keep: Box<dyn Fn(&RepoRow) -> bool>,
+}
+
+fn predicates(opts: &FilterOptions) -> Vec<Predicate> {
+    let mut predicates = Vec::new();
+    let mut add = |flag: String, column, keep: Box<dyn Fn(&RepoRow) -> bool>| predicates.push(Predicate { flag, column, keep });
+    if let Some(min) = opts.min_stars {
+        add(format!("--min-stars {}", min), "stargazers_count", Box::new(move |r| r.stargazers_count.is_some_and(|n| n >= min)));
+    }
+    if let Some(max) = opts.max_size_kb {
+        add(format!("--max-size-kb {}", max), "size_kb", Box::new(move |r| r.size_kb.is_some_and(|n| n <= max)));
+    }
+    if let Some(language) = opts.language.clone() {
+        add(format!("--language {}", language), "language", Box::new(move |r| r.language.as_deref().is_some_and(|l| l.trim().eq_ignore_ascii_case(&language))));
+    }
+    if let Some(after) = opts.pushed_after {
+        add(format!("--pushed-after {}", after), "pushed_at", Box::new(move |r| r.pushed_at.is_some_and(|d| d >= after)));
+    }
+    predicates
+}
+
+/// What `filter_csv_with` read and kept.
+#[derive(Debug, Default)]
+pub struct FilterSummary {
+    pub rows: usize,
+    pub kept: usize,
+    /// Rows skipped because they couldn't be parsed, listed in the rejected file
+    pub rejected: usize,
+    /// Rows without a `has_cargo_toml` or `has_cargo_lock` value, counted as `false`
+    pub missing_flags: usize,
+    /// Per `--min-stars`-style flag, the rows with both files that it eliminated; a row can fail
+    /// several. `--stratify-by` eliminates the rows outside its ranges.
+    pub eliminated: Vec<(String, usize)>,
+    /// Rows naming a repository an earlier kept row already named (compared case-insensitively);
+    /// listed in the rejected file, but not counted in `rejected`
+    pub duplicates: usize,
+    /// How many of the `kept` rows were written, with `--sample` or `--sample-frac`
+    pub sampled: Option<usize>,
+}
+
+/// `42,311 rows read, 39,870 kept, 12 rejected`
+impl fmt::Display for FilterSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} rows read, {} kept, {} rejected", thousands(self.rows), thousands(self.kept), thousands(self.rejected))?;
+        if self.duplicates > 0 {
+            write!(f, ", {} duplicates", thousands(self.duplicates))?;
+        }
+        match self.sampled {
+            Some(n) => write!(f, ", {} sampled", thousands(n)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+pub fn filter_csv(input: &str, output: &str) -> anyhow::Result<()> {
+    filter_csv_with(input, output, &FilterOptions::default()).map(|_| ())
+}
+
+/// Reads `input` (`-` for stdin) as CSV or JSONL and writes the `name` of every row with both
+/// flags set to `output`, one per line. Whether a CSV file starts with a header row is detected
+/// from its first row; JSONL fields are matched like CSV headers. Rows that can't be parsed are
+/// skipped and written to the rejected file, unless `opts.strict`. With `--sample`, a seeded
+/// random subset of them is written instead, sorted.
+pub fn filter_csv_with(input: &str, output: &str, opts: &FilterOptions) -> anyhow::Result<FilterSummary> {
+    let size = match (opts.sample, opts.sample_frac) {
+        (Some(n), _) => Some(SampleSize::Count(n)),
+        (None, Some(f)) if f > 0.0 && f <= 1.0 => Some(SampleSize::Fraction(f)),
+        (None, Some(f)) => anyhow::bail!("--sample-frac must be between 0 and 1, not {}", f),
+        (None, None) => None,
+    };
+    let strata = opts.stratify_by.as_deref().map(Strata::parse).transpose()?;
+    let stratified = match &strata {
+        Some(_) if size.is_none() => anyhow::bail!("--stratify-by needs --sample or --sample-frac"),
+        Some(s) => Some(["stargazers_count", "size_kb"].into_iter().find(|c| known_as(&s.column, c)).with_context(|| {
+            format!("--stratify-by can only use stargazers_count or size_kb (or stars, size), not `{}`", s.column)
+        })?),
+        None => None,
+    };
+    let predicates = predicates(opts);
+    let mut needed: Vec<(String, &str)> = predicates.iter().map(|p| (p.flag.clone(), p.column)).collect();
+    needed.extend(stratified.map(|column| (format!("--stratify-by {}", opts.stratify_by.as_deref().unwrap_or_default()), column)));
+    let source = if input == "-" { "stdin" } else { input };
+    let data = match input {
+        "-" => {
+            let mut data = Vec::new();
+            std::io::stdin().read_to_end(&mut data).context("failed to read stdin")?;
+            data
+        }
+        _ => fs::read(input).with_context(|| format!("failed to read {}", input))?,
+    };
+    let (rows, check_flags) = match opts.format.unwrap_or_else(|| detect_format(input, &data)) {
+        InputFormat::Csv => csv_rows(&data, source, opts, &needed)?,
+        InputFormat::Jsonl => jsonl_rows(&data, opts, &needed)?,
+    };
+
+    let mut summary = FilterSummary { eliminated: needed.iter().map(|(flag, _)| (flag.clone(), 0)).collect(), ..FilterSummary::default() };
+    let mut names = vec![Vec::new(); strata.as_ref().map_or(1, |s| s.buckets.len())];
+    let mut rejected = Vec::new();
+    if !check_flags {
+        tracing::warn!("{} has no has_cargo_toml or has_cargo_lock column; keeping repositories without checking for them", source);
+    }
+    let mut seen: HashMap<String, (String, u64)> = HashMap::new();
+    for (line, text, parsed) in rows {
+        summary.rows += 1;
+        let row = match parsed {
+            Ok(row) => row,
+            Err(error) if !opts.strict => {
+                rejected.push((line, format!("{:#}", error), text));
+                continue;
+            }
+            Err(error) => anyhow::bail!("{}: bad row on line {}: {:#}", source, line, error),
+        };
+        if check_flags && (row.has_toml.is_none() || row.has_lock.is_none()) {
+            summary.missing_flags += 1;
+        }
+        if check_flags && !(row.has_toml.unwrap_or(false) && row.has_lock.unwrap_or(false)) {
+            continue;
+        }
+        let mut keep = true;
+        for (p, (_, eliminated)) in predicates.iter().zip(&mut summary.eliminated) {
+            if !(p.keep)(&row) {
+                *eliminated += 1;
+                keep = false;
+            }
+        }
+        let stratum = match (&strata, stratified) {
+            (Some(s), Some(column)) => row.number(column).and_then(|v| s.bucket(v)),
+            _ => Some(0),
+        };
+        if keep && stratum.is_some() {
+            // The first of several spellings (`Owner/Repo`, `owner/repo`) is the one kept.
+            if let Some((first, first_line)) = seen.get(&row.name.to_ascii_lowercase()) {
+                summary.duplicates += 1;
+                rejected.push((line, format!("duplicate of {} (line {})", first, first_line), text));
+                continue;
+            }
+            seen.insert(row.name.to_ascii_lowercase(), (row.name.clone(), line));
+        }
+        match stratum {
+            Some(i) if keep => names[i].push(row.name),
+            Some(_) => {}
+            None => summary.eliminated.last_mut().expect("--stratify-by is counted last").1 += 1,
+        }
+    }
+    summary.kept = names.iter().map(Vec::len).sum();
+    summary.rejected = rejected.len() - summary.duplicates;
+    let names = match size {
+        Some(size) => sample_names(names, size, opts.seed),
+        None => names.concat(),
+    };
+    summary.sampled = size.map(|_| names.len());
+    write_names(output, &names)?;
+    if summary.missing_flags > 0 {
+        tracing::warn!("{} of {} rows in {} had no has_cargo_toml/has_cargo_lock value; treated as false", summary.missing_flags, summary.rows, source);
+    }
+    if !rejected.is_empty() {
+        let path = opts.rejected_file.clone().unwrap_or_else(|| Path::new(output).with_file_name(DEFAULT_REJECTED_FILE).display().to_string());
+        let mut w = csv::Writer::from_path(&path).with_context(|| format!("failed to write {}", path))?;
+        w.write_record(["line", "error", "row"])?;
+        for (line, error, text) in &rejected {
+            w.write_record([line.to_string().as_str(), error, text])?;
+        }
+        w.flush()?;
+        tracing::warn!("{} rows of {} were skipped as malformed or duplicates; see {}", rejected.len(), source, path);
+    }
+    Ok(summary)
+}
+
+/// A row's line number, its text for the rejected file, and the row.
+type Row = (u64, String, anyhow::Result<RepoRow>);
+
+/// The rows, and whether the input has a has_cargo_toml or has_cargo_lock column at all; without
+/// either (as `discover` writes without `--check-files`), the check is left to later steps.
+type Rows<'a> = (Box<dyn Iterator<Item = Row> + 'a>, bool);
+
+fn has_flag_columns(headers: &StringRecord) -> bool {
+    headers.iter().any(|n| known_as(n, "has_cargo_toml") || known_as(n, "has_cargo_lock"))
+}
+
+fn detect_format(input: &str, data: &[u8]) -> InputFormat {
+    let jsonl = match input {
+        "-" => data.trim_ascii_start().starts_with(b"{"),
+        _ => Path::new(input).extension().is_some_and(|e| e.eq_ignore_ascii_case("jsonl") || e.eq_ignore_ascii_case("ndjson")),
+    };
+    if jsonl { InputFormat::Jsonl } else { InputFormat::Csv }
+}
+
+fn csv_rows<'a>(data: &'a [u8], input: &str, opts: &FilterOptions, needed: &[(String, &str)]) -> anyhow::Result<Rows<'a>> {
+    let mut records = ReaderBuilder::new().has_headers(false).flexible(true).from_reader(data).into_records();
+    let Some(first) = records.next().transpose().with_context(|| format!("{}: unreadable first row", input))? else {
+        return Ok((Box::new(std::iter::empty()), true));
+    };
+    let (headers, first_is_data) = column_names(&first, false, opts, needed)?;
+    require_columns(&headers, &first, needed)?;
+    let check_flags = has_flag_columns(&headers);
+    let rows = first_is_data.then_some(Ok(first)).into_iter().chain(records).map(move |record| match record {
+        Ok(record) => (record.position().map_or(0, |p| p.line()), record.iter().collect::<Vec<_>>().join(","), parse_row(&record, &headers)),
+        Err(e) => (e.position().map_or(0, |p| p.line()), String::new(), Err(e.into())),
+    });
+    Ok((Box::new(rows), check_flags))
+}
+
+/// Each line's fields are named like a CSV header of their own, since objects needn't share
+/// them; only the first line's are required to have the predicates' fields.
+fn jsonl_rows<'a>(data: &'a [u8], opts: &'a FilterOptions, needed: &'a [(String, &str)]) -> anyhow::Result<Rows<'a>> {
+    let lines = data.split(|&b| b == b'\n').zip(1..).filter(|(l, _)| !l.trim_ascii().is_empty());
+    let mut check_flags = true;
+    if let Some(Ok((keys, _))) = lines.clone().next().map(|(l, _)| json_fields(l)) {
+        let (headers, _) = column_names(&keys, true, opts, needed)?;
+        require_columns(&headers, &keys, needed)?;
+        check_flags = has_flag_columns(&headers);
+    }
+    let rows = lines.map(move |(raw, line)| {
+        let row = json_fields(raw).and_then(|(keys, values)| parse_row(&values, &column_names(&keys, true, opts, needed)?.0));
+        (line, String::from_utf8_lossy(raw.trim_ascii()).into_owned(), row)
+    });
+    Ok((Box::new(rows), check_flags))
+}
+
+/// A JSON object's field names and values; strings are taken as they are, `null` as empty and
+/// anything else as its JSON text.
+fn json_fields(line: &[u8]) -> anyhow::Result<(StringRecord, StringRecord)> {
+    let serde_json::Value::Object(fields) = serde_json::from_slice(line)? else {
+        anyhow::bail!("not a JSON object");
+    };
+    let (keys, values): (Vec<String>, Vec<String>) = fields
+        .into_iter()
+        .map(|(k, v)| match v {
+            serde_json::Value::String(s) => (k, s),
+            serde_json::Value::Null => (k, String::new()),
+            v => (k, v.to_string()),
+        })
+        .unzip();
+    Ok((StringRecord::from(keys), StringRecord::from(values)))
+}
+
+fn parse_row(record: &StringRecord, headers: &StringRecord) -> anyhow::Result<RepoRow> {
+    let mut row: RepoRow = record.deserialize(Some(headers))?;
+    row.name = canonical_repo_name(&row.name).map_err(anyhow::Error::msg)?;
+    Ok(row)
+}
+
+/// `owner/repo` from a name as it appears in exports: `https://github.com/owner/repo.git/` and
+/// `git@github.com:owner/repo.git` become `owner/repo`. Anything else that isn't
+/// `^[\w.-]+/[\w.-]+$` is an error saying why.
+pub fn canonical_repo_name(raw: &str) -> Result<String, String> {
+    let mut name = raw.trim();
+    if name.is_empty() {
+        return Err("empty repository name".into());
+    }
+    for prefix in ["https://", "http://", "git@github.com:", "www."] {
+        name = name.strip_prefix(prefix).unwrap_or(name);
+    }
+    name = name.strip_prefix("github.com/").unwrap_or(name).trim_end_matches('/');
+    name = name.strip_suffix(".git").unwrap_or(name).trim_end_matches('/');
+    let part = |p: &str| !p.is_empty() && p != "." && p != ".." && p.chars().all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c));
+    match name.split_once('/') {
+        Some((owner, repo)) if part(owner) && part(repo) => Ok(format!("{}/{}", owner, repo)),
+        Some(_) => Err(format!("`{}` is not an owner/repo name", raw.trim())),
+        None => Err(format!("`{}` has no owner", raw.trim())),
+    }
+}
+
+/// The names to deserialize rows by, and whether `first` is a data row rather than a header
+/// (never with `header`). Overridden columns are renamed to the names `RepoRow` knows.
+/// Columns no predicate reads are renamed too, so their values are never parsed.
+fn column_names(first: &StringRecord, header: bool, opts: &FilterOptions, needed: &[(String, &str)]) -> anyhow::Result<(StringRecord, bool)> {
+    let overrides = [(&opts.name_column, "name"), (&opts.toml_column, "has_cargo_toml"), (&opts.lock_column, "has_cargo_lock")];
+    let is_header = header || first.iter().any(|f| {
+        let f = f.trim();
+        f.eq_ignore_ascii_case("id")
+            || ALIASES.iter().flat_map(|(_, a)| a.iter()).any(|k| f.eq_ignore_ascii_case(k))
+            || overrides.iter().any(|(o, _)| o.as_deref() == Some(f))
+    });
+    let mut names: Vec<String> = if is_header {
+        first.iter().map(|f| f.trim().to_ascii_lowercase()).collect()
+    } else {
+        (0..first.len()).map(|i| POSITIONAL.get(i).map_or_else(|| format!("column_{}", i), |p| p.to_string())).collect()
+    };
+    for (column, canonical) in overrides {
+        let Some(column) = column else { continue };
+        let index = match is_header {
+            true => first.iter().position(|f| f.trim() == column),
+            false => column.parse::<usize>().ok().filter(|&i| i < first.len()),
+        };
+        let index = index.with_context(|| format!("column `{}` not found (columns: {})", column, first.iter().collect::<Vec<_>>().join(", ")))?;
+        // The column it replaces stops counting, so e.g. `name` next to `--name-column full_name` isn't a duplicate.
+        for n in names.iter_mut().filter(|n| known_as(n, canonical)) {
+            *n = format!("_{}", n);
+        }
+        names[index] = canonical.to_string();
+    }
+    // With several aliases of one column (`name` and `full_name`), the first one is read.
+    for (canonical, _) in ALIASES {
+        for n in names.iter_mut().filter(|n| known_as(n, canonical)).skip(1) {
+            *n = format!("_{}", n);
+        }
+    }
+    if !names.iter().any(|n| known_as(n, "name")) {
+        anyhow::bail!("no repository name column (looked for name, full_name, repo_name); pass --name-column");
+    }
+    // The rest of ALIASES are the columns of the predicates and strata, ignored unless a flag reads them.
+    for (canonical, _) in &ALIASES[3..] {
+        if !needed.iter().any(|(_, column)| column == canonical) {
+            for n in names.iter_mut().filter(|n| known_as(n, canonical)) {
+                *n = format!("_{}", n);
+            }
+        }
+    }
+    Ok((StringRecord::from(names), !is_header))
+}
+
+/// Fails unless `headers` (from `column_names`) has every column a predicate or `--stratify-by` reads.
+fn require_columns(headers: &StringRecord, first: &StringRecord, needed: &[(String, &str)]) -> anyhow::Result<()> {
+    for (flag, canonical) in needed {
+        if !headers.iter().any(|n| known_as(n, canonical)) {
+            let aliases = ALIASES.iter().find(|(c, _)| c == canonical).map_or(&[][..], |(_, a)| a);
+            anyhow::bail!("{} needs a {} column (looked for {}; columns: {})", flag, canonical, aliases.join(", "), first.iter().collect::<Vec<_>>().join(", "));
+        }
+    }
+    Ok(())
+}
+
+/// Whether the (lowercased) header `name` is one of the names `RepoRow` reads as `canonical`.
+fn known_as(name: &str, canonical: &str) -> bool {
+    ALIASES.iter().any(|(c, aliases)| *c == canonical && aliases.contains(&name))
+}
+
+fn write_names(output: &str, names: &[String]) -> anyhow::Result<()> {
+    let mut w = BufWriter::new(File::create(output)?);
+    for name in names {
+        writeln!(w, "{}", name)?;
+    }
+    w.flush()?;
+    Ok(())
+}
@@ -0,0 +1,76 @@
+//! Heuristics flagging collected files as generated or vendored (`CodeEntry::generated`,
+//! `vendored`, `collect --skip-generated`/`--skip-vendored`), with per-repository counts in
+//! `flagged_files.jsonl` to audit them by.
+
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Written next to the code file: what each repository had flagged, and why.
+pub const FLAGGED_FILES_FILE: &str = "flagged_files.jsonl";
+
+/// Text of the header comments of generated files (bindgen, prost, tonic, Cargo.lock, ...),
+/// matched case-insensitively in a file's first `HEADER_LINES` lines.
+pub const DEFAULT_GENERATED_MARKERS: &[&str] = &["@generated", "automatically generated", "auto-generated", "this file is generated", "this file was generated", "code generated by", "do not edit"];
+
+/// Directories whose files are copies of other projects' code.
+pub const VENDORED_DIRS: &[&str] = &["vendor", "vendored", "third_party", "third-party"];
+
+/// File name endings of generated Rust.
+const GENERATED_SUFFIXES: &[&str] = &["_generated.rs", ".pb.rs"];
+
+const HEADER_LINES: usize = 10;
+
+/// `#[automatically_derived]` impls per line from which a file is generated, as expanded macro
+/// output is.
+const DERIVED_PER_LINE: f64 = 1.0 / 50.0;
+const MIN_DERIVED: usize = 5;
+
+/// Why a file is generated, by the first heuristic that matches: `path` (its name), `marker`
+/// (a header comment, `markers` included) or `automatically_derived`.
+pub fn generated_by(path: &Path, content: &str, markers: &[String]) -> Option<&'static str> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    if GENERATED_SUFFIXES.iter().any(|s| name.ends_with(s)) {
+        return Some("path");
+    }
+    let header = content.lines().take(HEADER_LINES).collect::<Vec<_>>().join("\n").to_lowercase();
+    if DEFAULT_GENERATED_MARKERS.iter().copied().chain(markers.iter().map(String::as_str)).any(|m| header.contains(&m.to_lowercase())) {
+        return Some("marker");
+    }
+    let derived = content.matches("#[automatically_derived]").count();
+    if derived >= MIN_DERIVED && derived as f64 >= content.lines().count() as f64 * DERIVED_PER_LINE {
+        return Some("automatically_derived");
+    }
+    None
+}
+
+/// Whether `path`, relative to the checkout, is under one of `VENDORED_DIRS`.
+pub fn is_vendored(path: &Path) -> bool {
+    path.parent().is_some_and(|dir| dir.components().any(|c| matches!(c, Component::Normal(n) if VENDORED_DIRS.iter().any(|v| n == *v))))
+}
+
+/// One line of `flagged_files.jsonl`, for a repository with at least one flagged file.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct FlaggedFiles {
+    /// As in `CodeEntry::name`
+    pub name: String,
+    pub dir: String,
+    pub generated: usize,
+    pub vendored: usize,
+    /// Generated files by heuristic, as in `CodeEntry::generated_by`
+    pub generated_by: BTreeMap<String, usize>,
+    /// Of those, dropped by `--skip-generated` or `--skip-vendored`
+    pub skipped: usize,
+}
+
+impl FlaggedFiles {
+    pub fn is_empty(&self) -> bool {
+        self.generated == 0 && self.vendored == 0
+    }
+}
+
+/// Where `collect` writes the flagged files of `code_file`.
+pub fn flagged_files_beside(code_file: &str) -> PathBuf {
+    Path::new(code_file).parent().unwrap_or(Path::new("")).join(FLAGGED_FILES_FILE)
+}
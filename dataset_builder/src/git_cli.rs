@@ -0,0 +1,156 @@
+//! The `cli` git backend: clones and fetches run by the `git` program, for partial clones and the
+//! servers libgit2 has trouble with.
+
+use std::path::Path;
+use std::process::Command;
+
+use base64::Engine;
+use git2::{ErrorClass, ErrorCode};
+
+use crate::clone::{AuthMode, AuthOptions, GitAuth, HistoryOptions, Submodules};
+
+/// The arguments of `git clone` from `url` into `dest`. Credentials are never among them; they
+/// are passed by `git_env`.
+pub fn clone_args(url: &str, dest: &Path, history: &HistoryOptions) -> Vec<String> {
+    let mut args = vec!["clone".to_string(), "--quiet".into()];
+    if history.depth > 0 {
+        args.push(format!("--depth={}", history.depth));
+    }
+    // `git clone --depth` implies --single-branch, unlike libgit2, which fetches every branch.
+    args.push(if history.single_branch { "--single-branch" } else { "--no-single-branch" }.into());
+    if let Some(filter) = history.filter {
+        args.push(format!("--filter={}", filter.spec()));
+    }
+    args.extend(["--".into(), url.into(), dest.display().to_string()]);
+    args
+}
+
+/// The arguments of `git fetch` of `refspecs` from origin: `depth` 0 fetches without a limit,
+/// and `None` the whole history of a shallow checkout.
+pub fn fetch_args(refspecs: &[&str], depth: Option<u32>) -> Vec<String> {
+    let mut args = vec!["fetch".to_string(), "--quiet".into()];
+    match depth {
+        Some(0) => {}
+        Some(depth) => args.push(format!("--depth={}", depth)),
+        None => args.push("--unshallow".into()),
+    }
+    args.push("origin".into());
+    args.extend(refspecs.iter().map(|r| r.to_string()));
+    args
+}
+
+/// The environment `git` runs with. The token goes in an `http.<url>.extraHeader` scoped to its
+/// host, set through `GIT_CONFIG_*` variables, so it shows neither in the process list nor in
+/// `.git/config`. Prompts are disabled, so a missing credential fails instead of hanging.
+pub fn git_env(opts: &AuthOptions, token: Option<&str>) -> Vec<(String, String)> {
+    let mut env = vec![("GIT_TERMINAL_PROMPT".to_string(), "0".to_string())];
+    let mut config = Vec::new();
+    match (opts.auth, token) {
+        (AuthMode::Token, Some(token)) => {
+            config.push((format!("http.{}/.extraHeader", opts.token_origin()), format!("Authorization: Basic {}", base64::engine::general_purpose::STANDARD.encode(format!("x-access-token:{}", token)))));
+            config.push(("credential.helper".to_string(), String::new()));
+        }
+        (AuthMode::Token | AuthMode::None, _) => config.push(("credential.helper".to_string(), String::new())),
+        (AuthMode::Ssh, _) => {
+            let key = opts.ssh_key.as_deref().map_or(String::new(), |key| format!(" -i '{}' -o IdentitiesOnly=yes", key.replace('\'', "'\\''")));
+            env.push(("GIT_SSH_COMMAND".to_string(), format!("ssh -o BatchMode=yes{}", key)));
+        }
+        (AuthMode::CredentialHelper, _) => {}
+    }
+    env.push(("GIT_CONFIG_COUNT".to_string(), config.len().to_string()));
+    for (i, (key, value)) in config.into_iter().enumerate() {
+        env.push((format!("GIT_CONFIG_KEY_{}", i), key));
+        env.push((format!("GIT_CONFIG_VALUE_{}", i), value));
+    }
+    env
+}
+
+/// Clones `url` into `dest` with `git clone`.
+pub(crate) fn clone(url: &str, dest: &Path, auth: GitAuth, history: &HistoryOptions) -> Result<(), git2::Error> {
+    git(None, &clone_args(url, dest, history), auth).map(drop)
+}
+
+/// Fetches the remote HEAD into the checkout at `dest` and hard-resets the working tree to it.
+pub(crate) fn update(dest: &Path, auth: GitAuth, history: &HistoryOptions) -> Result<(), git2::Error> {
+    git(Some(dest), &fetch_args(&["HEAD"], Some(history.depth)), auth)?;
+    git(Some(dest), &["reset".into(), "--quiet".into(), "--hard".into(), "FETCH_HEAD".into()], auth).map(drop)
+}
+
+/// Checks out `rev` with a detached HEAD, fetching it the way `clone::checkout_rev` does when the
+/// checkout doesn't have it.
+pub(crate) fn checkout_rev(dest: &Path, name: &str, rev: &str, auth: GitAuth, history: &HistoryOptions) -> anyhow::Result<()> {
+    let find = || git(Some(dest), &["rev-parse".into(), "--verify".into(), "--quiet".into(), format!("{}^{{commit}}", rev)], auth).ok().map(|sha| sha.trim().to_string());
+    let commit = match find() {
+        Some(sha) => sha,
+        None => {
+            let is_sha = rev.len() == 40 && rev.bytes().all(|b| b.is_ascii_hexdigit());
+            let wanted = if is_sha { rev.to_string() } else { format!("+refs/tags/{0}:refs/tags/{0}", rev) };
+            if let Err(e) = git(Some(dest), &fetch_args(&[&wanted], Some(history.depth)), auth) {
+                tracing::debug!(repo = name, "fetching {} alone failed ({}); fetching the whole history", rev, e.message());
+            }
+            match find() {
+                Some(sha) => sha,
+                None => {
+                    let shallow = dest.join(".git/shallow").exists();
+                    let all = ["+refs/heads/*:refs/remotes/origin/*", "+refs/tags/*:refs/tags/*"];
+                    git(Some(dest), &fetch_args(&all, if shallow { None } else { Some(0) }), auth).map_err(|e| auth.error(e, name))?;
+                    find().ok_or_else(|| anyhow::anyhow!("{} has no revision `{}`", name, rev))?
+                }
+            }
+        }
+    };
+    git(Some(dest), &["checkout".into(), "--quiet".into(), "--force".into(), "--detach".into(), commit], auth)?;
+    Ok(())
+}
+
+/// Initializes and checks out the submodules of the checkout at `dest`, recursively.
+pub(crate) fn update_submodules(dest: &Path, auth: GitAuth, mode: Submodules) -> Result<(), git2::Error> {
+    let mut args = vec!["submodule".to_string(), "update".into(), "--quiet".into(), "--init".into(), "--recursive".into()];
+    if mode == Submodules::Shallow {
+        args.push("--depth=1".into());
+    }
+    git(Some(dest), &args, auth).map(drop)
+}
+
+/// Whether `git` can be run at all.
+pub(crate) fn available() -> bool {
+    Command::new("git").arg("--version").output().is_ok_and(|o| o.status.success())
+}
+
+/// Runs `git` in `dir`, returning its stdout, or its stderr as an error.
+fn git(dir: Option<&Path>, args: &[String], auth: GitAuth) -> Result<String, git2::Error> {
+    let mut cmd = Command::new("git");
+    if let Some(dir) = dir {
+        cmd.arg("-C").arg(dir);
+    }
+    let output = cmd.args(args).envs(git_env(auth.opts, auth.token)).output().map_err(|e| {
+        git2::Error::new(ErrorCode::GenericError, ErrorClass::Os, format!("failed to run git ({}); --git-backend cli needs git on the PATH", e))
+    })?;
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        false => Err(cli_error(&String::from_utf8_lossy(&output.stderr))),
+    }
+}
+
+/// A failed `git` run as a `git2::Error`, so that it is classified and explained like libgit2's.
+fn cli_error(stderr: &str) -> git2::Error {
+    let lower = stderr.to_ascii_lowercase();
+    let message = stderr.lines().map(str::trim).filter(|l| !l.is_empty()).collect::<Vec<_>>().join("; ");
+    let status: Option<u16> = lower.split_once("returned error: ").and_then(|(_, rest)| rest.get(..3)?.parse().ok());
+    let auth = ["authentication failed", "could not read username", "could not read password", "permission denied (publickey"];
+    let network = ["could not resolve host", "failed to connect", "timed out", "connection reset", "early eof", "could not read from remote repository"];
+    let (code, class) = match status {
+        Some(401 | 403) => (ErrorCode::Auth, ErrorClass::Http),
+        _ if auth.iter().any(|m| lower.contains(m)) => (ErrorCode::Auth, ErrorClass::Http),
+        Some(_) => (ErrorCode::GenericError, ErrorClass::Http),
+        None if lower.contains("not found") => (ErrorCode::NotFound, ErrorClass::Http),
+        None if network.iter().any(|m| lower.contains(m)) => (ErrorCode::GenericError, ErrorClass::Net),
+        None => (ErrorCode::GenericError, ErrorClass::Os),
+    };
+    let message = match status {
+        // Spelled the way `CloneErrorClass::of` reads libgit2's.
+        Some(status) => format!("{} (http status code: {})", message, status),
+        None => message,
+    };
+    git2::Error::new(code, class, format!("git: {}", message))
+}
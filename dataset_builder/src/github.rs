@@ -0,0 +1,258 @@
+//! A GitHub REST API client that waits out rate limits and retries failures, shared by
+//! `discover` and `metadata`, and the tokens it and the clones rotate through.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::shutdown::{shutdown_requested, sleep_unless_shutdown as sleep};
+
+/// What the last response said of a token's rate limit window.
+#[derive(Clone, Copy, Default)]
+struct Quota {
+    remaining: Option<u64>,
+    reset: Option<Instant>,
+}
+
+impl Quota {
+    fn drained(&self, now: Instant) -> bool {
+        self.remaining == Some(0) && self.reset.is_some_and(|reset| reset > now)
+    }
+}
+
+/// The GitHub tokens of a run. API requests keep to one token until GitHub says it has no
+/// requests left, then move to the next one with some; clones take them in turn, one per
+/// repository. Tokens are never printed: they are told apart by their index, from 1, in logs and
+/// errors, and `Debug` shows only how many there are.
+#[derive(Clone)]
+pub struct Tokens {
+    tokens: Arc<Vec<String>>,
+    /// Per token, or for anonymous requests when there is none
+    quotas: Arc<Mutex<Vec<Quota>>>,
+    current: Arc<AtomicUsize>,
+    next_clone: Arc<AtomicUsize>,
+}
+
+impl Default for Tokens {
+    /// No token: anonymous requests and clones.
+    fn default() -> Self {
+        Tokens::new(Vec::new())
+    }
+}
+
+impl fmt::Debug for Tokens {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Tokens({})", self.tokens.len())
+    }
+}
+
+impl Tokens {
+    /// `tokens` without blanks and repeats, in order.
+    pub fn new<I: IntoIterator<Item = String>>(tokens: I) -> Self {
+        let mut seen = HashSet::new();
+        let tokens: Vec<String> = tokens.into_iter().map(|t| t.trim().to_string()).filter(|t| !t.is_empty() && seen.insert(t.clone())).collect();
+        let quotas = vec![Quota::default(); tokens.len().max(1)];
+        Tokens { tokens: Arc::new(tokens), quotas: Arc::new(Mutex::new(quotas)), current: Arc::default(), next_clone: Arc::default() }
+    }
+
+    /// `GITHUB_TOKEN` (given as `token`), the comma-separated `GITHUB_TOKENS`, the lines of
+    /// `token_file` and those `token_cmd` prints, together; a `#` line of the file is a comment.
+    pub fn load(token: Option<&str>, token_file: Option<&str>, token_cmd: Option<&str>) -> anyhow::Result<Self> {
+        let mut tokens: Vec<String> = token.map(String::from).into_iter().collect();
+        tokens.extend(std::env::var("GITHUB_TOKENS").unwrap_or_default().split(',').map(String::from));
+        if let Some(file) = token_file {
+            let text = fs::read_to_string(file).with_context(|| format!("failed to read {}", file))?;
+            tokens.extend(text.lines().filter(|l| !l.trim_start().starts_with('#')).map(String::from));
+        }
+        if let Some(cmd) = token_cmd {
+            tokens.extend(token_from_command(cmd)?);
+        }
+        Ok(Tokens::new(tokens))
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.tokens.iter().map(String::as_str)
+    }
+
+    /// The token of the next repository to clone, in turn; `None` without tokens.
+    pub(crate) fn for_clone(&self) -> Option<&str> {
+        let n = self.next_clone.fetch_add(1, Ordering::Relaxed);
+        self.tokens.get(n % self.tokens.len().max(1)).map(String::as_str)
+    }
+
+    /// `token 2 of 3`, to name a token without showing it.
+    pub(crate) fn label(&self, index: usize) -> String {
+        match self.tokens.len() {
+            0 | 1 => "the token".into(),
+            n => format!("token {} of {}", index + 1, n),
+        }
+    }
+
+    /// The index of the token for the next API request: the current one, or if GitHub said it is
+    /// out of requests the next one that isn't; when all are, how long until the first resets.
+    fn pick(&self) -> Result<usize, Duration> {
+        let quotas = self.quotas.lock().unwrap();
+        let now = Instant::now();
+        let current = self.current.load(Ordering::Relaxed);
+        let n = quotas.len();
+        match (0..n).map(|i| (current + i) % n).find(|i| !quotas[*i].drained(now)) {
+            Some(i) => {
+                if i != current {
+                    tracing::info!("{} is out of API requests; switching to {}", self.label(current), self.label(i));
+                    self.current.store(i, Ordering::Relaxed);
+                }
+                Ok(i)
+            }
+            None => Err(quotas.iter().filter_map(|q| q.reset).min().map_or(Duration::from_secs(60), |reset| reset.saturating_duration_since(now))),
+        }
+    }
+
+    /// Keeps what `response` says of the rate limit of token `index`.
+    fn record(&self, index: usize, response: &ureq::Response) {
+        let remaining = response.header("x-ratelimit-remaining").and_then(|r| r.trim().parse().ok());
+        if remaining.is_none() {
+            return;
+        }
+        let reset = if remaining == Some(0) { Some(Instant::now() + reset_wait(response).unwrap_or(Duration::from_secs(60))) } else { None };
+        self.quotas.lock().unwrap()[index] = Quota { remaining, reset };
+    }
+
+    fn all_drained(&self) -> bool {
+        let now = Instant::now();
+        self.quotas.lock().unwrap().iter().all(|q| q.drained(now))
+    }
+}
+
+/// The tokens `cmd` (run by the shell, e.g. `gh auth token`) prints, one per line. Its output
+/// isn't repeated in the error when it fails, only its status and what it wrote to stderr.
+fn token_from_command(cmd: &str) -> anyhow::Result<Vec<String>> {
+    let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let out = std::process::Command::new(shell).args([flag, cmd]).stdin(std::process::Stdio::null()).output().with_context(|| format!("failed to run --token-cmd `{}`", cmd))?;
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    anyhow::ensure!(out.status.success(), "--token-cmd `{}` failed ({}): {}", cmd, out.status, stderr.lines().next().unwrap_or_default());
+    let tokens: Vec<String> = String::from_utf8_lossy(&out.stdout).lines().map(|l| l.trim().to_string()).filter(|l| !l.is_empty()).collect();
+    anyhow::ensure!(!tokens.is_empty(), "--token-cmd `{}` printed no token", cmd);
+    Ok(tokens)
+}
+
+pub(crate) struct GithubClient<'a> {
+    agent: ureq::Agent,
+    api_url: &'a str,
+    tokens: &'a Tokens,
+    retries: u32,
+    /// Requests sent, retries included
+    pub(crate) requests: usize,
+}
+
+/// A repository's `license`, as the search and repository endpoints return it.
+#[derive(Deserialize)]
+pub(crate) struct License {
+    spdx_id: Option<String>,
+}
+
+impl License {
+    /// The SPDX id, unless GitHub couldn't tell (`NOASSERTION`).
+    pub(crate) fn spdx(license: Option<License>) -> Option<String> {
+        license.and_then(|l| l.spdx_id).filter(|id| id != "NOASSERTION")
+    }
+}
+
+impl<'a> GithubClient<'a> {
+    pub(crate) fn new(api_url: &'a str, tokens: &'a Tokens, retries: u32) -> Self {
+        let agent = ureq::AgentBuilder::new().timeout(Duration::from_secs(60)).build();
+        GithubClient { agent, api_url: api_url.trim_end_matches('/'), tokens, retries, requests: 0 }
+    }
+
+    /// GETs `path`, moving to another token when one runs out of requests, waiting out rate
+    /// limits once every token has, and retrying failures; `None` on 404. A 304 (for an
+    /// `If-None-Match` header) is returned like a 200.
+    pub(crate) fn get(&mut self, path: &str, query: &[(&str, &str)], headers: &[(&str, &str)]) -> anyhow::Result<Option<ureq::Response>> {
+        let mut attempt = 0;
+        loop {
+            let index = match self.tokens.pick() {
+                Ok(index) => index,
+                Err(wait) => {
+                    anyhow::ensure!(!shutdown_requested(), "interrupted while waiting for the rate limit to reset");
+                    let all = if self.tokens.len() > 1 { format!("all {} tokens are", self.tokens.len()) } else { "GitHub's API is".to_string() };
+                    tracing::warn!("{} out of requests; waiting {}s for the first to reset", all, wait.as_secs());
+                    sleep(wait);
+                    continue;
+                }
+            };
+            let mut request = self.agent.get(&format!("{}{}", self.api_url, path)).set("User-Agent", "dataset_builder").set("Accept", "application/vnd.github+json");
+            if let Some(token) = self.tokens.tokens.get(index) {
+                request = request.set("Authorization", &format!("Bearer {}", token));
+            }
+            for (k, v) in query {
+                request = request.query(k, v);
+            }
+            for (k, v) in headers {
+                request = request.set(k, v);
+            }
+            self.requests += 1;
+            let wait = match request.call() {
+                Ok(response) => {
+                    // The last request of a window is spent; the next one picks another token,
+                    // or waits for the reset.
+                    self.tokens.record(index, &response);
+                    return Ok(Some(response));
+                }
+                Err(ureq::Error::Status(404, _)) => return Ok(None),
+                Err(ureq::Error::Status(code @ (403 | 429), response)) => {
+                    let primary = response.header("x-ratelimit-remaining") == Some("0");
+                    if primary {
+                        self.tokens.record(index, &response);
+                        if !self.tokens.all_drained() {
+                            continue;
+                        }
+                    }
+                    let wait = retry_after(&response).or_else(|| reset_wait(&response).filter(|_| primary));
+                    match wait {
+                        Some(wait) => (wait, format!("rate limited ({})", code)),
+                        None if code == 429 || response.into_string().unwrap_or_default().contains("secondary rate limit") => (backoff(attempt), format!("secondary rate limit ({})", code)),
+                        None => anyhow::bail!("GitHub refused {} (403); check {}", path, self.tokens.label(index)),
+                    }
+                }
+                Err(ureq::Error::Status(422, response)) => anyhow::bail!("GitHub rejected the query: {}", response.into_string().unwrap_or_default()),
+                Err(ureq::Error::Status(code, _)) if code >= 500 => (backoff(attempt), format!("GitHub answered {}", code)),
+                Err(ureq::Error::Status(code, _)) => anyhow::bail!("GitHub answered {} for {}", code, path),
+                Err(e @ ureq::Error::Transport(_)) => (backoff(attempt), e.to_string()),
+            };
+            if attempt >= self.retries || shutdown_requested() {
+                anyhow::bail!("{} failed after {} attempts: {}", path, attempt + 1, wait.1);
+            }
+            attempt += 1;
+            tracing::warn!("{}; retrying {} in {}s (attempt {} of {})", wait.1, path, wait.0.as_secs(), attempt, self.retries);
+            sleep(wait.0);
+        }
+    }
+}
+
+fn retry_after(response: &ureq::Response) -> Option<Duration> {
+    response.header("retry-after")?.trim().parse().ok().map(Duration::from_secs)
+}
+
+/// Until `x-ratelimit-reset`, the epoch second the window resets at.
+fn reset_wait(response: &ureq::Response) -> Option<Duration> {
+    let reset: i64 = response.header("x-ratelimit-reset")?.trim().parse().ok()?;
+    Some(Duration::from_secs((reset - Utc::now().timestamp()).clamp(0, 3600) as u64 + 1))
+}
+
+pub(crate) fn backoff(attempt: u32) -> Duration {
+    Duration::from_secs(2u64 << attempt.min(6))
+}
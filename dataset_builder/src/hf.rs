@@ -0,0 +1,405 @@
+//! `export-hf`: the code and outputs files as a Hugging Face dataset directory, Parquet shards
+//! with `dataset_infos.json` and a README dataset card, and its upload to the Hub.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufRead, Read};
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Context;
+use arrow::datatypes::{DataType, Field, Schema};
+use base64::Engine;
+use clap::Args;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::columnar::{DatasetKind, ParquetWriter, DEFAULT_ROW_GROUP_ROWS};
+use crate::github::backoff;
+use crate::jsonl::open_jsonl;
+use crate::license::UNKNOWN_LICENSE;
+use crate::shutdown::{shutdown_requested, sleep_unless_shutdown as sleep};
+use crate::split::{SplitMap, SPLITS};
+
+pub const DEFAULT_HF_DIR: &str = "hf_dataset";
+pub const DEFAULT_HF_ENDPOINT: &str = "https://huggingface.co";
+
+/// Rows per Parquet shard; the Hub's viewer handles shards of a few hundred MB best.
+pub const DEFAULT_HF_SHARD_ROWS: usize = 100_000;
+
+pub const DATASET_INFOS_FILE: &str = "dataset_infos.json";
+
+#[derive(Args, Clone, Debug)]
+pub struct ExportHfOptions {
+    /// The code file written by `collect`, as the `code` config
+    #[arg(long, value_name = "FILE", required_unless_present = "outputs")]
+    pub code: Option<String>,
+    /// The outputs file written by `outputs`, as the `outputs` config; the tool versions of the
+    /// card are read from the `manifest.json` beside it
+    #[arg(long, value_name = "FILE")]
+    pub outputs: Option<String>,
+    /// The `splits.json` written by `split`, to put each repository's entries in its split
+    /// (default: everything in `train`)
+    #[arg(long, value_name = "FILE")]
+    pub splits: Option<String>,
+    /// The dataset directory
+    #[arg(long, value_name = "DIR", default_value = DEFAULT_HF_DIR)]
+    pub out: String,
+    /// Rows per Parquet shard
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_HF_SHARD_ROWS)]
+    pub shard_rows: usize,
+    /// Push the directory to this dataset repository on the Hub, `org/name`, creating it
+    #[arg(long, value_name = "ORG/NAME")]
+    pub repo_id: Option<String>,
+    /// Where to read the Hub token: `env:NAME`, an environment variable, or `file:PATH`, a file
+    /// holding it; the token itself is refused, since it stays in the shell history and shows in `ps`
+    #[arg(long, value_name = "env:NAME|file:PATH", default_value = "env:HF_TOKEN")]
+    pub hf_token: String,
+    /// Create the repository private
+    #[arg(long, requires = "repo_id")]
+    pub private: bool,
+    #[arg(long, value_name = "URL", env = "HF_ENDPOINT", default_value = DEFAULT_HF_ENDPOINT)]
+    pub hf_endpoint: String,
+    /// Retries of each request of the push after a network error, a 5xx or a 429, with
+    /// exponential backoff
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub retries: u32,
+}
+
+impl Default for ExportHfOptions {
+    fn default() -> Self {
+        ExportHfOptions {
+            code: None,
+            outputs: None,
+            splits: None,
+            out: DEFAULT_HF_DIR.to_string(),
+            shard_rows: DEFAULT_HF_SHARD_ROWS,
+            repo_id: None,
+            hf_token: "env:HF_TOKEN".to_string(),
+            private: false,
+            hf_endpoint: DEFAULT_HF_ENDPOINT.to_string(),
+            retries: 3,
+        }
+    }
+}
+
+/// A split of a config as written.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HfSplit {
+    pub rows: usize,
+    /// Of the JSON lines, as `num_bytes` in `dataset_infos.json`
+    pub bytes: u64,
+    /// Relative to the dataset directory
+    pub shards: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct HfSummary {
+    /// By config (`code`, `outputs`) and split
+    pub splits: BTreeMap<String, BTreeMap<String, HfSplit>>,
+    /// Shards uploaded by the push, and those the Hub had already
+    pub uploaded: usize,
+    pub already_uploaded: usize,
+}
+
+/// The `datasets` feature of an Arrow column type.
+fn feature(t: &DataType) -> Value {
+    let value = |dtype: &str| json!({"dtype": dtype, "_type": "Value"});
+    match t {
+        DataType::Utf8 | DataType::LargeUtf8 => value("string"),
+        DataType::Boolean => value("bool"),
+        DataType::UInt32 => value("uint32"),
+        DataType::UInt64 => value("uint64"),
+        DataType::Int64 => value("int64"),
+        DataType::Float64 => value("float64"),
+        DataType::List(f) => json!({"feature": feature(f.data_type()), "_type": "Sequence"}),
+        DataType::Struct(fields) => fields.iter().map(|f| (f.name().clone(), feature(f.data_type()))).collect::<serde_json::Map<_, _>>().into(),
+        other => value(&other.to_string().to_ascii_lowercase()),
+    }
+}
+
+pub fn hf_features(schema: &Schema) -> Value {
+    schema.fields().iter().map(|f: &std::sync::Arc<Field>| (f.name().clone(), feature(f.data_type()))).collect::<serde_json::Map<_, _>>().into()
+}
+
+#[derive(Deserialize)]
+struct Named {
+    name: String,
+    #[serde(default)]
+    license_spdx: Option<String>,
+}
+
+/// Writes the entries of `file` as `data/<config>/<split>-<n>-of-<total>.parquet`, counting the
+/// repositories of each license along the way.
+fn write_config(file: &str, kind: DatasetKind, config: &str, opts: &ExportHfOptions, splits: Option<&SplitMap>, licenses: &mut BTreeMap<String, String>) -> anyhow::Result<BTreeMap<String, HfSplit>> {
+    let dir = Path::new(&opts.out).join("data").join(config);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).with_context(|| format!("failed to replace {}", dir.display()))?;
+    }
+    fs::create_dir_all(&dir)?;
+    let mut written: BTreeMap<String, HfSplit> = BTreeMap::new();
+    // With the rows of the shard open for each split
+    let mut open: BTreeMap<String, (ParquetWriter, usize)> = BTreeMap::new();
+    let shard = |split: &str, n: usize| dir.join(format!("{}-{:05}.parquet", split, n));
+    for (i, line) in open_jsonl(file)?.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row: Value = serde_json::from_str(&line).with_context(|| format!("{}:{}: not JSON; `validate --repair` drops bad lines", file, i + 1))?;
+        let named: Named = serde_json::from_value(row.clone()).with_context(|| format!("{}:{}: no `name`", file, i + 1))?;
+        if let Some(l) = named.license_spdx {
+            licenses.insert(named.name.clone(), l);
+        }
+        let split = match splits {
+            Some(m) => m.repos.get(&named.name).with_context(|| format!("{}:{}: {} isn't in --splits", file, i + 1, named.name))?.clone(),
+            None => SPLITS[0].to_string(),
+        };
+        let s = written.entry(split.clone()).or_default();
+        if open.get(&split).is_some_and(|(_, rows)| *rows >= opts.shard_rows.max(1)) {
+            open.remove(&split).expect("open").0.finish()?;
+        }
+        let (w, rows) = match open.get_mut(&split) {
+            Some(w) => w,
+            None => {
+                let path = shard(&split, s.shards.len());
+                s.shards.push(path.display().to_string());
+                open.entry(split.clone()).or_insert((ParquetWriter::new(File::create(&path).with_context(|| format!("failed to write {}", path.display()))?, kind, DEFAULT_ROW_GROUP_ROWS)?, 0))
+            }
+        };
+        w.write_entry(&row).with_context(|| format!("failed to convert {}", file))?;
+        *rows += 1;
+        s.rows += 1;
+        s.bytes += line.len() as u64 + 1;
+    }
+    for (_, (w, _)) in open {
+        w.finish()?;
+    }
+    // The shard names carry their count, known now.
+    for (split, s) in &mut written {
+        let total = s.shards.len();
+        for (n, path) in s.shards.iter_mut().enumerate() {
+            let name = format!("{}-{:05}-of-{:05}.parquet", split, n, total);
+            fs::rename(&*path, dir.join(&name))?;
+            *path = format!("data/{}/{}", config, name);
+        }
+    }
+    Ok(written)
+}
+
+/// The README dataset card: YAML front matter with the configs' files, then split sizes, the
+/// license distribution and the tool versions.
+fn dataset_card(name: &str, summary: &BTreeMap<String, BTreeMap<String, HfSplit>>, licenses: &BTreeMap<String, String>, tools: Option<&Value>) -> String {
+    let mut by_license: BTreeMap<&str, usize> = BTreeMap::new();
+    for l in licenses.values() {
+        *by_license.entry(l.as_str()).or_default() += 1;
+    }
+    let license = match by_license.keys().collect::<Vec<_>>().as_slice() {
+        [only] if **only != UNKNOWN_LICENSE && !only.contains(' ') => only.to_ascii_lowercase(),
+        _ => "other".to_string(),
+    };
+    let mut card = String::from("---\nconfigs:\n");
+    for (config, splits) in summary {
+        card += &format!("- config_name: {}\n  data_files:\n", config);
+        for split in splits.keys() {
+            card += &format!("  - split: {}\n    path: data/{}/{}-*.parquet\n", split, config, split);
+        }
+    }
+    card += &format!("license: {}\ntags:\n- code\n- rust\n- static-analysis\n---\n\n# {}\n\n", license, name);
+    card += &format!("Rust repositories with their source files and static analysis results, built by dataset_builder {}.\n\n", env!("CARGO_PKG_VERSION"));
+    card += "## Splits\n\n| Config | Split | Rows | Shards |\n|---|---|---:|---:|\n";
+    for (config, splits) in summary {
+        for (split, s) in splits {
+            card += &format!("| {} | {} | {} | {} |\n", config, split, s.rows, s.shards.len());
+        }
+    }
+    if !by_license.is_empty() {
+        card += "\n## Licenses\n\nRepositories per license, as detected by `collect`. Each file's `license_spdx` has its repository's.\n\n| License | Repositories |\n|---|---:|\n";
+        for (l, n) in &by_license {
+            card += &format!("| {} | {} |\n", l, n);
+        }
+    }
+    if let Some(Value::Object(versions)) = tools {
+        card += "\n## Tools\n\nThe analyzers of the `outputs` config, from the run's `manifest.json`.\n\n| Tool | Version |\n|---|---|\n";
+        for (tool, v) in versions {
+            card += &format!("| {} | {} |\n", tool, v.as_str().unwrap_or("not installed"));
+        }
+    }
+    card
+}
+
+/// Writes the dataset directory, then pushes it when `--repo-id` is given.
+pub fn export_hf(opts: &ExportHfOptions) -> anyhow::Result<HfSummary> {
+    anyhow::ensure!(opts.code.is_some() || opts.outputs.is_some(), "nothing to export: pass --code, --outputs or both");
+    let token = opts.repo_id.as_ref().map(|_| resolve_token(&opts.hf_token)).transpose()?;
+    let splits = match &opts.splits {
+        Some(p) => Some(serde_json::from_str::<SplitMap>(&fs::read_to_string(p).with_context(|| format!("failed to read {}", p))?).with_context(|| format!("{} isn't a splits.json", p))?),
+        None => None,
+    };
+    fs::create_dir_all(&opts.out).with_context(|| format!("failed to create {}", opts.out))?;
+    let mut summary = HfSummary::default();
+    let mut licenses = BTreeMap::new();
+    for (file, kind, config) in [(&opts.code, DatasetKind::Code, "code"), (&opts.outputs, DatasetKind::Outputs, "outputs")] {
+        if let Some(file) = file {
+            let written = write_config(file, kind, config, opts, splits.as_ref(), &mut licenses)?;
+            summary.splits.insert(config.to_string(), written);
+        }
+    }
+    let name = opts.repo_id.clone().unwrap_or_else(|| Path::new(&opts.out).file_name().map_or(DEFAULT_HF_DIR.into(), |n| n.to_string_lossy().into_owned()));
+    let mut infos = serde_json::Map::new();
+    for (config, splits) in &summary.splits {
+        let kind = if config == "code" { DatasetKind::Code } else { DatasetKind::Outputs };
+        let download: u64 = splits.values().flat_map(|s| &s.shards).map(|p| fs::metadata(Path::new(&opts.out).join(p)).map_or(0, |m| m.len())).sum();
+        let split_infos: serde_json::Map<String, Value> = splits.iter().map(|(s, info)| (s.clone(), json!({"name": s, "num_bytes": info.bytes, "num_examples": info.rows, "dataset_name": name}))).collect();
+        infos.insert(
+            config.clone(),
+            json!({"config_name": config, "features": hf_features(&kind.schema()), "splits": split_infos, "download_size": download, "dataset_size": splits.values().map(|s| s.bytes).sum::<u64>()}),
+        );
+    }
+    fs::write(Path::new(&opts.out).join(DATASET_INFOS_FILE), serde_json::to_string_pretty(&Value::Object(infos))? + "\n")?;
+    let manifest = opts.outputs.as_ref().and_then(|o| fs::read_to_string(Path::new(o).parent().unwrap_or(Path::new("")).join("manifest.json")).ok()).and_then(|t| serde_json::from_str::<Value>(&t).ok());
+    let tools = manifest.as_ref().map(|m| &m["tools"]["versions"]);
+    fs::write(Path::new(&opts.out).join("README.md"), dataset_card(&name, &summary.splits, &licenses, tools))?;
+    if let (Some(repo_id), Some(token)) = (&opts.repo_id, token) {
+        let hub = Hub { agent: ureq::AgentBuilder::new().timeout(Duration::from_secs(600)).build(), endpoint: opts.hf_endpoint.trim_end_matches('/').to_string(), token, retries: opts.retries };
+        let shards: Vec<String> = summary.splits.values().flat_map(|s| s.values()).flat_map(|s| s.shards.clone()).collect();
+        (summary.uploaded, summary.already_uploaded) = hub.push(repo_id, opts.private, Path::new(&opts.out), &shards)?;
+    }
+    Ok(summary)
+}
+
+/// `env:NAME` as the value of the variable, `file:PATH` as the file's first line. Anything else
+/// is refused without being repeated: it would be the token itself.
+pub fn resolve_token(spec: &str) -> anyhow::Result<String> {
+    if let Some(var) = spec.strip_prefix("env:") {
+        return std::env::var(var).with_context(|| format!("--hf-token reads ${}, which isn't set", var));
+    }
+    if let Some(path) = spec.strip_prefix("file:") {
+        let text = fs::read_to_string(path).with_context(|| format!("--hf-token reads {}, which can't be read", path))?;
+        let token = text.lines().next().unwrap_or_default().trim();
+        anyhow::ensure!(!token.is_empty(), "--hf-token reads {}, which is empty", path);
+        return Ok(token.to_string());
+    }
+    anyhow::bail!("refusing a Hugging Face token given as an argument, where it stays in the shell history and shows in `ps`; export HF_TOKEN, or use --hf-token env:NAME or --hf-token file:<path>")
+}
+
+#[derive(Clone, Copy)]
+enum Body<'a> {
+    Text(&'a str),
+    File(&'a Path),
+}
+
+struct Hub {
+    agent: ureq::Agent,
+    endpoint: String,
+    token: String,
+    retries: u32,
+}
+
+/// The sha256 of a file in hex: the oid of its LFS object.
+fn sha256(path: &Path) -> anyhow::Result<String> {
+    let mut file = File::open(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+impl Hub {
+    /// Sends `body` with the request `make` builds, retrying network errors, 5xx and 429 answers.
+    fn call(&self, what: &str, make: impl Fn() -> ureq::Request, body: Body) -> anyhow::Result<ureq::Response> {
+        let mut attempt = 0;
+        loop {
+            let sent = match body {
+                Body::Text(text) => make().send_string(text),
+                Body::File(path) => make().send(File::open(path).with_context(|| format!("failed to read {}", path.display()))?),
+            };
+            let error = match sent {
+                Ok(response) => return Ok(response),
+                Err(ureq::Error::Status(code, response)) if code >= 500 || code == 429 => format!("the Hub answered {}: {}", code, response.into_string().unwrap_or_default()),
+                Err(ureq::Error::Status(code, response)) => anyhow::bail!("{}: the Hub answered {}: {}", what, code, response.into_string().unwrap_or_default()),
+                Err(e @ ureq::Error::Transport(_)) => e.to_string(),
+            };
+            if attempt >= self.retries || shutdown_requested() {
+                anyhow::bail!("{} failed after {} attempts: {}", what, attempt + 1, error);
+            }
+            attempt += 1;
+            tracing::warn!("{}; retrying {} in {}s (attempt {} of {})", error, what, backoff(attempt - 1).as_secs(), attempt, self.retries);
+            sleep(backoff(attempt - 1));
+        }
+    }
+
+    fn auth(&self) -> String {
+        format!("Bearer {}", self.token)
+    }
+
+    /// Creates the dataset repository; one that exists already is fine.
+    fn create_repo(&self, repo_id: &str, private: bool) -> anyhow::Result<()> {
+        let (org, name) = repo_id.split_once('/').with_context(|| format!("--repo-id `{}` isn't org/name", repo_id))?;
+        let body = json!({"type": "dataset", "name": name, "organization": org, "private": private});
+        let url = format!("{}/api/repos/create", self.endpoint);
+        match self.call(&format!("creating {}", repo_id), || self.agent.post(&url).set("Authorization", &self.auth()).set("Content-Type", "application/json"), Body::Text(&body.to_string())) {
+            Ok(_) => Ok(()),
+            Err(e) if format!("{:#}", e).contains("answered 409") => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Uploads a shard as an LFS object, unless the Hub has it already; true when it was sent.
+    fn upload(&self, repo_id: &str, path: &Path, oid: &str, size: u64) -> anyhow::Result<bool> {
+        let url = format!("{}/datasets/{}.git/info/lfs/objects/batch", self.endpoint, repo_id);
+        let body = json!({"operation": "upload", "transfers": ["basic"], "hash_algo": "sha256", "objects": [{"oid": oid, "size": size}]});
+        let what = format!("uploading {}", path.display());
+        let response = self.call(&what, || self.agent.post(&url).set("Authorization", &self.auth()).set("Accept", "application/vnd.git-lfs+json").set("Content-Type", "application/vnd.git-lfs+json"), Body::Text(&body.to_string()))?;
+        let batch: Value = serde_json::from_reader(response.into_reader()).with_context(|| format!("{}: the LFS batch answer isn't JSON", what))?;
+        let object = &batch["objects"][0];
+        if let Some(e) = object.get("error") {
+            anyhow::bail!("{}: the Hub refused the object: {}", what, e);
+        }
+        let Some(upload) = object["actions"].get("upload") else { return Ok(false) };
+        let href = upload["href"].as_str().with_context(|| format!("{}: no upload href", what))?;
+        let headers = |action: &Value| action["header"].as_object().map(|h| h.iter().map(|(k, v)| (k.clone(), v.as_str().unwrap_or_default().to_string())).collect::<Vec<_>>()).unwrap_or_default();
+        let put = || headers(upload).iter().fold(self.agent.put(href).set("Content-Length", &size.to_string()), |r, (k, v)| r.set(k, v));
+        self.call(&what, put, Body::File(path))?;
+        if let Some(verify) = object["actions"].get("verify") {
+            let href = verify["href"].as_str().with_context(|| format!("{}: no verify href", what))?;
+            let post = || headers(verify).iter().fold(self.agent.post(href).set("Authorization", &self.auth()).set("Content-Type", "application/vnd.git-lfs+json"), |r, (k, v)| r.set(k, v));
+            self.call(&what, post, Body::Text(&json!({"oid": oid, "size": size}).to_string()))?;
+        }
+        Ok(true)
+    }
+
+    /// Creates the repository, uploads the shards the Hub doesn't have, and commits them with the
+    /// card and `dataset_infos.json` to `main`. A failed push can be run again: shards uploaded
+    /// before are skipped. Returns the shards uploaded and those skipped.
+    fn push(&self, repo_id: &str, private: bool, dir: &Path, shards: &[String]) -> anyhow::Result<(usize, usize)> {
+        self.create_repo(repo_id, private)?;
+        let (mut uploaded, mut skipped) = (0, 0);
+        let mut lines = vec![json!({"key": "header", "value": {"summary": format!("Upload dataset_builder export ({} shards)", shards.len()), "description": ""}})];
+        for (i, shard) in shards.iter().enumerate() {
+            let path = dir.join(shard);
+            let (oid, size) = (sha256(&path)?, fs::metadata(&path)?.len());
+            match self.upload(repo_id, &path, &oid, size)? {
+                true => uploaded += 1,
+                false => skipped += 1,
+            }
+            tracing::info!(event = "shard_pushed", shard = shard.as_str(), "shard {} of {}: {}", i + 1, shards.len(), shard);
+            lines.push(json!({"key": "lfsFile", "value": {"path": shard, "algo": "sha256", "oid": oid, "size": size}}));
+        }
+        for file in ["README.md", DATASET_INFOS_FILE] {
+            let content = base64::engine::general_purpose::STANDARD.encode(fs::read(dir.join(file))?);
+            lines.push(json!({"key": "file", "value": {"path": file, "content": content, "encoding": "base64"}}));
+        }
+        let body: String = lines.iter().map(|l| l.to_string() + "\n").collect();
+        let url = format!("{}/api/datasets/{}/commit/main", self.endpoint, repo_id);
+        self.call(&format!("committing to {}", repo_id), || self.agent.post(&url).set("Authorization", &self.auth()).set("Content-Type", "application/x-ndjson"), Body::Text(&body))?;
+        Ok((uploaded, skipped))
+    }
+}
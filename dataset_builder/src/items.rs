@@ -0,0 +1,266 @@
+//! Item- and function-level entries for `collect --granularity`, parsed with syn.
+
+use clap::ValueEnum;
+use proc_macro2::{LineColumn, Span};
+use serde::{Deserialize, Serialize};
+use syn::spanned::Spanned;
+use syn::{Attribute, ImplItem, Item, TraitItem};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Granularity {
+    /// One entry per file
+    #[default]
+    File,
+    /// One entry per top-level item of a `.rs` file (fn, struct, impl, inline mod, macro_rules!, ...)
+    Item,
+    /// One entry per function and method, and per `macro_rules!` definition
+    Function,
+}
+
+/// The item an entry of `--granularity item` or `function` holds.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CodeItem {
+    /// `fn`, `struct`, `enum`, `impl`, `trait`, `mod`, `macro_rules`, ...
+    pub kind: String,
+    /// Qualified with the module of its file, e.g. `crate::parser::<Parser as Display>::fmt`
+    pub path: String,
+    /// A function's signature
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// A function's body, braces included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+    /// The attributes other than doc comments, as written
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<String>,
+    /// 1-based and inclusive, attributes and doc comments included
+    pub start_line: usize,
+    pub end_line: usize,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_unsafe: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_async: bool,
+    /// Has a `#[test]`-like attribute (`#[test]`, `#[tokio::test]`, ...)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_test: bool,
+}
+
+/// An item's source text and description.
+pub(crate) struct Extracted {
+    pub(crate) text: String,
+    pub(crate) item: CodeItem,
+}
+
+/// The items of `content`, a file whose items are in `module` (e.g. `crate::parser`), or why it
+/// doesn't parse.
+pub(crate) fn extract(content: &str, module: &str, granularity: Granularity) -> Result<Vec<Extracted>, String> {
+    let items = syn::parse_file(content)
+        .map(|file| {
+            let source = Source::new(content);
+            let mut out = Vec::new();
+            for item in &file.items {
+                match granularity {
+                    Granularity::Function => functions(&source, module, item, &mut out),
+                    _ => {
+                        if let Some(kind) = kind(item) {
+                            let name = item_name(&source, item).map_or_else(|| module.to_string(), |name| format!("{}::{}", module, name));
+                            out.push(source.extracted(kind, name, item.span(), attrs(item)));
+                        }
+                    }
+                }
+            }
+            out
+        })
+        .map_err(|e| format!("line {}: {}", e.span().start().line, e));
+    // proc-macro2 keeps the text of every parsed file for its spans otherwise, which adds up over
+    // a corpus; none of them outlive this call.
+    proc_macro2::extra::invalidate_current_thread_spans();
+    items
+}
+
+/// The functions of `item`, recursing into inline modules, impls and traits.
+fn functions(source: &Source, module: &str, item: &Item, out: &mut Vec<Extracted>) {
+    match item {
+        Item::Fn(f) => out.push(source.function(format!("{}::{}", module, f.sig.ident), f.span(), &f.attrs, &f.sig, f.block.span())),
+        Item::Mod(m) => {
+            for inner in m.content.iter().flat_map(|(_, items)| items) {
+                functions(source, &format!("{}::{}", module, m.ident), inner, out);
+            }
+        }
+        Item::Impl(i) => {
+            let owner = format!("{}::{}", module, impl_name(source, i));
+            for inner in &i.items {
+                if let ImplItem::Fn(f) = inner {
+                    out.push(source.function(format!("{}::{}", owner, f.sig.ident), f.span(), &f.attrs, &f.sig, f.block.span()));
+                }
+            }
+        }
+        Item::Trait(t) => {
+            for inner in &t.items {
+                if let TraitItem::Fn(f) = inner {
+                    if let Some(block) = &f.default {
+                        out.push(source.function(format!("{}::{}::{}", module, t.ident, f.sig.ident), f.span(), &f.attrs, &f.sig, block.span()));
+                    }
+                }
+            }
+        }
+        Item::Macro(m) if m.mac.path.is_ident("macro_rules") => {
+            let name = m.ident.as_ref().map_or_else(|| module.to_string(), |name| format!("{}::{}", module, name));
+            out.push(source.extracted("macro_rules", name, m.span(), &m.attrs));
+        }
+        _ => {}
+    }
+}
+
+fn kind(item: &Item) -> Option<&'static str> {
+    Some(match item {
+        Item::Fn(_) => "fn",
+        Item::Struct(_) => "struct",
+        Item::Enum(_) => "enum",
+        Item::Union(_) => "union",
+        Item::Trait(_) | Item::TraitAlias(_) => "trait",
+        Item::Impl(_) => "impl",
+        Item::Mod(_) => "mod",
+        Item::Const(_) => "const",
+        Item::Static(_) => "static",
+        Item::Type(_) => "type",
+        Item::ForeignMod(_) => "extern",
+        Item::Macro(m) if m.mac.path.is_ident("macro_rules") => "macro_rules",
+        Item::Macro(_) => "macro",
+        // Imports and anything syn can't parse further aren't worth an entry of their own.
+        _ => return None,
+    })
+}
+
+fn item_name(source: &Source, item: &Item) -> Option<String> {
+    Some(match item {
+        Item::Fn(i) => i.sig.ident.to_string(),
+        Item::Struct(i) => i.ident.to_string(),
+        Item::Enum(i) => i.ident.to_string(),
+        Item::Union(i) => i.ident.to_string(),
+        Item::Trait(i) => i.ident.to_string(),
+        Item::TraitAlias(i) => i.ident.to_string(),
+        Item::Mod(i) => i.ident.to_string(),
+        Item::Const(i) => i.ident.to_string(),
+        Item::Static(i) => i.ident.to_string(),
+        Item::Type(i) => i.ident.to_string(),
+        Item::Macro(i) => i.ident.as_ref()?.to_string(),
+        Item::Impl(i) => impl_name(source, i),
+        _ => return None,
+    })
+}
+
+/// `Type`, or `<Type as Trait>` for a trait impl, as written.
+pub(crate) fn impl_name(source: &Source, i: &syn::ItemImpl) -> String {
+    let ty = source.text(i.self_ty.span());
+    match &i.trait_ {
+        Some((_, path, _)) => format!("<{} as {}>", ty, source.text(path.span())),
+        None => ty.to_string(),
+    }
+}
+
+fn attrs(item: &Item) -> &[Attribute] {
+    match item {
+        Item::Fn(i) => &i.attrs,
+        Item::Struct(i) => &i.attrs,
+        Item::Enum(i) => &i.attrs,
+        Item::Union(i) => &i.attrs,
+        Item::Trait(i) => &i.attrs,
+        Item::TraitAlias(i) => &i.attrs,
+        Item::Impl(i) => &i.attrs,
+        Item::Mod(i) => &i.attrs,
+        Item::Const(i) => &i.attrs,
+        Item::Static(i) => &i.attrs,
+        Item::Type(i) => &i.attrs,
+        Item::ForeignMod(i) => &i.attrs,
+        Item::Macro(i) => &i.attrs,
+        _ => &[],
+    }
+}
+
+/// The parsed file's text, to cut items out of it by their spans.
+pub(crate) struct Source<'a> {
+    content: &'a str,
+    /// Byte offset of the start of each line
+    lines: Vec<usize>,
+}
+
+impl<'a> Source<'a> {
+    pub(crate) fn new(content: &'a str) -> Self {
+        let lines = std::iter::once(0).chain(content.match_indices('\n').map(|(i, _)| i + 1)).collect();
+        Source { content, lines }
+    }
+
+    fn offset(&self, at: LineColumn) -> usize {
+        let start = self.lines.get(at.line.saturating_sub(1)).copied().unwrap_or(self.content.len());
+        // Columns count characters, not bytes.
+        start + self.content[start..].chars().take(at.column).map(char::len_utf8).sum::<usize>()
+    }
+
+    pub(crate) fn text(&self, span: Span) -> &'a str {
+        self.between(span.start(), span.end())
+    }
+
+    pub(crate) fn between(&self, start: LineColumn, end: LineColumn) -> &'a str {
+        &self.content[self.offset(start)..self.offset(end)]
+    }
+
+    fn extracted(&self, kind: &str, path: String, span: Span, attrs: &[Attribute]) -> Extracted {
+        let item = CodeItem {
+            kind: kind.to_string(),
+            path,
+            doc: doc(attrs),
+            attributes: attrs.iter().filter(|a| !a.path().is_ident("doc")).map(|a| self.text(a.span()).to_string()).collect(),
+            start_line: span.start().line,
+            end_line: span.end().line,
+            is_test: attrs.iter().any(|a| a.path().segments.last().is_some_and(|s| s.ident == "test")),
+            ..CodeItem::default()
+        };
+        Extracted { text: self.text(span).to_string(), item }
+    }
+
+    fn function(&self, path: String, span: Span, attrs: &[Attribute], sig: &syn::Signature, body: Span) -> Extracted {
+        let mut f = self.extracted("fn", path, span, attrs);
+        f.item.signature = Some(self.text(sig.span()).to_string());
+        f.item.body = Some(self.text(body).to_string());
+        f.item.is_unsafe = sig.unsafety.is_some();
+        f.item.is_async = sig.asyncness.is_some();
+        f
+    }
+}
+
+/// The doc comment of an item, from its `///` lines, `/** */` blocks or `#[doc = "..."]`
+/// attributes; `None` when it is empty.
+pub(crate) fn doc(attrs: &[Attribute]) -> Option<String> {
+    let mut lines = Vec::new();
+    for value in attrs.iter().filter(|a| a.path().is_ident("doc")).filter_map(|a| match &a.meta.require_name_value().ok()?.value {
+        syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(s), .. }) => Some(s.value()),
+        _ => None,
+    }) {
+        match value.contains('\n') {
+            true => lines.extend(block_doc(&value)),
+            false => lines.push(value.strip_prefix(' ').map(String::from).unwrap_or(value)),
+        }
+    }
+    let text = lines.join("\n");
+    (!text.trim().is_empty()).then_some(text)
+}
+
+/// The lines of a `/** */` doc comment, without the blank first and last lines, the ` * ` in
+/// front of every line when they all have one, and their common indentation.
+fn block_doc(value: &str) -> Vec<String> {
+    let mut lines: Vec<&str> = value.lines().collect();
+    if lines.first().is_some_and(|l| l.trim().is_empty()) {
+        lines.remove(0);
+    }
+    if lines.last().is_some_and(|l| l.trim().is_empty()) {
+        lines.pop();
+    }
+    if lines.iter().all(|l| l.trim().is_empty() || l.trim_start().starts_with('*')) {
+        lines = lines.iter().map(|l| l.trim_start().strip_prefix('*').unwrap_or("")).collect();
+    }
+    let indent = lines.iter().filter(|l| !l.trim().is_empty()).map(|l| l.len() - l.trim_start().len()).min().unwrap_or(0);
+    lines.iter().map(|l| l.get(indent..).unwrap_or("").to_string()).collect()
+}
@@ -0,0 +1,423 @@
+//! The `join` step: `code.jsonl` with each repository's `outputs.jsonl` analysis and
+//! `metadata.jsonl` line attached to its entries, or with `--by-file`, the diagnostics of each
+//! file.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::Args;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::analyze::OutputEntry;
+use crate::analyzers::Report;
+use crate::collect::CodeEntry;
+use crate::jsonl::{open_jsonl, Codec, JsonlWriter};
+use crate::schema::from_line;
+use crate::shard::is_manifest;
+use crate::metadata::RepoMetadata;
+use crate::tool::ToolStatus;
+
+pub const DEFAULT_JOIN_FILE: &str = "dataset.jsonl";
+
+/// Written next to the joined file: the JSON Schema of its lines.
+pub const JOIN_SCHEMA_FILE: &str = "schema.json";
+
+#[derive(Args, Clone, Debug)]
+pub struct JoinOptions {
+    /// The code file written by `collect`
+    #[arg(long, value_name = "FILE")]
+    pub code: String,
+    /// The outputs file written by `outputs`
+    #[arg(long, value_name = "FILE")]
+    pub outputs: Option<String>,
+    /// The metadata file written by `metadata`
+    #[arg(long, value_name = "FILE")]
+    pub metadata: Option<String>,
+    #[arg(long, value_name = "FILE", default_value = DEFAULT_JOIN_FILE)]
+    pub out: String,
+    /// Also attach the clippy diagnostics and SAST findings of each entry's own file (its own
+    /// lines, for `--granularity` items)
+    #[arg(long)]
+    pub by_file: bool,
+}
+
+/// One line of the joined file: a code entry, and what is known of its repository.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct JoinedEntry {
+    #[serde(flatten)]
+    pub code: CodeEntry,
+    /// The repository's `metadata.jsonl` line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<RepoMetadata>,
+    /// One per `outputs.jsonl` entry of the repository: a single crate, or each workspace member
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub analysis: Vec<RepoAnalysis>,
+    /// With `--by-file`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub diagnostics: Vec<FileDiagnostic>,
+}
+
+/// What an `outputs.jsonl` entry says of its crate, without the tools' output.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RepoAnalysis {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest_path: Option<String>,
+    /// The commit analyzed, which differs from the entry's when the checkout changed in between
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    pub toolchain: String,
+    /// `cargo check` succeeded; absent when it didn't run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub builds: Option<bool>,
+    /// The status of every analyzer
+    pub tools: BTreeMap<String, ToolStatus>,
+    /// Clippy diagnostics per level; absent when clippy didn't run or the crate didn't build
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clippy_counts: Option<BTreeMap<String, usize>>,
+    /// Ids of the RustSec advisories of the dependencies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub advisories: Option<Vec<String>>,
+    /// Every local crate declares `#![forbid(unsafe_code)]`, by cargo-geiger
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forbids_unsafe: Option<bool>,
+    /// SAST findings in the crate
+    pub findings: usize,
+}
+
+/// A clippy diagnostic or SAST finding in the entry's file.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct FileDiagnostic {
+    /// `clippy`, `semgrep` or `codeql`
+    pub tool: String,
+    /// Lint name or rule id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    /// Clippy's level, or the SARIF level of a finding
+    pub severity: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_line: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<u64>,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct JoinSummary {
+    pub entries: usize,
+    pub diagnostics: usize,
+    /// Repositories (checkout directories) in the code file without outputs or metadata, and
+    /// the other way round; empty for an input that wasn't given
+    pub code_without_outputs: Vec<String>,
+    pub outputs_without_code: Vec<String>,
+    pub code_without_metadata: Vec<String>,
+    pub metadata_without_code: Vec<String>,
+}
+
+/// Where `join` writes the schema of `out`.
+pub fn join_schema_beside(out: &str) -> PathBuf {
+    Path::new(out).parent().unwrap_or(Path::new("")).join(JOIN_SCHEMA_FILE)
+}
+
+/// Streams the code file into `opts.out`, with the outputs and metadata of each entry's
+/// repository. Those files are indexed by checkout directory in a first pass that only keeps
+/// line offsets, and a repository's lines are read back when its first entry comes; memory
+/// stays at one repository's analysis.
+pub fn join_datasets(opts: &JoinOptions) -> anyhow::Result<JoinSummary> {
+    let mut outputs = opts.outputs.as_deref().map(|o| Index::<OutputEntry>::build_with(o, from_line)).transpose()?;
+    let mut metadata = opts.metadata.as_deref().map(Index::<RepoMetadata>::build).transpose()?;
+    let mut w = JsonlWriter::compressed(File::create(&opts.out).with_context(|| format!("failed to write {}", opts.out))?, Codec::of(&opts.out), None)?;
+    let mut summary = JoinSummary::default();
+    let mut seen: BTreeSet<String> = BTreeSet::new();
+    // The lines of the repository whose entries are being written; `--jobs` interleaves them.
+    let mut current: Option<(String, Vec<OutputEntry>, Option<RepoMetadata>)> = None;
+    for (i, line) in open_jsonl(&opts.code)?.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let code: CodeEntry = from_line(&line).with_context(|| format!("{}:{}: not a code entry; `validate --repair` drops bad lines", opts.code, i + 1))?;
+        let dir = key(&code.name, &code.dir).to_string();
+        if current.as_ref().is_none_or(|(d, _, _)| *d != dir) {
+            let analyzed = match outputs.as_mut() {
+                Some(index) => index.read(&dir)?,
+                None => Vec::new(),
+            };
+            let meta = match metadata.as_mut() {
+                Some(index) => index.read(&dir)?.pop(),
+                None => None,
+            };
+            current = Some((dir.clone(), analyzed, meta));
+        }
+        let (_, analyzed, meta) = current.as_ref().unwrap();
+        let diagnostics = match opts.by_file {
+            true => analyzed.iter().flat_map(|o| file_diagnostics(o, &code)).collect(),
+            false => Vec::new(),
+        };
+        summary.entries += 1;
+        summary.diagnostics += diagnostics.len();
+        seen.insert(dir);
+        w.write_entry(&JoinedEntry { code, metadata: meta.clone(), analysis: analyzed.iter().map(analysis).collect(), diagnostics })?;
+    }
+    w.finish()?;
+    std::fs::write(join_schema_beside(&opts.out), serde_json::to_string_pretty(&join_schema())? + "\n")?;
+    if let Some(index) = &outputs {
+        (summary.code_without_outputs, summary.outputs_without_code) = index.unmatched(&seen);
+    }
+    if let Some(index) = &metadata {
+        (summary.code_without_metadata, summary.metadata_without_code) = index.unmatched(&seen);
+    }
+    Ok(summary)
+}
+
+/// The checkout directory a line is keyed by; `name` held it in lines older than `dir`.
+pub(crate) fn key<'a>(name: &'a str, dir: &'a str) -> &'a str {
+    if dir.is_empty() { name } else { dir }
+}
+
+/// The offsets of a JSONL file's lines by checkout directory. A compressed file is decompressed
+/// to a temporary file first, since lines are read back by offset.
+pub(crate) struct Index<T> {
+    path: String,
+    file: BufReader<File>,
+    decompressed: Option<PathBuf>,
+    pub(crate) lines: BTreeMap<String, Vec<u64>>,
+    parse: fn(&str) -> serde_json::Result<T>,
+}
+
+impl<T> Drop for Index<T> {
+    fn drop(&mut self) {
+        if let Some(tmp) = &self.decompressed {
+            let _ = std::fs::remove_file(tmp);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Keyed {
+    name: String,
+    #[serde(default)]
+    dir: String,
+}
+
+impl<T: DeserializeOwned> Index<T> {
+    pub(crate) fn build(path: &str) -> anyhow::Result<Index<T>> {
+        Self::build_with(path, |line| serde_json::from_str(line))
+    }
+
+    /// Reads the lines back with `parse`, such as `from_line` for a versioned entry type.
+    pub(crate) fn build_with(path: &str, parse: fn(&str) -> serde_json::Result<T>) -> anyhow::Result<Index<T>> {
+        static DECOMPRESSED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let decompressed = (Codec::of(path) != Codec::None || is_manifest(path)).then(|| std::env::temp_dir().join(format!("dataset_builder_index_{}_{}.jsonl", std::process::id(), DECOMPRESSED.fetch_add(1, std::sync::atomic::Ordering::Relaxed))));
+        if let Some(tmp) = &decompressed {
+            let copied = File::create(tmp).map_err(anyhow::Error::from).and_then(|mut f| Ok(std::io::copy(&mut open_jsonl(path)?, &mut f)?));
+            if let Err(e) = copied {
+                let _ = std::fs::remove_file(tmp);
+                return Err(e.context(format!("failed to decompress {} to {}", path, tmp.display())));
+            }
+        }
+        let file = BufReader::new(File::open(decompressed.as_deref().unwrap_or(Path::new(path))).with_context(|| format!("failed to read {}", path))?);
+        // Built first, so an error below removes the decompressed file.
+        let mut index = Index { path: path.to_string(), file, decompressed, lines: BTreeMap::new(), parse };
+        let (mut offset, mut number, mut line) = (0, 0, String::new());
+        loop {
+            line.clear();
+            let read = index.file.read_line(&mut line)?;
+            if read == 0 {
+                break;
+            }
+            number += 1;
+            if !line.trim().is_empty() {
+                let k: Keyed = serde_json::from_str(&line).with_context(|| format!("{}:{}: no `name`; `validate --repair` drops bad lines", path, number))?;
+                index.lines.entry(key(&k.name, &k.dir).to_string()).or_default().push(offset);
+            }
+            offset += read as u64;
+        }
+        Ok(index)
+    }
+
+    /// The directories of `seen` without a line, and those with lines not in `seen`.
+    pub(crate) fn unmatched(&self, seen: &BTreeSet<String>) -> (Vec<String>, Vec<String>) {
+        (seen.iter().filter(|d| !self.lines.contains_key(*d)).cloned().collect(), self.lines.keys().filter(|d| !seen.contains(*d)).cloned().collect())
+    }
+
+    /// The lines of `dir`, in file order.
+    pub(crate) fn read(&mut self, dir: &str) -> anyhow::Result<Vec<T>> {
+        let mut out = Vec::new();
+        for offset in self.lines.get(dir).into_iter().flatten() {
+            self.file.seek(SeekFrom::Start(*offset))?;
+            let mut line = String::new();
+            self.file.read_line(&mut line)?;
+            out.push((self.parse)(&line).with_context(|| format!("{}: bad line at byte {}", self.path, offset))?);
+        }
+        Ok(out)
+    }
+}
+
+fn analysis(o: &OutputEntry) -> RepoAnalysis {
+    let report = |tool: &str| o.tools.get(tool).and_then(|t| t.report.as_ref());
+    RepoAnalysis {
+        package: o.package.clone(),
+        manifest_path: o.manifest_path.clone(),
+        commit: o.commit.clone(),
+        toolchain: o.toolchain.clone(),
+        builds: match report("check") {
+            Some(Report::Check(c)) => Some(c.builds),
+            _ => None,
+        },
+        tools: o.tools.iter().map(|(name, t)| (name.clone(), t.result.status.clone())).collect(),
+        clippy_counts: match report("clippy") {
+            Some(Report::Clippy(c)) if !c.compile_failed => Some(c.counts.clone()),
+            _ => None,
+        },
+        advisories: match report("audit") {
+            Some(Report::Audit(a)) => Some(a.advisories.iter().map(|a| a.advisory_id.clone()).collect()),
+            _ => None,
+        },
+        forbids_unsafe: match report("geiger") {
+            Some(Report::Geiger(g)) => Some(g.forbids_unsafe),
+            _ => None,
+        },
+        findings: o.findings.len(),
+    }
+}
+
+/// The diagnostics of `o` in the entry's file, and on its lines for an item.
+fn file_diagnostics(o: &OutputEntry, code: &CodeEntry) -> Vec<FileDiagnostic> {
+    let on_lines = |d: &FileDiagnostic| {
+        let Some(item) = &code.item else { return true };
+        let (start, end) = (d.start_line.unwrap_or(0), d.end_line.or(d.start_line).unwrap_or(u64::MAX));
+        start <= item.end_line as u64 && end >= item.start_line as u64
+    };
+    diagnostics(o).into_iter().filter(|(path, d)| *path == code.path && on_lines(d)).map(|(_, d)| d).collect()
+}
+
+/// The clippy diagnostics and SAST findings of `o` that name a file, with its path relative to
+/// the checkout. Clippy's paths are relative to the Cargo project, and the SAST tools' to the
+/// crate.
+pub(crate) fn diagnostics(o: &OutputEntry) -> Vec<(String, FileDiagnostic)> {
+    let dir = |manifest: Option<&String>| manifest.map(|m| m.rsplit_once('/').map_or("", |(d, _)| d).to_string()).unwrap_or_default();
+    let project = dir(o.project_manifest.as_ref());
+    let krate = dir(o.manifest_path.as_ref().or(o.project_manifest.as_ref()));
+    let mut out = Vec::new();
+    if let Some(Report::Clippy(c)) = o.tools.get("clippy").and_then(|t| t.report.as_ref()) {
+        for d in &c.diagnostics {
+            let Some(file) = &d.file else { continue };
+            out.push((relative(&project, file), FileDiagnostic { tool: "clippy".into(), rule: d.lint_name.clone(), severity: d.level.clone(), start_line: d.line_start, end_line: d.line_end, message: d.message.clone() }));
+        }
+    }
+    for f in &o.findings {
+        let Some(file) = &f.file else { continue };
+        out.push((relative(&krate, file), FileDiagnostic { tool: f.tool.clone(), rule: Some(f.rule_id.clone()), severity: f.severity.clone(), start_line: f.start_line, end_line: f.end_line, message: f.message.clone() }));
+    }
+    out
+}
+
+/// `file`, relative to the directory `base` of the checkout, as a checkout-relative path like
+/// `CodeEntry::path`.
+fn relative(base: &str, file: &str) -> String {
+    let mut parts: Vec<&str> = base.split('/').filter(|p| !p.is_empty()).collect();
+    for part in file.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => drop(parts.pop()),
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// The JSON Schema of a `JoinedEntry` line.
+pub fn join_schema() -> serde_json::Value {
+    let string = || json!({ "type": "string" });
+    let boolean = || json!({ "type": "boolean" });
+    let integer = || json!({ "type": "integer" });
+    let strings = || json!({ "type": "array", "items": { "type": "string" } });
+    let opt = |t: &str| json!({ "type": [t, "null"] });
+    let mut properties = serde_json::Map::new();
+    let mut add = |name: &str, schema: serde_json::Value| {
+        properties.insert(name.to_string(), schema);
+    };
+    add("schema_version", json!({ "type": "integer", "description": "Version of the code.jsonl record layout" }));
+    add("name", json!({ "type": "string", "description": "The repository: owner/repo on GitHub, its URL elsewhere" }));
+    add("dir", json!({ "type": "string", "description": "The checkout directory, which the inputs are joined on" }));
+    add("name_unresolved", boolean());
+    add("repo", json!({ "type": "string", "description": "The names-file entry the checkout was cloned from" }));
+    add("commit", json!({ "type": "string", "description": "HEAD commit of the checkout" }));
+    add("last_commit", json!({ "type": "string", "description": "The last commit that changed the file" }));
+    add("last_commit_date", json!({ "type": "string", "format": "date-time" }));
+    add("modified", json!({ "type": "boolean", "description": "The file differs from HEAD's" }));
+    add("path", json!({ "type": "string", "description": "Relative to the checkout, with / separators" }));
+    add("language", string());
+    add("kind", json!({ "enum": ["lib", "bin", "tests", "examples", "benches", "build", "other"] }));
+    add("package", string());
+    add("target", json!({ "type": "string", "description": "lib or bin:<name>, as in expanded.jsonl" }));
+    add("item", json!({ "type": "object", "description": "The item the content is, with --granularity item or function" }));
+    add("parse_failed", boolean());
+    add("content", string());
+    for name in ["lines", "bytes", "max_line_length", "tokens", "duplicate_count", "cluster_id", "near_duplicate_count"] {
+        add(name, integer());
+    }
+    add("avg_line_length", json!({ "type": "number" }));
+    add("content_hash", json!({ "type": "string", "description": "blake3 of content, in hex" }));
+    add("duplicate_repos", strings());
+    for name in ["from_submodule", "lossy_utf8", "generated", "vendored"] {
+        add(name, boolean());
+    }
+    add("original_newlines", json!({ "enum": ["crlf", "cr"] }));
+    add("license_spdx", string());
+    add("secrets_found", strings());
+    add("generated_by", json!({ "enum": ["path", "marker", "automatically_derived"] }));
+    let mut metadata = serde_json::Map::new();
+    for (name, schema) in [("name", string()), ("repo", string()), ("status", json!({ "enum": ["ok", "not_found"] })), ("topics", strings()), ("fetched_at", string())] {
+        metadata.insert(name.into(), schema);
+    }
+    for name in ["full_name", "license", "default_branch", "created_at", "pushed_at", "etag"] {
+        metadata.insert(name.into(), opt("string"));
+    }
+    for name in ["stars", "forks", "open_issues", "size_kb"] {
+        metadata.insert(name.into(), opt("integer"));
+    }
+    for name in ["archived", "fork"] {
+        metadata.insert(name.into(), opt("boolean"));
+    }
+    add("metadata", json!({ "type": "object", "description": "The repository's metadata.jsonl line", "properties": metadata }));
+    let statuses = json!({ "enum": ["completed", "timeout", "error", "tool_not_installed", "skipped_no_nightly", "skipped", "fetch_failed", "not_a_cargo_project", "skipped_build_failed"] });
+    let analysis = json!({
+        "type": "object",
+        "required": ["toolchain", "tools", "findings"],
+        "properties": {
+            "package": string(),
+            "manifest_path": string(),
+            "commit": string(),
+            "toolchain": string(),
+            "builds": boolean(),
+            "tools": { "type": "object", "additionalProperties": statuses },
+            "clippy_counts": { "type": "object", "additionalProperties": integer() },
+            "advisories": strings(),
+            "forbids_unsafe": boolean(),
+            "findings": { "type": "integer", "description": "SAST findings in the crate" }
+        }
+    });
+    add("analysis", json!({ "type": "array", "description": "One per outputs.jsonl entry of the repository: its crate, or each workspace member", "items": analysis }));
+    let diagnostic = json!({
+        "type": "object",
+        "required": ["tool", "severity", "message"],
+        "properties": { "tool": string(), "rule": string(), "severity": string(), "start_line": integer(), "end_line": integer(), "message": string() }
+    });
+    add("diagnostics", json!({ "type": "array", "description": "With --by-file: the clippy diagnostics and SAST findings of the entry's file, or item", "items": diagnostic }));
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "dataset_builder join entry",
+        "description": "A code.jsonl entry, with the metadata.jsonl line and outputs.jsonl analysis of its repository.",
+        "type": "object",
+        "required": ["name", "path", "content"],
+        "properties": properties
+    })
+}
@@ -0,0 +1,269 @@
+//! Crash-safe JSONL writing, compressed by extension (`.gz`, `.zst`), and reading, of sharded
+//! files through their manifest too.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::Context;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+
+use crate::shard::{is_manifest, ShardReader};
+
+/// How a JSONL file is compressed. Appending to a compressed file adds a gzip member or zstd
+/// frame, which readers decode as one stream.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Codec {
+    #[default]
+    None,
+    /// With a level from 0 to 9
+    Gzip(u32),
+    /// With a level from 1 to 22
+    Zstd(i32),
+}
+
+impl Codec {
+    pub const DEFAULT_GZIP_LEVEL: u32 = 6;
+    pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+    /// The codec of a file by its extension, at the default level.
+    pub fn of(path: &str) -> Codec {
+        match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("gz") => Codec::Gzip(Codec::DEFAULT_GZIP_LEVEL),
+            Some("zst") => Codec::Zstd(Codec::DEFAULT_ZSTD_LEVEL),
+            _ => Codec::None,
+        }
+    }
+
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            Codec::None => None,
+            Codec::Gzip(_) => Some("gz"),
+            Codec::Zstd(_) => Some("zst"),
+        }
+    }
+
+    /// `path` with the extension of `codec` added, as `--compress` names its file; `path` as it
+    /// is without `codec` or when it has the extension already.
+    pub fn path(codec: Option<Codec>, path: &str) -> anyhow::Result<String> {
+        match codec {
+            None => Ok(path.to_string()),
+            Some(c) if Codec::of(path).extension() == c.extension() => Ok(path.to_string()),
+            Some(c) if Codec::of(path) != Codec::None => anyhow::bail!("--compress {} doesn't match the extension of {}", c.extension().unwrap_or("none"), path),
+            Some(c) => Ok(match c.extension() {
+                Some(ext) => format!("{}.{}", path, ext),
+                None => path.to_string(),
+            }),
+        }
+    }
+}
+
+impl FromStr for Codec {
+    type Err = String;
+
+    /// `none`, `gzip`, `zstd`, or either with a level: `gzip:9`, `zstd:19`.
+    fn from_str(s: &str) -> Result<Codec, String> {
+        let (name, level) = s.split_once(':').map_or((s, None), |(n, l)| (n, Some(l)));
+        let level = |max: u32| level.map(|l| l.parse::<u32>().ok().filter(|l| *l <= max).ok_or(format!("`{}` isn't a level from 0 to {}", l, max))).transpose();
+        match name {
+            "none" => Ok(Codec::None),
+            "gzip" | "gz" => Ok(Codec::Gzip(level(9)?.unwrap_or(Codec::DEFAULT_GZIP_LEVEL))),
+            "zstd" | "zst" => Ok(Codec::Zstd(level(22)?.map_or(Codec::DEFAULT_ZSTD_LEVEL, |l| l.max(1) as i32))),
+            _ => Err(format!("`{}` isn't none, gzip[:LEVEL] or zstd[:LEVEL]", s)),
+        }
+    }
+}
+
+enum Sink {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Sink {
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Sink::Plain(w) => w,
+            Sink::Gzip(w) => w,
+            Sink::Zstd(w) => w,
+        }
+    }
+
+    fn file(&self) -> &File {
+        match self {
+            Sink::Plain(w) => w.get_ref(),
+            Sink::Gzip(w) => w.get_ref().get_ref(),
+            Sink::Zstd(w) => w.get_ref().get_ref(),
+        }
+    }
+
+    /// Ends the gzip member or zstd frame; writes nothing when it is ended already.
+    fn end_stream(&mut self) -> std::io::Result<()> {
+        // A finished gzip encoder refuses writes and flushes, so the buffer under it is flushed.
+        match self {
+            Sink::Plain(w) => w.flush(),
+            Sink::Gzip(w) => w.try_finish().and_then(|_| w.get_mut().flush()),
+            Sink::Zstd(w) => w.do_finish().and_then(|_| w.get_mut().flush()),
+        }
+    }
+}
+
+/// Appends JSONL entries, flushing each one so a crash loses at most the entry being written.
+/// Compressed files are flushed a compressed block at a time, and their stream is ended by
+/// `finish`, or when the writer is dropped.
+pub struct JsonlWriter {
+    w: Sink,
+    pub written: usize,
+    pub fsync_every: Option<usize>,
+}
+
+impl JsonlWriter {
+    pub fn new(file: File, fsync_every: Option<usize>) -> Self {
+        JsonlWriter { w: Sink::Plain(BufWriter::new(file)), written: 0, fsync_every }
+    }
+
+    /// A writer compressing with `codec`, starting a new gzip member or zstd frame at the end of
+    /// `file`.
+    pub fn compressed(file: File, codec: Codec, fsync_every: Option<usize>) -> anyhow::Result<Self> {
+        let w = BufWriter::new(file);
+        let w = match codec {
+            Codec::None => Sink::Plain(w),
+            Codec::Gzip(level) => Sink::Gzip(GzEncoder::new(w, flate2::Compression::new(level))),
+            Codec::Zstd(level) => Sink::Zstd(zstd::Encoder::new(w, level)?),
+        };
+        Ok(JsonlWriter { w, written: 0, fsync_every })
+    }
+
+    pub fn write_entry<T: Serialize>(&mut self, entry: &T) -> anyhow::Result<()> {
+        let w = self.w.writer();
+        serde_json::to_writer(&mut *w, entry)?;
+        w.write_all(b"\n")?;
+        w.flush()?;
+        self.written += 1;
+        if self.fsync_every.is_some_and(|n| self.written.is_multiple_of(n.max(1))) {
+            self.w.file().sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// Writes a line already serialized, such as one copied from another JSONL file.
+    pub fn write_line(&mut self, line: &str) -> anyhow::Result<()> {
+        let w = self.w.writer();
+        w.write_all(line.trim_end_matches('\n').as_bytes())?;
+        w.write_all(b"\n")?;
+        w.flush()?;
+        self.written += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> anyhow::Result<()> {
+        self.w.end_stream()?;
+        self.w.file().sync_all()?;
+        Ok(())
+    }
+}
+
+impl Drop for JsonlWriter {
+    fn drop(&mut self) {
+        let _ = self.w.end_stream();
+    }
+}
+
+/// Opens a JSONL file for reading, decompressing `.gz` and `.zst` files; a shard manifest reads
+/// as its shards, one after the other.
+pub fn open_jsonl(path: &str) -> anyhow::Result<Box<dyn BufRead + Send>> {
+    if is_manifest(path) {
+        return Ok(Box::new(ShardReader::open(path)?));
+    }
+    let file = BufReader::new(File::open(path).with_context(|| format!("failed to read {}", path))?);
+    Ok(match Codec::of(path) {
+        Codec::None => Box::new(file),
+        Codec::Gzip(_) => Box::new(BufReader::new(MultiGzDecoder::new(file))),
+        Codec::Zstd(_) => Box::new(BufReader::new(zstd::Decoder::with_buffer(file)?)),
+    })
+}
+
+/// The decompressed text of a JSONL file, and whether its compressed stream was cut short, as
+/// by a crash. The text then has the lines that were decoded.
+pub(crate) fn read_jsonl(path: &str) -> anyhow::Result<(String, bool)> {
+    let mut bytes = Vec::new();
+    let truncated = match open_jsonl(path)?.read_to_end(&mut bytes) {
+        Ok(_) => false,
+        Err(e) if Codec::of(path) != Codec::None => {
+            tracing::warn!(event = "truncated_stream", file = path, error = %e, "{} ends in an unfinished compressed stream", path);
+            true
+        }
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path)),
+    };
+    let text = String::from_utf8(bytes).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned());
+    Ok((text, truncated))
+}
+
+pub(crate) struct JsonlLine<'a> {
+    /// 1-based
+    pub(crate) number: usize,
+    pub(crate) text: &'a str,
+    /// `None` when the line is not valid JSON or lacks its trailing newline (a truncated write)
+    pub(crate) value: Option<serde_json::Value>,
+}
+
+pub(crate) fn scan_jsonl(text: &str) -> Vec<JsonlLine<'_>> {
+    text.split_inclusive('\n')
+        .enumerate()
+        .map(|(i, line)| JsonlLine {
+            number: i + 1,
+            text: line,
+            value: line.ends_with('\n').then(|| serde_json::from_str(line).ok()).flatten(),
+        })
+        .collect()
+}
+
+/// Writes `contents` to `<path>.tmp`, compressed as the extension of `path` says, and renames
+/// it over `path`.
+pub(crate) fn replace_file(path: &str, contents: &str) -> anyhow::Result<()> {
+    let tmp = format!("{}.tmp", path);
+    match Codec::of(path) {
+        Codec::None => fs::write(&tmp, contents)?,
+        codec => {
+            let mut w = JsonlWriter::compressed(File::create(&tmp)?, codec, None)?;
+            w.w.writer().write_all(contents.as_bytes())?;
+            w.finish()?;
+        }
+    }
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Keeps the lines of a per-checkout side file, such as `metrics.jsonl`, whose `dir` is in `keep`,
+/// so a resumed run appends only the repositories it analyzes again. A missing file is left missing.
+pub(crate) fn retain_checkouts(path: &Path, keep: &HashSet<String>) -> anyhow::Result<()> {
+    #[derive(Deserialize)]
+    struct Checkout {
+        #[serde(default)]
+        dir: String,
+    }
+    if !path.exists() {
+        return Ok(());
+    }
+    let name = path.display().to_string();
+    let mut kept = String::new();
+    for line in open_jsonl(&name)?.lines() {
+        let line = line.with_context(|| format!("failed to read {}", name))?;
+        let Ok(entry) = serde_json::from_str::<Checkout>(&line) else { continue };
+        if keep.contains(&entry.dir) {
+            kept.push_str(&line);
+            kept.push('\n');
+        }
+    }
+    replace_file(&name, &kept)
+}
+
+/// Opens a side file for this run: appended to with `resume`, truncated otherwise.
+pub(crate) fn open_for_run(path: &Path, resume: bool) -> anyhow::Result<File> {
+    let file = if resume { File::options().create(true).append(true).open(path) } else { File::create(path) };
+    file.with_context(|| format!("failed to write {}", path.display()))
+}
@@ -0,0 +1,185 @@
+//! The `label` step: file-level vulnerability labels from the clippy, semgrep and CodeQL results
+//! of `outputs.jsonl`, one per file of `code.jsonl`, negatives included.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::analyze::OutputEntry;
+use crate::analyzers::Report;
+use crate::join::{diagnostics, key, FileDiagnostic, Index};
+use crate::jsonl::{open_jsonl, Codec, JsonlWriter};
+use crate::schema::from_line;
+use crate::tool::ToolStatus;
+
+pub const DEFAULT_LABELS_FILE: &str = "labels.jsonl";
+
+/// Written next to the labels file: the findings in files that aren't in the code file.
+pub const UNMATCHED_FINDINGS_FILE: &str = "unmatched_findings.jsonl";
+
+/// The tools whose results are labels.
+pub const LABEL_TOOLS: &[&str] = &["clippy", "semgrep", "codeql"];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Severity {
+    /// SARIF `note`, clippy `note` and `help`
+    Note,
+    #[default]
+    Warning,
+    Error,
+}
+
+impl Severity {
+    /// The severity of a clippy level or SARIF level; `None` for `none` and the like.
+    pub fn parse(level: &str) -> Option<Severity> {
+        match level {
+            "error" => Some(Severity::Error),
+            "warning" => Some(Severity::Warning),
+            "note" | "help" | "info" => Some(Severity::Note),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Note => "note",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct LabelOptions {
+    /// The code file written by `collect`
+    #[arg(long, value_name = "FILE")]
+    pub code: String,
+    /// The outputs file written by `outputs`
+    #[arg(long, value_name = "FILE")]
+    pub outputs: String,
+    #[arg(long, value_name = "FILE", default_value = DEFAULT_LABELS_FILE)]
+    pub out: String,
+    /// The lowest severity that makes a file `flagged`: note, warning or error
+    #[arg(long, value_enum, default_value_t = Severity::Warning)]
+    pub min_severity: Severity,
+}
+
+/// One line of `labels.jsonl`, keyed by `name` and `path`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct FileLabel {
+    /// As in `CodeEntry::name`
+    pub name: String,
+    pub dir: String,
+    pub path: String,
+    /// Findings per tool, 0 for the tools that ran on the repository without finding anything
+    pub counts: BTreeMap<String, usize>,
+    /// The highest severity of the findings, `note`, `warning` or `error`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_severity: Option<String>,
+    /// Lint names and rule ids, each once
+    #[serde(default)]
+    pub rule_ids: Vec<String>,
+    /// Some finding is at `--min-severity` or above
+    pub flagged: bool,
+}
+
+/// One line of `unmatched_findings.jsonl`: a file with findings that the code file doesn't
+/// have, such as a generated file or a test fixture left out by `collect`'s filters.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct UnmatchedFindings {
+    pub name: String,
+    pub dir: String,
+    pub path: String,
+    pub counts: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Default)]
+pub struct LabelSummary {
+    pub repos: usize,
+    pub files: usize,
+    pub flagged: usize,
+    /// Repositories of the code file that `outputs.jsonl` doesn't have, so with no labels
+    pub repos_without_outputs: Vec<String>,
+    pub unmatched_files: usize,
+    pub unmatched_findings: usize,
+}
+
+/// Where `label` writes the unmatched findings of `labels_file`.
+pub fn unmatched_findings_beside(labels_file: &str) -> PathBuf {
+    Path::new(labels_file).parent().unwrap_or(Path::new("")).join(UNMATCHED_FINDINGS_FILE)
+}
+
+#[derive(Deserialize)]
+struct CodePath {
+    name: String,
+    #[serde(default)]
+    dir: String,
+    path: String,
+}
+
+/// Writes a label per file of `opts.code` whose repository `opts.outputs` has, in repository
+/// order. Only the paths of the code file are held in memory, and one repository's outputs at a
+/// time.
+pub fn label_files(opts: &LabelOptions) -> anyhow::Result<LabelSummary> {
+    let mut files: BTreeMap<String, (String, BTreeSet<String>)> = BTreeMap::new();
+    for (i, line) in open_jsonl(&opts.code)?.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: CodePath = serde_json::from_str(&line).with_context(|| format!("{}:{}: not a code entry; `validate --repair` drops bad lines", opts.code, i + 1))?;
+        // Entries of `--granularity` items share their file's label.
+        files.entry(key(&entry.name, &entry.dir).to_string()).or_insert_with(|| (entry.name.clone(), BTreeSet::new())).1.insert(entry.path);
+    }
+    let mut outputs: Index<OutputEntry> = Index::build_with(&opts.outputs, from_line)?;
+    let mut w = JsonlWriter::compressed(File::create(&opts.out).with_context(|| format!("failed to write {}", opts.out))?, Codec::of(&opts.out), None)?;
+    let mut unmatched = JsonlWriter::new(File::create(unmatched_findings_beside(&opts.out))?, None);
+    let mut summary = LabelSummary::default();
+    for (dir, (name, paths)) in &files {
+        let analyzed = outputs.read(dir)?;
+        if analyzed.is_empty() {
+            summary.repos_without_outputs.push(dir.clone());
+            continue;
+        }
+        summary.repos += 1;
+        // A zero only means something from a tool that ran, and for clippy, compiled the crate.
+        let completed = |o: &OutputEntry, t: &str| o.tools.get(t).is_some_and(|r| matches!(r.result.status, ToolStatus::Completed) && !matches!(&r.report, Some(Report::Clippy(c)) if c.compile_failed));
+        let ran: Vec<&str> = LABEL_TOOLS.iter().copied().filter(|t| analyzed.iter().any(|o| completed(o, t))).collect();
+        // Workspace members report the files they share once each.
+        let found: BTreeSet<(String, FileDiagnostic)> = analyzed.iter().flat_map(diagnostics).collect();
+        let mut by_path: BTreeMap<&str, Vec<&FileDiagnostic>> = BTreeMap::new();
+        for (path, d) in &found {
+            by_path.entry(path.as_str()).or_default().push(d);
+        }
+        let counts = |found: &[&FileDiagnostic]| {
+            let mut counts: BTreeMap<String, usize> = ran.iter().map(|t| (t.to_string(), 0)).collect();
+            for d in found {
+                *counts.entry(d.tool.clone()).or_default() += 1;
+            }
+            counts
+        };
+        for path in paths {
+            let found = by_path.remove(path.as_str()).unwrap_or_default();
+            let max = found.iter().filter_map(|d| Severity::parse(&d.severity)).max();
+            let rule_ids: BTreeSet<String> = found.iter().filter_map(|d| d.rule.clone()).collect();
+            let flagged = max.is_some_and(|m| m >= opts.min_severity);
+            summary.files += 1;
+            summary.flagged += usize::from(flagged);
+            w.write_entry(&FileLabel { name: name.clone(), dir: dir.clone(), path: path.clone(), counts: counts(&found), max_severity: max.map(|m| m.name().to_string()), rule_ids: rule_ids.into_iter().collect(), flagged })?;
+        }
+        for (path, found) in by_path {
+            summary.unmatched_files += 1;
+            summary.unmatched_findings += found.len();
+            let counts = counts(&found).into_iter().filter(|(_, n)| *n > 0).collect();
+            unmatched.write_entry(&UnmatchedFindings { name: name.clone(), dir: dir.clone(), path: path.to_string(), counts })?;
+        }
+    }
+    w.finish()?;
+    unmatched.finish()?;
+    Ok(summary)
+}
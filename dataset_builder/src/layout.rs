@@ -0,0 +1,199 @@
+//! What part of a crate each collected file is (`CodeEntry::kind`, `collect --code-kinds`), and
+//! the target it is compiled into (`CodeEntry::target`), by Cargo's standard layout relative to
+//! the nearest `Cargo.toml`.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Hash, Serialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum CodeKind {
+    /// `src/`, except binaries and test modules
+    Lib,
+    /// `src/main.rs` and `src/bin/`
+    Bin,
+    /// `tests/`, and `src` files that are `#[cfg(test)]` modules
+    Tests,
+    Examples,
+    Benches,
+    /// `build.rs`
+    Build,
+    /// Outside the standard layout, including manifests and every file outside a crate
+    #[default]
+    Other,
+}
+
+/// The crates of a checkout and the `src` files that are test modules.
+pub(crate) struct CrateLayout {
+    /// Directories with a `Cargo.toml`, the deepest first
+    roots: Vec<PathBuf>,
+    /// Files and directories of `#[cfg(test)] mod name;` modules
+    test_modules: HashSet<PathBuf>,
+    /// The targets of the roots that are packages
+    targets: HashMap<PathBuf, CrateTargets>,
+}
+
+/// A package's name and its library and binary targets, as Cargo's auto-discovery and the
+/// `[lib]` and `[[bin]]` tables of its manifest declare them.
+pub(crate) struct CrateTargets {
+    pub(crate) package: String,
+    pub(crate) lib: bool,
+    pub(crate) bins: Vec<String>,
+}
+
+impl CrateTargets {
+    /// The targets of the package in `crate_dir`; `None` without a `[package]` name.
+    pub(crate) fn read(crate_dir: &Path) -> Option<Self> {
+        let manifest: toml::Table = fs::read_to_string(crate_dir.join("Cargo.toml")).ok()?.parse().ok()?;
+        let package = manifest.get("package")?.get("name")?.as_str()?.to_string();
+        let lib = manifest.contains_key("lib") || crate_dir.join("src/lib.rs").is_file();
+        let declared = manifest.get("bin").and_then(|b| b.as_array()).into_iter().flatten();
+        let mut bins: Vec<String> = declared.filter_map(|b| Some(b.get("name")?.as_str()?.to_string())).collect();
+        if manifest["package"].get("autobins").and_then(|a| a.as_bool()) != Some(false) {
+            if crate_dir.join("src/main.rs").is_file() {
+                bins.push(package.clone());
+            }
+            for entry in fs::read_dir(crate_dir.join("src/bin")).into_iter().flatten().filter_map(Result::ok) {
+                let path = entry.path();
+                let name = match path.extension() {
+                    Some(e) if e == "rs" => path.file_stem(),
+                    None if path.join("main.rs").is_file() => path.file_name(),
+                    _ => None,
+                };
+                bins.extend(name.map(|n| n.to_string_lossy().into_owned()));
+            }
+        }
+        bins.sort();
+        bins.dedup();
+        Some(CrateTargets { package, lib, bins })
+    }
+
+    /// `lib`, then `bin:<name>` for each binary.
+    pub(crate) fn names(&self) -> Vec<String> {
+        let lib = self.lib.then(|| "lib".to_string());
+        lib.into_iter().chain(self.bins.iter().map(|b| format!("bin:{}", b))).collect()
+    }
+}
+
+impl CrateLayout {
+    /// The layout of the checkout whose files, after the collect's path filters, are `files`.
+    pub(crate) fn scan(files: &[PathBuf]) -> Self {
+        let mut roots: Vec<PathBuf> = files.iter().filter(|f| f.file_name().is_some_and(|n| n == "Cargo.toml")).filter_map(|f| Some(f.parent()?.to_path_buf())).collect();
+        roots.sort_by_key(|r| std::cmp::Reverse(r.components().count()));
+        let targets = roots.iter().filter_map(|r| Some((r.clone(), CrateTargets::read(r)?))).collect();
+        let mut layout = CrateLayout { roots, test_modules: HashSet::new(), targets };
+        for file in files.iter().filter(|f| f.extension().is_some_and(|e| e == "rs")) {
+            if !layout.crate_path(file).is_some_and(|rel| rel.starts_with("src")) {
+                continue;
+            }
+            let Ok(text) = fs::read_to_string(file) else { continue };
+            let dir = match file.file_stem().and_then(|s| s.to_str()) {
+                Some("mod" | "lib" | "main") => file.parent().unwrap().to_path_buf(),
+                _ => file.with_extension(""),
+            };
+            for name in cfg_test_modules(&text) {
+                layout.test_modules.insert(dir.join(format!("{}.rs", name)));
+                layout.test_modules.insert(dir.join(name));
+            }
+        }
+        layout
+    }
+
+    pub(crate) fn kind(&self, file: &Path, content: &str) -> CodeKind {
+        let Some(rel) = self.crate_path(file) else { return CodeKind::Other };
+        let top = rel.components().next().map(|c| c.as_os_str().to_string_lossy().into_owned()).unwrap_or_default();
+        match top.as_str() {
+            "build.rs" if rel.components().count() == 1 => CodeKind::Build,
+            "tests" => CodeKind::Tests,
+            "examples" => CodeKind::Examples,
+            "benches" => CodeKind::Benches,
+            "src" if self.test_modules.iter().any(|m| file.starts_with(m)) || is_test_file(content) => CodeKind::Tests,
+            "src" if rel == Path::new("src/main.rs") || rel.starts_with("src/bin") => CodeKind::Bin,
+            "src" if rel.extension().is_some_and(|e| e == "rs") => CodeKind::Lib,
+            _ => CodeKind::Other,
+        }
+    }
+
+    /// The package of `file` and its target, `lib` or `bin:<name>`, for files of `kind` lib or bin.
+    /// The modules of a package without a library are its main binary's.
+    pub(crate) fn target(&self, file: &Path, kind: CodeKind) -> Option<(String, String)> {
+        let root = self.roots.iter().find(|r| file.starts_with(r))?;
+        let targets = self.targets.get(root)?;
+        let target = match kind {
+            CodeKind::Lib if targets.lib => "lib".to_string(),
+            CodeKind::Lib if targets.bins.contains(&targets.package) => format!("bin:{}", targets.package),
+            CodeKind::Bin => match file.strip_prefix(root.join("src/bin")) {
+                Ok(rel) => format!("bin:{}", Path::new(rel.components().next()?.as_os_str()).file_stem()?.to_string_lossy()),
+                Err(_) => format!("bin:{}", targets.package),
+            },
+            _ => return None,
+        };
+        Some((targets.package.clone(), target))
+    }
+
+    /// The module the items of `file` are in, such as `crate::parser` for `src/parser.rs`. Binaries,
+    /// integration tests, examples and benches are crates of their own.
+    pub(crate) fn module_path(&self, file: &Path) -> String {
+        let parts: Vec<String> = match self.crate_path(file) {
+            Some(rel) => rel.with_extension("").components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect(),
+            None => Vec::new(),
+        };
+        let skip = match parts.first().map(String::as_str) {
+            Some("src") if parts.get(1).is_some_and(|p| p == "bin") => 3,
+            Some("src") => 1,
+            Some("tests" | "examples" | "benches") => 2,
+            _ => parts.len(),
+        };
+        let mut inner: Vec<&str> = parts.iter().skip(skip).map(String::as_str).collect();
+        if inner.last() == Some(&"mod") || inner == ["lib"] || inner == ["main"] {
+            inner.pop();
+        }
+        std::iter::once("crate").chain(inner).collect::<Vec<_>>().join("::")
+    }
+
+    /// The path of `file` relative to the root of its crate.
+    fn crate_path<'a>(&self, file: &'a Path) -> Option<&'a Path> {
+        self.roots.iter().find_map(|root| file.strip_prefix(root).ok())
+    }
+}
+
+/// A file that is only compiled for tests: `#![cfg(test)]` before any item.
+fn is_test_file(content: &str) -> bool {
+    let other_inner_attribute = |l: &str| l.starts_with("#![") && !l.starts_with("#![cfg(test)]");
+    content
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with("//") && !other_inner_attribute(l))
+        .is_some_and(|l| l.starts_with("#![cfg(test)]"))
+}
+
+/// The names of the out-of-line modules declared under `#[cfg(test)]`, as in
+/// `#[cfg(test)] mod tests;`.
+fn cfg_test_modules(text: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut under_cfg_test = false;
+    for line in text.lines().map(str::trim) {
+        let rest = match line.strip_prefix("#[cfg(test)]") {
+            Some(rest) => {
+                under_cfg_test = true;
+                rest.trim()
+            }
+            None => line,
+        };
+        if rest.is_empty() || rest.starts_with("#[") || rest.starts_with("//") {
+            continue;
+        }
+        if under_cfg_test {
+            let item = rest.strip_prefix("pub(crate) ").or_else(|| rest.strip_prefix("pub ")).unwrap_or(rest);
+            if let Some(name) = item.strip_prefix("mod ").and_then(|m| m.strip_suffix(';')) {
+                names.push(name.trim().to_string());
+            }
+        }
+        under_cfg_test = false;
+    }
+    names
+}
@@ -0,0 +1,107 @@
+//! Library behind the `dataset_builder` CLI: filter GitHub repository lists, clone them, run
+//! static analysis tools on every checkout, and collect their source code into JSONL datasets.
+
+pub mod analyze;
+pub mod analyzers;
+pub mod cache;
+pub mod clean;
+pub mod clone;
+pub mod collect;
+pub mod columnar;
+pub mod complexity;
+pub mod config;
+pub mod dedup;
+pub mod discover;
+pub mod docpairs;
+pub mod doctor;
+pub mod filter;
+pub mod generated;
+pub mod git_cli;
+pub mod github;
+pub mod hf;
+pub mod items;
+pub mod join;
+pub mod jsonl;
+pub mod labels;
+pub mod layout;
+pub mod license;
+pub mod logging;
+pub mod manifest;
+pub mod manifests;
+pub mod merge;
+pub mod metadata;
+pub mod parse;
+pub mod pipeline;
+pub mod plan;
+pub mod progress;
+pub mod provenance;
+pub mod sample;
+pub mod sandbox;
+pub mod schema;
+pub mod secrets;
+pub mod shard;
+pub mod shutdown;
+pub mod split;
+pub mod sqlite;
+pub mod stats;
+pub mod targets;
+pub mod tokenizer;
+pub mod tool;
+pub mod tools_config;
+pub mod unsafety;
+pub mod update;
+pub mod upload;
+pub mod validate;
+pub mod workspace;
+
+pub use analyze::{analyze_repo, run_outputs, run_outputs_with, AnalyzeOptions, OutputEntry, DEFAULT_MAX_TOOL_OUTPUT_BYTES};
+pub use analyzers::{builtin_analyzers, select_analyzers, Analyzer, RepoTarget, Report, Selection, ToolOutput};
+pub use cache::{cache_gc, clean_head, CacheKey, ResultCache};
+pub use clean::{clean_dataset, CleanKind, CleanOptions, CleanSummary, CleanedDir};
+pub use clone::{clone_repos, head_commit, is_git_url, sanitize, AuthMode, AuthOptions, CloneError, CloneErrorClass, CloneFilter, CloneOptions, CloneRecord, CloneSummary, GitBackend, HistoryOptions, OnExisting, Submodules, CLONE_MANIFEST_FILE, DEFAULT_CLONE_ERRORS_FILE, NAME_MAP_FILE};
+pub use collect::{
+    collect_code, collect_code_all, collect_code_into, collect_code_with, skipped_files_beside, CodeEntry, CodeSource, CollectOptions, CollectReport, CollectedCode, SkipReason, SkippedFile, SkippedFiles, DEFAULT_EXCLUDES,
+    SKIPPED_FILES_FILE,
+};
+pub use columnar::{code_schema, convert, jsonl_to_parquet, outputs_schema, parquet_path, parquet_rows, parquet_to_jsonl, upgrade_jsonl, working_jsonl, ConvertOptions, DatasetKind, FileFormat, ParquetWriter, DEFAULT_ROW_GROUP_ROWS, ENCODING_METADATA_KEY};
+pub use complexity::{file_metrics, metrics_beside, ComplexityReport, FileMetrics, Halstead, MetricsEntry, METRICS_FILE};
+pub use config::{write_config_template, Config, PipelineFlags, CONFIG_TEMPLATE, DEFAULT_CONFIG_FILE};
+pub use dedup::{content_hash, dedup_code, near_dedup_code, Dedup, DedupSummary, NearAction, NearDedupOptions, NearDedupSummary};
+pub use discover::{discover_crates, discover_repos, DiscoverCratesOptions, DiscoverCratesSummary, DiscoverOptions, DiscoverSummary};
+pub use docpairs::{extract_doc_pairs, extract_doc_pairs_all, DocPair, DocPairsOptions, DocPairsSummary, DEFAULT_DOC_PAIRS_FILE};
+pub use doctor::{doctor_checks, run_doctor, Check, CheckStatus, DoctorOptions, DEFAULT_MIN_FREE_GB};
+pub use filter::{canonical_repo_name, filter_csv, filter_csv_with, FilterOptions, FilterSummary, InputFormat};
+pub use generated::{flagged_files_beside, generated_by, is_vendored, FlaggedFiles, DEFAULT_GENERATED_MARKERS, FLAGGED_FILES_FILE, VENDORED_DIRS};
+pub use github::Tokens;
+pub use hf::{export_hf, hf_features, resolve_token, ExportHfOptions, HfSplit, HfSummary, DATASET_INFOS_FILE, DEFAULT_HF_DIR, DEFAULT_HF_ENDPOINT, DEFAULT_HF_SHARD_ROWS};
+pub use items::{CodeItem, Granularity};
+pub use join::{join_datasets, join_schema, join_schema_beside, FileDiagnostic, JoinOptions, JoinSummary, JoinedEntry, RepoAnalysis, DEFAULT_JOIN_FILE, JOIN_SCHEMA_FILE};
+pub use jsonl::{open_jsonl, Codec};
+pub use labels::{label_files, unmatched_findings_beside, FileLabel, LabelOptions, LabelSummary, Severity, UnmatchedFindings, DEFAULT_LABELS_FILE, LABEL_TOOLS, UNMATCHED_FINDINGS_FILE};
+pub use layout::CodeKind;
+pub use license::{detect_license, license_allowed, licenses_beside, LicenseTable, RepoLicense, LICENSES_FILE, UNKNOWN_LICENSE};
+pub use logging::init_logging;
+pub use manifest::RunManifest;
+pub use manifests::{extract_manifests, extract_manifests_all, Dependency, DependencyKind, LockedPackage, ManifestEntry, ManifestKind, ManifestsOptions, ManifestsSummary, Package, WorkspaceInfo, DEFAULT_MANIFESTS_FILE, INHERITED};
+pub use merge::{merge_conflicts_beside, merge_files, MergeConflict, MergeOptions, MergeSummary, MERGE_CONFLICTS_FILE};
+pub use metadata::{fetch_metadata, MetadataOptions, MetadataSummary, RepoMetadata, DEFAULT_METADATA_FILE};
+pub use pipeline::{Pipeline, PipelineState, Step, StepOptions, StepRecord, StepStatus, StreamError, StreamOptions, DEFAULT_STREAM_ERRORS_FILE, PIPELINE_STATE_FILE};
+pub use plan::{plan_clone, plan_collect, plan_outputs, print_plan, ClonePlan, CollectPlan, DryRunOptions, OutputsPlan, Plan, PlannedAnalyzer, PlannedClone};
+pub use progress::disable_progress;
+pub use provenance::{LastCommit, Provenance};
+pub use sandbox::{SandboxKind, SandboxOptions, DEFAULT_SANDBOX_IMAGE};
+pub use schema::{from_line, OutputEntryV3, Versioned, CODE_SCHEMA_VERSION, OUTPUT_SCHEMA_VERSION};
+pub use secrets::{redact_secrets, scan_secrets, secrets_report_beside, SecretFinding, SecretMatch, SecretRule, SecretsMode, SECRETS_REPORT_FILE};
+pub use shard::{manifest_path, parse_size, shard_jsonl, shard_path, Shard, ShardManifest, ShardOptions, MANIFEST_SUFFIX};
+pub use shutdown::{install_signal_handler, request_shutdown, shutdown_requested, Checkpoint, DEFAULT_SHUTDOWN_GRACE};
+pub use split::{split_dataset, split_path, split_point, SplitCounts, SplitMap, SplitOptions, SPLITS, SPLITS_FILE};
+pub use sqlite::{export_sqlite, ExportSqliteOptions, ExportSummary, DEFAULT_BATCH_ROWS, DEFAULT_SQLITE_FILE, SQLITE_SCHEMA};
+pub use stats::{corpus_stats, human_bytes, CodeStats, CorpusStats, FileShare, HistogramBucket, OutputStats, SlowRepo, StatsOptions, STATS_TOP};
+pub use targets::{expanded_beside, ir_beside, ExpandReport, ExpandedEntry, IrEntry, IrReport, TargetOutcome, TargetSource, DEFAULT_MAX_EXPANDED_BYTES, DEFAULT_MAX_IR_BYTES, EXPANDED_FILE, IR_FILE};
+pub use tokenizer::{Bpe, Tokenizer, TokenizerKind};
+pub use tool::{ResourceUsage, ToolResult, ToolStatus};
+pub use tools_config::{load_tools_config, CustomAnalyzer};
+pub use unsafety::{extract_unsafe, extract_unsafe_all, unsafe_summary_beside, UnsafeEntry, UnsafeKind, UnsafeOptions, UnsafeSummary, UNSAFE_SUMMARY_FILE};
+pub use update::{update_repos, UpdateOptions, UpdateRecord, UpdateStatus, UpdateSummary, UPDATE_CHANGELOG_FILE};
+pub use upload::{upload_files, upload_files_with, Credentials, Destination, StoreOptions, UploadSummary, UploadTarget, BLAKE3_METADATA, DEFAULT_PART_SIZE, GCS_ENDPOINT, MAX_PARTS};
+pub use validate::{validate_file, validate_jsonl, ValidateKind, ValidateOptions, ValidateSummary};
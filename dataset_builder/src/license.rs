@@ -0,0 +1,164 @@
+//! Per-repository license detection, from the root `Cargo.toml`'s `license` field or the text of
+//! its `LICENSE*` and `COPYING*` files, and the `collect --allow-licenses`/`--deny-licenses`
+//! filters.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Written next to the code file: the license of every repository collected or left out.
+pub const LICENSES_FILE: &str = "licenses.jsonl";
+
+/// The license of a repository where none is found.
+pub const UNKNOWN_LICENSE: &str = "unknown";
+
+/// Phrases of each license's text, after `normalize`; a text is scored by the share it has. The
+/// LGPL and AGPL come before the GPL, whose phrases their texts quote.
+const FINGERPRINTS: &[(&str, &[&str])] = &[
+    ("MIT", &["permission is hereby granted free of charge", "the above copyright notice and this permission notice shall be included", "the software is provided as is"]),
+    ("Apache-2.0", &["apache license", "version 2 0", "grant of patent license", "limitations under the license"]),
+    ("BSD-3-Clause", &["redistribution and use in source and binary forms", "neither the name", "this software is provided by the copyright holders and contributors as is"]),
+    ("BSD-2-Clause", &["redistribution and use in source and binary forms", "this software is provided by the copyright holders and contributors as is"]),
+    ("ISC", &["permission to use copy modify and or distribute this software for any purpose with or without fee is hereby granted"]),
+    ("MPL-2.0", &["mozilla public license", "version 2 0"]),
+    ("LGPL-3.0", &["gnu lesser general public license", "version 3 29 june 2007"]),
+    ("LGPL-2.1", &["gnu lesser general public license", "version 2 1 february 1999"]),
+    ("AGPL-3.0", &["gnu affero general public license", "version 3 19 november 2007"]),
+    ("GPL-3.0", &["gnu general public license", "version 3 29 june 2007"]),
+    ("GPL-2.0", &["gnu general public license", "version 2 june 1991"]),
+    ("Unlicense", &["this is free and unencumbered software released into the public domain"]),
+    ("CC0-1.0", &["cc0 1 0 universal", "no copyright"]),
+    ("Zlib", &["altered source versions must be plainly marked as such", "this notice may not be removed or altered from any source distribution"]),
+    ("BSL-1.0", &["boost software license version 1 0"]),
+];
+
+/// A text is only classified when it has at least this share of a license's phrases.
+const MIN_CONFIDENCE: f64 = 0.5;
+
+/// One line of `licenses.jsonl`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct RepoLicense {
+    /// As in `CodeEntry::name`
+    pub name: String,
+    pub dir: String,
+    /// An SPDX expression such as `MIT OR Apache-2.0`, or `unknown`
+    pub license_spdx: String,
+    /// 1 for a `Cargo.toml` field, else the share of the license's phrases its file has
+    pub license_confidence: f64,
+    /// What it was read from, relative to the checkout
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub license_files: Vec<String>,
+    /// Left out of the collect by `--allow-licenses` or `--deny-licenses`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub excluded: bool,
+}
+
+/// Where `collect` writes the licenses of `code_file`.
+pub fn licenses_beside(code_file: &str) -> PathBuf {
+    Path::new(code_file).parent().unwrap_or(Path::new("")).join(LICENSES_FILE)
+}
+
+/// The license of the checkout: the root `Cargo.toml`'s `package.license` (or
+/// `workspace.package.license`) when there is one, or else that of its license files, joined
+/// with `OR` when they differ, as for `LICENSE-MIT` and `LICENSE-APACHE`.
+pub fn detect_license(repo_path: &Path) -> (String, f64, Vec<String>) {
+    if let Some(license) = manifest_license(&repo_path.join("Cargo.toml")) {
+        return (license, 1.0, vec!["Cargo.toml".to_string()]);
+    }
+    let mut files: Vec<PathBuf> = fs::read_dir(repo_path)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| Some(e.ok()?.path()))
+        .filter(|p| p.is_file() && p.file_name().is_some_and(|n| ["LICENSE", "LICENCE", "COPYING"].iter().any(|l| n.to_string_lossy().to_ascii_uppercase().starts_with(l))))
+        .collect();
+    files.sort();
+    let mut found: BTreeMap<&str, f64> = BTreeMap::new();
+    let mut read = Vec::new();
+    for file in files {
+        let Some((id, confidence)) = fs::read_to_string(&file).ok().and_then(|text| classify(&text)) else { continue };
+        let best = found.entry(id).or_insert(0.0);
+        *best = best.max(confidence);
+        read.push(file.file_name().unwrap().to_string_lossy().into_owned());
+    }
+    match found.is_empty() {
+        true => (UNKNOWN_LICENSE.to_string(), 0.0, Vec::new()),
+        false => (found.keys().copied().collect::<Vec<_>>().join(" OR "), found.values().copied().fold(1.0, f64::min), read),
+    }
+}
+
+fn manifest_license(path: &Path) -> Option<String> {
+    let manifest: toml::Value = toml::from_str(&fs::read_to_string(path).ok()?).ok()?;
+    let license = manifest.get("package").and_then(|p| p.get("license")).or_else(|| manifest.get("workspace")?.get("package")?.get("license"))?.as_str()?;
+    // The old `MIT/Apache-2.0` form means either.
+    Some(license.split('/').map(str::trim).collect::<Vec<_>>().join(" OR ")).filter(|l| !l.is_empty())
+}
+
+/// The license a text is most likely the text of, and the share of its phrases found.
+fn classify(text: &str) -> Option<(&'static str, f64)> {
+    let text = normalize(text);
+    let mut best: Option<(&str, f64, usize)> = None;
+    for (id, phrases) in FINGERPRINTS {
+        let found = phrases.iter().filter(|p| text.contains(*p)).count();
+        let score = found as f64 / phrases.len() as f64;
+        // On a tie the license with more phrases wins, so BSD-3-Clause over BSD-2-Clause.
+        if score >= MIN_CONFIDENCE && best.is_none_or(|(_, s, n)| score > s || (score == s && phrases.len() > n)) {
+            best = Some((id, score, phrases.len()));
+        }
+    }
+    best.map(|(id, score, _)| (id, score))
+}
+
+/// Lowercase words separated by single spaces, so wrapping and punctuation don't matter.
+fn normalize(text: &str) -> String {
+    text.to_lowercase().split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).collect::<Vec<_>>().join(" ")
+}
+
+/// Whether a repository under `license` passes `allow` and `deny`. An expression passes when one
+/// of its `OR` alternatives does, which in turn passes when all of its `AND` terms are allowed
+/// and none is denied. `-only`, `-or-later` and `+` don't matter, and with an allowlist, an
+/// `unknown` license only passes when `unknown` is on it.
+pub fn license_allowed(license: &str, allow: &[String], deny: &[String]) -> bool {
+    let id = |s: &str| s.trim().trim_end_matches('+').trim_end_matches("-or-later").trim_end_matches("-only").to_ascii_lowercase();
+    let allow: Vec<String> = allow.iter().map(|a| id(a)).collect();
+    let deny: Vec<String> = deny.iter().map(|d| id(d)).collect();
+    let cleaned = license.replace(['(', ')'], " ");
+    cleaned.split(" OR ").any(|alternative| {
+        alternative.split(" AND ").all(|term| {
+            // An exception such as `WITH LLVM-exception` only relaxes the license.
+            let term = id(term.split(" WITH ").next().unwrap_or(term));
+            (allow.is_empty() || allow.contains(&term)) && !deny.contains(&term)
+        })
+    })
+}
+
+/// Repositories and collected files per license, to print once a collect is done.
+#[derive(Debug, Default)]
+pub struct LicenseTable {
+    /// Repositories, those excluded, and files collected
+    rows: BTreeMap<String, (usize, usize, usize)>,
+}
+
+impl LicenseTable {
+    pub fn add(&mut self, license: &RepoLicense, files: usize) {
+        let row = self.rows.entry(license.license_spdx.clone()).or_default();
+        row.0 += 1;
+        row.1 += usize::from(license.excluded);
+        row.2 += files;
+    }
+
+    /// Logs the table, most common license first.
+    pub fn log(&self) {
+        if self.rows.is_empty() {
+            return;
+        }
+        let width = self.rows.keys().map(String::len).max().unwrap_or(0).max("License".len());
+        tracing::info!("{:<width$}  {:>12}  {:>8}  {:>8}", "License", "Repositories", "Excluded", "Files", width = width);
+        let mut rows: Vec<_> = self.rows.iter().collect();
+        rows.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then(a.0.cmp(b.0)));
+        for (license, (repos, excluded, files)) in rows {
+            tracing::info!("{:<width$}  {:>12}  {:>8}  {:>8}", license, repos, excluded, files, width = width);
+        }
+    }
+}
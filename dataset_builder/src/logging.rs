@@ -0,0 +1,25 @@
+//! Log events on stderr through `tracing`, as text or as JSON lines (`--log-json`).
+
+use std::io::IsTerminal;
+
+use tracing::Level;
+
+/// Installs the process-wide subscriber: warnings only with `quiet`, then info, debug (`-v`)
+/// and trace (`-vv`). Everything goes to stderr, so stdout stays free for data.
+pub fn init_logging(verbose: u8, quiet: bool, json: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => Level::WARN,
+        (false, 0) => Level::INFO,
+        (false, 1) => Level::DEBUG,
+        (false, _) => Level::TRACE,
+    };
+    let builder = tracing_subscriber::fmt().with_max_level(level).with_writer(std::io::stderr).with_target(false)
+        .with_ansi(std::io::stderr().is_terminal());
+    if json {
+        builder.json().flatten_event(true).init();
+    } else if verbose == 0 {
+        builder.without_time().init();
+    } else {
+        builder.init();
+    }
+}
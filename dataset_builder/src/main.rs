@@ -1,264 +1,548 @@
-use std::fs::{self, File};
-use std::io::{BufWriter, Write};
-use std::path::Path;
-use std::process::Command;
-use std::time::Instant;
+use std::time::Duration;
 
-use clap::{Parser, Subcommand};
-use csv::ReaderBuilder;
-use git2::{Cred, FetchOptions, RemoteCallbacks, Repository};
-use ignore::WalkBuilder;
-use serde::Serialize;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+
+use dataset_builder::analyze::{run_outputs, AnalyzeOptions};
+use dataset_builder::cache::cache_gc;
+use dataset_builder::clean::{clean_dataset, CleanOptions};
+use dataset_builder::clone::{clone_repos, AuthOptions, CloneOptions};
+use dataset_builder::collect::{collect_code_all, CollectOptions};
+use dataset_builder::columnar::{convert, ConvertOptions};
+use dataset_builder::config::{write_config_template, Config, PipelineFlags, DEFAULT_CONFIG_FILE};
+use dataset_builder::dedup::{dedup_code, near_dedup_code, Dedup, NearDedupOptions};
+use dataset_builder::discover::{discover_crates, discover_repos, DiscoverCratesOptions, DiscoverOptions};
+use dataset_builder::docpairs::{extract_doc_pairs_all, DocPairsOptions, DEFAULT_DOC_PAIRS_FILE};
+use dataset_builder::doctor::{run_doctor, DoctorOptions};
+use dataset_builder::filter::{filter_csv_with, FilterOptions};
+use dataset_builder::github::Tokens;
+use dataset_builder::hf::{export_hf, ExportHfOptions};
+use dataset_builder::join::{join_datasets, JoinOptions};
+use dataset_builder::labels::{label_files, unmatched_findings_beside, LabelOptions};
+use dataset_builder::logging::init_logging;
+use dataset_builder::manifests::{extract_manifests_all, ManifestsOptions, DEFAULT_MANIFESTS_FILE};
+use dataset_builder::merge::{merge_conflicts_beside, merge_files, MergeOptions};
+use dataset_builder::metadata::{fetch_metadata, MetadataOptions};
+use dataset_builder::pipeline::{Pipeline, StepOptions, StreamOptions};
+use dataset_builder::plan::{plan_clone, plan_collect, plan_outputs, print_plan, DryRunOptions, Plan};
+use dataset_builder::progress::disable_progress;
+use dataset_builder::shutdown::{install_signal_handler, shutdown_requested, DEFAULT_SHUTDOWN_GRACE};
+use dataset_builder::split::{split_dataset, SplitOptions, SPLITS};
+use dataset_builder::sqlite::{export_sqlite, ExportSqliteOptions};
+use dataset_builder::stats::{corpus_stats, human_bytes, StatsOptions};
+use dataset_builder::unsafety::{extract_unsafe_all, UnsafeOptions};
+use dataset_builder::update::{update_repos, UpdateOptions, UpdateStatus};
+use dataset_builder::upload::{upload_files, StoreOptions, UploadTarget};
+use dataset_builder::validate::{validate_file, ValidateOptions};
 
 /// Dataset builder: filter, clone, analyze (tools + SAST + metadata), collect, or run all.
 #[derive(Parser)]
 #[command(name = "dataset_builder")]
 struct Cli {
-    /// GitHub token for authenticated cloning
-    #[arg(env = "GITHUB_TOKEN")]
-    token: String,
+    /// Read from GITHUB_TOKEN only: a token on the command line is refused, since it stays in the
+    /// shell history and shows in `ps`
+    #[arg(env = "GITHUB_TOKEN", hide_env_values = true, hide = true)]
+    token: Option<String>,
 
-    #[command(subcommand)]
-    command: Commands,
-}
+    /// A file of GitHub tokens, one per line, used with GITHUB_TOKEN and the comma-separated
+    /// GITHUB_TOKENS; they are rotated through as each runs out of API requests
+    #[arg(long, global = true, value_name = "FILE")]
+    token_file: Option<String>,
 
-#[derive(Subcommand)]
-enum Commands {
-    Filter { csv: String, out: String },
-    Clone { names: String, out: String },
-    Outputs { root: String, outputs: String },
-    Collect { root: String, code: String },
-    Full {},
-}
+    /// A command printing GitHub tokens, one per line, such as `gh auth token` or a keyring
+    /// helper; run by the shell, and used with the other tokens
+    #[arg(long, global = true, value_name = "COMMAND")]
+    token_cmd: Option<String>,
 
-#[derive(Debug, Serialize)]
-struct OutputEntry {
-    name: String,
-    clippy: String,
-    fmt: String,
-    audit: String,
-    auditable: String,
-    deny: String,
-    semgrep: String,
-    geiger: String,
-    codeql: String,
-    tree: String,
-    ast: String,
-    time_ms: Times,
-}
+    /// Don't draw progress bars (they are also off when stdout isn't a terminal, or with -v/--log-json)
+    #[arg(long, global = true)]
+    no_progress: bool,
 
-#[derive(Debug, Serialize)]
-struct Times {
-    clippy: u128,
-    fmt: u128,
-    audit: u128,
-    auditable: u128,
-    deny: u128,
-    semgrep: u128,
-    geiger: u128,
-    codeql: u128,
-    tree: u128,
-    ast: u128,
-}
+    /// Log more: `-v` adds per-repo and per-tool events, `-vv` everything
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
 
-#[derive(Debug, Serialize)]
-struct CodeEntry {
-    name: String,
-    path: String,
-    content: String,
-}
+    /// Only log warnings and errors
+    #[arg(short, long, global = true)]
+    quiet: bool,
 
-fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
-    match cli.command {
-        Commands::Filter { csv, out } => filter_csv(&csv, &out)?,
-        Commands::Clone { names, out } => clone_repos(&names, &out, &cli.token)?,
-        Commands::Outputs { root, outputs } => run_outputs(&root, &outputs)?,
-        Commands::Collect { root, code } => collect_code_all(&root, &code)?,
-        Commands::Full {} => run_full(&cli.token)?,
-    }
-    Ok(())
-}
+    /// Log events as JSON lines (e.g. `{"event":"tool_finished","tool":"clippy",...}`) on stderr
+    #[arg(long, global = true)]
+    log_json: bool,
 
-fn filter_csv(input: &str, output: &str) -> anyhow::Result<()> {
-    let mut rdr = ReaderBuilder::new().from_path(input)?;
-    let mut w = BufWriter::new(File::create(output)?);
-    for result in rdr.deserialize::<(String, String, bool, bool)>() {
-        let (_id, name, has_toml, has_lock) = result?;
-        if has_toml && has_lock {
-            writeln!(w, "{}", name)?;
-        }
-    }
-    Ok(())
-}
-
-fn clone_repos(names_file: &str, out_root: &str, token: &str) -> anyhow::Result<()> {
-    let names = fs::read_to_string(names_file)?;
-    for name in names.lines() {
-        let dest = Path::new(out_root).join(format!("dataset_{}", sanitize(name)));
-        fs::create_dir_all(&dest)?;
-        let mut callbacks = RemoteCallbacks::new();
-        let tok = token.to_string();
-        callbacks.credentials(move |_url, _user, _cred| Cred::userpass_plaintext("x-access-token", &tok));
-        let mut fo = FetchOptions::new();
-        fo.depth(1).remote_callbacks(callbacks);
-        Repository::clone(&format!("https://github.com/{}.git", name), &dest)?;
-    }
-    Ok(())
-}
+    /// After Ctrl-C, seconds the tools in flight get to finish before they are killed
+    #[arg(long, global = true, value_name = "SECS", default_value_t = DEFAULT_SHUTDOWN_GRACE.as_secs())]
+    shutdown_grace: u64,
 
-fn run_outputs(root: &str, outputs_file: &str) -> anyhow::Result<()> {
-    let mut w = BufWriter::new(File::create(outputs_file)?);
-    for entry in fs::read_dir(root)? {
-        let path = entry?.path();
-        if !path.is_dir() { continue; }
-        let name = path.file_name().unwrap().to_string_lossy();
-        let out = analyze_repo(&path, &name)?;
-        serde_json::to_writer(&mut w, &out)?;
-        w.write_all(b"\n")?;
-    }
-    Ok(())
-}
-
-fn analyze_repo(path: &Path, name: &str) -> anyhow::Result<OutputEntry> {
-    let mut times = Times { clippy:0, fmt:0, audit:0, auditable:0, deny:0, semgrep:0, geiger:0, codeql:0, tree:0, ast:0 };
-    macro_rules! measure {
-        ($field:ident, $func:expr) => {{
-            let start = Instant::now();
-            let res = $func;
-            times.$field = start.elapsed().as_millis();
-            res
-        }};
-    }
-
-    let clippy    = measure!(clippy, run_cmd(path, &["clippy","--message-format=json"])?);
-    let fmt       = measure!(fmt, run_cmd(path, &["fmt","--","--check"])?);
-    let audit     = measure!(audit, run_cmd(path, &["audit"])?);
-    let auditable = measure!(auditable, run_cmd(path, &["auditable"])?);
-    let deny      = measure!(deny, run_cmd(path, &["deny","check"])?);
-    let geiger    = measure!(geiger, run_cmd(path, &["geiger"])?);
-    let tree      = measure!(tree, run_ext_cmd(path, "cargo", &["tree"])?);
-    let ast       = measure!(ast, run_ext_cmd(path, "rustc", &["--emit=ast", "-Z", "unpretty=ast"])?);
-    let semgrep   = measure!(semgrep, run_ext_cmd(path, "semgrep", &["--config","p/rust","--json"])?);
-    let codeql    = measure!(codeql, run_ext_cmd(path, "codeql", &["database","analyze","--format=json"])?);
-
-    Ok(OutputEntry {
-        name:      name.into(),
-        clippy,
-        fmt,
-        audit,
-        auditable,
-        deny,
-        semgrep,
-        geiger,
-        codeql,
-        tree,
-        ast,
-        time_ms:  times,
-    })
+    #[command(subcommand)]
+    command: Commands,
 }
 
-fn run_cmd(dir: &Path, args: &[&str]) -> anyhow::Result<String> {
-    let out = Command::new("cargo")
-        .current_dir(dir)
-        .arg(args[0])
-        .args(&args[1..])
-        .output()?;
-    Ok(String::from_utf8_lossy(if !out.stdout.is_empty() { &out.stdout } else { &out.stderr }).into_owned())
+#[derive(Subcommand)]
+enum Commands {
+    /// Write the input CSV from a GitHub search query
+    Discover {
+        #[command(flatten)]
+        opts: DiscoverOptions,
+    },
+    /// Write the input CSV from the GitHub repositories of published crates
+    DiscoverCrates {
+        #[command(flatten)]
+        opts: DiscoverCratesOptions,
+    },
+    Filter {
+        /// CSV or JSONL file, or `-` for stdin
+        input: String,
+        out: String,
+        #[command(flatten)]
+        opts: FilterOptions,
+    },
+    Clone {
+        names: String,
+        out: String,
+        #[command(flatten)]
+        opts: CloneOptions,
+        #[command(flatten)]
+        dry_run: DryRunOptions,
+    },
+    /// Fetch each repository's GitHub metadata (stars, license, topics, ...) into a JSONL file
+    Metadata {
+        names: String,
+        out: String,
+        #[command(flatten)]
+        opts: MetadataOptions,
+    },
+    Outputs {
+        root: String,
+        outputs: String,
+        #[command(flatten)]
+        opts: Box<AnalyzeOptions>,
+        #[command(flatten)]
+        dry_run: DryRunOptions,
+    },
+    Collect {
+        root: String,
+        code: String,
+        #[command(flatten)]
+        opts: CollectOptions,
+        #[command(flatten)]
+        dry_run: DryRunOptions,
+    },
+    /// Drop exact duplicate files from a code file written by `collect`
+    Dedup {
+        code: String,
+        /// Where to write; the code file itself by default
+        #[arg(long, value_name = "FILE")]
+        out: Option<String>,
+        #[arg(long, value_enum, default_value_t = Dedup::Global)]
+        mode: Dedup,
+    },
+    /// Cluster or drop near-duplicate files of a code file, by MinHash over token shingles
+    NearDedup {
+        code: String,
+        /// Where to write; the code file itself by default
+        #[arg(long, value_name = "FILE")]
+        out: Option<String>,
+        #[command(flatten)]
+        opts: NearDedupOptions,
+    },
+    /// Write every unsafe block, fn, impl and trait of the checkouts under `root` to `out`, with
+    /// their context and `// SAFETY:` comments, and counts per repository beside it
+    UnsafeExtract {
+        root: String,
+        out: String,
+        #[command(flatten)]
+        opts: UnsafeOptions,
+    },
+    /// Write the doc comment, signature and body of every documented public item of the
+    /// checkouts under `root` to `out`
+    DocPairs {
+        root: String,
+        #[arg(default_value = DEFAULT_DOC_PAIRS_FILE)]
+        out: String,
+        #[command(flatten)]
+        opts: DocPairsOptions,
+    },
+    /// Write every Cargo.toml and Cargo.lock of the checkouts under `root` to `out`, parsed into
+    /// packages, features, dependencies and workspace membership, or locked packages
+    Manifests {
+        root: String,
+        #[arg(default_value = DEFAULT_MANIFESTS_FILE)]
+        out: String,
+        #[command(flatten)]
+        opts: ManifestsOptions,
+    },
+    /// Write the code file's entries with their repository's outputs and metadata attached, and
+    /// the JSON Schema of its lines beside it
+    Join {
+        #[command(flatten)]
+        opts: JoinOptions,
+    },
+    /// Write a vulnerability label per file of the code file from its repository's clippy, semgrep
+    /// and CodeQL results, and the findings in files the code file lacks beside it
+    Label {
+        #[command(flatten)]
+        opts: LabelOptions,
+    },
+    /// Write train, validation and test files with each repository in exactly one of them
+    Split {
+        #[command(flatten)]
+        opts: SplitOptions,
+    },
+    /// Load the code and outputs files into a SQLite database with indexed tables
+    ExportSqlite {
+        #[command(flatten)]
+        opts: ExportSqliteOptions,
+    },
+    /// Write the code and outputs files as a Hugging Face dataset directory, and push it to the Hub
+    ExportHf {
+        #[command(flatten)]
+        opts: ExportHfOptions,
+    },
+    /// Summarize a code file and an outputs file: totals, languages, size histograms, tool
+    /// outcomes, the slowest repositories, clippy warnings and unsafe counts
+    Stats {
+        #[command(flatten)]
+        opts: StatsOptions,
+    },
+    /// Merge outputs or code files from several machines, keeping the newest entry of each
+    /// repository (each file, for code) and recording the ones that disagree
+    Merge {
+        #[command(flatten)]
+        opts: MergeOptions,
+    },
+    /// Upload files to S3, an S3-compatible store or GCS in multipart uploads, a shard manifest with
+    /// its shards, skipping those already there with the same checksum
+    Upload {
+        /// Files and shard manifests
+        #[arg(required = true)]
+        files: Vec<String>,
+        /// `s3://bucket/prefix` or `gs://bucket/prefix`
+        #[arg(long, value_name = "URL")]
+        to: String,
+        #[command(flatten)]
+        store: StoreOptions,
+    },
+    /// Convert a code or outputs file from JSONL to Parquet, or from Parquet (`.parquet`) back to
+    /// JSONL, checking that no row is lost; `--upgrade` rewrites old lines in the newest schema
+    Convert {
+        input: String,
+        output: String,
+        #[command(flatten)]
+        opts: ConvertOptions,
+    },
+    /// Check a JSONL file (code, outputs, metadata or labels) line by line against its schema,
+    /// for duplicates and for truncation, or a shard manifest's shards against their checksums
+    Validate {
+        #[command(flatten)]
+        opts: ValidateOptions,
+    },
+    // `--format` is the filter's input format here; `full` writes the outputs file as JSONL.
+    /// Run the whole pipeline: filter → clone (→ metadata) → outputs → collect (→ upload), or
+    /// some of its steps with --from-step and --skip-steps; each is recorded in pipeline_state.json
+    #[command(mut_arg("output_format", |a| a.long("outputs-format").hide(true)))]
+    Full {
+        #[command(flatten)]
+        pipeline: PipelineFlags,
+        #[command(flatten)]
+        filter: Box<FilterOptions>,
+        #[command(flatten)]
+        opts: Box<AnalyzeOptions>,
+        #[command(flatten)]
+        stream: StreamOptions,
+        #[command(flatten)]
+        auth: AuthOptions,
+        #[command(flatten)]
+        upload: UploadTarget,
+        #[command(flatten)]
+        steps: StepOptions,
+        /// Run `doctor` first and stop if a required check fails
+        #[arg(long)]
+        preflight: bool,
+        #[command(flatten)]
+        dry_run: DryRunOptions,
+    },
+    /// Check the tools, toolchain, token, disk space and output paths `full` would need
+    Doctor {
+        #[command(flatten)]
+        pipeline: PipelineFlags,
+        #[command(flatten)]
+        opts: Box<AnalyzeOptions>,
+        #[command(flatten)]
+        doctor: DoctorOptions,
+    },
+    /// Manage the `dataset_builder.toml` that `full` reads
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Fetch the existing checkouts, move them to their remote's default branch, and analyze (and
+    /// with --code collect) again only the repositories that changed or failed before
+    Update {
+        #[command(flatten)]
+        opts: UpdateOptions,
+    },
+    /// Remove build directories, CodeQL databases, git histories or whole checkouts under the
+    /// datasets root, and print the space reclaimed
+    Clean {
+        #[command(flatten)]
+        opts: CleanOptions,
+    },
+    /// Delete `--cache-dir` entries that haven't been written or reused recently
+    CacheGc {
+        #[arg(env = "DATASET_BUILDER_CACHE_DIR")]
+        cache_dir: String,
+        /// Remove entries unused for more than this many days
+        #[arg(long, default_value_t = 30)]
+        older_than_days: u64,
+    },
 }
 
-fn run_ext_cmd(dir: &Path, cmd: &str, args: &[&str]) -> anyhow::Result<String> {
-    let out = Command::new(cmd)
-        .current_dir(dir)
-        .args(args)
-        .output()?;
-    Ok(String::from_utf8_lossy(if !out.stdout.is_empty() { &out.stdout } else { &out.stderr }).into_owned())
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Write a commented template with every setting at its default
+    Init {
+        #[arg(default_value = DEFAULT_CONFIG_FILE)]
+        path: String,
+        /// Replace the file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
 }
 
-fn collect_code_all(root: &str, code_file: &str) -> anyhow::Result<()> {
-    let mut w = BufWriter::new(File::create(code_file)?);
-    for entry in fs::read_dir(root)? {
-        let path = entry?.path();
-        if !path.is_dir() { continue; }
-        let name = path.file_name().unwrap().to_string_lossy().into_owned();
-        for mut ce in collect_code(&path)? {
-            ce.name = name.clone();
-            serde_json::to_writer(&mut w, &ce)?;
-            w.write_all(b"\n")?;
+fn main() -> anyhow::Result<()> {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches)?;
+    init_logging(cli.verbose, cli.quiet, cli.log_json);
+    install_signal_handler(Duration::from_secs(cli.shutdown_grace));
+    // Log lines would tear through the bars.
+    if cli.no_progress || cli.verbose > 0 || cli.log_json {
+        disable_progress();
+    }
+    // The value isn't repeated: it is the token.
+    anyhow::ensure!(
+        matches.value_source("token") != Some(ValueSource::CommandLine),
+        "refusing a GitHub token given as an argument, where it stays in the shell history and shows in `ps`; export GITHUB_TOKEN, or use --token-file <path> or --token-cmd <command> (e.g. --token-cmd 'gh auth token')"
+    );
+    if let Some((_, sub)) = matches.subcommand() {
+        anyhow::ensure!(
+            !sub.ids().any(|id| id == "ssh_key_passphrase") || sub.value_source("ssh_key_passphrase") != Some(ValueSource::CommandLine),
+            "refusing an SSH key passphrase given as an argument, where it stays in the shell history and shows in `ps`; export SSH_KEY_PASSPHRASE instead"
+        );
+    }
+    let tokens = Tokens::load(cli.token.as_deref(), cli.token_file.as_deref(), cli.token_cmd.as_deref())?;
+    match cli.command {
+        Commands::Discover { opts } => {
+            let summary = discover_repos(&opts, &tokens)?;
+            println!("✓ Wrote {} repositories to {} ({} requests, {} date ranges)", summary.repos, opts.out, summary.requests, summary.slices);
+        }
+        Commands::DiscoverCrates { opts } => {
+            let s = discover_crates(&opts)?;
+            println!(
+                "✓ Wrote {} repositories to {} ({} crates: {} below --min-downloads, {} not on GitHub)",
+                s.repos, opts.out, s.crates, s.below_min_downloads, s.not_on_github
+            );
+        }
+        Commands::Filter { input, out, opts } => {
+            let summary = filter_csv_with(&input, &out, &opts)?;
+            println!("✓ {} ({})", summary, out);
+            for (flag, eliminated) in &summary.eliminated {
+                println!("  {} eliminated by {}", eliminated, flag);
+            }
+        }
+        Commands::Clone { names, out, opts, dry_run } if dry_run.dry_run => print_plan(&Plan { clone: Some(plan_clone(&names, &out, &opts)?), ..Plan::default() }, dry_run.json)?,
+        Commands::Clone { names, out, opts, .. } => {
+            let summary = clone_repos(&names, &out, &tokens, &opts)?;
+            if summary.failure_rate() > opts.max_failure_rate {
+                anyhow::bail!("{} repositories failed to clone ({:.1}%, more than --max-failure-rate); retry them with --retry-from {}",
+                    summary.failed.len(), summary.failure_rate() * 100.0, opts.errors_file);
+            }
+            if shutdown_requested() {
+                anyhow::bail!("interrupted; run again to clone the rest (existing checkouts are skipped)");
+            }
+            if summary.budget_exhausted {
+                anyhow::bail!("stopped at the --max-total-gb budget; clone the rest elsewhere or with a larger budget with --retry-from {}", opts.errors_file);
+            }
+        }
+        Commands::Metadata { names, out, opts } => {
+            let s = fetch_metadata(&names, &out, &opts, &tokens)?;
+            println!(
+                "✓ Wrote the metadata of {} repositories to {} ({} fetched, {} unchanged, {} not found; {} requests)",
+                s.repos, out, s.fetched, s.unchanged, s.not_found, s.requests
+            );
+            if shutdown_requested() {
+                anyhow::bail!("interrupted; run again to fetch the rest");
+            }
+        }
+        Commands::Outputs { root, outputs, opts, dry_run } if dry_run.dry_run => print_plan(&Plan { outputs: Some(plan_outputs(&root, &outputs, &opts, &[])?), ..Plan::default() }, dry_run.json)?,
+        Commands::Outputs { root, outputs, opts, .. } => run_outputs(&root, &outputs, &opts)?,
+        Commands::Collect { root, code, opts, dry_run } if dry_run.dry_run => print_plan(&Plan { collect: Some(plan_collect(&root, &code, &opts, &[])?), ..Plan::default() }, dry_run.json)?,
+        Commands::Collect { root, code, opts, .. } => collect_code_all(&root, &code, &opts)?,
+        Commands::Dedup { code, out, mode } => {
+            let out = out.unwrap_or_else(|| code.clone());
+            let s = dedup_code(&code, &out, mode)?;
+            println!("✓ Wrote {} of {} entries to {} ({} duplicates, {} bytes, removed)", s.entries - s.removed, s.entries, out, s.removed, s.removed_bytes);
+        }
+        Commands::NearDedup { code, out, opts } => {
+            let out = out.unwrap_or_else(|| code.clone());
+            let s = near_dedup_code(&code, &out, &opts)?;
+            println!("✓ Wrote {} of {} entries to {} ({} in {} near-duplicate clusters; {} removed, {} bytes)", s.entries - s.removed, s.entries, out, s.clustered, s.clusters, s.removed, s.removed_bytes);
+        }
+        Commands::UnsafeExtract { root, out, opts } => {
+            let summaries = extract_unsafe_all(&root, &out, &opts)?;
+            let sites: usize = summaries.iter().map(|s| s.blocks + s.fns + s.impls + s.traits).sum();
+            let documented: usize = summaries.iter().map(|s| s.documented).sum();
+            println!("✓ Wrote {} unsafe sites of {} repositories to {} ({} with a SAFETY comment)", sites, summaries.len(), out, documented);
+        }
+        Commands::DocPairs { root, out, opts } => {
+            let s = extract_doc_pairs_all(&root, &out, &opts)?;
+            println!("✓ Wrote {} doc pairs from {} files of {} repositories to {} ({} docs too short, {} files unparsable)", s.pairs, s.files, s.repos, out, s.short_docs, s.parse_failed);
+        }
+        Commands::Manifests { root, out, opts } => {
+            let s = extract_manifests_all(&root, &out, &opts)?;
+            println!("✓ Wrote {} manifests and {} lockfiles of {} repositories to {} ({} unparsable)", s.manifests, s.lockfiles, s.repos, out, s.parse_failed);
+        }
+        Commands::Join { opts } => {
+            let s = join_datasets(&opts)?;
+            println!("✓ Wrote {} entries to {} ({} diagnostics attached)", s.entries, opts.out, s.diagnostics);
+            for (repos, what) in [(&s.code_without_outputs, "in the code file have no outputs"), (&s.outputs_without_code, "have outputs but no code"), (&s.code_without_metadata, "in the code file have no metadata"), (&s.metadata_without_code, "have metadata but no code")] {
+                if !repos.is_empty() {
+                    println!("  {} repositories {}: {}", repos.len(), what, repos.join(", "));
+                }
+            }
+        }
+        Commands::Label { opts } => {
+            let s = label_files(&opts)?;
+            println!("✓ Wrote {} labels of {} repositories to {} ({} flagged at {} or above)", s.files, s.repos, opts.out, s.flagged, opts.min_severity.name());
+            if s.unmatched_files > 0 {
+                println!("  {} findings in {} files not in the code file: {}", s.unmatched_findings, s.unmatched_files, unmatched_findings_beside(&opts.out).display());
+            }
+            if !s.repos_without_outputs.is_empty() {
+                println!("  {} repositories in the code file have no outputs: {}", s.repos_without_outputs.len(), s.repos_without_outputs.join(", "));
+            }
+        }
+        Commands::Split { opts } => {
+            let map = split_dataset(&opts)?;
+            println!("✓ Split {} repositories of {} (seed {})", map.repos.len(), opts.input, opts.seed);
+            for split in SPLITS {
+                let c = &map.counts[split];
+                println!("  {:<5} {:>6} repositories {:>9} files {:>14} bytes", split, c.repos, c.files, c.bytes);
+            }
+        }
+        Commands::ExportSqlite { opts } => {
+            let s = export_sqlite(&opts)?;
+            println!("✓ Wrote {} repositories, {} files, {} tool results and {} findings to {}", s.repos, s.files, s.tool_results, s.findings, opts.out);
+        }
+        Commands::ExportHf { opts } => {
+            let s = export_hf(&opts)?;
+            for (config, splits) in &s.splits {
+                for (split, info) in splits {
+                    println!("  {}/{}: {} rows in {} shards", config, split, info.rows, info.shards.len());
+                }
+            }
+            println!("✓ Wrote the dataset to {}", opts.out);
+            if let Some(repo_id) = &opts.repo_id {
+                println!("✓ Pushed to {}/datasets/{} ({} shards uploaded, {} already on the Hub)", opts.hf_endpoint.trim_end_matches('/'), repo_id, s.uploaded, s.already_uploaded);
+            }
+        }
+        Commands::Stats { opts } => {
+            let stats = corpus_stats(&opts)?;
+            print!("{}", stats.text());
+            if let Some(report) = &opts.report {
+                stats.write_report(report)?;
+                println!("✓ Wrote the report to {}", report);
+            }
+        }
+        Commands::Merge { opts } => {
+            let s = merge_files(&opts)?;
+            println!("✓ Merged {} entries of {} files into {} in {} ({} duplicates dropped)", s.read, opts.inputs.len(), s.written, opts.out, s.duplicates);
+            if s.conflicts > 0 {
+                println!("  {} duplicates had different results, see {}", s.conflicts, merge_conflicts_beside(&opts.out).display());
+            }
+        }
+        Commands::Upload { files, to, store } => {
+            let s = upload_files(&files, &to, &store)?;
+            println!("✓ Uploaded {} objects ({} bytes) to {}, {} already there", s.uploaded, s.bytes, to, s.skipped);
+        }
+        Commands::Convert { input, output, opts } => {
+            let rows = convert(&input, &output, &opts)?;
+            println!("✓ Converted {} rows of {} to {}", rows, input, output);
+        }
+        Commands::Validate { opts } => {
+            validate_file(&opts)?;
+        }
+        Commands::Full { pipeline: flags, filter, opts, stream, auth, upload, steps, preflight, dry_run } => {
+            let pipeline = Pipeline { tokens, filter: *filter, analyze: *opts, stream, auth, upload, steps, ..Pipeline::default() };
+            let pipeline = configure(pipeline, &flags, &matches)?;
+            if dry_run.dry_run {
+                return print_plan(&pipeline.plan()?, dry_run.json);
+            }
+            if preflight {
+                run_doctor(&pipeline, &DoctorOptions { fix_hints: true, ..DoctorOptions::default() })?;
+            }
+            pipeline.run()?
+        }
+        Commands::Doctor { pipeline: flags, opts, doctor } => {
+            let pipeline = configure(Pipeline { tokens, analyze: *opts, ..Pipeline::default() }, &flags, &matches)?;
+            run_doctor(&pipeline, &doctor)?
+        }
+        Commands::Config { action: ConfigAction::Init { path, force } } => {
+            write_config_template(&path, force)?;
+            println!("✓ Wrote {}; edit it, then run `dataset_builder full`", path);
+        }
+        Commands::Update { opts } => {
+            let s = update_repos(&opts, &tokens)?;
+            println!(
+                "✓ {} checkouts: {} updated, {} reset after a force-push, {} unchanged, {} gone, {} failed, {} skipped",
+                s.records.len(),
+                s.count(UpdateStatus::Updated),
+                s.count(UpdateStatus::Reset),
+                s.count(UpdateStatus::Unchanged),
+                s.count(UpdateStatus::Gone),
+                s.count(UpdateStatus::Failed),
+                s.count(UpdateStatus::Skipped)
+            );
+            println!("  {} repositories analyzed again into {}", s.reanalyzed(), opts.outputs);
+            if let Some(code) = &opts.code {
+                println!("  {} files collected again into {}", s.files_collected, code);
+            }
+            println!("  changelog: {}", s.changelog);
+        }
+        Commands::Clean { opts } => {
+            let s = clean_dataset(&opts)?;
+            if opts.dry_run {
+                for d in &s.dirs {
+                    println!("{} {} ({} files, {})", d.kind.name(), d.path.display(), d.files, human_bytes(d.bytes));
+                }
+            }
+            for (kind, bytes) in &s.bytes {
+                println!("  {}: {} in {} directories", kind.name(), human_bytes(*bytes), s.dirs.iter().filter(|d| d.kind == *kind).count());
+            }
+            let total = human_bytes(s.bytes.values().sum());
+            if opts.dry_run {
+                println!("✓ Would reclaim {} under {}", total, opts.root);
+            } else {
+                println!("✓ Reclaimed {} under {}", total, opts.root);
+            }
+            if !s.errors.is_empty() {
+                println!("  {} paths couldn't be read or removed and were skipped", s.errors.len());
+            }
+        }
+        Commands::CacheGc { cache_dir, older_than_days } => {
+            let removed = cache_gc(&cache_dir, Duration::from_secs(older_than_days * 24 * 60 * 60))?;
+            println!("✓ Removed {} cache entries unused for {} days from {}", removed, older_than_days, cache_dir);
         }
     }
     Ok(())
 }
 
-fn collect_code(repo_path: &Path) -> anyhow::Result<Vec<CodeEntry>> {
-    let mut entries = Vec::new();
-    WalkBuilder::new(repo_path)
-        .standard_filters(true)
-        .build()
-        .filter_map(Result::ok)
-        .filter(|d| d.file_type().map(|t| t.is_file()).unwrap_or(false))
-        .filter(|d| {
-            let p = d.path();
-            !p.starts_with(repo_path.join("target"))
-                && !p.starts_with(repo_path.join(".idea"))
-                && !p.starts_with(repo_path.join(".vscode"))
-        })
-        .for_each(|d| {
-            if let Ok(content) = fs::read_to_string(d.path()) {
-                entries.push(CodeEntry {
-                    name: String::new(),
-                    path: d.path().strip_prefix(repo_path).unwrap().display().to_string(),
-                    content,
-                });
-            }
-        });
-    Ok(entries)
-}
-
-fn sanitize(name: &str) -> String {
-    name.replace('/', "_")
-}
-
-fn run_full(token: &str) -> anyhow::Result<()> {
-    println!("Starting full dataset extraction pipeline...");
-    
-    // Step 1: Filter CSV (assuming input.csv exists)
-    let input_csv = "input.csv";
-    let filtered_repos = "filtered_repos.txt";
-    
-    if std::path::Path::new(input_csv).exists() {
-        println!("Step 1/4: Filtering repositories from {}", input_csv);
-        filter_csv(input_csv, filtered_repos)?;
-        println!("✓ Filtered repositories saved to {}", filtered_repos);
-    } else {
-        println!("⚠ Warning: {} not found, skipping filter step", input_csv);
-        println!("  Create input.csv with columns: id,name,has_toml,has_lock");
-        return Ok(());
+/// Layers the config file and then `flags` over `pipeline`, keeping the analysis options given
+/// on the command line of the subcommand in `matches`.
+fn configure(mut pipeline: Pipeline, flags: &PipelineFlags, matches: &ArgMatches) -> anyhow::Result<Pipeline> {
+    if let Some(config) = Config::find(flags.config.as_deref())? {
+        let (_, sub) = matches.subcommand().expect("a subcommand was parsed");
+        config.apply(&mut pipeline, |id| sub.value_source(id).is_some_and(|s| s != ValueSource::DefaultValue));
     }
-    
-    // Step 2: Clone repositories
-    let datasets_dir = "datasets";
-    println!("Step 2/4: Cloning repositories to {}/", datasets_dir);
-    clone_repos(filtered_repos, datasets_dir, token)?;
-    println!("✓ Repositories cloned successfully");
-    
-    // Step 3: Run analysis and collect outputs
-    let outputs_file = "outputs.jsonl";
-    println!("Step 3/4: Running analysis tools and collecting outputs");
-    run_outputs(datasets_dir, outputs_file)?;
-    println!("✓ Analysis outputs saved to {}", outputs_file);
-    
-    // Step 4: Collect source code
-    let code_file = "code.jsonl";
-    println!("Step 4/4: Collecting source code from repositories");
-    collect_code_all(datasets_dir, code_file)?;
-    println!("✓ Source code collected to {}", code_file);
-    
-    println!("\n🎉 Full pipeline completed successfully!");
-    println!("Generated files:");
-    println!("  - {}: Repository analysis and tool outputs", outputs_file);
-    println!("  - {}: Source code from all repositories", code_file);
-    println!("  - {}/: Downloaded repository datasets", datasets_dir);
-    
-    Ok(())
+    flags.apply(&mut pipeline);
+    Ok(pipeline)
 }
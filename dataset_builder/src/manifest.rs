@@ -0,0 +1,162 @@
+//! `manifest.json`: the environment and tool versions of an analysis run.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use serde::Serialize;
+
+use crate::analyze::AnalyzeOptions;
+use crate::analyzers::builtin_analyzers;
+use crate::sandbox::HOST;
+use crate::tool::{run_in, ToolEnv};
+
+/// Command used to probe each tool; the key is the name of the analyzer it gates.
+pub(crate) const TOOL_PROBES: &[(&str, &str, &[&str])] = &[
+    ("clippy", "cargo", &["clippy", "--version"]),
+    ("fmt", "cargo", &["fmt", "--version"]),
+    ("audit", "cargo", &["audit", "--version"]),
+    ("auditable", "cargo", &["auditable", "--version"]),
+    ("deny", "cargo", &["deny", "--version"]),
+    ("geiger", "cargo", &["geiger", "--version"]),
+    ("tree", "cargo", &["tree", "--version"]),
+    ("udeps", "cargo", &["udeps", "--version"]),
+    ("outdated", "cargo", &["outdated", "--version"]),
+    ("machete", "cargo", &["machete", "--version"]),
+    ("tokei", "tokei", &["--version"]),
+    ("vet", "cargo", &["vet", "--version"]),
+    ("crev", "cargo", &["crev", "--version"]),
+    ("expand", "cargo", &["expand", "--version"]),
+    ("ast", "cargo", &["+nightly", "--version"]),
+    ("hir", "cargo", &["+nightly", "--version"]),
+    ("mir", "cargo", &["+nightly", "--version"]),
+    ("semgrep", "semgrep", &["--version"]),
+    ("codeql", "codeql", &["--version"]),
+];
+
+/// Which tools answered their `--version` probe, and with what.
+#[derive(Debug, Serialize)]
+#[serde(transparent)]
+pub struct ToolInventory {
+    pub versions: BTreeMap<&'static str, Option<String>>,
+}
+
+impl ToolInventory {
+    /// Probes each tool where it will run: inside `opts.sandbox`, or on the host for analyzers
+    /// that don't need one, with `opts.tool_env` set.
+    pub fn probe(opts: &AnalyzeOptions) -> Self {
+        // An empty directory to mount, since the sandbox's own /tmp shadows the real one.
+        let scratch = std::env::temp_dir().join("dataset_builder_probe");
+        let _ = fs::create_dir_all(&scratch);
+        let builtins = builtin_analyzers();
+        let on_host: Vec<&str> = builtins.iter().filter(|a| !a.needs_sandbox()).map(|a| a.name()).collect();
+        let versions = TOOL_PROBES
+            .iter()
+            .map(|&(tool, cmd, args)| {
+                let sandbox = if on_host.contains(&tool) { &HOST } else { &opts.sandbox };
+                let env = ToolEnv { target_dir: None, vars: &opts.tool_env };
+                let version = run_in(sandbox, &scratch, &scratch, cmd, args, env, Some(Instant::now() + Duration::from_secs(60)))
+                    .ok()
+                    .filter(|out| out.exit_code == Some(0))
+                    .map(|out| out.stdout.trim().to_string());
+                (tool, version)
+            })
+            .collect();
+        ToolInventory { versions }
+    }
+
+    /// False only for probed tools that didn't answer; tools without a probe are assumed present.
+    pub fn is_installed(&self, tool: &str) -> bool {
+        self.versions.get(tool).is_none_or(Option::is_some)
+    }
+
+    pub fn missing(&self) -> Vec<&'static str> {
+        self.versions.iter().filter(|(_, v)| v.is_none()).map(|(t, _)| *t).collect()
+    }
+}
+
+/// Environment a run was produced in, written to `manifest.json` next to `outputs.jsonl`.
+#[derive(Debug, Serialize)]
+pub struct RunManifest {
+    pub run_id: String,
+    pub started_at: String,
+    pub dataset_builder_version: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub hostname: Option<String>,
+    /// `cargo --version` and `rustc -vV` of the default toolchain
+    pub toolchain: BTreeMap<&'static str, Option<String>>,
+    /// `--sandbox` the tools ran in (e.g. `docker:<image>`), absent when they ran on the host
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<String>,
+    /// `--shared-target-dir` the cargo-based tools built into, so its artifacts can be found later
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shared_target_dir: Option<String>,
+    /// `--rustc-wrapper` every build ran under
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rustc_wrapper: Option<String>,
+    pub tools: ToolInventory,
+}
+
+impl RunManifest {
+    /// Records the current environment and probes every analysis tool, under a fresh `run_id`.
+    pub fn capture(opts: &AnalyzeOptions) -> Self {
+        let toolchain = [("cargo", "cargo", &["--version"][..]), ("rustc", "rustc", &["-vV"][..])]
+            .into_iter()
+            .map(|(key, cmd, args)| {
+                let out = Command::new(cmd).args(args).output().ok().filter(|o| o.status.success());
+                (key, out.map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string()))
+            })
+            .collect();
+        RunManifest {
+            run_id: uuid::Uuid::new_v4().to_string(),
+            started_at: chrono::Utc::now().to_rfc3339(),
+            dataset_builder_version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            hostname: hostname(),
+            toolchain,
+            sandbox: opts.sandbox.describe(),
+            shared_target_dir: opts.target_root().map(|d| d.display().to_string()),
+            rustc_wrapper: opts.rustc_wrapper.clone(),
+            tools: ToolInventory::probe(opts),
+        }
+    }
+
+    /// Writes the manifest as `manifest.json` in the directory containing `outputs_file`.
+    pub fn write_beside(&self, outputs_file: &str) -> anyhow::Result<()> {
+        let path = Self::path_beside(outputs_file);
+        fs::write(&path, serde_json::to_string_pretty(self)?).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Renames an existing `manifest.json` to `manifest.<run_id>.json`, so entries appended to by
+    /// `--resume` still have the manifest of the run that produced them.
+    pub fn archive_beside(outputs_file: &str) -> anyhow::Result<()> {
+        let path = Self::path_beside(outputs_file);
+        let Ok(text) = fs::read_to_string(&path) else { return Ok(()) };
+        let old: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+        if let Some(run_id) = old["run_id"].as_str() {
+            fs::rename(&path, path.with_file_name(format!("manifest.{}.json", run_id)))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn path_beside(outputs_file: &str) -> PathBuf {
+        Path::new(outputs_file).parent().unwrap_or(Path::new("")).join("manifest.json")
+    }
+}
+
+fn hostname() -> Option<String> {
+    #[cfg(unix)]
+    {
+        let mut buf = [0u8; 256];
+        if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } == 0 {
+            let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+            return Some(String::from_utf8_lossy(&buf[..len]).into_owned());
+        }
+    }
+    std::env::var("COMPUTERNAME").or_else(|_| std::env::var("HOSTNAME")).ok()
+}
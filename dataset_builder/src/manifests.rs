@@ -0,0 +1,329 @@
+//! The `manifests` step: every `Cargo.toml` and `Cargo.lock` of the checkouts, parsed into
+//! package metadata, dependencies and workspace membership, or resolved packages.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::Context;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::collect::{checkouts, collect_code_into, CodeEntry, CodeSource, CollectOptions};
+use crate::jsonl::{Codec, JsonlWriter};
+use crate::progress::Progress;
+
+pub const DEFAULT_MANIFESTS_FILE: &str = "manifests.jsonl";
+
+/// What an inherited field, such as `version.workspace = true`, is recorded as.
+pub const INHERITED: &str = "workspace";
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct ManifestsOptions {
+    /// Also leave out the paths matching this gitignore-style pattern (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    pub exclude_glob: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ManifestKind {
+    /// A `Cargo.toml`
+    #[default]
+    Manifest,
+    /// A `Cargo.lock`
+    Lockfile,
+}
+
+/// One line of `manifests.jsonl`, keyed by `name` and `path`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ManifestEntry {
+    /// As in `CodeEntry::name`
+    pub name: String,
+    #[serde(default)]
+    pub dir: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// Relative to the checkout, e.g. `crates/core/Cargo.toml`
+    pub path: String,
+    pub kind: ManifestKind,
+    /// `[package]`; absent for a virtual manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<Package>,
+    /// `[features]`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub features: BTreeMap<String, Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<Dependency>,
+    /// `[workspace]`, for a workspace root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<WorkspaceInfo>,
+    /// The `path` of the workspace root this package is a member of, its own for a root package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace_root: Option<String>,
+    /// The `[[package]]`s of a `Cargo.lock`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub packages: Vec<LockedPackage>,
+    /// The file isn't valid TOML, so only its `content` is recorded
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub parse_failed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+/// Fields inherited from the workspace are `workspace`, as in `INHERITED`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Package {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edition: Option<String>,
+    /// The MSRV
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rust_version: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyKind {
+    Normal,
+    Dev,
+    Build,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Dependency {
+    /// The name it is used under
+    pub name: String,
+    /// The crate's name, when it is renamed with `package = "..."`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    /// The version requirement; absent for path and git dependencies without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub req: Option<String>,
+    pub kind: DependencyKind,
+    /// The `cfg(...)` or triple of a `[target.'...'.dependencies]` table
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(default)]
+    pub optional: bool,
+    #[serde(default = "default_true")]
+    pub default_features: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+    /// `dep.workspace = true`: the requirement is the workspace's
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub workspace: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct WorkspaceInfo {
+    /// The `members` globs, as written
+    #[serde(default)]
+    pub members: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: String,
+    /// Absent for path dependencies and workspace members
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct ManifestsSummary {
+    pub repos: usize,
+    pub manifests: usize,
+    pub lockfiles: usize,
+    pub parse_failed: usize,
+}
+
+/// Writes the parsed manifests and lockfiles of every checkout under `root` to `out`.
+pub fn extract_manifests_all(root: &str, out: &str, opts: &ManifestsOptions) -> anyhow::Result<ManifestsSummary> {
+    let mut w = JsonlWriter::compressed(File::create(out).with_context(|| format!("failed to write {}", out))?, Codec::of(out), None)?;
+    let repos = checkouts(root)?;
+    let progress = Progress::new(repos.len(), "repositories scanned for manifests");
+    let mut summary = ManifestsSummary { repos: repos.len(), ..ManifestsSummary::default() };
+    for (source, path) in &repos {
+        let bar = progress.start(&path.file_name().unwrap_or_default().to_string_lossy());
+        let s = extract_manifests(path, source, opts, &mut |entry| w.write_entry(&entry))?;
+        summary.manifests += s.manifests;
+        summary.lockfiles += s.lockfiles;
+        summary.parse_failed += s.parse_failed;
+        progress.finish(bar);
+    }
+    progress.done();
+    w.finish()?;
+    Ok(summary)
+}
+
+/// Hands the parsed `Cargo.toml` and `Cargo.lock` files of the checkout, as `collect` would find
+/// them whatever their size, to `emit`, in path order.
+pub fn extract_manifests(repo_path: &Path, source: &CodeSource, opts: &ManifestsOptions, emit: &mut dyn FnMut(ManifestEntry) -> anyhow::Result<()>) -> anyhow::Result<ManifestsSummary> {
+    let collect = CollectOptions { extensions: vec!["toml".into(), "lock".into()], exclude_glob: opts.exclude_glob.clone(), max_file_bytes: 0, ..CollectOptions::default() };
+    let mut files: Vec<CodeEntry> = Vec::new();
+    collect_code_into(repo_path, source, &collect, &mut |entry| {
+        if entry.path.rsplit('/').next().is_some_and(|n| n == "Cargo.toml" || n == "Cargo.lock") {
+            files.push(entry);
+        }
+        Ok(())
+    })?;
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut entries: Vec<ManifestEntry> = files.into_iter().map(parse).collect();
+    assign_workspaces(&mut entries);
+    let mut summary = ManifestsSummary { repos: 1, ..ManifestsSummary::default() };
+    for entry in entries {
+        match entry.kind {
+            ManifestKind::Lockfile => summary.lockfiles += 1,
+            ManifestKind::Manifest => summary.manifests += 1,
+        }
+        summary.parse_failed += usize::from(entry.parse_failed);
+        emit(entry)?;
+    }
+    Ok(summary)
+}
+
+fn parse(file: CodeEntry) -> ManifestEntry {
+    let kind = if file.path.ends_with("Cargo.lock") { ManifestKind::Lockfile } else { ManifestKind::Manifest };
+    let mut entry = ManifestEntry { name: file.name, dir: file.dir, repo: file.repo, commit: file.commit, path: file.path, kind, ..ManifestEntry::default() };
+    let Ok(table) = file.content.parse::<Table>() else {
+        return ManifestEntry { parse_failed: true, content: Some(file.content), ..entry };
+    };
+    match kind {
+        ManifestKind::Lockfile => entry.packages = table.get("package").and_then(Value::as_array).into_iter().flatten().filter_map(locked).collect(),
+        ManifestKind::Manifest => {
+            entry.package = table.get("package").and_then(Value::as_table).map(package);
+            entry.features = table.get("features").and_then(Value::as_table).into_iter().flatten().map(|(k, v)| (k.clone(), strings(v))).collect();
+            entry.dependencies = dependencies(&table, None);
+            for (target, t) in table.get("target").and_then(Value::as_table).into_iter().flatten() {
+                entry.dependencies.extend(t.as_table().map(|t| dependencies(t, Some(target))).unwrap_or_default());
+            }
+            entry.workspace = table.get("workspace").and_then(Value::as_table).map(|w| WorkspaceInfo {
+                members: w.get("members").map(strings).unwrap_or_default(),
+                exclude: w.get("exclude").map(strings).unwrap_or_default(),
+            });
+        }
+    }
+    entry
+}
+
+fn package(p: &Table) -> Package {
+    Package { name: p.get("name").and_then(Value::as_str).unwrap_or_default().to_string(), version: field(p, "version"), edition: field(p, "edition"), rust_version: field(p, "rust-version") }
+}
+
+/// A string field, or `INHERITED` for `{ workspace = true }`.
+fn field(table: &Table, key: &str) -> Option<String> {
+    match table.get(key)? {
+        Value::String(s) => Some(s.clone()),
+        Value::Table(t) if t.get("workspace").and_then(Value::as_bool) == Some(true) => Some(INHERITED.to_string()),
+        _ => None,
+    }
+}
+
+fn strings(value: &Value) -> Vec<String> {
+    value.as_array().into_iter().flatten().filter_map(Value::as_str).map(String::from).collect()
+}
+
+/// The dependencies of the `[dependencies]`, `[dev-dependencies]` and `[build-dependencies]`
+/// tables of `table`, the old `dev_dependencies` spellings included.
+fn dependencies(table: &Table, target: Option<&str>) -> Vec<Dependency> {
+    let kinds = [("dependencies", DependencyKind::Normal), ("dev-dependencies", DependencyKind::Dev), ("dev_dependencies", DependencyKind::Dev), ("build-dependencies", DependencyKind::Build), ("build_dependencies", DependencyKind::Build)];
+    let mut out = Vec::new();
+    for (key, kind) in kinds {
+        for (name, spec) in table.get(key).and_then(Value::as_table).into_iter().flatten() {
+            let mut dep = Dependency { name: name.clone(), package: None, req: None, kind, target: target.map(String::from), optional: false, default_features: true, features: Vec::new(), workspace: false };
+            match spec {
+                Value::String(req) => dep.req = Some(req.clone()),
+                Value::Table(t) => {
+                    dep.package = t.get("package").and_then(Value::as_str).map(String::from);
+                    dep.req = t.get("version").and_then(Value::as_str).map(String::from);
+                    dep.optional = t.get("optional").and_then(Value::as_bool).unwrap_or(false);
+                    dep.default_features = t.get("default-features").or_else(|| t.get("default_features")).and_then(Value::as_bool).unwrap_or(true);
+                    dep.features = t.get("features").map(strings).unwrap_or_default();
+                    dep.workspace = t.get("workspace").and_then(Value::as_bool).unwrap_or(false);
+                }
+                _ => {}
+            }
+            out.push(dep);
+        }
+    }
+    out
+}
+
+fn locked(value: &Value) -> Option<LockedPackage> {
+    let p = value.as_table()?;
+    let get = |key: &str| p.get(key).and_then(Value::as_str).map(String::from);
+    Some(LockedPackage { name: get("name")?, version: get("version")?, source: get("source"), checksum: get("checksum") })
+}
+
+/// Sets `workspace_root` on the packages that a workspace root's `members` globs (or the root
+/// itself) cover and its `exclude` doesn't.
+fn assign_workspaces(entries: &mut [ManifestEntry]) {
+    let roots: Vec<(String, WorkspaceInfo)> = entries.iter().filter_map(|e| Some((e.path.clone(), e.workspace.clone()?))).collect();
+    for entry in entries.iter_mut().filter(|e| e.package.is_some()) {
+        let dir = parent(&entry.path);
+        entry.workspace_root = roots
+            .iter()
+            .filter(|(root, ws)| {
+                let base = parent(root);
+                let Some(rel) = relative(dir, base) else { return false };
+                rel.is_empty() || (ws.members.iter().any(|m| glob_match(m.trim_end_matches('/'), rel)) && !ws.exclude.iter().any(|x| rel.starts_with(x.trim_end_matches('/'))))
+            })
+            // The nearest root wins, as for nested workspaces.
+            .max_by_key(|(root, _)| root.len())
+            .map(|(root, _)| root.clone());
+    }
+}
+
+/// The directory of a manifest path, `` for the checkout's root.
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+/// `dir` relative to `base`, when it is under it.
+fn relative<'a>(dir: &'a str, base: &str) -> Option<&'a str> {
+    match base.is_empty() {
+        true => Some(dir),
+        false if dir == base => Some(""),
+        false => dir.strip_prefix(base)?.strip_prefix('/'),
+    }
+}
+
+/// Whether `path` matches `pattern` segment by segment, where `*` matches within a segment and
+/// `**` any number of segments, as in `members = ["crates/*"]`.
+fn glob_match(pattern: &str, path: &str) -> bool {
+    fn segments(pattern: &[&str], path: &[&str]) -> bool {
+        match (pattern.first(), path.first()) {
+            (None, None) => true,
+            (Some(&"**"), _) => segments(&pattern[1..], path) || (!path.is_empty() && segments(pattern, &path[1..])),
+            (Some(p), Some(s)) => wildcard(p.as_bytes(), s.as_bytes()) && segments(&pattern[1..], &path[1..]),
+            _ => false,
+        }
+    }
+    fn wildcard(p: &[u8], s: &[u8]) -> bool {
+        match (p.first(), s.first()) {
+            (None, None) => true,
+            (Some(b'*'), _) => wildcard(&p[1..], s) || (!s.is_empty() && wildcard(p, &s[1..])),
+            (Some(b'?'), Some(_)) => wildcard(&p[1..], &s[1..]),
+            (Some(a), Some(b)) => a == b && wildcard(&p[1..], &s[1..]),
+            _ => false,
+        }
+    }
+    let pattern: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty() && *s != ".").collect();
+    let path: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    segments(&pattern, &path)
+}
@@ -0,0 +1,205 @@
+//! `merge`: the outputs or code files of several machines as one, with each repository (each
+//! file, for code) once.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::analyze::OutputEntry;
+use crate::collect::CodeEntry;
+use crate::columnar::DatasetKind;
+use crate::join::diagnostics;
+use crate::jsonl::{open_jsonl, Codec, JsonlWriter};
+use crate::schema::from_line;
+
+pub const MERGE_CONFLICTS_FILE: &str = "merge_conflicts.jsonl";
+
+#[derive(Args, Clone, Debug)]
+pub struct MergeOptions {
+    /// Outputs or code files, compressed or not, or shard manifests; on a tie, later ones win
+    #[arg(required = true)]
+    pub inputs: Vec<String>,
+    #[arg(long, value_name = "FILE")]
+    pub out: String,
+    /// What the inputs are, `outputs` or `code` (default: from the first line)
+    #[arg(long, value_enum)]
+    pub kind: Option<DatasetKind>,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        MergeOptions { inputs: Vec::new(), out: "merged.jsonl".into(), kind: None }
+    }
+}
+
+/// A line of `merge_conflicts.jsonl`: an entry in more than one input whose results differ.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct MergeConflict {
+    /// The repository's `name`, with a workspace member's `manifest_path` or a code entry's
+    /// `path` after a `:`
+    pub key: String,
+    /// The input whose entry was kept
+    pub kept: String,
+    pub dropped: String,
+    /// What differs, as `what: dropped → kept`
+    pub differences: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct MergeSummary {
+    pub read: usize,
+    pub written: usize,
+    /// Entries dropped for a newer one with the same key
+    pub duplicates: usize,
+    pub conflicts: usize,
+    /// Duplicates without a run timestamp on both sides, which the later input won
+    pub last_wins: usize,
+}
+
+/// Where `merge` records the conflicts of `out`.
+pub fn merge_conflicts_beside(out: &str) -> PathBuf {
+    Path::new(out).parent().unwrap_or(Path::new("")).join(MERGE_CONFLICTS_FILE)
+}
+
+/// The entry of a key that is winning so far.
+struct Kept {
+    input: usize,
+    line: usize,
+    started_at: Option<String>,
+    facts: BTreeMap<String, String>,
+}
+
+/// The `started_at` of every run whose `manifest.json` (or archived `manifest.<run_id>.json`)
+/// is in `dir`.
+fn run_starts(dir: &Path) -> HashMap<String, String> {
+    let Ok(entries) = fs::read_dir(if dir.as_os_str().is_empty() { Path::new(".") } else { dir }) else { return HashMap::new() };
+    let mut starts = HashMap::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !(name.starts_with("manifest.") && name.ends_with(".json")) {
+            continue;
+        }
+        let Ok(m) = fs::read_to_string(entry.path()).map(|t| serde_json::from_str::<Value>(&t).unwrap_or_default()) else { continue };
+        if let (Some(run_id), Some(started_at)) = (m["run_id"].as_str(), m["started_at"].as_str()) {
+            starts.insert(run_id.to_string(), started_at.to_string());
+        }
+    }
+    starts
+}
+
+/// What has to agree between two entries with the same key for them to be the same result.
+fn output_facts(o: &OutputEntry) -> BTreeMap<String, String> {
+    let mut facts: BTreeMap<String, String> = o.tools.iter().map(|(tool, t)| (tool.clone(), serde_json::to_value(&t.result.status).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default())).collect();
+    facts.insert("commit".into(), o.commit.clone().unwrap_or_default());
+    facts.insert("findings".into(), diagnostics(o).len().to_string());
+    facts
+}
+
+fn code_facts(e: &CodeEntry) -> BTreeMap<String, String> {
+    BTreeMap::from([("content_hash".to_string(), e.content_hash.clone()), ("commit".to_string(), e.commit.clone().unwrap_or_default())])
+}
+
+/// `what: dropped → kept` for every fact that differs, a fact one side lacks included.
+fn differences(dropped: &BTreeMap<String, String>, kept: &BTreeMap<String, String>) -> Vec<String> {
+    let keys: BTreeSet<&String> = dropped.keys().chain(kept.keys()).collect();
+    let show = |v: Option<&String>| v.map_or("-".to_string(), |v| if v.is_empty() { "none".into() } else { v.clone() });
+    keys.into_iter().filter(|k| dropped.get(*k) != kept.get(*k)).map(|k| format!("{}: {} → {}", k, show(dropped.get(k)), show(kept.get(k)))).collect()
+}
+
+/// The kind of `file` from its first line.
+fn detect(file: &str) -> anyhow::Result<Option<DatasetKind>> {
+    for line in open_jsonl(file)?.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            return Ok(serde_json::from_str(&line).ok().as_ref().and_then(DatasetKind::detect));
+        }
+    }
+    Ok(None)
+}
+
+/// Merges `opts.inputs` into `opts.out` in two passes over them: the first picks the entry to
+/// keep for every key, newest run first, and the second copies those lines as they are.
+pub fn merge_files(opts: &MergeOptions) -> anyhow::Result<MergeSummary> {
+    anyhow::ensure!(!opts.inputs.iter().any(|i| Path::new(i) == Path::new(&opts.out)), "--out {} is one of the inputs", opts.out);
+    let kind = match opts.kind {
+        Some(kind) => kind,
+        None => opts.inputs.iter().find_map(|i| detect(i).transpose()).transpose()?.context("every input is empty; pass --kind")?,
+    };
+    let mut summary = MergeSummary::default();
+    let mut kept: HashMap<String, Kept> = HashMap::new();
+    let mut conflicts = Vec::new();
+    for (input, file) in opts.inputs.iter().enumerate() {
+        let starts = run_starts(Path::new(file).parent().unwrap_or(Path::new("")));
+        for (n, line) in open_jsonl(file)?.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid = || format!("{}:{}: not a valid {} entry; `validate --repair` drops bad lines", file, n + 1, if kind == DatasetKind::Code { "code" } else { "outputs" });
+            let (key, started_at, facts) = match kind {
+                DatasetKind::Outputs => {
+                    let o: OutputEntry = from_line(&line).with_context(invalid)?;
+                    let member = o.workspace_root.is_some().then_some(o.manifest_path.as_deref()).flatten();
+                    (member.map_or(o.name.clone(), |m| format!("{}:{}", o.name, m)), starts.get(&o.run_id).cloned(), output_facts(&o))
+                }
+                DatasetKind::Code => {
+                    let e: CodeEntry = from_line(&line).with_context(invalid)?;
+                    let path = e.item.as_ref().map_or(e.path.clone(), |i| format!("{}#{}", e.path, i.path));
+                    (format!("{}:{}", e.name, path), None, code_facts(&e))
+                }
+            };
+            summary.read += 1;
+            let entry = Kept { input, line: n, started_at, facts };
+            let Some(old) = kept.get(&key) else {
+                kept.insert(key, entry);
+                continue;
+            };
+            summary.duplicates += 1;
+            let newer = match (&entry.started_at, &old.started_at) {
+                (Some(new), Some(old)) if new != old => new > old,
+                _ => {
+                    summary.last_wins += 1;
+                    true
+                }
+            };
+            let (winner, loser) = if newer { (&entry, old) } else { (old, &entry) };
+            let differences = differences(&loser.facts, &winner.facts);
+            if !differences.is_empty() {
+                conflicts.push(MergeConflict { key: key.clone(), kept: opts.inputs[winner.input].clone(), dropped: opts.inputs[loser.input].clone(), differences });
+            }
+            if newer {
+                kept.insert(key, entry);
+            }
+        }
+    }
+    if summary.last_wins > 0 {
+        tracing::warn!("{} duplicates have no run timestamp to compare (no manifest.json with their run_id beside the input); the later input's entry was kept", summary.last_wins);
+    }
+
+    let lines: HashSet<(usize, usize)> = kept.values().map(|k| (k.input, k.line)).collect();
+    let mut out = JsonlWriter::compressed(File::create(&opts.out).with_context(|| format!("failed to create {}", opts.out))?, Codec::of(&opts.out), None)?;
+    for (input, file) in opts.inputs.iter().enumerate() {
+        for (n, line) in open_jsonl(file)?.lines().enumerate() {
+            let line = line?;
+            if lines.contains(&(input, n)) {
+                out.write_line(&line)?;
+                summary.written += 1;
+            }
+        }
+    }
+    out.finish()?;
+    let conflicts_file = merge_conflicts_beside(&opts.out);
+    let mut w = JsonlWriter::new(File::create(&conflicts_file).with_context(|| format!("failed to create {}", conflicts_file.display()))?, None);
+    for c in &conflicts {
+        w.write_entry(c)?;
+    }
+    w.finish()?;
+    summary.conflicts = conflicts.len();
+    Ok(summary)
+}
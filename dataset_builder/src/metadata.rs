@@ -0,0 +1,177 @@
+//! The `metadata` step: each repository's GitHub metadata (stars, license, topics, ...), so the
+//! dataset can be filtered by license or popularity later.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::path::Path;
+
+use anyhow::Context;
+use chrono::Utc;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::clone::{github_repo, sanitize, split_pin};
+use crate::github::{GithubClient, License, Tokens};
+use crate::jsonl::{read_jsonl, scan_jsonl, Codec, JsonlWriter};
+use crate::shutdown::shutdown_requested;
+
+/// Where `full --metadata` writes unless `--metadata-file` says otherwise.
+pub const DEFAULT_METADATA_FILE: &str = "metadata.jsonl";
+
+#[derive(Args, Clone, Debug)]
+pub struct MetadataOptions {
+    /// Only the repositories with a checkout in this directory (as `clone` names them)
+    #[arg(long, value_name = "DIR")]
+    pub root: Option<String>,
+    /// Retries of a request after a network error, a server error or a rate limit
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    pub retries: u32,
+    #[arg(long, default_value = "https://api.github.com", hide = true)]
+    pub api_url: String,
+}
+
+impl Default for MetadataOptions {
+    fn default() -> Self {
+        MetadataOptions { root: None, retries: 5, api_url: "https://api.github.com".into() }
+    }
+}
+
+/// One line of `metadata.jsonl`. Only `name`, `repo`, `status` and `fetched_at` are set for a
+/// repository GitHub no longer has.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct RepoMetadata {
+    /// The checkout directory, as in `OutputEntry::dir` and `CodeEntry::dir`
+    pub name: String,
+    /// `owner/repo` as listed
+    pub repo: String,
+    /// `ok`, or `not_found` for a repository that was deleted or made private
+    pub status: String,
+    /// The current `owner/repo`, which differs from `repo` after a rename or transfer
+    pub full_name: Option<String>,
+    pub stars: Option<u64>,
+    pub forks: Option<u64>,
+    pub open_issues: Option<u64>,
+    /// SPDX id of the declared license
+    pub license: Option<String>,
+    pub topics: Vec<String>,
+    pub default_branch: Option<String>,
+    pub archived: Option<bool>,
+    pub fork: Option<bool>,
+    pub size_kb: Option<u64>,
+    pub created_at: Option<String>,
+    pub pushed_at: Option<String>,
+    /// Sent back as `If-None-Match` on the next run, which then costs no rate limit if unchanged
+    pub etag: Option<String>,
+    pub fetched_at: String,
+}
+
+#[derive(Debug, Default)]
+pub struct MetadataSummary {
+    pub repos: usize,
+    pub fetched: usize,
+    /// Answered `304 Not Modified` thanks to the entry of a previous run
+    pub unchanged: usize,
+    pub not_found: usize,
+    pub requests: usize,
+}
+
+#[derive(Deserialize)]
+struct RepoResponse {
+    full_name: String,
+    stargazers_count: u64,
+    forks_count: u64,
+    open_issues_count: u64,
+    license: Option<License>,
+    #[serde(default)]
+    topics: Vec<String>,
+    default_branch: String,
+    archived: bool,
+    fork: bool,
+    size: u64,
+    created_at: String,
+    pushed_at: Option<String>,
+}
+
+/// Writes the metadata of every repository in `names` to `out`, in their order. Entries already
+/// in `out` are revalidated with their ETag, and kept for repositories an interrupted run didn't
+/// reach; the file is only replaced once the run ends.
+pub fn fetch_metadata(names: &str, out: &str, opts: &MetadataOptions, tokens: &Tokens) -> anyhow::Result<MetadataSummary> {
+    let text = fs::read_to_string(names).with_context(|| format!("failed to read {}", names))?;
+    let mut seen = HashSet::new();
+    let repos: Vec<(String, String)> = text
+        .lines()
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .map(|line| split_pin(line).0)
+        .filter_map(|n| github_repo(n).map_err(|e| tracing::warn!("⚠ {}: {}; skipped", n, e)).ok())
+        .map(|repo| (format!("dataset_{}", sanitize(&repo)), repo))
+        .filter(|(dir, _)| seen.insert(dir.to_ascii_lowercase()))
+        .filter(|(dir, _)| opts.root.as_ref().is_none_or(|root| Path::new(root).join(dir).is_dir()))
+        .collect();
+    let previous: HashMap<String, RepoMetadata> = match read_jsonl(out) {
+        Ok((text, _)) => scan_jsonl(&text)
+            .into_iter()
+            .filter_map(|line| serde_json::from_value::<RepoMetadata>(line.value?).ok())
+            .map(|entry| (entry.name.clone(), entry))
+            .collect(),
+        Err(_) => HashMap::new(),
+    };
+
+    let tmp = format!("{}.tmp", out);
+    let mut w = JsonlWriter::compressed(File::create(&tmp).with_context(|| format!("failed to write {}", tmp))?, Codec::of(out), None)?;
+    let mut client = GithubClient::new(&opts.api_url, tokens, opts.retries);
+    let mut summary = MetadataSummary { repos: repos.len(), ..MetadataSummary::default() };
+    for (dir, repo) in &repos {
+        let old = previous.get(dir);
+        if shutdown_requested() {
+            if let Some(old) = old {
+                w.write_entry(old)?;
+            }
+            continue;
+        }
+        let entry = fetch_one(&mut client, dir, repo, old, &mut summary).with_context(|| format!("{} is left as it was; run again to retry", out))?;
+        tracing::debug!(repo = repo.as_str(), status = entry.status.as_str(), "metadata fetched");
+        w.write_entry(&entry)?;
+    }
+    w.finish()?;
+    fs::rename(&tmp, out)?;
+    summary.requests = client.requests;
+    Ok(summary)
+}
+
+fn fetch_one(client: &mut GithubClient, dir: &str, repo: &str, old: Option<&RepoMetadata>, summary: &mut MetadataSummary) -> anyhow::Result<RepoMetadata> {
+    let fetched_at = Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let etag = old.filter(|o| o.status == "ok").and_then(|o| o.etag.as_deref());
+    let headers: Vec<(&str, &str)> = etag.map(|etag| ("If-None-Match", etag)).into_iter().collect();
+    let Some(response) = client.get(&format!("/repos/{}", repo), &[], &headers)? else {
+        summary.not_found += 1;
+        tracing::warn!("⚠ {} not found on GitHub; deleted, renamed away or private", repo);
+        return Ok(RepoMetadata { name: dir.to_string(), repo: repo.to_string(), status: "not_found".into(), fetched_at, ..RepoMetadata::default() });
+    };
+    if response.status() == 304 {
+        summary.unchanged += 1;
+        return Ok(RepoMetadata { fetched_at, ..old.expect("only revalidated entries get a 304").clone() });
+    }
+    summary.fetched += 1;
+    let etag = response.header("etag").map(String::from);
+    let r: RepoResponse = serde_json::from_reader(response.into_reader()).with_context(|| format!("unexpected response for {}", repo))?;
+    Ok(RepoMetadata {
+        name: dir.to_string(),
+        repo: repo.to_string(),
+        status: "ok".into(),
+        full_name: Some(r.full_name),
+        stars: Some(r.stargazers_count),
+        forks: Some(r.forks_count),
+        open_issues: Some(r.open_issues_count),
+        license: License::spdx(r.license),
+        topics: r.topics,
+        default_branch: Some(r.default_branch),
+        archived: Some(r.archived),
+        fork: Some(r.fork),
+        size_kb: Some(r.size),
+        created_at: Some(r.created_at),
+        pushed_at: r.pushed_at,
+        etag,
+        fetched_at,
+    })
+}
@@ -0,0 +1,1008 @@
+//! Parsers turning raw tool output into structured reports.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::tool::ToolResult;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClippyDiag {
+    /// Lint or error code, e.g. `clippy::needless_return` or `E0308`
+    pub lint_name: Option<String>,
+    pub level: String,
+    pub file: Option<String>,
+    pub line_start: Option<u64>,
+    pub line_end: Option<u64>,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClippyReport {
+    pub diagnostics: Vec<ClippyDiag>,
+    /// Number of diagnostics per level (`warning`, `error`, ...)
+    pub counts: BTreeMap<String, usize>,
+    /// The crate did not build, so the lint set is incomplete and `diagnostics` is left empty
+    pub compile_failed: bool,
+    /// Crates cargo found up to date (e.g. in `--shared-target-dir`) instead of checking them again
+    #[serde(default)]
+    pub units_fresh: usize,
+    #[serde(default)]
+    pub units_built: usize,
+}
+
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    message: Option<RustcMessage>,
+    success: Option<bool>,
+    fresh: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct RustcMessage {
+    message: String,
+    level: String,
+    code: Option<RustcCode>,
+    #[serde(default)]
+    spans: Vec<RustcSpan>,
+}
+
+#[derive(Deserialize)]
+struct RustcCode {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct RustcSpan {
+    file_name: String,
+    line_start: u64,
+    line_end: u64,
+    is_primary: bool,
+}
+
+/// Turns cargo's `--message-format=json` stream into per-diagnostic records.
+pub fn parse_clippy(result: &ToolResult) -> ClippyReport {
+    let mut diagnostics = Vec::new();
+    let mut build_succeeded = None;
+    let mut rustc_error = false;
+    let (mut units_fresh, mut units_built) = (0, 0);
+    for line in result.stdout.lines() {
+        let Ok(msg) = serde_json::from_str::<CargoMessage>(line) else { continue };
+        match (msg.reason.as_str(), msg.message) {
+            ("compiler-message", Some(m)) => {
+                let lint_name = m.code.map(|c| c.code);
+                // Trailing "N warnings emitted" and "aborting due to N previous errors" summaries
+                // carry neither spans nor a code; the latter follows a denied clippy lint too.
+                if m.spans.is_empty() && lint_name.is_none() && (m.level != "error" || m.message.starts_with("aborting due to")) {
+                    continue;
+                }
+                if m.level == "error" && !lint_name.as_deref().is_some_and(|c| c.starts_with("clippy::")) {
+                    rustc_error = true;
+                }
+                let span = m.spans.iter().find(|s| s.is_primary).or(m.spans.first());
+                diagnostics.push(ClippyDiag {
+                    lint_name,
+                    level: m.level,
+                    file: span.map(|s| s.file_name.clone()),
+                    line_start: span.map(|s| s.line_start),
+                    line_end: span.map(|s| s.line_end),
+                    message: m.message,
+                });
+            }
+            ("compiler-artifact", _) if msg.fresh == Some(true) => units_fresh += 1,
+            ("compiler-artifact", _) => units_built += 1,
+            ("build-finished", _) => build_succeeded = msg.success,
+            _ => {}
+        }
+    }
+
+    let compile_failed = rustc_error || (build_succeeded.is_none() && result.exit_code != Some(0));
+    if compile_failed {
+        diagnostics.clear();
+    }
+    let mut counts = BTreeMap::new();
+    for d in &diagnostics {
+        *counts.entry(d.level.clone()).or_insert(0) += 1;
+    }
+    ClippyReport { diagnostics, counts, compile_failed, units_fresh, units_built }
+}
+
+/// How many distinct error codes `CheckReport` keeps.
+pub const CHECK_ERROR_CODES: usize = 10;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CheckReport {
+    /// `cargo check` succeeded
+    pub builds: bool,
+    pub errors: usize,
+    pub warnings: usize,
+    /// The first `CHECK_ERROR_CODES` distinct codes of the errors (`E0308`, ...), in the order reported
+    pub error_codes: Vec<String>,
+}
+
+/// Turns `cargo check --message-format=json` output into whether the crate builds and its errors.
+pub fn parse_check(result: &ToolResult) -> CheckReport {
+    let mut report = CheckReport { builds: false, errors: 0, warnings: 0, error_codes: Vec::new() };
+    let mut build_succeeded = None;
+    for line in result.stdout.lines() {
+        let Ok(msg) = serde_json::from_str::<CargoMessage>(line) else { continue };
+        match (msg.reason.as_str(), msg.message) {
+            // "aborting due to N previous errors" and the like carry neither spans nor a code.
+            ("compiler-message", Some(m)) if m.spans.is_empty() && m.code.is_none() => {}
+            ("compiler-message", Some(m)) if m.level == "error" => {
+                report.errors += 1;
+                if let Some(code) = m.code.map(|c| c.code) {
+                    if !report.error_codes.contains(&code) && report.error_codes.len() < CHECK_ERROR_CODES {
+                        report.error_codes.push(code);
+                    }
+                }
+            }
+            ("compiler-message", Some(m)) if m.level == "warning" => report.warnings += 1,
+            ("build-finished", _) => build_succeeded = msg.success,
+            _ => {}
+        }
+    }
+    report.builds = build_succeeded.unwrap_or(result.exit_code == Some(0)) && report.errors == 0;
+    report
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditStatus {
+    Ok,
+    /// The advisory database could not be fetched or opened, so no verdict is available
+    DbUnavailable,
+    /// cargo audit ran but its output was not the expected JSON (e.g. no Cargo.lock)
+    ParseError,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuditAdvisory {
+    pub advisory_id: String,
+    pub package: String,
+    pub version: String,
+    /// `low`/`medium`/`high`/`critical`, from the advisory or derived from its CVSS vector
+    pub severity: Option<String>,
+    /// CVSS v3 vector string
+    pub cvss: Option<String>,
+    pub cvss_score: Option<f64>,
+    pub patched_versions: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuditReport {
+    pub status: AuditStatus,
+    pub has_vulnerable_deps: bool,
+    pub advisories: Vec<AuditAdvisory>,
+}
+
+pub fn parse_audit(result: &ToolResult) -> AuditReport {
+    let report = |status, advisories: Vec<AuditAdvisory>| AuditReport {
+        status,
+        has_vulnerable_deps: !advisories.is_empty(),
+        advisories,
+    };
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(&result.stdout) else {
+        let stderr = result.stderr.to_lowercase();
+        let status = if stderr.contains("advisory database") || stderr.contains("advisory-db") {
+            AuditStatus::DbUnavailable
+        } else {
+            AuditStatus::ParseError
+        };
+        return report(status, Vec::new());
+    };
+
+    let str_at = |v: &serde_json::Value, ptr: &str| v.pointer(ptr).and_then(|x| x.as_str()).map(str::to_string);
+    let list = json.pointer("/vulnerabilities/list").and_then(|l| l.as_array()).cloned().unwrap_or_default();
+    let advisories = list
+        .iter()
+        .map(|v| {
+            let cvss = str_at(v, "/advisory/cvss");
+            let scored = cvss.as_deref().and_then(cvss3_base_score);
+            AuditAdvisory {
+                advisory_id: str_at(v, "/advisory/id").unwrap_or_default(),
+                package: str_at(v, "/package/name").or_else(|| str_at(v, "/advisory/package")).unwrap_or_default(),
+                version: str_at(v, "/package/version").unwrap_or_default(),
+                severity: str_at(v, "/advisory/severity").or_else(|| scored.map(|s| cvss_severity(s).to_string())),
+                cvss,
+                cvss_score: scored,
+                patched_versions: v
+                    .pointer("/versions/patched")
+                    .and_then(|p| p.as_array())
+                    .map(|p| p.iter().filter_map(|x| x.as_str().map(str::to_string)).collect())
+                    .unwrap_or_default(),
+            }
+        })
+        .collect();
+    report(AuditStatus::Ok, advisories)
+}
+
+/// CVSS v3.x base score of a vector such as `CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H`.
+fn cvss3_base_score(vector: &str) -> Option<f64> {
+    let metrics: HashMap<&str, &str> = vector.split('/').skip(1).filter_map(|m| m.split_once(':')).collect();
+    let changed = *metrics.get("S")? == "C";
+    let av = match *metrics.get("AV")? { "N" => 0.85, "A" => 0.62, "L" => 0.55, "P" => 0.2, _ => return None };
+    let ac = match *metrics.get("AC")? { "L" => 0.77, "H" => 0.44, _ => return None };
+    let pr = match (*metrics.get("PR")?, changed) {
+        ("N", _) => 0.85,
+        ("L", false) => 0.62,
+        ("L", true) => 0.68,
+        ("H", false) => 0.27,
+        ("H", true) => 0.5,
+        _ => return None,
+    };
+    let ui = match *metrics.get("UI")? { "N" => 0.85, "R" => 0.62, _ => return None };
+    let cia = |k| match metrics.get(k).copied() { Some("H") => Some(0.56), Some("L") => Some(0.22), Some("N") => Some(0.0), _ => None };
+    let iss = 1.0 - (1.0 - cia("C")?) * (1.0 - cia("I")?) * (1.0 - cia("A")?);
+    let impact = if changed { 7.52 * (iss - 0.029) - 3.25 * (iss - 0.02f64).powi(15) } else { 6.42 * iss };
+    if impact <= 0.0 {
+        return Some(0.0);
+    }
+    let exploitability = 8.22 * av * ac * pr * ui;
+    let raw = if changed { 1.08 * (impact + exploitability) } else { impact + exploitability };
+    // CVSS 3.1 "Roundup": smallest one-decimal number >= input, computed on integers to dodge float noise.
+    let int = (raw.min(10.0) * 100_000.0).round() as i64;
+    Some(if int % 10_000 == 0 { int as f64 / 100_000.0 } else { (int / 10_000 + 1) as f64 / 10.0 })
+}
+
+fn cvss_severity(score: f64) -> &'static str {
+    match score {
+        s if s >= 9.0 => "critical",
+        s if s >= 7.0 => "high",
+        s if s >= 4.0 => "medium",
+        s if s > 0.0 => "low",
+        _ => "none",
+    }
+}
+
+/// The most of one file's unified diff `--fmt-capture-diff` keeps.
+pub const FMT_DIFF_MAX_BYTES: usize = 64 << 10;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FmtFile {
+    /// Relative to the checkout, with `/` separators
+    pub path: String,
+    pub hunks: usize,
+    pub lines_removed: usize,
+    pub lines_added: usize,
+    /// The unified diff rustfmt would apply, with `--fmt-capture-diff`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    /// `diff` was cut at `FMT_DIFF_MAX_BYTES`, at a line boundary
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub diff_truncated: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FmtReport {
+    /// rustfmt checked the crate and would change nothing
+    pub formatted: bool,
+    /// The files rustfmt would reformat
+    pub files: Vec<FmtFile>,
+    /// Options of the repository's rustfmt config that stable rustfmt ignored; the files were
+    /// checked without them, so no diffs are captured
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unstable_options: Vec<String>,
+    /// rustfmt failed instead of checking, e.g. on a file that doesn't parse; its error is in `stderr`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub failed: bool,
+}
+
+/// One `Diff in <file>:<line>:` (or `... at line <line>:`) block of `cargo fmt -- --check`.
+struct FmtHunk<'a> {
+    start: usize,
+    lines: Vec<&'a str>,
+}
+
+/// Turns `cargo fmt -- --check --color never` output into the files it would reformat, with
+/// paths relative to `root`. With `capture_diff`, each file gets its changes as a unified diff.
+pub fn parse_fmt(result: &ToolResult, root: &Path, capture_diff: bool) -> FmtReport {
+    let mut by_file: Vec<(String, Vec<FmtHunk>)> = Vec::new();
+    for line in result.stdout.lines() {
+        if let Some(rest) = line.strip_prefix("Diff in ").and_then(|r| r.strip_suffix(':')) {
+            let (file, start) = match rest.rsplit_once(" at line ").or_else(|| rest.rsplit_once(':')) {
+                Some((file, n)) => (file, n.parse().unwrap_or(1)),
+                None => (rest, 1),
+            };
+            let path = Path::new(file);
+            let path = path.strip_prefix(root).unwrap_or(path).to_string_lossy().replace('\\', "/");
+            let hunk = FmtHunk { start, lines: Vec::new() };
+            match by_file.last_mut() {
+                Some((last, hunks)) if *last == path => hunks.push(hunk),
+                _ => by_file.push((path, vec![hunk])),
+            }
+        } else if let Some((_, hunks)) = by_file.last_mut() {
+            if line.starts_with([' ', '-', '+']) {
+                hunks.last_mut().unwrap().lines.push(line);
+            }
+        }
+    }
+
+    let mut unstable_options: Vec<String> = result
+        .stderr
+        .lines()
+        .filter(|l| l.contains("unstable features are only available in nightly"))
+        .filter_map(|l| l.split_once("can't set `")?.1.split_once(" = ").map(|(name, _)| name.to_string()))
+        .collect();
+    unstable_options.sort();
+    unstable_options.dedup();
+    let files: Vec<FmtFile> = by_file
+        .into_iter()
+        .map(|(path, hunks)| {
+            let count = |c: char| hunks.iter().flat_map(|h| &h.lines).filter(|l| l.starts_with(c)).count();
+            let (lines_removed, lines_added) = (count('-'), count('+'));
+            let diff = (capture_diff && unstable_options.is_empty()).then(|| unified_diff(&path, &hunks));
+            let diff_truncated = diff.as_ref().is_some_and(|d| d.len() > FMT_DIFF_MAX_BYTES);
+            let diff = diff.map(|d| if diff_truncated { d[..d.as_bytes()[..FMT_DIFF_MAX_BYTES].iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1)].to_string() } else { d });
+            FmtFile { path, hunks: hunks.len(), lines_removed, lines_added, diff, diff_truncated }
+        })
+        .collect();
+    let failed = files.is_empty() && result.exit_code != Some(0);
+    FmtReport { formatted: files.is_empty() && !failed, files, unstable_options, failed }
+}
+
+/// A file's hunks as a unified diff, each new-side start shifted by the lines the ones before it added.
+fn unified_diff(path: &str, hunks: &[FmtHunk]) -> String {
+    let mut diff = format!("--- a/{}\n+++ b/{}\n", path, path);
+    let mut shift: isize = 0;
+    for h in hunks {
+        let old = h.lines.iter().filter(|l| !l.starts_with('+')).count();
+        let new = h.lines.iter().filter(|l| !l.starts_with('-')).count();
+        let new_start = (h.start as isize + shift).max(0);
+        diff.push_str(&format!("@@ -{},{} +{},{} @@\n", h.start, old, new_start, new));
+        for l in &h.lines {
+            diff.push_str(l);
+            diff.push('\n');
+        }
+        shift += new as isize - old as isize;
+    }
+    diff
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TestReport {
+    /// `cargo-test` or `nextest`
+    pub runner: String,
+    /// The tests built; when they didn't, the counts are 0 and the errors are in `stderr`
+    pub compiled: bool,
+    /// Every test the run reported, ignored ones included; 0 for a crate without tests
+    pub tests: usize,
+    pub passed: usize,
+    pub failed: usize,
+    pub ignored: usize,
+    /// Names of the failing tests, as the runner prints them
+    pub failing: Vec<String>,
+    pub duration_ms: u64,
+}
+
+/// Turns `cargo test --no-fail-fast --message-format json` output into test counts: cargo's
+/// messages tell whether the build succeeded, libtest's `test <name> ... <outcome>` lines (on
+/// the same stdout) the outcome of each test.
+pub fn parse_cargo_test(result: &ToolResult) -> TestReport {
+    let mut report = TestReport { runner: "cargo-test".into(), compiled: false, tests: 0, passed: 0, failed: 0, ignored: 0, failing: Vec::new(), duration_ms: 0 };
+    for line in result.stdout.lines() {
+        if let Ok(msg) = serde_json::from_str::<CargoMessage>(line) {
+            if msg.reason == "build-finished" {
+                report.compiled = msg.success == Some(true);
+            }
+            continue;
+        }
+        let Some((name, outcome)) = line.strip_prefix("test ").and_then(|l| l.rsplit_once(" ... ")) else { continue };
+        match outcome {
+            "ok" => report.passed += 1,
+            "FAILED" => {
+                report.failed += 1;
+                report.failing.push(name.to_string());
+            }
+            o if o.starts_with("ignored") => report.ignored += 1,
+            _ => continue,
+        }
+        report.tests += 1;
+    }
+    report
+}
+
+/// Turns `cargo nextest run --message-format libtest-json` output into test counts.
+pub fn parse_nextest(result: &ToolResult) -> TestReport {
+    let mut report = TestReport { runner: "nextest".into(), compiled: false, tests: 0, passed: 0, failed: 0, ignored: 0, failing: Vec::new(), duration_ms: 0 };
+    for line in result.stdout.lines() {
+        let Ok(json) = serde_json::from_str::<serde_json::Value>(line) else { continue };
+        let field = |k: &str| json.get(k).and_then(|v| v.as_str());
+        match (field("type"), field("event")) {
+            (Some("suite"), _) => report.compiled = true,
+            (Some("test"), Some("ok")) => report.passed += 1,
+            (Some("test"), Some("failed")) => {
+                report.failed += 1;
+                report.failing.extend(field("name").map(str::to_string));
+            }
+            (Some("test"), Some("ignored")) => report.ignored += 1,
+            _ => continue,
+        }
+        if field("type") == Some("test") {
+            report.tests += 1;
+        }
+    }
+    // nextest exits with 4 when a crate has no tests, and 101 only when the build failed.
+    report.compiled |= matches!(result.exit_code, Some(0 | 4 | 100));
+    report
+}
+
+/// `path` relative to `root`, with `/` separators, or as it is when it isn't under it.
+fn relative_to(root: &Path, path: &str) -> String {
+    let p = Path::new(path);
+    p.strip_prefix(root).unwrap_or(p).to_string_lossy().replace('\\', "/")
+}
+
+/// The dependencies of one crate that a hygiene tool found unused.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UnusedDeps {
+    pub package: String,
+    /// Relative to the checkout
+    pub manifest_path: String,
+    pub normal: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub development: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub build: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UdepsReport {
+    pub unused_deps: Vec<UnusedDeps>,
+    /// Every unused dependency, of all kinds
+    pub unused: usize,
+}
+
+/// Turns `cargo udeps --output json` into the unused dependencies per crate; `None` when it
+/// printed no JSON, as when the build failed.
+pub fn parse_udeps(stdout: &str, root: &Path) -> Option<UdepsReport> {
+    let json: serde_json::Value = serde_json::from_str(stdout).ok()?;
+    let names = |v: &serde_json::Value, k: &str| -> Vec<String> {
+        v.get(k).and_then(|l| l.as_array()).map(|l| l.iter().filter_map(|n| n.as_str().map(str::to_string)).collect()).unwrap_or_default()
+    };
+    // Keyed by package id: `name version (source)`.
+    let unused_deps: Vec<UnusedDeps> = json
+        .get("unused_deps")?
+        .as_object()?
+        .iter()
+        .map(|(id, v)| UnusedDeps {
+            package: id.split(' ').next().unwrap_or(id).to_string(),
+            manifest_path: relative_to(root, v.get("manifest_path").and_then(|m| m.as_str()).unwrap_or_default()),
+            normal: names(v, "normal"),
+            development: names(v, "development"),
+            build: names(v, "build"),
+        })
+        .collect();
+    let unused = unused_deps.iter().map(|u| u.normal.len() + u.development.len() + u.build.len()).sum();
+    Some(UdepsReport { unused_deps, unused })
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OutdatedDep {
+    pub name: String,
+    /// The version the lockfile has
+    pub project: String,
+    /// The newest on the registry
+    pub latest: String,
+    /// `Normal`, `Development` or `Build`
+    pub kind: Option<String>,
+    /// The most significant part `latest` is ahead in: `major`, `minor` or `patch`
+    pub step: String,
+    /// How far ahead that part is, e.g. 2 from 1.4.0 to 3.0.0
+    pub steps: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OutdatedReport {
+    /// Direct dependencies with a newer release
+    pub behind: usize,
+    /// `behind` per `step`
+    pub by_step: BTreeMap<String, usize>,
+    pub dependencies: Vec<OutdatedDep>,
+}
+
+/// How far `latest` is ahead of `project`, by the most significant part that differs.
+fn semver_step(project: &str, latest: &str) -> Option<(&'static str, u64)> {
+    let parts = |v: &str| -> Option<[u64; 3]> {
+        let core = v.split(['-', '+']).next()?;
+        let mut it = core.split('.').map(|p| p.parse::<u64>());
+        Some([it.next()?.ok()?, it.next().unwrap_or(Ok(0)).ok()?, it.next().unwrap_or(Ok(0)).ok()?])
+    };
+    let (p, l) = (parts(project)?, parts(latest)?);
+    ["major", "minor", "patch"].into_iter().zip(p.into_iter().zip(l)).find(|(_, (p, l))| l > p).map(|(step, (p, l))| (step, l - p))
+}
+
+/// Turns `cargo outdated --root-deps-only --format json`, one object per crate, into the direct
+/// dependencies behind their latest release; `None` when it printed no JSON.
+pub fn parse_outdated(stdout: &str) -> Option<OutdatedReport> {
+    let crates: Vec<serde_json::Value> = stdout.lines().filter_map(|l| serde_json::from_str(l).ok()).collect();
+    if crates.is_empty() {
+        return None;
+    }
+    let mut dependencies = Vec::new();
+    for dep in crates.iter().filter_map(|c| c.get("dependencies")?.as_array()).flatten() {
+        let field = |k: &str| dep.get(k).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+        let (project, latest) = (field("project"), field("latest"));
+        // `---` and `Removed` have no version to compare.
+        let Some((step, steps)) = semver_step(&project, &latest) else { continue };
+        dependencies.push(OutdatedDep { name: field("name"), project, latest, kind: dep.get("kind").and_then(|k| k.as_str()).map(str::to_string), step: step.into(), steps });
+    }
+    let mut by_step = BTreeMap::new();
+    for d in &dependencies {
+        *by_step.entry(d.step.clone()).or_insert(0) += 1;
+    }
+    Some(OutdatedReport { behind: dependencies.len(), by_step, dependencies })
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MacheteReport {
+    /// Only the manifests with unused dependencies
+    pub manifests: Vec<UnusedDeps>,
+    pub unused: usize,
+}
+
+/// Turns `cargo machete` output, a `<package> -- <manifest>:` line followed by a tab-indented
+/// dependency per line for each crate, into the unused dependencies per manifest; `None` when
+/// it didn't finish its analysis.
+pub fn parse_machete(stdout: &str, root: &Path) -> Option<MacheteReport> {
+    if !stdout.lines().any(|l| l.trim() == "Done!") {
+        return None;
+    }
+    let mut manifests: Vec<UnusedDeps> = Vec::new();
+    for line in stdout.lines() {
+        if let Some(dep) = line.strip_prefix('\t') {
+            if let Some(m) = manifests.last_mut() {
+                m.normal.push(dep.trim().to_string());
+            }
+        } else if let Some((package, manifest)) = line.strip_suffix(':').and_then(|l| l.split_once(" -- ")) {
+            manifests.push(UnusedDeps { package: package.to_string(), manifest_path: relative_to(root, manifest), normal: Vec::new(), development: Vec::new(), build: Vec::new() });
+        }
+    }
+    let unused = manifests.iter().map(|m| m.normal.len()).sum();
+    Some(MacheteReport { manifests, unused })
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct VetDep {
+    pub name: String,
+    pub version: String,
+    /// `audited` (by the repository's audits or those it imports), `exempted`, `unreviewed`, or
+    /// `vetted`: passed in a run that failed on others, which doesn't say how
+    pub status: String,
+    /// The criteria an `unreviewed` dependency lacks, e.g. `safe-to-deploy`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing_criteria: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct VetReport {
+    /// The repository has a `supply-chain/config.toml` of its own; otherwise it was checked
+    /// against an empty store, with nothing audited or exempted
+    pub own_config: bool,
+    /// cargo-vet's own: `success`, `fail (vetting)` or `fail (violation)`
+    pub conclusion: String,
+    pub dependencies: Vec<VetDep>,
+    /// Dependencies per `status`
+    pub counts: BTreeMap<String, usize>,
+    /// Percentage of the third-party dependencies per `status`
+    pub coverage: BTreeMap<String, f64>,
+}
+
+/// Per-status counts and their share, in percent to one decimal, of all `statuses`.
+fn status_shares<'a>(statuses: impl Iterator<Item = &'a str>) -> (BTreeMap<String, usize>, BTreeMap<String, f64>) {
+    let mut counts = BTreeMap::new();
+    for status in statuses {
+        *counts.entry(status.to_string()).or_insert(0) += 1;
+    }
+    let total: usize = counts.values().sum();
+    let shares = counts.iter().map(|(s, &n)| (s.clone(), (n as f64 * 1000.0 / total as f64).round() / 10.0)).collect();
+    (counts, shares)
+}
+
+/// Turns `cargo vet --output-format json` into a status per third-party dependency. A failed
+/// check only lists the failures, so the rest of `third_party` (name and version, from the
+/// lockfile) are `vetted`. `None` when it printed no report, as when it couldn't read the store.
+pub fn parse_vet(stdout: &str, third_party: &[(String, String)], own_config: bool) -> Option<VetReport> {
+    let json: serde_json::Value = serde_json::from_str(stdout).ok()?;
+    let conclusion = json.get("conclusion")?.as_str()?.to_string();
+    let packages = |key: &str| json.get(key).and_then(|l| l.as_array()).cloned().unwrap_or_default();
+    let field = |p: &serde_json::Value, k: &str| p.get(k).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+    let dep = |p: &serde_json::Value, status: &str| VetDep {
+        name: field(p, "name"),
+        version: field(p, "version"),
+        status: status.to_string(),
+        missing_criteria: p.get("missing_criteria").and_then(|c| c.as_array()).map(|c| c.iter().filter_map(|c| c.as_str().map(str::to_string)).collect()).unwrap_or_default(),
+    };
+    let mut deps: Vec<VetDep> = Vec::new();
+    if conclusion == "success" {
+        deps.extend(packages("vetted_fully").iter().chain(&packages("vetted_partially")).map(|p| dep(p, "audited")));
+        deps.extend(packages("vetted_with_exemptions").iter().map(|p| dep(p, "exempted")));
+    } else {
+        deps.extend(packages("failures").iter().map(|p| dep(p, "unreviewed")));
+        let failed: Vec<(String, String)> = deps.iter().map(|d| (d.name.clone(), d.version.clone())).collect();
+        deps.extend(third_party.iter().filter(|p| !failed.contains(p)).map(|(name, version)| VetDep { name: name.clone(), version: version.clone(), status: "vetted".into(), missing_criteria: Vec::new() }));
+    }
+    deps.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+    let (counts, coverage) = status_shares(deps.iter().map(|d| d.status.as_str()));
+    Some(VetReport { own_config, conclusion, dependencies: deps, counts, coverage })
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CrevDep {
+    pub name: String,
+    pub version: String,
+    /// `reviewed` (crev's `pass`), `unreviewed` (`none`), or `flagged` (`warn`, `flagged`,
+    /// `dangerous`: a review found a problem)
+    pub status: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CrevReport {
+    pub dependencies: Vec<CrevDep>,
+    pub counts: BTreeMap<String, usize>,
+    /// Percentage of the dependencies per `status`
+    pub coverage: BTreeMap<String, f64>,
+}
+
+/// Whether `token` is a `x.y.z` version, possibly with a pre-release or build suffix.
+fn is_version(token: &str) -> bool {
+    let core = token.split(['-', '+']).next().unwrap_or_default();
+    let parts: Vec<&str> = core.split('.').collect();
+    parts.len() == 3 && parts.iter().all(|p| !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Turns the table of `cargo crev verify` into a status per dependency: a row starts with the
+/// status, and the crate is the column before its first version. Workspace crates (`local`) are
+/// left out. `None` when there is no row, as when it failed.
+pub fn parse_crev(stdout: &str) -> Option<CrevReport> {
+    let mut deps = Vec::new();
+    for line in stdout.lines() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let status = match tokens.first() {
+            Some(&"pass") => "reviewed",
+            Some(&"none") => "unreviewed",
+            Some(&("warn" | "flagged" | "dangerous")) => "flagged",
+            _ => continue,
+        };
+        let Some(at) = tokens.iter().skip(1).position(|t| is_version(t)).map(|i| i + 1) else { continue };
+        if at >= 2 {
+            deps.push(CrevDep { name: tokens[at - 1].to_string(), version: tokens[at].to_string(), status: status.into() });
+        }
+    }
+    if deps.is_empty() && !stdout.lines().any(|l| l.split_whitespace().next() == Some("local")) {
+        return None;
+    }
+    let (counts, coverage) = status_shares(deps.iter().map(|d| d.status.as_str()));
+    Some(CrevReport { dependencies: deps, counts, coverage })
+}
+
+/// Lines of one language, as tokei counts them.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct LanguageLines {
+    pub files: usize,
+    pub code: usize,
+    pub comments: usize,
+    pub blanks: usize,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TokeiReport {
+    /// By tokei's language name, e.g. `Rust` or `TOML`
+    pub languages: BTreeMap<String, LanguageLines>,
+    pub total: LanguageLines,
+}
+
+/// Turns `tokei --output json` into the lines per language and their total; `None` when it
+/// printed no JSON.
+pub fn parse_tokei(stdout: &str) -> Option<TokeiReport> {
+    let json: serde_json::Value = serde_json::from_str(stdout).ok()?;
+    let lines = |v: &serde_json::Value| {
+        let n = |k: &str| v.get(k).and_then(|n| n.as_u64()).unwrap_or(0) as usize;
+        LanguageLines { files: v.get("reports").and_then(|r| r.as_array()).map_or(0, |r| r.len()), code: n("code"), comments: n("comments"), blanks: n("blanks") }
+    };
+    let languages: BTreeMap<String, LanguageLines> = json.as_object()?.iter().filter(|(lang, _)| *lang != "Total").map(|(lang, v)| (lang.clone(), lines(v))).collect();
+    let total = LanguageLines {
+        files: languages.values().map(|l| l.files).sum(),
+        code: languages.values().map(|l| l.code).sum(),
+        comments: languages.values().map(|l| l.comments).sum(),
+        blanks: languages.values().map(|l| l.blanks).sum(),
+    };
+    Some(TokeiReport { languages, total })
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DenyDiag {
+    /// `advisories`, `bans`, `licenses` or `sources`; `None` for codes cargo-deny added later
+    pub check: Option<String>,
+    /// `error`, `warning`, `note` or `help`
+    pub severity: String,
+    /// e.g. `vulnerability`, `rejected`, `duplicate`
+    pub code: Option<String>,
+    #[serde(rename = "crate")]
+    pub krate: Option<String>,
+    pub version: Option<String>,
+    /// The RustSec id, for advisories
+    pub advisory_id: Option<String>,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DenyReport {
+    /// The repository has its own `deny.toml`; without one, the run used `--deny-config` or the
+    /// built-in default
+    pub own_config: bool,
+    pub diagnostics: Vec<DenyDiag>,
+    /// Number of diagnostics per `check:severity` (e.g. `licenses:error`)
+    pub counts: BTreeMap<String, usize>,
+}
+
+/// The check a cargo-deny diagnostic code belongs to.
+fn deny_check(code: &str) -> Option<&'static str> {
+    Some(match code {
+        "vulnerability" | "notice" | "unmaintained" | "unsound" | "yanked" | "index-failure" | "index-cache-load-failure"
+        | "advisory-not-detected" | "unknown-advisory" => "advisories",
+        "banned" | "allowed" | "not-allowed" | "duplicate" | "skipped" | "wildcard" | "unmatched-skip" | "unmatched-skip-root"
+        | "build-script-not-allowed" | "unmatched-path-bypass" | "unmatched-glob" | "unused-wrapper" | "banned-feature"
+        | "default-feature-enabled" | "path-bypassed" | "checksum-match" | "checksum-mismatch" | "denied-by-extension"
+        | "detected-executable" | "detected-executable-script" | "unable-to-check-path" | "feature-not-explicitly-allowed" => "bans",
+        "rejected" | "accepted" | "unlicensed" | "skipped-private-workspace-crate" | "license-not-encountered"
+        | "license-exception-not-encountered" | "missing-clarification-file" | "parse-error" | "empty-license-field"
+        | "no-license-field" | "gather-failure" => "licenses",
+        "git-source-underspecified" | "allowed-source" | "allowed-by-organization" | "source-not-allowed"
+        | "unmatched-source" | "unmatched-organization" => "sources",
+        _ => return None,
+    })
+}
+
+/// Turns the `--format json` diagnostics cargo-deny writes to stderr, one object per line, into
+/// per-diagnostic records. Log lines and the summary are left out.
+pub fn parse_deny(result: &ToolResult, own_config: bool) -> DenyReport {
+    let str_at = |v: &serde_json::Value, ptr: &str| v.pointer(ptr).and_then(|x| x.as_str()).map(str::to_string);
+    let mut diagnostics = Vec::new();
+    for line in result.stderr.lines() {
+        let Ok(json) = serde_json::from_str::<serde_json::Value>(line) else { continue };
+        if json.get("type").and_then(|t| t.as_str()) != Some("diagnostic") {
+            continue;
+        }
+        let code = str_at(&json, "/fields/code");
+        let advisory_id = str_at(&json, "/fields/advisory/id");
+        let check = code.as_deref().and_then(deny_check).or(advisory_id.is_some().then_some("advisories"));
+        diagnostics.push(DenyDiag {
+            check: check.map(str::to_string),
+            severity: str_at(&json, "/fields/severity").unwrap_or_default(),
+            code,
+            krate: str_at(&json, "/fields/graphs/0/Krate/name"),
+            version: str_at(&json, "/fields/graphs/0/Krate/version"),
+            advisory_id,
+            message: str_at(&json, "/fields/message").unwrap_or_default(),
+        });
+    }
+    let mut counts = BTreeMap::new();
+    for d in &diagnostics {
+        *counts.entry(format!("{}:{}", d.check.as_deref().unwrap_or("other"), d.severity)).or_insert(0) += 1;
+    }
+    DenyReport { own_config, diagnostics, counts }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GeigerStatus {
+    Ok,
+    /// geiger could not build the crate, so nothing was scanned
+    BuildFailed,
+    ParseError,
+}
+
+/// Unsafe items actually used by the build, per geiger's "used" column.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct UnsafeCounts {
+    pub functions: u64,
+    pub exprs: u64,
+    pub impls: u64,
+    pub traits: u64,
+    pub methods: u64,
+}
+
+impl UnsafeCounts {
+    pub(crate) fn add(&mut self, other: &UnsafeCounts) {
+        self.functions += other.functions;
+        self.exprs += other.exprs;
+        self.impls += other.impls;
+        self.traits += other.traits;
+        self.methods += other.methods;
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GeigerCrate {
+    pub name: String,
+    pub version: String,
+    #[serde(flatten)]
+    pub unsafe_counts: UnsafeCounts,
+    pub forbids_unsafe: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GeigerReport {
+    pub geiger_status: GeigerStatus,
+    pub crates: Vec<GeigerCrate>,
+    pub total: UnsafeCounts,
+    /// Every local (path) crate of the repo declares `#![forbid(unsafe_code)]`
+    pub forbids_unsafe: bool,
+}
+
+pub fn parse_geiger(result: &ToolResult) -> GeigerReport {
+    let parsed = serde_json::from_str::<serde_json::Value>(&result.stdout)
+        .ok()
+        .and_then(|json| parse_geiger_json(&json))
+        .or_else(|| parse_geiger_table(&result.stdout));
+    let Some((crates, forbids_unsafe)) = parsed else {
+        let geiger_status = if result.exit_code != Some(0) { GeigerStatus::BuildFailed } else { GeigerStatus::ParseError };
+        return GeigerReport { geiger_status, crates: Vec::new(), total: UnsafeCounts::default(), forbids_unsafe: false };
+    };
+    let mut total = UnsafeCounts::default();
+    for c in &crates {
+        total.add(&c.unsafe_counts);
+    }
+    GeigerReport { geiger_status: GeigerStatus::Ok, crates, total, forbids_unsafe }
+}
+
+/// Reads `cargo geiger --output-format Json`; returns the crates and whether all local crates forbid unsafe.
+fn parse_geiger_json(json: &serde_json::Value) -> Option<(Vec<GeigerCrate>, bool)> {
+    let count = |used: &serde_json::Value, key: &str| {
+        let c = used.get(key);
+        c.and_then(|c| c.get("unsafe_").or_else(|| c.get("unsafe"))).and_then(|n| n.as_u64()).unwrap_or(0)
+    };
+    let mut local_forbid = None;
+    let mut crates = Vec::new();
+    for pkg in json.get("packages")?.as_array()? {
+        let id = pkg.pointer("/package/id")?;
+        let used = pkg.pointer("/unsafety/used").cloned().unwrap_or_default();
+        let forbids_unsafe = pkg.pointer("/unsafety/forbids_unsafe").and_then(|f| f.as_bool()).unwrap_or(false);
+        if id.pointer("/source/Path").is_some() {
+            local_forbid = Some(local_forbid.unwrap_or(true) && forbids_unsafe);
+        }
+        crates.push(GeigerCrate {
+            name: id.get("name").and_then(|n| n.as_str()).unwrap_or_default().to_string(),
+            version: id.get("version").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+            unsafe_counts: UnsafeCounts {
+                functions: count(&used, "functions"),
+                exprs: count(&used, "exprs"),
+                impls: count(&used, "item_impls"),
+                traits: count(&used, "item_traits"),
+                methods: count(&used, "methods"),
+            },
+            forbids_unsafe,
+        });
+    }
+    Some((crates, local_forbid.unwrap_or(false)))
+}
+
+/// Fallback for geiger's human-readable table: five `used/total` columns, a status symbol, then `name version`.
+fn parse_geiger_table(text: &str) -> Option<(Vec<GeigerCrate>, bool)> {
+    let mut crates = Vec::new();
+    for line in text.lines() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.len() < 7 {
+            continue;
+        }
+        let used: Option<Vec<u64>> = tokens[..5]
+            .iter()
+            .map(|t| t.split_once('/').and_then(|(u, _)| u.parse().ok()))
+            .collect();
+        let Some(used) = used else { continue };
+        let (symbol, rest) = (tokens[5], &tokens[6..]);
+        // Name and version follow the symbol, possibly behind tree-drawing prefixes.
+        let rest: Vec<&str> = rest.iter().copied().filter(|t| t.chars().any(char::is_alphanumeric)).collect();
+        let (Some(name), Some(version)) = (rest.first(), rest.get(1)) else { continue };
+        crates.push(GeigerCrate {
+            name: name.to_string(),
+            version: version.to_string(),
+            unsafe_counts: UnsafeCounts { functions: used[0], exprs: used[1], impls: used[2], traits: used[3], methods: used[4] },
+            forbids_unsafe: symbol == "🔒" || symbol == ":)",
+        });
+    }
+    // The table lists the root crate first.
+    let forbids = crates.first().is_some_and(|c| c.forbids_unsafe);
+    (!crates.is_empty()).then_some((crates, forbids))
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Finding {
+    pub tool: String,
+    pub rule_id: String,
+    /// SARIF level: `error`, `warning`, `note`, or `none`
+    pub severity: String,
+    pub file: Option<String>,
+    pub start_line: Option<u64>,
+    pub end_line: Option<u64>,
+    pub message: String,
+}
+
+pub fn parse_semgrep(stdout: &str) -> Option<Vec<Finding>> {
+    let json: serde_json::Value = serde_json::from_str(stdout).ok()?;
+    let results = json.get("results")?.as_array()?;
+    Some(
+        results
+            .iter()
+            .map(|r| {
+                let severity = match r.pointer("/extra/severity").and_then(|s| s.as_str()) {
+                    Some("ERROR") => "error",
+                    Some("WARNING") => "warning",
+                    Some("INFO") => "note",
+                    _ => "none",
+                };
+                Finding {
+                    tool: "semgrep".into(),
+                    rule_id: r.get("check_id").and_then(|c| c.as_str()).unwrap_or_default().to_string(),
+                    severity: severity.to_string(),
+                    file: r.get("path").and_then(|p| p.as_str()).map(str::to_string),
+                    start_line: r.pointer("/start/line").and_then(|l| l.as_u64()),
+                    end_line: r.pointer("/end/line").and_then(|l| l.as_u64()),
+                    message: r.pointer("/extra/message").and_then(|m| m.as_str()).unwrap_or_default().to_string(),
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Reads results out of a SARIF 2.1 log (CodeQL's native output format).
+pub fn parse_sarif(tool: &str, text: &str) -> Option<Vec<Finding>> {
+    let json: serde_json::Value = serde_json::from_str(text).ok()?;
+    let mut findings = Vec::new();
+    for run in json.get("runs")?.as_array()? {
+        for r in run.get("results").and_then(|r| r.as_array()).into_iter().flatten() {
+            let loc = r.pointer("/locations/0/physicalLocation");
+            let start_line = loc.and_then(|l| l.pointer("/region/startLine")).and_then(|n| n.as_u64());
+            findings.push(Finding {
+                tool: tool.into(),
+                rule_id: r.get("ruleId").and_then(|id| id.as_str()).unwrap_or_default().to_string(),
+                severity: r.get("level").and_then(|l| l.as_str()).unwrap_or("warning").to_string(),
+                file: loc.and_then(|l| l.pointer("/artifactLocation/uri")).and_then(|u| u.as_str()).map(str::to_string),
+                start_line,
+                end_line: loc.and_then(|l| l.pointer("/region/endLine")).and_then(|n| n.as_u64()).or(start_line),
+                message: r.pointer("/message/text").and_then(|m| m.as_str()).unwrap_or_default().to_string(),
+            });
+        }
+    }
+    Some(findings)
+}
+
+/// Writes `findings` as a SARIF 2.1.0 log with one run per tool.
+pub fn write_sarif(path: &Path, findings: &[Finding]) -> anyhow::Result<()> {
+    let mut by_tool: BTreeMap<&str, Vec<serde_json::Value>> = BTreeMap::new();
+    for f in findings {
+        let mut region = serde_json::Map::new();
+        if let Some(l) = f.start_line {
+            region.insert("startLine".into(), l.into());
+        }
+        if let Some(l) = f.end_line {
+            region.insert("endLine".into(), l.into());
+        }
+        by_tool.entry(&f.tool).or_default().push(serde_json::json!({
+            "ruleId": f.rule_id,
+            "level": f.severity,
+            "message": { "text": f.message },
+            "locations": [{
+                "physicalLocation": {
+                    "artifactLocation": { "uri": f.file.as_deref().unwrap_or_default() },
+                    "region": region,
+                }
+            }],
+        }));
+    }
+    let runs: Vec<_> = by_tool
+        .into_iter()
+        .map(|(tool, results)| serde_json::json!({ "tool": { "driver": { "name": tool } }, "results": results }))
+        .collect();
+    let log = serde_json::json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": runs,
+    });
+    fs::write(path, serde_json::to_string_pretty(&log)?)?;
+    Ok(())
+}
@@ -0,0 +1,758 @@
+//! The `full` command: filter → clone (→ metadata) → outputs → collect.
+
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::Context;
+use clap::{Args, ValueEnum};
+use indicatif::ProgressBar;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::analyze::{analyze_repo, finish_outputs, outputs_working_file, prepare_analysis, run_outputs_with, AnalyzeOptions};
+use crate::analyzers::Selection;
+use crate::clone::{clone_record, clone_repos, clone_with_retries, output_name, plan_clones, strip_git, write_name_map, AuthOptions, CloneJob, CloneOptions, CloneOutcome, GitAuth, GitBackend, HistoryOptions, OnExisting, CLONE_MANIFEST_FILE, DEFAULT_CLONE_ERRORS_FILE};
+use crate::collect::{code_working_file, collect_code_all, collect_code_into, dedup_collected, finish_code, skipped_files_beside, CodeSource, CollectOptions, SkippedFiles};
+use crate::columnar::{parquet_path, FileFormat};
+use crate::config::{Config, DEFAULT_CONFIG_FILE};
+use crate::filter::{filter_csv_with, FilterOptions};
+use crate::generated::flagged_files_beside;
+use crate::github::Tokens;
+use crate::jsonl::{Codec, JsonlWriter};
+use crate::license::licenses_beside;
+use crate::manifest::RunManifest;
+use crate::metadata::{fetch_metadata, MetadataOptions, DEFAULT_METADATA_FILE};
+use crate::plan::{plan_clone, plan_collect, plan_outputs, ClonePlan, Plan};
+use crate::progress::Progress;
+use crate::secrets::{secrets_report_beside, SecretsMode};
+use crate::shard::{manifest_path, ShardOptions};
+use crate::shutdown::{shutdown_requested, tools_killed, Checkpoint};
+use crate::upload::{upload_files, Credentials, Destination, UploadTarget};
+
+/// Where `--stream` records failed repositories, next to the outputs file, unless `--errors-file`
+/// says otherwise.
+pub const DEFAULT_STREAM_ERRORS_FILE: &str = "stream_errors.jsonl";
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct StreamOptions {
+    /// Clone, analyze and collect each repository in turn (`--jobs` at once), deleting its
+    /// checkout afterwards, so disk usage stays bounded
+    #[arg(long)]
+    pub stream: bool,
+    /// With --stream, keep every checkout
+    #[arg(long, requires = "stream")]
+    pub keep_repos: bool,
+    /// With --stream, keep the checkouts of repositories that failed at any stage
+    #[arg(long, requires = "stream")]
+    pub keep_failed: bool,
+    /// With --stream, where to record failures (default: `stream_errors.jsonl` next to the outputs file)
+    #[arg(long, value_name = "PATH", requires = "stream")]
+    pub errors_file: Option<String>,
+}
+
+/// Written next to the outputs file after every step of `full`.
+pub const PIPELINE_STATE_FILE: &str = "pipeline_state.json";
+
+/// A step of `full`, in the order they run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    /// The input CSV to the filtered repositories file
+    Filter,
+    /// The filtered repositories to checkouts in the datasets directory (and metadata)
+    Clone,
+    /// The checkouts to the outputs file
+    Outputs,
+    /// The checkouts to the code file
+    Collect,
+}
+
+impl Step {
+    pub const ALL: [Step; 4] = [Step::Filter, Step::Clone, Step::Outputs, Step::Collect];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Step::Filter => "filter",
+            Step::Clone => "clone",
+            Step::Outputs => "outputs",
+            Step::Collect => "collect",
+        }
+    }
+}
+
+/// Which steps `full` runs; the others' files must already be there.
+#[derive(Args, Clone, Debug, Default)]
+pub struct StepOptions {
+    /// Start at this step, against the files the earlier ones wrote
+    #[arg(long, value_enum, value_name = "STEP")]
+    pub from_step: Option<Step>,
+    /// Steps not to run, comma-separated, e.g. `filter,clone`
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "STEPS")]
+    pub skip_steps: Vec<Step>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Completed,
+    Failed,
+    /// Not run, and never completed by an earlier run
+    Skipped,
+}
+
+/// What `pipeline_state.json` records of a step.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StepRecord {
+    pub step: Step,
+    pub status: StepStatus,
+    /// The run the step ran in: an earlier one when this run skipped it
+    pub run_id: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// `pipeline_state.json`: the steps of the last `full` run and the settings it resolved from
+/// the config file and flags.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PipelineState {
+    pub run_id: String,
+    pub updated_at: String,
+    pub config: Config,
+    pub steps: Vec<StepRecord>,
+}
+
+impl PipelineState {
+    pub fn path_beside(outputs_file: &str) -> PathBuf {
+        Path::new(outputs_file).parent().unwrap_or(Path::new("")).join(PIPELINE_STATE_FILE)
+    }
+
+    /// The state an earlier run left next to `outputs_file`; `None` when there is none.
+    pub fn load_beside(outputs_file: &str) -> anyhow::Result<Option<Self>> {
+        let path = Self::path_beside(outputs_file);
+        let Ok(text) = fs::read_to_string(&path) else { return Ok(None) };
+        serde_json::from_str(&text).map(Some).with_context(|| format!("{} isn't a pipeline state; remove it to start over", path.display()))
+    }
+
+    pub fn record(&self, step: Step) -> Option<&StepRecord> {
+        self.steps.iter().find(|r| r.step == step)
+    }
+
+    fn set(&mut self, record: StepRecord, outputs_file: &str) -> anyhow::Result<()> {
+        match self.steps.iter_mut().find(|r| r.step == record.step) {
+            Some(r) => *r = record,
+            None => self.steps.push(record),
+        }
+        self.steps.sort_by_key(|r| r.step);
+        self.updated_at = chrono::Utc::now().to_rfc3339();
+        let path = Self::path_beside(outputs_file);
+        fs::write(&path, serde_json::to_string_pretty(self)? + "\n").with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+/// `file` as `outputs` or `collect` leave it: with the extension of `--compress`, as Parquet,
+/// or as the manifest of its shards.
+fn finished_file(file: &str, format: FileFormat, compress: Option<Codec>, shard: &ShardOptions) -> anyhow::Result<String> {
+    let file = match format {
+        FileFormat::Parquet => parquet_path(file).display().to_string(),
+        FileFormat::Jsonl => Codec::path(compress, file)?,
+    };
+    Ok(match shard.enabled() {
+        true => manifest_path(&file).display().to_string(),
+        false => file,
+    })
+}
+
+/// One line of the `--stream` errors file: a repository and the stage it failed at.
+#[derive(Debug, Serialize)]
+pub struct StreamError {
+    pub name: String,
+    /// `clone`, `analyze`, `collect` or `delete`
+    pub stage: &'static str,
+    pub error: String,
+}
+
+/// Every step of the dataset build, with the files it reads and writes. `Default` uses the
+/// layout the `full` command runs in: `input.csv`, `datasets/`, `outputs.jsonl`, `code.jsonl`.
+#[derive(Clone, Debug)]
+pub struct Pipeline {
+    pub tokens: Tokens,
+    pub input_csv: String,
+    pub filtered_repos: String,
+    pub datasets_dir: String,
+    pub outputs_file: String,
+    pub code_file: String,
+    /// Fetch GitHub metadata into `metadata_file` after cloning
+    pub metadata: bool,
+    pub metadata_file: String,
+    /// Where the clone step records failures; `None` puts `clone_errors.jsonl` next to `outputs_file`
+    pub clone_errors: Option<String>,
+    pub on_existing: OnExisting,
+    /// Repositories cloned concurrently (without `--stream`, which clones on the analysis workers)
+    pub clone_jobs: usize,
+    /// Retries of a clone after a transient failure
+    pub clone_retries: u32,
+    /// How much of each repository's history to clone, and whether with its submodules
+    pub history: HistoryOptions,
+    /// Delete each checkout's `.git` once its revision is recorded
+    pub strip_git: bool,
+    pub git_backend: GitBackend,
+    pub auth: AuthOptions,
+    pub filter: FilterOptions,
+    pub analyze: AnalyzeOptions,
+    pub collect: CollectOptions,
+    pub stream: StreamOptions,
+    /// Where to upload the output files once the run succeeds
+    pub upload: UploadTarget,
+    pub steps: StepOptions,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Pipeline {
+            tokens: Tokens::default(),
+            input_csv: "input.csv".into(),
+            filtered_repos: "filtered_repos.txt".into(),
+            datasets_dir: "datasets".into(),
+            outputs_file: "outputs.jsonl".into(),
+            code_file: "code.jsonl".into(),
+            metadata: false,
+            metadata_file: DEFAULT_METADATA_FILE.into(),
+            clone_errors: None,
+            on_existing: OnExisting::Skip,
+            clone_jobs: 1,
+            clone_retries: 3,
+            history: HistoryOptions::default(),
+            strip_git: false,
+            git_backend: GitBackend::Libgit2,
+            auth: AuthOptions::default(),
+            filter: FilterOptions::default(),
+            analyze: AnalyzeOptions::default(),
+            collect: CollectOptions::default(),
+            stream: StreamOptions::default(),
+            upload: UploadTarget::default(),
+            steps: StepOptions::default(),
+        }
+    }
+}
+
+impl Pipeline {
+    pub fn run(&self) -> anyhow::Result<()> {
+        tracing::info!("Starting full dataset extraction pipeline...");
+        let config = Config::effective(self);
+        tracing::info!("Effective configuration:\n{}", toml::to_string(&config)?.trim_end());
+        let previous = PipelineState::load_beside(&self.outputs_file)?;
+        let steps = self.steps_to_run(previous.as_ref(), &config)?;
+        self.check_inputs(&steps)?;
+        // Fail on a bad --tools-config, analyzer name, sandbox or --compress before filtering and cloning.
+        if steps.contains(&Step::Outputs) {
+            self.analyze.validate()?;
+            self.outputs_written()?;
+        }
+        if steps.contains(&Step::Collect) {
+            self.code_written()?;
+        }
+        // Nor find out the upload can't happen once everything else has.
+        if let Some(to) = &self.upload.upload_to {
+            Destination::parse(to)?;
+            Credentials::from_env()?;
+        }
+        let run = RunManifest::capture(&self.analyze);
+        // A skipped outputs step keeps the manifest of the run that wrote the outputs file.
+        if steps.contains(&Step::Outputs) {
+            run.write_beside(&self.outputs_file)?;
+            tracing::info!("Run {} (environment recorded in manifest.json)", run.run_id);
+        }
+        let mut state = PipelineState { run_id: run.run_id.clone(), updated_at: String::new(), config, steps: Vec::new() };
+        for (i, step) in Step::ALL.into_iter().enumerate().filter(|(_, s)| !steps.contains(s)) {
+            tracing::info!("Step {}/4: {} skipped", i + 1, step.name());
+            let done = previous.as_ref().and_then(|p| p.record(step)).filter(|r| r.status == StepStatus::Completed);
+            state.set(done.cloned().unwrap_or_else(|| self.step_record(step, StepStatus::Skipped, &run.run_id)), &self.outputs_file)?;
+        }
+
+        // Step 1: Filter CSV
+        let input_csv = &self.input_csv;
+        let filtered_repos = &self.filtered_repos;
+        if steps.contains(&Step::Filter) {
+            self.step(&mut state, &[Step::Filter], || {
+                tracing::info!("Step 1/4: Filtering repositories from {}", input_csv);
+                let summary = filter_csv_with(input_csv, filtered_repos, &self.filter)?;
+                tracing::info!("✓ {} ({})", summary, filtered_repos);
+                for (flag, eliminated) in &summary.eliminated {
+                    tracing::info!("  {} eliminated by {}", eliminated, flag);
+                }
+                Ok(())
+            })?;
+        }
+
+        if self.stream.stream && steps.contains(&Step::Clone) {
+            self.step(&mut state, &[Step::Clone, Step::Outputs, Step::Collect], || {
+                // The checkouts are deleted as they go, so every filtered repository is looked up.
+                self.fetch_metadata(None)?;
+                self.run_streaming(&run)
+            })?;
+            return self.upload_outputs();
+        }
+
+        // Step 2: Clone repositories
+        let datasets_dir = &self.datasets_dir;
+        if steps.contains(&Step::Clone) {
+            self.step(&mut state, &[Step::Clone], || {
+                tracing::info!("Step 2/4: Cloning repositories to {}/", datasets_dir);
+                let clone = self.clone_options();
+                let summary = clone_repos(filtered_repos, datasets_dir, &self.tokens, &clone)?;
+                if shutdown_requested() {
+                    // Checkouts already there are skipped, so cloning needs no checkpoint to resume.
+                    anyhow::bail!("interrupted while cloning; run again with --resume to continue");
+                }
+                if summary.failed.is_empty() {
+                    tracing::info!("✓ Repositories cloned successfully");
+                } else {
+                    tracing::warn!("{} repositories failed to clone (see {}), continuing with the rest", summary.failed.len(), clone.errors_file);
+                }
+                self.fetch_metadata(Some(datasets_dir))
+            })?;
+        }
+
+        // Step 3: Run analysis and collect outputs
+        let outputs_file = &self.outputs_written()?;
+        if steps.contains(&Step::Outputs) {
+            self.step(&mut state, &[Step::Outputs], || {
+                tracing::info!("Step 3/4: Running analysis tools and collecting outputs");
+                run_outputs_with(datasets_dir, &self.outputs_file, &self.analyze, &run)?;
+                tracing::info!("✓ Analysis outputs saved to {}", outputs_file);
+                Ok(())
+            })?;
+        }
+
+        // Step 4: Collect source code
+        let code_file = &self.code_written()?;
+        if steps.contains(&Step::Collect) {
+            self.step(&mut state, &[Step::Collect], || {
+                tracing::info!("Step 4/4: Collecting source code from repositories");
+                collect_code_all(datasets_dir, &self.code_file, &self.collect_options())?;
+                tracing::info!("✓ Source code collected to {}", code_file);
+                Ok(())
+            })?;
+        }
+
+        tracing::info!("🎉 Full pipeline completed successfully!");
+        tracing::info!("Generated files: {} (analysis and tool outputs), {} (source code), {}/ (checkouts)", outputs_file, code_file, datasets_dir);
+        if self.metadata {
+            tracing::info!("GitHub metadata: {} (joins the others on `name`)", self.metadata_file);
+        }
+        self.upload_outputs()
+    }
+
+    /// What `run` would do, for `--dry-run`. A filter step is run into a temporary file, which the
+    /// clone step's plan reads instead of `filtered_repos`.
+    pub fn plan(&self) -> anyhow::Result<Plan> {
+        let config = Config::effective(self);
+        let previous = PipelineState::load_beside(&self.outputs_file)?;
+        let steps = self.steps_to_run(previous.as_ref(), &config)?;
+        self.check_inputs(&steps)?;
+        let mut plan = Plan { steps: steps.clone(), ..Plan::default() };
+        let mut cloned = Vec::new();
+        if steps.contains(&Step::Clone) {
+            let filtered = std::env::temp_dir().join(format!("dataset_builder_plan_{}.txt", std::process::id()));
+            let names_file = match steps.contains(&Step::Filter) {
+                true => {
+                    filter_csv_with(&self.input_csv, &filtered.to_string_lossy(), &self.filter)?;
+                    filtered.to_string_lossy().into_owned()
+                }
+                false => self.filtered_repos.clone(),
+            };
+            let clone = plan_clone(&names_file, &self.datasets_dir, &self.clone_options());
+            let _ = fs::remove_file(&filtered);
+            let clone = ClonePlan { names_file: self.filtered_repos.clone(), ..clone? };
+            cloned = clone.repos.iter().filter_map(|r| Path::new(&r.dest).file_name()).map(|d| d.to_string_lossy().into_owned()).collect();
+            plan.clone = Some(clone);
+        }
+        if steps.contains(&Step::Outputs) {
+            plan.outputs = Some(plan_outputs(&self.datasets_dir, &self.outputs_file, &self.analyze, &cloned)?);
+        }
+        if steps.contains(&Step::Collect) {
+            plan.collect = Some(plan_collect(&self.datasets_dir, &self.code_file, &self.collect_options(), &cloned)?);
+        }
+        Ok(plan)
+    }
+
+    /// The clone step's errors file, `clone_errors` or the default next to the outputs file.
+    pub fn clone_errors_file(&self) -> String {
+        self.beside_outputs(self.clone_errors.as_ref(), DEFAULT_CLONE_ERRORS_FILE)
+    }
+
+    /// `--stream`'s errors file, `stream.errors_file` or the default next to the outputs file.
+    pub fn stream_errors_file(&self) -> String {
+        self.beside_outputs(self.stream.errors_file.as_ref(), DEFAULT_STREAM_ERRORS_FILE)
+    }
+
+    fn beside_outputs(&self, file: Option<&String>, default: &str) -> String {
+        match file {
+            Some(file) => file.clone(),
+            None => Path::new(&self.outputs_file).parent().unwrap_or(Path::new("")).join(default).display().to_string(),
+        }
+    }
+
+    fn clone_options(&self) -> CloneOptions {
+        // Every field spelled out, so none of the `clone` command's cwd-relative defaults slips in.
+        CloneOptions {
+            on_existing: self.on_existing,
+            jobs: self.clone_jobs,
+            retries: self.clone_retries,
+            errors_file: self.clone_errors_file(),
+            max_failure_rate: 0.0,
+            retry_from: None,
+            min_free_gb: None,
+            max_total_gb: None,
+            max_repo_mb: None,
+            metadata_file: self.metadata_file.clone(),
+            history: self.history.clone(),
+            strip_git: self.strip_git,
+            git_backend: self.git_backend,
+            auth: self.auth.clone(),
+        }
+    }
+
+    /// The outputs file as the outputs step leaves it.
+    fn outputs_written(&self) -> anyhow::Result<String> {
+        finished_file(&self.outputs_file, self.analyze.format, self.analyze.compress, &self.analyze.shard)
+    }
+
+    /// The code file as the collect step leaves it.
+    fn code_written(&self) -> anyhow::Result<String> {
+        finished_file(&self.code_file, self.collect.format, self.collect.compress, &self.collect.shard)
+    }
+
+    fn collect_options(&self) -> CollectOptions {
+        CollectOptions { resume: self.analyze.resume, jobs: self.analyze.jobs, ..self.collect.clone() }
+    }
+
+    /// The steps `--from-step` and `--skip-steps` leave; with `--resume` alone, those the last
+    /// run didn't complete, unless it ran with other settings.
+    fn steps_to_run(&self, previous: Option<&PipelineState>, config: &Config) -> anyhow::Result<Vec<Step>> {
+        let StepOptions { from_step, skip_steps } = &self.steps;
+        let mut steps: Vec<Step> = Step::ALL.into_iter().filter(|s| from_step.is_none_or(|from| *s >= from) && !skip_steps.contains(s)).collect();
+        if self.analyze.resume && from_step.is_none() && skip_steps.is_empty() {
+            match previous {
+                Some(p) if p.config == *config => steps.retain(|s| p.record(*s).is_none_or(|r| r.status != StepStatus::Completed)),
+                Some(_) => tracing::info!("The settings differ from those in {}, so no step is taken as done", PIPELINE_STATE_FILE),
+                None => {}
+            }
+        }
+        let streamed = [Step::Clone, Step::Outputs, Step::Collect].iter().filter(|s| steps.contains(s)).count();
+        if self.stream.stream && streamed != 0 && streamed != 3 {
+            anyhow::bail!("--stream runs clone, outputs and collect together; only filter can be left out");
+        }
+        Ok(steps)
+    }
+
+    /// Checks that the files the first of `steps` reads are there, and those of any later step
+    /// whose producing step doesn't run.
+    fn check_inputs(&self, steps: &[Step]) -> anyhow::Result<()> {
+        for step in steps {
+            match step {
+                Step::Filter if !Path::new(&self.input_csv).is_file() => {
+                    anyhow::bail!("{} not found; create it with columns: id,name,has_toml,has_lock, or set paths.input_csv in {}",
+                        self.input_csv, DEFAULT_CONFIG_FILE);
+                }
+                Step::Clone if !steps.contains(&Step::Filter) && !Path::new(&self.filtered_repos).is_file() => {
+                    anyhow::bail!("{} not found; the {} step needs the filter step's output", self.filtered_repos, step.name());
+                }
+                Step::Outputs | Step::Collect if !steps.contains(&Step::Clone) => {
+                    let checkouts = fs::read_dir(&self.datasets_dir).map(|d| d.flatten().any(|e| e.path().is_dir())).unwrap_or(false);
+                    anyhow::ensure!(checkouts, "{}/ has no checkouts; the {} step needs the clone step's", self.datasets_dir, step.name());
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// The files `step` reads and writes, with no run times yet.
+    fn step_record(&self, step: Step, status: StepStatus, run_id: &str) -> StepRecord {
+        let (inputs, outputs) = match step {
+            Step::Filter => (vec![self.input_csv.clone()], vec![self.filtered_repos.clone()]),
+            Step::Clone => {
+                let mut outputs = vec![self.datasets_dir.clone()];
+                if self.metadata {
+                    outputs.push(self.metadata_file.clone());
+                }
+                (vec![self.filtered_repos.clone()], outputs)
+            }
+            Step::Outputs => (vec![self.datasets_dir.clone()], vec![self.outputs_written().unwrap_or_else(|_| self.outputs_file.clone())]),
+            Step::Collect => (vec![self.datasets_dir.clone()], vec![self.code_written().unwrap_or_else(|_| self.code_file.clone())]),
+        };
+        StepRecord { step, status, run_id: run_id.to_string(), inputs, outputs, started_at: None, finished_at: None, error: None }
+    }
+
+    /// Runs `f` as `steps` (several with `--stream`), recording how it went in the state file.
+    fn step(&self, state: &mut PipelineState, steps: &[Step], f: impl FnOnce() -> anyhow::Result<()>) -> anyhow::Result<()> {
+        let started_at = chrono::Utc::now().to_rfc3339();
+        let result = f();
+        let (status, error) = match &result {
+            Ok(()) => (StepStatus::Completed, None),
+            Err(e) => (StepStatus::Failed, Some(format!("{:#}", e))),
+        };
+        let (run_id, finished_at) = (state.run_id.clone(), chrono::Utc::now().to_rfc3339());
+        for step in steps {
+            let record = StepRecord { started_at: Some(started_at.clone()), finished_at: Some(finished_at.clone()), error: error.clone(), ..self.step_record(*step, status, &run_id) };
+            state.set(record, &self.outputs_file)?;
+        }
+        result
+    }
+
+    /// With `--upload-to`, uploads the outputs and code files (their shard manifests, when they
+    /// were sharded), manifest.json and the metadata file.
+    fn upload_outputs(&self) -> anyhow::Result<()> {
+        let Some(to) = &self.upload.upload_to else { return Ok(()) };
+        let mut files = vec![self.outputs_written()?, self.code_written()?];
+        files.push(RunManifest::path_beside(&self.outputs_file).display().to_string());
+        if self.metadata {
+            files.push(self.metadata_file.clone());
+        }
+        files.retain(|f| Path::new(f).exists());
+        tracing::info!("Uploading {} to {}", files.join(", "), to);
+        let s = upload_files(&files, to, &self.upload.store)?;
+        tracing::info!("✓ Uploaded {} objects ({} bytes) to {}, {} already there", s.uploaded, s.bytes, to, s.skipped);
+        Ok(())
+    }
+
+    /// With `metadata`, fetches the metadata of the filtered repositories (those checked out in
+    /// `root`, if given).
+    fn fetch_metadata(&self, root: Option<&str>) -> anyhow::Result<()> {
+        if !self.metadata {
+            return Ok(());
+        }
+        tracing::info!("Fetching GitHub metadata into {}", self.metadata_file);
+        let opts = MetadataOptions { root: root.map(String::from), ..MetadataOptions::default() };
+        let s = fetch_metadata(&self.filtered_repos, &self.metadata_file, &opts, &self.tokens)?;
+        tracing::info!("✓ Metadata of {} repositories ({} fetched, {} unchanged, {} not found)", s.repos, s.fetched, s.unchanged, s.not_found);
+        if shutdown_requested() {
+            anyhow::bail!("interrupted while fetching metadata; run again to continue");
+        }
+        Ok(())
+    }
+
+    /// Steps 2-4 one repository at a time: clone, analyze, collect, then delete the checkout.
+    fn run_streaming(&self, run: &RunManifest) -> anyhow::Result<()> {
+        tracing::info!("Steps 2-4/4: Streaming repositories through clone → outputs → collect");
+        self.history.validate(self.git_backend)?;
+        let analyzers = prepare_analysis(&self.analyze, run)?;
+        // Without a checkpoint the output files can't tell which repositories were finished.
+        let checkpoint = match self.analyze.resume {
+            true => Some(Checkpoint::load_beside(&self.outputs_file, "stream")?
+                .context("--resume with --stream needs the checkpoint.json of an interrupted --stream run")?),
+            false => None,
+        };
+        let (mut done, cloned_before) = checkpoint.map(|c| (c.completed, c.cloned)).unwrap_or_default();
+        let names = fs::read_to_string(&self.filtered_repos)?;
+        let mut clashes = Vec::new();
+        let mut work = plan_clones(&names, &self.datasets_dir, &mut clashes);
+        write_name_map(&self.datasets_dir, &work)?;
+        work.retain(|job| !done.iter().any(|d| d == job.name));
+        if self.analyze.resume {
+            tracing::info!("Resuming: {} repositories already streamed, {} to go", done.len(), work.len());
+        }
+        let errors_file = &self.stream_errors_file();
+        let open = |path: &str| match self.analyze.resume {
+            true => OpenOptions::new().create(true).append(true).open(path),
+            false => File::create(path),
+        };
+        fs::create_dir_all(&self.datasets_dir)?;
+        let (outputs_working, code_working) = (outputs_working_file(&self.outputs_file, &self.analyze)?, code_working_file(&self.code_file, &self.collect)?);
+        let out = StreamWriters {
+            clones: Mutex::new(JsonlWriter::new(open(&Path::new(&self.datasets_dir).join(CLONE_MANIFEST_FILE).display().to_string())?, None)),
+            outputs: Mutex::new(JsonlWriter::compressed(open(&outputs_working)?, self.analyze.compress.unwrap_or(Codec::of(&outputs_working)), self.analyze.fsync_every)?),
+            code: Mutex::new(JsonlWriter::compressed(open(&code_working)?, self.collect.compress.unwrap_or(Codec::of(&code_working)), self.collect.fsync_every)?),
+            skipped: Mutex::new(JsonlWriter::new(open(&skipped_files_beside(&self.code_file).display().to_string())?, None)),
+            licenses: Mutex::new(JsonlWriter::new(open(&licenses_beside(&self.code_file).display().to_string())?, None)),
+            flagged: Mutex::new(JsonlWriter::new(open(&flagged_files_beside(&self.code_file).display().to_string())?, None)),
+            secrets: match self.collect.secrets {
+                SecretsMode::Off => None,
+                _ => Some(Mutex::new(JsonlWriter::new(open(&secrets_report_beside(&self.code_file).display().to_string())?, None))),
+            },
+            errors: Mutex::new(JsonlWriter::new(open(errors_file)?, None)),
+            progress: Progress::new(work.len(), "repositories streamed"),
+            cloned_before,
+            kept: Mutex::new(Vec::new()),
+        };
+        if !self.analyze.resume {
+            for e in clashes {
+                out.record(&e.name, "clone", e.error)?;
+            }
+        }
+
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(self.analyze.jobs.max(1)).build()?;
+        let results: Vec<Option<bool>> = pool.install(|| {
+            work.par_iter()
+                .map(|job| {
+                    // After Ctrl-C, only the repositories already in flight are finished.
+                    if shutdown_requested() {
+                        return Ok(None);
+                    }
+                    let bar = out.progress.start(job.name);
+                    let failed = self.stream_one(job, &analyzers, run, &bar, &out);
+                    out.progress.finish(bar);
+                    failed
+                })
+                .collect::<anyhow::Result<_>>()
+        })?;
+        out.progress.done();
+        out.clones.into_inner().unwrap().finish()?;
+        out.outputs.into_inner().unwrap().finish()?;
+        out.code.into_inner().unwrap().finish()?;
+        out.skipped.into_inner().unwrap().finish()?;
+        out.licenses.into_inner().unwrap().finish()?;
+        out.flagged.into_inner().unwrap().finish()?;
+        if let Some(secrets) = out.secrets {
+            secrets.into_inner().unwrap().finish()?;
+        }
+        out.errors.into_inner().unwrap().finish()?;
+
+        let streamed = results.iter().flatten().count();
+        if shutdown_requested() {
+            let finished: Vec<String> = work.iter().zip(&results).filter(|(_, r)| r.is_some()).map(|(job, _)| job.name.to_string()).collect();
+            // Those not reached this time are still to be deleted by the next run.
+            let mut cloned = out.kept.into_inner().unwrap();
+            cloned.extend(out.cloned_before.into_iter().filter(|name| !finished.contains(name)));
+            cloned.sort();
+            cloned.dedup();
+            done.extend(finished);
+            Checkpoint { cloned, ..Checkpoint::new("stream", done) }.write_beside(&self.outputs_file)?;
+            anyhow::bail!("interrupted after streaming {} of {} repositories; run again with --stream --resume to continue from checkpoint.json",
+                streamed, work.len());
+        }
+        Checkpoint::remove_beside(&self.outputs_file, "stream");
+        dedup_collected(&code_working, &self.collect)?;
+        finish_outputs(&outputs_working, &self.analyze)?;
+        finish_code(&code_working, &self.collect)?;
+        let failed = results.iter().flatten().filter(|f| **f).count();
+        tracing::info!("✓ Streamed {} repositories, {} failed (see {})", work.len(), failed, errors_file);
+        tracing::info!("Generated files: {} (analysis and tool outputs), {} (source code)", self.outputs_written()?, self.code_written()?);
+        Ok(())
+    }
+
+    /// Streams one repository, returning whether any stage failed, or `None` when Ctrl-C cut its
+    /// analysis short. Only failing to write the output files is an error.
+    fn stream_one(&self, job: &CloneJob, analyzers: &Selection, run: &RunManifest, bar: &ProgressBar, out: &StreamWriters) -> anyhow::Result<Option<bool>> {
+        let (name, dest) = (job.name, job.dest.as_path());
+        bar.set_message("cloning");
+        let cloned = clone_with_retries(job, GitAuth { opts: &self.auth, token: self.tokens.for_clone() }, self.on_existing, &self.history, self.git_backend, self.clone_retries, bar).0.map(|outcome| (clone_record(job, &outcome, &self.history, self.strip_git), outcome));
+        let cloned = cloned.and_then(|(record, outcome)| {
+            if self.strip_git {
+                strip_git(dest)?;
+            }
+            Ok((record, outcome))
+        });
+        let mut commit = None;
+        let cloned = match cloned {
+            Ok((record, outcome)) => {
+                if let Some(record) = record {
+                    out.clones.lock().unwrap().write_entry(&record)?;
+                    commit = Some(record.commit);
+                }
+                // Or cloned by the interrupted run this one resumes.
+                matches!(outcome, CloneOutcome::Cloned) || out.cloned_before.iter().any(|n| n == name)
+            }
+            Err(e) => {
+                out.record(name, "clone", format!("{:#}", e))?;
+                return Ok(Some(true));
+            }
+        };
+        bar.set_message("analyzing");
+        let dir = dest.file_name().unwrap().to_string_lossy();
+        let repo = output_name(name);
+        let mut entries = analyze_repo(dest, &dir, analyzers, &self.analyze, run);
+        for entry in &mut entries {
+            entry.name = repo.clone();
+            entry.repo = Some(name.to_string());
+            entry.commit = entry.commit.take().or_else(|| commit.clone());
+        }
+        if tools_killed() {
+            // Its checkout is kept, and it is streamed (then deleted) again on --resume.
+            if cloned {
+                out.kept.lock().unwrap().push(name.to_string());
+            }
+            return Ok(None);
+        }
+        let mut failed = false;
+        {
+            let mut w = out.outputs.lock().unwrap();
+            for entry in &entries {
+                w.write_entry(entry)?;
+            }
+        }
+        let tool_errors: Vec<&str> = entries.iter().flat_map(|e| &e.errors).map(String::as_str).collect();
+        if !tool_errors.is_empty() {
+            out.record(name, "analyze", tool_errors.join("; "))?;
+            failed = true;
+        }
+        bar.set_message("collecting");
+        let source = CodeSource { name: repo.clone(), name_unresolved: false, repo: Some(name.to_string()), commit };
+        // A write error is kept apart, since it fails the run rather than the repository.
+        let mut write_error = None;
+        let emit = &mut |file| out.code.lock().unwrap().write_entry(&file).map_err(|e| anyhow::anyhow!("{:#}", write_error.insert(e)));
+        let collected = collect_code_into(dest, &source, &self.collect, emit);
+        if let Some(e) = write_error {
+            return Err(e);
+        }
+        match collected {
+            Ok(report) => {
+                out.licenses.lock().unwrap().write_entry(&report.license)?;
+                if !report.flagged.is_empty() {
+                    out.flagged.lock().unwrap().write_entry(&report.flagged)?;
+                }
+                if let Some(w) = &out.secrets {
+                    let mut w = w.lock().unwrap();
+                    report.secrets.iter().try_for_each(|f| w.write_entry(f))?;
+                }
+                if !report.skipped.is_empty() {
+                    out.skipped.lock().unwrap().write_entry(&SkippedFiles { name: repo.clone(), dir: dir.to_string(), files: report.skipped })?;
+                }
+            }
+            Err(e) => {
+                out.record(name, "collect", format!("{:#}", e))?;
+                failed = true;
+            }
+        }
+        // Checkouts that were already there before the run are never deleted.
+        if cloned && !self.stream.keep_repos && !(failed && self.stream.keep_failed) {
+            if let Err(e) = fs::remove_dir_all(dest) {
+                out.record(name, "delete", e.to_string())?;
+            }
+        }
+        Ok(Some(failed))
+    }
+}
+
+/// The files `--stream` appends to, shared by its workers.
+struct StreamWriters {
+    clones: Mutex<JsonlWriter>,
+    outputs: Mutex<JsonlWriter>,
+    code: Mutex<JsonlWriter>,
+    skipped: Mutex<JsonlWriter>,
+    licenses: Mutex<JsonlWriter>,
+    flagged: Mutex<JsonlWriter>,
+    /// With `--secrets`
+    secrets: Option<Mutex<JsonlWriter>>,
+    errors: Mutex<JsonlWriter>,
+    progress: Progress,
+    /// `Checkpoint::cloned` of the run being resumed
+    cloned_before: Vec<String>,
+    /// The checkouts this run cloned for repositories Ctrl-C cut short
+    kept: Mutex<Vec<String>>,
+}
+
+impl StreamWriters {
+    fn record(&self, name: &str, stage: &'static str, error: String) -> anyhow::Result<()> {
+        self.progress.suspend(|| tracing::warn!(event = "repo_failed", repo = name, stage, reason = %error, "✗ {}: {} failed", name, stage));
+        self.errors.lock().unwrap().write_entry(&StreamError { name: name.to_string(), stage, error })
+    }
+}
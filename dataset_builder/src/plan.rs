@@ -0,0 +1,248 @@
+//! `--dry-run`: what `clone`, `outputs`, `collect` and `full` would do, worked out from the names
+//! file, the checkouts and the files of earlier runs, without cloning or spawning any tool.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::analyze::{resume_state, AnalyzeOptions};
+use crate::analyzers::select_analyzers;
+use crate::cache::clean_head;
+use crate::clone::{clone_names, github_repo, metadata_sizes, plan_clones, skip_oversized, CloneError, CloneOptions, OnExisting};
+use crate::collect::CollectOptions;
+use crate::columnar::{working_jsonl, FileFormat};
+use crate::jsonl::Codec;
+use crate::pipeline::Step;
+use crate::sandbox::SandboxKind;
+use crate::shutdown::Checkpoint;
+use crate::stats::human_bytes;
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct DryRunOptions {
+    /// Print what would be cloned, analyzed and collected, and exit without cloning or running
+    /// any tool
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Print the --dry-run plan as JSON
+    #[arg(long, requires = "dry_run")]
+    pub json: bool,
+}
+
+/// What a command would do, one part per step it runs.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Plan {
+    /// With `full`, the steps that would run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<Step>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clone: Option<ClonePlan>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outputs: Option<OutputsPlan>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collect: Option<CollectPlan>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ClonePlan {
+    pub names_file: String,
+    pub datasets_dir: String,
+    pub on_existing: OnExisting,
+    /// Where the failed clones would be recorded
+    pub errors_file: String,
+    pub repos: Vec<PlannedClone>,
+    /// Lines that wouldn't be cloned: invalid names, clashing destinations, over `--max-repo-mb`
+    pub rejected: Vec<CloneError>,
+    /// What the new clones would take, from the sizes in the metadata file
+    pub estimated_bytes: u64,
+    /// New clones the metadata file has no size for, left out of `estimated_bytes`
+    pub without_size: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PlannedClone {
+    pub name: String,
+    pub dest: String,
+    /// The destination is already there, so `on_existing` decides what happens to it
+    pub exists: bool,
+    /// GitHub's size of the repository, from the metadata file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct OutputsPlan {
+    pub root: String,
+    /// Where entries are written; a Parquet output is written as JSONL until the end
+    pub outputs_file: String,
+    /// The checkout directories that would be analyzed
+    pub repos: Vec<String>,
+    /// In run order
+    pub analyzers: Vec<PlannedAnalyzer>,
+    /// Known analyzers left out by `--only`/`--skip`, or opt-in and not enabled
+    pub skipped_analyzers: Vec<String>,
+    pub repo_timeout_secs: Option<u64>,
+    /// Checkouts `--resume` skips, their entries being in the outputs file already
+    pub resumed: Vec<String>,
+    /// Checkouts of `repos` whose HEAD has a `--cache-dir` result for every analyzer, reused as
+    /// long as the tools' versions haven't changed
+    pub cached: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PlannedAnalyzer {
+    pub name: String,
+    /// `{repo}`, `{crate}` and `{target}` stand for the checkout, the crate's directory and
+    /// `--lib` or `--bin NAME`; `None` for analyzers computed in process
+    pub command: Option<String>,
+    pub timeout_secs: Option<u64>,
+    /// Runs inside `--sandbox`
+    pub sandboxed: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CollectPlan {
+    pub root: String,
+    /// Where entries are written; a Parquet output is written as JSONL until the end
+    pub code_file: String,
+    /// The checkout directories that would be collected
+    pub repos: Vec<String>,
+    /// Checkouts `--resume` skips, listed as collected in the checkpoint
+    pub resumed: Vec<String>,
+}
+
+/// What `clone_repos` would do with `names_file`.
+pub fn plan_clone(names_file: &str, out_root: &str, opts: &CloneOptions) -> anyhow::Result<ClonePlan> {
+    let names = clone_names(names_file, opts)?;
+    let mut rejected = Vec::new();
+    let mut work = plan_clones(&names, out_root, &mut rejected);
+    if let Some(max) = opts.max_repo_mb {
+        work = skip_oversized(work, &opts.metadata_file, max, &mut rejected);
+    }
+    let sizes = metadata_sizes(&opts.metadata_file).unwrap_or_default();
+    let repos: Vec<PlannedClone> = work
+        .iter()
+        .map(|job| PlannedClone {
+            name: job.name.to_string(),
+            dest: job.dest.display().to_string(),
+            exists: job.dest.exists(),
+            size_bytes: github_repo(job.name).ok().and_then(|repo| sizes.get(&repo.to_ascii_lowercase())).map(|kb| kb * 1024),
+        })
+        .collect();
+    // Skipped and updated checkouts take no more room than they already do.
+    let new: Vec<&PlannedClone> = repos.iter().filter(|r| !r.exists || opts.on_existing == OnExisting::Overwrite).collect();
+    Ok(ClonePlan {
+        names_file: names_file.to_string(),
+        datasets_dir: out_root.to_string(),
+        on_existing: opts.on_existing,
+        errors_file: opts.errors_file.clone(),
+        estimated_bytes: new.iter().filter_map(|r| r.size_bytes).sum(),
+        without_size: new.iter().filter(|r| r.size_bytes.is_none()).count(),
+        repos,
+        rejected,
+    })
+}
+
+/// What `run_outputs` would do under `root`, where `cloned` are the checkout directories an
+/// earlier step would add.
+pub fn plan_outputs(root: &str, outputs_file: &str, opts: &AnalyzeOptions, cloned: &[String]) -> anyhow::Result<OutputsPlan> {
+    // Not `validate`, which probes the sandbox.
+    let selection = select_analyzers(opts)?;
+    let outputs_file = written_file(outputs_file, opts.format, opts.compress)?;
+    let done = match opts.resume {
+        true => resume_state(&outputs_file, opts, Checkpoint::load_beside(&outputs_file, "outputs")?.as_ref()).0,
+        false => HashSet::new(),
+    };
+    let (resumed, repos): (Vec<String>, Vec<String>) = checkout_dirs(root, cloned).into_iter().partition(|d| done.contains(d));
+    let cached = match opts.cache() {
+        Some(cache) if !selection.run.is_empty() => {
+            let heads = cache.heads();
+            let cached = |head: String| selection.run.iter().all(|a| heads.contains(&(head.clone(), a.name().to_string())));
+            repos.iter().filter(|d| clean_head(&Path::new(root).join(d)).is_some_and(&cached)).cloned().collect()
+        }
+        _ => Vec::new(),
+    };
+    let analyzers = selection
+        .run
+        .iter()
+        .map(|a| PlannedAnalyzer {
+            name: a.name().to_string(),
+            command: a.command(opts),
+            timeout_secs: a.timeout_secs(opts),
+            sandboxed: a.needs_sandbox() && opts.sandbox.sandbox != SandboxKind::None,
+        })
+        .collect();
+    Ok(OutputsPlan { root: root.to_string(), outputs_file, repos, analyzers, skipped_analyzers: selection.skipped, repo_timeout_secs: opts.repo_timeout, resumed, cached })
+}
+
+/// What `collect_code_all` would do under `root`, `cloned` as in `plan_outputs`.
+pub fn plan_collect(root: &str, code_file: &str, opts: &CollectOptions, cloned: &[String]) -> anyhow::Result<CollectPlan> {
+    let code_file = written_file(code_file, opts.format, opts.compress)?;
+    let done = match opts.resume {
+        true => Checkpoint::load_beside(&code_file, "collect")?.map(|c| c.completed).unwrap_or_default(),
+        false => Vec::new(),
+    };
+    let (resumed, repos) = checkout_dirs(root, cloned).into_iter().partition(|d| done.contains(d));
+    Ok(CollectPlan { root: root.to_string(), code_file, repos, resumed })
+}
+
+/// The file a step appends to, as `run_outputs` and `collect_code_all` resolve it.
+fn written_file(file: &str, format: FileFormat, compress: Option<Codec>) -> anyhow::Result<String> {
+    match format {
+        FileFormat::Parquet => Ok(working_jsonl(file)),
+        _ => Codec::path(compress, file),
+    }
+}
+
+/// The checkout directories under `root` and those `cloned` would add, sorted.
+fn checkout_dirs(root: &str, cloned: &[String]) -> Vec<String> {
+    let existing = fs::read_dir(root).into_iter().flatten().flatten().filter(|e| e.path().is_dir()).map(|e| e.file_name().to_string_lossy().into_owned());
+    let mut dirs: Vec<String> = existing.chain(cloned.iter().cloned()).collect();
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+/// Prints `plan`, as JSON with `json`.
+pub fn print_plan(plan: &Plan, json: bool) -> anyhow::Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(plan)?);
+        return Ok(());
+    }
+    println!("Dry run: nothing is cloned, analyzed or collected");
+    if !plan.steps.is_empty() {
+        println!("Steps: {}", plan.steps.iter().map(|s| s.name()).collect::<Vec<_>>().join(", "));
+    }
+    if let Some(c) = &plan.clone {
+        let existing = c.repos.iter().filter(|r| r.exists).count();
+        let on_existing = c.on_existing.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default();
+        println!("Clone: {} repositories from {} into {}/ ({} already there, --on-existing {})", c.repos.len(), c.names_file, c.datasets_dir, existing, on_existing);
+        for r in &c.repos {
+            let size = r.size_bytes.map_or(String::new(), |b| format!(" ({})", human_bytes(b)));
+            println!("  {} -> {}{}{}", r.name, r.dest, size, if r.exists { " [exists]" } else { "" });
+        }
+        for e in &c.rejected {
+            println!("  not cloned: {} ({})", e.name, e.error);
+        }
+        println!("  failures recorded in {}", c.errors_file);
+        println!("  estimated disk: {} ({} repositories without a size in the metadata file)", human_bytes(c.estimated_bytes), c.without_size);
+    }
+    if let Some(o) = &plan.outputs {
+        println!("Outputs: {} checkouts of {}/ into {} ({} skipped by --resume, {} from --cache-dir)", o.repos.len(), o.root, o.outputs_file, o.resumed.len(), o.cached.len());
+        let secs = |s: Option<u64>| s.map_or("none".to_string(), |s| format!("{}s", s));
+        println!("  repo timeout: {}", secs(o.repo_timeout_secs));
+        for a in &o.analyzers {
+            let command = a.command.as_deref().unwrap_or("(in process)");
+            println!("  {}: {} (timeout {}{})", a.name, command, secs(a.timeout_secs), if a.sandboxed { ", sandboxed" } else { "" });
+        }
+        if !o.skipped_analyzers.is_empty() {
+            println!("  not run: {}", o.skipped_analyzers.join(", "));
+        }
+    }
+    if let Some(c) = &plan.collect {
+        println!("Collect: {} checkouts of {}/ into {} ({} skipped by --resume)", c.repos.len(), c.root, c.code_file, c.resumed.len());
+    }
+    Ok(())
+}
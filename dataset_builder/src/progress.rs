@@ -0,0 +1,58 @@
+//! Progress bars for the long-running steps, drawn on stderr while stdout is a terminal.
+
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turns progress bars off for the rest of the process (`--no-progress`).
+pub fn disable_progress() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// An overall `done/total` bar with an ETA, and a line per item in flight.
+pub(crate) struct Progress {
+    multi: MultiProgress,
+    total: ProgressBar,
+}
+
+impl Progress {
+    /// Bars for `len` items; hidden with `--no-progress` or when stdout isn't a terminal, so logs stay clean.
+    pub(crate) fn new(len: usize, unit: &str) -> Self {
+        let visible = !DISABLED.load(Ordering::Relaxed) && std::io::stdout().is_terminal();
+        let target = if visible { ProgressDrawTarget::stderr() } else { ProgressDrawTarget::hidden() };
+        let multi = MultiProgress::with_draw_target(target);
+        let style = format!("[{{elapsed_precise}}] {{bar:40}} {{pos}}/{{len}} {} (ETA {{eta}})", unit);
+        let total = multi.add(ProgressBar::new(len as u64).with_style(ProgressStyle::with_template(&style).unwrap()));
+        Progress { multi, total }
+    }
+
+    /// A spinner line for `name` showing how long it has been running and `set_message` updates.
+    pub(crate) fn start(&self, name: &str) -> ProgressBar {
+        let style = ProgressStyle::with_template("  {spinner} {prefix} [{elapsed}] {wide_msg}").unwrap();
+        let bar = self.multi.insert_before(&self.total, ProgressBar::new_spinner().with_style(style).with_prefix(name.to_string()));
+        bar.enable_steady_tick(Duration::from_millis(200));
+        bar
+    }
+
+    /// Removes an item's line and counts it as done.
+    pub(crate) fn finish(&self, bar: ProgressBar) {
+        bar.finish_and_clear();
+        self.multi.remove(&bar);
+        self.total.inc(1);
+    }
+
+    /// Runs `f` (e.g. logging to stderr) with the bars hidden, so its output doesn't go through them.
+    pub(crate) fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.multi.suspend(f)
+    }
+
+    /// Clears the bars so the step's summary prints on a clean terminal.
+    pub(crate) fn done(&self) {
+        self.total.finish_and_clear();
+        let _ = self.multi.clear();
+    }
+}
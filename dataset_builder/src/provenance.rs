@@ -0,0 +1,94 @@
+//! Git provenance of collected files: the last commit touching each path and whether the working
+//! tree differs from HEAD, read with one history walk per checkout.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use git2::{Commit, Repository, Sort, Status, StatusOptions, TreeWalkMode, TreeWalkResult};
+
+/// The last commit of a path: its id and author date (RFC 3339, UTC).
+#[derive(Clone, Debug, PartialEq)]
+pub struct LastCommit {
+    pub commit: String,
+    pub date: String,
+}
+
+/// What `collect` stamps the entries of a checkout with, when it still has `.git`.
+#[derive(Debug, Default)]
+pub struct Provenance {
+    pub head: String,
+    last: HashMap<String, LastCommit>,
+    modified: HashSet<String>,
+}
+
+impl Provenance {
+    /// Walks the history of the checkout's HEAD once, newest first, until every file of HEAD's
+    /// tree has been attributed. A shallow clone attributes what it can't see further back to its
+    /// oldest commit. `None` without `.git` or without a commit.
+    pub fn open(repo_path: &Path) -> Option<Provenance> {
+        let repo = Repository::open(repo_path).ok()?;
+        let head = repo.head().ok()?.peel_to_commit().ok()?;
+        let mut pending = HashSet::new();
+        head.tree()
+            .ok()?
+            .walk(TreeWalkMode::PreOrder, |dir, entry| {
+                if entry.kind() == Some(git2::ObjectType::Blob) {
+                    pending.insert(format!("{}{}", dir, entry.name().unwrap_or_default()));
+                }
+                TreeWalkResult::Ok
+            })
+            .ok()?;
+        let mut last = HashMap::new();
+        let mut walk = repo.revwalk().ok()?;
+        walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME).ok()?;
+        walk.push(head.id()).ok()?;
+        for id in walk.filter_map(Result::ok) {
+            if pending.is_empty() {
+                break;
+            }
+            let Ok(commit) = repo.find_commit(id) else { continue };
+            let date = chrono::DateTime::from_timestamp(commit.author().when().seconds(), 0).unwrap_or_default().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+            for path in changed_paths(&repo, &commit) {
+                if pending.remove(&path) {
+                    last.insert(path, LastCommit { commit: id.to_string(), date: date.clone() });
+                }
+            }
+        }
+        let mut opts = StatusOptions::new();
+        opts.include_untracked(true).recurse_untracked_dirs(true).exclude_submodules(true);
+        let modified = match repo.statuses(Some(&mut opts)) {
+            Ok(statuses) => statuses.iter().filter(|s| !s.status().intersects(Status::CURRENT | Status::IGNORED)).filter_map(|s| s.path().map(String::from)).collect(),
+            Err(_) => HashSet::new(),
+        };
+        Some(Provenance { head: head.id().to_string(), last, modified })
+    }
+
+    /// The last commit of `path`, relative to the checkout with `/` separators; `None` for a
+    /// file HEAD doesn't have.
+    pub fn last_commit(&self, path: &str) -> Option<&LastCommit> {
+        self.last.get(path)
+    }
+
+    /// Whether the working tree copy of `path` differs from HEAD's, or HEAD doesn't have it.
+    pub fn modified(&self, path: &str) -> bool {
+        self.modified.contains(path)
+    }
+}
+
+/// The paths `commit` changed: against its parent, or for a merge, against every parent, as
+/// `git log` attributes them. A root commit (or the oldest of a shallow clone) changed them all.
+fn changed_paths(repo: &Repository, commit: &Commit) -> HashSet<String> {
+    let Ok(tree) = commit.tree() else { return HashSet::new() };
+    let diff = |parent: Option<Commit>| -> HashSet<String> {
+        let parent_tree = parent.and_then(|p| p.tree().ok());
+        let Ok(diff) = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None) else { return HashSet::new() };
+        diff.deltas().filter_map(|d| d.new_file().path().map(|p| p.to_string_lossy().replace('\\', "/"))).collect()
+    };
+    let parents: Vec<Commit> = commit.parents().collect();
+    if parents.is_empty() {
+        return diff(None);
+    }
+    let mut sets = parents.into_iter().map(|p| diff(Some(p)));
+    let first = sets.next().unwrap_or_default();
+    sets.fold(first, |acc, set| acc.intersection(&set).cloned().collect())
+}
@@ -0,0 +1,115 @@
+//! Seeded sampling for `filter --sample`: the same seed and input always yield the same repositories.
+
+use anyhow::Context;
+
+/// SplitMix64: tiny and fully specified, so a seed picks the same sample on every platform and
+/// dependency version.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+}
+
+/// Reservoir sampling (Algorithm R): `k` items chosen uniformly from `items`, in one pass.
+pub fn reservoir<T>(items: impl IntoIterator<Item = T>, k: usize, rng: &mut Rng) -> Vec<T> {
+    let mut sample = Vec::with_capacity(k);
+    for (i, item) in items.into_iter().enumerate() {
+        if i < k {
+            sample.push(item);
+        } else {
+            let j = rng.below(i + 1);
+            if j < k {
+                sample[j] = item;
+            }
+        }
+    }
+    sample
+}
+
+/// How many to sample: `--sample N` or `--sample-frac F`.
+#[derive(Clone, Copy, Debug)]
+pub enum SampleSize {
+    Count(usize),
+    Fraction(f64),
+}
+
+impl SampleSize {
+    pub fn of(self, total: usize) -> usize {
+        match self {
+            SampleSize::Count(n) => n.min(total),
+            SampleSize::Fraction(f) => ((total as f64 * f).round() as usize).min(total),
+        }
+    }
+}
+
+/// `--stratify-by stars:0-100,100-1000,1000+`: a numeric column and its buckets. Ranges include
+/// their lower bound and exclude their upper one; `1000+` has none.
+#[derive(Clone, Debug)]
+pub struct Strata {
+    pub column: String,
+    pub buckets: Vec<(u64, Option<u64>)>,
+}
+
+impl Strata {
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let bad = || format!("bad --stratify-by `{}` (expected e.g. stars:0-100,100-1000,1000+)", spec);
+        let (column, ranges) = spec.split_once(':').with_context(bad)?;
+        let buckets = ranges
+            .split(',')
+            .map(|range| {
+                let range = range.trim();
+                match range.strip_suffix('+') {
+                    Some(lo) => Some((lo.parse().ok()?, None)),
+                    None => {
+                        let (lo, hi) = range.split_once('-')?;
+                        let (lo, hi): (u64, u64) = (lo.parse().ok()?, hi.parse().ok()?);
+                        (lo < hi).then_some((lo, Some(hi)))
+                    }
+                }
+            })
+            .collect::<Option<Vec<_>>>()
+            .with_context(bad)?;
+        if column.trim().is_empty() || buckets.is_empty() {
+            anyhow::bail!(bad());
+        }
+        Ok(Strata { column: column.trim().to_ascii_lowercase(), buckets })
+    }
+
+    /// The first bucket holding `value`.
+    pub fn bucket(&self, value: u64) -> Option<usize> {
+        self.buckets.iter().position(|&(lo, hi)| value >= lo && hi.is_none_or(|hi| value < hi))
+    }
+}
+
+/// Samples `size` names across `strata` (one list per stratum, or a single one), each stratum
+/// contributing in proportion to its size, and returns them sorted.
+pub fn sample_names(strata: Vec<Vec<String>>, size: SampleSize, seed: u64) -> Vec<String> {
+    let total: usize = strata.iter().map(Vec::len).sum();
+    let k = size.of(total);
+    // Largest remainder, so the quotas add up to `k`; ties go to the earlier stratum.
+    let mut quotas: Vec<usize> = strata.iter().map(|s| k * s.len() / total.max(1)).collect();
+    let mut by_remainder: Vec<usize> = (0..strata.len()).collect();
+    by_remainder.sort_by_key(|&i| std::cmp::Reverse((k * strata[i].len()) % total.max(1)));
+    for &i in by_remainder.iter().take(k - quotas.iter().sum::<usize>()) {
+        quotas[i] += 1;
+    }
+    let mut rng = Rng::new(seed);
+    let mut names: Vec<String> = strata.into_iter().zip(quotas).flat_map(|(s, quota)| reservoir(s, quota, &mut rng)).collect();
+    names.sort();
+    names
+}
@@ -0,0 +1,204 @@
+//! `--sandbox`: run the tools that build a repository inside a container or namespace jail.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use anyhow::bail;
+use clap::{Args, ValueEnum};
+
+use crate::tool::{run_with_deadline, ToolEnv};
+
+/// Image used by `--sandbox docker` unless `--sandbox-image` says otherwise (see `Dockerfile.sandbox`).
+pub const DEFAULT_SANDBOX_IMAGE: &str = "dataset-builder-sandbox:latest";
+
+/// Where the sandbox's cargo home lives; the host's registry cache is mounted read-only inside it.
+const SANDBOX_CARGO_HOME: &str = "/tmp/cargo-home";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum SandboxKind {
+    /// Run tools directly on the host
+    #[default]
+    None,
+    /// An ephemeral `docker run --network none` container per tool invocation
+    Docker,
+    /// bubblewrap: the host's programs and toolchains read-only, the checkout writable, no network
+    Bwrap,
+}
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct SandboxOptions {
+    /// Run tools that build the repository (and so execute its build scripts and proc macros) in a sandbox
+    #[arg(long, value_enum, default_value_t = SandboxKind::None)]
+    pub sandbox: SandboxKind,
+    /// Image for `--sandbox docker`; it needs the toolchain and every enabled tool
+    #[arg(long, value_name = "IMAGE")]
+    pub sandbox_image: Option<String>,
+    /// Memory limit per container, in docker's format (e.g. `4g`)
+    #[arg(long, value_name = "SIZE")]
+    pub sandbox_memory: Option<String>,
+    /// CPU limit per container (e.g. `2`)
+    #[arg(long, value_name = "CPUS")]
+    pub sandbox_cpus: Option<String>,
+}
+
+/// Tools run straight on the host.
+pub(crate) const HOST: SandboxOptions = SandboxOptions { sandbox: SandboxKind::None, sandbox_image: None, sandbox_memory: None, sandbox_cpus: None };
+
+impl SandboxOptions {
+    /// Fails unless the requested runtime can start sandboxes, so tools never fall back to the host.
+    pub fn check(&self) -> anyhow::Result<()> {
+        let (runtime, probe): (&str, &[&str]) = match self.sandbox {
+            SandboxKind::None => return Ok(()),
+            SandboxKind::Docker => ("docker", &["info", "--format", "{{.ServerVersion}}"]),
+            SandboxKind::Bwrap => {
+                if self.sandbox_memory.is_some() || self.sandbox_cpus.is_some() {
+                    bail!("--sandbox-memory and --sandbox-cpus need --sandbox docker");
+                }
+                ("bwrap", &["--unshare-all", "--die-with-parent", "--ro-bind", "/", "/", "true"])
+            }
+        };
+        let mut cmd = Command::new(runtime);
+        cmd.args(probe);
+        let problem = match run_with_deadline(cmd, Some(Instant::now() + Duration::from_secs(30))) {
+            Ok(ran) if ran.out.status.success() => return Ok(()),
+            Ok(ran) => String::from_utf8_lossy(&ran.out.stderr).trim().to_string(),
+            Err(e) => format!("{:#}", e),
+        };
+        bail!("--sandbox {} requested but {} cannot start sandboxes ({}); refusing to run analysis tools on the host", runtime, runtime, problem)
+    }
+
+    /// Short description for `manifest.json`, `None` without a sandbox.
+    pub fn describe(&self) -> Option<String> {
+        match self.sandbox {
+            SandboxKind::None => None,
+            SandboxKind::Docker => Some(format!("docker:{}", self.image())),
+            SandboxKind::Bwrap => Some("bwrap".into()),
+        }
+    }
+
+    fn image(&self) -> &str {
+        self.sandbox_image.as_deref().unwrap_or(DEFAULT_SANDBOX_IMAGE)
+    }
+
+    /// A command running `program` in `dir`, which can only write to the checkout at `root` and
+    /// to `env.target_dir` (exported as `CARGO_TARGET_DIR`), with `env.vars` set (on docker or
+    /// bwrap too, so a `PATH` there finds them), and the name of the container to kill if it
+    /// overruns its deadline.
+    pub(crate) fn command(&self, root: &Path, dir: &Path, program: &str, env: ToolEnv) -> (Command, Option<String>) {
+        let root = absolute(root);
+        let dir = absolute(dir);
+        let target_dir = env.target_dir;
+        match self.sandbox {
+            SandboxKind::None => {
+                let mut cmd = Command::new(program);
+                cmd.current_dir(dir).envs(env.vars.iter().map(|(var, value)| (var, value)));
+                if let Some(target) = target_dir {
+                    cmd.env("CARGO_TARGET_DIR", target);
+                }
+                (cmd, None)
+            }
+            SandboxKind::Docker => {
+                let name = format!("dataset-builder-{}", uuid::Uuid::new_v4());
+                let mut cmd = Command::new("docker");
+                cmd.envs(env.vars.iter().map(|(var, value)| (var, value)));
+                cmd.args(["run", "--rm", "--init", "--network", "none", "--name", &name]);
+                cmd.args(["--tmpfs", "/tmp:exec", "-e", "HOME=/tmp", "-e", "CARGO_NET_OFFLINE=true"]);
+                cmd.arg("-e").arg(format!("CARGO_HOME={}", SANDBOX_CARGO_HOME));
+                #[cfg(unix)]
+                cmd.arg("--user").arg(unsafe { format!("{}:{}", libc::getuid(), libc::getgid()) });
+                for (var, value) in env.vars {
+                    cmd.arg("-e").arg(format!("{}={}", var, value));
+                }
+                if let Some(memory) = &self.sandbox_memory {
+                    cmd.args(["--memory", memory]);
+                }
+                if let Some(cpus) = &self.sandbox_cpus {
+                    cmd.args(["--cpus", cpus]);
+                }
+                for (host, inside) in cargo_caches() {
+                    cmd.arg("-v").arg(format!("{}:{}:ro", host.display(), inside));
+                }
+                cmd.arg("-v").arg(format!("{}:{}", root.display(), root.display()));
+                if let Some(target) = target_dir {
+                    cmd.arg("-v").arg(format!("{}:{}", target.display(), target.display()));
+                    cmd.arg("-e").arg(format!("CARGO_TARGET_DIR={}", target.display()));
+                }
+                cmd.arg("-w").arg(&dir).arg(self.image()).arg(program);
+                (cmd, Some(name))
+            }
+            SandboxKind::Bwrap => {
+                let mut cmd = Command::new("bwrap");
+                cmd.envs(env.vars.iter().map(|(var, value)| (var, value)));
+                cmd.args(["--unshare-all", "--die-with-parent", "--new-session", "--clearenv"]);
+                for dir in bwrap_read_only() {
+                    cmd.arg("--ro-bind-try").arg(&dir).arg(&dir);
+                }
+                cmd.args(["--dev", "/dev", "--proc", "/proc", "--tmpfs", "/tmp"]);
+                // The variables docker is given, plus what finds the host's toolchains under the read-only root.
+                cmd.args(["--setenv", "HOME", "/tmp", "--setenv", "CARGO_HOME", SANDBOX_CARGO_HOME, "--setenv", "CARGO_NET_OFFLINE", "true"]);
+                for (var, value) in toolchain_env() {
+                    cmd.arg("--setenv").arg(var).arg(value);
+                }
+                for (var, value) in env.vars {
+                    cmd.arg("--setenv").arg(var).arg(value);
+                }
+                for (host, inside) in cargo_caches() {
+                    cmd.arg("--ro-bind").arg(host).arg(inside);
+                }
+                cmd.arg("--bind").arg(&root).arg(&root);
+                if let Some(target) = target_dir {
+                    cmd.arg("--bind").arg(target).arg(target).arg("--setenv").arg("CARGO_TARGET_DIR").arg(target);
+                }
+                cmd.arg("--chdir").arg(&dir).arg(program);
+                (cmd, None)
+            }
+        }
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// `PATH`, where rustup keeps the toolchains (found from `HOME`, which the sandbox changes) and
+/// the host's choice of toolchain, for bwrap's otherwise empty environment.
+fn toolchain_env() -> Vec<(&'static str, OsString)> {
+    let rustup_home = std::env::var_os("RUSTUP_HOME").or_else(|| std::env::var_os("HOME").map(|h| Path::new(&h).join(".rustup").into_os_string()));
+    let vars = [("PATH", std::env::var_os("PATH")), ("RUSTUP_HOME", rustup_home), ("RUSTUP_TOOLCHAIN", std::env::var_os("RUSTUP_TOOLCHAIN"))];
+    vars.into_iter().filter_map(|(var, value)| Some((var, value?))).collect()
+}
+
+/// What bwrap lets tools read of the host: the system's programs, libraries and certificates,
+/// rustup's toolchains, cargo's `bin` and the other directories on `PATH`. Not the rest of `/`,
+/// and not `HOME`, whose credentials (`~/.aws`, `~/.ssh`, token files, cargo's own) a build
+/// script could otherwise copy into the checkout.
+fn bwrap_read_only() -> Vec<PathBuf> {
+    // `/etc/alternatives` holds Debian's `cc` and friends, `ld.so.cache` the dynamic linker's paths.
+    let system = ["/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/libx32", "/etc/ssl", "/etc/alternatives", "/etc/ld.so.cache"];
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let cargo_bin = std::env::var_os("CARGO_HOME").map(PathBuf::from).or_else(|| home.as_ref().map(|h| h.join(".cargo"))).map(|c| c.join("bin"));
+    let rustup = toolchain_env().into_iter().find(|(var, _)| *var == "RUSTUP_HOME").map(|(_, dir)| PathBuf::from(dir));
+    let path = std::env::var_os("PATH").map(|p| std::env::split_paths(&p).collect::<Vec<_>>()).unwrap_or_default();
+    let mut dirs: Vec<PathBuf> = system.into_iter().map(PathBuf::from).chain(rustup).chain(cargo_bin).chain(path).filter(|d| d.is_absolute()).collect();
+    // A `PATH` entry holding the home directory (`/`, `/home`) would bind all of it again.
+    dirs.retain(|d| !home.as_ref().is_some_and(|h| h.starts_with(d)));
+    // Sorted, a directory is bound before those inside it.
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+/// The host's downloaded crates and git dependencies, and where they appear in the sandbox.
+fn cargo_caches() -> Vec<(PathBuf, String)> {
+    let home = std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| Path::new(&h).join(".cargo")));
+    let Some(home) = home else { return Vec::new() };
+    ["registry", "git"]
+        .into_iter()
+        .map(|dir| (home.join(dir), format!("{}/{}", SANDBOX_CARGO_HOME, dir)))
+        .filter(|(host, _)| host.is_dir())
+        .collect()
+}
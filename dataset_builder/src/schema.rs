@@ -0,0 +1,164 @@
+//! The versions of the `outputs.jsonl` and `code.jsonl` record layouts. Every reader (`join`,
+//! `merge`, `validate`, `stats`, `convert`, `export-sqlite`, `label`) takes lines of the current
+//! version and of the one before, upgrading the latter as it goes; `convert --upgrade` rewrites
+//! a file in the current layout.
+
+use std::collections::BTreeMap;
+
+use serde::de::{DeserializeOwned, Error as _};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::analyze::OutputEntry;
+use crate::analyzers::{Report, ToolOutput};
+use crate::collect::CodeEntry;
+use crate::parse::Finding;
+use crate::tool::ToolResult;
+use crate::workspace::DepGraph;
+
+/// Version of the `outputs.jsonl` record layout. 1 stored each tool as a single string
+/// (stdout, or stderr when stdout was empty); 2 stores a `ToolResult` per tool; 3 replaces the
+/// `cargo tree` text with `dep_graph` (the text is only kept with `--include-tree-text`); 4 moves
+/// every tool into the `tools` map, with its parsed output under `report`.
+pub const OUTPUT_SCHEMA_VERSION: u32 = 4;
+
+/// Version of the `code.jsonl` record layout. 1 has no `schema_version`; 2 adds it, and the
+/// `package` and `target` of lib and bin files.
+pub const CODE_SCHEMA_VERSION: u32 = 2;
+
+/// An entry type whose lines carry a `schema_version`.
+pub trait Versioned: DeserializeOwned {
+    /// The version this build writes
+    const CURRENT: u32;
+    /// The version of a line without `schema_version`
+    const UNVERSIONED: u32 = 1;
+
+    /// Reads a line of version `CURRENT - 1` into the current layout.
+    fn upgrade(line: &str) -> serde_json::Result<Self>;
+}
+
+impl Versioned for OutputEntry {
+    const CURRENT: u32 = OUTPUT_SCHEMA_VERSION;
+
+    fn upgrade(line: &str) -> serde_json::Result<Self> {
+        Ok(serde_json::from_str::<OutputEntryV3>(line)?.into())
+    }
+}
+
+impl Versioned for CodeEntry {
+    const CURRENT: u32 = CODE_SCHEMA_VERSION;
+
+    // Version 1 only lacks the version: `package` and `target` are optional.
+    fn upgrade(line: &str) -> serde_json::Result<Self> {
+        let mut entry: serde_json::Map<String, Value> = serde_json::from_str(line)?;
+        entry.insert("schema_version".into(), CODE_SCHEMA_VERSION.into());
+        serde_json::from_value(Value::Object(entry))
+    }
+}
+
+#[derive(Deserialize)]
+struct Version {
+    schema_version: Option<u32>,
+}
+
+/// Reads a line of the current or the previous version of `T`, upgrading the latter.
+pub fn from_line<T: Versioned>(line: &str) -> serde_json::Result<T> {
+    match serde_json::from_str::<Version>(line)?.schema_version.unwrap_or(T::UNVERSIONED) {
+        v if v == T::CURRENT => serde_json::from_str(line),
+        v if v + 1 == T::CURRENT => T::upgrade(line),
+        v => Err(serde_json::Error::custom(format_args!("schema_version {} isn't supported: this build reads {} and {}", v, T::CURRENT - 1, T::CURRENT))),
+    }
+}
+
+/// An `outputs.jsonl` line of version 3: each tool is a field of its own, with the parsed
+/// output of clippy, audit and geiger beside it.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct OutputEntryV3 {
+    pub schema_version: u32,
+    pub run_id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace_root: Option<String>,
+    pub clippy: ToolResult,
+    /// A `ClippyReport`; dropped on upgrade if it no longer reads as one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clippy_report: Option<Value>,
+    pub fmt: ToolResult,
+    pub audit: ToolResult,
+    /// An `AuditReport`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_report: Option<Value>,
+    pub auditable: ToolResult,
+    pub deny: ToolResult,
+    pub semgrep: ToolResult,
+    pub geiger: ToolResult,
+    /// A `GeigerReport`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub geiger_report: Option<Value>,
+    pub codeql: ToolResult,
+    pub findings: Vec<Finding>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dep_graph: Option<DepGraph>,
+    /// `cargo tree` text, only with `--include-tree-text`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tree: Option<ToolResult>,
+    pub ast: ToolResult,
+    pub toolchain: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ast_toolchain: Option<String>,
+    /// Milliseconds per tool and phase, every one of them present
+    pub time_ms: BTreeMap<String, u128>,
+    pub errors: Vec<String>,
+}
+
+impl From<OutputEntryV3> for OutputEntry {
+    fn from(v3: OutputEntryV3) -> Self {
+        fn parsed<R: DeserializeOwned>(report: Option<Value>) -> Option<R> {
+            report.and_then(|r| serde_json::from_value(r).ok())
+        }
+        let with_report = |result: ToolResult, report: Option<Report>| ToolOutput { report, ..result.into() };
+        let mut tools = BTreeMap::from([
+            ("clippy".to_string(), with_report(v3.clippy, parsed(v3.clippy_report).map(Report::Clippy))),
+            ("fmt".to_string(), v3.fmt.into()),
+            ("audit".to_string(), with_report(v3.audit, parsed(v3.audit_report).map(Report::Audit))),
+            ("auditable".to_string(), v3.auditable.into()),
+            ("deny".to_string(), v3.deny.into()),
+            ("semgrep".to_string(), v3.semgrep.into()),
+            ("geiger".to_string(), with_report(v3.geiger, parsed(v3.geiger_report).map(Report::Geiger))),
+            ("codeql".to_string(), v3.codeql.into()),
+            ("ast".to_string(), v3.ast.into()),
+        ]);
+        if let Some(tree) = v3.tree {
+            tools.insert("tree".to_string(), tree.into());
+        }
+        OutputEntry {
+            schema_version: OUTPUT_SCHEMA_VERSION,
+            run_id: v3.run_id,
+            dir: String::new(),
+            name: v3.name,
+            name_unresolved: false,
+            repo: None,
+            commit: None,
+            project_manifest: None,
+            other_manifests: Vec::new(),
+            not_a_cargo_project: false,
+            package: v3.package,
+            manifest_path: v3.manifest_path,
+            workspace_root: v3.workspace_root,
+            tools,
+            findings: v3.findings,
+            dep_graph: v3.dep_graph,
+            toolchain: v3.toolchain,
+            ast_toolchain: v3.ast_toolchain,
+            fetch: None,
+            fetch_failed: false,
+            time_ms: v3.time_ms,
+            total_duration_ms: 0,
+            errors: v3.errors,
+        }
+    }
+}
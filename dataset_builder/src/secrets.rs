@@ -0,0 +1,146 @@
+//! Secret scanning of collected files (`collect --secrets`): committed cloud keys, tokens and
+//! private keys are redacted, tagged or left out, and listed in `secrets_report.jsonl`.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Written next to the code file: one line per secret found, without its value.
+pub const SECRETS_REPORT_FILE: &str = "secrets_report.jsonl";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SecretsMode {
+    /// Don't scan
+    #[default]
+    Off,
+    /// Replace each secret with `<REDACTED:rule>`
+    Redact,
+    /// Keep the content, listing the rules that matched in `secrets_found`
+    Tag,
+    /// Leave out the files with a secret, as `secrets` in `skipped_files.jsonl`
+    SkipFile,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum SecretRule {
+    /// `AKIA...` and `ASIA...` access key ids
+    AwsAccessKeyId,
+    /// 40 base64 characters assigned to something named like `aws_secret_access_key`
+    AwsSecretAccessKey,
+    /// `ghp_`, `gho_`, `ghu_`, `ghs_`, `ghr_` and `github_pat_` tokens
+    GithubToken,
+    /// `xoxb-`, `xoxp-`, ... tokens
+    SlackToken,
+    /// `-----BEGIN ... PRIVATE KEY-----` blocks
+    PrivateKey,
+    /// Quoted strings of 32 or more base64-like characters with over 4.5 bits of entropy per
+    /// character; the most prone to false positives
+    HighEntropy,
+}
+
+impl SecretRule {
+    pub fn name(self) -> &'static str {
+        match self {
+            SecretRule::AwsAccessKeyId => "aws-access-key-id",
+            SecretRule::AwsSecretAccessKey => "aws-secret-access-key",
+            SecretRule::GithubToken => "github-token",
+            SecretRule::SlackToken => "slack-token",
+            SecretRule::PrivateKey => "private-key",
+            SecretRule::HighEntropy => "high-entropy",
+        }
+    }
+}
+
+/// One line of `secrets_report.jsonl`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SecretFinding {
+    /// As in `CodeEntry::name`
+    pub name: String,
+    pub dir: String,
+    pub path: String,
+    pub rule: SecretRule,
+    /// 1-based line of the secret's start in the file
+    pub line: usize,
+}
+
+/// Where `collect` writes the secrets report of `code_file`.
+pub fn secrets_report_beside(code_file: &str) -> PathBuf {
+    Path::new(code_file).parent().unwrap_or(Path::new("")).join(SECRETS_REPORT_FILE)
+}
+
+/// A secret in a text: the bytes to redact, and its rule and line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SecretMatch {
+    pub rule: SecretRule,
+    pub range: Range<usize>,
+    pub line: usize,
+}
+
+/// The patterns of every rule; a `secret` group, when there is one, is the part to redact.
+fn patterns() -> &'static [(SecretRule, Regex)] {
+    static PATTERNS: OnceLock<Vec<(SecretRule, Regex)>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            // To the END line or, in a key cut short, to the end of the base64 lines that follow
+            // (split by newlines or by `\n` escapes).
+            (SecretRule::PrivateKey, r"-----BEGIN [A-Z0-9 ]*PRIVATE KEY( BLOCK)?-----(?:(?s:.*?-----END [A-Z0-9 ]*PRIVATE KEY( BLOCK)?-----)|(?:(?:\s|\\n)+[A-Za-z0-9+/=]+)*)"),
+            (SecretRule::AwsAccessKeyId, r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b"),
+            (SecretRule::AwsSecretAccessKey, r#"(?i)aws_?secret_?(?:access_?)?key\w*["']?\s*[:=]\s*["']?(?P<secret>[A-Za-z0-9/+=]{40})\b"#),
+            (SecretRule::GithubToken, r"\b(?:gh[pousr]_[A-Za-z0-9]{36}|github_pat_[A-Za-z0-9_]{82})\b"),
+            (SecretRule::SlackToken, r"\bxox[abposr]-[A-Za-z0-9-]{10,}"),
+            (SecretRule::HighEntropy, r#"["'](?P<secret>[A-Za-z0-9+/=_-]{32,})["']"#),
+        ]
+        .into_iter()
+        .map(|(rule, pattern)| (rule, Regex::new(pattern).expect("a valid secret pattern")))
+        .collect()
+    })
+}
+
+/// The secrets of `content` by the rules not `disabled`, in order and without overlaps: a
+/// secret matched by an earlier rule isn't matched again.
+pub fn scan_secrets(content: &str, disabled: &[SecretRule]) -> Vec<SecretMatch> {
+    let mut found: Vec<SecretMatch> = Vec::new();
+    for (rule, regex) in patterns().iter().filter(|(rule, _)| !disabled.contains(rule)) {
+        for caps in regex.captures_iter(content) {
+            let m = caps.name("secret").unwrap_or_else(|| caps.get(0).unwrap());
+            if *rule == SecretRule::HighEntropy && entropy(m.as_str()) <= 4.5 {
+                continue;
+            }
+            if found.iter().any(|f| f.range.start < m.end() && m.start() < f.range.end) {
+                continue;
+            }
+            let line = content[..m.start()].matches('\n').count() + 1;
+            found.push(SecretMatch { rule: *rule, range: m.range(), line });
+        }
+    }
+    found.sort_by_key(|f| f.range.start);
+    found
+}
+
+/// `content` with every match replaced by `<REDACTED:rule>`.
+pub fn redact_secrets(content: &str, found: &[SecretMatch]) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut at = 0;
+    for f in found {
+        out.push_str(&content[at..f.range.start]);
+        out.push_str(&format!("<REDACTED:{}>", f.rule.name()));
+        at = f.range.end;
+    }
+    out.push_str(&content[at..]);
+    out
+}
+
+/// Shannon entropy of `s`, in bits per character.
+fn entropy(s: &str) -> f64 {
+    let mut counts = [0usize; 256];
+    for b in s.bytes() {
+        counts[b as usize] += 1;
+    }
+    let n = s.len() as f64;
+    counts.iter().filter(|c| **c > 0).map(|&c| c as f64 / n).map(|p| -p * p.log2()).sum()
+}
@@ -0,0 +1,287 @@
+//! Sharded output for `collect` and `outputs` (`--shard-size`, `--shard-entries`): `code-00000.jsonl`,
+//! `code-00001.jsonl`, ... and a `code.manifest.json` listing them, which every command reading
+//! JSONL takes in place of the file.
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{BufRead, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::jsonl::{open_jsonl, Codec, JsonlWriter};
+
+/// What a shard manifest's name ends in, after the stem of the file it was sharded from.
+pub const MANIFEST_SUFFIX: &str = ".manifest.json";
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct ShardOptions {
+    /// Split the file into shards of at most this many bytes of lines, before compression: `1GB`,
+    /// `500MiB`, `100000`
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, conflicts_with = "output_format")]
+    pub shard_size: Option<u64>,
+    /// Split the file into shards of at most N entries
+    #[arg(long, value_name = "N", conflicts_with = "output_format")]
+    pub shard_entries: Option<usize>,
+}
+
+impl ShardOptions {
+    pub fn enabled(&self) -> bool {
+        self.shard_size.is_some() || self.shard_entries.is_some()
+    }
+}
+
+/// `<stem>.manifest.json`, in the directory of the shards.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ShardManifest {
+    pub entries: usize,
+    /// Of the shard files as written, compressed or not
+    pub bytes: u64,
+    pub shards: Vec<Shard>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Shard {
+    /// Relative to the manifest's directory
+    pub path: String,
+    pub entries: usize,
+    pub bytes: u64,
+    /// The blake3 of the file's bytes, in hex
+    pub blake3: String,
+}
+
+impl Shard {
+    /// The record of a shard file as it is on disk, with `path` as the manifest lists it.
+    pub fn of(file: &Path, path: &str) -> anyhow::Result<Shard> {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update_reader(File::open(file).with_context(|| format!("failed to read {}", file.display()))?)?;
+        let entries = open_jsonl(&file.display().to_string())?.lines().filter(|l| !matches!(l, Ok(l) if l.trim().is_empty())).count();
+        Ok(Shard { path: path.to_string(), entries, bytes: fs::metadata(file)?.len(), blake3: hasher.finalize().to_hex().to_string() })
+    }
+}
+
+impl ShardManifest {
+    pub fn load(path: &str) -> anyhow::Result<ShardManifest> {
+        let text = fs::read_to_string(path).with_context(|| format!("failed to read {}", path))?;
+        serde_json::from_str(&text).with_context(|| format!("{} isn't a shard manifest", path))
+    }
+
+    /// The shard files, in order, resolved against the directory of `manifest`.
+    pub fn files(&self, manifest: &str) -> Vec<PathBuf> {
+        let dir = Path::new(manifest).parent().unwrap_or(Path::new(""));
+        self.shards.iter().map(|s| dir.join(&s.path)).collect()
+    }
+}
+
+pub fn is_manifest(path: &str) -> bool {
+    path.ends_with(MANIFEST_SUFFIX)
+}
+
+/// The name of `file` without its `.jsonl` and compression extensions, and those extensions:
+/// `("code", ".jsonl.zst")` for `code.jsonl.zst`.
+fn split_name(file: &str) -> (PathBuf, String) {
+    let mut path = PathBuf::from(file);
+    let mut ext = String::new();
+    for _ in 0..2 {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(e @ ("gz" | "zst" | "jsonl")) if !ext.starts_with(".jsonl") => {
+                ext = format!(".{}{}", e, ext);
+                path.set_extension("");
+            }
+            _ => break,
+        }
+    }
+    (path, ext)
+}
+
+/// Where the manifest of `file` is written: `code.manifest.json` for `code.jsonl`.
+pub fn manifest_path(file: &str) -> PathBuf {
+    let (stem, _) = split_name(file);
+    PathBuf::from(format!("{}{}", stem.display(), MANIFEST_SUFFIX))
+}
+
+/// The `n`th shard of `file`: `code-00002.jsonl.zst` for `code.jsonl.zst`.
+pub fn shard_path(file: &str, n: usize) -> PathBuf {
+    let (stem, ext) = split_name(file);
+    PathBuf::from(format!("{}-{:05}{}", stem.display(), n, ext))
+}
+
+/// `1GB`, `500MiB`, `64k` or a number of bytes; `KB`, `MB`, `GB` and `TB` are powers of 1000,
+/// `KiB` to `TiB` powers of 1024, and the units are case-insensitive.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (number, unit) = (&s[..split], s[split..].trim().to_ascii_lowercase());
+    let number: f64 = number.parse().map_err(|_| format!("`{}` isn't a size such as 1GB or 500MiB", s))?;
+    let scale: u64 = match unit.as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "t" | "tb" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        _ => return Err(format!("`{}` isn't a unit: B, KB, MB, GB, TB, KiB, MiB, GiB or TiB", unit)),
+    };
+    let bytes = (number * scale as f64) as u64;
+    if bytes == 0 {
+        return Err("the size must be at least one byte".to_string());
+    }
+    Ok(bytes)
+}
+
+/// Splits the JSONL `input` into shards beside it, compressed with `codec`, starting a new shard
+/// before an entry that would take one over `--shard-size` or `--shard-entries`. An entry larger
+/// than `--shard-size` gets a shard of its own. Shards listed by an earlier manifest are removed
+/// first, and the manifest is written last.
+pub fn shard_jsonl(input: &str, codec: Codec, opts: &ShardOptions) -> anyhow::Result<ShardManifest> {
+    let manifest = manifest_path(input).display().to_string();
+    if let Ok(old) = ShardManifest::load(&manifest) {
+        for file in old.files(&manifest) {
+            let _ = fs::remove_file(file);
+        }
+    }
+    let mut shards = Vec::new();
+    let mut current: Option<(JsonlWriter, u64)> = None;
+    let finish = |w: JsonlWriter, shards: &mut Vec<Shard>| -> anyhow::Result<()> {
+        w.finish()?;
+        let file = shard_path(input, shards.len());
+        let name = file.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        shards.push(Shard::of(&file, &name)?);
+        Ok(())
+    };
+    for line in open_jsonl(input)?.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let size = line.len() as u64 + 1;
+        let full = current.as_ref().is_some_and(|(w, bytes)| opts.shard_entries.is_some_and(|n| w.written >= n.max(1)) || opts.shard_size.is_some_and(|n| bytes + size > n));
+        if full {
+            let (w, _) = current.take().unwrap();
+            finish(w, &mut shards)?;
+        }
+        let (w, bytes) = match &mut current {
+            Some(c) => c,
+            None => {
+                let file = shard_path(input, shards.len());
+                let w = JsonlWriter::compressed(File::create(&file).with_context(|| format!("failed to write {}", file.display()))?, codec, None)?;
+                current.insert((w, 0))
+            }
+        };
+        w.write_line(&line)?;
+        *bytes += size;
+    }
+    if let Some((w, _)) = current {
+        finish(w, &mut shards)?;
+    }
+    let m = ShardManifest { entries: shards.iter().map(|s| s.entries).sum(), bytes: shards.iter().map(|s| s.bytes).sum(), shards };
+    fs::write(&manifest, serde_json::to_string_pretty(&m)? + "\n").with_context(|| format!("failed to write {}", manifest))?;
+    Ok(m)
+}
+
+/// Shards the finished JSONL `file`, removes it and reports the shards.
+pub(crate) fn finish_as_shards(file: &str, codec: Codec, opts: &ShardOptions) -> anyhow::Result<()> {
+    let m = shard_jsonl(file, codec, opts)?;
+    fs::remove_file(file)?;
+    tracing::info!("Shards: wrote {} entries in {} shards, listed in {}", m.entries, m.shards.len(), manifest_path(file).display());
+    Ok(())
+}
+
+/// The lines of every shard of a manifest, one after the other; a shard is opened once the one
+/// before it is read.
+pub(crate) struct ShardReader {
+    files: VecDeque<PathBuf>,
+    current: Option<Box<dyn BufRead + Send>>,
+}
+
+impl ShardReader {
+    pub(crate) fn open(manifest: &str) -> anyhow::Result<ShardReader> {
+        let files = ShardManifest::load(manifest)?.files(manifest);
+        if let Some(missing) = files.iter().find(|f| !f.exists()) {
+            anyhow::bail!("{} lists {}, which doesn't exist", manifest, missing.display());
+        }
+        Ok(ShardReader { files: files.into(), current: None })
+    }
+
+    /// The shard being read, opening the next one when it is at its end.
+    fn current(&mut self) -> std::io::Result<Option<&mut Box<dyn BufRead + Send>>> {
+        loop {
+            if let Some(r) = &mut self.current {
+                if !r.fill_buf()?.is_empty() {
+                    break;
+                }
+            }
+            let Some(file) = self.files.pop_front() else {
+                self.current = None;
+                return Ok(None);
+            };
+            self.current = Some(open_jsonl(&file.display().to_string()).map_err(std::io::Error::other)?);
+        }
+        Ok(self.current.as_mut())
+    }
+}
+
+impl Read for ShardReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.current()? {
+            Some(r) => r.read(buf),
+            None => Ok(0),
+        }
+    }
+}
+
+impl BufRead for ShardReader {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        match self.current()? {
+            Some(r) => r.fill_buf(),
+            None => Ok(&[]),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Some(r) = &mut self.current {
+            r.consume(amt);
+        }
+    }
+}
+
+/// `validate` on a manifest: every shard is validated by `check`, which says whether it is good
+/// (or was repaired), and checked against the entries, size and checksum the manifest has for
+/// it. With `repair`, the manifest is updated for the shards `check` rewrote; a shard that no
+/// longer matches its checksum, changed since it was written or corrupted in a transfer, can't
+/// be repaired. Returns the bad shards.
+pub(crate) fn validate_manifest(manifest: &str, repair: bool, mut check: impl FnMut(&str) -> anyhow::Result<bool>) -> anyhow::Result<Vec<String>> {
+    let mut m = ShardManifest::load(manifest)?;
+    let listed = m.clone();
+    let files = m.files(manifest);
+    let mut failed = Vec::new();
+    for (shard, file) in m.shards.iter_mut().zip(&files) {
+        let Ok(found) = Shard::of(file, &shard.path) else {
+            tracing::warn!("✗ {}: missing or unreadable", shard.path);
+            failed.push(shard.path.clone());
+            continue;
+        };
+        if found.blake3 != shard.blake3 || found.bytes != shard.bytes {
+            tracing::warn!("✗ {}: {} bytes with blake3 {}, but the manifest has {} bytes with {}", shard.path, found.bytes, found.blake3, shard.bytes, shard.blake3);
+            failed.push(shard.path.clone());
+            continue;
+        }
+        if !check(&file.display().to_string())? {
+            failed.push(shard.path.clone());
+            continue;
+        }
+        *shard = Shard::of(file, &shard.path)?;
+    }
+    (m.entries, m.bytes) = (m.shards.iter().map(|s| s.entries).sum(), m.shards.iter().map(|s| s.bytes).sum());
+    if repair && m != listed {
+        fs::write(manifest, serde_json::to_string_pretty(&m)? + "\n")?;
+        tracing::info!("✓ Updated {}", manifest);
+    }
+    tracing::info!("{}: {} shards, {} entries, {} bad", manifest, m.shards.len(), m.entries, failed.len());
+    Ok(failed)
+}
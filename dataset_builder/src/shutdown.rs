@@ -0,0 +1,146 @@
+//! Ctrl-C handling: the first one stops dispatching repositories and lets the ones in flight
+//! finish (killing their tools after a grace period), the second exits at once.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// How long in-flight tools may keep running after Ctrl-C unless `--shutdown-grace` says otherwise.
+pub const DEFAULT_SHUTDOWN_GRACE: Duration = Duration::from_secs(30);
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static KILLED: AtomicBool = AtomicBool::new(false);
+static GRACE_MS: AtomicU64 = AtomicU64::new(DEFAULT_SHUTDOWN_GRACE.as_millis() as u64);
+static SEEN: OnceLock<Instant> = OnceLock::new();
+/// Process groups of the running tools, so a second Ctrl-C can kill them before exiting.
+static GROUPS: [AtomicI32; 256] = [const { AtomicI32::new(0) }; 256];
+
+/// Installs the SIGINT/SIGTERM handler; in-flight tools are killed `grace` after the first signal.
+pub fn install_signal_handler(grace: Duration) {
+    GRACE_MS.store(grace.as_millis() as u64, Ordering::Relaxed);
+    #[cfg(unix)]
+    unsafe {
+        let handler = on_signal as extern "C" fn(libc::c_int);
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, handler as libc::sighandler_t);
+    }
+}
+
+/// Only async-signal-safe calls: atomics, `write`, `kill` and `_exit`.
+#[cfg(unix)]
+extern "C" fn on_signal(_: libc::c_int) {
+    if REQUESTED.swap(true, Ordering::SeqCst) {
+        for group in &GROUPS {
+            let pgid = group.load(Ordering::SeqCst);
+            if pgid > 0 {
+                unsafe { libc::kill(-pgid, libc::SIGKILL) };
+            }
+        }
+        unsafe { libc::_exit(130) };
+    }
+    let msg = b"\nInterrupted: finishing the repositories in flight and writing checkpoint.json (Ctrl-C again to exit now)\n";
+    unsafe { libc::write(2, msg.as_ptr().cast(), msg.len()) };
+}
+
+/// Behaves as if Ctrl-C had been pressed once.
+pub fn request_shutdown() {
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Whether Ctrl-C was pressed; no new repository may start once it was.
+pub fn shutdown_requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Sleeps in steps, so Ctrl-C doesn't wait out a rate-limit window or a retry backoff.
+pub(crate) fn sleep_unless_shutdown(duration: Duration) {
+    let until = Instant::now() + duration;
+    while Instant::now() < until && !shutdown_requested() {
+        std::thread::sleep(Duration::from_millis(200).min(until - Instant::now()));
+    }
+}
+
+/// Whether the grace period is over, so running tools must be killed and no new ones started.
+pub(crate) fn grace_expired() -> bool {
+    shutdown_requested() && SEEN.get_or_init(Instant::now).elapsed() >= Duration::from_millis(GRACE_MS.load(Ordering::Relaxed))
+}
+
+/// Records that a tool was killed (or not started) for the shutdown, so the results of the
+/// repositories in flight are incomplete and must not be written.
+pub(crate) fn mark_killed() {
+    KILLED.store(true, Ordering::SeqCst);
+}
+
+pub(crate) fn tools_killed() -> bool {
+    KILLED.load(Ordering::SeqCst)
+}
+
+/// Tracks a tool's process group while it runs; untracked if all slots are taken.
+pub(crate) struct GroupGuard(Option<usize>);
+
+impl GroupGuard {
+    pub(crate) fn new(pgid: u32) -> Self {
+        let slot = GROUPS.iter().position(|g| g.compare_exchange(0, pgid as i32, Ordering::SeqCst, Ordering::SeqCst).is_ok());
+        GroupGuard(slot)
+    }
+}
+
+impl Drop for GroupGuard {
+    fn drop(&mut self) {
+        if let Some(slot) = self.0 {
+            GROUPS[slot].store(0, Ordering::SeqCst);
+        }
+    }
+}
+
+/// `checkpoint.json`, written next to the output of a step cut short by Ctrl-C, and read by `--resume`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Checkpoint {
+    pub interrupted_at: String,
+    /// `outputs`, `collect`, `stream` or `discover`
+    pub step: String,
+    /// Repositories whose entries are all in the output file, including those of resumed runs (for
+    /// `discover`, the finished date ranges and pages)
+    pub completed: Vec<String>,
+    /// With `stream`, the repositories cut short whose checkouts an interrupted run cloned and
+    /// kept, so the resumed run deletes them as if it had cloned them itself
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cloned: Vec<String>,
+}
+
+impl Checkpoint {
+    pub fn new(step: &str, mut completed: Vec<String>) -> Self {
+        completed.sort();
+        Checkpoint { interrupted_at: chrono::Utc::now().to_rfc3339(), step: step.to_string(), completed, cloned: Vec::new() }
+    }
+
+    /// The checkpoint next to `file` when it was left by an interrupted `step`.
+    pub fn load_beside(file: &str, step: &str) -> anyhow::Result<Option<Self>> {
+        let path = Self::path_beside(file);
+        let Ok(text) = fs::read_to_string(&path) else { return Ok(None) };
+        let checkpoint: Self = serde_json::from_str(&text).with_context(|| format!("invalid checkpoint {}", path.display()))?;
+        Ok((checkpoint.step == step).then_some(checkpoint))
+    }
+
+    pub fn write_beside(&self, file: &str) -> anyhow::Result<()> {
+        let path = Self::path_beside(file);
+        fs::write(&path, serde_json::to_string_pretty(self)?).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Removes the checkpoint next to `file` left by `step`, once that step has been finished
+    /// or restarted from scratch.
+    pub fn remove_beside(file: &str, step: &str) {
+        if Self::load_beside(file, step).is_ok_and(|c| c.is_some()) {
+            let _ = fs::remove_file(Self::path_beside(file));
+        }
+    }
+
+    fn path_beside(file: &str) -> PathBuf {
+        Path::new(file).parent().unwrap_or(Path::new("")).join("checkpoint.json")
+    }
+}
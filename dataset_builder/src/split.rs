@@ -0,0 +1,215 @@
+//! `split`: train/validation/test files with every repository in exactly one of them, decided by
+//! a seeded hash of its name, so adding repositories later leaves the others where they were.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::jsonl::{open_jsonl, Codec, JsonlWriter};
+use crate::sample::Strata;
+
+pub const SPLITS_FILE: &str = "splits.json";
+
+/// The splits, in the order their fractions are laid out on `0..1`.
+pub const SPLITS: [&str; 3] = ["train", "val", "test"];
+
+#[derive(Args, Clone, Debug)]
+pub struct SplitOptions {
+    /// A code, outputs or labels file; anything with a `name` per line
+    #[arg(long = "in", value_name = "FILE")]
+    pub input: String,
+    /// Where to write `train.jsonl`, `val.jsonl`, `test.jsonl` and `splits.json` (default: the
+    /// input's directory)
+    #[arg(long, value_name = "DIR")]
+    pub out_dir: Option<String>,
+    #[arg(long, default_value_t = 0.9)]
+    pub train: f64,
+    #[arg(long, default_value_t = 0.05)]
+    pub val: f64,
+    #[arg(long, default_value_t = 0.05)]
+    pub test: f64,
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+    /// Split each range of a repository attribute in the fractions: `files`, `bytes` or `flagged`
+    /// (percent of files flagged in `--labels`), e.g. `files:0-100,100-1000,1000+`. Repositories
+    /// outside every range are split together.
+    #[arg(long, value_name = "COLUMN:RANGES")]
+    pub stratify_by: Option<String>,
+    /// The labels file written by `label`, for `--stratify-by flagged:...`
+    #[arg(long, value_name = "FILE")]
+    pub labels: Option<String>,
+}
+
+impl Default for SplitOptions {
+    fn default() -> Self {
+        SplitOptions { input: String::new(), out_dir: None, train: 0.9, val: 0.05, test: 0.05, seed: 0, stratify_by: None, labels: None }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SplitCounts {
+    pub repos: usize,
+    pub files: usize,
+    /// Of the lines written
+    pub bytes: u64,
+}
+
+/// `splits.json`: how the split was made, its counts, and every repository's split.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SplitMap {
+    pub input: String,
+    pub seed: u64,
+    /// `train`, `val` and `test`
+    pub fractions: BTreeMap<String, f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stratify_by: Option<String>,
+    pub counts: BTreeMap<String, SplitCounts>,
+    /// Repository `name` to its split
+    pub repos: BTreeMap<String, String>,
+}
+
+/// `train.jsonl` and the like in `dir`, with the compression extension of `input`.
+pub fn split_path(dir: &Path, split: &str, input: &str) -> PathBuf {
+    match Codec::of(input).extension() {
+        Some(ext) => dir.join(format!("{}.jsonl.{}", split, ext)),
+        None => dir.join(format!("{}.jsonl", split)),
+    }
+}
+
+/// Where `name` falls on `0..1` for `seed`: the first 53 bits of the blake3 of both.
+pub fn split_point(name: &str, seed: u64) -> f64 {
+    let hash = blake3::hash(format!("{}:{}", seed, name).as_bytes());
+    let bits = u64::from_le_bytes(hash.as_bytes()[..8].try_into().expect("8 bytes"));
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[derive(Deserialize)]
+struct Named {
+    name: String,
+    #[serde(default)]
+    flagged: bool,
+}
+
+/// The split of each repository. Without strata a repository's split only depends on its own
+/// point, so it never moves. Within a stratum, repositories are ranked by their points and cut
+/// at the fractions, so the stratum is balanced exactly, but a repository near a cut can move
+/// when others join the stratum.
+fn assign(repos: &BTreeMap<String, SplitCounts>, strata: Option<(&Strata, &HashMap<String, u64>)>, fractions: [f64; 3], seed: u64) -> BTreeMap<String, String> {
+    let Some((strata, values)) = strata else {
+        return repos
+            .keys()
+            .map(|name| {
+                let point = split_point(name, seed);
+                let split = if point < fractions[0] { 0 } else if point < fractions[0] + fractions[1] { 1 } else { 2 };
+                (name.clone(), SPLITS[split].to_string())
+            })
+            .collect();
+    };
+    let mut groups: BTreeMap<Option<usize>, Vec<(f64, &String)>> = BTreeMap::new();
+    for name in repos.keys() {
+        groups.entry(strata.bucket(values.get(name).copied().unwrap_or(0))).or_default().push((split_point(name, seed), name));
+    }
+    let mut assigned = BTreeMap::new();
+    for mut group in groups.into_values() {
+        group.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let n = group.len();
+        // Largest remainder, so the quotas add up to the stratum's size.
+        let mut quotas: Vec<usize> = fractions.iter().map(|f| (n as f64 * f).floor() as usize).collect();
+        let mut by_remainder = [0, 1, 2];
+        by_remainder.sort_by(|&a, &b| (n as f64 * fractions[b]).fract().total_cmp(&(n as f64 * fractions[a]).fract()));
+        for &i in by_remainder.iter().take(n - quotas.iter().sum::<usize>()) {
+            quotas[i] += 1;
+        }
+        let mut names = group.into_iter().map(|(_, name)| name);
+        for (split, quota) in SPLITS.iter().zip(quotas) {
+            assigned.extend(names.by_ref().take(quota).map(|name| (name.clone(), split.to_string())));
+        }
+    }
+    assigned
+}
+
+/// Writes every line of `opts.input` to the file of its repository's split, and `splits.json`.
+/// The input is read twice; only the repository names and counts are held in memory.
+pub fn split_dataset(opts: &SplitOptions) -> anyhow::Result<SplitMap> {
+    let fractions = [opts.train, opts.val, opts.test];
+    anyhow::ensure!(fractions.iter().all(|f| (0.0..=1.0).contains(f)), "--train, --val and --test must be fractions from 0 to 1");
+    anyhow::ensure!((fractions.iter().sum::<f64>() - 1.0).abs() < 1e-6, "--train, --val and --test add up to {}, not 1", fractions.iter().sum::<f64>());
+    let strata = opts.stratify_by.as_deref().map(Strata::parse).transpose()?;
+    if let Some(s) = &strata {
+        anyhow::ensure!(["files", "bytes", "flagged"].contains(&s.column.as_str()), "--stratify-by can only use files, bytes or flagged, not `{}`", s.column);
+        anyhow::ensure!(s.column != "flagged" || opts.labels.is_some(), "--stratify-by flagged needs --labels");
+    }
+    let mut repos: BTreeMap<String, SplitCounts> = BTreeMap::new();
+    let read = |file: &str, f: &mut dyn FnMut(&str, Named) -> anyhow::Result<()>| -> anyhow::Result<()> {
+        for (i, line) in open_jsonl(file)?.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let named = serde_json::from_str(&line).with_context(|| format!("{}:{}: no `name`; `validate --repair` drops bad lines", file, i + 1))?;
+            f(&line, named)?;
+        }
+        Ok(())
+    };
+    read(&opts.input, &mut |line, e| {
+        let counts = repos.entry(e.name).or_default();
+        counts.files += 1;
+        counts.bytes += line.len() as u64 + 1;
+        Ok(())
+    })?;
+    let values: HashMap<String, u64> = match strata.as_ref().map(|s| s.column.as_str()) {
+        Some("files") => repos.iter().map(|(n, c)| (n.clone(), c.files as u64)).collect(),
+        Some("bytes") => repos.iter().map(|(n, c)| (n.clone(), c.bytes)).collect(),
+        Some(_) => {
+            let mut labeled: HashMap<String, (u64, u64)> = HashMap::new();
+            read(opts.labels.as_deref().unwrap_or_default(), &mut |_, l| {
+                let (flagged, files) = labeled.entry(l.name).or_default();
+                *flagged += u64::from(l.flagged);
+                *files += 1;
+                Ok(())
+            })?;
+            labeled.into_iter().map(|(n, (flagged, files))| (n, flagged * 100 / files.max(1))).collect()
+        }
+        None => HashMap::new(),
+    };
+    let assigned = assign(&repos, strata.as_ref().map(|s| (s, &values)), fractions, opts.seed);
+
+    let dir = match &opts.out_dir {
+        Some(d) => PathBuf::from(d),
+        None => Path::new(&opts.input).parent().map(Path::to_path_buf).unwrap_or_default(),
+    };
+    fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    let mut writers = SPLITS
+        .iter()
+        .map(|s| {
+            let path = split_path(&dir, s, &opts.input);
+            Ok((s.to_string(), JsonlWriter::compressed(File::create(&path).with_context(|| format!("failed to write {}", path.display()))?, Codec::of(&opts.input), None)?))
+        })
+        .collect::<anyhow::Result<HashMap<String, JsonlWriter>>>()?;
+    read(&opts.input, &mut |line, e| writers.get_mut(&assigned[&e.name]).expect("a split per name").write_line(line))?;
+    for (_, w) in writers {
+        w.finish()?;
+    }
+    let mut counts: BTreeMap<String, SplitCounts> = SPLITS.iter().map(|s| (s.to_string(), SplitCounts::default())).collect();
+    for (name, c) in &repos {
+        let total = counts.get_mut(&assigned[name]).expect("a known split");
+        total.repos += 1;
+        total.files += c.files;
+        total.bytes += c.bytes;
+    }
+    let map = SplitMap {
+        input: opts.input.clone(),
+        seed: opts.seed,
+        fractions: SPLITS.iter().map(|s| s.to_string()).zip(fractions).collect(),
+        stratify_by: opts.stratify_by.clone(),
+        counts,
+        repos: assigned,
+    };
+    fs::write(dir.join(SPLITS_FILE), serde_json::to_string_pretty(&map)? + "\n")?;
+    Ok(map)
+}
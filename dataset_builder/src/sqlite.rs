@@ -0,0 +1,238 @@
+//! `export-sqlite`: the code and outputs files as a SQLite database, with tables `repos`,
+//! `files`, `tool_results` and `findings`, for ad-hoc SQL over the corpus.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::BufRead;
+
+use anyhow::Context;
+use clap::Args;
+use rusqlite::{params, Connection};
+
+use crate::analyze::OutputEntry;
+use crate::collect::CodeEntry;
+use crate::join::{diagnostics, key};
+use crate::jsonl::open_jsonl;
+use crate::schema::{from_line, Versioned};
+
+pub const DEFAULT_SQLITE_FILE: &str = "dataset.db";
+
+/// Rows inserted per transaction.
+pub const DEFAULT_BATCH_ROWS: usize = 10_000;
+
+/// Every table has a natural key besides its `id`, which `--append` upserts by. Findings have
+/// none, so those of an outputs entry replace the ones imported for its repository and member.
+pub const SQLITE_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS repos (
+    id INTEGER PRIMARY KEY,
+    -- The checkout directory, as `dir` in the JSONL files
+    dir TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    repo TEXT,
+    commit_sha TEXT,
+    license_spdx TEXT
+);
+CREATE INDEX IF NOT EXISTS repos_name ON repos (name);
+CREATE TABLE IF NOT EXISTS files (
+    id INTEGER PRIMARY KEY,
+    repo_id INTEGER NOT NULL REFERENCES repos (id),
+    path TEXT NOT NULL,
+    -- The qualified path of a `--granularity` entry, '' for a whole file
+    item TEXT NOT NULL DEFAULT '',
+    kind TEXT NOT NULL,
+    language TEXT,
+    lines INTEGER NOT NULL,
+    bytes INTEGER NOT NULL,
+    tokens INTEGER,
+    content_hash TEXT NOT NULL,
+    generated INTEGER NOT NULL,
+    vendored INTEGER NOT NULL,
+    -- NULL with --no-content
+    content TEXT,
+    UNIQUE (repo_id, path, item)
+);
+CREATE INDEX IF NOT EXISTS files_path ON files (path);
+CREATE TABLE IF NOT EXISTS tool_results (
+    id INTEGER PRIMARY KEY,
+    repo_id INTEGER NOT NULL REFERENCES repos (id),
+    -- The `manifest_path` of a workspace member's entry, '' for a single crate
+    manifest TEXT NOT NULL DEFAULT '',
+    tool TEXT NOT NULL,
+    status TEXT NOT NULL,
+    exit_code INTEGER,
+    time_ms INTEGER,
+    error TEXT,
+    UNIQUE (repo_id, manifest, tool)
+);
+CREATE TABLE IF NOT EXISTS findings (
+    id INTEGER PRIMARY KEY,
+    repo_id INTEGER NOT NULL REFERENCES repos (id),
+    manifest TEXT NOT NULL DEFAULT '',
+    -- clippy, semgrep or codeql
+    tool TEXT NOT NULL,
+    rule_id TEXT,
+    severity TEXT NOT NULL,
+    -- Relative to the checkout, like files.path
+    path TEXT NOT NULL,
+    start_line INTEGER,
+    end_line INTEGER,
+    message TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS findings_rule_id ON findings (rule_id);
+CREATE INDEX IF NOT EXISTS findings_path ON findings (path);
+CREATE INDEX IF NOT EXISTS findings_repo ON findings (repo_id, manifest);
+";
+
+#[derive(Args, Clone, Debug)]
+pub struct ExportSqliteOptions {
+    /// The code file written by `collect`
+    #[arg(long, value_name = "FILE", required_unless_present = "outputs")]
+    pub code: Option<String>,
+    /// The outputs file written by `outputs`
+    #[arg(long, value_name = "FILE")]
+    pub outputs: Option<String>,
+    #[arg(long, value_name = "FILE", default_value = DEFAULT_SQLITE_FILE)]
+    pub out: String,
+    /// Leave `files.content` NULL, for a database a fraction of the size
+    #[arg(long)]
+    pub no_content: bool,
+    /// Add to an existing database, updating the rows with the same natural key, instead of
+    /// replacing it
+    #[arg(long)]
+    pub append: bool,
+    /// Rows inserted per transaction
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_BATCH_ROWS)]
+    pub batch_rows: usize,
+}
+
+impl Default for ExportSqliteOptions {
+    fn default() -> Self {
+        ExportSqliteOptions { code: None, outputs: None, out: DEFAULT_SQLITE_FILE.to_string(), no_content: false, append: false, batch_rows: DEFAULT_BATCH_ROWS }
+    }
+}
+
+/// Rows written by this run, updated rows included.
+#[derive(Debug, Default)]
+pub struct ExportSummary {
+    pub repos: usize,
+    pub files: usize,
+    pub tool_results: usize,
+    pub findings: usize,
+}
+
+/// The open database, with a transaction committed every `batch_rows` rows.
+struct Export {
+    conn: Connection,
+    batch_rows: usize,
+    pending: usize,
+    repo_ids: HashMap<String, i64>,
+    summary: ExportSummary,
+}
+
+impl Export {
+    fn row(&mut self) -> rusqlite::Result<()> {
+        self.pending += 1;
+        if self.pending >= self.batch_rows.max(1) {
+            self.conn.execute_batch("COMMIT; BEGIN")?;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+
+    /// The id of the repository, inserting or updating its row the first time it is seen.
+    fn repo(&mut self, name: &str, dir: &str, repo: Option<&str>, commit: Option<&str>, license: Option<&str>) -> rusqlite::Result<i64> {
+        if let Some(id) = self.repo_ids.get(key(name, dir)) {
+            return Ok(*id);
+        }
+        let id = self.conn.prepare_cached(
+            "INSERT INTO repos (dir, name, repo, commit_sha, license_spdx) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (dir) DO UPDATE SET name = excluded.name, repo = coalesce(excluded.repo, repo), commit_sha = coalesce(excluded.commit_sha, commit_sha), license_spdx = coalesce(excluded.license_spdx, license_spdx)
+             RETURNING id",
+        )?
+        .query_row(params![key(name, dir), name, repo, commit, license], |r| r.get(0))?;
+        self.repo_ids.insert(key(name, dir).to_string(), id);
+        self.summary.repos += 1;
+        self.row()?;
+        Ok(id)
+    }
+
+    fn file(&mut self, e: &CodeEntry, content: bool) -> rusqlite::Result<()> {
+        let repo_id = self.repo(&e.name, &e.dir, e.repo.as_deref(), e.commit.as_deref(), e.license_spdx.as_deref())?;
+        let item = e.item.as_ref().map_or("", |i| i.path.as_str());
+        let kind = serde_json::to_value(e.kind).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default();
+        self.conn
+            .prepare_cached(
+                "INSERT INTO files (repo_id, path, item, kind, language, lines, bytes, tokens, content_hash, generated, vendored, content) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+                 ON CONFLICT (repo_id, path, item) DO UPDATE SET kind = excluded.kind, language = excluded.language, lines = excluded.lines, bytes = excluded.bytes, tokens = excluded.tokens,
+                 content_hash = excluded.content_hash, generated = excluded.generated, vendored = excluded.vendored, content = excluded.content",
+            )?
+            .execute(params![repo_id, e.path, item, kind, e.language, e.lines as i64, e.bytes as i64, e.tokens.map(|t| t as i64), e.content_hash, e.generated, e.vendored, content.then_some(&e.content)])?;
+        self.summary.files += 1;
+        self.row()
+    }
+
+    fn output(&mut self, o: &OutputEntry) -> rusqlite::Result<()> {
+        let repo_id = self.repo(&o.name, &o.dir, o.repo.as_deref(), o.commit.as_deref(), None)?;
+        let manifest = o.workspace_root.is_some().then_some(o.manifest_path.as_deref()).flatten().unwrap_or("");
+        for (tool, t) in &o.tools {
+            let status = serde_json::to_value(&t.result.status).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default();
+            self.conn
+                .prepare_cached(
+                    "INSERT INTO tool_results (repo_id, manifest, tool, status, exit_code, time_ms, error) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                     ON CONFLICT (repo_id, manifest, tool) DO UPDATE SET status = excluded.status, exit_code = excluded.exit_code, time_ms = excluded.time_ms, error = excluded.error",
+                )?
+                .execute(params![repo_id, manifest, tool, status, t.result.exit_code, o.time_ms.get(tool).map(|ms| *ms as i64), t.result.error])?;
+            self.summary.tool_results += 1;
+            self.row()?;
+        }
+        self.conn.prepare_cached("DELETE FROM findings WHERE repo_id = ?1 AND manifest = ?2")?.execute(params![repo_id, manifest])?;
+        for (path, d) in diagnostics(o) {
+            self.conn
+                .prepare_cached("INSERT INTO findings (repo_id, manifest, tool, rule_id, severity, path, start_line, end_line, message) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)")?
+                .execute(params![repo_id, manifest, d.tool, d.rule, d.severity, path, d.start_line.map(|l| l as i64), d.end_line.map(|l| l as i64), d.message])?;
+            self.summary.findings += 1;
+            self.row()?;
+        }
+        Ok(())
+    }
+}
+
+/// Calls `f` with every entry of a JSONL file, naming the file and line on a bad one.
+fn each_entry<T: Versioned>(file: &str, mut f: impl FnMut(T) -> rusqlite::Result<()>) -> anyhow::Result<()> {
+    for (i, line) in open_jsonl(file)?.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = from_line(&line).with_context(|| format!("{}:{}: not a valid entry; `validate --repair` drops bad lines", file, i + 1))?;
+        f(entry).with_context(|| format!("{}:{}: failed to insert", file, i + 1))?;
+    }
+    Ok(())
+}
+
+/// Imports `opts.code` and `opts.outputs` into `opts.out`, one line at a time, replacing the
+/// database unless `--append`.
+pub fn export_sqlite(opts: &ExportSqliteOptions) -> anyhow::Result<ExportSummary> {
+    anyhow::ensure!(opts.code.is_some() || opts.outputs.is_some(), "nothing to export: pass --code, --outputs or both");
+    if !opts.append {
+        for file in [opts.out.clone(), format!("{}-journal", opts.out)] {
+            match fs::remove_file(&file) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e).with_context(|| format!("failed to replace {}", file)),
+                _ => {}
+            }
+        }
+    }
+    let conn = Connection::open(&opts.out).with_context(|| format!("failed to open {}", opts.out))?;
+    conn.execute_batch("PRAGMA foreign_keys = ON; PRAGMA synchronous = NORMAL;")?;
+    conn.execute_batch(SQLITE_SCHEMA).with_context(|| format!("failed to create the tables of {}", opts.out))?;
+    let mut export = Export { conn, batch_rows: opts.batch_rows, pending: 0, repo_ids: HashMap::new(), summary: ExportSummary::default() };
+    export.conn.execute_batch("BEGIN")?;
+    if let Some(code) = &opts.code {
+        each_entry(code, |e: CodeEntry| export.file(&e, !opts.no_content))?;
+    }
+    if let Some(outputs) = &opts.outputs {
+        each_entry(outputs, |o: OutputEntry| export.output(&o))?;
+    }
+    export.conn.execute_batch("COMMIT")?;
+    Ok(export.summary)
+}
@@ -0,0 +1,358 @@
+//! `stats`: a summary of a code file and an outputs file, read in one streaming pass each, as
+//! text, JSON or HTML. It is also a quick check that a run produced what it should have.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::BufRead;
+use std::path::Path;
+
+use anyhow::Context;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::analyze::OutputEntry;
+use crate::analyzers::Report;
+use crate::jsonl::open_jsonl;
+use crate::parse::GeigerStatus;
+use crate::schema::from_line;
+
+/// How many of the slowest repositories and most frequent lints are listed.
+pub const STATS_TOP: usize = 10;
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct StatsOptions {
+    /// The code file written by `collect`
+    #[arg(long, value_name = "FILE", required_unless_present = "outputs")]
+    pub code: Option<String>,
+    /// The outputs file written by `outputs`
+    #[arg(long, value_name = "FILE")]
+    pub outputs: Option<String>,
+    /// Also write the summary as JSON (`.json`) or as an HTML page (`.html`)
+    #[arg(long, value_name = "FILE")]
+    pub report: Option<String>,
+}
+
+/// Files and bytes of a language or extension.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct FileShare {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// A histogram bucket: values from `from` up to, not including, `to` (no `to` for the last).
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct HistogramBucket {
+    pub from: u64,
+    pub to: Option<u64>,
+    pub count: usize,
+}
+
+fn histogram(bounds: &[u64]) -> Vec<HistogramBucket> {
+    bounds.iter().enumerate().map(|(i, from)| HistogramBucket { from: *from, to: bounds.get(i + 1).copied(), count: 0 }).collect()
+}
+
+fn add(buckets: &mut [HistogramBucket], value: u64) {
+    if let Some(b) = buckets.iter_mut().rev().find(|b| value >= b.from) {
+        b.count += 1;
+    }
+}
+
+const SIZE_BOUNDS: [u64; 8] = [0, 1 << 10, 4 << 10, 16 << 10, 64 << 10, 256 << 10, 1 << 20, 16 << 20];
+const REPO_SIZE_BOUNDS: [u64; 7] = [0, 16 << 10, 256 << 10, 1 << 20, 16 << 20, 256 << 20, 1 << 30];
+const COUNT_BOUNDS: [u64; 6] = [0, 1, 10, 100, 1000, 10_000];
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CodeStats {
+    pub repos: usize,
+    pub files: usize,
+    pub bytes: u64,
+    pub lines: u64,
+    /// Lines that aren't code entries
+    pub invalid_lines: usize,
+    pub languages: BTreeMap<String, FileShare>,
+    /// By file extension, `(none)` for files without one
+    pub extensions: BTreeMap<String, FileShare>,
+    pub file_bytes: Vec<HistogramBucket>,
+    pub repo_bytes: Vec<HistogramBucket>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SlowRepo {
+    pub name: String,
+    pub ms: u128,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct OutputStats {
+    pub repos: usize,
+    /// One per crate: a workspace has an entry per member
+    pub entries: usize,
+    pub invalid_lines: usize,
+    /// Entries per tool and outcome: `passed` and `failed` for a completed run by exit code,
+    /// otherwise the status (`timeout`, `error`, `tool_not_installed`, ...)
+    pub tools: BTreeMap<String, BTreeMap<String, usize>>,
+    pub slowest: Vec<SlowRepo>,
+    /// Entries by their number of clippy warnings
+    pub clippy_warnings: Vec<HistogramBucket>,
+    /// The most frequent clippy lints, with their number of diagnostics
+    pub top_lints: Vec<(String, usize)>,
+    /// Entries by their total count of unsafe functions, expressions, impls, traits and
+    /// methods, when cargo-geiger scanned some of them
+    pub unsafe_counts: Option<Vec<HistogramBucket>>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CorpusStats {
+    pub code: Option<CodeStats>,
+    pub outputs: Option<OutputStats>,
+    /// Signs the run went wrong: invalid lines, a tool that never completed, an empty file
+    pub warnings: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct CodeLine {
+    name: String,
+    path: String,
+    #[serde(default)]
+    language: Option<String>,
+    #[serde(default)]
+    bytes: u64,
+    #[serde(default)]
+    lines: u64,
+    // Required, but never read into memory.
+    #[serde(rename = "content")]
+    _content: serde::de::IgnoredAny,
+}
+
+/// Every line of `file` that `parse` reads, and the number it doesn't.
+fn each_line<T>(file: &str, parse: fn(&str) -> serde_json::Result<T>, mut f: impl FnMut(T)) -> anyhow::Result<usize> {
+    let mut invalid = 0;
+    for line in open_jsonl(file)?.lines() {
+        let line = line.with_context(|| format!("failed to read {}", file))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse(&line) {
+            Ok(entry) => f(entry),
+            Err(_) => invalid += 1,
+        }
+    }
+    Ok(invalid)
+}
+
+/// A 64-bit digest of a repository name: distinct repositories are counted in 8 bytes each
+/// rather than by their names.
+fn digest(name: &str) -> u64 {
+    u64::from_le_bytes(blake3::hash(name.as_bytes()).as_bytes()[..8].try_into().unwrap())
+}
+
+fn code_stats(file: &str) -> anyhow::Result<CodeStats> {
+    let mut s = CodeStats { file_bytes: histogram(&SIZE_BOUNDS), repo_bytes: histogram(&REPO_SIZE_BOUNDS), ..CodeStats::default() };
+    let mut repos: HashMap<u64, u64> = HashMap::new();
+    s.invalid_lines = each_line(file, |line| serde_json::from_str(line), |e: CodeLine| {
+        s.files += 1;
+        s.bytes += e.bytes;
+        s.lines += e.lines;
+        let language = s.languages.entry(e.language.unwrap_or_else(|| "(unknown)".into())).or_default();
+        (language.files, language.bytes) = (language.files + 1, language.bytes + e.bytes);
+        let file_name = e.path.rsplit('/').next().unwrap_or_default();
+        let extension = file_name.rsplit_once('.').filter(|(stem, _)| !stem.is_empty()).map_or("(none)".to_string(), |(_, ext)| ext.to_ascii_lowercase());
+        let extension = s.extensions.entry(extension).or_default();
+        (extension.files, extension.bytes) = (extension.files + 1, extension.bytes + e.bytes);
+        add(&mut s.file_bytes, e.bytes);
+        *repos.entry(digest(&e.name)).or_default() += e.bytes;
+    })?;
+    s.repos = repos.len();
+    for bytes in repos.values() {
+        add(&mut s.repo_bytes, *bytes);
+    }
+    Ok(s)
+}
+
+fn output_stats(file: &str) -> anyhow::Result<OutputStats> {
+    let mut s = OutputStats { clippy_warnings: histogram(&COUNT_BOUNDS), ..OutputStats::default() };
+    let mut repos = HashSet::new();
+    let mut lints: HashMap<String, usize> = HashMap::new();
+    let mut unsafe_counts = histogram(&COUNT_BOUNDS);
+    let mut geiger = false;
+    s.invalid_lines = each_line(file, from_line, |o: OutputEntry| {
+        s.entries += 1;
+        repos.insert(digest(&o.name));
+        // Every entry of a workspace has the repository's time; it is listed once.
+        if !s.slowest.iter().any(|slow| slow.name == o.name) {
+            s.slowest.push(SlowRepo { name: o.name.clone(), ms: o.total_duration_ms });
+            s.slowest.sort_by(|a, b| b.ms.cmp(&a.ms).then_with(|| a.name.cmp(&b.name)));
+            s.slowest.truncate(STATS_TOP);
+        }
+        for (tool, t) in &o.tools {
+            let outcome = match (serde_json::to_value(&t.result.status).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default(), t.result.exit_code) {
+                (status, Some(0)) if status == "completed" => "passed".to_string(),
+                (status, _) if status == "completed" => "failed".to_string(),
+                (status, _) => status,
+            };
+            *s.tools.entry(tool.clone()).or_default().entry(outcome).or_default() += 1;
+            match &t.report {
+                Some(Report::Clippy(c)) => {
+                    add(&mut s.clippy_warnings, c.counts.get("warning").copied().unwrap_or(0) as u64);
+                    for d in &c.diagnostics {
+                        if let Some(lint) = &d.lint_name {
+                            *lints.entry(lint.clone()).or_default() += 1;
+                        }
+                    }
+                }
+                Some(Report::Geiger(g)) if matches!(g.geiger_status, GeigerStatus::Ok) => {
+                    geiger = true;
+                    let u = &g.total;
+                    add(&mut unsafe_counts, u.functions + u.exprs + u.impls + u.traits + u.methods);
+                }
+                _ => {}
+            }
+        }
+    })?;
+    s.repos = repos.len();
+    let mut lints: Vec<(String, usize)> = lints.into_iter().collect();
+    lints.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    lints.truncate(STATS_TOP);
+    s.top_lints = lints;
+    s.unsafe_counts = geiger.then_some(unsafe_counts);
+    Ok(s)
+}
+
+/// Reads `opts.code` and `opts.outputs` line by line. Memory grows with the number of
+/// repositories and distinct lints, languages and extensions, never with the size of the files.
+pub fn corpus_stats(opts: &StatsOptions) -> anyhow::Result<CorpusStats> {
+    anyhow::ensure!(opts.code.is_some() || opts.outputs.is_some(), "nothing to summarize: pass --code, --outputs or both");
+    let mut stats = CorpusStats::default();
+    if let Some(file) = &opts.code {
+        let code = code_stats(file)?;
+        if code.invalid_lines > 0 {
+            stats.warnings.push(format!("{}: {} lines aren't code entries; `validate --repair` drops them", file, code.invalid_lines));
+        }
+        if code.files == 0 {
+            stats.warnings.push(format!("{}: no code entries", file));
+        } else if code.bytes == 0 {
+            stats.warnings.push(format!("{}: every file is empty", file));
+        }
+        stats.code = Some(code);
+    }
+    if let Some(file) = &opts.outputs {
+        let outputs = output_stats(file)?;
+        if outputs.invalid_lines > 0 {
+            stats.warnings.push(format!("{}: {} lines aren't outputs entries; `validate --repair` drops them", file, outputs.invalid_lines));
+        }
+        if outputs.entries == 0 {
+            stats.warnings.push(format!("{}: no outputs entries", file));
+        }
+        for (tool, outcomes) in &outputs.tools {
+            let ran = outcomes.get("passed").copied().unwrap_or(0) + outcomes.get("failed").copied().unwrap_or(0);
+            let skipped = outcomes.iter().filter(|(o, _)| o.starts_with("skipped")).map(|(_, n)| n).sum::<usize>();
+            if ran == 0 && skipped < outcomes.values().sum::<usize>() {
+                stats.warnings.push(format!("{} never completed: {}", tool, outcomes.iter().map(|(o, n)| format!("{} {}", n, o)).collect::<Vec<_>>().join(", ")));
+            }
+        }
+        stats.outputs = Some(outputs);
+    }
+    Ok(stats)
+}
+
+/// `1.5 MiB`, `12.0 KiB`, `800 B`.
+pub fn human_bytes(n: u64) -> String {
+    match n {
+        n if n >= 1 << 30 => format!("{:.1} GiB", n as f64 / (1u64 << 30) as f64),
+        n if n >= 1 << 20 => format!("{:.1} MiB", n as f64 / (1u64 << 20) as f64),
+        n if n >= 1 << 10 => format!("{:.1} KiB", n as f64 / 1024.0),
+        n => format!("{} B", n),
+    }
+}
+
+/// The range of a bucket, with `unit` formatting its bounds.
+fn range(b: &HistogramBucket, unit: fn(u64) -> String) -> String {
+    match b.to {
+        Some(to) if to == b.from + 1 => unit(b.from),
+        Some(to) => format!("{}–{}", unit(b.from), unit(to)),
+        None => format!("≥ {}", unit(b.from)),
+    }
+}
+
+fn plain(n: u64) -> String {
+    n.to_string()
+}
+
+/// A section of the summary: a title and rows of a label and a value, for the text and the
+/// HTML renderings alike.
+type Section = (String, Vec<(String, String)>);
+
+impl CorpusStats {
+    fn sections(&self) -> Vec<Section> {
+        let mut out = Vec::new();
+        let shares = |m: &BTreeMap<String, FileShare>| {
+            let mut v: Vec<_> = m.iter().collect();
+            v.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(b.0)));
+            v.into_iter().map(|(k, s)| (k.clone(), format!("{} files, {}", s.files, human_bytes(s.bytes)))).collect()
+        };
+        let buckets = |v: &[HistogramBucket], unit: fn(u64) -> String| v.iter().map(|b| (range(b, unit), b.count.to_string())).collect();
+        if let Some(c) = &self.code {
+            out.push(("Code".into(), vec![("repositories".into(), c.repos.to_string()), ("files".into(), c.files.to_string()), ("bytes".into(), human_bytes(c.bytes)), ("lines".into(), c.lines.to_string())]));
+            out.push(("Languages".into(), shares(&c.languages)));
+            out.push(("Extensions".into(), shares(&c.extensions)));
+            out.push(("File sizes".into(), buckets(&c.file_bytes, human_bytes)));
+            out.push(("Repository sizes".into(), buckets(&c.repo_bytes, human_bytes)));
+        }
+        if let Some(o) = &self.outputs {
+            out.push(("Outputs".into(), vec![("repositories".into(), o.repos.to_string()), ("entries".into(), o.entries.to_string())]));
+            out.push(("Tools".into(), o.tools.iter().map(|(t, outcomes)| (t.clone(), outcomes.iter().map(|(k, n)| format!("{} {}", n, k)).collect::<Vec<_>>().join(", "))).collect()));
+            out.push(("Slowest repositories".into(), o.slowest.iter().map(|s| (s.name.clone(), format!("{:.1}s", s.ms as f64 / 1000.0))).collect()));
+            out.push(("Clippy warnings per entry".into(), buckets(&o.clippy_warnings, plain)));
+            out.push(("Most frequent lints".into(), o.top_lints.iter().map(|(l, n)| (l.clone(), n.to_string())).collect()));
+            if let Some(u) = &o.unsafe_counts {
+                out.push(("Unsafe items per entry (cargo-geiger)".into(), buckets(u, plain)));
+            }
+        }
+        out
+    }
+
+    /// The summary as `stats` prints it.
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        for (title, rows) in self.sections() {
+            let width = rows.iter().map(|(k, _)| k.chars().count()).max().unwrap_or(0);
+            let _ = writeln!(out, "{}", title);
+            for (k, v) in rows {
+                let _ = writeln!(out, "  {:width$}  {}", k, v, width = width);
+            }
+        }
+        for w in &self.warnings {
+            let _ = writeln!(out, "⚠ {}", w);
+        }
+        out
+    }
+
+    /// A standalone HTML page with a table per section.
+    pub fn html(&self) -> String {
+        let escape = |s: &str| s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+        let mut out = String::from("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Corpus statistics</title>\n<style>body{font-family:sans-serif;margin:2em}table{border-collapse:collapse;margin-bottom:1.5em}td{padding:2px 12px;border-bottom:1px solid #ddd}.warn{color:#b00}</style></head><body>\n<h1>Corpus statistics</h1>\n");
+        for w in &self.warnings {
+            let _ = writeln!(out, "<p class=\"warn\">⚠ {}</p>", escape(w));
+        }
+        for (title, rows) in self.sections() {
+            let _ = writeln!(out, "<h2>{}</h2>\n<table>", escape(&title));
+            for (k, v) in rows {
+                let _ = writeln!(out, "<tr><td>{}</td><td>{}</td></tr>", escape(&k), escape(&v));
+            }
+            out.push_str("</table>\n");
+        }
+        out.push_str("</body></html>\n");
+        out
+    }
+
+    /// Writes the summary to `path`, as JSON or HTML by its extension.
+    pub fn write_report(&self, path: &str) -> anyhow::Result<()> {
+        let text = match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::to_string_pretty(self)?,
+            Some("html" | "htm") => self.html(),
+            _ => anyhow::bail!("--report {}: write a .json or .html file", path),
+        };
+        fs::write(path, text).with_context(|| format!("failed to write {}", path))
+    }
+}
@@ -0,0 +1,127 @@
+//! What the nightly analyzers print for each library and binary target of a crate: `expand`'s
+//! macro-expanded source, and the `ast`, `hir` and `mir` dumps. The text goes to `expanded.jsonl`
+//! and `ir.jsonl`, keyed by package and target as `CodeEntry` is, so the entries only carry sizes.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Written next to the outputs file, unless `--expanded-file` says otherwise: one line per
+/// target `expand` expanded.
+pub const EXPANDED_FILE: &str = "expanded.jsonl";
+
+/// Written next to the outputs file, unless `--ir-file` says otherwise: one line per target and
+/// representation `ast`, `hir` or `mir` dumped.
+pub const IR_FILE: &str = "ir.jsonl";
+
+/// `--max-expanded-bytes` when none is given. Derives make an expansion many times the size of
+/// its source.
+pub const DEFAULT_MAX_EXPANDED_BYTES: usize = 4 << 20;
+
+/// `--max-ir-bytes` when none is given; MIR runs to several times the expanded source.
+pub const DEFAULT_MAX_IR_BYTES: usize = 8 << 20;
+
+/// Where the expanded sources of `outputs_file` are written without `--expanded-file`.
+pub fn expanded_beside(outputs_file: &str) -> PathBuf {
+    Path::new(outputs_file).parent().unwrap_or(Path::new("")).join(EXPANDED_FILE)
+}
+
+/// Where the IR dumps of `outputs_file` are written without `--ir-file`.
+pub fn ir_beside(outputs_file: &str) -> PathBuf {
+    Path::new(outputs_file).parent().unwrap_or(Path::new("")).join(IR_FILE)
+}
+
+/// What a tool printed for one target.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct TargetSource {
+    /// As in `CodeEntry::package`
+    pub package: String,
+    /// `lib` or `bin:<name>`, as in `CodeEntry::target`
+    pub target: String,
+    pub content: String,
+    /// Size of the whole output, before the size cap cut `content` at a line end
+    pub bytes: usize,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+impl TargetSource {
+    pub(crate) fn new(package: &str, target: &str, content: String, max: usize) -> Self {
+        let bytes = content.len();
+        let truncated = max > 0 && bytes > max;
+        let content = if truncated { content[..content.as_bytes()[..max].iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1)].to_string() } else { content };
+        TargetSource { package: package.to_string(), target: target.to_string(), content, bytes, truncated }
+    }
+}
+
+/// One line of `expanded.jsonl`; `dir`, `package` and `target` join with `CodeEntry`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ExpandedEntry {
+    /// As in `OutputEntry::name`
+    pub name: String,
+    #[serde(default)]
+    pub dir: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    #[serde(flatten)]
+    pub source: TargetSource,
+}
+
+/// One line of `ir.jsonl`, keyed as `ExpandedEntry` is plus the representation.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct IrEntry {
+    /// As in `OutputEntry::name`
+    pub name: String,
+    #[serde(default)]
+    pub dir: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// `ast`, `hir` or `mir`, the analyzer that dumped it
+    pub repr: String,
+    #[serde(flatten)]
+    pub source: TargetSource,
+}
+
+/// A target a tool ran on: the size of its output, or why there is none.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct TargetOutcome {
+    pub target: String,
+    #[serde(default)]
+    pub bytes: usize,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// The first error cargo reported, such as a proc macro that failed to build
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The `expand` report of a crate.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ExpandReport {
+    pub package: String,
+    pub targets: Vec<TargetOutcome>,
+    pub expanded: usize,
+    pub failed: usize,
+}
+
+/// The `ast`, `hir` or `mir` report of a crate. A target that doesn't compile is a failure of
+/// its own; the others' dumps are kept.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct IrReport {
+    pub package: String,
+    pub targets: Vec<TargetOutcome>,
+    pub emitted: usize,
+    pub failed: usize,
+}
+
+/// Why a target's run failed: the first `error` line of its stderr, or its exit code.
+pub(crate) fn target_error(stderr: &str, exit_code: Option<i32>) -> String {
+    match stderr.lines().map(str::trim).find(|l| l.starts_with("error")) {
+        Some(line) => line.to_string(),
+        None => format!("exit code {}", exit_code.map_or("none".to_string(), |c| c.to_string())),
+    }
+}
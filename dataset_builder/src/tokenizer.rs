@@ -0,0 +1,145 @@
+//! Token counts for `CodeEntry::tokens` (`collect --tokenizer`).
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use anyhow::Context;
+use base64::Engine;
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum TokenizerKind {
+    /// No `tokens` field
+    #[default]
+    None,
+    /// Runs of non-whitespace characters
+    Whitespace,
+    /// Byte-pair encoding with the ranks of a tiktoken file (`--tokenizer-file`)
+    Tiktoken,
+}
+
+/// A loaded tokenizer; `None` counts nothing.
+pub enum Tokenizer {
+    None,
+    Whitespace,
+    Bpe(Arc<Bpe>),
+}
+
+impl Tokenizer {
+    /// The tokenizer of `kind`, loading `file` for `Tiktoken`. Files are loaded once per process.
+    pub fn new(kind: TokenizerKind, file: Option<&Path>) -> anyhow::Result<Self> {
+        Ok(match kind {
+            TokenizerKind::None => Tokenizer::None,
+            TokenizerKind::Whitespace => Tokenizer::Whitespace,
+            TokenizerKind::Tiktoken => {
+                let file = file.context("--tokenizer tiktoken needs --tokenizer-file, e.g. cl100k_base.tiktoken")?;
+                static LOADED: OnceLock<Mutex<HashMap<PathBuf, Arc<Bpe>>>> = OnceLock::new();
+                let mut loaded = LOADED.get_or_init(Default::default).lock().unwrap();
+                match loaded.get(file) {
+                    Some(bpe) => Tokenizer::Bpe(bpe.clone()),
+                    None => {
+                        let bpe = Arc::new(Bpe::load(file)?);
+                        loaded.insert(file.to_path_buf(), bpe.clone());
+                        Tokenizer::Bpe(bpe)
+                    }
+                }
+            }
+        })
+    }
+
+    pub fn count(&self, text: &str) -> Option<usize> {
+        match self {
+            Tokenizer::None => None,
+            Tokenizer::Whitespace => Some(text.split_whitespace().count()),
+            Tokenizer::Bpe(bpe) => Some(pieces(text).map(|p| bpe.count(p.as_bytes())).sum()),
+        }
+    }
+}
+
+/// Merge ranks of a tiktoken file: a base64 token and its rank per line.
+pub struct Bpe {
+    ranks: HashMap<Vec<u8>, u32>,
+}
+
+impl Bpe {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let mut ranks = HashMap::new();
+        for (i, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let parsed = line.split_once(' ').and_then(|(token, rank)| Some((base64::engine::general_purpose::STANDARD.decode(token).ok()?, rank.trim().parse().ok()?)));
+            let (token, rank) = parsed.with_context(|| format!("{}:{}: expected `<base64 token> <rank>`", path.display(), i + 1))?;
+            ranks.insert(token, rank);
+        }
+        Ok(Bpe { ranks })
+    }
+
+    /// Tokens of one piece: its bytes, merged pair by pair, lowest rank first.
+    fn count(&self, piece: &[u8]) -> usize {
+        if self.ranks.contains_key(piece) {
+            return 1;
+        }
+        let mut parts: Vec<&[u8]> = piece.chunks(1).collect();
+        loop {
+            let best = (0..parts.len().saturating_sub(1))
+                .filter_map(|i| {
+                    let merged = &piece[offset(piece, parts[i])..offset(piece, parts[i + 1]) + parts[i + 1].len()];
+                    self.ranks.get(merged).map(|rank| (*rank, i, merged))
+                })
+                .min();
+            let Some((_, i, merged)) = best else { return parts.len() };
+            parts[i] = merged;
+            parts.remove(i + 1);
+        }
+    }
+}
+
+fn offset(piece: &[u8], part: &[u8]) -> usize {
+    part.as_ptr() as usize - piece.as_ptr() as usize
+}
+
+/// Splits text the way tiktoken's pattern roughly does before merging: words and punctuation
+/// runs with their leading space, digits in threes, and whitespace. Counts may differ slightly
+/// from tiktoken's own.
+fn pieces(text: &str) -> impl Iterator<Item = &str> {
+    #[derive(PartialEq)]
+    enum Class {
+        Letter,
+        Digit,
+        Space,
+        Other,
+    }
+    let class = |c: char| match c {
+        _ if c.is_alphabetic() => Class::Letter,
+        _ if c.is_numeric() => Class::Digit,
+        _ if c.is_whitespace() => Class::Space,
+        _ => Class::Other,
+    };
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        let mut chars = rest.char_indices().peekable();
+        let (_, first) = chars.next()?;
+        let mut kind = class(first);
+        let mut digits = usize::from(kind == Class::Digit);
+        // A single space starts the word or punctuation run after it.
+        if first == ' ' {
+            if let Some(&(_, next)) = chars.peek() {
+                if matches!(class(next), Class::Letter | Class::Other) {
+                    kind = class(next);
+                    chars.next();
+                }
+            }
+        }
+        let end = chars
+            .find(|&(_, c)| {
+                let stop = class(c) != kind || (kind == Class::Digit && digits == 3);
+                digits += usize::from(kind == Class::Digit);
+                stop
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        let (piece, tail) = rest.split_at(end);
+        rest = tail;
+        Some(piece)
+    })
+}
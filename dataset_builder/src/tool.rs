@@ -0,0 +1,376 @@
+//! Running external tools under deadlines and capturing their results.
+
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::sandbox::{SandboxOptions, HOST};
+use crate::shutdown;
+
+/// What a tool process is given besides its arguments: `CARGO_TARGET_DIR`, which the sandbox also
+/// lets it write to, and the run's variables (see `AnalyzeOptions::tool_vars`). They are set on
+/// the command alone, never on dataset_builder's own environment, which every command inherits.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct ToolEnv<'a> {
+    pub(crate) target_dir: Option<&'a Path>,
+    pub(crate) vars: &'a [(String, String)],
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Limits {
+    pub(crate) tool: Option<Duration>,
+    pub(crate) repo: Option<Duration>,
+}
+
+impl Limits {
+    /// Earliest of the per-tool and per-repo deadlines for a tool starting now.
+    pub fn deadline(&self, repo_start: Instant) -> Option<Instant> {
+        let tool = self.tool.map(|t| Instant::now() + t);
+        let repo = self.repo.map(|r| repo_start + r);
+        match (tool, repo) {
+            (Some(t), Some(r)) => Some(t.min(r)),
+            (t, r) => t.or(r),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TimedOut(pub(crate) Duration);
+
+impl std::fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "timed out after {:.1}s", self.0.as_secs_f64())
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolStatus {
+    /// The tool ran to completion; `exit_code` says whether it passed
+    Completed,
+    Timeout,
+    /// The tool could not be run at all (spawn failure, I/O error)
+    Error,
+    /// The binary was missing when the run started, so the tool was never invoked
+    ToolNotInstalled,
+    /// The tool needs a nightly toolchain and none is installed
+    SkippedNoNightly,
+    /// Left out by `--only`/`--skip`, or an opt-in analyzer that wasn't enabled
+    Skipped,
+    /// Not run because `cargo fetch` could not download the repository's dependencies
+    FetchFailed,
+    /// Not run because the checkout has no `Cargo.toml`, at its root or one level down
+    NotACargoProject,
+    /// Not run because `cargo check` found that the crate doesn't build
+    SkippedBuildFailed,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ToolResult {
+    pub status: ToolStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stdout: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stderr: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The output could not be parsed into structured results, so `stdout` is kept verbatim
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub parse_error: bool,
+    /// `stdout` or `stderr` went over `--max-tool-output-bytes` and was cut short, ending in a
+    /// `[truncated N bytes]` marker, or was still held open by a process that escaped the tool's
+    /// process group when its deadline passed, ending in `[truncated: ...]`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// Unix only; absent when the tool didn't finish
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<ResourceUsage>,
+}
+
+/// Peak memory and CPU time of a tool process, including the children it waited for (rustc).
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct ResourceUsage {
+    pub peak_rss_kb: u64,
+    pub user_cpu_ms: u64,
+    pub sys_cpu_ms: u64,
+}
+
+impl ResourceUsage {
+    /// Usage of two processes run one after the other.
+    pub(crate) fn merge(a: Option<Self>, b: Option<Self>) -> Option<Self> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(ResourceUsage {
+                peak_rss_kb: a.peak_rss_kb.max(b.peak_rss_kb),
+                user_cpu_ms: a.user_cpu_ms + b.user_cpu_ms,
+                sys_cpu_ms: a.sys_cpu_ms + b.sys_cpu_ms,
+            }),
+            (a, b) => a.or(b),
+        }
+    }
+
+    #[cfg(unix)]
+    fn from_rusage(ru: &libc::rusage) -> Self {
+        let ms = |t: libc::timeval| t.tv_sec as u64 * 1000 + t.tv_usec as u64 / 1000;
+        // ru_maxrss is in kilobytes on Linux but in bytes on macOS.
+        let rss = ru.ru_maxrss as u64;
+        let peak_rss_kb = if cfg!(target_os = "macos") { rss / 1024 } else { rss };
+        ResourceUsage { peak_rss_kb, user_cpu_ms: ms(ru.ru_utime), sys_cpu_ms: ms(ru.ru_stime) }
+    }
+}
+
+impl ToolResult {
+    pub(crate) fn from_output(ran: Ran) -> Self {
+        ToolResult {
+            status: ToolStatus::Completed,
+            exit_code: ran.out.status.code(),
+            stdout: String::from_utf8_lossy(&ran.out.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&ran.out.stderr).into_owned(),
+            error: None,
+            parse_error: false,
+            truncated: ran.cut_short,
+            usage: ran.usage,
+        }
+    }
+
+    pub(crate) fn with_status(status: ToolStatus) -> Self {
+        ToolResult { status, exit_code: None, stdout: String::new(), stderr: String::new(), error: None, parse_error: false, truncated: false, usage: None }
+    }
+
+    pub(crate) fn failed(e: &anyhow::Error) -> Self {
+        ToolResult {
+            status: if e.is::<TimedOut>() { ToolStatus::Timeout } else { ToolStatus::Error },
+            exit_code: None,
+            stdout: String::new(),
+            stderr: String::new(),
+            error: Some(format!("{:#}", e)),
+            parse_error: false,
+            truncated: false,
+            usage: None,
+        }
+    }
+
+    /// Cuts `stdout` and `stderr` down to `max` bytes each, at a character boundary, and returns
+    /// the full text of every stream it shortened.
+    pub(crate) fn truncate_output(&mut self, max: usize) -> Vec<(&'static str, String)> {
+        let mut spilled = Vec::new();
+        for (stream, text) in [("stdout", &mut self.stdout), ("stderr", &mut self.stderr)] {
+            if text.len() <= max {
+                continue;
+            }
+            let cut = (0..=max).rev().find(|&i| text.is_char_boundary(i)).unwrap_or(0);
+            let full = std::mem::take(text);
+            *text = format!("{}\n[truncated {} bytes]", &full[..cut], full.len() - cut);
+            spilled.push((stream, full));
+            self.truncated = true;
+        }
+        spilled
+    }
+}
+
+/// `cargo [+toolchain] <args>` in `dir`, on the host, with the run's `vars`.
+pub(crate) fn run_cmd(dir: &Path, toolchain: Option<&str>, args: &[&str], vars: &[(String, String)], deadline: Option<Instant>) -> anyhow::Result<ToolResult> {
+    run_cargo(&HOST, dir, dir, toolchain, args, ToolEnv { target_dir: None, vars }, deadline)
+}
+
+/// `cargo [+toolchain] <args>` in `dir` under `sandbox`, confined to the checkout at `root`,
+/// building into `env.target_dir` when set.
+pub(crate) fn run_cargo(sandbox: &SandboxOptions, root: &Path, dir: &Path, toolchain: Option<&str>, args: &[&str], env: ToolEnv, deadline: Option<Instant>) -> anyhow::Result<ToolResult> {
+    let toolchain = toolchain.map(|tc| format!("+{}", tc));
+    let args: Vec<&str> = toolchain.as_deref().into_iter().chain(args.iter().copied()).collect();
+    let subcommand = args.iter().find(|a| !a.starts_with('+')).copied().unwrap_or_default();
+    run_in(sandbox, root, dir, "cargo", &args, env, deadline).with_context(|| format!("failed to run cargo {}", subcommand))
+}
+
+/// `cargo fetch` on the host, with network: it doesn't get the run's variables, which turn it off.
+pub(crate) fn cargo_fetch(dir: &Path, toolchain: Option<&str>, deadline: Option<Instant>) -> anyhow::Result<ToolResult> {
+    let mut cmd = Command::new("cargo");
+    cmd.current_dir(dir);
+    if let Some(tc) = toolchain {
+        cmd.arg(format!("+{}", tc));
+    }
+    cmd.arg("fetch");
+    let ran = run_with_deadline(cmd, deadline).context("failed to run cargo fetch")?;
+    Ok(ToolResult::from_output(ran))
+}
+
+/// Runs `program` in `dir` under `sandbox`, which confines it to the checkout at `root` (and
+/// `env.target_dir`, exported as `CARGO_TARGET_DIR`).
+pub(crate) fn run_in(sandbox: &SandboxOptions, root: &Path, dir: &Path, program: &str, args: &[&str], env: ToolEnv, deadline: Option<Instant>) -> anyhow::Result<ToolResult> {
+    let (mut cmd, container) = sandbox.command(root, dir, program, env);
+    cmd.args(args);
+    let kill_container = || {
+        // Killing the `docker run` client leaves the container running; stop it by name.
+        if let Some(name) = &container {
+            let _ = Command::new("docker").args(["kill", name]).stdout(Stdio::null()).stderr(Stdio::null()).status();
+        }
+    };
+    let ran = run_until(cmd, deadline, kill_container).with_context(|| format!("failed to run {}", program))?;
+    Ok(ToolResult::from_output(ran))
+}
+
+/// Every environment variable dataset_builder reads a credential from: the GitHub tokens, the
+/// object store keys of `--upload-to`, the default `--hf-token` and the SSH key passphrase. They
+/// are kept from the tools, since the analyzed repositories' build scripts run under them and
+/// could write them into the checkout, or into the output that is stored.
+pub(crate) const CREDENTIAL_ENV_VARS: &[&str] =
+    &["GITHUB_TOKEN", "GITHUB_TOKENS", "AWS_ACCESS_KEY_ID", "AWS_SECRET_ACCESS_KEY", "AWS_SESSION_TOKEN", "HF_TOKEN", "SSH_KEY_PASSPHRASE"];
+
+/// How long the output of a tool without a deadline is waited for once it has exited and its
+/// process group is killed, in case a process that left the group holds the pipes open.
+const PIPE_GRACE: Duration = Duration::from_secs(10);
+
+/// Marks a stream cut short by `PIPE_GRACE` or the deadline.
+const PIPE_HELD_OPEN: &str = "[truncated: the output was still held open by a background process]";
+
+/// A process that exited: its output and resource usage, and whether a pipe was still held open
+/// by a process that escaped its group, so the output ends in `PIPE_HELD_OPEN`.
+pub(crate) struct Ran {
+    pub(crate) out: Output,
+    pub(crate) usage: Option<ResourceUsage>,
+    pub(crate) cut_short: bool,
+}
+
+/// Runs `cmd` in its own process group, killing the whole group if `deadline` passes.
+pub(crate) fn run_with_deadline(cmd: Command, deadline: Option<Instant>) -> anyhow::Result<Ran> {
+    run_until(cmd, deadline, || {})
+}
+
+/// `run_with_deadline`, calling `on_timeout` after killing the process group. The group is also
+/// killed once the Ctrl-C grace period is over, and once the tool exits, so a background process
+/// it left running (a daemon started by a build script, an sccache server) can't keep its pipes
+/// open; one that left the group too is waited for only until the deadline.
+fn run_until(mut cmd: Command, deadline: Option<Instant>, on_timeout: impl FnOnce()) -> anyhow::Result<Ran> {
+    let start = Instant::now();
+    if deadline.is_some_and(|d| start >= d) {
+        return Err(TimedOut(Duration::ZERO).into());
+    }
+    if shutdown::grace_expired() {
+        shutdown::mark_killed();
+        anyhow::bail!("not started: interrupted by Ctrl-C");
+    }
+    cmd.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    for var in CREDENTIAL_ENV_VARS {
+        cmd.env_remove(var);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+    let mut child = cmd.spawn()?;
+    let _group = shutdown::GroupGuard::new(child.id());
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+
+    let (status, usage) = loop {
+        if let Some(done) = wait_child(&mut child)? {
+            break done;
+        }
+        let timed_out = deadline.is_some_and(|d| Instant::now() >= d);
+        if timed_out || shutdown::grace_expired() {
+            kill_process_group(&mut child);
+            let _ = child.wait();
+            on_timeout();
+            // Don't join the readers: a grandchild that escaped the group could hold the pipes open.
+            if !timed_out {
+                shutdown::mark_killed();
+                anyhow::bail!("killed: interrupted by Ctrl-C");
+            }
+            return Err(TimedOut(start.elapsed()).into());
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    kill_process_group(&mut child);
+    // A moment past a deadline that has just passed, for the readers to drain pipes already closed.
+    let until = deadline.unwrap_or_else(|| Instant::now() + PIPE_GRACE).max(Instant::now() + Duration::from_secs(1));
+    let (stdout, stdout_cut) = stdout.collect(until);
+    let (stderr, stderr_cut) = stderr.collect(until);
+    Ok(Ran { out: Output { status, stdout, stderr }, usage, cut_short: stdout_cut || stderr_cut })
+}
+
+/// Reaps `child` if it has exited, with its resource usage from `wait4` where the platform has it.
+#[cfg(unix)]
+fn wait_child(child: &mut Child) -> std::io::Result<Option<(ExitStatus, Option<ResourceUsage>)>> {
+    use std::os::unix::process::ExitStatusExt;
+    let mut status = 0;
+    let mut ru: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        match unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, libc::WNOHANG, &mut ru) } {
+            0 => return Ok(None),
+            -1 => {
+                let e = std::io::Error::last_os_error();
+                if e.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+            _ => return Ok(Some((ExitStatus::from_raw(status), Some(ResourceUsage::from_rusage(&ru))))),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn wait_child(child: &mut Child) -> std::io::Result<Option<(ExitStatus, Option<ResourceUsage>)>> {
+    child.try_wait().map(|status| status.map(|s| (s, None)))
+}
+
+/// A pipe read to its end on a thread of its own, into a buffer that can be taken before then.
+struct PipeReader {
+    buf: Arc<Mutex<Vec<u8>>>,
+    reader: thread::JoinHandle<()>,
+}
+
+impl PipeReader {
+    /// What was read by `until`, and whether the pipe was still open then. A reader still
+    /// blocked is left behind: it ends when the last process holding the pipe does.
+    fn collect(self, until: Instant) -> (Vec<u8>, bool) {
+        while !self.reader.is_finished() && Instant::now() < until {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let open = !self.reader.is_finished();
+        let mut buf = std::mem::take(&mut *self.buf.lock().unwrap_or_else(|e| e.into_inner()));
+        if open {
+            if buf.last().is_some_and(|&b| b != b'\n') {
+                buf.push(b'\n');
+            }
+            buf.extend_from_slice(PIPE_HELD_OPEN.as_bytes());
+        }
+        (buf, open)
+    }
+}
+
+fn read_pipe<R: std::io::Read + Send + 'static>(pipe: Option<R>) -> PipeReader {
+    let buf = Arc::new(Mutex::new(Vec::new()));
+    let shared = buf.clone();
+    let reader = thread::spawn(move || {
+        let Some(mut p) = pipe else { return };
+        let mut chunk = [0; 8192];
+        loop {
+            match p.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => shared.lock().unwrap_or_else(|e| e.into_inner()).extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(_) => break,
+            }
+        }
+    });
+    PipeReader { buf, reader }
+}
+
+fn kill_process_group(child: &mut Child) {
+    #[cfg(unix)]
+    unsafe {
+        // Negative pid targets the group created by `process_group(0)`, so rustc children die too.
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    #[cfg(not(unix))]
+    let _ = child.kill();
+}
@@ -0,0 +1,148 @@
+//! User-defined analyzers from a `tools.toml` file (`--tools-config`).
+
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+use crate::analyze::AnalyzeOptions;
+use crate::analyzers::{builtin_analyzers, Analyzer, RepoTarget, Report, ToolOutput};
+use crate::tool::{run_in, Limits};
+
+/// `time_ms` keys the run uses for its own phases.
+const RESERVED_NAMES: &[&str] = &["fetch", "metadata", "codeql_create", "codeql_analyze"];
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ToolsConfig {
+    #[serde(default)]
+    tool: Vec<CustomAnalyzer>,
+}
+
+/// One `[[tool]]` entry: an external command run on every crate like the built-in analyzers.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomAnalyzer {
+    /// Key of the tool's output in `tools` and `time_ms`
+    pub name: String,
+    pub command: String,
+    /// Arguments; `{repo}` is replaced by the checkout's path, `{crate}` by the crate's directory
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub working_dir: WorkingDir,
+    #[serde(default)]
+    pub format: OutputFormat,
+    /// Overrides `--tool-timeout` for this tool; `--repo-timeout` still applies
+    pub timeout_secs: Option<u64>,
+    /// With `false` the tool only runs when named in `--enable`
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkingDir {
+    /// The crate's directory (a workspace member's own directory)
+    #[default]
+    Crate,
+    /// The checkout root
+    Repo,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// Output is stored as-is
+    #[default]
+    Text,
+    /// stdout is parsed into `report`; unparseable output is kept with `parse_error: true`
+    Json,
+}
+
+/// Reads and validates a tools config, failing on the first bad entry.
+pub fn load_tools_config(path: &str) -> anyhow::Result<Vec<CustomAnalyzer>> {
+    let text = fs::read_to_string(path).with_context(|| format!("failed to read tools config {}", path))?;
+    let config: ToolsConfig = toml::from_str(&text).with_context(|| format!("invalid tools config {}", path))?;
+    let builtins = builtin_analyzers();
+    for (i, tool) in config.tool.iter().enumerate() {
+        let problem = if tool.name.is_empty() || !tool.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            Some("`name` must be non-empty and contain only letters, digits, `-` and `_`".to_string())
+        } else if builtins.iter().any(|a| a.name() == tool.name) || RESERVED_NAMES.contains(&tool.name.as_str()) {
+            Some(format!("`{}` is already used by a built-in analyzer", tool.name))
+        } else if config.tool[..i].iter().any(|t| t.name == tool.name) {
+            Some(format!("`{}` is defined more than once", tool.name))
+        } else if tool.command.trim().is_empty() {
+            Some("`command` is empty".to_string())
+        } else if tool.timeout_secs == Some(0) {
+            Some("`timeout_secs` must be positive".to_string())
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            bail!("invalid tools config {}: [[tool]] #{} (`{}`): {}", path, i + 1, tool.name, problem);
+        }
+    }
+    Ok(config.tool)
+}
+
+impl Analyzer for CustomAnalyzer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        self.enabled
+    }
+
+    /// Custom commands run on any checkout; one that needs a Cargo project fails on its own.
+    fn needs_cargo_project(&self) -> bool {
+        false
+    }
+
+    fn fingerprint(&self) -> Vec<String> {
+        vec![format!("{:?}", self)]
+    }
+
+    fn command(&self, _opts: &AnalyzeOptions) -> Option<String> {
+        Some([self.command.as_str()].into_iter().chain(self.args.iter().map(String::as_str)).collect::<Vec<_>>().join(" "))
+    }
+
+    fn timeout_secs(&self, opts: &AnalyzeOptions) -> Option<u64> {
+        self.timeout_secs.or(opts.tool_timeout)
+    }
+
+    fn run(&self, t: &RepoTarget) -> anyhow::Result<ToolOutput> {
+        let absolute = |p: &Path| fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf()).display().to_string();
+        let (repo, krate) = (absolute(t.path), absolute(&t.dir));
+        let args: Vec<String> = self.args.iter().map(|a| a.replace("{repo}", &repo).replace("{crate}", &krate)).collect();
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let dir = match self.working_dir {
+            WorkingDir::Crate => &t.dir,
+            WorkingDir::Repo => t.path,
+        };
+        let deadline = match self.timeout_secs {
+            Some(secs) => Limits { tool: Some(Duration::from_secs(secs)), ..t.limits }.deadline(t.repo_start),
+            None => t.deadline(),
+        };
+        let mut out: ToolOutput = run_in(t.sandbox, t.path, dir, &self.command, &args, t.env(None), deadline)?.into();
+        if self.format == OutputFormat::Json {
+            match serde_json::from_str(&out.result.stdout) {
+                Ok(json) => {
+                    out.report = Some(Report::Json(json));
+                    if !t.opts.raw {
+                        out.result.stdout.clear();
+                    }
+                }
+                Err(_) => out.result.parse_error = true,
+            }
+        }
+        Ok(out)
+    }
+}
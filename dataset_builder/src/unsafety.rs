@@ -0,0 +1,274 @@
+//! The `unsafe-extract` step: every `unsafe` block, fn, impl and trait of the checkouts' `.rs`
+//! files as an entry of its own, with the lines around it and its `// SAFETY:` comment.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::Args;
+use proc_macro2::Span;
+use serde::{Deserialize, Serialize};
+use syn::spanned::Spanned;
+use syn::visit::{self, Visit};
+
+use crate::collect::{checkouts, rust_files, CodeSource};
+use crate::items::{impl_name, Source};
+use crate::jsonl::{Codec, JsonlWriter};
+use crate::progress::Progress;
+
+/// Written next to the unsafe file: one line of counts per repository.
+pub const UNSAFE_SUMMARY_FILE: &str = "unsafe_summary.jsonl";
+
+#[derive(Args, Clone, Debug)]
+pub struct UnsafeOptions {
+    /// Lines of context kept before and after each snippet
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub context_lines: usize,
+    /// Also leave out the paths matching this gitignore-style pattern (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    pub exclude_glob: Vec<String>,
+}
+
+impl Default for UnsafeOptions {
+    fn default() -> Self {
+        UnsafeOptions { context_lines: 3, exclude_glob: Vec::new() }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnsafeKind {
+    /// An `unsafe { ... }` expression
+    Block,
+    /// An `unsafe fn`, free or in an impl or trait
+    Fn,
+    /// An `unsafe impl`
+    Impl,
+    /// An `unsafe trait`
+    Trait,
+}
+
+/// One line of the unsafe file. `name`, `path` and `start_line` join with `CodeEntry` and its
+/// `item`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UnsafeEntry {
+    /// As in `CodeEntry::name`
+    pub name: String,
+    #[serde(default)]
+    pub dir: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    pub path: String,
+    pub kind: UnsafeKind,
+    /// The innermost named item the snippet is in, or is, e.g. `crate::buf::Buf::get`
+    pub item_path: String,
+    /// 1-based and inclusive; those of an fn, impl or trait include its attributes
+    pub start_line: usize,
+    pub end_line: usize,
+    pub snippet: String,
+    /// The `// SAFETY:` comment right above the snippet's first line, without the slashes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safety_comment: Option<String>,
+    /// Up to `--context-lines` whole lines before the snippet's first line and after its last
+    pub context_before: String,
+    pub context_after: String,
+}
+
+/// One line of `unsafe_summary.jsonl`, to compare with cargo-geiger's counts.
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct UnsafeSummary {
+    pub name: String,
+    pub dir: String,
+    /// `.rs` files read, and those with anything unsafe
+    pub files: usize,
+    pub files_with_unsafe: usize,
+    pub blocks: usize,
+    pub fns: usize,
+    pub impls: usize,
+    pub traits: usize,
+    /// With `safety_comment`
+    pub documented: usize,
+    /// Files that didn't parse, whose unsafe code isn't counted
+    pub parse_failed: usize,
+}
+
+/// Where `unsafe-extract` writes the summary of `unsafe_file`.
+pub fn unsafe_summary_beside(unsafe_file: &str) -> PathBuf {
+    Path::new(unsafe_file).parent().unwrap_or(Path::new("")).join(UNSAFE_SUMMARY_FILE)
+}
+
+/// Writes the unsafe code of every checkout under `root` to `unsafe_file`, and the count of each
+/// repository to `unsafe_summary.jsonl` beside it.
+pub fn extract_unsafe_all(root: &str, unsafe_file: &str, opts: &UnsafeOptions) -> anyhow::Result<Vec<UnsafeSummary>> {
+    let mut w = JsonlWriter::compressed(File::create(unsafe_file).with_context(|| format!("failed to write {}", unsafe_file))?, Codec::of(unsafe_file), None)?;
+    let repos = checkouts(root)?;
+    let progress = Progress::new(repos.len(), "repositories scanned for unsafe code");
+    let mut summaries = Vec::new();
+    for (source, path) in &repos {
+        let bar = progress.start(&path.file_name().unwrap_or_default().to_string_lossy());
+        summaries.push(extract_unsafe(path, source, opts, &mut |entry| w.write_entry(&entry))?);
+        progress.finish(bar);
+    }
+    progress.done();
+    w.finish()?;
+    let mut s = JsonlWriter::new(File::create(unsafe_summary_beside(unsafe_file))?, None);
+    for summary in &summaries {
+        s.write_entry(summary)?;
+    }
+    s.finish()?;
+    Ok(summaries)
+}
+
+/// Hands the unsafe code of the checkout's `.rs` files, as `collect` would find them, to `emit`,
+/// and returns its counts.
+pub fn extract_unsafe(repo_path: &Path, source: &CodeSource, opts: &UnsafeOptions, emit: &mut dyn FnMut(UnsafeEntry) -> anyhow::Result<()>) -> anyhow::Result<UnsafeSummary> {
+    let dir = repo_path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let mut summary = UnsafeSummary { name: source.name.clone(), dir, ..UnsafeSummary::default() };
+    for (module, file) in rust_files(repo_path, source, &opts.exclude_glob)? {
+        summary.files += 1;
+        let Ok(sites) = find_unsafe(&file.content, &module) else {
+            summary.parse_failed += 1;
+            continue;
+        };
+        summary.files_with_unsafe += usize::from(!sites.is_empty());
+        let lines: Vec<&str> = file.content.lines().collect();
+        for site in sites {
+            match site.kind {
+                UnsafeKind::Block => summary.blocks += 1,
+                UnsafeKind::Fn => summary.fns += 1,
+                UnsafeKind::Impl => summary.impls += 1,
+                UnsafeKind::Trait => summary.traits += 1,
+            }
+            let (start, end) = (site.start_line, site.end_line);
+            let safety_comment = safety_comment(&lines[..start - 1]);
+            summary.documented += usize::from(safety_comment.is_some());
+            emit(UnsafeEntry {
+                name: file.name.clone(),
+                dir: file.dir.clone(),
+                repo: file.repo.clone(),
+                commit: file.commit.clone(),
+                path: file.path.clone(),
+                kind: site.kind,
+                item_path: site.item_path,
+                start_line: start,
+                end_line: end,
+                snippet: site.snippet,
+                safety_comment,
+                context_before: lines[(start - 1).saturating_sub(opts.context_lines)..start - 1].join("\n"),
+                context_after: lines[end.min(lines.len())..(end + opts.context_lines).min(lines.len())].join("\n"),
+            })?;
+        }
+    }
+    Ok(summary)
+}
+
+struct Site {
+    kind: UnsafeKind,
+    item_path: String,
+    start_line: usize,
+    end_line: usize,
+    snippet: String,
+}
+
+/// The unsafe code of `content`, a file whose items are in `module`, in source order.
+fn find_unsafe(content: &str, module: &str) -> Result<Vec<Site>, String> {
+    let found = syn::parse_file(content)
+        .map(|file| {
+            let mut finder = Finder { source: Source::new(content), path: vec![module.to_string()], sites: Vec::new() };
+            finder.visit_file(&file);
+            finder.sites
+        })
+        .map_err(|e| e.to_string());
+    // As in `items::extract`.
+    proc_macro2::extra::invalidate_current_thread_spans();
+    found
+}
+
+struct Finder<'a> {
+    source: Source<'a>,
+    /// The module and items the visitor is in
+    path: Vec<String>,
+    sites: Vec<Site>,
+}
+
+impl Finder<'_> {
+    fn site(&mut self, kind: UnsafeKind, span: Span) {
+        let snippet = self.source.text(span).to_string();
+        self.sites.push(Site { kind, item_path: self.path.join("::"), start_line: span.start().line, end_line: span.end().line, snippet });
+    }
+
+    fn within(&mut self, name: String, f: impl FnOnce(&mut Self)) {
+        self.path.push(name);
+        f(self);
+        self.path.pop();
+    }
+}
+
+impl<'ast> Visit<'ast> for Finder<'_> {
+    fn visit_item_mod(&mut self, i: &'ast syn::ItemMod) {
+        self.within(i.ident.to_string(), |f| visit::visit_item_mod(f, i));
+    }
+
+    fn visit_item_fn(&mut self, i: &'ast syn::ItemFn) {
+        self.within(i.sig.ident.to_string(), |f| {
+            if i.sig.unsafety.is_some() {
+                f.site(UnsafeKind::Fn, i.span());
+            }
+            visit::visit_item_fn(f, i)
+        });
+    }
+
+    fn visit_item_impl(&mut self, i: &'ast syn::ItemImpl) {
+        let name = impl_name(&self.source, i);
+        self.within(name, |f| {
+            if i.unsafety.is_some() {
+                f.site(UnsafeKind::Impl, i.span());
+            }
+            visit::visit_item_impl(f, i)
+        });
+    }
+
+    fn visit_impl_item_fn(&mut self, i: &'ast syn::ImplItemFn) {
+        self.within(i.sig.ident.to_string(), |f| {
+            if i.sig.unsafety.is_some() {
+                f.site(UnsafeKind::Fn, i.span());
+            }
+            visit::visit_impl_item_fn(f, i)
+        });
+    }
+
+    fn visit_item_trait(&mut self, i: &'ast syn::ItemTrait) {
+        self.within(i.ident.to_string(), |f| {
+            if i.unsafety.is_some() {
+                f.site(UnsafeKind::Trait, i.span());
+            }
+            visit::visit_item_trait(f, i)
+        });
+    }
+
+    fn visit_trait_item_fn(&mut self, i: &'ast syn::TraitItemFn) {
+        self.within(i.sig.ident.to_string(), |f| {
+            if i.sig.unsafety.is_some() {
+                f.site(UnsafeKind::Fn, i.span());
+            }
+            visit::visit_trait_item_fn(f, i)
+        });
+    }
+
+    fn visit_expr_unsafe(&mut self, i: &'ast syn::ExprUnsafe) {
+        self.site(UnsafeKind::Block, i.span());
+        visit::visit_expr_unsafe(self, i);
+    }
+}
+
+/// The `// SAFETY:` comment ending right above the snippet, whose preceding lines are `above`:
+/// from its `SAFETY:` line (in any case) to the end of the run of `//` lines.
+fn safety_comment(above: &[&str]) -> Option<String> {
+    let comments: Vec<&str> = above.iter().rev().map(|l| l.trim()).take_while(|l| l.starts_with("//") && !l.starts_with("///")).collect();
+    let start = comments.iter().rposition(|l| l.to_ascii_uppercase().contains("SAFETY:"))?;
+    let text: Vec<&str> = comments[..=start].iter().rev().map(|l| l.trim_start_matches('/')).map(|l| l.strip_prefix(' ').unwrap_or(l)).collect();
+    Some(text.join("\n"))
+}
+
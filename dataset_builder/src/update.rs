@@ -0,0 +1,289 @@
+//! `update`: the existing checkouts fetched and moved to their remote's default branch, and only
+//! the repositories that changed (or failed before) analyzed and collected again.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::BufRead;
+use std::path::Path;
+
+use anyhow::Context;
+use clap::Args;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::analyze::{run_outputs, AnalyzeOptions};
+use crate::clone::{clone_record, clone_records, name_map, refresh_checkout, write_clone_manifest, AuthOptions, CloneErrorClass, CloneJob, CloneOutcome, CloneRecord, GitAuth, HistoryOptions, Refresh};
+use crate::collect::{recollect, CollectOptions};
+use crate::github::{backoff, Tokens};
+use crate::jsonl::{open_jsonl, JsonlWriter};
+use crate::progress::Progress;
+use crate::shutdown::sleep_unless_shutdown;
+
+/// Written next to the outputs file, unless `--changelog` says otherwise: what `update` did to
+/// each checkout that wasn't already up to date.
+pub const UPDATE_CHANGELOG_FILE: &str = "update_changelog.jsonl";
+
+#[derive(Args, Clone, Debug)]
+pub struct UpdateOptions {
+    /// The directory `clone` put the checkouts in
+    #[arg(long, value_name = "DIR", default_value = "datasets")]
+    pub root: String,
+    /// The outputs file to bring up to date: the entries of the repositories analyzed again are
+    /// replaced, the others kept
+    #[arg(long, value_name = "FILE", default_value = "outputs.jsonl")]
+    pub outputs: String,
+    /// Also collect the changed repositories again into this JSONL code file, with collect's
+    /// default options
+    #[arg(long, value_name = "FILE")]
+    pub code: Option<String>,
+    /// Where to write the changelog (default: update_changelog.jsonl beside --outputs)
+    #[arg(long, value_name = "FILE")]
+    pub changelog: Option<String>,
+    /// Retries of a fetch after a network error, a rate limit or a 5xx
+    #[arg(long, default_value_t = 3)]
+    pub fetch_retries: u32,
+    #[command(flatten)]
+    pub auth: AuthOptions,
+    /// How the changed repositories are analyzed; `--jobs` is also the checkouts fetched at a time
+    #[command(flatten)]
+    pub analyze: Box<AnalyzeOptions>,
+}
+
+impl Default for UpdateOptions {
+    fn default() -> Self {
+        UpdateOptions {
+            root: "datasets".into(),
+            outputs: "outputs.jsonl".into(),
+            code: None,
+            changelog: None,
+            fetch_retries: 3,
+            auth: AuthOptions::default(),
+            analyze: Box::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateStatus {
+    /// Fast-forwarded to commits the remote added
+    Updated,
+    /// The remote's history was rewritten (a force-push); the checkout was hard-reset to it
+    Reset,
+    Unchanged,
+    /// The remote doesn't exist any more; the checkout is kept as it was
+    Gone,
+    /// The fetch failed; the checkout is kept as it was
+    Failed,
+    /// Not fetched: not a git checkout, or pinned to a revision by the names file
+    Skipped,
+}
+
+/// One line of the changelog.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UpdateRecord {
+    /// The checkout directory
+    pub name: String,
+    /// The names-file entry, when `clone` recorded it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    pub status: UpdateStatus,
+    /// HEAD before the fetch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_commit: Option<String>,
+    /// HEAD after it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_commit: Option<String>,
+    /// Analyzed again: at another commit than its outputs entries (and then with `--code`
+    /// collected again too), with errors in them, or without any
+    #[serde(default)]
+    pub reanalyzed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct UpdateSummary {
+    /// Every checkout, in directory order
+    pub records: Vec<UpdateRecord>,
+    /// Files collected again into `--code`
+    pub files_collected: usize,
+    pub changelog: String,
+}
+
+impl UpdateSummary {
+    pub fn count(&self, status: UpdateStatus) -> usize {
+        self.records.iter().filter(|r| r.status == status).count()
+    }
+
+    pub fn reanalyzed(&self) -> usize {
+        self.records.iter().filter(|r| r.reanalyzed).count()
+    }
+}
+
+/// What the outputs file says of a checkout: the commit analyzed, and whether a tool failed.
+#[derive(Deserialize)]
+struct Analyzed {
+    name: String,
+    #[serde(default)]
+    dir: String,
+    #[serde(default)]
+    commit: Option<String>,
+    #[serde(default)]
+    errors: Vec<String>,
+}
+
+/// The outputs entries' commit and errors by checkout directory, none when there is no file yet.
+fn analyzed(outputs: &str) -> anyhow::Result<HashMap<String, (Option<String>, bool)>> {
+    let mut by_dir: HashMap<String, (Option<String>, bool)> = HashMap::new();
+    if !Path::new(outputs).exists() {
+        return Ok(by_dir);
+    }
+    for line in open_jsonl(outputs)?.lines() {
+        let line = line.with_context(|| format!("failed to read {}", outputs))?;
+        // Unreadable lines are dropped, and their repositories analyzed again, by --resume.
+        let Ok(a) = serde_json::from_str::<Analyzed>(&line) else { continue };
+        let dir = if a.dir.is_empty() { a.name } else { a.dir };
+        let entry = by_dir.entry(dir).or_default();
+        entry.0 = entry.0.take().or(a.commit);
+        entry.1 |= !a.errors.is_empty();
+    }
+    Ok(by_dir)
+}
+
+/// Fetches one checkout, retrying transient failures.
+fn fetch(path: &Path, name: &str, auth: GitAuth, retries: u32, progress: &Progress) -> anyhow::Result<Refresh> {
+    let bar = progress.start(name);
+    let mut attempt = 0;
+    let result = loop {
+        match refresh_checkout(path, name, auth, &bar) {
+            Err(e) if attempt < retries && CloneErrorClass::of(&e).is_transient() => {
+                tracing::warn!("fetching {} failed ({:#}); retrying", name, e);
+                sleep_unless_shutdown(backoff(attempt));
+                attempt += 1;
+            }
+            result => break result,
+        }
+    };
+    progress.finish(bar);
+    result
+}
+
+/// Fetches every checkout in `opts.root` (but pinned ones), then analyzes again into
+/// `opts.outputs` the repositories whose HEAD isn't the commit their entries were analyzed at,
+/// or whose entries have errors, and with `opts.code` collects the changed ones again. Fetch
+/// failures don't stop the others; every checkout that wasn't unchanged is written to the
+/// changelog.
+pub fn update_repos(opts: &UpdateOptions, tokens: &Tokens) -> anyhow::Result<UpdateSummary> {
+    let records = clone_records(&opts.root);
+    let names = name_map(&opts.root);
+    let analyzed = analyzed(&opts.outputs)?;
+    let mut dirs: Vec<String> = fs::read_dir(&opts.root)
+        .with_context(|| format!("failed to read {}", opts.root))?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .collect();
+    dirs.sort();
+
+    let progress = Progress::new(dirs.len(), "checkouts fetched");
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(opts.analyze.jobs.max(1)).build()?;
+    let mut updates: Vec<UpdateRecord> = pool.install(|| {
+        dirs.par_iter()
+            .map(|dir| {
+                let path = Path::new(&opts.root).join(dir);
+                let record = records.get(dir);
+                let mut update = UpdateRecord {
+                    name: dir.clone(),
+                    repo: names.get(dir).or(record.map(|r| &r.repo)).cloned(),
+                    status: UpdateStatus::Skipped,
+                    old_commit: None,
+                    new_commit: None,
+                    reanalyzed: false,
+                    note: None,
+                };
+                if let Some(rev) = record.and_then(|r| r.requested.as_deref()) {
+                    update.note = Some(format!("pinned to {}", rev));
+                    return update;
+                }
+                if !path.join(".git").exists() {
+                    update.note = Some("not a git checkout".into());
+                    return update;
+                }
+                let auth = GitAuth { opts: &opts.auth, token: tokens.for_clone() };
+                match fetch(&path, dir, auth, opts.fetch_retries, &progress) {
+                    Ok(refresh) => {
+                        update.status = match refresh.fast_forward {
+                            _ if refresh.old == refresh.new => UpdateStatus::Unchanged,
+                            Some(false) => UpdateStatus::Reset,
+                            _ => UpdateStatus::Updated,
+                        };
+                        if update.status == UpdateStatus::Reset {
+                            update.note = Some(format!("history rewritten: {} isn't in the remote's branch any more; reset to it", refresh.old));
+                        }
+                        update.old_commit = Some(refresh.old);
+                        update.new_commit = Some(refresh.new);
+                    }
+                    Err(e) => {
+                        let class = CloneErrorClass::of(&e);
+                        update.status = if class == CloneErrorClass::NotFound { UpdateStatus::Gone } else { UpdateStatus::Failed };
+                        update.note = Some(format!("{:#}", e));
+                        tracing::warn!("{}: {:#}", dir, e);
+                    }
+                }
+                update
+            })
+            .collect()
+    });
+    progress.done();
+
+    // Against the commit the outputs were analyzed at, or else the one clone recorded. Without
+    // entries, or with errors in them, --resume analyzes a repository whatever its commit.
+    let mut changed = HashSet::new();
+    for update in &mut updates {
+        let entries = analyzed.get(&update.name);
+        if let Some(new) = &update.new_commit {
+            let baseline = entries.and_then(|(commit, _)| commit.clone()).or_else(|| records.get(&update.name).map(|r| r.commit.clone())).or(update.old_commit.clone());
+            if baseline.as_ref() != Some(new) {
+                changed.insert(update.name.clone());
+            }
+        }
+        update.reanalyzed = changed.contains(&update.name) || entries.is_none_or(|(_, failed)| *failed);
+    }
+
+    let moved: Vec<CloneRecord> = updates
+        .iter()
+        .filter(|u| matches!(u.status, UpdateStatus::Updated | UpdateStatus::Reset | UpdateStatus::Gone))
+        .filter_map(|u| {
+            let job = CloneJob { name: u.repo.as_deref()?, rev: None, dest: Path::new(&opts.root).join(&u.name) };
+            let record = clone_record(&job, &CloneOutcome::Updated, &HistoryOptions::default(), false)?;
+            Some(CloneRecord { gone: u.status == UpdateStatus::Gone, ..record })
+        })
+        .collect();
+    if !moved.is_empty() {
+        write_clone_manifest(&opts.root, moved)?;
+    }
+
+    let mut changed: Vec<String> = changed.into_iter().collect();
+    changed.sort();
+    let analyze = AnalyzeOptions { resume: true, rerun_failed: true, force: changed.clone(), ..(*opts.analyze).clone() };
+    run_outputs(&opts.root, &opts.outputs, &analyze)?;
+    let mut summary = UpdateSummary::default();
+    if let Some(code) = &opts.code {
+        let collect = CollectOptions { jobs: opts.analyze.jobs, ..CollectOptions::default() };
+        summary.files_collected = recollect(&opts.root, &changed.into_iter().collect(), code, &collect)?;
+    }
+
+    summary.changelog = match &opts.changelog {
+        Some(path) => path.clone(),
+        None => Path::new(&opts.outputs).with_file_name(UPDATE_CHANGELOG_FILE).display().to_string(),
+    };
+    let file = fs::File::create(&summary.changelog).with_context(|| format!("failed to write {}", summary.changelog))?;
+    let mut w = JsonlWriter::new(file, None);
+    for update in updates.iter().filter(|u| u.status != UpdateStatus::Unchanged || u.reanalyzed) {
+        w.write_entry(update)?;
+    }
+    w.finish()?;
+    summary.records = updates;
+    Ok(summary)
+}
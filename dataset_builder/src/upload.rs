@@ -0,0 +1,472 @@
+//! `upload` and `full --upload-to`: output files and shard manifests copied to S3, an
+//! S3-compatible store or GCS (through the S3 interoperability of its XML API), in multipart
+//! uploads that a rerun skips for the objects already there with the same checksum.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use base64::Engine;
+use clap::Args;
+use hmac::{Hmac, KeyInit, Mac};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+
+use crate::github::backoff;
+use crate::shard::{is_manifest, parse_size, ShardManifest};
+use crate::shutdown::{shutdown_requested, sleep_unless_shutdown as sleep};
+
+pub const DEFAULT_PART_SIZE: u64 = 64 << 20;
+
+/// The most parts S3 takes in one upload; a file too large for that gets larger parts.
+pub const MAX_PARTS: u64 = 10_000;
+
+/// The user metadata holding an object's blake3, which a rerun compares before uploading again.
+pub const BLAKE3_METADATA: &str = "x-amz-meta-blake3";
+
+pub const GCS_ENDPOINT: &str = "https://storage.googleapis.com";
+
+/// Where and how to upload. Credentials aren't options: they come from the environment, see
+/// [`Credentials::resolve`].
+#[derive(Args, Clone, Debug)]
+pub struct StoreOptions {
+    /// An S3-compatible endpoint (MinIO, R2, Ceph, ...) instead of AWS or GCS; buckets are
+    /// addressed by path
+    #[arg(long, value_name = "URL", env = "AWS_ENDPOINT_URL")]
+    pub endpoint_url: Option<String>,
+    /// The bucket's region (default: $AWS_REGION, $AWS_DEFAULT_REGION, or us-east-1; `auto` for gs://)
+    #[arg(long, value_name = "REGION")]
+    pub region: Option<String>,
+    /// Files larger than this are uploaded in parts of this size: `64MiB`, `1GB`
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "64MiB")]
+    pub part_size: u64,
+    /// Objects and parts uploaded concurrently
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    pub upload_jobs: usize,
+    /// Retries of each request after a network error, a 5xx or a 429, with exponential backoff
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub upload_retries: u32,
+}
+
+impl Default for StoreOptions {
+    fn default() -> Self {
+        StoreOptions { endpoint_url: None, region: None, part_size: DEFAULT_PART_SIZE, upload_jobs: 4, upload_retries: 3 }
+    }
+}
+
+/// `full --upload-to`: the output files uploaded once the run succeeds.
+#[derive(Args, Clone, Debug, Default)]
+pub struct UploadTarget {
+    /// Upload the outputs and code files (or their shard manifests and shards), manifest.json and
+    /// the metadata file to `s3://bucket/prefix` or `gs://bucket/prefix` after the run
+    #[arg(long, value_name = "URL")]
+    pub upload_to: Option<String>,
+    #[command(flatten)]
+    pub store: StoreOptions,
+}
+
+/// Objects of one `upload`.
+#[derive(Debug, Default)]
+pub struct UploadSummary {
+    pub uploaded: usize,
+    /// Already in the bucket with the same size and blake3
+    pub skipped: usize,
+    /// Of the objects uploaded
+    pub bytes: u64,
+}
+
+/// `s3://bucket/prefix` or `gs://bucket/prefix`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Destination {
+    pub gcs: bool,
+    pub bucket: String,
+    /// Without leading or trailing slashes; empty for the bucket's root
+    pub prefix: String,
+}
+
+impl Destination {
+    pub fn parse(url: &str) -> anyhow::Result<Destination> {
+        let (gcs, rest) = match (url.strip_prefix("s3://"), url.strip_prefix("gs://")) {
+            (Some(rest), _) => (false, rest),
+            (_, Some(rest)) => (true, rest),
+            _ => anyhow::bail!("`{}` isn't an s3://bucket/prefix or gs://bucket/prefix URL", url),
+        };
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        anyhow::ensure!(!bucket.is_empty(), "`{}` names no bucket", url);
+        Ok(Destination { gcs, bucket: bucket.to_string(), prefix: prefix.trim_matches('/').to_string() })
+    }
+
+    /// The key of `name` under the prefix.
+    pub fn key(&self, name: &str) -> String {
+        match self.prefix.is_empty() {
+            true => name.to_string(),
+            false => format!("{}/{}", self.prefix, name),
+        }
+    }
+}
+
+/// An access key, for AWS or an S3-compatible store, or an HMAC key for GCS.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl Credentials {
+    pub fn from_env() -> anyhow::Result<Credentials> {
+        Credentials::resolve(|name| std::env::var(name).ok().filter(|v| !v.is_empty()))
+    }
+
+    /// `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN`, or else the
+    /// `$AWS_PROFILE` (default `default`) profile of `$AWS_SHARED_CREDENTIALS_FILE` (default
+    /// `~/.aws/credentials`), with `var` looking up the environment.
+    pub fn resolve(var: impl Fn(&str) -> Option<String>) -> anyhow::Result<Credentials> {
+        if let (Some(id), Some(secret)) = (var("AWS_ACCESS_KEY_ID"), var("AWS_SECRET_ACCESS_KEY")) {
+            return Ok(Credentials { access_key_id: id, secret_access_key: secret, session_token: var("AWS_SESSION_TOKEN") });
+        }
+        let file = var("AWS_SHARED_CREDENTIALS_FILE").map(PathBuf::from).or_else(|| var("HOME").map(|home| Path::new(&home).join(".aws/credentials")));
+        let profile = var("AWS_PROFILE").unwrap_or_else(|| "default".into());
+        let mut keys = HashMap::new();
+        let mut section = String::new();
+        for line in file.and_then(|f| fs::read_to_string(f).ok()).unwrap_or_default().lines().map(str::trim) {
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.trim().to_string();
+            } else if let Some((k, v)) = line.split_once('=').filter(|_| section == profile && !line.starts_with(['#', ';'])) {
+                keys.insert(k.trim().to_string(), v.trim().to_string());
+            }
+        }
+        match (keys.remove("aws_access_key_id"), keys.remove("aws_secret_access_key")) {
+            (Some(id), Some(secret)) => Ok(Credentials { access_key_id: id, secret_access_key: secret, session_token: keys.remove("aws_session_token") }),
+            _ => anyhow::bail!("no credentials for the object store: set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY (an HMAC key, for GCS), or the [{}] profile of ~/.aws/credentials", profile),
+        }
+    }
+}
+
+/// A file to upload as `key`. `listed` is true for a shard, whose blake3 is its manifest's
+/// rather than computed, and is checked against the file before it's sent.
+struct Object {
+    local: PathBuf,
+    key: String,
+    size: u64,
+    blake3: String,
+    listed: bool,
+}
+
+fn blake3_file(path: &Path) -> anyhow::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(File::open(path).with_context(|| format!("failed to read {}", path.display()))?)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encodes everything but the unreserved characters, and `/` unless `slash`, as SigV4
+/// canonical requests do.
+fn encode(s: &str, slash: bool) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            b'/' if !slash => "/".to_string(),
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// The text of the first `<tag>` element of an XML answer.
+fn xml_text<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    Some(&xml[start..start + xml[start..].find(&format!("</{}>", tag))?])
+}
+
+/// The files to upload: each file as itself, and a shard manifest as its shards (under their
+/// paths relative to it) followed by the manifest, so no reader finds a manifest whose shards
+/// aren't there yet.
+fn plan(files: &[String], dest: &Destination) -> anyhow::Result<(Vec<Object>, Vec<Object>)> {
+    let (mut data, mut manifests) = (Vec::new(), Vec::new());
+    for file in files {
+        let path = Path::new(file);
+        let name = path.file_name().with_context(|| format!("{} isn't a file", file))?.to_string_lossy();
+        let size = fs::metadata(path).with_context(|| format!("failed to read {}", file))?.len();
+        let object = Object { local: path.to_path_buf(), key: dest.key(&name), size, blake3: blake3_file(path)?, listed: false };
+        if !is_manifest(file) {
+            data.push(object);
+            continue;
+        }
+        let manifest = ShardManifest::load(file)?;
+        for (shard, local) in manifest.shards.iter().zip(manifest.files(file)) {
+            let size = fs::metadata(&local).with_context(|| format!("{} lists {}, which doesn't exist", file, local.display()))?.len();
+            anyhow::ensure!(size == shard.bytes, "{} is {} bytes where {} lists {}; `validate` the manifest", local.display(), size, file, shard.bytes);
+            data.push(Object { local, key: dest.key(&shard.path), size, blake3: shard.blake3.clone(), listed: true });
+        }
+        manifests.push(object);
+    }
+    Ok((data, manifests))
+}
+
+/// A request the store answered with a status `send` doesn't retry.
+#[derive(Debug)]
+struct Refused {
+    code: u16,
+    body: String,
+}
+
+impl std::fmt::Display for Refused {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the store answered {}: {}", self.code, self.body)
+    }
+}
+
+impl std::error::Error for Refused {}
+
+struct Store {
+    agent: ureq::Agent,
+    /// `scheme://host[:port]`
+    endpoint: String,
+    /// `bucket.host/key` rather than `host/bucket/key`
+    virtual_host: bool,
+    bucket: String,
+    region: String,
+    credentials: Credentials,
+    retries: u32,
+}
+
+impl Store {
+    fn new(dest: &Destination, opts: &StoreOptions, credentials: Credentials) -> Store {
+        let env = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let region = opts.region.clone().or_else(|| env("AWS_REGION")).or_else(|| env("AWS_DEFAULT_REGION"));
+        let region = region.unwrap_or_else(|| if dest.gcs { "auto" } else { "us-east-1" }.to_string());
+        let (endpoint, virtual_host) = match (&opts.endpoint_url, dest.gcs) {
+            (Some(url), _) => (url.trim_end_matches('/').to_string(), false),
+            (None, true) => (GCS_ENDPOINT.to_string(), false),
+            // A bucket name with dots doesn't match the wildcard certificate of its virtual host.
+            (None, false) => (format!("https://s3.{}.amazonaws.com", region), !dest.bucket.contains('.')),
+        };
+        let agent = ureq::AgentBuilder::new().timeout_connect(std::time::Duration::from_secs(30)).build();
+        Store { agent, endpoint, virtual_host, bucket: dest.bucket.clone(), region, credentials, retries: opts.upload_retries }
+    }
+
+    /// The request for `key`, signed with AWS Signature Version 4 over `payload`.
+    fn signed(&self, method: &str, key: &str, query: &[(&str, &str)], headers: &[(&str, &str)], payload: &[u8]) -> ureq::Request {
+        let (url, headers) = self.sign(chrono::Utc::now(), method, key, query, headers, payload);
+        headers.iter().fold(self.agent.request(method, &url), |r, (k, v)| r.set(k, v))
+    }
+
+    /// The URL and headers, `Authorization` included, of a request signed at `now`.
+    fn sign(&self, now: chrono::DateTime<chrono::Utc>, method: &str, key: &str, query: &[(&str, &str)], headers: &[(&str, &str)], payload: &[u8]) -> (String, Vec<(String, String)>) {
+        let (date, time) = (now.format("%Y%m%d").to_string(), now.format("%Y%m%dT%H%M%SZ").to_string());
+        let (scheme, authority) = self.endpoint.split_once("://").unwrap_or(("https", &self.endpoint));
+        let (host, path) = match self.virtual_host {
+            true => (format!("{}.{}", self.bucket, authority), encode(&format!("/{}", key), false)),
+            false => (authority.to_string(), encode(&format!("/{}/{}", self.bucket, key), false)),
+        };
+        let mut params: Vec<String> = query.iter().map(|(k, v)| format!("{}={}", encode(k, true), encode(v, true))).collect();
+        params.sort();
+        let query = params.join("&");
+        let payload_hash = hex(&Sha256::digest(payload));
+        let mut signed: Vec<(String, String)> = headers.iter().map(|(k, v)| (k.to_ascii_lowercase(), v.trim().to_string())).collect();
+        signed.extend([("host".into(), host.clone()), ("x-amz-content-sha256".into(), payload_hash.clone()), ("x-amz-date".into(), time.clone())]);
+        if let Some(token) = &self.credentials.session_token {
+            signed.push(("x-amz-security-token".into(), token.clone()));
+        }
+        signed.sort();
+        let names = signed.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>().join(";");
+        let canonical = format!("{}\n{}\n{}\n{}\n{}\n{}", method, path, query, signed.iter().map(|(k, v)| format!("{}:{}\n", k, v)).collect::<String>(), names, payload_hash);
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", time, scope, hex(&Sha256::digest(canonical.as_bytes())));
+        let key = [date.as_str(), &self.region, "s3", "aws4_request"].iter().fold(format!("AWS4{}", self.credentials.secret_access_key).into_bytes(), |k, part| hmac(&k, part.as_bytes()));
+        let authorization = format!("AWS4-HMAC-SHA256 Credential={}/{},SignedHeaders={},Signature={}", self.credentials.access_key_id, scope, names, hex(&hmac(&key, to_sign.as_bytes())));
+        signed.push(("authorization".into(), authorization));
+        (format!("{}://{}{}{}{}", scheme, host, path, if query.is_empty() { "" } else { "?" }, query), signed)
+    }
+
+    /// Sends a request signed afresh for each attempt, retrying network errors, 5xx and 429 answers.
+    fn send(&self, what: &str, method: &str, key: &str, query: &[(&str, &str)], headers: &[(&str, &str)], payload: &[u8]) -> anyhow::Result<ureq::Response> {
+        let mut attempt = 0;
+        loop {
+            let request = self.signed(method, key, query, headers, payload);
+            let sent = match method {
+                "HEAD" | "GET" => request.call(),
+                _ => request.send_bytes(payload),
+            };
+            let error = match sent {
+                Ok(response) => return Ok(response),
+                Err(ureq::Error::Status(code, response)) if code >= 500 || code == 429 => format!("the store answered {}: {}", code, response.into_string().unwrap_or_default()),
+                Err(ureq::Error::Status(code, response)) => {
+                    return Err(anyhow::Error::new(Refused { code, body: response.into_string().unwrap_or_default() }).context(what.to_string()));
+                }
+                Err(e @ ureq::Error::Transport(_)) => e.to_string(),
+            };
+            if attempt >= self.retries || shutdown_requested() {
+                anyhow::bail!("{} failed after {} attempts: {}", what, attempt + 1, error);
+            }
+            attempt += 1;
+            tracing::warn!("{}; retrying {} in {}s (attempt {} of {})", error, what, backoff(attempt - 1).as_secs(), attempt, self.retries);
+            sleep(backoff(attempt - 1));
+        }
+    }
+
+    /// The size and blake3 of the object at `key`, if there is one.
+    fn head(&self, key: &str) -> anyhow::Result<Option<(u64, Option<String>)>> {
+        match self.send(&format!("looking up {}", key), "HEAD", key, &[], &[], &[]) {
+            Ok(r) => Ok(Some((r.header("Content-Length").and_then(|l| l.parse().ok()).unwrap_or(0), r.header(BLAKE3_METADATA).map(str::to_string)))),
+            Err(e) if e.downcast_ref::<Refused>().is_some_and(|r| r.code == 404) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Uploads an object unless the bucket has it with the same size and blake3, then checks
+    /// what the bucket has; true when it was sent.
+    fn upload(&self, o: &Object, part_size: u64) -> anyhow::Result<bool> {
+        if self.head(&o.key)? == Some((o.size, Some(o.blake3.clone()))) {
+            return Ok(false);
+        }
+        if o.listed {
+            let actual = blake3_file(&o.local)?;
+            anyhow::ensure!(actual == o.blake3, "{} doesn't match the blake3 its manifest lists; `validate` the manifest before uploading", o.local.display());
+        }
+        let what = format!("uploading {}", o.local.display());
+        let meta = [(BLAKE3_METADATA, o.blake3.as_str())];
+        let part = part_size.max(o.size.div_ceil(MAX_PARTS)).max(1);
+        match o.size <= part {
+            true => self.send(&what, "PUT", &o.key, &[], &meta, &fs::read(&o.local)?).map(drop)?,
+            false => self.multipart(&what, o, part, &meta)?,
+        }
+        match self.head(&o.key)? {
+            Some((size, Some(blake3))) if size == o.size && blake3 == o.blake3 => Ok(true),
+            found => anyhow::bail!("{} didn't verify after the upload: {} bytes with blake3 {} were sent, the store has {:?}", o.key, o.size, o.blake3, found),
+        }
+    }
+
+    /// Uploads `o` in parts of `part` bytes, concurrently. An upload that fails is left open, and
+    /// the next run resumes it: only the parts the store doesn't have with the same size and
+    /// SHA-256 are sent again before it's completed.
+    fn multipart(&self, what: &str, o: &Object, part: u64, meta: &[(&str, &str)]) -> anyhow::Result<()> {
+        let (id, stored) = match self.open_upload(what, &o.key)? {
+            Some(id) => {
+                let stored = self.list_parts(what, &o.key, &id)?;
+                tracing::info!("Resuming the upload of {}, which has {} parts already", o.local.display(), stored.len());
+                (id, stored)
+            }
+            None => {
+                let headers = [meta, &[("x-amz-checksum-algorithm", "SHA256")]].concat();
+                let created = self.send(what, "POST", &o.key, &[("uploads", "")], &headers, &[])?.into_string()?;
+                let id = xml_text(&created, "UploadId").with_context(|| format!("{}: no UploadId in {}", what, created))?.to_string();
+                (id, HashMap::new())
+            }
+        };
+        let parts = o.size.div_ceil(part);
+        let sent: Vec<(String, String)> = (0..parts)
+            .into_par_iter()
+            .map(|n| {
+                anyhow::ensure!(!shutdown_requested(), "interrupted; run again to upload the rest");
+                let mut buf = vec![0; part.min(o.size - n * part) as usize];
+                let mut file = File::open(&o.local)?;
+                file.seek(SeekFrom::Start(n * part))?;
+                file.read_exact(&mut buf)?;
+                let checksum = base64::engine::general_purpose::STANDARD.encode(Sha256::digest(&buf));
+                if let Some(p) = stored.get(&(n + 1)).filter(|p| p.size == buf.len() as u64 && p.checksum.as_deref() == Some(&checksum)) {
+                    return Ok((p.etag.clone(), checksum));
+                }
+                let number = (n + 1).to_string();
+                let query = [("partNumber", number.as_str()), ("uploadId", &id)];
+                let response = self.send(&format!("{} (part {} of {})", what, n + 1, parts), "PUT", &o.key, &query, &[("x-amz-checksum-sha256", &checksum)], &buf)?;
+                let etag = response.header("ETag").map(str::to_string).with_context(|| format!("{}: no ETag for part {}", what, n + 1))?;
+                Ok((etag, checksum))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let parts: String = sent
+            .iter()
+            .enumerate()
+            .map(|(n, (etag, checksum))| format!("<Part><PartNumber>{}</PartNumber><ETag>{}</ETag><ChecksumSHA256>{}</ChecksumSHA256></Part>", n + 1, etag, checksum))
+            .collect();
+        let body = format!("<CompleteMultipartUpload>{}</CompleteMultipartUpload>", parts);
+        let answer = self.send(what, "POST", &o.key, &[("uploadId", &id)], &[("Content-Type", "application/xml")], body.as_bytes())?.into_string()?;
+        // An upload that fails to complete can still be answered 200, with an <Error> body.
+        anyhow::ensure!(!answer.contains("<Error>"), "{}: the store didn't complete the upload: {}", what, answer);
+        Ok(())
+    }
+
+    /// The newest multipart upload still open for exactly `key`, if any.
+    fn open_upload(&self, what: &str, key: &str) -> anyhow::Result<Option<String>> {
+        let listed = self.send(what, "GET", "", &[("uploads", ""), ("prefix", key)], &[], &[])?.into_string()?;
+        // Listed by key, then by when they were started.
+        let open = listed.split("<Upload>").skip(1).filter(|u| xml_text(u, "Key") == Some(key)).filter_map(|u| xml_text(u, "UploadId")).last();
+        Ok(open.map(str::to_string))
+    }
+
+    /// The parts an open upload has, by number, following `ListParts`' pages.
+    fn list_parts(&self, what: &str, key: &str, id: &str) -> anyhow::Result<HashMap<u64, StoredPart>> {
+        let mut parts = HashMap::new();
+        let mut marker = String::new();
+        loop {
+            let mut query = vec![("uploadId", id)];
+            if !marker.is_empty() {
+                query.push(("part-number-marker", &marker));
+            }
+            let page = self.send(what, "GET", key, &query, &[], &[])?.into_string()?;
+            for p in page.split("<Part>").skip(1) {
+                let (Some(number), Some(etag)) = (xml_text(p, "PartNumber").and_then(|n| n.parse().ok()), xml_text(p, "ETag")) else { continue };
+                let size = xml_text(p, "Size").and_then(|s| s.parse().ok()).unwrap_or(0);
+                parts.insert(number, StoredPart { etag: etag.replace("&quot;", "\""), size, checksum: xml_text(p, "ChecksumSHA256").map(str::to_string) });
+            }
+            match (xml_text(&page, "IsTruncated"), xml_text(&page, "NextPartNumberMarker")) {
+                (Some("true"), Some(next)) if next != marker => marker = next.to_string(),
+                _ => return Ok(parts),
+            }
+        }
+    }
+}
+
+/// A part of an open upload as `ListParts` gives it. `checksum` is its base64 SHA-256, which
+/// stores without additional checksums leave out, so their parts are all sent again.
+struct StoredPart {
+    etag: String,
+    size: u64,
+    checksum: Option<String>,
+}
+
+/// Uploads `files` under the prefix of `to`, shard manifests with their shards, using the
+/// credentials of the environment.
+pub fn upload_files(files: &[String], to: &str, opts: &StoreOptions) -> anyhow::Result<UploadSummary> {
+    upload_files_with(files, to, opts, Credentials::from_env()?)
+}
+
+/// `upload_files` with `credentials` instead of the environment's.
+pub fn upload_files_with(files: &[String], to: &str, opts: &StoreOptions, credentials: Credentials) -> anyhow::Result<UploadSummary> {
+    let dest = Destination::parse(to)?;
+    let store = Store::new(&dest, opts, credentials);
+    let (data, manifests) = plan(files, &dest)?;
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(opts.upload_jobs.max(1)).build()?;
+    let mut summary = UploadSummary::default();
+    for objects in [data, manifests] {
+        let sent = pool.install(|| {
+            objects
+                .par_iter()
+                .map(|o| {
+                    anyhow::ensure!(!shutdown_requested(), "interrupted; run again to upload the rest");
+                    store.upload(o, opts.part_size)
+                })
+                .collect::<anyhow::Result<Vec<bool>>>()
+        })?;
+        for (o, sent) in objects.iter().zip(sent) {
+            match sent {
+                true => (summary.uploaded, summary.bytes) = (summary.uploaded + 1, summary.bytes + o.size),
+                false => summary.skipped += 1,
+            }
+            tracing::info!("{} {} as {}", if sent { "Uploaded" } else { "Already uploaded:" }, o.local.display(), o.key);
+        }
+    }
+    Ok(summary)
+}
+
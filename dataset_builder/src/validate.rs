@@ -0,0 +1,286 @@
+//! `validate`: every line of a dataset file checked against the struct of its kind, duplicate
+//! keys, unfinished compressed streams, and with `--cross-check` the repositories of two files
+//! against each other.
+
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+use std::sync::Arc;
+
+use anyhow::Context;
+use clap::{Args, ValueEnum};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::analyze::OutputEntry;
+use crate::collect::CodeEntry;
+use crate::columnar::DatasetKind;
+use crate::join::key;
+use crate::jsonl::{open_jsonl, read_jsonl, replace_file, scan_jsonl};
+use crate::labels::FileLabel;
+use crate::metadata::RepoMetadata;
+use crate::schema::from_line;
+use crate::shard::{is_manifest, validate_manifest};
+
+/// How many repositories `--cross-check` lists in each direction.
+const LISTED: usize = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ValidateKind {
+    /// `code.jsonl`, keyed by `name`, `path` and the item's path
+    Code,
+    /// `outputs.jsonl`, keyed by `name` and a workspace member's `manifest_path`
+    Outputs,
+    /// `metadata.jsonl`, keyed by `name`
+    Metadata,
+    /// `labels.jsonl`, keyed by `name` and `path`
+    Labels,
+}
+
+impl ValidateKind {
+    /// The kind of a file from its first line.
+    pub fn detect(line: &Value) -> Option<ValidateKind> {
+        match DatasetKind::detect(line) {
+            Some(DatasetKind::Code) => Some(ValidateKind::Code),
+            Some(DatasetKind::Outputs) => Some(ValidateKind::Outputs),
+            None => match line {
+                Value::Object(m) if m.contains_key("counts") && m.contains_key("path") => Some(ValidateKind::Labels),
+                Value::Object(m) if m.contains_key("repo") && m.contains_key("status") => Some(ValidateKind::Metadata),
+                _ => None,
+            },
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ValidateKind::Code => "code",
+            ValidateKind::Outputs => "outputs",
+            ValidateKind::Metadata => "metadata",
+            ValidateKind::Labels => "labels",
+        }
+    }
+
+    /// The key of a line of this kind, or why it isn't one.
+    fn key(self, line: &str) -> serde_json::Result<String> {
+        Ok(match self {
+            ValidateKind::Code => {
+                let e: CodeEntry = from_line(line)?;
+                let path = e.item.as_ref().map_or(e.path.clone(), |i| format!("{}#{}", e.path, i.path));
+                format!("{}:{}", key(&e.name, &e.dir), path)
+            }
+            ValidateKind::Outputs => {
+                let o: OutputEntry = from_line(line)?;
+                let member = o.workspace_root.is_some().then_some(o.manifest_path.as_deref()).flatten();
+                member.map_or(key(&o.name, &o.dir).to_string(), |m| format!("{}:{}", key(&o.name, &o.dir), m))
+            }
+            ValidateKind::Metadata => serde_json::from_str::<RepoMetadata>(line)?.name,
+            ValidateKind::Labels => {
+                let l: FileLabel = serde_json::from_str(line)?;
+                format!("{}:{}", key(&l.name, &l.dir), l.path)
+            }
+        })
+    }
+}
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct ValidateOptions {
+    /// A JSONL file, compressed or not, or a shard manifest
+    pub file: String,
+    /// What the lines are (default: from the first line; lines of a file whose kind isn't known
+    /// are only checked to be JSON)
+    #[arg(long, value_enum)]
+    pub kind: Option<ValidateKind>,
+    /// Drop the bad lines, rewriting the file atomically
+    #[arg(long, conflicts_with = "fix")]
+    pub repair: bool,
+    /// Write a copy without the bad lines to FILE, compressed by its extension, and leave the file
+    /// as it is
+    #[arg(long, value_name = "FILE")]
+    pub fix: Option<String>,
+    /// Check that every repository of the file is in FILE and the other way round, as the code
+    /// and outputs files of a run should be
+    #[arg(long, value_name = "FILE")]
+    pub cross_check: Option<String>,
+    /// Report the errors without failing
+    #[arg(long)]
+    pub warn_only: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ValidateSummary {
+    pub kind: Option<ValidateKind>,
+    pub lines: usize,
+    /// Lines that aren't JSON, or lack their newline as an interrupted write leaves them
+    pub invalid: usize,
+    /// JSON lines that aren't an entry of `kind`
+    pub mismatched: usize,
+    /// Lines with the key of an earlier line
+    pub duplicates: usize,
+    /// The compressed stream (of some shard) ends unfinished
+    pub unfinished: bool,
+    /// Shards that are missing or don't match their manifest's checksum
+    pub bad_shards: Vec<String>,
+    /// `--repair` or `--fix` wrote the lines without the bad ones, as one finished stream
+    pub repaired: bool,
+    pub dropped: usize,
+    /// Repositories of the file that `--cross-check`'s file doesn't have, and the other way round
+    pub only_here: Vec<String>,
+    pub only_there: Vec<String>,
+}
+
+impl ValidateSummary {
+    /// Everything wrong that `--repair` or `--fix` didn't take care of.
+    pub fn errors(&self) -> usize {
+        let lines = if self.repaired { 0 } else { self.bad_lines() + usize::from(self.unfinished) };
+        lines + self.bad_shards.len() + self.only_here.len() + self.only_there.len()
+    }
+
+    pub fn bad_lines(&self) -> usize {
+        self.invalid + self.mismatched + self.duplicates
+    }
+}
+
+/// Checks one JSONL file into `summary`, with the keys seen so far (across the shards of a
+/// manifest) in `seen`. Returns whether it is good or was repaired.
+fn check_file(file: &str, opts: &ValidateOptions, seen: &mut HashMap<String, (Arc<str>, usize)>, summary: &mut ValidateSummary) -> anyhow::Result<bool> {
+    let (text, truncated) = read_jsonl(file)?;
+    let lines = scan_jsonl(&text);
+    if summary.kind.is_none() {
+        summary.kind = opts.kind.or_else(|| lines.iter().find_map(|l| l.value.as_ref()).and_then(ValidateKind::detect));
+    }
+    let here: Arc<str> = file.into();
+    let mut bad = HashSet::new();
+    for line in &lines {
+        let problem = match (&line.value, summary.kind) {
+            (None, _) => {
+                summary.invalid += 1;
+                if line.text.ends_with('\n') { "invalid JSON".to_string() } else { "truncated".to_string() }
+            }
+            (Some(_), None) => continue,
+            (Some(_), Some(kind)) => match kind.key(line.text) {
+                Err(e) => {
+                    summary.mismatched += 1;
+                    format!("not a valid {} entry: {}", kind.name(), e)
+                }
+                Ok(k) => match seen.get(&k) {
+                    Some((first, number)) => {
+                        summary.duplicates += 1;
+                        let at = if **first == *file { format!("line {}", number) } else { format!("{}:{}", first, number) };
+                        format!("duplicate of {} ({})", at, k)
+                    }
+                    None => {
+                        seen.insert(k, (here.clone(), line.number));
+                        continue;
+                    }
+                },
+            },
+        };
+        tracing::warn!("✗ {}:{}: {}", file, line.number, problem);
+        bad.insert(line.number);
+    }
+    summary.lines += lines.len();
+    if truncated {
+        tracing::warn!("✗ {}: the compressed stream ends unfinished after line {}", file, lines.len());
+        summary.unfinished = true;
+    }
+    if bad.is_empty() && !truncated && opts.fix.is_none() {
+        return Ok(true);
+    }
+    // Rewritten as one finished stream, so entries appended later are readable.
+    let good: String = lines.iter().filter(|l| !bad.contains(&l.number)).map(|l| l.text).collect();
+    if let Some(fix) = &opts.fix {
+        replace_file(fix, &good).with_context(|| format!("failed to write {}", fix))?;
+        tracing::info!("✓ Wrote {} without its {} bad lines to {}", file, bad.len(), fix);
+    } else if opts.repair {
+        replace_file(file, &good)?;
+        tracing::info!("✓ Dropped {} bad lines from {}", bad.len(), file);
+    } else {
+        return Ok(false);
+    }
+    summary.repaired = true;
+    summary.dropped += bad.len();
+    Ok(true)
+}
+
+#[derive(Deserialize)]
+struct Named {
+    name: String,
+    #[serde(default)]
+    dir: String,
+}
+
+/// The repositories of a file, by checkout directory as `join` matches them; bad lines are
+/// left out, as they are reported by the line checks.
+fn repositories(file: &str) -> anyhow::Result<HashSet<String>> {
+    let mut repos = HashSet::new();
+    for line in open_jsonl(file)?.lines() {
+        let line = line.with_context(|| format!("failed to read {}", file))?;
+        if let Ok(n) = serde_json::from_str::<Named>(&line) {
+            repos.insert(key(&n.name, &n.dir).to_string());
+        }
+    }
+    Ok(repos)
+}
+
+fn list(what: &str, repos: &[String]) {
+    for r in repos.iter().take(LISTED) {
+        tracing::warn!("✗ {}: {}", r, what);
+    }
+    if repos.len() > LISTED {
+        tracing::warn!("✗ ... and {} more {}", repos.len() - LISTED, what);
+    }
+}
+
+/// Validates `opts.file`, or every shard of a manifest. Fails on any error `--repair` or `--fix`
+/// didn't take care of, unless `--warn-only`.
+pub fn validate_file(opts: &ValidateOptions) -> anyhow::Result<ValidateSummary> {
+    let mut summary = ValidateSummary::default();
+    let mut seen = HashMap::new();
+    if is_manifest(&opts.file) {
+        anyhow::ensure!(opts.fix.is_none(), "--fix writes a copy of a JSONL file; --repair rewrites the bad shards of {}", opts.file);
+        summary.bad_shards = validate_manifest(&opts.file, opts.repair, |shard| check_file(shard, opts, &mut seen, &mut summary))?;
+    } else {
+        check_file(&opts.file, opts, &mut seen, &mut summary)?;
+        let kind = summary.kind.map_or(String::new(), |k| format!(" of {} entries", k.name()));
+        tracing::info!("{}: {} lines{}, {} valid, {} bad", opts.file, summary.lines, kind, summary.lines - summary.bad_lines(), summary.bad_lines());
+    }
+    if let Some(other) = &opts.cross_check {
+        let (here, there) = (repositories(&opts.file)?, repositories(other)?);
+        summary.only_here = here.difference(&there).cloned().collect();
+        summary.only_there = there.difference(&here).cloned().collect();
+        summary.only_here.sort();
+        summary.only_there.sort();
+        list(&format!("in {} but not in {}", opts.file, other), &summary.only_here);
+        list(&format!("in {} but not in {}", other, opts.file), &summary.only_there);
+        tracing::info!("{} and {}: {} repositories in both, {} in only one", opts.file, other, here.len() - summary.only_here.len(), summary.only_here.len() + summary.only_there.len());
+    }
+    let errors = summary.errors();
+    if errors > 0 {
+        let mut what = Vec::new();
+        let lines = if summary.repaired { 0 } else { summary.bad_lines() };
+        if lines > 0 {
+            what.push(format!("{} bad lines", lines));
+        }
+        if summary.unfinished && !summary.repaired {
+            what.push("an unfinished compressed stream".into());
+        }
+        if !summary.bad_shards.is_empty() {
+            what.push(format!("bad shards: {}", summary.bad_shards.join(", ")));
+        }
+        if !summary.only_here.is_empty() || !summary.only_there.is_empty() {
+            what.push(format!("{} repositories missing from the other file", summary.only_here.len() + summary.only_there.len()));
+        }
+        let hint = if lines > 0 || summary.unfinished && !summary.repaired { "; rerun with --repair to drop the bad lines, or --fix <copy>" } else { "" };
+        let message = format!("{} has {}{}", opts.file, what.join(" and "), hint);
+        if !opts.warn_only {
+            anyhow::bail!(message);
+        }
+        tracing::warn!("{}", message);
+    }
+    Ok(summary)
+}
+
+/// `validate` without a kind, cross-check or copy: the lines are checked against the kind of
+/// the first one, if it is known.
+pub fn validate_jsonl(file: &str, repair: bool) -> anyhow::Result<()> {
+    validate_file(&ValidateOptions { file: file.to_string(), repair, ..ValidateOptions::default() }).map(|_| ())
+}
@@ -0,0 +1,217 @@
+//! Workspace members and the resolved dependency graph, from `cargo metadata`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::tool::run_cmd;
+
+#[derive(Deserialize)]
+pub(crate) struct CargoMetadata {
+    pub(crate) packages: Vec<MetadataPackage>,
+    pub(crate) workspace_members: Vec<String>,
+    pub(crate) workspace_root: PathBuf,
+    /// Absent with `--no-deps`
+    pub(crate) resolve: Option<MetadataResolve>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct MetadataPackage {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) version: String,
+    /// `None` for path dependencies and workspace members
+    pub(crate) source: Option<String>,
+    pub(crate) manifest_path: PathBuf,
+    pub(crate) dependencies: Vec<MetadataDependency>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct MetadataDependency {
+    pub(crate) name: String,
+    pub(crate) rename: Option<String>,
+    pub(crate) kind: Option<String>,
+    pub(crate) optional: bool,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct MetadataResolve {
+    pub(crate) nodes: Vec<ResolveNode>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ResolveNode {
+    pub(crate) id: String,
+    pub(crate) deps: Vec<NodeDep>,
+    pub(crate) features: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct NodeDep {
+    /// Name the dependency is imported under (its rename, with `-` as `_`)
+    pub(crate) name: String,
+    pub(crate) pkg: String,
+    pub(crate) dep_kinds: Vec<DepKindInfo>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct DepKindInfo {
+    pub(crate) kind: Option<String>,
+}
+
+/// A workspace member crate; paths are relative to the checkout.
+pub struct Member {
+    pub id: String,
+    pub name: String,
+    pub manifest_path: String,
+    pub dir: String,
+}
+
+pub(crate) struct Workspace {
+    /// Workspace root relative to the checkout, usually `.`
+    pub(crate) root: String,
+    pub(crate) members: Vec<Member>,
+    /// More than one member, or a virtual manifest at the root
+    pub(crate) is_workspace: bool,
+    pub(crate) metadata: Option<CargoMetadata>,
+}
+
+/// The `Cargo.toml` files of a checkout, relative to it: the root's, then those of its first-level
+/// subdirectories, in name order. The first is the project that is analyzed.
+pub(crate) fn find_manifests(path: &Path) -> Vec<String> {
+    let mut subdirs: Vec<String> = fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .filter(|name| !name.starts_with('.') && name != "target" && path.join(name).join("Cargo.toml").is_file())
+        .collect();
+    subdirs.sort();
+    let root = path.join("Cargo.toml").is_file().then(|| "Cargo.toml".to_string());
+    root.into_iter().chain(subdirs.into_iter().map(|dir| format!("{}/Cargo.toml", dir))).collect()
+}
+
+/// Lists the workspace members with `cargo metadata`; `None` if the manifest can't be read.
+/// Falls back to `--no-deps` (no `dep_graph`) when dependencies can't be resolved.
+pub(crate) fn cargo_workspace(path: &Path, toolchain: Option<&str>, vars: &[(String, String)], deadline: Option<Instant>) -> Option<Workspace> {
+    let metadata = |extra: &[&str]| -> Option<CargoMetadata> {
+        let args = [&["metadata", "--format-version", "1"], extra].concat();
+        let out = run_cmd(path, toolchain, &args, vars, deadline).ok()?;
+        if out.exit_code != Some(0) {
+            return None;
+        }
+        serde_json::from_str(&out.stdout).ok()
+    };
+    let meta = metadata(&[]).or_else(|| metadata(&["--no-deps"]))?;
+    let checkout = fs::canonicalize(path).ok()?;
+    let relative = |p: &Path| {
+        let rel = p.strip_prefix(&checkout).unwrap_or(p).to_string_lossy().into_owned();
+        if rel.is_empty() { ".".to_string() } else { rel }
+    };
+    let members: Vec<Member> = meta.packages.iter()
+        .filter(|p| meta.workspace_members.contains(&p.id))
+        .map(|p| Member {
+            id: p.id.clone(),
+            name: p.name.clone(),
+            manifest_path: relative(&p.manifest_path),
+            dir: relative(p.manifest_path.parent().unwrap_or(&p.manifest_path)),
+        })
+        .collect();
+    let is_workspace = members.len() > 1 || members.iter().all(|m| m.dir != ".");
+    let root = relative(&meta.workspace_root);
+    Some(Workspace { root, members, is_workspace, metadata: meta.resolve.is_some().then_some(meta) })
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DepGraph {
+    /// Every resolved package; two versions of one crate are two nodes
+    pub nodes: Vec<DepNode>,
+    pub edges: Vec<DepEdge>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DepNode {
+    pub name: String,
+    pub version: String,
+    /// Registry or git URL; absent for path dependencies
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Features enabled by the resolver
+    pub features: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DepEdge {
+    /// Indices into `nodes`
+    pub from: usize,
+    pub to: usize,
+    /// `normal`, `dev`, or `build`
+    pub kind: String,
+    pub optional: bool,
+}
+
+/// The part of the resolved graph reachable from `root` (the whole graph when `None`). Dev
+/// dependencies are only followed from the root, the way cargo resolves them.
+pub(crate) fn dep_graph(meta: &CargoMetadata, root: Option<&str>) -> Option<DepGraph> {
+    let resolve = meta.resolve.as_ref()?;
+    let packages: HashMap<&str, &MetadataPackage> = meta.packages.iter().map(|p| (p.id.as_str(), p)).collect();
+    let nodes: HashMap<&str, &ResolveNode> = resolve.nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+
+    let mut order: Vec<&str> = match root {
+        Some(id) => vec![id],
+        None => resolve.nodes.iter().map(|n| n.id.as_str()).collect(),
+    };
+    let mut index: HashMap<&str, usize> = order.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+    let mut edges = Vec::new();
+    let mut next = 0;
+    while next < order.len() {
+        let from = order[next];
+        next += 1;
+        let (Some(node), Some(pkg)) = (nodes.get(from), packages.get(from)) else { continue };
+        for dep in &node.deps {
+            let mut kinds: Vec<&str> = dep.dep_kinds.iter().map(|k| k.kind.as_deref().unwrap_or("normal")).collect();
+            kinds.sort_unstable();
+            kinds.dedup();
+            for kind in kinds {
+                if kind == "dev" && root.is_some_and(|r| r != from) {
+                    continue;
+                }
+                let to = *index.entry(dep.pkg.as_str()).or_insert_with(|| {
+                    order.push(dep.pkg.as_str());
+                    order.len() - 1
+                });
+                let optional = pkg.dependencies.iter().any(|d| {
+                    d.optional && d.kind.as_deref().unwrap_or("normal") == kind
+                        && d.rename.as_ref().unwrap_or(&d.name).replace('-', "_") == dep.name
+                });
+                edges.push(DepEdge { from: index[from], to, kind: kind.to_string(), optional });
+            }
+        }
+    }
+    let nodes = order.iter().filter_map(|id| {
+        let pkg = packages.get(id)?;
+        Some(DepNode {
+            name: pkg.name.clone(),
+            version: pkg.version.clone(),
+            source: pkg.source.clone(),
+            features: nodes.get(id).map(|n| n.features.clone()).unwrap_or_default(),
+        })
+    }).collect();
+    Some(DepGraph { nodes, edges })
+}
+
+/// Channel pinned by the repo's `rust-toolchain.toml` or legacy `rust-toolchain` file.
+pub(crate) fn pinned_toolchain(repo: &Path) -> Option<String> {
+    ["rust-toolchain.toml", "rust-toolchain"].iter().find_map(|f| {
+        let text = fs::read_to_string(repo.join(f)).ok()?;
+        let channel = text.lines().find_map(|l| {
+            let (key, value) = l.split_once('=')?;
+            (key.trim() == "channel").then(|| value.trim().trim_matches('"').to_string())
+        });
+        // The legacy file may be just the channel name on its own.
+        channel.or_else(|| Some(text.trim().to_string()).filter(|t| !t.is_empty() && !t.contains('[')))
+    })
+}
@@ -0,0 +1,173 @@
+mod common;
+
+use dataset_builder::{analyze_repo, select_analyzers, AnalyzeOptions, Report, RunManifest, SandboxKind, SandboxOptions, ToolStatus};
+
+fn split(opts: AnalyzeOptions) -> (Vec<String>, Vec<String>) {
+    let selection = select_analyzers(&opts).unwrap();
+    (selection.run.iter().map(|a| a.name().to_string()).collect(), selection.skipped)
+}
+
+#[test]
+fn only_and_skip_leave_the_rest_as_skipped() {
+    let (run, skipped) = split(AnalyzeOptions { only: vec!["clippy".into(), "geiger".into()], ..AnalyzeOptions::default() });
+    assert_eq!(run, ["clippy", "geiger"]);
+    assert_eq!(skipped, ["check", "fmt", "audit", "auditable", "deny", "tree", "test", "udeps", "outdated", "machete", "tokei", "complexity", "vet", "crev", "expand", "ast", "hir", "mir", "semgrep", "codeql"]);
+
+    let (run, skipped) = split(AnalyzeOptions { skip: vec!["codeql".into(), "semgrep".into()], ..AnalyzeOptions::default() });
+    assert_eq!(run, ["check", "clippy", "fmt", "audit", "auditable", "deny", "geiger", "ast"]);
+    assert_eq!(skipped, ["tree", "test", "udeps", "outdated", "machete", "tokei", "complexity", "vet", "crev", "expand", "hir", "mir", "semgrep", "codeql"]);
+
+    let (run, _) = split(AnalyzeOptions { only: vec!["clippy".into()], include_tree_text: true, ..AnalyzeOptions::default() });
+    assert_eq!(run, ["clippy", "tree"]);
+
+    let (run, _) = split(AnalyzeOptions { only: vec!["check".into()], emit: vec!["mir".into(), "hir".into()], ..AnalyzeOptions::default() });
+    assert_eq!(run, ["check", "hir", "mir"]);
+}
+
+#[test]
+fn unknown_names_are_rejected_with_a_suggestion() {
+    let Err(err) = select_analyzers(&AnalyzeOptions { skip: vec!["codql".into()], ..AnalyzeOptions::default() }) else { panic!() };
+    let err = err.to_string();
+    assert!(err.contains("--skip: unknown analyzer `codql`; did you mean `codeql`?"), "{}", err);
+
+    let Err(err) = select_analyzers(&AnalyzeOptions { only: vec!["pylint".into()], ..AnalyzeOptions::default() }) else { panic!() };
+    assert!(!err.to_string().contains("did you mean"), "{}", err);
+}
+
+#[test]
+fn sandbox_limits_need_docker() {
+    let mut opts = AnalyzeOptions::default();
+    opts.sandbox.sandbox = SandboxKind::Bwrap;
+    opts.sandbox.sandbox_memory = Some("4g".into());
+    let err = opts.validate().unwrap_err().to_string();
+    assert!(err.contains("--sandbox docker"), "{}", err);
+    assert!(AnalyzeOptions::default().validate().is_ok());
+}
+
+#[cfg(unix)]
+#[test]
+fn bwrap_starts_tools_with_only_the_variables_it_sets() {
+    let base = std::env::temp_dir().join(format!("dataset_builder_bwrap_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(&base).unwrap();
+    // Prints the arguments it was started with, one per line, instead of starting a sandbox.
+    let tools = common::fake_tools(&[("bwrap", "#!/bin/sh\nprintf '%s\\n' \"$@\"\n")]);
+    let config = base.join("tools.toml");
+    std::fs::write(&config, "[[tool]]\nname = \"env\"\ncommand = \"env\"\n").unwrap();
+    let repo = base.join("repo");
+    std::fs::create_dir_all(&repo).unwrap();
+    let mut opts = AnalyzeOptions { tools_config: Some(config.display().to_string()), only: vec!["env".into()], tool_env: tools.env(), ..AnalyzeOptions::default() };
+    opts.sandbox.sandbox = SandboxKind::Bwrap;
+
+    let entries = analyze_repo(&repo, "repo", &select_analyzers(&opts).unwrap(), &opts, &RunManifest::capture(&opts));
+    let _ = std::fs::remove_dir_all(&base);
+
+    let args: Vec<&str> = entries[0].tools["env"].result.stdout.lines().collect();
+    assert!(args.contains(&"--clearenv"), "{:?}", args);
+    let set: Vec<&str> = args.windows(2).filter(|w| w[0] == "--setenv").map(|w| w[1]).collect();
+    assert_eq!(&set[..4], ["HOME", "CARGO_HOME", "CARGO_NET_OFFLINE", "PATH"]);
+    assert!(set.iter().all(|v| ["HOME", "PATH", "RUSTUP_HOME", "RUSTUP_TOOLCHAIN", "CARGO_HOME", "CARGO_NET_OFFLINE", "CARGO_BUILD_JOBS", "RUSTC_WRAPPER"].contains(v)), "{:?}", set);
+    assert_eq!(args.last(), Some(&"env"));
+    let home = std::path::PathBuf::from(std::env::var("HOME").unwrap());
+    let read_only: Vec<&str> = args.windows(2).filter(|w| w[0] == "--ro-bind-try").map(|w| w[1]).collect();
+    assert!(read_only.contains(&"/usr") && !read_only.iter().any(|d| home.starts_with(d)), "{:?}", read_only);
+}
+
+#[cfg(unix)]
+#[test]
+fn bwrap_tools_cannot_read_the_home_directory() {
+    let sandbox = SandboxOptions { sandbox: SandboxKind::Bwrap, ..SandboxOptions::default() };
+    if sandbox.check().is_err() {
+        eprintln!("bwrap can't start sandboxes here; skipping");
+        return;
+    }
+    let base = std::env::temp_dir().join(format!("dataset_builder_bwrap_home_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(base.join("repo")).unwrap();
+    let secret = std::path::PathBuf::from(std::env::var("HOME").unwrap()).join(format!(".dataset_builder_sandbox_probe_{}", std::process::id()));
+    std::fs::write(&secret, "home-secret\n").unwrap();
+    let config = base.join("tools.toml");
+    std::fs::write(&config, format!("[[tool]]\nname = \"cat\"\ncommand = \"cat\"\nargs = [\"{}\"]\n", secret.display())).unwrap();
+    let opts = AnalyzeOptions { tools_config: Some(config.display().to_string()), only: vec!["cat".into()], sandbox, ..AnalyzeOptions::default() };
+
+    let entries = analyze_repo(&base.join("repo"), "repo", &select_analyzers(&opts).unwrap(), &opts, &RunManifest::capture(&opts));
+    let _ = std::fs::remove_file(&secret);
+    let _ = std::fs::remove_dir_all(&base);
+
+    let cat = &entries[0].tools["cat"].result;
+    assert!(!cat.stdout.contains("home-secret") && cat.exit_code != Some(0), "{:?}", cat);
+}
+
+#[test]
+fn unfetchable_dependencies_mark_dependent_tools_fetch_failed() {
+    let repo = std::env::temp_dir().join(format!("dataset_builder_fetch_{}", std::process::id()));
+    std::fs::create_dir_all(repo.join("src")).unwrap();
+    let manifest = "[package]\nname = \"nofetch\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\ngone = { path = \"../gone\" }\n";
+    std::fs::write(repo.join("Cargo.toml"), manifest).unwrap();
+    std::fs::write(repo.join("src/main.rs"), "fn main() {}\n").unwrap();
+
+    let opts = AnalyzeOptions { only: vec!["clippy".into(), "fmt".into()], ..AnalyzeOptions::default() };
+    let entries = analyze_repo(&repo, "nofetch", &select_analyzers(&opts).unwrap(), &opts, &RunManifest::capture(&opts));
+    let _ = std::fs::remove_dir_all(&repo);
+
+    let entry = &entries[0];
+    assert!(entry.fetch_failed && entry.fetch.as_ref().is_some_and(|f| f.exit_code != Some(0)));
+    assert!(matches!(entry.tools["clippy"].result.status, ToolStatus::FetchFailed));
+    assert!(!matches!(entry.tools["fmt"].result.status, ToolStatus::FetchFailed));
+}
+
+#[test]
+fn shared_target_dir_is_reused_across_runs() {
+    let base = std::env::temp_dir().join(format!("dataset_builder_shared_{}", std::process::id()));
+    let repo = base.join("scratch");
+    std::fs::create_dir_all(repo.join("src")).unwrap();
+    std::fs::write(repo.join("Cargo.toml"), "[package]\nname = \"scratch\"\nversion = \"0.1.0\"\nedition = \"2021\"\n").unwrap();
+    std::fs::write(repo.join("src/main.rs"), "fn main() {}\n").unwrap();
+
+    let target = base.join("target");
+    let opts = AnalyzeOptions { only: vec!["clippy".into()], shared_target_dir: Some(target.display().to_string()), ..AnalyzeOptions::default() };
+    let run = RunManifest::capture(&opts);
+    let units = |report: &Option<Report>| match report {
+        Some(Report::Clippy(r)) => (r.units_fresh, r.units_built),
+        other => panic!("no clippy report: {:?}", other),
+    };
+    let first = analyze_repo(&repo, "scratch", &select_analyzers(&opts).unwrap(), &opts, &run);
+    let second = analyze_repo(&repo, "scratch", &select_analyzers(&opts).unwrap(), &opts, &run);
+    let built_in_checkout = repo.join("target").exists();
+    let built_in_shared = target.join("debug").is_dir();
+    let _ = std::fs::remove_dir_all(&base);
+
+    assert_eq!(run.shared_target_dir.as_deref(), Some(target.to_str().unwrap()));
+    assert!(built_in_shared && !built_in_checkout);
+    assert_eq!(units(&first[0].tools["clippy"].report), (0, 1));
+    assert_eq!(units(&second[0].tools["clippy"].report), (1, 0));
+}
+
+#[test]
+fn the_cargo_project_is_found_one_level_down_or_marked_missing() {
+    let base = std::env::temp_dir().join(format!("dataset_builder_project_{}", std::process::id()));
+    let (nested, docs) = (base.join("nested"), base.join("docs"));
+    for krate in ["app", "tools"] {
+        std::fs::create_dir_all(nested.join(krate).join("src")).unwrap();
+        std::fs::write(nested.join(krate).join("Cargo.toml"), format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n", krate)).unwrap();
+        std::fs::write(nested.join(krate).join("src/main.rs"), "fn main() {}\n").unwrap();
+    }
+    std::fs::create_dir_all(&docs).unwrap();
+    std::fs::write(docs.join("README.md"), "# Mirror of the docs\n").unwrap();
+
+    let opts = AnalyzeOptions { only: vec!["fmt".into(), "semgrep".into()], ..AnalyzeOptions::default() };
+    let selection = select_analyzers(&opts).unwrap();
+    let run = RunManifest::capture(&opts);
+    let nested = analyze_repo(&nested, "nested", &selection, &opts, &run);
+    let docs = analyze_repo(&docs, "docs", &selection, &opts, &run);
+    let _ = std::fs::remove_dir_all(&base);
+
+    let entry = &nested[0];
+    assert_eq!((entry.project_manifest.as_deref(), entry.manifest_path.as_deref()), (Some("app/Cargo.toml"), Some("app/Cargo.toml")));
+    assert_eq!(entry.other_manifests, ["tools/Cargo.toml"]);
+    assert!(!entry.not_a_cargo_project && entry.tools["fmt"].result.exit_code == Some(0), "{:?}", entry.tools["fmt"]);
+    let entry = &docs[0];
+    assert!(entry.not_a_cargo_project && entry.project_manifest.is_none() && entry.fetch.is_none());
+    assert!(matches!(entry.tools["fmt"].result.status, ToolStatus::NotACargoProject));
+    assert!(!matches!(entry.tools["semgrep"].result.status, ToolStatus::NotACargoProject), "semgrep doesn't need one");
+}
@@ -0,0 +1,61 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use dataset_builder::{cache_gc, clean_head, CacheKey, ResultCache, ToolOutput, ToolResult, ToolStatus};
+
+fn scratch(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_cache_{}_{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn key(tool: &str) -> CacheKey {
+    CacheKey { head: "0123abcd".into(), tool: tool.into(), version: Some("clippy 0.1.80".into()), args: vec!["raw=false".into()] }
+}
+
+#[test]
+fn hits_are_marked_cached_and_gc_prunes_them() {
+    let dir = scratch("hits");
+    let cache = ResultCache::new(&dir);
+    let result: ToolResult = serde_json::from_value(serde_json::json!({ "status": "completed", "exit_code": 0, "stdout": "ok" })).unwrap();
+    let output: ToolOutput = result.into();
+    cache.put(&key("clippy"), &output, 1234).unwrap();
+
+    let (hit, ms) = cache.get(&key("clippy")).expect("cache hit");
+    assert!(hit.cached && ms == 1234);
+    assert!(matches!(hit.result.status, ToolStatus::Completed) && hit.result.stdout == "ok");
+    assert!(cache.get(&CacheKey { version: Some("clippy 0.1.81".into()), ..key("clippy") }).is_none());
+    assert!(cache.get(&key("fmt")).is_none());
+
+    assert_eq!(cache_gc(&dir.display().to_string(), Duration::from_secs(3600)).unwrap(), 0);
+    assert_eq!(cache_gc(&dir.display().to_string(), Duration::ZERO).unwrap(), 1);
+    assert!(cache.get(&key("clippy")).is_none());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn only_clean_checkouts_have_a_head_to_cache_by() {
+    let dir = scratch("head");
+    assert_eq!(clean_head(&dir), None, "not a git repository");
+
+    let repo = git2::Repository::init(&dir).unwrap();
+    fs::write(dir.join("Cargo.toml"), "[package]\nname = \"demo\"\n").unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path("Cargo.toml".as_ref()).unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let sig = git2::Signature::now("test", "test@example.com").unwrap();
+    let commit = repo.commit(Some("HEAD"), &sig, &sig, "init", &tree, &[]).unwrap();
+
+    // What the tools leave behind doesn't make the tree dirty.
+    fs::write(dir.join("Cargo.lock"), "").unwrap();
+    fs::create_dir_all(dir.join("target/debug")).unwrap();
+    fs::write(dir.join("target/debug/demo"), "").unwrap();
+    assert_eq!(clean_head(&dir), Some(commit.to_string()));
+
+    fs::write(dir.join("Cargo.toml"), "[package]\nname = \"changed\"\n").unwrap();
+    assert_eq!(clean_head(&dir), None);
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,57 @@
+use std::fs;
+use std::path::Path;
+
+use dataset_builder::parse::{parse_nextest, TestReport};
+use dataset_builder::{analyze_repo, select_analyzers, AnalyzeOptions, Report, RunManifest, ToolResult, ToolStatus};
+
+fn test_report(base: &Path, name: &str, lib: &str) -> TestReport {
+    let dir = base.join(name);
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(dir.join("Cargo.toml"), format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n", name)).unwrap();
+    fs::write(dir.join("src/lib.rs"), lib).unwrap();
+    let opts = AnalyzeOptions { only: vec!["clippy".into()], with_tests: true, ..AnalyzeOptions::default() };
+    let mut entries = analyze_repo(&dir, name, &select_analyzers(&opts).unwrap(), &opts, &RunManifest::capture(&opts));
+    match entries.remove(0).tools.remove("test").unwrap().report {
+        Some(Report::Test(r)) => r,
+        other => panic!("no test report: {:?}", other),
+    }
+}
+
+#[test]
+fn with_tests_counts_outcomes_and_tells_no_tests_from_no_build() {
+    let base = std::env::temp_dir().join(format!("dataset_builder_cargo_test_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&base);
+    let tests = "pub fn f() {}\n\n#[cfg(test)]\nmod tests {\n    #[test]\n    fn passes() {}\n    #[test]\n    fn also_passes() {}\n    #[test]\n    fn fails() { panic!(\"no\") }\n    #[test]\n    #[ignore]\n    fn slow() {}\n}\n";
+
+    let mixed = test_report(&base, "mixed", tests);
+    let none = test_report(&base, "none", "pub fn f() {}\n");
+    let broken = test_report(&base, "broken", "pub fn f() -> u32 { \"no\" }\n");
+    let _ = fs::remove_dir_all(&base);
+
+    assert_eq!((mixed.runner.as_str(), mixed.compiled), ("cargo-test", true));
+    assert_eq!((mixed.tests, mixed.passed, mixed.failed, mixed.ignored), (4, 2, 1, 1));
+    assert_eq!(mixed.failing, ["tests::fails"]);
+    assert!(none.compiled && none.tests == 0 && none.failing.is_empty());
+    assert!(!broken.compiled && broken.tests == 0);
+}
+
+#[test]
+fn nextest_libtest_json_is_counted() {
+    let stdout = r#"{"type":"suite","event":"started","test_count":3,"nextest":{"crate":"a","test_binary":"a","kind":"lib"}}
+{"type":"test","event":"started","name":"a::lib$tests::passes"}
+{"type":"test","name":"a::lib$tests::passes","event":"ok","exec_time":0.001}
+{"type":"test","name":"a::lib$tests::fails","event":"failed","exec_time":0.002,"stdout":"thread panicked"}
+{"type":"test","name":"a::lib$tests::slow","event":"ignored"}
+{"type":"suite","event":"failed","passed":1,"failed":1,"ignored":1}
+"#;
+    let result = |exit_code, stdout: &str| ToolResult { status: ToolStatus::Completed, exit_code: Some(exit_code), stdout: stdout.into(), stderr: String::new(), error: None, parse_error: false, truncated: false, usage: None };
+
+    let report = parse_nextest(&result(100, stdout));
+    let empty = parse_nextest(&result(4, ""));
+    let unbuilt = parse_nextest(&result(101, ""));
+
+    assert_eq!((report.tests, report.passed, report.failed, report.ignored, report.compiled), (3, 1, 1, 1, true));
+    assert_eq!(report.failing, ["a::lib$tests::fails"]);
+    assert!(empty.compiled && empty.tests == 0);
+    assert!(!unbuilt.compiled);
+}
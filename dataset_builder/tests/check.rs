@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::Path;
+
+use dataset_builder::{analyze_repo, select_analyzers, AnalyzeOptions, OutputEntry, Report, RunManifest, ToolStatus};
+
+fn analyze(base: &Path, name: &str, lib: &str, opts: &AnalyzeOptions) -> OutputEntry {
+    let dir = base.join(name);
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(dir.join("Cargo.toml"), format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n", name)).unwrap();
+    fs::write(dir.join("src/lib.rs"), lib).unwrap();
+    analyze_repo(&dir, name, &select_analyzers(opts).unwrap(), opts, &RunManifest::capture(opts)).remove(0)
+}
+
+fn status(entry: &OutputEntry, tool: &str) -> ToolStatus {
+    entry.tools[tool].result.status.clone()
+}
+
+#[test]
+fn a_crate_that_doesnt_build_skips_the_tools_that_build_it() {
+    let base = std::env::temp_dir().join(format!("dataset_builder_check_broken_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&base);
+    let opts = AnalyzeOptions { only: vec!["check".into(), "clippy".into(), "fmt".into()], with_tests: true, ..AnalyzeOptions::default() };
+    let broken = "pub fn f() -> u32 { \"no\" }\npub fn g() -> u8 { 1u32 }\n";
+
+    let skipped = analyze(&base, "broken", broken, &opts);
+    let kept_going = analyze(&base, "broken", broken, &AnalyzeOptions { keep_going_on_build_failure: true, ..opts.clone() });
+    let _ = fs::remove_dir_all(&base);
+
+    let Some(Report::Check(check)) = &skipped.tools["check"].report else { panic!("no check report") };
+    assert!(!check.builds);
+    assert_eq!((check.errors, check.error_codes.as_slice()), (2, ["E0308".to_string()].as_slice()));
+    assert!(matches!(status(&skipped, "clippy"), ToolStatus::SkippedBuildFailed) && matches!(status(&skipped, "test"), ToolStatus::SkippedBuildFailed));
+    assert!(matches!(status(&skipped, "fmt"), ToolStatus::Completed), "fmt doesn't build the crate");
+    assert_eq!(skipped.time_ms["clippy"], 0);
+    assert!(skipped.errors.is_empty(), "{:?}", skipped.errors);
+    assert!(matches!(&kept_going.tools["clippy"].report, Some(Report::Clippy(c)) if c.compile_failed));
+}
+
+#[test]
+fn a_crate_that_builds_is_checked_and_timed() {
+    let base = std::env::temp_dir().join(format!("dataset_builder_check_ok_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&base);
+    let opts = AnalyzeOptions { only: vec!["check".into(), "clippy".into()], ..AnalyzeOptions::default() };
+
+    let entry = analyze(&base, "fine", "pub fn f() { let unused = 1; }\n", &opts);
+    let _ = fs::remove_dir_all(&base);
+
+    let Some(Report::Check(check)) = &entry.tools["check"].report else { panic!("no check report") };
+    assert!(check.builds && check.errors == 0 && check.error_codes.is_empty());
+    assert_eq!(check.warnings, 1);
+    assert!(entry.time_ms.contains_key("check"));
+    assert!(matches!(status(&entry, "clippy"), ToolStatus::Completed));
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use dataset_builder::{clean_dataset, CleanKind, CleanOptions};
+
+/// Two checkouts with build output, a CodeQL database and history, and a file of the run.
+fn datasets(test: &str) -> PathBuf {
+    let mut files = Vec::new();
+    for repo in ["repo_a", "repo_b"] {
+        let file = |path: &str, content: Vec<u8>| (format!("datasets/{}/{}", repo, path), content);
+        files.push(file("Cargo.toml", b"[package]\nname = \"a\"\n".to_vec()));
+        files.push(file("src/lib.rs", b"pub fn f() {}\n".to_vec()));
+        files.push(file("target/debug/deps/liba.rlib", vec![0u8; 1000]));
+        files.push(file(".git/objects/pack", vec![0u8; 300]));
+    }
+    files.push(("datasets/repo_a/.codeql-db/db-rust/data".into(), vec![0u8; 50]));
+    // Not cargo's: no Cargo.toml beside it.
+    files.push(("datasets/repo_b/docs/target/index.md".into(), b"# docs\n".to_vec()));
+    files.push(("datasets/outputs.jsonl".into(), b"{}\n".to_vec()));
+    common::scratch(test, &files)
+}
+
+fn opts(root: &Path, what: &[CleanKind]) -> CleanOptions {
+    CleanOptions { root: root.join("datasets").display().to_string(), what: what.to_vec(), ..CleanOptions::default() }
+}
+
+#[test]
+fn targets_and_codeql_databases_are_removed_after_a_dry_run_lists_them() {
+    let root = datasets("clean_targets");
+    fs::create_dir_all(root.join("outside/target")).unwrap();
+    fs::write(root.join("outside/target/keep"), "x").unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(root.join("outside/target"), root.join("datasets/repo_a/src/target")).unwrap();
+    let options = opts(&root, &[CleanKind::Targets, CleanKind::Codeql]);
+
+    let dry = clean_dataset(&CleanOptions { dry_run: true, ..options.clone() }).unwrap();
+    let still_there = root.join("datasets/repo_a/target/debug/deps/liba.rlib").exists();
+    let cleaned = clean_dataset(&options).unwrap();
+    let gone = ["repo_a/target", "repo_b/target", "repo_a/.codeql-db"].map(|d| !root.join("datasets").join(d).exists());
+    let kept = ["repo_a/src/lib.rs", "repo_a/.git/objects/pack", "repo_b/docs/target/index.md"].map(|f| root.join("datasets").join(f).exists());
+    let outside = root.join("outside/target/keep").exists();
+    fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(dry.dirs.iter().map(|d| (d.kind, d.files)).collect::<Vec<_>>(), [(CleanKind::Codeql, 1), (CleanKind::Targets, 1), (CleanKind::Targets, 1)]);
+    assert!(still_there, "--dry-run removes nothing");
+    assert_eq!((cleaned.bytes[&CleanKind::Targets], cleaned.bytes[&CleanKind::Codeql]), (2000, 50));
+    assert_eq!(dry.bytes, cleaned.bytes);
+    assert!(cleaned.errors.is_empty(), "{:?}", cleaned.errors);
+    assert_eq!((gone, kept), ([true; 3], [true; 3]));
+    assert!(outside, "a symlink out of the root isn't followed");
+}
+
+#[test]
+fn checkouts_take_everything_in_them_and_git_leaves_the_files() {
+    let root = datasets("clean_checkouts");
+
+    let git = clean_dataset(&opts(&root, &[CleanKind::Git])).unwrap();
+    let files_left = root.join("datasets/repo_a/src/lib.rs").exists() && !root.join("datasets/repo_a/.git").exists();
+    let checkouts = clean_dataset(&opts(&root, &[CleanKind::Targets, CleanKind::Checkouts])).unwrap();
+    let left: Vec<String> = fs::read_dir(root.join("datasets")).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+    let missing = clean_dataset(&CleanOptions { root: root.join("nowhere").display().to_string(), ..CleanOptions::default() });
+    fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!((git.dirs.len(), git.bytes[&CleanKind::Git]), (2, 600));
+    assert!(files_left);
+    assert_eq!(checkouts.bytes.keys().collect::<Vec<_>>(), [&CleanKind::Checkouts], "targets are counted with their checkout");
+    assert_eq!(checkouts.bytes[&CleanKind::Checkouts], 2 * (1000 + 21 + 14) + 50 + 7);
+    assert_eq!(left, ["outputs.jsonl"]);
+    assert!(missing.is_err());
+}
@@ -0,0 +1,57 @@
+use dataset_builder::parse::parse_clippy;
+use dataset_builder::{ToolResult, ToolStatus};
+
+fn result(exit_code: i32, lines: &[&str]) -> ToolResult {
+    let stdout = lines.iter().map(|l| format!("{}\n", l)).collect();
+    ToolResult { status: ToolStatus::Completed, exit_code: Some(exit_code), stdout, stderr: String::new(), error: None, parse_error: false, truncated: false, usage: None }
+}
+
+const ARTIFACT: &str = r#"{"reason":"compiler-artifact","package_id":"app 0.1.0","target":{"name":"app"},"fresh":false}"#;
+
+fn finished(success: bool) -> String {
+    format!(r#"{{"reason":"build-finished","success":{}}}"#, success)
+}
+
+#[test]
+fn a_warning_keeps_its_lint_and_primary_span() {
+    // The secondary span comes first, as rustc lists labels in source order.
+    let warning = r#"{"reason":"compiler-message","package_id":"app 0.1.0","message":{"rendered":"warning: unneeded `return` statement","code":{"code":"clippy::needless_return","explanation":null},"level":"warning","message":"unneeded `return` statement","spans":[{"file_name":"src/lib.rs","line_start":1,"line_end":1,"is_primary":false},{"file_name":"src/lib.rs","line_start":2,"line_end":3,"is_primary":true}]}}"#;
+    let summary = r#"{"reason":"compiler-message","package_id":"app 0.1.0","message":{"rendered":"warning: 1 warning emitted","code":null,"level":"warning","message":"1 warning emitted","spans":[]}}"#;
+
+    let report = parse_clippy(&result(0, &[warning, summary, ARTIFACT, &finished(true)]));
+
+    assert!(!report.compile_failed);
+    assert_eq!(report.diagnostics.len(), 1, "the summary line isn't a diagnostic");
+    let d = &report.diagnostics[0];
+    assert_eq!((d.lint_name.as_deref(), d.level.as_str(), d.message.as_str()), (Some("clippy::needless_return"), "warning", "unneeded `return` statement"));
+    assert_eq!((d.file.as_deref(), d.line_start, d.line_end), (Some("src/lib.rs"), Some(2), Some(3)));
+    assert_eq!(report.counts.into_iter().collect::<Vec<_>>(), [("warning".to_string(), 1)]);
+    assert_eq!((report.units_fresh, report.units_built), (0, 1));
+}
+
+#[test]
+fn a_denied_clippy_lint_fails_the_run_but_not_the_build() {
+    let denied = r#"{"reason":"compiler-message","package_id":"app 0.1.0","message":{"rendered":"error: written amount is not handled","code":{"code":"clippy::unused_io_amount","explanation":null},"level":"error","message":"written amount is not handled","spans":[{"file_name":"src/main.rs","line_start":5,"line_end":5,"is_primary":true}]}}"#;
+    let warning = r#"{"reason":"compiler-message","package_id":"app 0.1.0","message":{"rendered":"warning: redundant clone","code":{"code":"clippy::redundant_clone","explanation":null},"level":"warning","message":"redundant clone","spans":[{"file_name":"src/main.rs","line_start":8,"line_end":8,"is_primary":true}]}}"#;
+    let aborting = r#"{"reason":"compiler-message","package_id":"app 0.1.0","message":{"rendered":"error: aborting due to 1 previous error; 1 warning emitted","code":null,"level":"error","message":"aborting due to 1 previous error; 1 warning emitted","spans":[]}}"#;
+
+    let report = parse_clippy(&result(101, &[denied, warning, aborting, &finished(false)]));
+
+    assert!(!report.compile_failed, "clippy's own errors don't mean the crate doesn't build");
+    let lints: Vec<_> = report.diagnostics.iter().map(|d| (d.lint_name.as_deref(), d.level.as_str())).collect();
+    assert_eq!(lints, [(Some("clippy::unused_io_amount"), "error"), (Some("clippy::redundant_clone"), "warning")], "the summary isn't a diagnostic");
+    assert_eq!((report.counts["error"], report.counts["warning"]), (1, 1));
+}
+
+#[test]
+fn a_rustc_error_marks_the_build_failed_and_drops_the_partial_lints() {
+    let mismatch = r#"{"reason":"compiler-message","package_id":"app 0.1.0","message":{"rendered":"error[E0308]: mismatched types","code":{"code":"E0308","explanation":"Expected type did not match the received type."},"level":"error","message":"mismatched types","spans":[{"file_name":"src/lib.rs","line_start":3,"line_end":3,"is_primary":true}]}}"#;
+    let warning = r#"{"reason":"compiler-message","package_id":"app 0.1.0","message":{"rendered":"warning: unused variable: `x`","code":{"code":"unused_variables","explanation":null},"level":"warning","message":"unused variable: `x`","spans":[{"file_name":"src/lib.rs","line_start":2,"line_end":2,"is_primary":true}]}}"#;
+
+    let aborting = r#"{"reason":"compiler-message","package_id":"app 0.1.0","message":{"rendered":"error: aborting due to 1 previous error","code":null,"level":"error","message":"aborting due to 1 previous error","spans":[]}}"#;
+
+    let report = parse_clippy(&result(101, &[warning, mismatch, aborting, &finished(false)]));
+
+    assert!(report.compile_failed);
+    assert!(report.diagnostics.is_empty() && report.counts.is_empty(), "{:?}", report.diagnostics);
+}
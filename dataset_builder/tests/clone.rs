@@ -0,0 +1,281 @@
+mod common;
+
+use std::fs;
+use std::sync::Arc;
+
+use common::serve;
+use dataset_builder::{clone_repos, collect_code, collect_code_all, sanitize, AuthMode, AuthOptions, CloneError, CloneErrorClass, CloneFilter, CloneOptions, HistoryOptions, CloneRecord, CodeEntry, CollectOptions, OnExisting, Submodules, Tokens, CLONE_MANIFEST_FILE, NAME_MAP_FILE};
+
+#[test]
+fn malformed_names_are_skipped_without_cloning() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_clone_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let names = dir.join("names.txt");
+    fs::write(&names, "a/b/c\n\nno-owner\n  \n").unwrap();
+
+    let opts = CloneOptions { errors_file: dir.join("errors.jsonl").display().to_string(), ..CloneOptions::default() };
+    let summary = clone_repos(names.to_str().unwrap(), dir.join("out").to_str().unwrap(), &Tokens::default(), &opts).unwrap();
+    let created = dir.join("out").exists();
+    let errors = fs::read_to_string(dir.join("errors.jsonl")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let failed: Vec<&str> = summary.failed.iter().map(|(n, _)| n.as_str()).collect();
+    assert_eq!(failed, ["a/b/c", "no-owner"], "blank lines are ignored");
+    assert!(summary.failed.iter().all(|(_, e)| e.contains("skipped without cloning")));
+    assert_eq!(summary.cloned, 0);
+    assert!(!created, "no clone was attempted");
+    let errors: Vec<CloneError> = errors.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert!(errors.iter().all(|e| e.class == CloneErrorClass::InvalidName && e.attempts == 0), "{:?}", errors);
+    assert_eq!(summary.failure_rate(), 1.0);
+}
+
+#[test]
+fn only_transient_errors_are_retried() {
+    let git = |code, class, message| CloneErrorClass::of(&anyhow::Error::from(git2::Error::new(code, class, message)).context("while cloning"));
+    use git2::{ErrorClass as C, ErrorCode as E};
+    assert_eq!(git(E::GenericError, C::Net, "failed to resolve address for github.com"), CloneErrorClass::Network);
+    assert_eq!(git(E::GenericError, C::Http, "unexpected http status code: 502"), CloneErrorClass::Server);
+    assert_eq!(git(E::GenericError, C::Http, "unexpected http status code: 429"), CloneErrorClass::RateLimited);
+    assert_eq!(git(E::GenericError, C::Http, "unexpected http status code: 404"), CloneErrorClass::NotFound);
+    assert_eq!(git(E::Auth, C::Http, "authentication required but no callback set"), CloneErrorClass::Auth);
+    assert_eq!(git(E::GenericError, C::Os, "No space left on device"), CloneErrorClass::Other);
+    assert!(CloneErrorClass::Network.is_transient() && CloneErrorClass::Server.is_transient() && CloneErrorClass::RateLimited.is_transient());
+    assert!(!CloneErrorClass::NotFound.is_transient() && !CloneErrorClass::Auth.is_transient());
+}
+
+/// Commits `file` with `content` on top of HEAD, returning the commit's SHA.
+fn commit(repo: &git2::Repository, file: &str, content: &str) -> String {
+    fs::write(repo.workdir().unwrap().join(file), content).unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path(file.as_ref()).unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let sig = git2::Signature::now("test", "test@example.com").unwrap();
+    let parents: Vec<git2::Commit> = repo.head().ok().map(|h| h.peel_to_commit().unwrap()).into_iter().collect();
+    repo.commit(Some("HEAD"), &sig, &sig, content, &tree, &parents.iter().collect::<Vec<_>>()).unwrap().to_string()
+}
+
+#[test]
+fn pinned_revisions_are_checked_out_and_recorded() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_clone_pins_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let origin = git2::Repository::init(dir.join("origin")).unwrap();
+    let first = commit(&origin, "lib.rs", "v1");
+    commit(&origin, "lib.rs", "v2");
+    // Existing checkouts (kept by `--on-existing skip`) of the same origin.
+    let out = dir.join("out");
+    for checkout in ["dataset_a__b", "dataset_c__d", "dataset_e__f"] {
+        git2::Repository::clone(dir.join("origin").to_str().unwrap(), out.join(checkout)).unwrap();
+    }
+    // A tag the checkouts don't have yet, so it has to be fetched.
+    let third = commit(&origin, "lib.rs", "v3");
+    origin.tag_lightweight("v3", &origin.revparse_single(&third).unwrap(), false).unwrap();
+    fs::write(out.join(CLONE_MANIFEST_FILE), "{\"name\":\"dataset_x__y\",\"repo\":\"x/y\",\"commit\":\"abc\",\"branch\":\"main\",\"committed_at\":\"2020-01-01T00:00:00Z\"}\n").unwrap();
+    fs::write(dir.join("names.txt"), format!("a/b {}\nc/d,v3\ne/f\n", first)).unwrap();
+
+    let opts = CloneOptions { jobs: 2, errors_file: dir.join("errors.jsonl").display().to_string(), ..CloneOptions::default() };
+    let summary = clone_repos(dir.join("names.txt").to_str().unwrap(), out.to_str().unwrap(), &Tokens::default(), &opts).unwrap();
+    let manifest = fs::read_to_string(out.join(CLONE_MANIFEST_FILE)).unwrap();
+    let pinned = fs::read_to_string(out.join("dataset_a__b/lib.rs")).unwrap();
+    let code = collect_code(&out.join("dataset_c__d")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(summary.failed.is_empty(), "{:?}", summary.failed);
+    assert_eq!(summary.skipped, 3);
+    assert_eq!(pinned, "v1");
+    let records: Vec<CloneRecord> = manifest.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let by_name = |name: &str| records.iter().find(|r| r.name == name).unwrap_or_else(|| panic!("{}", manifest));
+    let a = by_name("dataset_a__b");
+    assert_eq!((a.repo.as_str(), a.requested.as_deref(), a.commit.as_str(), a.branch.as_deref()), ("a/b", Some(first.as_str()), first.as_str(), None));
+    assert_eq!((by_name("dataset_c__d").commit.as_str(), code[0].commit.as_deref()), (third.as_str(), Some(third.as_str())));
+    let e = by_name("dataset_e__f");
+    assert!(e.requested.is_none() && e.branch.is_some() && e.committed_at.ends_with('Z'), "{:?}", e);
+    assert_eq!(by_name("dataset_x__y").commit, "abc", "records of other checkouts are kept");
+}
+
+#[test]
+fn retry_from_clones_only_the_recorded_failures() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_clone_retry_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    for checkout in ["dataset_a__b", "dataset_c__d"] {
+        commit(&git2::Repository::init(dir.join("out").join(checkout)).unwrap(), "lib.rs", "v1");
+    }
+    fs::write(dir.join("names.txt"), "a/b\nc/d\n").unwrap();
+    fs::write(dir.join("errors.jsonl"), "{\"name\":\"C/D\",\"class\":\"network\",\"attempts\":4,\"error\":\"timed out\"}\n").unwrap();
+    let errors = dir.join("errors.jsonl").display().to_string();
+    let opts = CloneOptions { retry_from: Some(errors.clone()), errors_file: errors, ..CloneOptions::default() };
+
+    let summary = clone_repos(dir.join("names.txt").to_str().unwrap(), dir.join("out").to_str().unwrap(), &Tokens::default(), &opts).unwrap();
+    let manifest = fs::read_to_string(dir.join("out").join(CLONE_MANIFEST_FILE)).unwrap();
+    let errors = fs::read_to_string(dir.join("errors.jsonl")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!((summary.skipped, summary.failed.len()), (1, 0));
+    assert!(manifest.contains("dataset_c__d") && !manifest.contains("dataset_a__b"), "{}", manifest);
+    assert_eq!(errors, "", "the retried failure is cleared");
+}
+
+#[test]
+fn authentication_failures_name_the_repo_and_the_mode() {
+    let ssh = AuthOptions { auth: AuthMode::Ssh, git_host: Some("ghe.example.com".into()), ..AuthOptions::default() };
+    assert_eq!(ssh.remote_url("org/mirror"), "git@ghe.example.com:org/mirror.git");
+    assert_eq!(AuthOptions::default().remote_url("org/mirror"), "https://github.com/org/mirror.git");
+
+    let url = serve(Arc::new(|_| (401, "WWW-Authenticate: Basic realm=\"git\"\r\n".into(), String::new())));
+    let dir = std::env::temp_dir().join(format!("dataset_builder_clone_auth_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("names.txt"), "org/private\n").unwrap();
+    for (mode, tried) in [(AuthMode::None, "tried --auth none"), (AuthMode::Token, "tried --auth token")] {
+        let opts = CloneOptions {
+            errors_file: dir.join("errors.jsonl").display().to_string(),
+            auth: AuthOptions { auth: mode, git_host: Some(url.clone()), ..AuthOptions::default() },
+            ..CloneOptions::default()
+        };
+        let summary = clone_repos(dir.join("names.txt").to_str().unwrap(), dir.join("out").to_str().unwrap(), &Tokens::new(["token".to_string()]), &opts).unwrap();
+        let errors = fs::read_to_string(dir.join("errors.jsonl")).unwrap();
+        let error: CloneError = serde_json::from_str(errors.trim()).unwrap();
+        assert!(summary.failed[0].1.contains("org/private") && summary.failed[0].1.contains(tried), "{:?}", summary.failed);
+        assert_eq!((error.class, error.attempts), (CloneErrorClass::Auth, 1), "authentication failures aren't retried");
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn git_urls_get_their_own_checkouts_and_keep_the_listed_name() {
+    let gitlab = AuthOptions { host_template: Some("https://gitlab.com/{}.git".into()), ..AuthOptions::default() };
+    assert_eq!(gitlab.remote_url("group/app"), "https://gitlab.com/group/app.git");
+    assert_eq!(gitlab.remote_url("git@codeberg.org:a/b.git"), "git@codeberg.org:a/b.git");
+    assert_eq!(sanitize("https://github.com/tokio-rs/tokio.git"), "tokio-rs__tokio");
+    assert_eq!(sanitize("https://gitlab.com/group/sub/app.git"), "gitlab.com__group__sub__app");
+    assert_eq!(sanitize("git@gitlab.com:group/sub/app.git"), "gitlab.com__group__sub__app", "the same repository over SSH");
+    assert_ne!(sanitize("https://git.sr.ht/~a/b_c"), sanitize("https://git.sr.ht/~a_b/c"));
+    assert_ne!(sanitize("my_group/app"), sanitize("my/group_app"));
+    assert_ne!(sanitize("foo/bar-baz"), sanitize("foo_bar/baz"));
+    assert_ne!(sanitize("a/b__c"), sanitize("https://a/b/c"));
+    let odd = [sanitize("https://example.com/x/y?z:w"), sanitize("owner/repo."), sanitize(&format!("owner/{}", "r".repeat(300)))];
+    assert!(odd.iter().all(|d| d.len() <= 100 && !d.ends_with('.') && d.chars().all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))), "{:?}", odd);
+
+    let dir = std::env::temp_dir().join(format!("dataset_builder_clone_urls_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let entries = ["https://git.sr.ht/~a/b_c", "https://gitlab.com/group/sub/app.git"];
+    for entry in entries {
+        commit(&git2::Repository::init(dir.join("out").join(format!("dataset_{}", sanitize(entry)))).unwrap(), "lib.rs", entry);
+    }
+    fs::write(dir.join("names.txt"), entries.join("\n")).unwrap();
+    let opts = CloneOptions { errors_file: dir.join("errors.jsonl").display().to_string(), ..CloneOptions::default() };
+    let out = dir.join("out").display().to_string();
+
+    let summary = clone_repos(dir.join("names.txt").to_str().unwrap(), &out, &Tokens::default(), &opts).unwrap();
+    // Without their manifest lines, the entries come from the name map alone.
+    fs::remove_file(dir.join("out").join(CLONE_MANIFEST_FILE)).unwrap();
+    collect_code_all(&out, dir.join("code.jsonl").to_str().unwrap(), &CollectOptions::default()).unwrap();
+    let code = fs::read_to_string(dir.join("code.jsonl")).unwrap();
+    let map: std::collections::HashMap<String, String> = serde_json::from_str(&fs::read_to_string(dir.join("out").join(NAME_MAP_FILE)).unwrap()).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!((summary.skipped, summary.failed.len()), (2, 0), "{:?}", summary.failed);
+    assert_eq!(map[&format!("dataset_{}", sanitize(entries[1]))], entries[1]);
+    let code: Vec<CodeEntry> = code.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(code.len(), 2);
+    assert!(code.iter().all(|e| e.repo.as_deref() == Some(e.content.as_str())), "{:?}", code);
+}
+
+#[test]
+fn full_single_branch_clones_are_recorded_with_their_options() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_clone_history_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let origin = git2::Repository::init(dir.join("origin")).unwrap();
+    commit(&origin, "lib.rs", "v1");
+    let head = commit(&origin, "lib.rs", "v2");
+    origin.branch("other", &origin.revparse_single(&head).unwrap().peel_to_commit().unwrap(), false).unwrap();
+    let default = origin.head().unwrap().shorthand().unwrap().to_string();
+    let url = format!("file://{}", dir.join("origin").display());
+    fs::write(dir.join("names.txt"), format!("{}\n", url)).unwrap();
+    let history = HistoryOptions { depth: 0, single_branch: true, ..HistoryOptions::default() };
+    let opts = CloneOptions { history: history.clone(), errors_file: dir.join("errors.jsonl").display().to_string(), ..CloneOptions::default() };
+    let out = dir.join("out");
+
+    let summary = clone_repos(dir.join("names.txt").to_str().unwrap(), out.to_str().unwrap(), &Tokens::default(), &opts).unwrap();
+    let checkout = git2::Repository::open(out.join(format!("dataset_{}", sanitize(&url)))).unwrap();
+    let branches: Vec<String> = checkout.branches(Some(git2::BranchType::Remote)).unwrap().map(|b| b.unwrap().0.name().unwrap().unwrap().to_string()).filter(|b| b != "origin/HEAD").collect();
+    let mut walk = checkout.revwalk().unwrap();
+    walk.push_head().unwrap();
+    let commits = walk.count();
+    let manifest = fs::read_to_string(out.join(CLONE_MANIFEST_FILE)).unwrap();
+    let filtered = CloneOptions { history: HistoryOptions { filter: Some(CloneFilter::BlobNone), ..history.clone() }, ..opts.clone() };
+    let err = clone_repos(dir.join("names.txt").to_str().unwrap(), out.to_str().unwrap(), &Tokens::default(), &filtered).unwrap_err();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(summary.cloned, 1, "{:?}", summary.failed);
+    assert_eq!((branches, commits), (vec![format!("origin/{}", default)], 2));
+    let record: CloneRecord = serde_json::from_str(manifest.trim()).unwrap();
+    assert_eq!((record.shallow, record.history), (false, Some(history)));
+    assert!(err.to_string().contains("--filter blob:none"), "{}", err);
+}
+
+#[test]
+fn clones_stop_at_the_disk_budget_and_oversized_repos_are_skipped() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_clone_budget_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let mut names = String::from("big/repo\n");
+    for origin in ["a", "b", "c"] {
+        commit(&git2::Repository::init(dir.join(origin)).unwrap(), "lib.rs", &"x".repeat(4096));
+        names.push_str(&format!("file://{}\n", dir.join(origin).display()));
+    }
+    fs::write(dir.join("names.txt"), names).unwrap();
+    fs::write(dir.join("metadata.jsonl"), "{\"name\":\"dataset_big__repo\",\"repo\":\"big/repo\",\"status\":\"ok\",\"size_kb\":900000,\"topics\":[],\"fetched_at\":\"\"}\n").unwrap();
+    let opts = CloneOptions {
+        history: HistoryOptions { depth: 0, ..HistoryOptions::default() },
+        max_total_gb: Some(1e-6),
+        max_repo_mb: Some(100),
+        metadata_file: dir.join("metadata.jsonl").display().to_string(),
+        errors_file: dir.join("errors.jsonl").display().to_string(),
+        ..CloneOptions::default()
+    };
+
+    let summary = clone_repos(dir.join("names.txt").to_str().unwrap(), dir.join("out").to_str().unwrap(), &Tokens::default(), &opts).unwrap();
+    let errors = fs::read_to_string(dir.join("errors.jsonl")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!((summary.cloned, summary.failed.len(), summary.not_attempted.len()), (1, 0, 3), "{:?}", summary);
+    assert!(summary.budget_exhausted && summary.bytes > 4096);
+    assert_eq!(summary.largest.len(), 1);
+    let errors: Vec<CloneError> = errors.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let classes: Vec<CloneErrorClass> = errors.iter().map(|e| e.class).collect();
+    assert_eq!(classes, [CloneErrorClass::TooLarge, CloneErrorClass::Budget, CloneErrorClass::Budget]);
+    assert_eq!(errors[0].name, "big/repo");
+}
+
+#[test]
+fn submodules_are_initialized_on_request_and_their_files_tagged() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_clone_submodules_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    commit(&git2::Repository::init(dir.join("vendored")).unwrap(), "lib.rs", "pub fn vendored() {}");
+    let origin = git2::Repository::init(dir.join("origin")).unwrap();
+    let mut sub = origin.submodule(&format!("file://{}", dir.join("vendored").display()), "deps/sub".as_ref(), true).unwrap();
+    sub.clone(None).unwrap();
+    sub.add_finalize().unwrap();
+    commit(&origin, "main.rs", "fn main() {}");
+    let url = format!("file://{}", dir.join("origin").display());
+    fs::write(dir.join("names.txt"), format!("{}\n", url)).unwrap();
+    let opts = |submodules| CloneOptions {
+        history: HistoryOptions { depth: 0, submodules, ..HistoryOptions::default() },
+        on_existing: OnExisting::Overwrite,
+        errors_file: dir.join("errors.jsonl").display().to_string(),
+        ..CloneOptions::default()
+    };
+    let (names, out) = (dir.join("names.txt"), dir.join("out"));
+    let manifest = || serde_json::from_str::<CloneRecord>(fs::read_to_string(out.join(CLONE_MANIFEST_FILE)).unwrap().trim()).unwrap();
+
+    clone_repos(names.to_str().unwrap(), out.to_str().unwrap(), &Tokens::default(), &opts(Submodules::Skip)).unwrap();
+    let skipped = manifest();
+    let summary = clone_repos(names.to_str().unwrap(), out.to_str().unwrap(), &Tokens::default(), &opts(Submodules::Init)).unwrap();
+    let initialized = manifest();
+    let code = collect_code(&out.join(format!("dataset_{}", sanitize(&url)))).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(summary.cloned, 1, "{:?}", summary.failed);
+    assert!(skipped.has_uninitialized_submodules && !initialized.has_uninitialized_submodules);
+    let tagged: Vec<(&str, bool)> = code.iter().filter(|e| e.path.ends_with(".rs")).map(|e| (e.path.as_str(), e.from_submodule)).collect();
+    assert!(tagged.contains(&("main.rs", false)) && tagged.contains(&("deps/sub/lib.rs", true)), "{:?}", tagged);
+}
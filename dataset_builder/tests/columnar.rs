@@ -0,0 +1,59 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use dataset_builder::{collect_code_all, convert, parquet_rows, CodeEntry, CollectOptions, ConvertOptions, FileFormat, Granularity, OutputEntry, Report};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+
+fn checkout(test: &str) -> PathBuf {
+    common::scratch(test, &[("repo/Cargo.toml", "[package]\nname = \"repo\"\n"), ("repo/src/lib.rs", "/// Adds.\n#[inline]\npub fn add(a: u8, b: u8) -> u8 {\n    a + b\n}\n\npub unsafe fn raw() {}\n")])
+}
+
+const OUTPUT: &str = r#"{"schema_version":4,"run_id":"r","name":"owner/a","dir":"dataset_a","project_manifest":"Cargo.toml","tools":{"clippy":{"status":"completed","exit_code":0,"report":{"diagnostics":[{"lint_name":"clippy::needless_return","level":"warning","file":"src/lib.rs","line_start":3,"line_end":3,"message":"unneeded `return`"}],"counts":{"warning":1},"compile_failed":false}}},"findings":[{"tool":"semgrep","rule_id":"rust.lang.unsafe","severity":"warning","file":"src/lib.rs","start_line":20,"end_line":21,"message":"unsafe block"}],"toolchain":"stable","time_ms":{"clippy":1200},"total_duration_ms":1500,"errors":[]}"#;
+
+#[test]
+fn parquet_collect_converts_back_to_the_same_entries() {
+    let root = checkout("columnar_collect");
+    let (jsonl, parquet) = (root.join("code.jsonl"), root.join("code.parquet"));
+    let opts = CollectOptions { granularity: Granularity::Function, ..CollectOptions::default() };
+
+    collect_code_all(root.to_str().unwrap(), jsonl.to_str().unwrap(), &opts).unwrap();
+    let expected: Vec<CodeEntry> = fs::read_to_string(&jsonl).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let opts = CollectOptions { format: FileFormat::Parquet, row_group_size: Some(2), ..opts };
+    collect_code_all(root.to_str().unwrap(), jsonl.to_str().unwrap(), &opts).unwrap();
+    let jsonl_left = jsonl.exists();
+    let row_groups = SerializedFileReader::new(fs::File::open(&parquet).unwrap()).unwrap().metadata().num_row_groups();
+    let back = root.join("back.jsonl");
+    let rows = convert(parquet.to_str().unwrap(), back.to_str().unwrap(), &ConvertOptions::default()).unwrap();
+    let entries: Vec<CodeEntry> = fs::read_to_string(&back).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    fs::remove_dir_all(&root).unwrap();
+
+    assert!(!jsonl_left, "the working JSONL file is removed once converted");
+    assert_eq!((rows, expected.len(), row_groups), (3, 3, 2), "Cargo.toml and two functions, two rows a group");
+    let fields = |e: &CodeEntry| (e.path.clone(), e.content.clone(), e.content_hash.clone(), e.kind, e.lines, e.avg_line_length, e.item.clone().map(|i| (i.path, i.doc, i.attributes, i.start_line, i.is_unsafe)));
+    assert_eq!(entries.iter().map(fields).collect::<Vec<_>>(), expected.iter().map(fields).collect::<Vec<_>>());
+    let add = entries.iter().find_map(|e| e.item.as_ref().filter(|i| i.path == "crate::add")).unwrap();
+    assert_eq!((add.attributes.as_slice(), add.doc.as_deref()), (&["#[inline]".to_string()][..], Some("Adds.")));
+}
+
+#[test]
+fn outputs_keep_tool_results_through_a_round_trip() {
+    let root = checkout("columnar_outputs");
+    let path = |f: &str| root.join(f).display().to_string();
+    fs::write(path("outputs.jsonl"), [OUTPUT, "\n", OUTPUT, "\n"].concat()).unwrap();
+    fs::write(path("extra.jsonl"), OUTPUT.replace(r#""toolchain""#, r#""unknown_field":1,"toolchain""#) + "\n").unwrap();
+
+    let rows = convert(&path("outputs.jsonl"), &path("outputs.parquet"), &ConvertOptions::default()).unwrap();
+    let footer = parquet_rows(&path("outputs.parquet")).unwrap();
+    convert(&path("outputs.parquet"), &path("back.jsonl"), &ConvertOptions::default()).unwrap();
+    let back: Vec<OutputEntry> = fs::read_to_string(path("back.jsonl")).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let extra = convert(&path("extra.jsonl"), &path("extra.parquet"), &ConvertOptions::default()).unwrap_err();
+    fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!((rows, footer, back.len()), (2, 2, 2));
+    let Some(Report::Clippy(clippy)) = &back[0].tools["clippy"].report else { panic!("no clippy report") };
+    assert_eq!((clippy.counts["warning"], clippy.diagnostics[0].line_start), (1, Some(3)));
+    assert_eq!((back[0].findings[0].rule_id.as_str(), back[0].findings[0].end_line, back[0].time_ms["clippy"], back[0].total_duration_ms), ("rust.lang.unsafe", Some(21), 1200, 1500));
+    assert!(format!("{:#}", extra).contains("entries 1 to 1 don't fit the Parquet schema"), "{:#}", extra);
+}
@@ -0,0 +1,126 @@
+//! A local stand-in for api.github.com, the Hugging Face Hub and S3, shared by the tests of the
+//! commands that call them, fake tools for the tests of the analyzers that run them, and the
+//! scratch directories the tests write their checkouts to.
+// Each test crate that includes this reads only some of the request.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The request's method, path, query parameters, headers (with lowercased names) and body.
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+pub type Handler = dyn Fn(&Request) -> (u16, String, String) + Send + Sync;
+
+/// Answers every request with `handler(request)`, which returns the status, extra header lines
+/// and body.
+pub fn serve(handler: Arc<Handler>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut headers = HashMap::new();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                if let Some((k, v)) = line.split_once(':') {
+                    headers.insert(k.trim().to_ascii_lowercase(), v.trim().to_string());
+                }
+                line.clear();
+            }
+            let mut body = vec![0; headers.get("content-length").and_then(|l| l.parse().ok()).unwrap_or(0)];
+            reader.read_exact(&mut body).unwrap();
+            let method = request_line.split(' ').next().unwrap_or("GET").to_string();
+            let target = request_line.split(' ').nth(1).unwrap_or("/");
+            let (path, query) = target.split_once('?').unwrap_or((target, ""));
+            let query = query.split('&').filter_map(|kv| kv.split_once('=')).map(|(k, v)| (k.to_string(), decode(v))).collect();
+            let (status, extra, body) = handler(&Request { method, path: path.to_string(), query, headers, body });
+            let _ = write!(stream, "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n{}", status, body.len(), extra, body);
+        }
+    });
+    url
+}
+
+fn decode(v: &str) -> String {
+    let bytes = v.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' => {
+                out.push(u8::from_str_radix(&v[i + 1..i + 3], 16).unwrap());
+                i += 2;
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8(out).unwrap()
+}
+
+/// A directory of executable scripts standing in for tools, removed when dropped.
+pub struct FakeTools {
+    pub bin: PathBuf,
+}
+
+impl FakeTools {
+    /// A `PATH` that finds the fakes before the host's tools.
+    pub fn path(&self) -> String {
+        format!("{}:{}", self.bin.display(), std::env::var("PATH").unwrap_or_default())
+    }
+
+    /// `AnalyzeOptions::tool_env` for a run that uses the fakes.
+    pub fn env(&self) -> Vec<(String, String)> {
+        vec![("PATH".to_string(), self.path())]
+    }
+}
+
+impl Drop for FakeTools {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.bin);
+    }
+}
+
+/// Writes each `(name, script)` as an executable in a directory of its own.
+#[cfg(unix)]
+pub fn fake_tools(tools: &[(&str, &str)]) -> FakeTools {
+    use std::os::unix::fs::PermissionsExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let bin = std::env::temp_dir().join(format!("dataset_builder_fake_tools_{}_{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed)));
+    let _ = std::fs::remove_dir_all(&bin);
+    std::fs::create_dir_all(&bin).unwrap();
+    for (name, script) in tools {
+        std::fs::write(bin.join(name), script).unwrap();
+        std::fs::set_permissions(bin.join(name), std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    FakeTools { bin }
+}
+
+/// A fresh `dataset_builder_{test}_{pid}` under the temp dir holding `files`, as (path, content)
+/// pairs relative to it.
+pub fn scratch<P: AsRef<Path>, C: AsRef<[u8]>>(test: &str, files: &[(P, C)]) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("dataset_builder_{}_{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    for (path, content) in files {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    root
+}
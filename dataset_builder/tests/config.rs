@@ -0,0 +1,47 @@
+use std::fs;
+
+use dataset_builder::{Config, OnExisting, Pipeline, PipelineFlags, CONFIG_TEMPLATE};
+
+#[test]
+fn template_parses_and_only_spells_out_the_defaults() {
+    let template: Config = toml::from_str(CONFIG_TEMPLATE).unwrap();
+    let mut pipeline = Pipeline::default();
+    template.apply(&mut pipeline, |_| false);
+    assert_eq!(Config::effective(&pipeline), Config::effective(&Pipeline::default()));
+
+    let effective = toml::to_string(&Config::effective(&pipeline)).unwrap();
+    assert_eq!(toml::from_str::<Config>(&effective).unwrap(), Config::effective(&pipeline), "{}", effective);
+    assert!(toml::from_str::<Config>("[analyze]\nthreads = 4\n").is_err(), "unknown keys are rejected");
+}
+
+#[test]
+fn flags_override_the_config_which_overrides_defaults() {
+    let config: Config = toml::from_str(
+        "[paths]\ninput_csv = \"repos.csv\"\ncode_file = \"src.jsonl\"\noutputs_file = \"out/outputs.jsonl\"\n\n[analyze]\njobs = 4\nskip = [\"codeql\"]\ntool_timeout_secs = 60\n\n[clone]\non_existing = \"update\"\njobs = 8\n",
+    )
+    .unwrap();
+    let mut pipeline = Pipeline::default();
+    pipeline.analyze.jobs = 2;
+    config.apply(&mut pipeline, |id| id == "jobs");
+    PipelineFlags { code_file: Some("code.jsonl".into()), clone_jobs: Some(2), ..PipelineFlags::default() }.apply(&mut pipeline);
+
+    assert_eq!((pipeline.input_csv.as_str(), pipeline.code_file.as_str()), ("repos.csv", "code.jsonl"));
+    assert_eq!(pipeline.outputs_file, "out/outputs.jsonl");
+    assert_eq!(pipeline.stream_errors_file(), "out/stream_errors.jsonl", "next to the outputs file, not in the working directory");
+    assert_eq!(pipeline.analyze.jobs, 2, "given on the command line");
+    assert_eq!((pipeline.analyze.skip.as_slice(), pipeline.analyze.tool_timeout), (&["codeql".to_string()][..], Some(60)));
+    assert_eq!((pipeline.on_existing, pipeline.clone_jobs), (OnExisting::Update, 2));
+}
+
+#[test]
+fn full_refuses_to_run_without_its_input() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_config_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("missing.csv").display().to_string();
+    let pipeline = Pipeline { input_csv: input.clone(), outputs_file: dir.join("outputs.jsonl").display().to_string(), ..Pipeline::default() };
+    let err = format!("{:#}", pipeline.run().unwrap_err());
+    let manifest_written = dir.join("manifest.json").exists();
+    let _ = fs::remove_dir_all(&dir);
+    assert!(err.contains(&input) && err.contains("not found"), "{}", err);
+    assert!(!manifest_written, "nothing is written before the inputs are checked");
+}
@@ -0,0 +1,123 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use dataset_builder::{collect_code_all, content_hash, dedup_code, near_dedup_code, CodeEntry, CollectOptions, Dedup, NearAction, NearDedupOptions};
+
+/// Checkouts `a` and `b` sharing a vendored `lib.rs`, with `a` also holding a copy of it.
+fn checkouts(test: &str) -> std::path::PathBuf {
+    let files = [
+        ("dataset_a/src/lib.rs", "pub fn shared() {}\n"),
+        ("dataset_a/src/copy.rs", "pub fn shared() {}\n"),
+        ("dataset_a/src/main.rs", "fn main() {}\n"),
+        ("dataset_b/src/lib.rs", "pub fn shared() {}\n"),
+    ];
+    common::scratch(test, &files)
+}
+
+fn read(path: &Path) -> Vec<CodeEntry> {
+    fs::read_to_string(path).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+}
+
+#[test]
+fn collect_dedup_keeps_one_copy_per_repository_or_per_corpus() {
+    let root = checkouts("dedup_collect");
+    let out = root.join("out");
+    fs::create_dir_all(&out).unwrap();
+    let collect = |mode, file: &str| {
+        let code = out.join(file);
+        collect_code_all(root.to_str().unwrap(), code.to_str().unwrap(), &CollectOptions { dedup: mode, ..CollectOptions::default() }).unwrap();
+        read(&code)
+    };
+
+    let none = collect(Dedup::None, "none.jsonl");
+    let within = collect(Dedup::WithinRepo, "within.jsonl");
+    let global = collect(Dedup::Global, "global.jsonl");
+    fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!((none.len(), within.len(), global.len()), (4, 3, 2));
+    assert!(none.iter().all(|e| e.content_hash == content_hash(&e.content) && e.duplicate_count == 0));
+    let shared = global.iter().find(|e| e.content == "pub fn shared() {}\n").unwrap();
+    let other = if shared.name == "dataset_a" { "dataset_b" } else { "dataset_a" };
+    assert_eq!((shared.duplicate_count, shared.duplicate_repos.as_slice()), (2, &[other.to_string()][..]));
+    let in_a = within.iter().find(|e| e.name == "dataset_a" && e.content == "pub fn shared() {}\n").unwrap();
+    assert_eq!((in_a.duplicate_count, in_a.duplicate_repos.len()), (1, 0));
+}
+
+#[test]
+fn dedup_rewrites_an_existing_code_file_without_hashes() {
+    let line = |name: &str, path: &str, content: &str| format!(r#"{{"name":"{}","repo":null,"path":"{}","content":"{}"}}"#, name, path, content);
+    let text = [line("x/a", "a.rs", "same"), line("x/b", "b.rs", "same"), line("x/b", "c.rs", "other")].join("\n") + "\n";
+    let dir = common::scratch("dedup_file", &[("code.jsonl", text)]);
+    let (input, output) = (dir.join("code.jsonl").display().to_string(), dir.join("dedup.jsonl").display().to_string());
+
+    let summary = dedup_code(&input, &output, Dedup::Global).unwrap();
+    let entries = read(Path::new(&output));
+    fs::write(dir.join("code.jsonl"), "{\"name\":\n").unwrap();
+    let bad = dedup_code(&input, &input, Dedup::Global).unwrap_err();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!((summary.entries, summary.removed, summary.removed_bytes), (3, 1, 4));
+    let paths: Vec<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+    assert_eq!(paths, ["a.rs", "c.rs"], "the first copy is kept, in the file's order");
+    assert_eq!((entries[0].content_hash.as_str(), entries[0].duplicate_repos.as_slice()), (content_hash("same").as_str(), &["x/b".to_string()][..]));
+    assert!(format!("{:#}", bad).contains("code.jsonl:1"), "{:#}", bad);
+}
+
+#[test]
+fn near_duplicates_are_clustered_reproducibly_or_dropped() {
+    let body = "pub fn parse(input: &str) -> Result<Vec<Token>, Error> {\\n    let mut tokens = Vec::new();\\n    for word in input.split_whitespace() {\\n        tokens.push(Token::new(word)?);\\n    }\\n    Ok(tokens)\\n}\\n";
+    let line = |name: &str, header: &str, content: &str| format!(r#"{{"name":"{}","repo":null,"path":"lib.rs","content":"{}{}"}}"#, name, header, content);
+    let text = [
+        line("x/a", "// Copyright 2019 Someone\\n", body),
+        line("x/b", "fn unrelated() { println!(\\\"hello, world\\\"); }", ""),
+        line("x/c", "// Copyright 2023 Someone\\n", &body.replace("\\n    ", "\\n\\t")),
+    ]
+    .join("\n");
+    let dir = common::scratch("near_dedup", &[("code.jsonl", text + "\n")]);
+    let input = dir.join("code.jsonl").display().to_string();
+    let run = |out: &str, opts: NearDedupOptions| {
+        let out = dir.join(out).display().to_string();
+        (near_dedup_code(&input, &out, &opts).unwrap(), read(Path::new(&out)))
+    };
+
+    let (annotated, entries) = run("annotated.jsonl", NearDedupOptions { threshold: 0.7, ..NearDedupOptions::default() });
+    let (_, again) = run("again.jsonl", NearDedupOptions { threshold: 0.7, ..NearDedupOptions::default() });
+    let (dropped, kept) = run("dropped.jsonl", NearDedupOptions { threshold: 0.7, action: NearAction::Drop, ..NearDedupOptions::default() });
+    let invalid = near_dedup_code(&input, &input, &NearDedupOptions { bands: 7, ..NearDedupOptions::default() }).unwrap_err();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let clusters: Vec<Option<usize>> = entries.iter().map(|e| e.cluster_id).collect();
+    assert_eq!(clusters, [Some(0), None, Some(0)], "only the copyright year and the indentation differ");
+    assert_eq!(again.iter().map(|e| e.cluster_id).collect::<Vec<_>>(), clusters, "the same seed gives the same clusters");
+    assert_eq!((annotated.clusters, annotated.clustered, annotated.removed), (1, 2, 0));
+    assert_eq!(kept.iter().map(|e| (e.name.as_str(), e.near_duplicate_count)).collect::<Vec<_>>(), [("x/a", 1), ("x/b", 0)]);
+    assert_eq!(dropped.removed, 1);
+    assert!(invalid.to_string().contains("multiple of --bands"), "{}", invalid);
+}
+
+#[test]
+fn windows_line_endings_and_boms_dedup_with_their_lf_copies() {
+    let root = checkouts("dedup_newlines");
+    fs::write(root.join("dataset_b/src/lib.rs"), "\u{feff}pub fn shared() {}\r\n").unwrap();
+    fs::write(root.join("dataset_b/src/old.rs"), "fn old() {}\rfn older() {}\r").unwrap();
+    let out = root.join("out");
+    fs::create_dir_all(&out).unwrap();
+    let collect = |normalize_newlines, file: &str| {
+        let code = out.join(file);
+        collect_code_all(root.to_str().unwrap(), code.to_str().unwrap(), &CollectOptions { dedup: Dedup::Global, normalize_newlines, ..CollectOptions::default() }).unwrap();
+        read(&code)
+    };
+
+    let normalized = collect(true, "normalized.jsonl");
+    let raw = collect(false, "raw.jsonl");
+    fs::remove_dir_all(&root).unwrap();
+
+    let shared = normalized.iter().find(|e| e.content == "pub fn shared() {}\n").unwrap();
+    assert_eq!((normalized.len(), shared.duplicate_count), (3, 2), "the CRLF copy with a BOM hashes like the others");
+    let old = normalized.iter().find(|e| e.path == "src/old.rs").unwrap();
+    assert_eq!((old.content.as_str(), old.original_newlines.as_deref(), old.lines), ("fn old() {}\nfn older() {}\n", Some("cr"), 2));
+    assert_eq!(raw.len(), 4);
+    assert!(raw.iter().any(|e| e.content.starts_with('\u{feff}') && e.original_newlines.is_none()));
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use std::fs;
+
+use dataset_builder::parse::parse_deny;
+use dataset_builder::{analyze_repo, select_analyzers, AnalyzeOptions, Report, RunManifest, ToolResult, ToolStatus};
+
+const STDERR: &str = r#"{"type":"log","fields":{"timestamp":"2024-05-01T10:00:00Z","level":"WARN","message":"unable to find a config path"}}
+{"type":"diagnostic","fields":{"severity":"error","code":"vulnerability","message":"Double free in insert","advisory":{"id":"RUSTSEC-2024-0001"},"labels":[],"graphs":[{"Krate":{"name":"smallvec","version":"1.0.0"},"parents":[]}]}}
+{"type":"diagnostic","fields":{"severity":"error","code":"rejected","message":"failed to satisfy license requirements","labels":[],"graphs":[{"Krate":{"name":"gpl-thing","version":"0.2.0"}}]}}
+{"type":"diagnostic","fields":{"severity":"warning","code":"duplicate","message":"found 2 duplicate entries for crate 'syn'","labels":[],"graphs":[]}}
+{"type":"summary","fields":{"advisories":{"errors":1,"warnings":0,"notes":0,"helps":0}}}
+"#;
+
+#[test]
+fn diagnostics_are_parsed_into_check_severity_and_crate() {
+    let result = ToolResult { status: ToolStatus::Completed, exit_code: Some(1), stdout: String::new(), stderr: STDERR.into(), error: None, parse_error: false, truncated: false, usage: None };
+
+    let report = parse_deny(&result, false);
+
+    let rows: Vec<_> = report.diagnostics.iter().map(|d| (d.check.as_deref(), d.severity.as_str(), d.krate.as_deref(), d.advisory_id.as_deref())).collect();
+    assert_eq!(rows, [
+        (Some("advisories"), "error", Some("smallvec"), Some("RUSTSEC-2024-0001")),
+        (Some("licenses"), "error", Some("gpl-thing"), None),
+        (Some("bans"), "warning", None, None),
+    ]);
+    assert_eq!(report.diagnostics[0].version.as_deref(), Some("1.0.0"));
+    assert_eq!(report.counts.iter().map(|(k, v)| (k.as_str(), *v)).collect::<Vec<_>>(), [("advisories:error", 1), ("bans:warning", 1), ("licenses:error", 1)]);
+    assert!(!report.own_config);
+}
+
+#[cfg(unix)]
+#[test]
+fn a_default_config_is_written_for_the_run_only_when_the_repo_has_none() {
+    let base = std::env::temp_dir().join(format!("dataset_builder_deny_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&base);
+    // Reports its arguments and the first line of the config it was given.
+    let script = r#"#!/bin/sh
+case "$*" in *--version*) echo "cargo-deny 0.16.1"; exit 0;; esac
+first=$(head -n 1 deny.toml 2>/dev/null)
+printf '{"type":"diagnostic","fields":{"severity":"note","code":"args","message":"%s"}}\n' "$*" >&2
+printf '{"type":"diagnostic","fields":{"severity":"note","code":"config","message":"%s"}}\n' "$first" >&2
+"#;
+    let tools = common::fake_tools(&[("cargo-deny", script)]);
+    for krate in ["plain", "configured"] {
+        let dir = base.join(krate);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("Cargo.toml"), format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n", krate)).unwrap();
+        fs::write(dir.join("src/lib.rs"), "pub fn f() {}\n").unwrap();
+    }
+    fs::write(base.join("configured/deny.toml"), "# ours\n[licenses]\nallow = [\"MIT\"]\n").unwrap();
+
+    let opts = AnalyzeOptions { only: vec!["deny".into()], tool_env: tools.env(), ..AnalyzeOptions::default() };
+    let (selection, run) = (select_analyzers(&opts).unwrap(), RunManifest::capture(&opts));
+    let report = |name: &str| match analyze_repo(&base.join(name), name, &selection, &opts, &run).remove(0).tools.remove("deny").unwrap().report {
+        Some(Report::Deny(r)) => r,
+        other => panic!("no deny report: {:?}", other),
+    };
+    let (plain, configured) = (report("plain"), report("configured"));
+    let left_behind = base.join("plain/deny.toml").exists();
+    let kept = fs::read_to_string(base.join("configured/deny.toml")).unwrap();
+    let _ = fs::remove_dir_all(&base);
+
+    let messages = |r: &dataset_builder::parse::DenyReport| r.diagnostics.iter().map(|d| d.message.clone()).collect::<Vec<_>>();
+    assert!(!plain.own_config && configured.own_config);
+    assert_eq!(messages(&plain), ["deny --format json check advisories bans licenses", "# Written by dataset_builder for one cargo-deny run; not the repository's."]);
+    assert_eq!(messages(&configured), ["deny --format json check", "# ours"]);
+    assert!(!left_behind, "the config written for the run is removed");
+    assert!(kept.starts_with("# ours"));
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+use common::serve;
+
+use dataset_builder::{discover_crates, discover_repos, filter_csv_with, DiscoverCratesOptions, DiscoverOptions, FilterOptions, Tokens};
+
+/// `count` repositories created on each of the given days, searched like GitHub does: at most
+/// 100 per page and 1,000 per query.
+fn search(days: &[(&str, usize)], query: &HashMap<String, String>) -> String {
+    let q = &query["q"];
+    let (from, to) = q.split_whitespace().find_map(|t| t.strip_prefix("created:")).and_then(|r| r.split_once("..")).unwrap();
+    let repos: Vec<String> = days
+        .iter()
+        .filter(|(day, _)| (from..=to).contains(day))
+        .flat_map(|(day, n)| (0..*n).map(move |i| format!(r#"{{"full_name":"o{}/r{}","stargazers_count":{},"size":10,"language":"Rust","license":{{"spdx_id":"MIT"}},"fork":false,"archived":false,"created_at":"{}T00:00:00Z","pushed_at":null}}"#, day, i, i, day)))
+        .collect();
+    let page: usize = query["page"].parse().unwrap();
+    let items: Vec<&String> = repos.iter().take(1000).skip((page - 1) * 100).take(100).collect();
+    format!(r#"{{"total_count":{},"items":[{}]}}"#, repos.len(), items.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(","))
+}
+
+fn options(dir: &std::path::Path, api_url: String) -> DiscoverOptions {
+    DiscoverOptions {
+        query: "language:rust".into(),
+        out: dir.join("repos.csv").display().to_string(),
+        exclude_forks: true,
+        exclude_archived: false,
+        check_files: false,
+        resume: false,
+        retries: 1,
+        api_url,
+    }
+}
+
+#[test]
+fn queries_over_the_cap_are_sliced_by_creation_date() {
+    let days = [("2015-03-03", 5), ("2020-05-01", 700), ("2020-05-02", 800)];
+    let queries = Arc::new(Mutex::new(Vec::new()));
+    let seen = queries.clone();
+    let url = serve(Arc::new(move |r| {
+        seen.lock().unwrap().push(r.query["q"].clone());
+        (200, String::new(), search(&days, &r.query))
+    }));
+    let dir = std::env::temp_dir().join(format!("dataset_builder_discover_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let summary = discover_repos(&options(&dir, url), &Tokens::default()).unwrap();
+    let csv = fs::read_to_string(dir.join("repos.csv")).unwrap();
+    // Without --check-files there are no has_cargo_toml/has_cargo_lock columns, so filter doesn't check them.
+    let filter = FilterOptions { min_stars: Some(600), ..FilterOptions::default() };
+    let filtered = filter_csv_with(dir.join("repos.csv").to_str().unwrap(), dir.join("names.txt").to_str().unwrap(), &filter).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(filtered.kept, 300);
+    assert_eq!(summary.repos, 1505, "every repository is reachable once no range has more than 1,000");
+    assert_eq!(csv.lines().next().unwrap(), "name,stargazers_count,size_kb,language,license,fork,archived,created_at,pushed_at");
+    assert_eq!(csv.lines().count(), 1506);
+    assert!(csv.contains("\no2015-03-03/r4,4,10,Rust,MIT,false,false,2015-03-03T00:00:00Z,\n"), "{}", &csv[..300]);
+    let queries = queries.lock().unwrap();
+    assert!(queries.iter().all(|q| q.starts_with("language:rust fork:false created:")), "{:?}", &queries[..3]);
+}
+
+#[test]
+fn rate_limits_are_waited_out_and_failures_resume_from_the_last_page() {
+    let days = [("2021-01-01", 250)];
+    let state = Arc::new(Mutex::new((0usize, true)));
+    let server = state.clone();
+    let url = serve(Arc::new(move |r| {
+        let mut state = server.lock().unwrap();
+        state.0 += 1;
+        match (state.0, r.query["page"].as_str()) {
+            (1, _) => (403, "Retry-After: 0\r\n".into(), r#"{"message":"You have exceeded a secondary rate limit"}"#.into()),
+            (_, "3") if state.1 => (502, String::new(), "{}".into()),
+            _ => (200, String::new(), search(&days, &r.query)),
+        }
+    }));
+    let dir = std::env::temp_dir().join(format!("dataset_builder_discover_resume_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut opts = options(&dir, url);
+
+    let err = discover_repos(&opts, &Tokens::default()).unwrap_err();
+    let checkpoint = fs::read_to_string(dir.join("checkpoint.json")).unwrap_or_default();
+    state.lock().unwrap().1 = false;
+    opts.resume = true;
+    let resumed = discover_repos(&opts, &Tokens::default()).unwrap();
+    let csv = fs::read_to_string(dir.join("repos.csv")).unwrap();
+    let checkpoint_left = dir.join("checkpoint.json").exists();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(format!("{:#}", err).contains("--resume"), "{:#}", err);
+    assert!(checkpoint.contains("\"discover\"") && checkpoint.contains("#2"), "{}", checkpoint);
+    assert_eq!(resumed.repos, 50, "only the third page is fetched again");
+    assert_eq!(csv.lines().count(), 251, "no repository is written twice");
+    assert!(!checkpoint_left);
+}
+
+#[test]
+fn crates_are_grouped_by_their_github_repository() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_discover_crates_{}", std::process::id()));
+    fs::create_dir_all(dir.join("data")).unwrap();
+    // Newer dumps keep downloads in their own file; the readme column holds whole READMEs.
+    fs::write(
+        dir.join("data/crates.csv"),
+        "created_at,description,id,name,readme,repository\n\
+         2015,x,1,tokio,\"# Tokio\nmulti-line\",https://github.com/tokio-rs/tokio\n\
+         2016,x,2,tokio-macros,,https://github.com/Tokio-rs/tokio.git/tree/master/tokio-macros\n\
+         2017,x,3,tiny,,https://github.com/someone/tiny\n\
+         2018,x,4,elsewhere,,https://gitlab.com/a/b\n\
+         2019,x,5,norepo,,\n",
+    )
+    .unwrap();
+    fs::write(dir.join("data/crate_downloads.csv"), "crate_id,downloads\n1,900000\n2,50000\n3,12\n4,70000\n5,80000\n").unwrap();
+    let opts = DiscoverCratesOptions { db_dump: dir.display().to_string(), out: dir.join("repos.csv").display().to_string(), min_downloads: 10_000 };
+
+    let summary = discover_crates(&opts).unwrap();
+    let csv = fs::read_to_string(dir.join("repos.csv")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(csv, "name,crates,downloads\ntokio-rs/tokio,tokio;tokio-macros,950000\n");
+    assert_eq!((summary.crates, summary.below_min_downloads, summary.not_on_github, summary.repos), (5, 1, 2, 1));
+}
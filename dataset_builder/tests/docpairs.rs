@@ -0,0 +1,107 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use dataset_builder::{extract_doc_pairs, extract_doc_pairs_all, CodeSource, DocPair, DocPairsOptions};
+
+const LIB: &str = r#"/// Adds two numbers.
+///
+/// ```
+/// assert_eq!(add(1, 2), 3);
+/// ```
+pub fn add(a: u8, b: u8) -> u8 {
+    a + b
+}
+
+/// Private, so not a pair.
+fn helper() {}
+
+/**
+ * A point.
+ *
+ *     let p = Point { x: 1 };
+ */
+pub struct Point<T>
+where
+    T: Copy,
+{
+    pub x: T,
+}
+
+impl<T: Copy> Point<T> {
+    /// The x coordinate.
+    pub fn x(&self) -> T {
+        self.x
+    }
+}
+
+/// Hidden.
+#[doc(hidden)]
+pub fn internal() {}
+
+///
+pub const EMPTY: u8 = 0;
+
+pub mod shapes {
+    /// Has an area.
+    pub trait Shape {
+        /// The area.
+        fn area(&self) -> f64;
+    }
+}
+"#;
+
+fn checkout(test: &str) -> PathBuf {
+    common::scratch(test, &[("repo/Cargo.toml", "[package]\nname = \"p\"\n"), ("repo/src/lib.rs", LIB)])
+}
+
+fn pairs(test: &str, opts: &DocPairsOptions) -> Vec<DocPair> {
+    let root = checkout(test);
+    let mut pairs = Vec::new();
+    extract_doc_pairs(&root.join("repo"), &CodeSource { name: "o/p".into(), ..CodeSource::default() }, opts, &mut |p| {
+        pairs.push(p);
+        Ok(())
+    })
+    .unwrap();
+    fs::remove_dir_all(&root).unwrap();
+    pairs
+}
+
+#[test]
+fn pairs_documented_public_items_with_their_signature_and_body() {
+    let pairs = pairs("docpairs_items", &DocPairsOptions::default());
+
+    let paths: Vec<(&str, &str)> = pairs.iter().map(|p| (p.kind.as_str(), p.item_path.as_str())).collect();
+    assert_eq!(paths, [("fn", "crate::add"), ("struct", "crate::Point"), ("fn", "crate::Point<T>::x"), ("trait", "crate::shapes::Shape"), ("fn", "crate::shapes::Shape::area")]);
+    let add = &pairs[0];
+    assert_eq!((add.name.as_str(), add.path.as_str(), add.start_line, add.end_line), ("o/p", "src/lib.rs", 1, 8));
+    assert_eq!(add.doc, "Adds two numbers.\n\n```\nassert_eq!(add(1, 2), 3);\n```");
+    assert_eq!((add.signature.as_str(), add.body.as_deref()), ("fn add(a: u8, b: u8) -> u8", Some("{\n    a + b\n}")));
+    let point = &pairs[1];
+    assert_eq!(point.doc, "A point.\n\n    let p = Point { x: 1 };", "block comments lose their stars, code keeps its indentation");
+    assert_eq!((point.signature.as_str(), point.body.as_deref()), ("struct Point<T>\nwhere\n    T: Copy,", Some("{\n    pub x: T,\n}")));
+    assert_eq!((pairs[4].signature.as_str(), pairs[4].body.as_deref()), ("fn area(&self) -> f64", None));
+}
+
+#[test]
+fn min_doc_chars_drops_trivial_docs() {
+    let pairs = pairs("docpairs_min", &DocPairsOptions { min_doc_chars: 14, ..DocPairsOptions::default() });
+
+    let paths: Vec<&str> = pairs.iter().map(|p| p.item_path.as_str()).collect();
+    assert_eq!(paths, ["crate::add", "crate::Point", "crate::Point<T>::x"]);
+}
+
+#[test]
+fn extract_all_writes_one_line_per_pair() {
+    let root = checkout("docpairs_all");
+    let out = root.join("docpairs.jsonl");
+
+    let summary = extract_doc_pairs_all(root.to_str().unwrap(), out.to_str().unwrap(), &DocPairsOptions { min_doc_chars: 14, ..DocPairsOptions::default() }).unwrap();
+    let written = fs::read_to_string(&out).unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!((summary.repos, summary.files, summary.pairs, summary.short_docs, summary.parse_failed), (1, 1, 3, 2, 0));
+    let first: DocPair = serde_json::from_str(written.lines().next().unwrap()).unwrap();
+    assert_eq!((first.dir.as_str(), written.lines().count()), ("repo", 3));
+}
@@ -0,0 +1,52 @@
+use std::fs;
+
+use dataset_builder::{doctor_checks, AnalyzeOptions, Check, CheckStatus, DoctorOptions, Pipeline};
+
+fn find<'a>(checks: &'a [Check], name: &str) -> &'a Check {
+    checks.iter().find(|c| c.name == name).unwrap_or_else(|| panic!("no `{}` check in {:?}", name, checks))
+}
+
+#[test]
+fn paths_disk_and_token_are_checked_against_the_pipeline() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_doctor_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = |file: &str| dir.join(file).display().to_string();
+    let pipeline = Pipeline {
+        input_csv: path("missing.csv"),
+        datasets_dir: path("not/created/yet"),
+        outputs_file: path("outputs.jsonl"),
+        code_file: path("code.jsonl"),
+        filtered_repos: path("filtered.txt"),
+        analyze: AnalyzeOptions { only: vec!["fmt".into()], ..AnalyzeOptions::default() },
+        ..Pipeline::default()
+    };
+    let checks = doctor_checks(&pipeline, &DoctorOptions { min_free_gb: 0, ..DoctorOptions::default() });
+    let roomy = doctor_checks(&pipeline, &DoctorOptions { min_free_gb: 1 << 40, ..DoctorOptions::default() });
+    let leftovers = fs::read_dir(&dir).unwrap().count();
+    let _ = fs::remove_dir_all(&dir);
+
+    assert_eq!(find(&checks, "input").status, CheckStatus::Fail);
+    assert!(find(&checks, "input").hint.is_some());
+    assert_eq!(find(&checks, "token").status, CheckStatus::Warn, "no token is allowed, but noted");
+    // The checkouts directory doesn't exist yet, so its closest existing ancestor is checked.
+    let writes: Vec<&Check> = checks.iter().filter(|c| c.name.starts_with("write ")).collect();
+    assert!(writes.len() == 1 && writes[0].name == format!("write {}", dir.display()), "{:?}", writes);
+    assert_eq!(writes[0].status, CheckStatus::Pass);
+    assert_eq!(leftovers, 0, "the write probe is removed");
+    assert_eq!(find(&checks, "disk").status, CheckStatus::Pass);
+    assert_eq!(find(&roomy, "disk").status, CheckStatus::Fail);
+}
+
+#[test]
+fn only_selected_tools_are_required() {
+    let opts = AnalyzeOptions { only: vec!["fmt".into()], ..AnalyzeOptions::default() };
+    let checks = doctor_checks(&Pipeline { analyze: opts, ..Pipeline::default() }, &DoctorOptions::default());
+    for tool in ["clippy", "audit", "auditable", "deny", "geiger", "semgrep", "codeql", "nightly"] {
+        assert_ne!(find(&checks, tool).status, CheckStatus::Fail, "{} is not selected", tool);
+    }
+
+    let opts = AnalyzeOptions { only: vec!["clipy".into()], ..AnalyzeOptions::default() };
+    let checks = doctor_checks(&Pipeline { analyze: opts, ..Pipeline::default() }, &DoctorOptions::default());
+    let analyzers = find(&checks, "analyzers");
+    assert!(analyzers.status == CheckStatus::Fail && analyzers.detail.contains("did you mean `clippy`"), "{:?}", analyzers);
+}
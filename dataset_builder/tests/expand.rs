@@ -0,0 +1,88 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use dataset_builder::{collect_code, run_outputs, AnalyzeOptions, ExpandedEntry, OutputEntry, Report, ToolStatus};
+
+fn write_crate(dir: &Path, name: &str, files: &[(&str, &str)]) {
+    fs::create_dir_all(dir).unwrap();
+    fs::write(dir.join("Cargo.toml"), format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n", name)).unwrap();
+    for (path, content) in files {
+        fs::create_dir_all(dir.join(path).parent().unwrap()).unwrap();
+        fs::write(dir.join(path), content).unwrap();
+    }
+}
+
+#[test]
+fn collected_files_carry_the_package_and_target_they_are_compiled_into() {
+    let base = std::env::temp_dir().join(format!("dataset_builder_targets_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&base);
+    let repo = base.join("repo");
+    write_crate(&repo, "app", &[("src/lib.rs", "pub mod util;\n"), ("src/util.rs", "pub fn f() {}\n"), ("src/main.rs", "fn main() {}\n"), ("src/bin/tool/main.rs", "mod cli;\nfn main() {}\n"), ("src/bin/tool/cli.rs", "pub fn run() {}\n"), ("tests/it.rs", "#[test]\nfn t() {}\n")]);
+    write_crate(&repo.join("cli"), "cli", &[("src/main.rs", "mod args;\nfn main() {}\n"), ("src/args.rs", "pub struct Args;\n")]);
+
+    let entries = collect_code(&repo).unwrap();
+    let _ = fs::remove_dir_all(&base);
+
+    let mut targets: Vec<_> = entries.iter().map(|e| (e.path.as_str(), e.package.as_deref(), e.target.as_deref())).collect();
+    targets.sort();
+    assert_eq!(targets, [
+        ("Cargo.toml", None, None),
+        ("cli/Cargo.toml", None, None),
+        ("cli/src/args.rs", Some("cli"), Some("bin:cli")),
+        ("cli/src/main.rs", Some("cli"), Some("bin:cli")),
+        ("src/bin/tool/cli.rs", Some("app"), Some("bin:tool")),
+        ("src/bin/tool/main.rs", Some("app"), Some("bin:tool")),
+        ("src/lib.rs", Some("app"), Some("lib")),
+        ("src/main.rs", Some("app"), Some("bin:app")),
+        ("src/util.rs", Some("app"), Some("lib")),
+        ("tests/it.rs", None, None),
+    ]);
+}
+
+#[cfg(unix)]
+#[test]
+fn each_target_is_expanded_into_its_own_line_and_failures_keep_their_error() {
+    // `cargo +nightly` can't hand the fake to a toolchain that isn't there.
+    if !std::process::Command::new("cargo").args(["+nightly", "--version"]).output().is_ok_and(|o| o.status.success()) {
+        return;
+    }
+    let base = std::env::temp_dir().join(format!("dataset_builder_expand_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&base);
+    let script = r#"#!/bin/sh
+case "$*" in
+  *--version*) echo "cargo-expand 1.0.95";;
+  *--lib*) printf 'pub struct S;\n#[automatically_derived]\nimpl ::core::fmt::Debug for S {}\n';;
+  *"--bin app"*) printf 'fn main() {}\n';;
+  *) echo '   Compiling derive v0.1.0' >&2; echo 'error: proc-macro derive panicked' >&2; exit 101;;
+esac
+"#;
+    let tools = common::fake_tools(&[("cargo-expand", script)]);
+    let repo = base.join("datasets/app");
+    write_crate(&repo, "app", &[("src/lib.rs", "#[derive(Debug)]\npub struct S;\n"), ("src/main.rs", "fn main() {}\n"), ("src/bin/tool.rs", "fn main() {}\n")]);
+    let root = base.join("datasets").display().to_string();
+    let outputs = base.join("outputs.jsonl").display().to_string();
+    let opts = AnalyzeOptions { only: vec!["expand".into()], max_expanded_bytes: Some(30), tool_env: tools.env(), ..AnalyzeOptions::default() };
+
+    run_outputs(&root, &outputs, &opts).unwrap();
+    let lines: Vec<ExpandedEntry> = fs::read_to_string(base.join("expanded.jsonl")).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let entry: OutputEntry = serde_json::from_str(fs::read_to_string(&outputs).unwrap().lines().next().unwrap()).unwrap();
+    let code = collect_code(&repo).unwrap();
+    let _ = fs::remove_dir_all(&base);
+
+    let expanded: Vec<_> = lines.iter().map(|l| (l.dir.as_str(), l.source.target.as_str(), l.source.content.as_str(), l.source.bytes, l.source.truncated)).collect();
+    assert_eq!(expanded, [("app", "lib", "pub struct S;\n", 72, true), ("app", "bin:app", "fn main() {}\n", 13, false)]);
+    for line in &lines {
+        assert!(code.iter().any(|c| c.dir == line.dir && c.package.as_deref() == Some(line.source.package.as_str()) && c.target.as_deref() == Some(line.source.target.as_str())));
+    }
+    let out = &entry.tools["expand"];
+    assert!(matches!(out.result.status, ToolStatus::Completed) && out.result.stdout.is_empty());
+    match &out.report {
+        Some(Report::Expand(r)) => {
+            assert_eq!((r.package.as_str(), r.expanded, r.failed), ("app", 2, 1));
+            assert_eq!(r.targets.iter().map(|t| (t.target.as_str(), t.error.as_deref())).collect::<Vec<_>>(), [("lib", None), ("bin:app", None), ("bin:tool", Some("error: proc-macro derive panicked"))]);
+        }
+        other => panic!("no expand report: {:?}", other),
+    }
+}
@@ -0,0 +1,167 @@
+use std::fs;
+use std::path::Path;
+
+use dataset_builder::{filter_csv, filter_csv_with, FilterOptions, FilterSummary, InputFormat};
+
+#[test]
+fn keeps_only_repos_with_manifest_and_lockfile() {
+    let out = std::env::temp_dir().join(format!("dataset_builder_filter_{}.txt", std::process::id()));
+    let input = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/repos.csv");
+
+    filter_csv(input.to_str().unwrap(), out.to_str().unwrap()).unwrap();
+
+    let names = fs::read_to_string(&out).unwrap();
+    fs::remove_file(&out).unwrap();
+    assert_eq!(names.lines().collect::<Vec<_>>(), ["rust-lang/rust", "tokio-rs/tokio"]);
+}
+
+fn filter_text(test: &str, csv: &str, opts: &FilterOptions) -> (anyhow::Result<FilterSummary>, Vec<String>) {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_filter_{}_{}", test, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("input.csv"), csv).unwrap();
+    let out = dir.join("out.txt");
+    let res = filter_csv_with(dir.join("input.csv").to_str().unwrap(), out.to_str().unwrap(), opts);
+    let names = fs::read_to_string(&out).unwrap_or_default().lines().map(String::from).collect();
+    fs::remove_dir_all(&dir).unwrap();
+    (res, names)
+}
+
+#[test]
+fn headerless_files_are_read_by_position() {
+    let (res, names) = filter_text("headerless", "1,a/keep,true,true\n2,b/drop,true,false\n", &FilterOptions::default());
+    assert_eq!(res.unwrap().rows, 2, "the first row is data, not a header");
+    assert_eq!(names, ["a/keep"]);
+}
+
+#[test]
+fn reordered_columns_are_matched_by_name_and_missing_flags_are_false() {
+    let csv = "full_name,stars,has_cargo_lock,HAS_CARGO_TOML\na/keep,10,true,true\nb/no-toml,3,true,\nc/short,1\n";
+    let (res, names) = filter_text("reordered", csv, &FilterOptions::default());
+    let summary = res.unwrap();
+    assert_eq!(names, ["a/keep"]);
+    assert_eq!((summary.rows, summary.kept, summary.missing_flags), (3, 1, 2));
+}
+
+#[test]
+fn column_overrides_map_arbitrary_schemas() {
+    let opts = FilterOptions { name_column: Some("repo".into()), toml_column: Some("manifest".into()), lock_column: Some("lockfile".into()), ..FilterOptions::default() };
+    let csv = "lockfile,repo,name,manifest\ntrue,a/keep,ignored,true\nfalse,b/drop,ignored,true\n";
+    let (res, names) = filter_text("overrides", csv, &opts);
+    assert_eq!(res.unwrap().kept, 1);
+    assert_eq!(names, ["a/keep"], "`name` is replaced by --name-column");
+
+    let headerless = FilterOptions { name_column: Some("0".into()), toml_column: Some("2".into()), lock_column: Some("1".into()), ..FilterOptions::default() };
+    let (_, names) = filter_text("by_index", "a/keep,true,true\nb/drop,false,true\n", &headerless);
+    assert_eq!(names, ["a/keep"]);
+
+    let missing = FilterOptions { name_column: Some("repo".into()), ..FilterOptions::default() };
+    let err = filter_text("missing", "name,has_toml,has_lock\na/b,true,true\n", &missing).0.unwrap_err();
+    assert!(err.to_string().contains("column `repo` not found"), "{}", err);
+}
+
+#[test]
+fn rejects_malformed_rows() {
+    let csv = "id,name,has_toml,has_lock\n1, a/keep ,YES,1\n2,b/bad,maybe,true\n3,,true,true\n4,c/keep,t,True\n";
+    let strict = FilterOptions { strict: true, ..FilterOptions::default() };
+    let err = filter_text("strict", csv, &strict).0.unwrap_err();
+    assert!(format!("{:#}", err).contains("bad row on line 3"), "{:#}", err);
+
+    let dir = std::env::temp_dir().join(format!("dataset_builder_filter_rejected_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let rejected = dir.join("rejected.csv");
+    let opts = FilterOptions { rejected_file: Some(rejected.display().to_string()), ..FilterOptions::default() };
+    let (res, names) = filter_text("tolerant", csv, &opts);
+    let report = fs::read_to_string(&rejected).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    let summary = res.unwrap();
+    assert_eq!(names, ["a/keep", "c/keep"]);
+    assert_eq!(summary.to_string(), "4 rows read, 2 kept, 2 rejected");
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", report);
+    assert!(lines[1].starts_with("3,") && lines[1].contains("`maybe` is not a boolean") && lines[1].contains("b/bad"), "{}", report);
+    assert!(lines[2].starts_with("4,") && lines[2].contains("empty repository name"), "{}", report);
+}
+
+#[test]
+fn predicates_narrow_the_selection_and_count_what_they_eliminate() {
+    let csv = "full_name,stargazers_count,size_kb,language,pushed_at,has_cargo_toml,has_cargo_lock\n\
+        a/keep,120,500,Rust,2023-04-01T10:00:00Z,true,true\n\
+        b/few-stars,3,500,Rust,2023-04-01T10:00:00Z,true,true\n\
+        c/old-and-big,500,99999,rust,2019-01-01 00:00:00 UTC,true,true\n\
+        d/no-language,500,10,,2023-01-01,true,true\n\
+        e/no-lock,1,10,Go,2023-01-01,true,false\n";
+    let opts = FilterOptions {
+        min_stars: Some(10),
+        max_size_kb: Some(1000),
+        language: Some("rust".into()),
+        pushed_after: "2022-01-01".parse().ok(),
+        ..FilterOptions::default()
+    };
+    let (res, names) = filter_text("predicates", csv, &opts);
+    let summary = res.unwrap();
+    assert_eq!(names, ["a/keep"]);
+    let eliminated: Vec<(&str, usize)> = summary.eliminated.iter().map(|(f, n)| (f.as_str(), *n)).collect();
+    assert_eq!(eliminated, [("--min-stars 10", 1), ("--max-size-kb 1000", 1), ("--language rust", 1), ("--pushed-after 2022-01-01", 1)]);
+
+    // Unused columns aren't parsed; a flag's missing column is an error before any row is read.
+    let (res, names) = filter_text("unused", "name,stars,has_toml,has_lock\na/b,lots,true,true\n", &FilterOptions::default());
+    assert_eq!((res.unwrap().rejected, names.len()), (0, 1));
+    let err = filter_text("no_column", "name,has_toml,has_lock\na/b,true,true\n", &FilterOptions { min_stars: Some(1), ..FilterOptions::default() }).0.unwrap_err();
+    assert!(err.to_string().contains("--min-stars 1 needs a stargazers_count column"), "{}", err);
+}
+
+#[test]
+fn names_are_canonicalized_and_deduplicated() {
+    let csv = "name,has_toml,has_lock\n\
+        https://github.com/Owner/Repo.git/,true,true\n\
+        owner/repo,true,true\n\
+        git@github.com:a/b.git,true,true\n\
+        github.com/c/d/,true,true\n\
+        no-owner,true,true\n\
+        a/b/c,true,true\n\
+        bad/na me,true,true\n";
+    let dir = std::env::temp_dir().join(format!("dataset_builder_filter_names_rejected_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let rejected = dir.join("rejected.csv");
+    let opts = FilterOptions { rejected_file: Some(rejected.display().to_string()), ..FilterOptions::default() };
+    let (res, names) = filter_text("names", csv, &opts);
+    let report = fs::read_to_string(&rejected).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(names, ["Owner/Repo", "a/b", "c/d"]);
+    assert_eq!(res.unwrap().to_string(), "7 rows read, 3 kept, 3 rejected, 1 duplicates");
+    let errors: Vec<&str> = report.lines().skip(1).collect();
+    assert!(errors[0].starts_with("3,duplicate of Owner/Repo (line 2)"), "{}", report);
+    assert!(errors[1].contains("`no-owner` has no owner") && errors[2].contains("`a/b/c` is not an owner/repo name"), "{}", report);
+}
+
+#[test]
+fn jsonl_rows_and_stdin_are_read_like_csv() {
+    let jsonl = "{\"full_name\":\"a/keep\",\"has_cargo_toml\":true,\"has_cargo_lock\":true,\"owner\":{\"login\":\"a\"}}\n\
+        \n\
+        {\"full_name\":\"b/drop\",\"has_cargo_toml\":true,\"has_cargo_lock\":null}\n\
+        not json\n\
+        {\"repo\":\"c/keep\",\"has_toml\":\"yes\",\"has_lock\":1}\n";
+    let opts = FilterOptions { format: Some(InputFormat::Jsonl), ..FilterOptions::default() };
+    let (res, names) = filter_text("jsonl", jsonl, &opts);
+    assert_eq!(names, ["a/keep"]);
+    assert_eq!(res.unwrap().to_string(), "4 rows read, 1 kept, 2 rejected", "blank lines aren't rows; a line without a name field is rejected");
+
+    let dir = std::env::temp_dir().join(format!("dataset_builder_filter_stdin_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("out.txt");
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_dataset_builder"))
+        .args(["--quiet", "filter", "-", out.to_str().unwrap()])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    std::io::Write::write_all(&mut child.stdin.take().unwrap(), jsonl.as_bytes()).unwrap();
+    let status = child.wait().unwrap();
+    let names = fs::read_to_string(&out).unwrap();
+    let rejected = dir.join("rejected_rows.csv").exists();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(status.success());
+    assert_eq!(names, "a/keep\n", "stdin starting with `{{` is read as JSONL");
+    assert!(rejected);
+}
@@ -0,0 +1,63 @@
+use std::fs;
+use std::path::Path;
+
+use dataset_builder::parse::{parse_fmt, FmtReport};
+use dataset_builder::{analyze_repo, select_analyzers, AnalyzeOptions, Report, RunManifest, ToolResult, ToolStatus};
+
+fn result(exit_code: i32, stdout: &str, stderr: &str) -> ToolResult {
+    ToolResult { status: ToolStatus::Completed, exit_code: Some(exit_code), stdout: stdout.into(), stderr: stderr.into(), error: None, parse_error: false, truncated: false, usage: None }
+}
+
+#[test]
+fn check_output_becomes_per_file_counts_and_unified_diffs() {
+    // Two hunks in one file, the first adding a line, and another file in the older header form.
+    let stdout = "Diff in /work/repo/src/lib.rs:1:\n-fn f(){let x=1;}\n+fn f() {\n+    let x = 1;\n+}\n \nDiff in /work/repo/src/lib.rs:10:\n-fn g( ) {}\n+fn g() {}\nDiff in /work/repo/src/main.rs at line 3:\n-use b;use a;\n+use a;\n+use b;\n";
+    let root = Path::new("/work/repo");
+
+    let counted = parse_fmt(&result(1, stdout, ""), root, false);
+    let captured = parse_fmt(&result(1, stdout, ""), root, true);
+    let clean = parse_fmt(&result(0, "", ""), root, true);
+    let broken = parse_fmt(&result(1, "", "error: expected item, found `}`\n"), root, true);
+
+    let files: Vec<_> = counted.files.iter().map(|f| (f.path.as_str(), f.hunks, f.lines_removed, f.lines_added, f.diff.is_some())).collect();
+    assert_eq!(files, [("src/lib.rs", 2, 2, 4, false), ("src/main.rs", 1, 1, 2, false)]);
+    assert!(!counted.formatted && !counted.failed);
+    let diff = captured.files[0].diff.as_deref().unwrap();
+    assert_eq!(diff, "--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -1,2 +1,4 @@\n-fn f(){let x=1;}\n+fn f() {\n+    let x = 1;\n+}\n \n@@ -10,1 +12,1 @@\n-fn g( ) {}\n+fn g() {}\n");
+    assert!(clean.formatted && clean.files.is_empty());
+    assert!(broken.failed && !broken.formatted && broken.files.is_empty());
+}
+
+fn fmt_report(base: &Path, name: &str, source: &str, rustfmt_toml: Option<&str>, opts: &AnalyzeOptions) -> FmtReport {
+    let dir = base.join(name);
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(dir.join("Cargo.toml"), format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n", name)).unwrap();
+    fs::write(dir.join("src/lib.rs"), source).unwrap();
+    if let Some(config) = rustfmt_toml {
+        fs::write(dir.join("rustfmt.toml"), config).unwrap();
+    }
+    let mut entries = analyze_repo(&dir, name, &select_analyzers(opts).unwrap(), opts, &RunManifest::capture(opts));
+    match entries.remove(0).tools.remove("fmt").unwrap().report {
+        Some(Report::Fmt(r)) => r,
+        other => panic!("no fmt report: {:?}", other),
+    }
+}
+
+#[test]
+fn diffs_are_captured_unless_the_crate_needs_nightly_options() {
+    let base = std::env::temp_dir().join(format!("dataset_builder_fmt_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&base);
+    let opts = AnalyzeOptions { only: vec!["fmt".into()], fmt_capture_diff: true, ..AnalyzeOptions::default() };
+    let messy = "pub fn f(){let x=1;\nlet _ = x;}\n";
+
+    let tidy = fmt_report(&base, "tidy", "pub fn f() {}\n", None, &opts);
+    let plain = fmt_report(&base, "plain", messy, None, &opts);
+    let nightly = fmt_report(&base, "nightly", messy, Some("imports_granularity = \"Crate\"\n"), &opts);
+    let _ = fs::remove_dir_all(&base);
+
+    assert!(tidy.formatted && tidy.files.is_empty());
+    assert_eq!((plain.files.len(), plain.files[0].path.as_str(), plain.files[0].lines_removed), (1, "src/lib.rs", 2));
+    assert!(plain.files[0].diff.as_deref().is_some_and(|d| d.starts_with("--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -1,3 +1,5 @@\n-pub fn f(){let x=1;\n")), "{:?}", plain.files[0].diff);
+    assert_eq!(nightly.unstable_options, ["imports_granularity"]);
+    assert!(!nightly.formatted && nightly.files[0].diff.is_none());
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use dataset_builder::{collect_code_all, collect_code_with, flagged_files_beside, generated_by, is_vendored, CodeEntry, CollectOptions, FlaggedFiles, SkipReason};
+
+fn checkout(test: &str) -> PathBuf {
+    let derived = "#[automatically_derived]\nimpl Clone for A { fn clone(&self) -> A { A } }\n".repeat(5);
+    let files = [
+        ("repo/Cargo.toml", "[package]\nname = \"p\"\n".to_string()),
+        ("repo/src/lib.rs", "pub mod bindings;\n".to_string()),
+        ("repo/src/bindings.rs", "/* automatically generated by rust-bindgen 0.69.4 */\n\npub const A: u32 = 1;\n".to_string()),
+        ("repo/src/api.pb.rs", "pub struct Request {}\n".to_string()),
+        ("repo/src/expanded.rs", derived),
+        ("repo/src/schema.rs", "// Produced by our-codegen; edits are lost.\npub struct Schema;\n".to_string()),
+        ("repo/vendor/dep/src/lib.rs", "pub fn dep() {}\n".to_string()),
+    ];
+    common::scratch(test, &files)
+}
+
+#[test]
+fn flags_generated_files_by_name_marker_or_derives() {
+    assert_eq!(generated_by(Path::new("src/proto/api.pb.rs"), "", &[]), Some("path"));
+    assert_eq!(generated_by(Path::new("src/schema_generated.rs"), "", &[]), Some("path"));
+    assert_eq!(generated_by(Path::new("src/lib.rs"), "// @generated by prost-build\n", &[]), Some("marker"));
+    assert_eq!(generated_by(Path::new("src/lib.rs"), "// This file is generated. Do not edit.\n", &[]), Some("marker"));
+    assert_eq!(generated_by(Path::new("src/lib.rs"), &format!("{}// @generated\n", "\n".repeat(20)), &[]), None, "markers only count near the top");
+    assert_eq!(generated_by(Path::new("src/lib.rs"), "// OUR-CODEGEN output\n", &["our-codegen".into()]), Some("marker"));
+    assert_eq!(generated_by(Path::new("src/lib.rs"), &"#[automatically_derived]\nimpl X for Y {}\n".repeat(5), &[]), Some("automatically_derived"));
+    assert_eq!(generated_by(Path::new("src/lib.rs"), &format!("#[automatically_derived]\nimpl X for Y {{}}\n{}", "fn f() {}\n".repeat(500)), &[]), None);
+    assert!(is_vendored(Path::new("vendor/serde/src/lib.rs")) && is_vendored(Path::new("crates/third-party/x.rs")));
+    assert!(!is_vendored(Path::new("src/vendor.rs")) && !is_vendored(Path::new("src/vendoring/mod.rs")));
+}
+
+#[test]
+fn collect_flags_or_skips_generated_and_vendored_files() {
+    let root = checkout("generated_collect");
+    let repo = root.join("repo");
+    let flagged = collect_code_with(&repo, &CollectOptions::default()).unwrap();
+    let skipped = collect_code_with(&repo, &CollectOptions { skip_generated: true, skip_vendored: true, generated_marker: vec!["our-codegen".into()], ..CollectOptions::default() }).unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    let mut flags: Vec<(&str, Option<&str>, bool)> = flagged.entries.iter().map(|e| (e.path.as_str(), e.generated_by.as_deref(), e.vendored)).collect();
+    flags.sort();
+    assert_eq!(
+        flags,
+        [
+            ("Cargo.toml", None, false),
+            ("src/api.pb.rs", Some("path"), false),
+            ("src/bindings.rs", Some("marker"), false),
+            ("src/expanded.rs", Some("automatically_derived"), false),
+            ("src/lib.rs", None, false),
+            ("src/schema.rs", None, false),
+            ("vendor/dep/src/lib.rs", None, true)
+        ]
+    );
+    assert!(flagged.entries.iter().all(|e| e.generated == e.generated_by.is_some()));
+    assert_eq!((flagged.flagged.generated, flagged.flagged.vendored, flagged.flagged.skipped), (3, 1, 0));
+    let mut left: Vec<&str> = skipped.entries.iter().map(|e| e.path.as_str()).collect();
+    left.sort();
+    assert_eq!(left, ["Cargo.toml", "src/lib.rs"]);
+    let reasons: Vec<SkipReason> = skipped.skipped.iter().map(|s| s.reason).collect();
+    assert_eq!((reasons.iter().filter(|r| **r == SkipReason::Generated).count(), reasons.iter().filter(|r| **r == SkipReason::Vendored).count()), (4, 1));
+    assert_eq!(skipped.flagged.skipped, 5);
+}
+
+#[test]
+fn collect_writes_per_repository_counts_beside_the_code_file() {
+    let root = checkout("generated_report");
+    let out = root.join("code.jsonl");
+
+    collect_code_all(root.to_str().unwrap(), out.to_str().unwrap(), &CollectOptions { skip_vendored: true, ..CollectOptions::default() }).unwrap();
+    let report = fs::read_to_string(flagged_files_beside(out.to_str().unwrap())).unwrap();
+    let code = fs::read_to_string(&out).unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    let lines: Vec<FlaggedFiles> = report.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(lines.len(), 1);
+    assert_eq!((lines[0].dir.as_str(), lines[0].generated, lines[0].vendored, lines[0].skipped), ("repo", 3, 1, 1));
+    let by: Vec<(&str, usize)> = lines[0].generated_by.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+    assert_eq!(by, [("automatically_derived", 1), ("marker", 1), ("path", 1)]);
+    let entries: Vec<CodeEntry> = code.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(entries.iter().filter(|e| e.generated).count(), 3);
+    assert!(entries.iter().all(|e| !e.vendored));
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use dataset_builder::{export_hf, parquet_rows, split_dataset, ExportHfOptions, SplitOptions};
+use serde_json::{json, Value};
+
+fn code(repo: usize, file: usize) -> String {
+    let license = if repo == 0 { "Apache-2.0" } else { "MIT" };
+    format!(r#"{{"name":"owner/repo{}","dir":"dataset_repo{}","path":"src/f{}.rs","kind":"lib","content":"fn f() {{}}","lines":1,"bytes":9,"content_hash":"h","license_spdx":"{}"}}"#, repo, repo, file, license) + "\n"
+}
+
+const OUTPUT: &str = r#"{"schema_version":4,"run_id":"r","name":"owner/repo0","dir":"dataset_repo0","project_manifest":"Cargo.toml","tools":{"semgrep":{"status":"completed","exit_code":0}},"findings":[],"toolchain":"stable","time_ms":{},"errors":[]}"#;
+
+fn inputs(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_{}_{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("code.jsonl"), (0..3).flat_map(|r| (0..2).map(move |f| code(r, f))).collect::<String>()).unwrap();
+    fs::write(dir.join("outputs.jsonl"), [OUTPUT, "\n"].concat()).unwrap();
+    fs::write(dir.join("manifest.json"), r#"{"run_id":"r","tools":{"versions":{"semgrep":"1.50.0","codeql":null}}}"#).unwrap();
+    dir
+}
+
+#[test]
+fn export_writes_parquet_shards_infos_and_a_card() {
+    let dir = inputs("hf_export");
+    let path = |f: &str| dir.join(f).display().to_string();
+    let map = split_dataset(&SplitOptions { input: path("code.jsonl"), out_dir: Some(path("splits")), train: 0.5, val: 0.0, test: 0.5, seed: 3, ..SplitOptions::default() }).unwrap();
+    let opts = ExportHfOptions { code: Some(path("code.jsonl")), outputs: Some(path("outputs.jsonl")), splits: Some(path("splits/splits.json")), out: path("hf"), shard_rows: 3, ..ExportHfOptions::default() };
+
+    let summary = export_hf(&opts).unwrap();
+    let shards: Vec<(String, usize)> = summary.splits["code"].values().flat_map(|s| &s.shards).map(|s| (s.clone(), parquet_rows(&path(&format!("hf/{}", s))).unwrap())).collect();
+    let infos: Value = serde_json::from_str(&fs::read_to_string(path("hf/dataset_infos.json")).unwrap()).unwrap();
+    let card = fs::read_to_string(path("hf/README.md")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let train = map.counts["train"].files;
+    assert_eq!(shards.iter().map(|(_, rows)| rows).sum::<usize>(), 6);
+    assert!(shards.iter().all(|(s, rows)| *rows <= 3 && s.starts_with("data/code/") && s.contains("-of-")), "{:?}", shards);
+    assert_eq!(infos["code"]["splits"]["train"]["num_examples"], json!(train));
+    assert_eq!((infos["code"]["features"]["content"]["dtype"].as_str(), infos["outputs"]["features"]["findings"]["_type"].as_str()), (Some("string"), Some("Sequence")));
+    assert!(card.contains("- config_name: code\n  data_files:\n") && card.contains(&format!("path: data/outputs/{}-*.parquet", map.repos["owner/repo0"])), "{}", card);
+    assert!(card.contains("license: other") && card.contains("| MIT | 2 |") && card.contains("| Apache-2.0 | 1 |"), "{}", card);
+    assert!(card.contains("| semgrep | 1.50.0 |") && card.contains("| codeql | not installed |"), "{}", card);
+}
+
+#[test]
+fn push_resumes_with_the_shards_the_hub_is_missing() {
+    let dir = inputs("hf_push");
+    let path = |f: &str| dir.join(f).display().to_string();
+    let (url_cell, stored, puts, seen) = (Arc::new(Mutex::new(String::new())), Arc::new(Mutex::new(HashSet::new())), Arc::new(Mutex::new(0)), Arc::new(Mutex::new(Vec::new())));
+    let (u, st, p, s) = (url_cell.clone(), stored.clone(), puts.clone(), seen.clone());
+    let url = common::serve(Arc::new(move |r: &common::Request| {
+        s.lock().unwrap().push((r.method.clone(), r.path.clone(), String::from_utf8_lossy(&r.body).into_owned(), r.headers.get("authorization").cloned()));
+        match r.path.as_str() {
+            "/api/repos/create" if st.lock().unwrap().is_empty() => (200, String::new(), "{}".into()),
+            "/api/repos/create" => (409, String::new(), r#"{"error":"exists"}"#.into()),
+            "/datasets/org/corpus.git/info/lfs/objects/batch" => {
+                let body: Value = serde_json::from_slice(&r.body).unwrap();
+                let oid = body["objects"][0]["oid"].as_str().unwrap().to_string();
+                let actions = if st.lock().unwrap().contains(&oid) { json!({}) } else { json!({"upload": {"href": format!("{}/upload/{}", u.lock().unwrap(), oid), "header": {"x-amz": "1"}}}) };
+                (200, String::new(), json!({"objects": [{"oid": oid, "size": body["objects"][0]["size"], "actions": actions}]}).to_string())
+            }
+            upload if upload.starts_with("/upload/") => {
+                *p.lock().unwrap() += 1;
+                // A network failure on the second shard of the first push.
+                if *p.lock().unwrap() == 2 {
+                    return (500, String::new(), "{}".into());
+                }
+                st.lock().unwrap().insert(upload.trim_start_matches("/upload/").to_string());
+                (200, String::new(), String::new())
+            }
+            "/api/datasets/org/corpus/commit/main" => (200, String::new(), r#"{"commitOid":"c"}"#.into()),
+            _ => (404, String::new(), "{}".into()),
+        }
+    }));
+    *url_cell.lock().unwrap() = url.clone();
+    fs::write(dir.join("hf_token"), "hf_secret\n").unwrap();
+    let opts = ExportHfOptions { code: Some(path("code.jsonl")), out: path("hf"), shard_rows: 3, repo_id: Some("org/corpus".into()), hf_token: format!("file:{}", path("hf_token")), private: true, hf_endpoint: url, retries: 0, ..ExportHfOptions::default() };
+
+    let refused = export_hf(&ExportHfOptions { hf_token: "hf_on_argv".into(), ..opts.clone() }).unwrap_err();
+    let failed = export_hf(&opts).unwrap_err();
+    let resumed = export_hf(&opts).unwrap();
+    let requests = seen.lock().unwrap().clone();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let refused = format!("{:#}", refused);
+    assert!(refused.contains("--hf-token env:NAME") && !refused.contains("hf_on_argv"), "{}", refused);
+    assert!(format!("{:#}", failed).contains("answered 500"), "{:#}", failed);
+    assert_eq!((resumed.uploaded, resumed.already_uploaded), (1, 1));
+    let create: Value = serde_json::from_str(&requests[0].2).unwrap();
+    assert_eq!((create["name"].as_str(), create["organization"].as_str(), create["private"].as_bool(), create["type"].as_str()), (Some("corpus"), Some("org"), Some(true), Some("dataset")));
+    assert!(requests.iter().filter(|r| !r.1.starts_with("/upload/")).all(|r| r.3.as_deref() == Some("Bearer hf_secret")));
+    let commits: Vec<&String> = requests.iter().filter(|r| r.1.ends_with("/commit/main")).map(|r| &r.2).collect();
+    assert_eq!(commits.len(), 1, "the failed push didn't commit");
+    let lines: Vec<Value> = commits[0].lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let files: Vec<(&str, &str)> = lines.iter().skip(1).map(|l| (l["key"].as_str().unwrap(), l["value"]["path"].as_str().unwrap())).collect();
+    assert_eq!(files, [("lfsFile", "data/code/train-00000-of-00002.parquet"), ("lfsFile", "data/code/train-00001-of-00002.parquet"), ("file", "README.md"), ("file", "dataset_infos.json")]);
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use dataset_builder::parse::{parse_machete, parse_outdated, parse_udeps};
+use dataset_builder::{analyze_repo, select_analyzers, AnalyzeOptions, Report, RunManifest, ToolStatus};
+
+#[test]
+fn udeps_outdated_and_machete_output_is_parsed_per_crate() {
+    let root = Path::new("/work/repo");
+    let udeps = r#"{"success":false,"unused_deps":{"app 0.1.0 (path+file:///work/repo/app)":{"manifest_path":"/work/repo/app/Cargo.toml","normal":["log","regex"],"development":["tempfile"],"build":[]}},"note":"Note: They might be false-positive."}"#;
+    let outdated = concat!(
+        r#"{"crate_name":"app","dependencies":[{"name":"clap","project":"2.34.0","compat":"2.34.0","latest":"4.5.1","kind":"Normal","platform":null},"#,
+        r#"{"name":"serde","project":"1.0.100","compat":"1.0.200","latest":"1.0.200","kind":"Normal","platform":null},"#,
+        r#"{"name":"gone","project":"0.1.0","compat":"---","latest":"Removed","kind":"Development","platform":null}]}"#,
+        "\n",
+    );
+    let machete = "Analyzing dependencies of crates in this directory...\ncargo-machete found the following unused dependencies in /work/repo:\napp -- /work/repo/app/Cargo.toml:\n\tlog\n\tonce_cell\ncore -- /work/repo/core/Cargo.toml:\n\tbytes\n\nIf you believe cargo-machete has detected an unused dependency incorrectly,\nyou can add the dependency to the list of dependencies to ignore in the\n`[package.metadata.cargo-machete]` section of the appropriate Cargo.toml.\n\nDone!\n";
+
+    let udeps = parse_udeps(udeps, root).unwrap();
+    let outdated = parse_outdated(outdated).unwrap();
+    let machete = parse_machete(machete, root).unwrap();
+
+    assert_eq!((udeps.unused, udeps.unused_deps[0].package.as_str(), udeps.unused_deps[0].manifest_path.as_str()), (3, "app", "app/Cargo.toml"));
+    assert_eq!((udeps.unused_deps[0].normal.clone(), udeps.unused_deps[0].development.clone()), (vec!["log".to_string(), "regex".into()], vec!["tempfile".to_string()]));
+    let steps: Vec<_> = outdated.dependencies.iter().map(|d| (d.name.as_str(), d.step.as_str(), d.steps)).collect();
+    assert_eq!(steps, [("clap", "major", 2), ("serde", "patch", 100)]);
+    assert_eq!((outdated.behind, outdated.by_step.get("major").copied()), (2, Some(1)));
+    let unused: Vec<_> = machete.manifests.iter().map(|m| (m.package.as_str(), m.manifest_path.as_str(), m.normal.len())).collect();
+    assert_eq!((unused, machete.unused), (vec![("app", "app/Cargo.toml", 2), ("core", "core/Cargo.toml", 1)], 3));
+    assert!(parse_udeps("error: could not compile `app`", root).is_none() && parse_outdated("").is_none() && parse_machete("Analyzing dependencies...\n", root).is_none());
+}
+
+#[cfg(unix)]
+#[test]
+fn machete_runs_once_per_checkout_with_its_own_time_when_enabled() {
+    let base = std::env::temp_dir().join(format!("dataset_builder_hygiene_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&base);
+    let script = "#!/bin/sh\ncase \"$*\" in *--version*) echo \"cargo-machete 0.7.0\"; exit 0;; esac\nprintf 'ws -- %s/Cargo.toml:\\n\\tunused_dep\\n\\nDone!\\n' \"$(pwd -P)\"\nexit 1\n";
+    let tools = common::fake_tools(&[("cargo-machete", script)]);
+    let dir = base.join("ws");
+    for member in ["a", "b"] {
+        fs::create_dir_all(dir.join(member).join("src")).unwrap();
+        fs::write(dir.join(member).join("Cargo.toml"), format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n", member)).unwrap();
+        fs::write(dir.join(member).join("src/lib.rs"), "pub fn f() {}\n").unwrap();
+    }
+    fs::write(dir.join("Cargo.toml"), "[workspace]\nmembers = [\"a\", \"b\"]\nresolver = \"2\"\n").unwrap();
+
+    let default = AnalyzeOptions { only: vec!["check".into()], tool_env: tools.env(), ..AnalyzeOptions::default() };
+    let opts = AnalyzeOptions { enable: vec!["machete".into()], ..default.clone() };
+    let off = analyze_repo(&dir, "ws", &select_analyzers(&default).unwrap(), &default, &RunManifest::capture(&default));
+    let entries = analyze_repo(&dir, "ws", &select_analyzers(&opts).unwrap(), &opts, &RunManifest::capture(&opts));
+    let _ = fs::remove_dir_all(&base);
+
+    assert!(off.iter().all(|e| matches!(e.tools["machete"].result.status, ToolStatus::Skipped)), "opt-in");
+    assert_eq!(entries.len(), 2);
+    for entry in &entries {
+        let out = &entry.tools["machete"];
+        assert!(matches!(out.result.status, ToolStatus::Completed) && !out.result.parse_error);
+        match &out.report {
+            Some(Report::Machete(r)) => assert_eq!((r.manifests[0].manifest_path.as_str(), r.manifests[0].normal.clone()), ("Cargo.toml", vec!["unused_dep".to_string()])),
+            other => panic!("no machete report: {:?}", other),
+        }
+        assert!(entry.time_ms.contains_key("machete"));
+    }
+}
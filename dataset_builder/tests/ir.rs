@@ -0,0 +1,46 @@
+use std::fs;
+
+use dataset_builder::{run_outputs, AnalyzeOptions, IrEntry, OutputEntry, Report};
+
+#[test]
+fn a_target_that_does_not_compile_leaves_the_others_dumps() {
+    if !std::process::Command::new("cargo").args(["+nightly", "--version"]).output().is_ok_and(|o| o.status.success()) {
+        return;
+    }
+    let base = std::env::temp_dir().join(format!("dataset_builder_ir_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&base);
+    let repo = base.join("datasets/app");
+    fs::create_dir_all(repo.join("src/bin")).unwrap();
+    fs::write(repo.join("Cargo.toml"), "[package]\nname = \"app\"\nversion = \"0.1.0\"\nedition = \"2021\"\n").unwrap();
+    fs::write(repo.join("src/lib.rs"), "pub fn add(a: u8, b: u8) -> u8 {\n    a + b\n}\n").unwrap();
+    fs::write(repo.join("src/main.rs"), "fn main() {\n    println!(\"{}\", app::add(1, 2));\n}\n").unwrap();
+    fs::write(repo.join("src/bin/broken.rs"), "fn main() {\n    let x: u8 = \"no\";\n}\n").unwrap();
+    let root = base.join("datasets").display().to_string();
+    let outputs = base.join("outputs.jsonl").display().to_string();
+    let opts = AnalyzeOptions { only: vec!["ast".into()], emit: vec!["hir".into(), "mir".into()], max_ir_bytes: Some(400), ..AnalyzeOptions::default() };
+
+    run_outputs(&root, &outputs, &opts).unwrap();
+    let lines: Vec<IrEntry> = fs::read_to_string(base.join("ir.jsonl")).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let entry: OutputEntry = serde_json::from_str(fs::read_to_string(&outputs).unwrap().lines().next().unwrap()).unwrap();
+    let _ = fs::remove_dir_all(&base);
+
+    let keys: Vec<_> = lines.iter().map(|l| (l.repr.as_str(), l.source.package.as_str(), l.source.target.as_str())).collect();
+    assert_eq!(keys, [("ast", "app", "lib"), ("ast", "app", "bin:app"), ("ast", "app", "bin:broken"), ("hir", "app", "lib"), ("hir", "app", "bin:app"), ("hir", "app", "bin:broken"), ("mir", "app", "lib"), ("mir", "app", "bin:app")]);
+    let mir = &lines[6].source;
+    assert!(mir.content.contains("fn add(") && mir.content.len() <= 400 && mir.content.ends_with('\n'), "{:?}", mir);
+    assert_eq!(mir.truncated, mir.bytes > 400);
+    // A type error only stops the compiler after HIR is built.
+    for (repr, counts) in [("hir", (3, 0)), ("mir", (2, 1))] {
+        let out = &entry.tools[repr];
+        assert!(out.result.stdout.is_empty(), "the dumps stay out of the entry");
+        match &out.report {
+            Some(Report::Ir(r)) => assert_eq!((r.emitted, r.failed), counts),
+            other => panic!("no {} report: {:?}", repr, other),
+        }
+    }
+    match &entry.tools["mir"].report {
+        Some(Report::Ir(r)) => assert!(r.targets[2].error.as_deref().is_some_and(|e| e.starts_with("error[E0308]")), "{:?}", r.targets[2]),
+        other => panic!("no mir report: {:?}", other),
+    }
+    assert!(entry.ast_toolchain.as_deref().is_some_and(|t| t.starts_with("nightly")));
+}
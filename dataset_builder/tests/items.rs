@@ -0,0 +1,103 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use dataset_builder::{collect_code_with, CodeEntry, CollectOptions, Granularity};
+
+const PARSER: &str = r#"use std::fmt;
+
+/// A parser.
+pub struct Parser;
+
+impl Parser {
+    /// Parses `s`.
+    ///
+    /// Never fails.
+    #[inline]
+    pub async unsafe fn parse(&self, s: &str) -> usize {
+        s.len()
+    }
+}
+
+impl fmt::Display for Parser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "parser")
+    }
+}
+
+macro_rules! twice {
+    ($e:expr) => { $e + $e };
+}
+
+pub trait Named {
+    fn name(&self) -> &str;
+    fn greet(&self) -> String { format!("hi {}", self.name()) }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn parses() { assert_eq!(2, twice!(1)); }
+}
+"#;
+
+fn checkout(test: &str) -> PathBuf {
+    common::scratch(test, &[("Cargo.toml", "[package]\nname = \"p\"\n"), ("src/lib.rs", "pub mod parser;\n"), ("src/parser.rs", PARSER), ("src/broken.rs", "fn (")])
+}
+
+fn collect(root: &Path, granularity: Granularity) -> Vec<CodeEntry> {
+    let mut entries = collect_code_with(root, &CollectOptions { granularity, ..CollectOptions::default() }).unwrap().entries;
+    entries.sort_by_key(|e| (e.path.clone(), e.item.as_ref().map(|i| i.start_line)));
+    entries
+}
+
+#[test]
+fn function_granularity_emits_every_function_method_and_macro() {
+    let root = checkout("items_function");
+
+    let entries = collect(&root, Granularity::Function);
+    fs::remove_dir_all(&root).unwrap();
+
+    let parser: Vec<&CodeEntry> = entries.iter().filter(|e| e.path == "src/parser.rs").collect();
+    let paths: Vec<&str> = parser.iter().map(|e| e.item.as_ref().unwrap().path.as_str()).collect();
+    assert_eq!(
+        paths,
+        ["crate::parser::Parser::parse", "crate::parser::<Parser as fmt::Display>::fmt", "crate::parser::twice", "crate::parser::Named::greet", "crate::parser::tests::parses"]
+    );
+    let parse = parser[0].item.as_ref().unwrap();
+    assert_eq!(parser[0].content, "/// Parses `s`.\n    ///\n    /// Never fails.\n    #[inline]\n    pub async unsafe fn parse(&self, s: &str) -> usize {\n        s.len()\n    }");
+    assert_eq!((parse.start_line, parse.end_line, parse.is_async, parse.is_unsafe, parse.is_test), (7, 13, true, true, false));
+    assert_eq!(parse.signature.as_deref(), Some("async unsafe fn parse(&self, s: &str) -> usize"));
+    assert_eq!(parse.body.as_deref(), Some("{\n        s.len()\n    }"));
+    assert_eq!((parse.doc.as_deref(), parse.attributes.as_slice()), (Some("Parses `s`.\n\nNever fails."), &["#[inline]".to_string()][..]));
+    assert_eq!(parser[2].item.as_ref().unwrap().kind, "macro_rules");
+    assert!(parser[4].item.as_ref().unwrap().is_test);
+    assert_eq!(parser[0].lines, 7, "sizes are those of the item");
+}
+
+#[test]
+fn item_granularity_keeps_top_level_items_and_unparsable_files_whole() {
+    let root = checkout("items_item");
+
+    let entries = collect(&root, Granularity::Item);
+    fs::remove_dir_all(&root).unwrap();
+
+    let items: Vec<(&str, &str, &str)> = entries.iter().filter_map(|e| e.item.as_ref().map(|i| (e.path.as_str(), i.kind.as_str(), i.path.as_str()))).collect();
+    assert_eq!(
+        items,
+        [
+            ("src/lib.rs", "mod", "crate::parser"),
+            ("src/parser.rs", "struct", "crate::parser::Parser"),
+            ("src/parser.rs", "impl", "crate::parser::Parser"),
+            ("src/parser.rs", "impl", "crate::parser::<Parser as fmt::Display>"),
+            ("src/parser.rs", "macro_rules", "crate::parser::twice"),
+            ("src/parser.rs", "trait", "crate::parser::Named"),
+            ("src/parser.rs", "mod", "crate::parser::tests"),
+        ]
+    );
+    let broken = entries.iter().find(|e| e.path == "src/broken.rs").unwrap();
+    assert!(broken.parse_failed && broken.item.is_none() && broken.content == "fn (");
+    let manifest = entries.iter().find(|e| e.path == "Cargo.toml").unwrap();
+    assert!(!manifest.parse_failed && manifest.item.is_none(), "only .rs files are split");
+}
@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use dataset_builder::{join_datasets, join_schema_beside, JoinOptions, JoinSummary, JoinedEntry};
+
+fn code(dir: &str, path: &str, item: Option<(usize, usize)>) -> String {
+    let item = item.map(|(start, end)| format!(r#","item":{{"kind":"fn","path":"crate::f","start_line":{},"end_line":{}}}"#, start, end)).unwrap_or_default();
+    format!(r#"{{"name":"owner/{}","dir":"dataset_{}","path":"{}","content":"fn f() {{}}"{}}}"#, dir, dir, path, item) + "\n"
+}
+
+fn output(dir: &str, project: &str) -> String {
+    let clippy = r#"{"status":"completed","exit_code":0,"report":{"diagnostics":[{"lint_name":"clippy::needless_return","level":"warning","file":"src/lib.rs","line_start":3,"line_end":3,"message":"unneeded `return`"},{"lint_name":"clippy::unwrap_used","level":"warning","file":"src/main.rs","line_start":9,"line_end":9,"message":"used `unwrap()`"}],"counts":{"warning":2},"compile_failed":false}}"#;
+    let finding = r#"{"tool":"semgrep","rule_id":"rust.lang.unsafe","severity":"warning","file":"./src/lib.rs","start_line":20,"end_line":21,"message":"unsafe block"}"#;
+    format!(
+        r#"{{"schema_version":4,"run_id":"r","name":"owner/{}","dir":"dataset_{}","project_manifest":"{}","manifest_path":"{}","tools":{{"clippy":{}}},"findings":[{}],"toolchain":"stable","time_ms":{{}},"errors":[]}}"#,
+        dir, dir, project, project, clippy, finding
+    ) + "\n"
+}
+
+fn inputs(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_{}_{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let code = [code("a", "src/lib.rs", None), code("a", "src/main.rs", Some((1, 5))), code("b", "sub/src/lib.rs", Some((18, 25))), code("c", "src/lib.rs", None)].concat();
+    fs::write(dir.join("code.jsonl"), code).unwrap();
+    fs::write(dir.join("outputs.jsonl"), [output("a", "Cargo.toml"), output("b", "sub/Cargo.toml"), output("gone", "Cargo.toml")].concat()).unwrap();
+    let meta = |dir: &str, stars: u64| format!(r#"{{"name":"dataset_{}","repo":"owner/{}","status":"ok","stars":{},"topics":[],"fetched_at":"2024-01-01T00:00:00Z"}}"#, dir, dir, stars) + "\n";
+    fs::write(dir.join("metadata.jsonl"), [meta("a", 10), meta("c", 3)].concat()).unwrap();
+    dir
+}
+
+fn join(dir: &Path, by_file: bool, out: &str) -> (JoinSummary, Vec<JoinedEntry>) {
+    let path = |f: &str| Some(dir.join(f).display().to_string());
+    let opts = JoinOptions { code: path("code.jsonl").unwrap(), outputs: path("outputs.jsonl"), metadata: path("metadata.jsonl"), out: path(out).unwrap(), by_file };
+    let summary = join_datasets(&opts).unwrap();
+    let entries = fs::read_to_string(&opts.out).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    (summary, entries)
+}
+
+#[test]
+fn entries_get_their_repository_analysis_and_metadata() {
+    let dir = inputs("join_repo");
+
+    let (summary, entries) = join(&dir, false, "dataset.jsonl");
+    let schema: serde_json::Value = serde_json::from_str(&fs::read_to_string(join_schema_beside(dir.join("dataset.jsonl").to_str().unwrap())).unwrap()).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(entries.len(), 4);
+    let a = &entries[0];
+    assert_eq!((a.code.path.as_str(), a.metadata.as_ref().and_then(|m| m.stars), a.analysis.len()), ("src/lib.rs", Some(10), 1));
+    assert_eq!((a.analysis[0].clippy_counts.as_ref().map(|c| c["warning"]), a.analysis[0].findings), (Some(2), 1));
+    assert!(a.diagnostics.is_empty(), "only with --by-file");
+    assert!(entries[2].metadata.is_none() && entries[3].analysis.is_empty());
+    assert_eq!((summary.code_without_outputs.as_slice(), summary.outputs_without_code.as_slice()), (&["dataset_c".to_string()][..], &["dataset_gone".to_string()][..]));
+    assert_eq!((summary.code_without_metadata.as_slice(), summary.metadata_without_code.len()), (&["dataset_b".to_string()][..], 0));
+    assert!(schema["properties"]["analysis"]["items"]["properties"]["tools"].is_object() && schema["properties"]["content"]["type"] == "string");
+}
+
+#[test]
+fn by_file_maps_diagnostics_onto_the_entry_of_their_file_and_lines() {
+    let dir = inputs("join_by_file");
+
+    let (summary, entries) = join(&dir, true, "dataset.jsonl");
+    fs::remove_dir_all(&dir).unwrap();
+
+    let rules = |i: usize| entries[i].diagnostics.iter().map(|d| d.rule.clone().unwrap_or_default()).collect::<Vec<_>>();
+    assert_eq!(rules(0), ["clippy::needless_return", "rust.lang.unsafe"]);
+    assert!(rules(1).is_empty(), "line 9 is outside the item's lines 1 to 5");
+    assert_eq!(rules(2), ["rust.lang.unsafe"], "paths are relative to the project in sub/, and lines 20-21 are in the item");
+    assert_eq!(summary.diagnostics, 3);
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use dataset_builder::{collect_code_all, dedup_code, join_datasets, open_jsonl, validate_jsonl, Codec, CollectOptions, Dedup, JoinOptions};
+
+fn checkout(test: &str) -> PathBuf {
+    let files = ["dataset_a", "dataset_b"].map(|repo| [(format!("{}/Cargo.toml", repo), "[package]\nname = \"a\"\n"), (format!("{}/src/lib.rs", repo), "pub fn shared() {}\n")]);
+    common::scratch(test, files.as_flattened())
+}
+
+fn lines(path: &Path) -> usize {
+    open_jsonl(path.to_str().unwrap()).unwrap().lines().count()
+}
+
+#[test]
+fn codecs_parse_with_levels_and_name_their_files() {
+    assert_eq!(("zstd:9".parse(), "gzip".parse(), "none".parse()), (Ok(Codec::Zstd(9)), Ok(Codec::Gzip(6)), Ok(Codec::None)));
+    assert!("lz4".parse::<Codec>().is_err() && "gzip:10".parse::<Codec>().is_err());
+    assert_eq!(Codec::of("outputs.jsonl.zst"), Codec::Zstd(3));
+    assert_eq!(Codec::path(Some(Codec::Gzip(9)), "code.jsonl").unwrap(), "code.jsonl.gz");
+    assert_eq!(Codec::path(Some(Codec::Zstd(3)), "code.jsonl.zst").unwrap(), "code.jsonl.zst");
+    assert!(Codec::path(Some(Codec::Zstd(3)), "code.jsonl.gz").is_err());
+}
+
+#[test]
+fn compressed_files_are_written_and_read_by_extension() {
+    let root = checkout("jsonl_compressed");
+    let path = |f: &str| root.join(f);
+    let opts = CollectOptions { compress: Some(Codec::Zstd(9)), ..CollectOptions::default() };
+
+    collect_code_all(root.to_str().unwrap(), path("code.jsonl").to_str().unwrap(), &opts).unwrap();
+    let (plain_left, zst) = (path("code.jsonl").exists(), path("code.jsonl.zst"));
+    let magic = fs::read(&zst).unwrap()[..4].to_vec();
+    let dedup = dedup_code(zst.to_str().unwrap(), path("dedup.jsonl.gz").to_str().unwrap(), Dedup::Global).unwrap();
+    let gz_magic = fs::read(path("dedup.jsonl.gz")).unwrap()[..2].to_vec();
+    let opts = JoinOptions { code: path("dedup.jsonl.gz").display().to_string(), outputs: None, metadata: None, out: path("dataset.jsonl.zst").display().to_string(), by_file: false };
+    let joined = join_datasets(&opts).unwrap();
+    let (collected, deduped, written) = (lines(&zst), lines(&path("dedup.jsonl.gz")), lines(&path("dataset.jsonl.zst")));
+    fs::remove_dir_all(&root).unwrap();
+
+    assert!(!plain_left, "--compress adds .zst to the name");
+    assert_eq!((magic.as_slice(), gz_magic.as_slice()), (&[0x28, 0xb5, 0x2f, 0xfd][..], &[0x1f, 0x8b][..]));
+    assert_eq!((collected, dedup.removed, deduped, joined.entries, written), (4, 2, 2, 2, 2));
+}
+
+#[test]
+fn unfinished_streams_are_repaired_and_frames_appended_after_them_are_read() {
+    let root = checkout("jsonl_unfinished");
+    let file = root.join("outputs.jsonl.zst");
+    let mut finished = zstd::Encoder::new(Vec::new(), 3).unwrap();
+    finished.write_all(b"{\"name\":\"a\"}\n{\"name\":\"b\"}\n").unwrap();
+    let mut bytes = finished.finish().unwrap();
+    // A crash after the flush of the third line, before the frame was ended.
+    let mut cut = zstd::Encoder::new(Vec::new(), 3).unwrap();
+    cut.write_all(b"{\"name\":\"c\"}\n").unwrap();
+    cut.flush().unwrap();
+    bytes.extend(cut.get_ref());
+    fs::write(&file, bytes).unwrap();
+
+    let unrepaired = validate_jsonl(file.to_str().unwrap(), false).unwrap_err();
+    validate_jsonl(file.to_str().unwrap(), true).unwrap();
+    let mut appended = fs::OpenOptions::new().append(true).open(&file).unwrap();
+    appended.write_all(&zstd::encode_all(&b"{\"name\":\"d\"}\n"[..], 3).unwrap()).unwrap();
+    let valid = validate_jsonl(file.to_str().unwrap(), false);
+    let names = lines(&file);
+    fs::remove_dir_all(&root).unwrap();
+
+    assert!(format!("{:#}", unrepaired).contains("unfinished compressed stream"), "{:#}", unrepaired);
+    assert!(valid.is_ok(), "the repaired file is one finished frame");
+    assert_eq!(names, 4, "the flushed line of the cut frame is kept");
+}
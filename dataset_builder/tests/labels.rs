@@ -0,0 +1,81 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use dataset_builder::{label_files, unmatched_findings_beside, FileLabel, LabelOptions, LabelSummary, Severity, UnmatchedFindings};
+
+fn code(dir: &str, path: &str) -> String {
+    format!(r#"{{"name":"owner/{}","dir":"dataset_{}","path":"{}","content":"fn f() {{}}"}}"#, dir, dir, path) + "\n"
+}
+
+fn output(dir: &str, project: &str, member: &str) -> String {
+    let clippy = r#"{"status":"completed","exit_code":0,"report":{"diagnostics":[{"lint_name":"clippy::needless_return","level":"warning","file":"src/lib.rs","line_start":3,"line_end":3,"message":"unneeded `return`"},{"lint_name":"clippy::unused_io_amount","level":"error","file":"src/main.rs","line_start":9,"line_end":9,"message":"read amount not handled"}],"counts":{"warning":1,"error":1},"compile_failed":false}}"#;
+    let semgrep = r#"{"status":"completed","exit_code":0}"#;
+    let findings = [
+        r#"{"tool":"semgrep","rule_id":"rust.lang.unsafe","severity":"note","file":"src/lib.rs","start_line":20,"end_line":21,"message":"unsafe block"}"#,
+        r#"{"tool":"semgrep","rule_id":"rust.lang.unsafe","severity":"note","file":"src/lib.rs","start_line":30,"end_line":30,"message":"unsafe block"}"#,
+        r#"{"tool":"semgrep","rule_id":"rust.lang.transmute","severity":"warning","file":"build/generated.rs","start_line":1,"end_line":1,"message":"transmute"}"#,
+    ];
+    format!(
+        r#"{{"schema_version":4,"run_id":"r","name":"owner/{}","dir":"dataset_{}","project_manifest":"{}","manifest_path":"{}","tools":{{"clippy":{},"semgrep":{}}},"findings":[{}],"toolchain":"stable","time_ms":{{}},"errors":[]}}"#,
+        dir,
+        dir,
+        project,
+        member,
+        clippy,
+        semgrep,
+        findings.join(",")
+    ) + "\n"
+}
+
+fn inputs(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_{}_{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("code.jsonl"), [code("a", "src/lib.rs"), code("a", "src/main.rs"), code("a", "src/util.rs"), code("b", "src/lib.rs")].concat()).unwrap();
+    // Two workspace members of one project report the same clippy diagnostics.
+    fs::write(dir.join("outputs.jsonl"), [output("a", "Cargo.toml", "Cargo.toml"), output("a", "Cargo.toml", "Cargo.toml")].concat()).unwrap();
+    dir
+}
+
+fn label(dir: &Path, min_severity: Severity) -> (LabelSummary, Vec<FileLabel>, Vec<UnmatchedFindings>) {
+    let path = |f: &str| dir.join(f).display().to_string();
+    let opts = LabelOptions { code: path("code.jsonl"), outputs: path("outputs.jsonl"), out: path("labels.jsonl"), min_severity };
+    let summary = label_files(&opts).unwrap();
+    let labels = fs::read_to_string(&opts.out).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let unmatched = fs::read_to_string(unmatched_findings_beside(&opts.out)).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    (summary, labels, unmatched)
+}
+
+#[test]
+fn every_file_gets_a_label_and_clean_files_explicit_zeros() {
+    let dir = inputs("labels_files");
+
+    let (summary, labels, unmatched) = label(&dir, Severity::Warning);
+    fs::remove_dir_all(&dir).unwrap();
+
+    let paths: Vec<&str> = labels.iter().map(|l| l.path.as_str()).collect();
+    assert_eq!(paths, ["src/lib.rs", "src/main.rs", "src/util.rs"], "dataset_b has no outputs");
+    let lib = &labels[0];
+    assert_eq!((lib.counts["clippy"], lib.counts["semgrep"], lib.max_severity.as_deref()), (1, 2, Some("warning")), "the members' shared diagnostics count once");
+    assert_eq!(lib.rule_ids, ["clippy::needless_return", "rust.lang.unsafe"]);
+    assert!(lib.flagged && labels[1].flagged);
+    let util = &labels[2];
+    assert_eq!((util.counts.get("clippy"), util.counts.get("semgrep"), util.counts.get("codeql")), (Some(&0), Some(&0), None), "codeql didn't run");
+    assert!(!util.flagged && util.max_severity.is_none() && util.rule_ids.is_empty());
+    assert_eq!((summary.repos, summary.files, summary.flagged, summary.repos_without_outputs.as_slice()), (1, 3, 2, &["dataset_b".to_string()][..]));
+    assert_eq!((summary.unmatched_files, summary.unmatched_findings), (1, 1));
+    assert_eq!((unmatched[0].path.as_str(), unmatched[0].counts.len(), unmatched[0].counts["semgrep"]), ("build/generated.rs", 1, 1));
+}
+
+#[test]
+fn min_severity_decides_what_is_flagged() {
+    let dir = inputs("labels_severity");
+
+    let (errors, labels, _) = label(&dir, Severity::Error);
+    let (notes, _, _) = label(&dir, Severity::Note);
+    fs::remove_dir_all(&dir).unwrap();
+
+    let flagged: Vec<bool> = labels.iter().map(|l| l.flagged).collect();
+    assert_eq!(flagged, [false, true, false], "only main.rs has an error");
+    assert_eq!((errors.flagged, notes.flagged), (1, 2));
+}
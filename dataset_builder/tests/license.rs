@@ -0,0 +1,81 @@
+mod common;
+
+use std::fs;
+
+use dataset_builder::{collect_code_all, detect_license, license_allowed, licenses_beside, CodeEntry, CollectOptions, RepoLicense};
+
+const MIT: &str = "MIT License\n\nPermission is hereby granted, free of charge, to any person obtaining a copy\nof this software ...\n\nThe above copyright notice and this permission notice shall be included in all\ncopies or substantial portions of the Software.\n\nTHE SOFTWARE IS PROVIDED \"AS IS\", WITHOUT WARRANTY OF ANY KIND.\n";
+const APACHE: &str = "                                 Apache License\n                           Version 2.0, January 2004\n\n3. Grant of Patent License. ...\nlimitations under the License.\n";
+const GPL: &str = "                    GNU GENERAL PUBLIC LICENSE\n                       Version 3, 29 June 2007\n";
+const LGPL: &str = "                   GNU LESSER GENERAL PUBLIC LICENSE\n                       Version 3, 29 June 2007\n\n  This version of the GNU Lesser General Public License incorporates\nthe terms and conditions of version 3 of the GNU General Public License\n";
+
+#[test]
+fn detects_licenses_from_the_manifest_or_license_files() {
+    let root = common::scratch(
+        "license_detect",
+        &[
+            ("manifest/Cargo.toml", "[package]\nname = \"a\"\nlicense = \"MIT/Apache-2.0\"\n"),
+            ("manifest/LICENSE", GPL),
+            ("workspace/Cargo.toml", "[workspace.package]\nlicense = \"Apache-2.0 WITH LLVM-exception\"\n"),
+            ("dual/Cargo.toml", "[package]\nname = \"b\"\n"),
+            ("dual/LICENSE-MIT", MIT),
+            ("dual/LICENSE-APACHE", APACHE),
+            ("gpl/COPYING", GPL),
+            ("lgpl/COPYING.LESSER", LGPL),
+            ("none/README.md", "no license"),
+        ],
+    );
+
+    let detected: Vec<(String, f64)> = ["manifest", "workspace", "dual", "gpl", "lgpl", "none"].iter().map(|d| detect_license(&root.join(d))).map(|(l, c, _)| (l, c)).collect();
+    let files = detect_license(&root.join("dual")).2;
+    fs::remove_dir_all(&root).unwrap();
+
+    let licenses: Vec<&str> = detected.iter().map(|(l, _)| l.as_str()).collect();
+    assert_eq!(licenses, ["MIT OR Apache-2.0", "Apache-2.0 WITH LLVM-exception", "Apache-2.0 OR MIT", "GPL-3.0", "LGPL-3.0", "unknown"]);
+    assert_eq!((detected[0].1, detected[2].1, detected[5].1), (1.0, 1.0, 0.0));
+    assert_eq!(files, ["LICENSE-APACHE", "LICENSE-MIT"]);
+}
+
+#[test]
+fn allow_and_deny_follow_the_spdx_expression() {
+    let list = |ids: &[&str]| ids.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    let (allow, deny) = (list(&["MIT", "Apache-2.0", "BSD-3-Clause"]), list(&["GPL-3.0"]));
+
+    assert!(license_allowed("MIT OR Apache-2.0", &allow, &[]));
+    assert!(license_allowed("GPL-3.0 OR MIT", &allow, &deny), "MIT can be chosen");
+    assert!(!license_allowed("MIT AND GPL-3.0-or-later", &[], &deny));
+    assert!(!license_allowed("MPL-2.0", &allow, &[]));
+    assert!(license_allowed("(Apache-2.0 WITH LLVM-exception)", &allow, &[]));
+    assert!(!license_allowed("unknown", &allow, &[]), "unknown is excluded by an allowlist");
+    assert!(license_allowed("unknown", &list(&["MIT", "unknown"]), &[]) && license_allowed("unknown", &[], &deny));
+}
+
+#[test]
+fn collect_stamps_entries_and_leaves_out_repositories_not_allowed() {
+    let root = common::scratch(
+        "license_collect",
+        &[
+            ("dataset_mit/Cargo.toml", "[package]\nname = \"a\"\nlicense = \"MIT\"\n"),
+            ("dataset_mit/src/lib.rs", "pub fn a() {}\n"),
+            ("dataset_gpl/Cargo.toml", "[package]\nname = \"b\"\n"),
+            ("dataset_gpl/COPYING", GPL),
+            ("dataset_gpl/src/lib.rs", "pub fn b() {}\n"),
+            ("dataset_none/src/lib.rs", "pub fn c() {}\n"),
+        ],
+    );
+    let out = root.join("code.jsonl");
+
+    let opts = CollectOptions { allow_licenses: vec!["MIT".into(), "Apache-2.0".into()], ..CollectOptions::default() };
+    collect_code_all(root.to_str().unwrap(), out.to_str().unwrap(), &opts).unwrap();
+    let code = fs::read_to_string(&out).unwrap();
+    let licenses = fs::read_to_string(licenses_beside(out.to_str().unwrap())).unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    let entries: Vec<CodeEntry> = code.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(entries.len(), 2);
+    assert!(entries.iter().all(|e| e.dir == "dataset_mit" && e.license_spdx.as_deref() == Some("MIT")));
+    let mut licenses: Vec<RepoLicense> = licenses.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    licenses.sort_by_key(|l| l.dir.clone());
+    let rows: Vec<(&str, &str, bool)> = licenses.iter().map(|l| (l.dir.as_str(), l.license_spdx.as_str(), l.excluded)).collect();
+    assert_eq!(rows, [("dataset_gpl", "GPL-3.0", true), ("dataset_mit", "MIT", false), ("dataset_none", "unknown", true)]);
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use dataset_builder::{extract_manifests, extract_manifests_all, CodeSource, DependencyKind, ManifestEntry, ManifestKind, ManifestsOptions, INHERITED};
+
+const ROOT: &str = r#"[workspace]
+members = ["crates/*"]
+exclude = ["crates/scratch"]
+
+[workspace.package]
+version = "0.3.0"
+"#;
+
+const CORE: &str = r#"[package]
+name = "core"
+version.workspace = true
+edition = "2021"
+rust-version = "1.70"
+
+[features]
+default = ["std"]
+std = []
+fast = ["dep:simd"]
+
+[dependencies]
+serde = { version = "1", default-features = false, features = ["derive"] }
+simd = { version = "0.2", optional = true }
+log.workspace = true
+json = { package = "serde_json", version = "1.0" }
+
+[dev-dependencies]
+proptest = "1"
+
+[build-dependencies]
+cc = "1.0"
+
+[target.'cfg(windows)'.dependencies]
+winapi = "0.3"
+"#;
+
+const LOCK: &str = r#"# This file is automatically @generated by Cargo.
+version = 3
+
+[[package]]
+name = "core"
+version = "0.3.0"
+
+[[package]]
+name = "serde"
+version = "1.0.190"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "91d3c334ca1ee894a2c6f6ad698fe8c435b76d504b13d436f0685d648d6d96f7"
+"#;
+
+fn checkout(test: &str) -> PathBuf {
+    let files = [
+        ("repo/Cargo.toml", ROOT),
+        ("repo/Cargo.lock", LOCK),
+        ("repo/crates/core/Cargo.toml", CORE),
+        ("repo/crates/scratch/Cargo.toml", "[package]\nname = \"scratch\"\n"),
+        ("repo/tools/broken/Cargo.toml", "[package\nname = "),
+        ("repo/rustfmt.toml", "edition = \"2021\"\n"),
+    ];
+    common::scratch(test, &files)
+}
+
+#[test]
+fn manifests_are_parsed_into_packages_dependencies_and_workspaces() {
+    let root = checkout("manifests_parse");
+    let mut entries = Vec::new();
+    let summary = extract_manifests(&root.join("repo"), &CodeSource { name: "o/r".into(), ..CodeSource::default() }, &ManifestsOptions::default(), &mut |e| {
+        entries.push(e);
+        Ok(())
+    })
+    .unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    let paths: Vec<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+    assert_eq!(paths, ["Cargo.lock", "Cargo.toml", "crates/core/Cargo.toml", "crates/scratch/Cargo.toml", "tools/broken/Cargo.toml"], "rustfmt.toml isn't a manifest");
+    assert_eq!((summary.manifests, summary.lockfiles, summary.parse_failed), (4, 1, 1));
+    let core = &entries[2];
+    let package = core.package.as_ref().unwrap();
+    assert_eq!((package.name.as_str(), package.version.as_deref(), package.edition.as_deref(), package.rust_version.as_deref()), ("core", Some(INHERITED), Some("2021"), Some("1.70")));
+    assert_eq!(core.features["fast"], ["dep:simd"]);
+    let deps: Vec<(&str, Option<&str>, DependencyKind, bool, bool)> = core.dependencies.iter().map(|d| (d.name.as_str(), d.req.as_deref(), d.kind, d.optional, d.default_features)).collect();
+    assert_eq!(
+        deps,
+        [
+            ("json", Some("1.0"), DependencyKind::Normal, false, true),
+            ("log", None, DependencyKind::Normal, false, true),
+            ("serde", Some("1"), DependencyKind::Normal, false, false),
+            ("simd", Some("0.2"), DependencyKind::Normal, true, true),
+            ("proptest", Some("1"), DependencyKind::Dev, false, true),
+            ("cc", Some("1.0"), DependencyKind::Build, false, true),
+            ("winapi", Some("0.3"), DependencyKind::Normal, false, true),
+        ]
+    );
+    assert_eq!((core.dependencies[0].package.as_deref(), core.dependencies[1].workspace, core.dependencies[6].target.as_deref()), (Some("serde_json"), true, Some("cfg(windows)")));
+    assert_eq!(core.workspace_root.as_deref(), Some("Cargo.toml"));
+    assert_eq!(entries[3].workspace_root, None, "excluded from the workspace");
+    assert_eq!(entries[1].workspace.as_ref().unwrap().members, ["crates/*"]);
+}
+
+#[test]
+fn lockfiles_are_resolved_packages_and_bad_files_stay_raw() {
+    let root = checkout("manifests_lock");
+    let out = root.join("manifests.jsonl");
+
+    let summary = extract_manifests_all(root.to_str().unwrap(), out.to_str().unwrap(), &ManifestsOptions::default()).unwrap();
+    let text = fs::read_to_string(&out).unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(summary.repos, 1);
+    let entries: Vec<ManifestEntry> = text.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let lock = entries.iter().find(|e| e.kind == ManifestKind::Lockfile).unwrap();
+    let packages: Vec<(&str, &str, Option<&str>)> = lock.packages.iter().map(|p| (p.name.as_str(), p.version.as_str(), p.checksum.as_deref().map(|c| &c[..8]))).collect();
+    assert_eq!(packages, [("core", "0.3.0", None), ("serde", "1.0.190", Some("91d3c334"))]);
+    assert!(lock.packages[1].source.as_deref().is_some_and(|s| s.starts_with("registry+")));
+    let broken = entries.iter().find(|e| e.path == "tools/broken/Cargo.toml").unwrap();
+    assert!(broken.parse_failed && broken.content.as_deref() == Some("[package\nname = ") && broken.package.is_none());
+    assert!(entries.iter().all(|e| e.name == "repo" && e.dir == "repo"));
+}
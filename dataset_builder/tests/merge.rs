@@ -0,0 +1,77 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use dataset_builder::{merge_conflicts_beside, merge_files, MergeConflict, MergeOptions};
+
+fn output(run: &str, name: &str, clippy: &str, member: Option<&str>) -> String {
+    let member = member.map_or(String::new(), |m| format!(r#","manifest_path":"{}","workspace_root":"Cargo.toml""#, m));
+    format!(r#"{{"schema_version":4,"run_id":"{}","name":"{}","dir":"d","project_manifest":"Cargo.toml"{},"tools":{{"clippy":{{"status":"{}","exit_code":0}}}},"findings":[],"toolchain":"stable","time_ms":{{}},"errors":[]}}"#, run, name, member, clippy) + "\n"
+}
+
+fn code(name: &str, path: &str, hash: &str) -> String {
+    format!(r#"{{"name":"{}","dir":"d","path":"{}","kind":"lib","content":"fn f() {{}}","content_hash":"{}"}}"#, name, path, hash) + "\n"
+}
+
+fn machines(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_{}_{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    for m in ["m1", "m2"] {
+        fs::create_dir_all(dir.join(m)).unwrap();
+    }
+    dir
+}
+
+fn names(file: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_to_string(file).unwrap().lines().map(|l| {
+        let v: serde_json::Value = serde_json::from_str(l).unwrap();
+        format!("{}{}", v["name"].as_str().unwrap(), v["path"].as_str().or(v["manifest_path"].as_str()).map_or(String::new(), |p| format!(":{}", p)))
+    }).collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn outputs_keep_the_newest_run_of_each_repository() {
+    let dir = machines("merge_outputs");
+    let path = |f: &str| dir.join(f).display().to_string();
+    // m1 ran last, though it is listed first.
+    fs::write(path("m1/manifest.json"), r#"{"run_id":"r1","started_at":"2024-03-01T00:00:00Z"}"#).unwrap();
+    fs::write(path("m2/manifest.json"), r#"{"run_id":"r2","started_at":"2024-02-01T00:00:00Z"}"#).unwrap();
+    fs::write(path("m1/outputs.jsonl"), [output("r1", "o/a", "completed", None), output("r1", "o/c", "completed", None), output("r1", "o/w", "completed", Some("x/Cargo.toml")), output("r1", "o/w", "completed", Some("y/Cargo.toml"))].concat()).unwrap();
+    let mut gz = flate2::write::GzEncoder::new(fs::File::create(path("m2/outputs.jsonl.gz")).unwrap(), flate2::Compression::default());
+    gz.write_all([output("r2", "o/a", "timeout", None), output("r2", "o/b", "completed", None), output("r2", "o/c", "completed", None)].concat().as_bytes()).unwrap();
+    gz.finish().unwrap();
+    let opts = MergeOptions { inputs: vec![path("m1/outputs.jsonl"), path("m2/outputs.jsonl.gz")], out: path("merged.jsonl"), ..MergeOptions::default() };
+
+    let summary = merge_files(&opts).unwrap();
+    let merged = names(&dir.join("merged.jsonl"));
+    let clippy_a = fs::read_to_string(path("merged.jsonl")).unwrap().lines().find(|l| l.contains(r#""name":"o/a""#)).unwrap().contains(r#""status":"completed""#);
+    let conflicts: Vec<MergeConflict> = fs::read_to_string(merge_conflicts_beside(&path("merged.jsonl"))).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    fs::write(path("m2/bad.jsonl"), "{\"name\":\"o/d\"}\n").unwrap();
+    let bad = merge_files(&MergeOptions { inputs: vec![path("m1/outputs.jsonl"), path("m2/bad.jsonl")], ..opts.clone() });
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!((summary.read, summary.written, summary.duplicates, summary.conflicts, summary.last_wins), (7, 5, 2, 1, 0));
+    assert_eq!(merged, ["o/a", "o/b", "o/c", "o/w:x/Cargo.toml", "o/w:y/Cargo.toml"], "workspace members are separate entries");
+    assert!(clippy_a, "the newer run's entry is kept");
+    assert_eq!(conflicts, [MergeConflict { key: "o/a".into(), kept: path("m1/outputs.jsonl"), dropped: path("m2/outputs.jsonl.gz"), differences: vec!["clippy: timeout → completed".into()] }]);
+    assert!(format!("{:#}", bad.unwrap_err()).contains("bad.jsonl:1: not a valid outputs entry"));
+}
+
+#[test]
+fn code_without_run_timestamps_keeps_the_later_input_by_name_and_path() {
+    let dir = machines("merge_code");
+    let path = |f: &str| dir.join(f).display().to_string();
+    fs::write(path("m1/code.jsonl"), [code("o/a", "src/lib.rs", "h1"), code("o/a", "src/main.rs", "h2")].concat()).unwrap();
+    fs::write(path("m2/code.jsonl"), [code("o/a", "src/lib.rs", "h3"), code("o/b", "src/lib.rs", "h4")].concat()).unwrap();
+
+    let summary = merge_files(&MergeOptions { inputs: vec![path("m1/code.jsonl"), path("m2/code.jsonl")], out: path("code.jsonl.zst"), ..MergeOptions::default() }).unwrap();
+    let text = String::from_utf8(zstd::decode_all(fs::File::open(path("code.jsonl.zst")).unwrap()).unwrap()).unwrap();
+    let conflicts = fs::read_to_string(merge_conflicts_beside(&path("code.jsonl.zst"))).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!((summary.written, summary.duplicates, summary.last_wins), (3, 1, 1));
+    assert!(text.contains(r#""content_hash":"h3""#) && !text.contains(r#""content_hash":"h1""#));
+    assert!(conflicts.contains(r#""key":"o/a:src/lib.rs""#) && conflicts.contains("content_hash: h1 → h3"), "{}", conflicts);
+}
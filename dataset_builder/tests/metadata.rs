@@ -0,0 +1,74 @@
+mod common;
+
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+use common::serve;
+
+use dataset_builder::{fetch_metadata, MetadataOptions, RepoMetadata, Tokens};
+
+fn repo(full_name: &str, stars: u64) -> String {
+    format!(
+        r#"{{"full_name":"{}","stargazers_count":{},"forks_count":3,"open_issues_count":4,"license":{{"spdx_id":"Apache-2.0"}},"topics":["async","io"],"default_branch":"main","archived":false,"fork":false,"size":512,"created_at":"2016-01-01T00:00:00Z","pushed_at":"2024-05-01T00:00:00Z","owner":{{"login":"x"}}}}"#,
+        full_name, stars
+    )
+}
+
+fn read(path: &std::path::Path) -> Vec<RepoMetadata> {
+    fs::read_to_string(path).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+}
+
+#[test]
+fn metadata_is_written_per_checkout_and_revalidated_with_etags() {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = seen.clone();
+    let url = serve(Arc::new(move |r| {
+        log.lock().unwrap().push((r.path.clone(), r.headers.get("if-none-match").cloned()));
+        match (r.path.as_str(), r.headers.get("if-none-match").map(String::as_str)) {
+            ("/repos/tokio-rs/tokio", Some("\"t1\"")) => (304, String::new(), String::new()),
+            ("/repos/tokio-rs/tokio", _) => (200, "ETag: \"t1\"\r\n".into(), repo("tokio-rs/tokio", 25000)),
+            ("/repos/old/name", _) => (200, "ETag: \"n1\"\r\n".into(), repo("new/name", 7)),
+            _ => (404, String::new(), r#"{"message":"Not Found"}"#.into()),
+        }
+    }));
+    let dir = std::env::temp_dir().join(format!("dataset_builder_metadata_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("names.txt"), "tokio-rs/tokio\nhttps://github.com/old/name\n\ngone/repo\nTokio-rs/Tokio\n").unwrap();
+    let (names, out) = (dir.join("names.txt"), dir.join("metadata.jsonl"));
+    let opts = MetadataOptions { api_url: url, ..MetadataOptions::default() };
+
+    let first = fetch_metadata(names.to_str().unwrap(), out.to_str().unwrap(), &opts, &Tokens::new(["secret".to_string()])).unwrap();
+    let entries = read(&out);
+    let second = fetch_metadata(names.to_str().unwrap(), out.to_str().unwrap(), &opts, &Tokens::new(["secret".to_string()])).unwrap();
+    let again = read(&out);
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!((first.repos, first.fetched, first.unchanged, first.not_found), (3, 2, 0, 1), "the second Tokio line is a duplicate");
+    assert_eq!((second.fetched, second.unchanged, second.not_found), (1, 1, 1));
+    let tokio = &entries[0];
+    assert_eq!((tokio.name.as_str(), tokio.repo.as_str(), tokio.status.as_str()), ("dataset_tokio-rs__tokio", "tokio-rs/tokio", "ok"));
+    assert_eq!((tokio.stars, tokio.forks, tokio.open_issues, tokio.size_kb), (Some(25000), Some(3), Some(4), Some(512)));
+    assert_eq!((tokio.license.as_deref(), tokio.topics.as_slice(), tokio.etag.as_deref()), (Some("Apache-2.0"), &["async".to_string(), "io".to_string()][..], Some("\"t1\"")));
+    assert_eq!(entries[1].full_name.as_deref(), Some("new/name"), "a renamed repository keeps the listed name in `repo`");
+    assert_eq!((entries[2].status.as_str(), entries[2].stars), ("not_found", None));
+    assert_eq!(RepoMetadata { fetched_at: String::new(), ..again[0].clone() }, RepoMetadata { fetched_at: String::new(), ..tokio.clone() }, "a 304 keeps the entry");
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.iter().filter(|(_, etag)| etag.is_some()).count(), 2, "{:?}", seen);
+    assert!(!seen.iter().any(|(path, etag)| path == "/repos/gone/repo" && etag.is_some()), "not_found entries have no ETag to send");
+}
+
+#[test]
+fn root_limits_the_lookups_to_existing_checkouts() {
+    let url = serve(Arc::new(|r| (200, String::new(), repo(r.path.trim_start_matches("/repos/"), 1))));
+    let dir = std::env::temp_dir().join(format!("dataset_builder_metadata_root_{}", std::process::id()));
+    fs::create_dir_all(dir.join("datasets/dataset_a__cloned")).unwrap();
+    fs::write(dir.join("names.txt"), "a/cloned\nb/failed-to-clone\n").unwrap();
+    let opts = MetadataOptions { root: Some(dir.join("datasets").display().to_string()), api_url: url, ..MetadataOptions::default() };
+
+    let summary = fetch_metadata(dir.join("names.txt").to_str().unwrap(), dir.join("metadata.jsonl").to_str().unwrap(), &opts, &Tokens::default()).unwrap();
+    let entries = read(&dir.join("metadata.jsonl"));
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!((summary.repos, summary.requests), (1, 1));
+    assert_eq!(entries[0].name, "dataset_a__cloned");
+}
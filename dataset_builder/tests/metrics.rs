@@ -0,0 +1,83 @@
+use std::fs;
+
+use dataset_builder::parse::parse_tokei;
+use dataset_builder::{file_metrics, run_outputs, AnalyzeOptions, MetricsEntry, OutputEntry, Report};
+
+const SOURCE: &str = r#"
+fn f(x: i32, v: &[i32]) -> i32 {
+    if x > 0 && x < 10 || x == 42 {
+        for i in v {
+            if *i == x {
+                return 1;
+            }
+        }
+        0
+    } else if x < 0 {
+        -1
+    } else {
+        match x { 0 => 0, _ => 2 }
+    }
+}
+
+struct S(Option<u8>);
+
+trait T {
+    fn declared(&self);
+}
+
+impl S {
+    fn g(&self) -> Option<u8> {
+        let inc = |y: u8| y + 1;
+        Some(inc(self.0?))
+    }
+}
+"#;
+
+#[test]
+fn complexity_counts_decisions_nesting_and_tokens() {
+    let m = file_metrics("src/lib.rs", SOURCE).unwrap();
+    // f: if, &&, ||, for, if, else if and a second match arm; g: one `?`.
+    assert_eq!((m.functions, m.closures, m.cyclomatic, m.cyclomatic_max), (2, 1, 10, 8));
+    // f: if 1, the `||` and `&&` runs 1 each, for 2, the nested if 3, else if 1, else 1, the match in it 2.
+    assert_eq!((m.cognitive, m.cognitive_max), (12, 12));
+
+    let tiny = file_metrics("src/a.rs", "/// Adds.\nfn a() -> u8 { 1 + 1 }\n").unwrap().halstead;
+    // Operators fn, (), ->, {}, +; operands a, u8, 1 twice. The doc comment counts for nothing.
+    assert_eq!((tiny.distinct_operators, tiny.operators, tiny.distinct_operands, tiny.operands), (5, 5, 3, 4));
+    assert_eq!((tiny.volume, tiny.difficulty, tiny.effort), (27.0, 3.33, 90.0));
+    assert!(file_metrics("src/bad.rs", "fn broken( {").is_err());
+
+    let tokei = parse_tokei(r#"{"Rust":{"blanks":3,"code":20,"comments":4,"reports":[{"name":"a.rs","stats":{}},{"name":"b.rs","stats":{}}],"children":{},"inaccurate":false},"TOML":{"blanks":0,"code":5,"comments":0,"reports":[{"name":"Cargo.toml","stats":{}}],"children":{},"inaccurate":false},"Total":{"blanks":3,"code":25,"comments":4,"reports":[],"children":{},"inaccurate":false}}"#).unwrap();
+    assert_eq!(tokei.languages.keys().collect::<Vec<_>>(), ["Rust", "TOML"]);
+    assert_eq!((tokei.languages["Rust"].files, tokei.total.files, tokei.total.code, tokei.total.comments), (2, 3, 25, 4));
+    assert!(parse_tokei("tokei: unknown output format").is_none());
+}
+
+#[test]
+fn per_file_metrics_are_written_beside_the_outputs_once_per_repository() {
+    let base = std::env::temp_dir().join(format!("dataset_builder_metrics_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&base);
+    let repo = base.join("datasets/repo");
+    fs::create_dir_all(repo.join("src")).unwrap();
+    fs::write(repo.join("src/lib.rs"), SOURCE).unwrap();
+    fs::write(repo.join("src/broken.rs"), "fn broken( {\n").unwrap();
+    let root = base.join("datasets").display().to_string();
+    let outputs = base.join("outputs.jsonl").display().to_string();
+    let opts = AnalyzeOptions { only: vec!["complexity".into()], ..AnalyzeOptions::default() };
+
+    run_outputs(&root, &outputs, &opts).unwrap();
+    let first = fs::read_to_string(base.join("metrics.jsonl")).unwrap();
+    run_outputs(&root, &outputs, &AnalyzeOptions { resume: true, force: vec!["repo".into()], ..opts.clone() }).unwrap();
+    let resumed = fs::read_to_string(base.join("metrics.jsonl")).unwrap();
+    let entry: OutputEntry = serde_json::from_str(fs::read_to_string(&outputs).unwrap().lines().next().unwrap()).unwrap();
+    let _ = fs::remove_dir_all(&base);
+
+    let lines: Vec<MetricsEntry> = first.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(lines.iter().map(|l| (l.dir.as_str(), l.metrics.path.as_str(), l.metrics.cognitive)).collect::<Vec<_>>(), [("repo", "src/lib.rs", 12)]);
+    assert_eq!(resumed, first, "a repository analyzed again replaces its lines");
+    match &entry.tools["complexity"].report {
+        Some(Report::Complexity(r)) => assert_eq!((r.files, r.parse_failed, r.functions, r.cyclomatic_max, r.cognitive_mean), (1, 1, 2, 8, 6.0)),
+        other => panic!("no complexity report: {:?}", other),
+    }
+    assert!(entry.time_ms.contains_key("complexity"));
+}
@@ -0,0 +1,174 @@
+use std::fs;
+use std::io::BufRead;
+
+use dataset_builder::{open_jsonl, AnalyzeOptions, Codec, CollectOptions, Pipeline, PipelineState, ShardOptions, Step, StepOptions, StepStatus, StreamOptions, DEFAULT_CLONE_ERRORS_FILE, PIPELINE_STATE_FILE};
+
+#[test]
+fn stream_writes_both_files_and_records_failed_clones() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_stream_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    // Already checked out, so it is analyzed without cloning (and, not being cloned by the run, kept).
+    let local = dir.join("datasets/dataset_local__crate");
+    fs::create_dir_all(local.join("src")).unwrap();
+    fs::write(local.join("Cargo.toml"), "[package]\nname = \"local\"\nversion = \"0.1.0\"\nedition = \"2021\"\n").unwrap();
+    fs::write(local.join("src/main.rs"), "fn main() {}\n").unwrap();
+    // A local repository that isn't there, which fails to clone without the network. The filter
+    // takes only `owner/name` rows, so the names file is written instead.
+    let missing = format!("file://{}", dir.join("no-such-origin").display());
+    fs::write(dir.join("input.csv"), "id,name,has_toml,has_lock\n1,local/crate,true,true\n").unwrap();
+    fs::write(dir.join("filtered.txt"), format!("local/crate\n{}\n", missing)).unwrap();
+
+    let path = |file: &str| dir.join(file).display().to_string();
+    let pipeline = Pipeline {
+        input_csv: path("input.csv"),
+        filtered_repos: path("filtered.txt"),
+        datasets_dir: path("datasets"),
+        outputs_file: path("outputs.jsonl"),
+        code_file: path("code.jsonl"),
+        clone_retries: 0,
+        analyze: AnalyzeOptions { only: vec!["fmt".into()], ..AnalyzeOptions::default() },
+        stream: StreamOptions { stream: true, errors_file: Some(path("errors.jsonl")), ..StreamOptions::default() },
+        steps: StepOptions { skip_steps: vec![Step::Filter], ..StepOptions::default() },
+        ..Pipeline::default()
+    };
+    pipeline.run().unwrap();
+
+    let outputs = fs::read_to_string(dir.join("outputs.jsonl")).unwrap();
+    let code = fs::read_to_string(dir.join("code.jsonl")).unwrap();
+    let errors = fs::read_to_string(dir.join("errors.jsonl")).unwrap();
+    let kept = local.join("src/main.rs").is_file();
+    let checkouts: Vec<_> = fs::read_dir(dir.join("datasets")).unwrap().flatten().filter(|e| e.path().is_dir()).map(|e| e.file_name()).collect();
+    let _ = fs::remove_dir_all(&dir);
+
+    assert!(outputs.lines().count() == 1 && outputs.contains("\"name\":\"local/crate\",\"dir\":\"dataset_local__crate\""), "{}", outputs);
+    assert!(code.contains("\"path\":\"src/main.rs\"") && code.contains("\"name\":\"local/crate\",\"dir\":\"dataset_local__crate\""), "{}", code);
+    let error: serde_json::Value = serde_json::from_str(errors.lines().next().unwrap()).unwrap();
+    assert_eq!(error["name"], missing.as_str());
+    assert_eq!(error["stage"], "clone");
+    assert!(kept, "the checkout the run didn't clone is kept");
+    assert_eq!(checkouts, ["dataset_local__crate"], "the failed clone leaves no checkout behind");
+}
+
+#[test]
+fn full_takes_the_filter_format_beside_the_analysis_options() {
+    let help = std::process::Command::new(env!("CARGO_BIN_EXE_dataset_builder")).args(["full", "--help"]).output().unwrap();
+    let text = String::from_utf8_lossy(&help.stdout);
+
+    assert!(help.status.success(), "{}", String::from_utf8_lossy(&help.stderr));
+    assert!(text.contains("--format <FORMAT>") && text.contains("Input format"), "{}", text);
+}
+
+fn local_checkout(test: &str) -> (std::path::PathBuf, Pipeline) {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_{}_{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let local = dir.join("datasets/dataset_local__crate");
+    fs::create_dir_all(local.join("src")).unwrap();
+    fs::write(local.join("Cargo.toml"), "[package]\nname = \"local\"\nversion = \"0.1.0\"\nedition = \"2021\"\n").unwrap();
+    fs::write(local.join("src/main.rs"), "fn main() {}\n").unwrap();
+    fs::write(dir.join("input.csv"), "id,name,has_toml,has_lock\n1,local/crate,true,true\n").unwrap();
+    let path = |file: &str| dir.join(file).display().to_string();
+    let pipeline = Pipeline {
+        input_csv: path("input.csv"),
+        filtered_repos: path("filtered.txt"),
+        datasets_dir: path("datasets"),
+        outputs_file: path("outputs.jsonl"),
+        code_file: path("code.jsonl"),
+        analyze: AnalyzeOptions { only: vec!["fmt".into()], ..AnalyzeOptions::default() },
+        ..Pipeline::default()
+    };
+    (dir, pipeline)
+}
+
+#[test]
+fn skipped_steps_need_their_files_and_are_recorded_in_the_state_file() {
+    let (dir, pipeline) = local_checkout("pipeline_steps");
+    let empty = Pipeline { datasets_dir: dir.join("nothing").display().to_string(), steps: StepOptions { from_step: Some(Step::Outputs), ..StepOptions::default() }, ..pipeline.clone() };
+
+    let missing = empty.run().unwrap_err().to_string();
+    let nothing_written = !dir.join("manifest.json").exists() && !dir.join(PIPELINE_STATE_FILE).exists();
+    Pipeline { steps: StepOptions { skip_steps: vec![Step::Filter, Step::Clone], ..StepOptions::default() }, ..pipeline }.run().unwrap();
+    let state: PipelineState = serde_json::from_str(&fs::read_to_string(dir.join(PIPELINE_STATE_FILE)).unwrap()).unwrap();
+    let filtered = dir.join("filtered.txt").exists();
+    let code = fs::read_to_string(dir.join("code.jsonl")).unwrap();
+    let _ = fs::remove_dir_all(&dir);
+
+    assert!(missing.contains("has no checkouts; the outputs step needs the clone step's"), "{}", missing);
+    assert!(nothing_written, "a missing prerequisite stops the run before it writes anything");
+    assert_eq!(state.steps.iter().map(|r| (r.step, r.status)).collect::<Vec<_>>(), [(Step::Filter, StepStatus::Skipped), (Step::Clone, StepStatus::Skipped), (Step::Outputs, StepStatus::Completed), (Step::Collect, StepStatus::Completed)]);
+    assert!(state.steps[2].started_at.is_some() && state.steps[2].outputs[0].ends_with("outputs.jsonl"));
+    assert_eq!(state.config.analyze.only.as_deref(), Some(&["fmt".to_string()][..]));
+    assert!(!filtered && code.contains("\"path\":\"src/main.rs\""));
+}
+
+#[test]
+fn resume_runs_only_the_steps_the_last_run_did_not_complete() {
+    let (dir, pipeline) = local_checkout("pipeline_resume");
+
+    Pipeline { steps: StepOptions { skip_steps: vec![Step::Collect], ..StepOptions::default() }, ..pipeline.clone() }.run().unwrap();
+    let first: PipelineState = serde_json::from_str(&fs::read_to_string(dir.join(PIPELINE_STATE_FILE)).unwrap()).unwrap();
+    let outputs = fs::read_to_string(dir.join("outputs.jsonl")).unwrap();
+    let manifest = fs::read_to_string(dir.join("manifest.json")).unwrap();
+    let clone_errors = dir.join(DEFAULT_CLONE_ERRORS_FILE).is_file();
+    fs::remove_file(dir.join("filtered.txt")).unwrap();
+    let resumed = Pipeline { analyze: AnalyzeOptions { resume: true, ..pipeline.analyze.clone() }, ..pipeline };
+    resumed.run().unwrap();
+    let state: PipelineState = serde_json::from_str(&fs::read_to_string(dir.join(PIPELINE_STATE_FILE)).unwrap()).unwrap();
+    let outputs_after = fs::read_to_string(dir.join("outputs.jsonl")).unwrap();
+    let manifest_after = fs::read_to_string(dir.join("manifest.json")).unwrap();
+    let code = dir.join("code.jsonl").is_file();
+    let _ = fs::remove_dir_all(&dir);
+
+    assert!(clone_errors, "the clone step's errors file is written next to the outputs file");
+    assert_eq!(first.steps.iter().map(|r| r.status).collect::<Vec<_>>(), [StepStatus::Completed, StepStatus::Completed, StepStatus::Completed, StepStatus::Skipped]);
+    assert!(state.steps.iter().all(|r| r.status == StepStatus::Completed), "{:?}", state.steps);
+    let runs: Vec<bool> = state.steps.iter().map(|r| r.run_id == first.run_id).collect();
+    assert_eq!(runs, [true, true, true, false], "the completed steps keep the run that did them");
+    assert!(outputs_after == outputs && manifest_after == manifest && code, "only collect ran again");
+}
+
+#[test]
+fn full_and_stream_compress_the_outputs_file_as_outputs_does() {
+    let (dir, pipeline) = local_checkout("pipeline_compress");
+    fs::write(dir.join("filtered.txt"), "local/crate\n").unwrap();
+    let analyze = AnalyzeOptions { compress: Some(Codec::Gzip(6)), ..pipeline.analyze.clone() };
+    let steps = StepOptions { skip_steps: vec![Step::Filter, Step::Collect], ..StepOptions::default() };
+    let path = |file: &str| dir.join(file).display().to_string();
+
+    Pipeline { analyze: analyze.clone(), steps, ..pipeline.clone() }.run().unwrap();
+    let full = open_jsonl(&path("outputs.jsonl.gz")).unwrap().lines().map(Result::unwrap).collect::<Vec<_>>();
+    let state: PipelineState = serde_json::from_str(&fs::read_to_string(dir.join(PIPELINE_STATE_FILE)).unwrap()).unwrap();
+    let plain_after_full = dir.join("outputs.jsonl").exists();
+    fs::remove_file(dir.join("outputs.jsonl.gz")).unwrap();
+    let stream = StreamOptions { stream: true, keep_repos: true, ..StreamOptions::default() };
+    Pipeline { analyze, stream, steps: StepOptions { skip_steps: vec![Step::Filter], ..StepOptions::default() }, ..pipeline }.run().unwrap();
+    let streamed = open_jsonl(&path("outputs.jsonl.gz")).unwrap().lines().map(Result::unwrap).collect::<Vec<_>>();
+    let plain_after_stream = dir.join("outputs.jsonl").exists();
+    let _ = fs::remove_dir_all(&dir);
+
+    assert!(full.len() == 1 && full[0].contains("\"dir\":\"dataset_local__crate\""), "{:?}", full);
+    assert!(state.steps[2].outputs[0].ends_with("outputs.jsonl.gz"), "{:?}", state.steps[2]);
+    assert!(streamed.len() == 1 && streamed[0].contains("\"dir\":\"dataset_local__crate\""), "{:?}", streamed);
+    assert!(!plain_after_full && !plain_after_stream, "nothing is written under the uncompressed name");
+}
+
+#[test]
+fn full_and_stream_shard_the_outputs_file_and_stream_the_code_file() {
+    let (dir, pipeline) = local_checkout("pipeline_shard");
+    fs::write(dir.join("filtered.txt"), "local/crate\n").unwrap();
+    let shard = ShardOptions { shard_entries: Some(1), shard_size: None };
+    let analyze = AnalyzeOptions { shard: shard.clone(), ..pipeline.analyze.clone() };
+    let exists = |file: &str| dir.join(file).exists();
+
+    Pipeline { analyze: analyze.clone(), steps: StepOptions { skip_steps: vec![Step::Filter, Step::Collect], ..StepOptions::default() }, ..pipeline.clone() }.run().unwrap();
+    let full = (exists("outputs.manifest.json"), exists("outputs-00000.jsonl"), exists("outputs.jsonl"));
+    let state: PipelineState = serde_json::from_str(&fs::read_to_string(dir.join(PIPELINE_STATE_FILE)).unwrap()).unwrap();
+    let stream = StreamOptions { stream: true, keep_repos: true, ..StreamOptions::default() };
+    let collect = CollectOptions { shard, ..CollectOptions::default() };
+    Pipeline { analyze, collect, stream, steps: StepOptions { skip_steps: vec![Step::Filter], ..StepOptions::default() }, ..pipeline }.run().unwrap();
+    let streamed = [exists("outputs.manifest.json"), exists("code.manifest.json"), !exists("outputs.jsonl"), !exists("code.jsonl")];
+    let _ = fs::remove_dir_all(&dir);
+
+    assert_eq!(full, (true, true, false), "the outputs file is split into shards with a manifest");
+    assert!(state.steps[2].outputs[0].ends_with("outputs.manifest.json"), "{:?}", state.steps[2]);
+    assert_eq!(streamed, [true; 4], "--stream shards both files");
+}
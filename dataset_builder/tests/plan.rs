@@ -0,0 +1,64 @@
+use std::fs;
+
+use dataset_builder::{plan_clone, plan_outputs, AnalyzeOptions, CloneOptions, RepoMetadata};
+
+#[test]
+fn the_plan_resolves_clones_sizes_commands_and_resumed_checkouts_without_touching_anything() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_plan_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("datasets/dataset_owner__done")).unwrap();
+    fs::create_dir_all(dir.join("datasets/dataset_owner__todo")).unwrap();
+    let path = |file: &str| dir.join(file).display().to_string();
+    fs::write(path("names.txt"), "owner/todo\nowner/new\nowner/sized\nnot a repo\n").unwrap();
+    let metadata = |repo: &str, size_kb| serde_json::to_string(&RepoMetadata { repo: repo.into(), size_kb: Some(size_kb), ..RepoMetadata::default() }).unwrap();
+    fs::write(path("metadata.jsonl"), format!("{}\n{}\n", metadata("owner/sized", 2048), metadata("owner/todo", 1))).unwrap();
+    // The last line was cut short by a crash; a real run would rewrite the file without it.
+    let outputs = "{\"name\":\"owner/done\",\"dir\":\"dataset_owner__done\"}\n{\"name\":\"owner/to";
+    fs::write(path("outputs.jsonl"), outputs).unwrap();
+    fs::write(path("tools.toml"), "[[tool]]\nname = \"lines\"\ncommand = \"wc\"\nargs = [\"-l\", \"{crate}/src/lib.rs\"]\ntimeout_secs = 5\n").unwrap();
+
+    let clone = plan_clone(&path("names.txt"), &path("datasets"), &CloneOptions { metadata_file: path("metadata.jsonl"), ..CloneOptions::default() }).unwrap();
+    let opts = AnalyzeOptions { only: vec!["clippy".into(), "complexity".into(), "lines".into()], tools_config: Some(path("tools.toml")), tool_timeout: Some(60), resume: true, ..AnalyzeOptions::default() };
+    let cloned = ["dataset_owner__new".to_string(), "dataset_owner__sized".to_string()];
+    let outputs_plan = plan_outputs(&path("datasets"), &path("outputs.jsonl"), &opts, &cloned).unwrap();
+    let untouched = fs::read_to_string(path("outputs.jsonl")).unwrap() == outputs;
+    let created = dir.join("datasets/dataset_owner__new").exists() || dir.join("manifest.json").exists();
+    let _ = fs::remove_dir_all(&dir);
+
+    let repos: Vec<_> = clone.repos.iter().map(|r| (r.name.as_str(), r.exists, r.size_bytes)).collect();
+    assert_eq!(repos, [("owner/todo", true, Some(1024)), ("owner/new", false, None), ("owner/sized", false, Some(2 << 20))]);
+    assert_eq!((clone.estimated_bytes, clone.without_size, clone.rejected.len()), (2 << 20, 1, 1), "the existing checkout is skipped, so only the new ones count");
+    assert_eq!(outputs_plan.repos, ["dataset_owner__new", "dataset_owner__sized", "dataset_owner__todo"]);
+    assert_eq!(outputs_plan.resumed, ["dataset_owner__done"]);
+    let analyzers: Vec<_> = outputs_plan.analyzers.iter().map(|a| (a.name.as_str(), a.command.as_deref(), a.timeout_secs)).collect();
+    assert_eq!(analyzers, [("clippy", Some("cargo clippy --message-format=json"), Some(60)), ("complexity", None, Some(60)), ("lines", Some("wc -l {crate}/src/lib.rs"), Some(5))]);
+    assert!(untouched && !created, "a dry run changes nothing");
+}
+
+#[test]
+fn full_dry_run_prints_the_plan_as_json_and_writes_nothing() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_full_plan_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = |file: &str| dir.join(file).display().to_string();
+    fs::write(path("input.csv"), "id,name,has_toml,has_lock\n1,owner/kept,true,true\n2,owner/dropped,false,false\n").unwrap();
+
+    let out = std::process::Command::new(env!("CARGO_BIN_EXE_dataset_builder"))
+        .args(["full", "--dry-run", "--json", "--only", "fmt", "--input-csv", &path("input.csv"), "--filtered-repos", &path("filtered.txt")])
+        .args(["--datasets-dir", &path("datasets"), "--outputs-file", &path("outputs.jsonl"), "--code-file", &path("code.jsonl")])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    let left: Vec<String> = fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+    let _ = fs::remove_dir_all(&dir);
+
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let plan: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(plan["steps"], serde_json::json!(["filter", "clone", "outputs", "collect"]));
+    assert_eq!(plan["clone"]["repos"].as_array().unwrap().iter().map(|r| r["name"].as_str().unwrap()).collect::<Vec<_>>(), ["owner/kept"]);
+    assert_eq!(plan["clone"]["errors_file"], path("clone_errors.jsonl"), "next to the outputs file, not in the working directory");
+    assert_eq!(plan["outputs"]["repos"], serde_json::json!(["dataset_owner__kept"]));
+    assert_eq!(plan["outputs"]["analyzers"][0]["command"], "cargo fmt -- --check --color never");
+    assert_eq!(plan["collect"]["repos"], serde_json::json!(["dataset_owner__kept"]));
+    assert_eq!(left, ["input.csv"], "nothing is filtered, cloned or recorded");
+}
@@ -0,0 +1,63 @@
+use std::fs;
+use std::path::PathBuf;
+
+use dataset_builder::{collect_code_with, CollectOptions, Provenance};
+
+/// Commits `files` at `time` (seconds since the epoch) on top of HEAD.
+fn commit(repo: &git2::Repository, files: &[(&str, &str)], time: i64) -> String {
+    let mut index = repo.index().unwrap();
+    for (path, content) in files {
+        let full = repo.workdir().unwrap().join(path);
+        fs::create_dir_all(full.parent().unwrap()).unwrap();
+        fs::write(full, content).unwrap();
+        index.add_path(path.as_ref()).unwrap();
+    }
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let sig = git2::Signature::new("test", "test@example.com", &git2::Time::new(time, 0)).unwrap();
+    let parents: Vec<git2::Commit> = repo.head().ok().map(|h| h.peel_to_commit().unwrap()).into_iter().collect();
+    repo.commit(Some("HEAD"), &sig, &sig, "commit", &tree, &parents.iter().collect::<Vec<_>>()).unwrap().to_string()
+}
+
+fn temp(test: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("dataset_builder_{}_{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    root
+}
+
+#[test]
+fn every_file_gets_the_last_commit_that_changed_it() {
+    let root = temp("provenance_last");
+    let repo = git2::Repository::init(&root).unwrap();
+    let first = commit(&repo, &[("Cargo.toml", "[package]\nname = \"p\"\n"), ("src/lib.rs", "pub mod a;\n"), ("src/a.rs", "pub fn a() {}\n")], 1_600_000_000);
+    let second = commit(&repo, &[("src/a.rs", "pub fn a() -> u8 { 1 }\n")], 1_700_000_000);
+    let head = commit(&repo, &[("README.md", "docs\n")], 1_700_000_100);
+
+    let provenance = Provenance::open(&root).unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(provenance.head, head);
+    let last = |path| provenance.last_commit(path).map(|l| (l.commit.clone(), l.date.clone()));
+    assert_eq!(last("src/a.rs"), Some((second, "2023-11-14T22:13:20Z".to_string())));
+    assert_eq!(last("src/lib.rs"), Some((first.clone(), "2020-09-13T12:26:40Z".to_string())));
+    assert_eq!(last("Cargo.toml").map(|(c, _)| c), Some(first));
+    assert_eq!(last("src/gone.rs"), None);
+}
+
+#[test]
+fn collect_stamps_entries_and_flags_working_tree_changes() {
+    let root = temp("provenance_collect");
+    let repo = git2::Repository::init(&root).unwrap();
+    let first = commit(&repo, &[("src/lib.rs", "pub mod a;\n"), ("src/a.rs", "pub fn a() {}\n")], 1_600_000_000);
+    fs::write(root.join("src/a.rs"), "pub fn a() { todo!() }\n").unwrap();
+    fs::write(root.join("src/new.rs"), "pub fn new() {}\n").unwrap();
+
+    let code = collect_code_with(&root, &CollectOptions::default()).unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    let mut rows: Vec<(&str, Option<&str>, Option<bool>)> = code.entries.iter().map(|e| (e.path.as_str(), e.last_commit.as_deref(), e.modified)).collect();
+    rows.sort();
+    assert_eq!(rows, [("src/a.rs", Some(first.as_str()), Some(true)), ("src/lib.rs", Some(first.as_str()), Some(false)), ("src/new.rs", None, Some(true))]);
+    assert!(code.entries.iter().all(|e| e.commit.as_deref() == Some(first.as_str())));
+    assert!(code.entries.iter().all(|e| e.last_commit_date.as_deref() == e.last_commit.as_ref().map(|_| "2020-09-13T12:26:40Z")));
+}
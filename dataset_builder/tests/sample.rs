@@ -0,0 +1,66 @@
+use std::fs;
+
+use dataset_builder::sample::{reservoir, sample_names, Rng, SampleSize, Strata};
+use dataset_builder::{filter_csv_with, FilterOptions};
+
+#[test]
+fn the_same_seed_picks_the_same_sample() {
+    let items: Vec<u32> = (0..1000).collect();
+    let a = reservoir(items.clone(), 20, &mut Rng::new(42));
+    assert_eq!(a, reservoir(items.clone(), 20, &mut Rng::new(42)));
+    assert_ne!(a, reservoir(items.clone(), 20, &mut Rng::new(43)));
+    assert_eq!(reservoir(items, 2000, &mut Rng::new(1)).len(), 1000, "a sample can't exceed its input");
+
+    // Every item is about equally likely to be picked.
+    let mut hits = [0u32; 10];
+    let mut rng = Rng::new(7);
+    for _ in 0..10_000 {
+        for i in reservoir(0..10, 3, &mut rng) {
+            hits[i] += 1;
+        }
+    }
+    assert!(hits.iter().all(|&h| (2700..3300).contains(&h)), "{:?}", hits);
+}
+
+#[test]
+fn strata_are_sampled_in_proportion_and_sorted() {
+    let strata = Strata::parse("stars:0-100,100-1000,1000+").unwrap();
+    assert_eq!((strata.bucket(99), strata.bucket(100), strata.bucket(5000)), (Some(0), Some(1), Some(2)));
+    assert!(Strata::parse("stars:100-10").is_err() && Strata::parse("0-100").is_err());
+
+    let names = |prefix: &str, n: usize| (0..n).map(|i| format!("{}/{:03}", prefix, i)).collect::<Vec<_>>();
+    let sample = sample_names(vec![names("low", 600), names("mid", 300), names("high", 100)], SampleSize::Fraction(0.1), 3);
+    let count = |prefix: &str| sample.iter().filter(|n| n.starts_with(prefix)).count();
+    assert_eq!((count("low"), count("mid"), count("high")), (60, 30, 10));
+    assert!(sample.windows(2).all(|w| w[0] < w[1]), "sorted");
+
+    let uneven = sample_names(vec![names("a", 5), names("b", 5), names("c", 5)], SampleSize::Count(4), 0);
+    assert_eq!(uneven.len(), 4, "remainders still add up to the sample size");
+}
+
+#[test]
+fn filter_writes_a_reproducible_sample() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_sample_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.csv");
+    let rows: String = (0..200).map(|i| format!("o/r{:03},{},true,{}\n", i, i * 10, i % 4 != 0)).collect();
+    fs::write(&input, format!("name,stars,has_toml,has_lock\n{}", rows)).unwrap();
+    let run = |out: &str, opts: &FilterOptions| {
+        let out = dir.join(out);
+        let summary = filter_csv_with(input.to_str().unwrap(), out.to_str().unwrap(), opts).unwrap();
+        (summary, fs::read_to_string(out).unwrap())
+    };
+
+    let opts = FilterOptions { sample: Some(15), seed: 9, ..FilterOptions::default() };
+    let (summary, first) = run("a.txt", &opts);
+    let (_, second) = run("b.txt", &opts);
+    let stratified = FilterOptions { sample: Some(10), stratify_by: Some("stars:0-500,500-1000".into()), ..FilterOptions::default() };
+    let (strat_summary, strat) = run("c.txt", &stratified);
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(summary.to_string(), "200 rows read, 150 kept, 0 rejected, 15 sampled");
+    assert_eq!(first, second);
+    assert!(first.lines().all(|n| n[3..].parse::<u32>().unwrap() % 4 != 0), "only qualifying rows are sampled");
+    assert_eq!(strat.lines().count(), 10);
+    assert_eq!(strat_summary.eliminated, [("--stratify-by stars:0-500,500-1000".to_string(), 75)]);
+}
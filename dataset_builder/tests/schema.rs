@@ -0,0 +1,78 @@
+use std::fs;
+
+use dataset_builder::{convert, from_line, parquet_rows, CodeEntry, ConvertOptions, OutputEntry, Report, ToolStatus, CODE_SCHEMA_VERSION, OUTPUT_SCHEMA_VERSION};
+
+fn tool(status: &str) -> String {
+    format!(r#"{{"status":"{}","exit_code":0}}"#, status)
+}
+
+fn output_v3(name: &str) -> String {
+    let clippy_report = r#"{"diagnostics":[{"lint_name":"clippy::needless_return","level":"warning","file":"src/lib.rs","line_start":3,"line_end":3,"message":"unneeded `return`"}],"counts":{"warning":1},"compile_failed":false}"#;
+    let (done, skipped) = (tool("completed"), tool("skipped"));
+    format!(
+        r#"{{"schema_version":3,"run_id":"r","name":"{}","package":"a","clippy":{},"clippy_report":{},"fmt":{},"audit":{},"auditable":{},"deny":{},"semgrep":{},"geiger":{},"codeql":{},"findings":[],"ast":{},"toolchain":"stable","time_ms":{{"clippy":1200,"fmt":3,"audit":0,"auditable":0,"deny":0,"semgrep":0,"geiger":0,"codeql":0,"codeql_create":0,"codeql_analyze":0,"metadata":40,"tree":0,"ast":0}},"errors":[]}}"#,
+        name, done, clippy_report, done, skipped, skipped, skipped, skipped, skipped, skipped, skipped
+    )
+}
+
+fn output_v4(name: &str) -> String {
+    format!(r#"{{"schema_version":4,"run_id":"r","name":"{}","dir":"d","tools":{{"clippy":{}}},"findings":[],"toolchain":"stable","time_ms":{{"clippy":1200}},"errors":[]}}"#, name, tool("completed"))
+}
+
+const CODE_V1: &str = r#"{"name":"owner/a","dir":"a","path":"src/lib.rs","kind":"lib","content":"pub fn f() {}\n","lines":1,"bytes":14}"#;
+const CODE_V2: &str = r#"{"schema_version":2,"name":"owner/a","dir":"a","path":"src/lib.rs","kind":"lib","package":"a","target":"lib","content":"pub fn f() {}\n","lines":1,"bytes":14}"#;
+
+#[test]
+fn each_supported_version_reads_into_the_newest_layout_and_round_trips() {
+    let v3: OutputEntry = from_line(&output_v3("owner/a")).unwrap();
+    let v4: OutputEntry = from_line(&output_v4("owner/a")).unwrap();
+    let v1: CodeEntry = from_line(CODE_V1).unwrap();
+    let v2: CodeEntry = from_line(CODE_V2).unwrap();
+
+    assert_eq!((v3.schema_version, v4.schema_version, v1.schema_version, v2.schema_version), (OUTPUT_SCHEMA_VERSION, OUTPUT_SCHEMA_VERSION, CODE_SCHEMA_VERSION, CODE_SCHEMA_VERSION));
+    assert_eq!(v3.tools.keys().collect::<Vec<_>>(), ["ast", "audit", "auditable", "clippy", "codeql", "deny", "fmt", "geiger", "semgrep"]);
+    assert!(matches!(v3.tools["audit"].result.status, ToolStatus::Skipped));
+    match &v3.tools["clippy"].report {
+        Some(Report::Clippy(r)) => assert_eq!((r.diagnostics.len(), r.counts["warning"]), (1, 1)),
+        other => panic!("clippy_report wasn't carried over: {:?}", other),
+    }
+    assert_eq!((v3.package.as_deref(), v3.time_ms["metadata"]), (Some("a"), 40));
+    assert_eq!((v1.path.as_str(), v1.package.as_deref(), v2.target.as_deref()), ("src/lib.rs", None, Some("lib")));
+
+    // What an upgraded line is written as reads back the same, now as the current version.
+    let again: OutputEntry = from_line(&serde_json::to_string(&v3).unwrap()).unwrap();
+    assert_eq!(serde_json::to_value(&again).unwrap(), serde_json::to_value(&v3).unwrap());
+    let again: CodeEntry = from_line(&serde_json::to_string(&v1).unwrap()).unwrap();
+    assert_eq!(serde_json::to_value(&again).unwrap(), serde_json::to_value(&v1).unwrap());
+
+    let too_old = from_line::<OutputEntry>(r#"{"schema_version":2,"run_id":"r","name":"a"}"#).unwrap_err().to_string();
+    assert!(too_old.contains("schema_version 2 isn't supported"), "{}", too_old);
+    assert!(from_line::<CodeEntry>(r#"{"schema_version":3,"name":"a","path":"p","content":""}"#).is_err());
+}
+
+#[test]
+fn convert_upgrade_rewrites_old_lines_and_keeps_current_ones_as_they_are() {
+    let base = std::env::temp_dir().join(format!("dataset_builder_schema_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&base);
+    fs::create_dir_all(&base).unwrap();
+    let path = |f: &str| base.join(f).display().to_string();
+    fs::write(path("outputs.jsonl"), format!("{}\n{}\n", output_v3("owner/old"), output_v4("owner/new"))).unwrap();
+    fs::write(path("code.jsonl"), format!("{}\n{}\n", CODE_V1, CODE_V2)).unwrap();
+    let upgrade = ConvertOptions { upgrade: true, ..ConvertOptions::default() };
+
+    let outputs = convert(&path("outputs.jsonl"), &path("outputs.v4.jsonl"), &upgrade).unwrap();
+    let code = convert(&path("code.jsonl"), &path("code.v2.jsonl"), &upgrade).unwrap();
+    let parquet = convert(&path("outputs.jsonl"), &path("outputs.parquet"), &upgrade).unwrap();
+    let parquet_rows = parquet_rows(&path("outputs.parquet")).unwrap();
+    let upgraded = fs::read_to_string(path("outputs.v4.jsonl")).unwrap();
+    let code_lines = fs::read_to_string(path("code.v2.jsonl")).unwrap();
+    let _ = fs::remove_dir_all(&base);
+
+    assert_eq!((outputs, code, parquet, parquet_rows), (2, 2, 2, 2));
+    let lines: Vec<OutputEntry> = upgraded.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(lines.iter().map(|o| (o.name.as_str(), o.schema_version, o.tools.len())).collect::<Vec<_>>(), [("owner/old", 4, 9), ("owner/new", 4, 1)]);
+    let current: serde_json::Value = serde_json::from_str(&output_v4("owner/new")).unwrap();
+    assert_eq!(serde_json::from_str::<serde_json::Value>(upgraded.lines().nth(1).unwrap()).unwrap(), current, "a current line isn't touched");
+    let code: Vec<CodeEntry> = code_lines.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert!(code.iter().all(|c| c.schema_version == CODE_SCHEMA_VERSION && c.content == "pub fn f() {}\n"));
+}
@@ -0,0 +1,69 @@
+use std::fs;
+use std::io::BufRead;
+use std::path::PathBuf;
+
+use dataset_builder::{collect_code_all, convert, join_datasets, open_jsonl, parse_size, validate_jsonl, CollectOptions, ConvertOptions, JoinOptions, ShardManifest, ShardOptions};
+
+fn checkout(test: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("dataset_builder_{}_{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    for repo in ["dataset_a", "dataset_b", "dataset_c"] {
+        fs::create_dir_all(root.join(repo).join("src")).unwrap();
+        fs::write(root.join(repo).join("Cargo.toml"), "[package]\nname = \"a\"\n").unwrap();
+        fs::write(root.join(repo).join("src/lib.rs"), format!("pub fn {}() {{}}\n", repo)).unwrap();
+    }
+    root
+}
+
+#[test]
+fn sizes_parse_with_decimal_and_binary_units() {
+    assert_eq!((parse_size("1GB"), parse_size("500MiB"), parse_size("64k"), parse_size("1.5kb"), parse_size("100")), (Ok(1_000_000_000), Ok(500 << 20), Ok(64_000), Ok(1500), Ok(100)));
+    assert!(parse_size("1PB").is_err() && parse_size("GB").is_err() && parse_size("0").is_err());
+}
+
+#[test]
+fn collect_rolls_shards_on_entry_boundaries_that_readers_take_through_the_manifest() {
+    let root = checkout("shard_collect");
+    let path = |f: &str| root.join(f).display().to_string();
+    let opts = CollectOptions { shard: ShardOptions { shard_entries: Some(4), shard_size: None }, ..CollectOptions::default() };
+
+    collect_code_all(root.to_str().unwrap(), &path("code.jsonl.zst"), &opts).unwrap();
+    let manifest = ShardManifest::load(&path("code.manifest.json")).unwrap();
+    let hashes: Vec<String> = manifest.shards.iter().map(|s| blake3::hash(&fs::read(root.join(&s.path)).unwrap()).to_hex().to_string()).collect();
+    let working_left = root.join("code.jsonl.zst").exists();
+    let read = open_jsonl(&path("code.manifest.json")).unwrap().lines().count();
+    let opts = JoinOptions { code: path("code.manifest.json"), outputs: None, metadata: None, out: path("dataset.jsonl"), by_file: false };
+    let joined = join_datasets(&opts).unwrap();
+    let rows = convert(&path("code.manifest.json"), &path("code.parquet"), &ConvertOptions::default()).unwrap();
+    // Rerun with a byte cap: a shard per line, as every line is over 20 bytes.
+    let opts = CollectOptions { shard: ShardOptions { shard_entries: None, shard_size: Some(20) }, ..CollectOptions::default() };
+    collect_code_all(root.to_str().unwrap(), &path("code.jsonl.zst"), &opts).unwrap();
+    let by_size = ShardManifest::load(&path("code.manifest.json")).unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    assert!(!working_left, "the working file is removed once sharded");
+    let shards: Vec<(&str, usize)> = manifest.shards.iter().map(|s| (s.path.as_str(), s.entries)).collect();
+    assert_eq!(shards, [("code-00000.jsonl.zst", 4), ("code-00001.jsonl.zst", 2)]);
+    assert_eq!(hashes, manifest.shards.iter().map(|s| s.blake3.clone()).collect::<Vec<_>>());
+    assert_eq!((manifest.entries, manifest.bytes), (6, manifest.shards.iter().map(|s| s.bytes).sum()));
+    assert_eq!((read, joined.entries, rows), (6, 6, 6));
+    assert_eq!((by_size.shards.len(), by_size.entries), (6, 6));
+}
+
+#[test]
+fn validate_checks_shards_against_their_manifest() {
+    let root = checkout("shard_validate");
+    let path = |f: &str| root.join(f).display().to_string();
+    let opts = CollectOptions { shard: ShardOptions { shard_entries: Some(3), shard_size: None }, ..CollectOptions::default() };
+    collect_code_all(root.to_str().unwrap(), &path("code.jsonl"), &opts).unwrap();
+
+    let valid = validate_jsonl(&path("code.manifest.json"), false);
+    let mut shard = fs::read_to_string(path("code-00001.jsonl")).unwrap();
+    shard.replace_range(..1, "[");
+    fs::write(path("code-00001.jsonl"), shard).unwrap();
+    let corrupted = validate_jsonl(&path("code.manifest.json"), true).unwrap_err();
+    fs::remove_dir_all(&root).unwrap();
+
+    assert!(valid.is_ok(), "{:?}", valid);
+    assert!(format!("{:#}", corrupted).contains("bad shards: code-00001.jsonl"), "{:#}", corrupted);
+}
@@ -0,0 +1,122 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Two scratch crates and a tools config whose only tool runs `sh -c <script>`.
+fn scratch(test: &str, script: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_shutdown_{}_{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    for name in ["a", "b"] {
+        let repo = dir.join("root").join(name);
+        fs::create_dir_all(repo.join("src")).unwrap();
+        fs::write(repo.join("Cargo.toml"), format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n", name)).unwrap();
+        fs::write(repo.join("src/main.rs"), "fn main() {}\n").unwrap();
+    }
+    fs::write(dir.join("tools.toml"), format!("[[tool]]\nname = \"slow\"\ncommand = \"sh\"\nargs = [\"-c\", \"{}\"]\n", script)).unwrap();
+    dir
+}
+
+fn outputs(dir: &Path, extra: &[&str]) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_dataset_builder"))
+        .current_dir(dir)
+        .args(["outputs", "root", "out.jsonl", "--tools-config", "tools.toml", "--only", "slow", "--jobs", "1"])
+        .args(extra)
+        .env("RUST_BACKTRACE", "0")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap()
+}
+
+fn interrupt(child: &Child) {
+    std::thread::sleep(Duration::from_secs(1));
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) };
+}
+
+#[test]
+fn ctrl_c_finishes_the_repo_in_flight_and_resume_does_the_rest() {
+    let dir = scratch("resume", "sleep 3");
+    let mut run = outputs(&dir, &[]);
+    interrupt(&run);
+    assert!(!run.wait().unwrap().success());
+    let checkpoint: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join("checkpoint.json")).unwrap()).unwrap();
+    let first = fs::read_to_string(dir.join("out.jsonl")).unwrap();
+    assert_eq!(checkpoint["step"], "outputs");
+    assert_eq!(checkpoint["completed"].as_array().unwrap().len(), 1, "{}", checkpoint);
+    assert!(first.lines().count() == 1 && first.ends_with('\n'), "{}", first);
+
+    assert!(outputs(&dir, &["--resume"]).wait().unwrap().success());
+    let all = fs::read_to_string(dir.join("out.jsonl")).unwrap();
+    let checkpoint_left = dir.join("checkpoint.json").exists();
+    let _ = fs::remove_dir_all(&dir);
+    assert!(all.lines().count() == 2 && all.contains("\"name\":\"a\"") && all.contains("\"name\":\"b\""), "{}", all);
+    assert!(!checkpoint_left, "the checkpoint is removed once the step completes");
+}
+
+#[test]
+fn tools_still_running_after_the_grace_period_are_killed() {
+    let dir = scratch("grace", "sleep 60");
+    let start = Instant::now();
+    let mut run = outputs(&dir, &["--shutdown-grace", "0"]);
+    interrupt(&run);
+    assert!(!run.wait().unwrap().success());
+    let elapsed = start.elapsed();
+    let checkpoint = fs::read_to_string(dir.join("checkpoint.json")).unwrap();
+    let out = fs::read_to_string(dir.join("out.jsonl")).unwrap();
+    let _ = fs::remove_dir_all(&dir);
+    assert!(elapsed < Duration::from_secs(30), "{:?}", elapsed);
+    assert!(checkpoint.contains("\"completed\": []"), "{}", checkpoint);
+    assert!(out.is_empty(), "the killed repository's entry is not written: {}", out);
+}
+
+/// A local origin with one crate committed, and the names file listing its `file://` URL.
+fn origin(dir: &Path) -> String {
+    let origin = git2::Repository::init(dir.join("origin")).unwrap();
+    fs::create_dir_all(dir.join("origin/src")).unwrap();
+    fs::write(dir.join("origin/Cargo.toml"), "[package]\nname = \"origin\"\nversion = \"0.1.0\"\nedition = \"2021\"\n").unwrap();
+    fs::write(dir.join("origin/src/main.rs"), "fn main() {}\n").unwrap();
+    let mut index = origin.index().unwrap();
+    index.add_all(["*"], git2::IndexAddOption::DEFAULT, None).unwrap();
+    let tree = origin.find_tree(index.write_tree().unwrap()).unwrap();
+    let sig = git2::Signature::now("test", "test@example.com").unwrap();
+    origin.commit(Some("HEAD"), &sig, &sig, "init", &tree, &[]).unwrap();
+    let url = format!("file://{}", dir.join("origin").display());
+    fs::write(dir.join("filtered.txt"), format!("{}\n", url)).unwrap();
+    url
+}
+
+fn stream(dir: &Path, extra: &[&str]) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_dataset_builder"))
+        .current_dir(dir)
+        .args(["full", "--stream", "--skip-steps", "filter", "--filtered-repos", "filtered.txt", "--datasets-dir", "datasets"])
+        .args(["--outputs-file", "out.jsonl", "--code-file", "code.jsonl", "--tools-config", "tools.toml", "--only", "slow", "--jobs", "1"])
+        .args(extra)
+        .env("RUST_BACKTRACE", "0")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap()
+}
+
+#[test]
+fn a_checkout_kept_by_an_interrupted_stream_is_deleted_by_the_resumed_one() {
+    let dir = scratch("stream", "sleep 60");
+    let url = origin(&dir);
+    let mut run = stream(&dir, &["--shutdown-grace", "0"]);
+    interrupt(&run);
+    assert!(!run.wait().unwrap().success());
+    let checkpoint: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join("checkpoint.json")).unwrap()).unwrap();
+    let checkouts = || fs::read_dir(dir.join("datasets")).unwrap().flatten().filter(|e| e.path().is_dir()).count();
+    let kept = checkouts();
+
+    fs::write(dir.join("tools.toml"), "[[tool]]\nname = \"slow\"\ncommand = \"true\"\n").unwrap();
+    let resumed = stream(&dir, &["--resume"]).wait().unwrap().success();
+    let left = checkouts();
+    let out = fs::read_to_string(dir.join("out.jsonl")).unwrap();
+    let _ = fs::remove_dir_all(&dir);
+    assert_eq!(checkpoint["cloned"], serde_json::json!([url]), "{}", checkpoint);
+    assert_eq!(kept, 1, "the interrupted run keeps the checkout for the resumed one");
+    assert!(resumed && out.lines().count() == 1, "{}", out);
+    assert_eq!(left, 0, "the resumed run deletes the checkout the interrupted one cloned");
+}
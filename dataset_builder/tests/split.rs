@@ -0,0 +1,66 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use dataset_builder::{split_dataset, SplitMap, SplitOptions};
+
+fn code(repos: usize) -> String {
+    (0..repos).flat_map(|r| (0..1 + r % 3).map(move |f| format!(r#"{{"name":"owner/repo{}","dir":"dataset_repo{}","path":"src/f{}.rs","content":"fn f() {{}}"}}"#, r, r, f) + "\n")).collect()
+}
+
+fn inputs(test: &str, repos: usize) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_{}_{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("code.jsonl"), code(repos)).unwrap();
+    dir
+}
+
+fn split(dir: &Path, opts: SplitOptions) -> (SplitMap, BTreeMap<String, Vec<String>>) {
+    let map = split_dataset(&SplitOptions { input: dir.join("code.jsonl").display().to_string(), ..opts }).unwrap();
+    let names = ["train", "val", "test"].into_iter().map(|s| {
+        let lines = fs::read_to_string(dir.join(format!("{}.jsonl", s))).unwrap();
+        (s.to_string(), lines.lines().map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap()["name"].as_str().unwrap().to_string()).collect())
+    });
+    (map, names.collect())
+}
+
+#[test]
+fn repositories_keep_their_split_when_others_are_added() {
+    let dir = inputs("split_stable", 40);
+    let opts = || SplitOptions { train: 0.5, val: 0.25, test: 0.25, seed: 42, ..SplitOptions::default() };
+
+    let (before, files) = split(&dir, opts());
+    let written: SplitMap = serde_json::from_str(&fs::read_to_string(dir.join("splits.json")).unwrap()).unwrap();
+    fs::write(dir.join("code.jsonl"), code(60)).unwrap();
+    let (after, _) = split(&dir, opts());
+    let (reseeded, _) = split(&dir, SplitOptions { seed: 7, ..opts() });
+    let bad = split_dataset(&SplitOptions { input: dir.join("code.jsonl").display().to_string(), train: 0.9, val: 0.2, ..SplitOptions::default() });
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(written, before);
+    for (split, names) in &files {
+        assert!(names.iter().all(|n| &before.repos[n] == split), "every file of a repository is in its split");
+        assert_eq!((before.counts[split].files, before.counts[split].repos), (names.len(), before.repos.values().filter(|s| *s == split).count()));
+    }
+    assert_eq!(before.counts.values().map(|c| c.files).sum::<usize>(), 79);
+    assert!(before.repos.iter().all(|(name, split)| &after.repos[name] == split));
+    assert_ne!(after.repos, reseeded.repos);
+    assert!(format!("{:#}", bad.unwrap_err()).contains("add up to"));
+}
+
+#[test]
+fn stratified_splits_cut_each_stratum_at_the_fractions() {
+    let dir = inputs("split_strata", 40);
+    // Half the repositories have every file flagged, half none.
+    let labels: String = (0..40).map(|r| format!(r#"{{"name":"owner/repo{}","path":"src/f0.rs","flagged":{}}}"#, r, r % 2 == 0) + "\n").collect();
+    fs::write(dir.join("labels.jsonl"), labels).unwrap();
+    let opts = SplitOptions { train: 0.5, val: 0.25, test: 0.25, stratify_by: Some("flagged:0-50,50+".into()), labels: Some(dir.join("labels.jsonl").display().to_string()), ..SplitOptions::default() };
+
+    let (map, _) = split(&dir, opts);
+    fs::remove_dir_all(&dir).unwrap();
+
+    let flagged = |split: &str| map.repos.iter().filter(|(n, s)| *s == split && n.trim_start_matches("owner/repo").parse::<usize>().unwrap() % 2 == 0).count();
+    assert_eq!((map.counts["train"].repos, map.counts["val"].repos, map.counts["test"].repos), (20, 10, 10));
+    assert_eq!((flagged("train"), flagged("val"), flagged("test")), (10, 5, 5));
+}
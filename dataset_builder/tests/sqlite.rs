@@ -0,0 +1,77 @@
+use std::fs;
+use std::path::PathBuf;
+
+use dataset_builder::{export_sqlite, ExportSqliteOptions};
+use rusqlite::Connection;
+
+fn code(dir: &str, path: &str, content: &str) -> String {
+    format!(r#"{{"name":"owner/{}","dir":"dataset_{}","commit":"abc","path":"{}","kind":"lib","content":"{}","lines":1,"bytes":{},"content_hash":"h-{}","license_spdx":"MIT"}}"#, dir, dir, path, content, content.len(), content) + "\n"
+}
+
+const OUTPUT: &str = r#"{"schema_version":4,"run_id":"r","name":"owner/a","dir":"dataset_a","project_manifest":"Cargo.toml","tools":{"clippy":{"status":"completed","exit_code":0,"report":{"diagnostics":[{"lint_name":"clippy::needless_return","level":"warning","file":"src/lib.rs","line_start":3,"line_end":3,"message":"unneeded `return`"}],"counts":{"warning":1},"compile_failed":false}},"semgrep":{"status":"completed","exit_code":0}},"findings":[{"tool":"semgrep","rule_id":"rust.lang.unsafe","severity":"warning","file":"src/lib.rs","start_line":20,"end_line":21,"message":"unsafe block"}],"toolchain":"stable","time_ms":{"clippy":1200},"errors":[]}"#;
+
+fn inputs(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_{}_{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("code.jsonl"), [code("a", "src/lib.rs", "fn a() {}"), code("a", "src/main.rs", "fn main() {}"), code("b", "src/lib.rs", "fn b() {}")].concat()).unwrap();
+    fs::write(dir.join("outputs.jsonl"), [OUTPUT, "\n"].concat()).unwrap();
+    dir
+}
+
+fn count(db: &Connection, sql: &str) -> i64 {
+    db.query_row(sql, [], |r| r.get(0)).unwrap()
+}
+
+#[test]
+fn code_and_outputs_load_into_related_tables() {
+    let dir = inputs("sqlite_export");
+    let path = |f: &str| dir.join(f).display().to_string();
+    let opts = ExportSqliteOptions { code: Some(path("code.jsonl")), outputs: Some(path("outputs.jsonl")), out: path("dataset.db"), batch_rows: 2, ..ExportSqliteOptions::default() };
+
+    let summary = export_sqlite(&opts).unwrap();
+    let db = Connection::open(path("dataset.db")).unwrap();
+    let tables = (count(&db, "SELECT count(*) FROM repos"), count(&db, "SELECT count(*) FROM files"), count(&db, "SELECT count(*) FROM tool_results"), count(&db, "SELECT count(*) FROM findings"));
+    let flagged: Vec<(String, String, String)> = db
+        .prepare("SELECT r.name, f.path, x.rule_id FROM findings x JOIN repos r ON r.id = x.repo_id JOIN files f ON f.repo_id = r.id AND f.path = x.path ORDER BY x.rule_id")
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let clippy: (String, i64) = db.query_row("SELECT status, time_ms FROM tool_results WHERE tool = 'clippy'", [], |r| Ok((r.get(0)?, r.get(1)?))).unwrap();
+    let indexes = count(&db, "SELECT count(*) FROM sqlite_master WHERE type = 'index' AND name IN ('repos_name', 'files_path', 'findings_rule_id')");
+    let license: String = db.query_row("SELECT license_spdx FROM repos WHERE dir = 'dataset_a'", [], |r| r.get(0)).unwrap();
+    drop(db);
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(tables, (2, 3, 2, 2));
+    assert_eq!((summary.repos, summary.files, summary.tool_results, summary.findings), (2, 3, 2, 2));
+    assert_eq!(flagged, [("owner/a".into(), "src/lib.rs".into(), "clippy::needless_return".into()), ("owner/a".into(), "src/lib.rs".into(), "rust.lang.unsafe".into())]);
+    assert_eq!((clippy, indexes, license), (("completed".to_string(), 1200), 3, "MIT".to_string()), "outputs keep the license the code file gave");
+}
+
+#[test]
+fn append_upserts_by_natural_key() {
+    let dir = inputs("sqlite_append");
+    let path = |f: &str| dir.join(f).display().to_string();
+    let opts = ExportSqliteOptions { code: Some(path("code.jsonl")), outputs: Some(path("outputs.jsonl")), out: path("dataset.db"), no_content: true, ..ExportSqliteOptions::default() };
+    export_sqlite(&opts).unwrap();
+    let empty = count(&Connection::open(path("dataset.db")).unwrap(), "SELECT count(*) FROM files WHERE content IS NULL");
+    fs::write(path("more.jsonl"), [code("a", "src/lib.rs", "fn a2() {}"), code("c", "src/lib.rs", "fn c() {}")].concat()).unwrap();
+
+    let added = ExportSqliteOptions { code: Some(path("more.jsonl")), append: true, ..opts.clone() };
+    export_sqlite(&added).unwrap();
+    export_sqlite(&ExportSqliteOptions { code: None, ..added.clone() }).unwrap();
+    let db = Connection::open(path("dataset.db")).unwrap();
+    let tables = (count(&db, "SELECT count(*) FROM repos"), count(&db, "SELECT count(*) FROM files"), count(&db, "SELECT count(*) FROM tool_results"), count(&db, "SELECT count(*) FROM findings"));
+    let hash: String = db.query_row("SELECT content_hash FROM files f JOIN repos r ON r.id = f.repo_id WHERE r.dir = 'dataset_a' AND f.path = 'src/lib.rs'", [], |r| r.get(0)).unwrap();
+    drop(db);
+    export_sqlite(&ExportSqliteOptions { append: false, ..added }).unwrap();
+    let replaced = count(&Connection::open(path("dataset.db")).unwrap(), "SELECT count(*) FROM files");
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(empty, 3, "--no-content leaves content NULL");
+    assert_eq!(tables, (3, 4, 2, 2), "the changed file and the outputs imported again replace their rows");
+    assert_eq!((hash.as_str(), replaced), ("h-fn a2() {}", 2));
+}
@@ -0,0 +1,73 @@
+use std::fs;
+
+use dataset_builder::{corpus_stats, FileShare, StatsOptions};
+
+fn code(name: &str, path: &str, language: &str, bytes: usize) -> String {
+    format!(r#"{{"name":"{}","path":"{}","language":"{}","content":"{}","lines":2,"bytes":{}}}"#, name, path, language, "x".repeat(bytes), bytes) + "\n"
+}
+
+fn output(name: &str, ms: u64, clippy: &str, geiger: Option<u64>) -> String {
+    let geiger = geiger.map_or(String::new(), |n| format!(r#","geiger":{{"status":"completed","exit_code":0,"report":{{"geiger_status":"ok","crates":[],"total":{{"functions":{},"exprs":0,"impls":0,"traits":0,"methods":0}},"forbids_unsafe":false}}}}"#, n));
+    format!(r#"{{"schema_version":4,"run_id":"r","name":"{}","dir":"d","project_manifest":"Cargo.toml","tools":{{"clippy":{}{}}},"findings":[],"toolchain":"stable","time_ms":{{}},"total_duration_ms":{},"errors":[]}}"#, name, clippy, geiger, ms) + "\n"
+}
+
+fn clippy(warnings: usize) -> String {
+    let diagnostics = vec![r#"{"lint_name":"clippy::needless_return","level":"warning","message":"m"}"#; warnings].join(",");
+    format!(r#"{{"status":"completed","exit_code":0,"report":{{"diagnostics":[{}],"counts":{{"warning":{}}},"compile_failed":false}}}}"#, diagnostics, warnings)
+}
+
+#[test]
+fn code_totals_count_repositories_languages_and_sizes() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_stats_code_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("code.jsonl").display().to_string();
+    // Collect with --jobs interleaves the files of its repositories.
+    let lines = [code("o/a", "src/lib.rs", "Rust", 100), code("o/b", "Cargo.toml", "TOML", 2000), code("o/a", "src/main.rs", "Rust", 300), code("o/b", "README", "Text", 0)];
+    fs::write(&file, lines.concat() + "not json\n\n").unwrap();
+
+    let stats = corpus_stats(&StatsOptions { code: Some(file.clone()), ..StatsOptions::default() }).unwrap();
+    let text = stats.text();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let code = stats.code.unwrap();
+    assert_eq!((code.repos, code.files, code.bytes, code.lines, code.invalid_lines), (2, 4, 2400, 8, 1));
+    assert_eq!(code.languages["Rust"], FileShare { files: 2, bytes: 400 });
+    assert_eq!(code.extensions.keys().collect::<Vec<_>>(), ["(none)", "rs", "toml"]);
+    assert_eq!(code.file_bytes.iter().map(|b| b.count).take(3).collect::<Vec<_>>(), [3, 1, 0], "under 1 KiB, then 1–4 KiB");
+    assert_eq!(code.repo_bytes[0].count, 2, "both repositories are under 16 KiB");
+    assert!(stats.warnings.iter().any(|w| w.contains("1 lines aren't code entries")), "{:?}", stats.warnings);
+    assert!(text.contains("repositories  2") && text.contains("1.0 KiB–4.0 KiB"), "{}", text);
+}
+
+#[test]
+fn outputs_count_tool_outcomes_and_write_reports() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_stats_outputs_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = |f: &str| dir.join(f).display().to_string();
+    let timeout = r#"{"status":"timeout"}"#;
+    let failed = r#"{"status":"completed","exit_code":101}"#;
+    // o/w is a workspace with two entries and a single time.
+    let lines = [output("o/a", 5000, &clippy(0), Some(0)), output("o/w", 9000, &clippy(12), Some(3)), output("o/w", 9000, &clippy(1), None), output("o/b", 100, timeout, None), output("o/c", 700, failed, None)];
+    fs::write(path("outputs.jsonl"), lines.concat()).unwrap();
+    let opts = StatsOptions { outputs: Some(path("outputs.jsonl")), ..StatsOptions::default() };
+
+    let stats = corpus_stats(&opts).unwrap();
+    stats.write_report(&path("stats.html")).unwrap();
+    stats.write_report(&path("stats.json")).unwrap();
+    let html = fs::read_to_string(path("stats.html")).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(path("stats.json")).unwrap()).unwrap();
+    let bad = stats.write_report(&path("stats.txt"));
+    fs::remove_dir_all(&dir).unwrap();
+
+    let o = stats.outputs.unwrap();
+    assert_eq!((o.repos, o.entries), (4, 5));
+    let clippy: Vec<(&str, usize)> = o.tools["clippy"].iter().map(|(k, n)| (k.as_str(), *n)).collect();
+    assert_eq!(clippy, [("failed", 1), ("passed", 3), ("timeout", 1)]);
+    assert_eq!(o.slowest.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), ["o/w", "o/a", "o/c", "o/b"]);
+    assert_eq!(o.clippy_warnings.iter().map(|b| b.count).take(4).collect::<Vec<_>>(), [1, 1, 1, 0], "0, 1–9, 10–99 warnings");
+    assert_eq!(o.top_lints, [("clippy::needless_return".to_string(), 13)]);
+    assert_eq!(o.unsafe_counts.unwrap().iter().map(|b| b.count).take(2).collect::<Vec<_>>(), [1, 1]);
+    assert!(html.starts_with("<!DOCTYPE html>") && html.contains("<h2>Slowest repositories</h2>"));
+    assert_eq!(json["outputs"]["tools"]["geiger"]["passed"], 2);
+    assert!(format!("{:#}", bad.unwrap_err()).contains(".json or .html"));
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use std::fs;
+
+use dataset_builder::parse::{parse_crev, parse_vet};
+use dataset_builder::{analyze_repo, select_analyzers, AnalyzeOptions, Report, RunManifest, ToolStatus};
+
+#[test]
+fn vet_and_crev_output_becomes_a_status_per_dependency() {
+    let success = r#"{"conclusion":"success","vetted_fully":[{"name":"serde","version":"1.0.200"}],"vetted_partially":[],"vetted_with_exemptions":[{"name":"syn","version":"2.0.60"},{"name":"quote","version":"1.0.36"}]}"#;
+    let failure = r#"{"conclusion":"fail (vetting)","failures":[{"name":"libc","version":"0.2.150","missing_criteria":["safe-to-deploy"]}],"suggest":null}"#;
+    let lockfile = [("libc".to_string(), "0.2.150".to_string()), ("log".to_string(), "0.4.21".to_string())];
+    let crev = "status issues lines  geiger flgs crate                version         latest_t\nlocal   0  0     120      0      app                  0.1.0\npass    0  0    4521      2      log                  0.4.21          0.4.21\nnone    0  0   98012    301      libc                 0.2.150\nflagged 1  1    3010      0  CB  smallvec             1.0.0           ↑1.13.2\n";
+
+    let vetted = parse_vet(success, &[], true).unwrap();
+    let failed = parse_vet(failure, &lockfile, false).unwrap();
+    let reviewed = parse_crev(crev).unwrap();
+
+    let statuses = |deps: Vec<(&str, &str)>| deps.into_iter().map(|(n, s)| (n.to_string(), s.to_string())).collect::<Vec<_>>();
+    assert_eq!(vetted.dependencies.iter().map(|d| (d.name.clone(), d.status.clone())).collect::<Vec<_>>(), statuses(vec![("quote", "exempted"), ("serde", "audited"), ("syn", "exempted")]));
+    assert_eq!((vetted.counts["exempted"], vetted.coverage["audited"], vetted.coverage["exempted"]), (2, 33.3, 66.7));
+    assert_eq!(failed.dependencies.iter().map(|d| (d.name.clone(), d.status.clone())).collect::<Vec<_>>(), statuses(vec![("libc", "unreviewed"), ("log", "vetted")]));
+    assert_eq!((failed.dependencies[0].missing_criteria.as_slice(), failed.conclusion.as_str(), failed.own_config), (&["safe-to-deploy".to_string()][..], "fail (vetting)", false));
+    assert_eq!(reviewed.dependencies.iter().map(|d| (d.name.clone(), d.status.clone())).collect::<Vec<_>>(), statuses(vec![("log", "reviewed"), ("libc", "unreviewed"), ("smallvec", "flagged")]));
+    assert_eq!(reviewed.dependencies[2].version, "1.0.0");
+    assert!(parse_vet("error: no store", &[], false).is_none() && parse_crev("error: could not find crev id\n").is_none());
+}
+
+#[cfg(unix)]
+#[test]
+fn vet_gets_an_empty_store_outside_the_checkout_and_a_missing_crev_is_marked() {
+    let base = std::env::temp_dir().join(format!("dataset_builder_supply_chain_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&base);
+    // Reports the first line of the config in the store it was pointed at as a missing criterion.
+    let vet = r#"#!/bin/sh
+case "$*" in *--version*) echo "cargo-vet 0.10.0"; exit 0;; esac
+store=supply-chain; prev=
+for a in "$@"; do [ "$prev" = "--store-path" ] && store=$a; prev=$a; done
+printf '{"conclusion":"fail (vetting)","failures":[{"name":"dep","version":"1.0.0","missing_criteria":["%s"]}]}\n' "$(head -n 1 "$store/config.toml")"
+exit 255
+"#;
+    // What cargo says when there is no cargo-crev on PATH.
+    let crev = "#!/bin/sh\necho 'error: no such command: `crev`' >&2\nexit 101\n";
+    let tools = common::fake_tools(&[("cargo-vet", vet), ("cargo-crev", crev)]);
+    let dir = base.join("plain");
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(dir.join("Cargo.toml"), "[package]\nname = \"plain\"\nversion = \"0.1.0\"\nedition = \"2021\"\n").unwrap();
+    fs::write(dir.join("src/lib.rs"), "pub fn f() {}\n").unwrap();
+
+    let opts = AnalyzeOptions { only: vec!["vet".into(), "crev".into()], tool_env: tools.env(), ..AnalyzeOptions::default() };
+    let mut entry = analyze_repo(&dir, "plain", &select_analyzers(&opts).unwrap(), &opts, &RunManifest::capture(&opts)).remove(0);
+    let stray = dir.join("supply-chain").exists();
+    let store_left = std::env::temp_dir().join(format!("dataset_builder_vet_{}_plain", std::process::id())).exists();
+    let _ = fs::remove_dir_all(&base);
+
+    match entry.tools.remove("vet").unwrap().report {
+        Some(Report::Vet(r)) => assert_eq!((r.own_config, r.dependencies[0].missing_criteria.clone()), (false, vec!["[cargo-vet]".to_string()])),
+        other => panic!("no vet report: {:?}", other),
+    }
+    assert!(!stray && !store_left, "nothing is left in the checkout or the temp dir");
+    assert!(matches!(entry.tools["crev"].result.status, ToolStatus::ToolNotInstalled));
+    assert!(entry.errors.is_empty(), "{:?}", entry.errors);
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use common::serve;
+use dataset_builder::{fetch_metadata, MetadataOptions, Tokens};
+
+fn repo(full_name: &str) -> String {
+    format!(
+        r#"{{"full_name":"{}","stargazers_count":1,"forks_count":0,"open_issues_count":0,"license":null,"topics":[],"default_branch":"main","archived":false,"fork":false,"size":1,"created_at":"2020-01-01T00:00:00Z","pushed_at":"2024-01-01T00:00:00Z","owner":{{"login":"x"}}}}"#,
+        full_name
+    )
+}
+
+#[test]
+fn requests_move_to_the_next_token_when_one_runs_out() {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = seen.clone();
+    let url = serve(Arc::new(move |r| {
+        let token = r.headers.get("authorization").cloned().unwrap_or_default().trim_start_matches("Bearer ").to_string();
+        let mut log = log.lock().unwrap();
+        log.push(token.clone());
+        let first_uses = log.iter().filter(|t| **t == "first").count();
+        let reset = format!("X-RateLimit-Reset: {}\r\n", chrono::Utc::now().timestamp() + 3600);
+        match token.as_str() {
+            // The last request of its window, then refused.
+            "first" if first_uses == 1 => (200, format!("X-RateLimit-Remaining: 1\r\n{}", reset), repo(r.path.trim_start_matches("/repos/"))),
+            "first" => (403, format!("X-RateLimit-Remaining: 0\r\n{}", reset), r#"{"message":"API rate limit exceeded"}"#.into()),
+            _ => (200, "X-RateLimit-Remaining: 4999\r\n".into(), repo(r.path.trim_start_matches("/repos/"))),
+        }
+    }));
+    let dir = std::env::temp_dir().join(format!("dataset_builder_tokens_rotate_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("names.txt"), "a/one\nb/two\nc/three\n").unwrap();
+    let opts = MetadataOptions { api_url: url, retries: 0, ..MetadataOptions::default() };
+    let tokens = Tokens::new(["first".to_string(), "second".to_string()]);
+
+    let started = Instant::now();
+    let summary = fetch_metadata(dir.join("names.txt").to_str().unwrap(), dir.join("metadata.jsonl").to_str().unwrap(), &opts, &tokens).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(summary.fetched, 3);
+    assert_eq!(*seen.lock().unwrap(), ["first", "first", "second", "second"], "the refused request is sent again with the other token");
+    assert!(started.elapsed().as_secs() < 30, "no wait while another token has requests left");
+}
+
+#[test]
+fn tokens_are_loaded_from_a_file_and_a_command_and_never_shown() {
+    let url = serve(Arc::new(|r| match r.headers.get("authorization").map(String::as_str) {
+        Some("Bearer ghp_second") => (403, String::new(), r#"{"message":"Resource not accessible"}"#.into()),
+        _ => (200, String::new(), repo("a/one")),
+    }));
+    let dir = std::env::temp_dir().join(format!("dataset_builder_tokens_file_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("tokens.txt"), "# CI tokens\nghp_first\n\n  ghp_second  \nghp_first\n").unwrap();
+    fs::write(dir.join("names.txt"), "a/one\n").unwrap();
+
+    let tokens = Tokens::load(Some("ghp_second"), Some(dir.join("tokens.txt").to_str().unwrap()), Some("echo ghp_third")).unwrap();
+    let opts = MetadataOptions { api_url: url, ..MetadataOptions::default() };
+    let err = fetch_metadata(dir.join("names.txt").to_str().unwrap(), dir.join("metadata.jsonl").to_str().unwrap(), &opts, &tokens).unwrap_err();
+    let missing = Tokens::load(None, Some(dir.join("nowhere.txt").to_str().unwrap()), None);
+    let failing = Tokens::load(None, None, Some("printf 'ghp_%s' printed; exit 3")).unwrap_err();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(tokens.iter().collect::<Vec<_>>(), ["ghp_second", "ghp_first", "ghp_third"], "blanks, comments and repeats are dropped");
+    assert_eq!(format!("{:?}", tokens), "Tokens(3)");
+    let err = format!("{:#}", err);
+    assert!(err.contains("check token 1 of 3") && !err.contains("ghp_"), "{}", err);
+    assert!(missing.is_err());
+    assert!(!format!("{:#}", failing).contains("ghp_printed"), "{:#}", failing);
+}
+
+#[test]
+fn a_token_given_as_an_argument_is_refused_without_repeating_it() {
+    let run = |args: &[&str]| std::process::Command::new(env!("CARGO_BIN_EXE_dataset_builder")).args(args).env_remove("GITHUB_TOKEN").env("RUST_BACKTRACE", "0").output().unwrap();
+
+    let refused = run(&["ghp_on_argv", "filter", "nowhere.csv", "nowhere.txt"]);
+    let stderr = String::from_utf8_lossy(&refused.stderr);
+
+    assert!(!refused.status.success());
+    assert!(stderr.contains("export GITHUB_TOKEN, or use --token-file") && !stderr.contains("ghp_on_argv"), "{}", stderr);
+}
+
+#[test]
+fn an_ssh_key_passphrase_given_as_an_argument_is_refused() {
+    let refused = std::process::Command::new(env!("CARGO_BIN_EXE_dataset_builder"))
+        .args(["clone", "nowhere.txt", "nowhere", "--auth", "ssh", "--ssh-key", "id_ed25519", "--ssh-key-passphrase", "hunter2"])
+        .env_remove("SSH_KEY_PASSPHRASE")
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&refused.stderr);
+
+    assert!(!refused.status.success());
+    assert!(stderr.contains("export SSH_KEY_PASSPHRASE") && !stderr.contains("hunter2"), "{}", stderr);
+}
@@ -0,0 +1,201 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use dataset_builder::{analyze_repo, load_tools_config, run_outputs, select_analyzers, AnalyzeOptions, Report, RunManifest, Selection, ToolStatus};
+
+fn write_config(test: &str, text: &str) -> String {
+    let path: PathBuf = std::env::temp_dir().join(format!("dataset_builder_{}_{}.toml", test, std::process::id()));
+    fs::write(&path, text).unwrap();
+    path.display().to_string()
+}
+
+fn names(opts: &AnalyzeOptions) -> Vec<String> {
+    select_analyzers(opts).unwrap().run.iter().map(|a| a.name().to_string()).collect()
+}
+
+#[test]
+fn example_config_adds_tools_and_honors_enabled() {
+    let config = Path::new(env!("CARGO_MANIFEST_DIR")).join("tools.example.toml").display().to_string();
+    let tools = load_tools_config(&config).unwrap();
+    assert_eq!(tools.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), ["mirai", "scc"]);
+
+    let opts = AnalyzeOptions { tools_config: Some(config), ..AnalyzeOptions::default() };
+    let selected = names(&opts);
+    assert!(selected.contains(&"scc".to_string()) && selected.contains(&"clippy".to_string()));
+    assert!(!selected.contains(&"mirai".to_string()), "mirai is opt-in");
+
+    let opts = AnalyzeOptions { enable: vec!["mirai".into()], skip: vec!["scc".into()], ..opts };
+    let selected = names(&opts);
+    assert!(selected.contains(&"mirai".to_string()) && !selected.contains(&"scc".to_string()));
+}
+
+#[test]
+fn invalid_entries_are_rejected_with_their_position() {
+    let clash = write_config("clash", "[[tool]]\nname = \"lines\"\ncommand = \"wc\"\n\n[[tool]]\nname = \"clippy\"\ncommand = \"true\"\n");
+    let err = format!("{:#}", load_tools_config(&clash).unwrap_err());
+    assert!(err.contains("#2 (`clippy`)") && err.contains("built-in"), "{}", err);
+
+    let unknown = write_config("unknown", "[[tool]]\nname = \"x\"\ncommand = \"true\"\nformat = \"xml\"\n");
+    let err = format!("{:#}", load_tools_config(&unknown).unwrap_err());
+    assert!(err.contains("invalid tools config") && err.contains("xml"), "{}", err);
+
+    let opts = AnalyzeOptions { tools_config: Some(clash), ..AnalyzeOptions::default() };
+    assert!(select_analyzers(&opts).is_err());
+}
+
+#[test]
+fn custom_tool_output_is_recorded_under_its_name() {
+    let config = write_config(
+        "run",
+        "[[tool]]\nname = \"where\"\ncommand = \"sh\"\nargs = [\"-c\", \"printf '{\\\"dir\\\": \\\"%s\\\"}' \\\"$0\\\"\", \"{crate}\"]\nformat = \"json\"\n",
+    );
+    let opts = AnalyzeOptions { tools_config: Some(config), ..AnalyzeOptions::default() };
+    let custom = Selection {
+        run: select_analyzers(&opts).unwrap().run.into_iter().filter(|a| a.name() == "where").collect(),
+        skipped: vec!["clippy".into()],
+    };
+    // A scratch crate, since `cargo metadata` writes a Cargo.lock into the checkout.
+    let repo = std::env::temp_dir().join(format!("dataset_builder_run_{}", std::process::id()));
+    fs::create_dir_all(repo.join("src")).unwrap();
+    fs::write(repo.join("Cargo.toml"), "[package]\nname = \"scratch\"\nversion = \"0.1.0\"\nedition = \"2021\"\n").unwrap();
+    fs::write(repo.join("src/main.rs"), "fn main() {}\n").unwrap();
+
+    let entries = analyze_repo(&repo, "scratch", &custom, &opts, &RunManifest::capture(&opts));
+    let out = &entries[0].tools["where"];
+    assert!(matches!(out.result.status, ToolStatus::Completed) && out.result.exit_code == Some(0));
+    let Some(Report::Json(json)) = &out.report else { panic!("no json report: {:?}", out.report) };
+    assert_eq!(json["dir"], fs::canonicalize(&repo).unwrap().display().to_string());
+    assert!(entries[0].time_ms.contains_key("where"));
+    assert!(out.started_at.is_some() && out.finished_at >= out.started_at);
+    assert_eq!(out.result.usage.is_some(), cfg!(unix));
+    assert!(entries[0].total_duration_ms >= entries[0].time_ms["where"]);
+    assert!(matches!(entries[0].tools["clippy"].result.status, ToolStatus::Skipped) && entries[0].time_ms["clippy"] == 0);
+    let _ = fs::remove_dir_all(&repo);
+}
+
+#[test]
+fn oversized_output_is_truncated_and_spilled() {
+    let config = write_config("big", "[[tool]]\nname = \"big\"\ncommand = \"sh\"\nargs = [\"-c\", \"printf 'ééééé'\"]\n");
+    let overflow = std::env::temp_dir().join(format!("dataset_builder_overflow_{}", std::process::id()));
+    let opts = AnalyzeOptions {
+        tools_config: Some(config),
+        only: vec!["big".into()],
+        max_tool_output_bytes: Some(5),
+        overflow_dir: Some(overflow.display().to_string()),
+        ..AnalyzeOptions::default()
+    };
+    let repo = std::env::temp_dir().join(format!("dataset_builder_big_{}", std::process::id()));
+    fs::create_dir_all(repo.join("src")).unwrap();
+    fs::write(repo.join("Cargo.toml"), "[package]\nname = \"big\"\nversion = \"0.1.0\"\nedition = \"2021\"\n").unwrap();
+    fs::write(repo.join("src/main.rs"), "fn main() {}\n").unwrap();
+
+    let entries = analyze_repo(&repo, "big", &select_analyzers(&opts).unwrap(), &opts, &RunManifest::capture(&opts));
+    let spilled = fs::read_to_string(overflow.join(repo.file_name().unwrap()).join("big.stdout"));
+    let _ = fs::remove_dir_all(&repo);
+    let _ = fs::remove_dir_all(&overflow);
+
+    let out = &entries[0].tools["big"].result;
+    // "é" is two bytes, so the cut falls back to the character boundary at 4.
+    assert!(out.truncated);
+    assert_eq!(out.stdout, "éé\n[truncated 6 bytes]");
+    assert_eq!(spilled.unwrap(), "ééééé");
+    assert!(serde_json::from_str::<serde_json::Value>(&serde_json::to_string(&entries[0]).unwrap()).is_ok());
+}
+
+#[test]
+fn per_repo_logs_keep_each_tools_full_stderr() {
+    let config = write_config("log", "[[tool]]\nname = \"noisy\"\ncommand = \"sh\"\nargs = [\"-c\", \"echo 'something went wrong' >&2; exit 3\"]\n");
+    let logs = std::env::temp_dir().join(format!("dataset_builder_logs_{}", std::process::id()));
+    let opts = AnalyzeOptions {
+        tools_config: Some(config),
+        only: vec!["noisy".into()],
+        max_tool_output_bytes: Some(9),
+        per_repo_logs: Some(logs.display().to_string()),
+        ..AnalyzeOptions::default()
+    };
+    let repo = std::env::temp_dir().join(format!("dataset_builder_noisy_{}", std::process::id()));
+    fs::create_dir_all(repo.join("src")).unwrap();
+    fs::write(repo.join("Cargo.toml"), "[package]\nname = \"noisy\"\nversion = \"0.1.0\"\nedition = \"2021\"\n").unwrap();
+    fs::write(repo.join("src/main.rs"), "fn main() {}\n").unwrap();
+
+    let entries = analyze_repo(&repo, "noisy", &select_analyzers(&opts).unwrap(), &opts, &RunManifest::capture(&opts));
+    let log = fs::read_to_string(logs.join(format!("{}.log", repo.file_name().unwrap().to_string_lossy())));
+    let _ = fs::remove_dir_all(&repo);
+    let _ = fs::remove_dir_all(&logs);
+
+    assert!(entries[0].tools["noisy"].result.truncated);
+    let log = log.unwrap();
+    assert!(log.contains("===== fetch (exit code 0) ====="), "{}", log);
+    assert!(log.contains("===== noisy (exit code 3) =====\nsomething went wrong\n"), "{}", log);
+}
+
+#[test]
+fn credentials_are_kept_out_of_the_tools_environment() {
+    let root = std::env::temp_dir().join(format!("dataset_builder_env_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("datasets/dataset_owner__repo")).unwrap();
+    let config = write_config("env", "[[tool]]\nname = \"env\"\ncommand = \"env\"\n");
+    let outputs = root.join("outputs.jsonl");
+    // Set on the run's own process only, so the other tests never see them.
+    let mut run = std::process::Command::new(env!("CARGO_BIN_EXE_dataset_builder"));
+    run.arg("outputs").arg(root.join("datasets")).arg(&outputs).args(["--tools-config", &config, "--only", "env"]);
+    for var in ["GITHUB_TOKEN", "AWS_ACCESS_KEY_ID", "AWS_SECRET_ACCESS_KEY", "AWS_SESSION_TOKEN", "HF_TOKEN", "SSH_KEY_PASSPHRASE"] {
+        run.env(var, "leaked-credential");
+    }
+
+    let out = run.output().unwrap();
+    let written = fs::read_to_string(&outputs).unwrap_or_default();
+    let _ = fs::remove_dir_all(&root);
+
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let entry: serde_json::Value = serde_json::from_str(written.lines().next().unwrap()).unwrap();
+    let stdout = entry["tools"]["env"]["stdout"].as_str().unwrap();
+    assert!(stdout.contains("PATH=") && !stdout.contains("leaked-credential"), "{}", stdout);
+}
+
+#[test]
+fn run_variables_are_set_on_each_tool_not_on_the_process() {
+    let root = std::env::temp_dir().join(format!("dataset_builder_tool_vars_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("datasets/dataset_owner__repo")).unwrap();
+    let config = write_config("tool_vars", "[[tool]]\nname = \"env\"\ncommand = \"env\"\n");
+    let opts = AnalyzeOptions { tools_config: Some(config), only: vec!["env".into()], cargo_jobs: Some(3), ..AnalyzeOptions::default() };
+    let outputs = root.join("outputs.jsonl").display().to_string();
+
+    run_outputs(&root.join("datasets").display().to_string(), &outputs, &opts).unwrap();
+    let entry: serde_json::Value = serde_json::from_str(fs::read_to_string(&outputs).unwrap().lines().next().unwrap()).unwrap();
+    let _ = fs::remove_dir_all(&root);
+
+    let stdout = entry["tools"]["env"]["stdout"].as_str().unwrap();
+    assert!(stdout.contains("CARGO_BUILD_JOBS=3\n") && stdout.contains("CARGO_NET_OFFLINE=true\n"), "{}", stdout);
+    assert!(std::env::var_os("CARGO_BUILD_JOBS").is_none() && std::env::var_os("CARGO_NET_OFFLINE").is_none(), "dataset_builder's own environment is left alone");
+}
+
+#[cfg(unix)]
+#[test]
+fn background_processes_left_by_a_tool_dont_hold_its_run_open() {
+    // `daemon` leaves a sleep in its process group; `escaped` one in a session of its own, which
+    // still holds stdout open when the tool's deadline passes.
+    let config = write_config(
+        "background",
+        "[[tool]]\nname = \"daemon\"\ncommand = \"sh\"\nargs = [\"-c\", \"echo started; sleep 1000 &\"]\ntimeout_secs = 60\n\n[[tool]]\nname = \"escaped\"\ncommand = \"sh\"\nargs = [\"-c\", \"echo started; setsid sleep 5 &\"]\ntimeout_secs = 2\n",
+    );
+    let opts = AnalyzeOptions { tools_config: Some(config), only: vec!["daemon".into(), "escaped".into()], ..AnalyzeOptions::default() };
+    let repo = std::env::temp_dir().join(format!("dataset_builder_background_{}", std::process::id()));
+    fs::create_dir_all(repo.join("src")).unwrap();
+    fs::write(repo.join("Cargo.toml"), "[package]\nname = \"background\"\nversion = \"0.1.0\"\nedition = \"2021\"\n").unwrap();
+    fs::write(repo.join("src/main.rs"), "fn main() {}\n").unwrap();
+
+    let start = std::time::Instant::now();
+    let entries = analyze_repo(&repo, "background", &select_analyzers(&opts).unwrap(), &opts, &RunManifest::capture(&opts));
+    let elapsed = start.elapsed();
+    let _ = fs::remove_dir_all(&repo);
+
+    assert!(elapsed < std::time::Duration::from_secs(30), "{:?}", elapsed);
+    let daemon = &entries[0].tools["daemon"].result;
+    assert!(matches!(daemon.status, ToolStatus::Completed), "{:?}", daemon);
+    assert_eq!((daemon.stdout.as_str(), daemon.truncated), ("started\n", false));
+    let escaped = &entries[0].tools["escaped"].result;
+    assert!(matches!(escaped.status, ToolStatus::Completed), "{:?}", escaped);
+    assert!(escaped.truncated && escaped.stdout.starts_with("started\n[truncated: "), "{:?}", escaped);
+}
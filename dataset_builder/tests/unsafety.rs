@@ -0,0 +1,91 @@
+use std::fs;
+use std::path::PathBuf;
+
+use dataset_builder::{extract_unsafe, extract_unsafe_all, unsafe_summary_beside, CodeSource, UnsafeEntry, UnsafeKind, UnsafeOptions, UnsafeSummary};
+
+const BUF: &str = r#"pub struct Buf(Vec<u8>);
+
+impl Buf {
+    pub fn first(&self) -> u8 {
+        let len = self.0.len();
+        assert!(len > 0);
+        // SAFETY: the buffer isn't empty,
+        // as asserted above.
+        unsafe { *self.0.get_unchecked(0) }
+    }
+
+    /// # Safety
+    /// `i` is in bounds.
+    pub unsafe fn get(&self, i: usize) -> u8 {
+        unsafe { *self.0.get_unchecked(i) }
+    }
+}
+
+unsafe impl Send for Buf {}
+
+pub unsafe trait Zeroable {}
+"#;
+
+fn checkout(test: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("dataset_builder_{}_{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    for (path, content) in [("repo/Cargo.toml", "[package]\nname = \"p\"\n"), ("repo/src/lib.rs", "pub mod buf;\n"), ("repo/src/buf.rs", BUF), ("repo/src/broken.rs", "unsafe fn (")] {
+        fs::create_dir_all(root.join(path).parent().unwrap()).unwrap();
+        fs::write(root.join(path), content).unwrap();
+    }
+    root
+}
+
+#[test]
+fn finds_unsafe_blocks_fns_impls_and_traits_with_their_context() {
+    let root = checkout("unsafe_sites");
+    let mut entries: Vec<UnsafeEntry> = Vec::new();
+    let source = CodeSource { name: "o/p".into(), ..CodeSource::default() };
+
+    let summary = extract_unsafe(&root.join("repo"), &source, &UnsafeOptions { context_lines: 2, ..UnsafeOptions::default() }, &mut |e| {
+        entries.push(e);
+        Ok(())
+    })
+    .unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    let sites: Vec<(UnsafeKind, &str, usize)> = entries.iter().map(|e| (e.kind, e.item_path.as_str(), e.start_line)).collect();
+    assert_eq!(
+        sites,
+        [
+            (UnsafeKind::Block, "crate::buf::Buf::first", 9),
+            (UnsafeKind::Fn, "crate::buf::Buf::get", 12),
+            (UnsafeKind::Block, "crate::buf::Buf::get", 15),
+            (UnsafeKind::Impl, "crate::buf::<Buf as Send>", 19),
+            (UnsafeKind::Trait, "crate::buf::Zeroable", 21),
+        ]
+    );
+    let first = &entries[0];
+    assert_eq!((first.name.as_str(), first.path.as_str(), first.snippet.as_str()), ("o/p", "src/buf.rs", "unsafe { *self.0.get_unchecked(0) }"));
+    assert_eq!(first.safety_comment.as_deref(), Some("SAFETY: the buffer isn't empty,\nas asserted above."));
+    assert_eq!(first.context_before, "        // SAFETY: the buffer isn't empty,\n        // as asserted above.");
+    assert_eq!(first.context_after, "    }\n");
+    assert!(entries[1].snippet.starts_with("/// # Safety") && entries[1].safety_comment.is_none(), "doc comments aren't SAFETY comments");
+    assert_eq!(
+        summary,
+        UnsafeSummary { name: "o/p".into(), dir: "repo".into(), files: 3, files_with_unsafe: 1, blocks: 2, fns: 1, impls: 1, traits: 1, documented: 1, parse_failed: 1 }
+    );
+}
+
+#[test]
+fn extract_all_writes_entries_and_a_summary_per_repository() {
+    let root = checkout("unsafe_all");
+    let out = root.join("unsafe.jsonl");
+
+    let summaries = extract_unsafe_all(root.to_str().unwrap(), out.to_str().unwrap(), &UnsafeOptions::default()).unwrap();
+    let written = fs::read_to_string(&out).unwrap();
+    let summary = fs::read_to_string(unsafe_summary_beside(out.to_str().unwrap())).unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(summaries.len(), 1);
+    assert_eq!(written.lines().count(), 5);
+    let entry: UnsafeEntry = serde_json::from_str(written.lines().next().unwrap()).unwrap();
+    assert_eq!((entry.dir.as_str(), entry.context_before.lines().count()), ("repo", 3));
+    let line: UnsafeSummary = serde_json::from_str(summary.trim()).unwrap();
+    assert_eq!(line, summaries[0]);
+}
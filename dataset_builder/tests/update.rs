@@ -0,0 +1,103 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use common::serve;
+
+use dataset_builder::{collect_code_all, update_repos, AnalyzeOptions, CloneRecord, CodeEntry, CollectOptions, OutputEntry, Tokens, UpdateOptions, UpdateRecord, UpdateStatus, CLONE_MANIFEST_FILE};
+
+/// Commits `file` with `content` on top of HEAD, returning the commit's SHA.
+fn commit(repo: &git2::Repository, file: &str, content: &str) -> String {
+    fs::write(repo.workdir().unwrap().join(file), content).unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path(file.as_ref()).unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let sig = git2::Signature::now("test", "test@example.com").unwrap();
+    let parents: Vec<git2::Commit> = repo.head().ok().map(|h| h.peel_to_commit().unwrap()).into_iter().collect();
+    repo.commit(Some("HEAD"), &sig, &sig, content, &tree, &parents.iter().collect::<Vec<_>>()).unwrap().to_string()
+}
+
+/// An origin with a crate in it under `dir/origins/name`, checked out into `dir/datasets/name`.
+fn origin(dir: &Path, name: &str) -> git2::Repository {
+    let origin = git2::Repository::init(dir.join("origins").join(name)).unwrap();
+    commit(&origin, "Cargo.toml", &format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[lib]\npath = \"lib.rs\"\n", name));
+    commit(&origin, "lib.rs", "pub fn f() {}\n");
+    git2::Repository::clone(dir.join("origins").join(name).to_str().unwrap(), dir.join("datasets").join(name)).unwrap();
+    origin
+}
+
+fn opts(dir: &Path) -> UpdateOptions {
+    let path = |f: &str| dir.join(f).display().to_string();
+    let analyze = AnalyzeOptions { only: vec!["fmt".into()], ..AnalyzeOptions::default() };
+    UpdateOptions { root: path("datasets"), outputs: path("outputs.jsonl"), analyze: Box::new(analyze), ..UpdateOptions::default() }
+}
+
+#[test]
+fn checkouts_are_fast_forwarded_reset_after_a_force_push_or_flagged_gone() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_update_status_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let (ahead, rewritten) = (origin(&dir, "ahead"), origin(&dir, "rewritten"));
+    let _steady = origin(&dir, "steady");
+    origin(&dir, "gone");
+    origin(&dir, "pinned");
+    let new_ahead = commit(&ahead, "lib.rs", "pub fn f() {}\npub fn g() {}\n");
+    // A force-push: the last commit replaced by another.
+    let old_rewritten = rewritten.head().unwrap().peel_to_commit().unwrap();
+    rewritten.reset(old_rewritten.parent(0).unwrap().as_object(), git2::ResetType::Hard, None).unwrap();
+    let new_rewritten = commit(&rewritten, "lib.rs", "pub fn h() {}\n");
+    // Deleted on the server, which answers 404.
+    let server = serve(Arc::new(|_| (404, String::new(), String::new())));
+    git2::Repository::open(dir.join("datasets/gone")).unwrap().remote_set_url("origin", &format!("{}/o/gone.git", server)).unwrap();
+    let pinned = r#"{"name":"pinned","repo":"o/pinned","requested":"v1","commit":"abc","branch":null,"committed_at":"2020-01-01T00:00:00Z"}"#;
+    let gone = r#"{"name":"gone","repo":"o/gone","commit":"abc","branch":"master","committed_at":"2020-01-01T00:00:00Z"}"#;
+    fs::write(dir.join("datasets").join(CLONE_MANIFEST_FILE), format!("{}\n{}\n", pinned, gone)).unwrap();
+
+    let summary = update_repos(&opts(&dir), &Tokens::default()).unwrap();
+    let rewritten_file = fs::read_to_string(dir.join("datasets/rewritten/lib.rs")).unwrap();
+    let changelog: Vec<UpdateRecord> = fs::read_to_string(dir.join("update_changelog.jsonl")).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let manifest: Vec<CloneRecord> = fs::read_to_string(dir.join("datasets").join(CLONE_MANIFEST_FILE)).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let outputs = fs::read_to_string(dir.join("outputs.jsonl")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let status: Vec<(&str, UpdateStatus)> = summary.records.iter().map(|r| (r.name.as_str(), r.status)).collect();
+    let expected = [("ahead", UpdateStatus::Updated), ("gone", UpdateStatus::Gone), ("pinned", UpdateStatus::Skipped), ("rewritten", UpdateStatus::Reset), ("steady", UpdateStatus::Unchanged)];
+    assert_eq!(status, expected, "{:?}", summary.records);
+    let by_name = |name: &str| summary.records.iter().find(|r| r.name == name).unwrap();
+    assert_eq!(by_name("ahead").new_commit.as_deref(), Some(new_ahead.as_str()));
+    assert_eq!(by_name("rewritten").new_commit.as_deref(), Some(new_rewritten.as_str()));
+    assert!(by_name("rewritten").note.as_ref().unwrap().contains("history rewritten"));
+    assert_eq!(rewritten_file, "pub fn h() {}\n");
+    assert_eq!(changelog.len(), 5, "nothing was analyzed yet, so even the unchanged checkout is");
+    assert!(manifest.iter().any(|r| r.name == "gone" && r.gone), "{:?}", manifest);
+    assert_eq!(outputs.lines().count(), 5);
+}
+
+#[test]
+fn only_changed_repositories_are_analyzed_and_collected_again() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_update_changed_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let a = origin(&dir, "a");
+    origin(&dir, "b");
+    let code = dir.join("code.jsonl").display().to_string();
+    collect_code_all(&dir.join("datasets").display().to_string(), &code, &CollectOptions::default()).unwrap();
+    let opts = opts(&dir);
+    update_repos(&opts, &Tokens::default()).unwrap();
+    let new_a = commit(&a, "lib.rs", "pub fn changed() {}\n");
+
+    let summary = update_repos(&UpdateOptions { code: Some(code.clone()), ..opts.clone() }, &Tokens::default()).unwrap();
+    let outputs: Vec<OutputEntry> = fs::read_to_string(&opts.outputs).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let entries: Vec<CodeEntry> = fs::read_to_string(&code).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let changelog = fs::read_to_string(dir.join("update_changelog.jsonl")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(summary.records.iter().filter(|r| r.reanalyzed).map(|r| r.name.as_str()).collect::<Vec<_>>(), ["a"]);
+    assert_eq!(outputs.len(), 2, "a's old entry was replaced");
+    assert_eq!(outputs.iter().find(|o| o.dir == "a").unwrap().commit.as_deref(), Some(new_a.as_str()));
+    let lib = |dir: &str| entries.iter().find(|e| e.dir == dir && e.path == "lib.rs").map(|e| e.content.clone());
+    assert_eq!((lib("a").as_deref(), lib("b").as_deref()), (Some("pub fn changed() {}\n"), Some("pub fn f() {}\n")));
+    assert_eq!(entries.iter().filter(|e| e.dir == "a").count(), entries.iter().filter(|e| e.dir == "b").count());
+    assert_eq!(changelog.lines().count(), 1);
+}
@@ -0,0 +1,187 @@
+mod common;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+use base64::Engine;
+use dataset_builder::{shard_jsonl, upload_files_with, Codec, Credentials, Destination, ShardOptions, StoreOptions};
+use sha2::{Digest, Sha256};
+
+#[test]
+fn credentials_come_from_the_environment_then_the_shared_file() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_upload_credentials_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("credentials");
+    fs::write(&file, "[default]\naws_access_key_id = DEFAULT\naws_secret_access_key = d\n\n[ci]\n# rotated monthly\naws_access_key_id=CI\naws_secret_access_key=c\naws_session_token=t\n").unwrap();
+    let env = |vars: &'static [(&'static str, &'static str)]| {
+        let file = file.display().to_string();
+        move |name: &str| match name {
+            "AWS_SHARED_CREDENTIALS_FILE" => Some(file.clone()),
+            _ => vars.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string()),
+        }
+    };
+
+    let from_vars = Credentials::resolve(env(&[("AWS_ACCESS_KEY_ID", "ENV"), ("AWS_SECRET_ACCESS_KEY", "e")])).unwrap();
+    let default = Credentials::resolve(env(&[])).unwrap();
+    let profile = Credentials::resolve(env(&[("AWS_PROFILE", "ci")])).unwrap();
+    let missing = Credentials::resolve(env(&[("AWS_PROFILE", "prod")]));
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!((from_vars.access_key_id.as_str(), from_vars.session_token), ("ENV", None));
+    assert_eq!((default.access_key_id.as_str(), default.secret_access_key.as_str()), ("DEFAULT", "d"));
+    assert_eq!((profile.access_key_id.as_str(), profile.session_token.as_deref()), ("CI", Some("t")));
+    assert!(format!("{:#}", missing.unwrap_err()).contains("[prod] profile"));
+    let gcs = Destination::parse("gs://bucket/runs/2024/").unwrap();
+    assert_eq!((gcs.gcs, gcs.bucket.as_str(), gcs.key("code.jsonl")), (true, "bucket", "runs/2024/code.jsonl".to_string()));
+    assert!(Destination::parse("https://bucket/prefix").is_err());
+}
+
+/// The parts of an upload, by number.
+type Parts = BTreeMap<String, Vec<u8>>;
+
+/// An S3 bucket in memory: objects with their blake3 metadata, and multipart uploads in progress
+/// with their key.
+#[derive(Default)]
+struct Bucket {
+    objects: HashMap<String, (Vec<u8>, String)>,
+    uploads: BTreeMap<String, (String, String, Parts)>,
+    /// Method, key and query (`name=value`) of every request, with whether its payload hash was right
+    log: Vec<(String, String, Vec<String>, bool)>,
+    lost_part: bool,
+    /// A part number every attempt at which fails
+    broken_part: Option<String>,
+}
+
+fn sha256_base64(bytes: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(Sha256::digest(bytes))
+}
+
+fn s3(bucket: Arc<Mutex<Bucket>>) -> String {
+    common::serve(Arc::new(move |r: &common::Request| {
+        let mut b = bucket.lock().unwrap();
+        let key = r.path.trim_start_matches("/corpus/").to_string();
+        let signed = r.headers.get("authorization").is_some_and(|a| a.starts_with("AWS4-HMAC-SHA256 Credential=AKIDTEST/") && a.contains("/eu-west-1/s3/aws4_request,"));
+        let hashed = r.headers.get("x-amz-content-sha256").is_some_and(|h| *h == Sha256::digest(&r.body).iter().map(|b| format!("{:02x}", b)).collect::<String>());
+        let mut query: Vec<String> = r.query.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        query.sort();
+        b.log.push((r.method.clone(), key.clone(), query, signed && hashed));
+        let meta = r.headers.get("x-amz-meta-blake3").cloned().unwrap_or_default();
+        match (r.method.as_str(), r.query.get("uploadId")) {
+            ("HEAD", _) => match b.objects.get(&key) {
+                Some((bytes, blake3)) => (200, format!("x-amz-meta-blake3: {}\r\n", blake3), String::from_utf8_lossy(bytes).into_owned()),
+                None => (404, String::new(), String::new()),
+            },
+            ("GET", None) => {
+                let open = b.uploads.iter().filter(|(_, u)| u.0.starts_with(&r.query["prefix"]));
+                (200, String::new(), open.map(|(id, u)| format!("<Upload><Key>{}</Key><UploadId>{}</UploadId></Upload>", u.0, id)).collect())
+            }
+            ("GET", Some(id)) => {
+                let parts = b.uploads[id].2.iter().map(|(n, bytes)| {
+                    format!("<Part><PartNumber>{}</PartNumber><ETag>&quot;etag-{}&quot;</ETag><Size>{}</Size><ChecksumSHA256>{}</ChecksumSHA256></Part>", n, n, bytes.len(), sha256_base64(bytes))
+                });
+                (200, String::new(), format!("<ListPartsResult><IsTruncated>false</IsTruncated>{}</ListPartsResult>", parts.collect::<String>()))
+            }
+            ("PUT", Some(id)) => {
+                let part = r.query["partNumber"].clone();
+                // The first attempt at the second part is lost.
+                if part == "2" && !b.lost_part {
+                    b.lost_part = true;
+                    return (503, String::new(), "<Error><Code>SlowDown</Code></Error>".into());
+                }
+                if b.broken_part.as_ref() == Some(&part) {
+                    return (500, String::new(), "<Error><Code>InternalError</Code></Error>".into());
+                }
+                if r.headers.get("x-amz-checksum-sha256") != Some(&sha256_base64(&r.body)) {
+                    return (400, String::new(), "<Error><Code>BadDigest</Code></Error>".into());
+                }
+                b.uploads.get_mut(id).unwrap().2.insert(part.clone(), r.body.clone());
+                (200, format!("ETag: \"etag-{}\"\r\n", part), String::new())
+            }
+            ("PUT", None) => {
+                b.objects.insert(key, (r.body.clone(), meta));
+                (200, String::new(), String::new())
+            }
+            ("POST", None) => {
+                let id = format!("upload-{}", b.log.len());
+                b.uploads.insert(id.clone(), (key, meta, BTreeMap::new()));
+                (200, String::new(), format!("<InitiateMultipartUploadResult><UploadId>{}</UploadId></InitiateMultipartUploadResult>", id))
+            }
+            ("POST", Some(id)) => {
+                let (_, meta, parts) = b.uploads.remove(id).unwrap();
+                let body = String::from_utf8_lossy(&r.body);
+                let listed: Vec<&str> = body.split("<PartNumber>").skip(1).map(|p| &p[..p.find('<').unwrap()]).collect();
+                let bytes = listed.iter().flat_map(|n| parts[*n].clone()).collect();
+                b.objects.insert(key, (bytes, meta));
+                (200, String::new(), "<CompleteMultipartUploadResult/>".into())
+            }
+            _ => (400, String::new(), String::new()),
+        }
+    }))
+}
+
+#[test]
+fn shards_go_before_their_manifest_and_reruns_skip_what_is_there() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_upload_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = |f: &str| dir.join(f).display().to_string();
+    fs::write(path("code.jsonl"), (0..30).map(|i| format!(r#"{{"name":"owner/repo{}","path":"src/lib.rs","content":"fn f() {{}}"}}"#, i) + "\n").collect::<String>()).unwrap();
+    let manifest = shard_jsonl(&path("code.jsonl"), Codec::None, &ShardOptions { shard_entries: Some(10), shard_size: None }).unwrap();
+    fs::write(path("outputs.bin"), (0..2500u32).map(|i| b'a' + (i % 26) as u8).collect::<Vec<u8>>()).unwrap();
+    let bucket = Arc::new(Mutex::new(Bucket::default()));
+    let opts = StoreOptions { endpoint_url: Some(s3(bucket.clone())), region: Some("eu-west-1".into()), part_size: 1000, upload_retries: 1, ..StoreOptions::default() };
+    let files = [path("code.manifest.json"), path("outputs.bin")];
+    let keys = || Credentials { access_key_id: "AKIDTEST".into(), secret_access_key: "secret".into(), session_token: None };
+
+    let first = upload_files_with(&files, "s3://corpus/run-1", &opts, keys()).unwrap();
+    let sent = bucket.lock().unwrap().log.len();
+    let again = upload_files_with(&files, "s3://corpus/run-1", &opts, keys()).unwrap();
+    let b = bucket.lock().unwrap();
+    let stored = |key: &str| b.objects[&format!("run-1/{}", key)].0.clone();
+    let shards: Vec<(Vec<u8>, Vec<u8>)> = manifest.shards.iter().map(|s| (stored(&s.path), fs::read(path(&s.path)).unwrap())).collect();
+    let (code, outputs) = ((stored("code.manifest.json"), fs::read(path("code.manifest.json")).unwrap()), (stored("outputs.bin"), fs::read(path("outputs.bin")).unwrap()));
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!((first.uploaded, first.skipped, again.uploaded, again.skipped), (5, 0, 0, 5));
+    assert!(shards.iter().chain([&code, &outputs]).all(|(stored, local)| stored == local));
+    assert!(b.log.iter().all(|l| l.3), "every request is signed over its payload");
+    let put = |key: &str| b.log.iter().rposition(|l| l.0 != "HEAD" && l.1 == format!("run-1/{}", key)).unwrap();
+    assert!(manifest.shards.iter().all(|s| put(&s.path) < put("code.manifest.json")), "the manifest is uploaded after its shards");
+    let parts: Vec<&Vec<String>> = b.log.iter().filter(|l| l.0 == "PUT" && l.1 == "run-1/outputs.bin").map(|l| &l.2).collect();
+    assert_eq!(parts.len(), 4, "three parts, the second sent twice");
+    assert!(b.log[sent..].iter().all(|l| l.0 == "HEAD"), "the rerun only looks the objects up");
+}
+
+#[test]
+fn a_failed_multipart_upload_is_left_open_and_the_rerun_sends_only_its_missing_part() {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_upload_resume_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("outputs.bin").display().to_string();
+    let content: Vec<u8> = (0..3500u32).map(|i| b'a' + (i % 26) as u8).collect();
+    fs::write(&file, &content).unwrap();
+    // The last part fails for good, after the others are sent one at a time.
+    let bucket = Arc::new(Mutex::new(Bucket { lost_part: true, broken_part: Some("4".into()), ..Bucket::default() }));
+    let opts = StoreOptions { endpoint_url: Some(s3(bucket.clone())), region: Some("eu-west-1".into()), part_size: 1000, upload_jobs: 1, upload_retries: 0 };
+    let keys = || Credentials { access_key_id: "AKIDTEST".into(), secret_access_key: "secret".into(), session_token: None };
+    let files = [file.clone()];
+
+    let failed = upload_files_with(&files, "s3://corpus", &opts, keys());
+    let (open, sent) = {
+        let mut b = bucket.lock().unwrap();
+        b.broken_part = None;
+        (b.uploads.values().map(|u| u.2.keys().cloned().collect::<Vec<_>>()).collect::<Vec<_>>(), b.log.len())
+    };
+    let resumed = upload_files_with(&files, "s3://corpus", &opts, keys()).unwrap();
+    let b = bucket.lock().unwrap();
+    let stored = b.objects.get("outputs.bin").map(|o| o.0.clone());
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(format!("{:#}", failed.unwrap_err()).contains("part 4 of 4"));
+    assert_eq!(open, [vec!["1", "2", "3"]], "the failed upload is left open with the parts it got");
+    let parts: Vec<&Vec<String>> = b.log[sent..].iter().filter(|l| l.0 == "PUT").map(|l| &l.2).collect();
+    assert!(parts.len() == 1 && parts[0].contains(&"partNumber=4".to_string()), "only the missing part is sent again: {:?}", parts);
+    assert!(b.log[sent..].iter().all(|l| l.0 != "POST" || l.2.iter().any(|q| q.starts_with("uploadId="))), "no new upload is started");
+    assert_eq!((resumed.uploaded, stored, b.uploads.len()), (1, Some(content), 0));
+}
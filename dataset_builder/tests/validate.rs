@@ -0,0 +1,64 @@
+use std::fs;
+use std::path::PathBuf;
+
+use dataset_builder::{validate_file, ValidateKind, ValidateOptions};
+
+fn output(name: &str) -> String {
+    format!(r#"{{"schema_version":4,"run_id":"r","name":"{}","dir":"{}","project_manifest":"Cargo.toml","tools":{{}},"findings":[],"toolchain":"stable","time_ms":{{}},"errors":[]}}"#, name, name) + "\n"
+}
+
+fn code(name: &str, path: &str) -> String {
+    format!(r#"{{"name":"{}","dir":"{}","path":"{}","kind":"lib","content":"fn f() {{}}"}}"#, name, name, path) + "\n"
+}
+
+fn dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dataset_builder_{}_{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn lines_are_checked_against_the_kind_of_the_first_and_fixed_into_a_copy() {
+    let dir = dir("validate_schema");
+    let path = |f: &str| dir.join(f).display().to_string();
+    // A line without `tools`, a repeated repository and a last line cut off by a crash.
+    let text = [output("a"), output("b").replace(r#""tools":{},"#, ""), output("c"), output("a"), output("d").trim_end().to_string()].concat();
+    fs::write(path("outputs.jsonl"), &text).unwrap();
+    let opts = ValidateOptions { file: path("outputs.jsonl"), ..ValidateOptions::default() };
+
+    let failed = validate_file(&opts).unwrap_err();
+    let warned = validate_file(&ValidateOptions { warn_only: true, ..opts.clone() }).unwrap();
+    let fixed = validate_file(&ValidateOptions { fix: Some(path("clean.jsonl.zst")), ..opts.clone() }).unwrap();
+    let clean = String::from_utf8(zstd::decode_all(fs::File::open(path("clean.jsonl.zst")).unwrap()).unwrap()).unwrap();
+    let unchanged = fs::read_to_string(path("outputs.jsonl")).unwrap();
+    let as_code = validate_file(&ValidateOptions { kind: Some(ValidateKind::Code), warn_only: true, ..opts.clone() }).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(format!("{:#}", failed).contains("has 3 bad lines; rerun with --repair"), "{:#}", failed);
+    assert_eq!((warned.kind, warned.lines, warned.invalid, warned.mismatched, warned.duplicates), (Some(ValidateKind::Outputs), 5, 1, 1, 1));
+    assert_eq!((fixed.errors(), fixed.dropped), (0, 3));
+    assert_eq!(clean, [output("a"), output("c")].concat());
+    assert_eq!(unchanged, text, "--fix leaves the file as it is");
+    assert_eq!(as_code.mismatched, 4, "no outputs line is a code entry");
+}
+
+#[test]
+fn cross_check_lists_repositories_missing_from_either_file() {
+    let dir = dir("validate_cross");
+    let path = |f: &str| dir.join(f).display().to_string();
+    fs::write(path("code.jsonl"), [code("a", "src/lib.rs"), code("a", "src/main.rs"), code("b", "src/lib.rs"), code("x", "src/lib.rs")].concat()).unwrap();
+    fs::write(path("outputs.jsonl"), [output("a"), output("b"), output("y")].concat()).unwrap();
+    let metadata = r#"{"name":"a","repo":"o/a","status":"ok","topics":[],"fetched_at":"2024-03-01T00:00:00Z"}"#.to_string() + "\n";
+    fs::write(path("metadata.jsonl"), metadata.repeat(2)).unwrap();
+    let opts = ValidateOptions { file: path("code.jsonl"), cross_check: Some(path("outputs.jsonl")), ..ValidateOptions::default() };
+
+    let failed = validate_file(&opts).unwrap_err();
+    let warned = validate_file(&ValidateOptions { warn_only: true, ..opts.clone() }).unwrap();
+    let metadata = validate_file(&ValidateOptions { file: path("metadata.jsonl"), warn_only: true, ..ValidateOptions::default() }).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(format!("{:#}", failed).contains("2 repositories missing from the other file"), "{:#}", failed);
+    assert_eq!((warned.kind, warned.bad_lines(), warned.only_here, warned.only_there), (Some(ValidateKind::Code), 0, vec!["x".to_string()], vec!["y".to_string()]));
+    assert_eq!((metadata.kind, metadata.duplicates), (Some(ValidateKind::Metadata), 1));
+}